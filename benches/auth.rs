@@ -0,0 +1,86 @@
+//! The work the auth middleware does on every request, `cargo bench --bench auth`.
+//!
+//! Compare a run against a baseline before and after changing the middleware:
+//! `cargo bench --bench auth -- --save-baseline main`, then `-- --baseline main`.
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use saleor_app::{
+    app::app_manifest,
+    config::AppConfig,
+    saleor::{verify_jwt, verify_jwt_with_jwks, AplId, AplKeyring, AplStore, AuthData, EncryptedAplStore, FileAplStore, JwksCache, JwtValidation, MigratingAplStore, SaleorPermission},
+    testing::MockSaleor,
+};
+use tokio::runtime::Runtime;
+
+const PERMISSIONS: &[SaleorPermission] = &[SaleorPermission::ManageProducts];
+
+fn jwt_verification(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let saleor = runtime.block_on(MockSaleor::start());
+    let token = saleor.token(PERMISSIONS);
+    let validation = JwtValidation::default();
+    let api_url = saleor.api_url();
+
+    let mut group = c.benchmark_group("jwt verification");
+    // parses the JWKS and builds its decoding key for every token
+    group.bench_function("uncached", |b| {
+        b.iter(|| verify_jwt(saleor.jwks(), black_box(&token), PERMISSIONS, &validation).unwrap())
+    });
+    // what the middleware does for installations, the JWKS and its decoding keys are reused
+    let cache = JwksCache::new();
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            let jwks = cache.get(&api_url, saleor.jwks()).unwrap();
+            verify_jwt_with_jwks(&jwks, black_box(&token), PERMISSIONS, &validation).unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// The stack `main` hands to `app::build`: the file store, encrypted with `APL_ENCRYPTION_KEY`,
+/// behind the migrating store that also looks installations up under previous app ids.
+fn apl_stack(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    // `FileAplStore` keeps its file in the working directory
+    let dir = std::env::temp_dir().join(format!("saleor-app-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+
+    let auth_data = AuthData {
+        domain: Some("shop.example.com".to_string()),
+        ..AuthData::new("https://shop.example.com/graphql/", "app-token")
+    };
+    let apl_id = AplId::from_auth_data(&auth_data);
+    let config = AppConfig { previous_app_ids: vec!["previous-app".to_string()], ..Default::default() };
+    let keyring = AplKeyring::parse(&AplKeyring::generate_key()).unwrap();
+    let stacks: [(&str, Arc<dyn AplStore>); 2] = [
+        ("file", Arc::new(FileAplStore)),
+        ("encrypted file", Arc::new(EncryptedAplStore::new(FileAplStore, keyring))),
+    ];
+
+    let mut group = c.benchmark_group("apl stack lookup");
+    for (name, store) in stacks {
+        let store = MigratingAplStore::from_config(store, &config);
+        runtime.block_on(store.set(&apl_id, auth_data.clone())).unwrap();
+        group.bench_function(name, |b| b.to_async(&runtime).iter(|| store.get(black_box(&apl_id))));
+    }
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn manifest_serialization(c: &mut Criterion) {
+    let manifest = app_manifest("https://app.example.com");
+
+    let mut group = c.benchmark_group("manifest");
+    group.bench_function("serialize", |b| b.iter(|| serde_json::to_vec(black_box(&manifest)).unwrap()));
+    group.bench_function("build and serialize", |b| {
+        b.iter(|| serde_json::to_vec(&app_manifest(black_box("https://app.example.com"))).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, jwt_verification, apl_stack, manifest_serialization);
+criterion_main!(benches);
@@ -1,7 +1,48 @@
+use std::{env, fs, path::{Path, PathBuf}};
+
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=schemas/saleor.graphql");
+
     cynic_codegen::register_schema("saleor")
         .from_sdl_file("schemas/saleor.graphql")
         .unwrap()
         .as_default()
         .unwrap();
-}
\ No newline at end of file
+
+    embed_assets();
+}
+
+/// Generates a list of `include_bytes!` for every file in `assets/` when the `embed-assets` feature is enabled.
+fn embed_assets() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let assets_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets");
+
+    let mut files = vec![];
+    if env::var("CARGO_FEATURE_EMBED_ASSETS").is_ok() && assets_dir.is_dir() {
+        println!("cargo:rerun-if-changed=assets");
+        collect_files(&assets_dir, &mut files);
+    }
+    files.sort();
+
+    let entries = files
+        .iter()
+        .map(|file| {
+            let relative = file.strip_prefix(&assets_dir).unwrap().to_string_lossy().replace('\\', "/");
+            format!("    ({:?}, include_bytes!({:?})),\n", relative, file.display().to_string())
+        })
+        .collect::<String>();
+    let generated = format!("pub static EMBEDDED_ASSETS: &[(&str, &[u8])] = &[\n{entries}];\n");
+    fs::write(out_dir.join("embedded_assets.rs"), generated).unwrap();
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,10 @@
+fn main() {
+    println!("cargo:rerun-if-changed=../../schemas/saleor.graphql");
+
+    // the fragments are checked against the schema saleor-app is built with
+    cynic_codegen::register_schema("saleor")
+        .from_sdl_file("../../schemas/saleor.graphql")
+        .unwrap()
+        .as_default()
+        .unwrap();
+}
@@ -0,0 +1,154 @@
+//! Writes the badge into the metadata of created and updated products.
+
+use async_trait::async_trait;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use cynic::MutationBuilder;
+use saleor_app::{
+    http_client::HttpClient,
+    saleor::{
+        run_app_operation, subscriptions::WebhookSubscription, AuthData, MetadataError, MetadataInput, MetadataItem, SaleorAsyncWebhookEvent, WebhookContext,
+        WebhookHandler,
+    },
+};
+
+use crate::settings::BadgeSettings;
+
+pub const PRODUCT_CHANGED_PATH: &str = "/api/webhooks/product-changed";
+/// Key of the public product metadata storefronts read the badge from.
+pub const BADGE_METADATA_KEY: &str = "badge";
+
+#[derive(Debug)]
+pub struct BadgeError(pub String);
+
+impl std::fmt::Display for BadgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "badge error: {}", self.0)
+    }
+}
+
+impl std::error::Error for BadgeError {}
+
+/// Saleor retries deliveries answered with an error.
+impl IntoResponse for BadgeError {
+    fn into_response(self) -> Response {
+        tracing::warn!("{}", self);
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Product", schema_module = "saleor_app::saleor::schema")]
+pub struct BadgedProduct {
+    pub id: cynic::Id,
+    pub name: String,
+    pub metadata: Vec<MetadataItem>,
+}
+
+impl BadgedProduct {
+    pub fn badge(&self) -> Option<&str> {
+        self.metadata.iter().find(|item| item.key == BADGE_METADATA_KEY).map(|item| item.value.as_str())
+    }
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "ProductCreated", schema_module = "saleor_app::saleor::schema")]
+pub struct ProductCreated {
+    pub product: Option<BadgedProduct>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "ProductUpdated", schema_module = "saleor_app::saleor::schema")]
+pub struct ProductUpdated {
+    pub product: Option<BadgedProduct>,
+}
+
+/// Payload of [`ProductBadger`], one variant per event of the subscription.
+#[derive(cynic::InlineFragments, Debug)]
+#[cynic(graphql_type = "Event", schema_module = "saleor_app::saleor::schema")]
+pub enum ProductChanged {
+    ProductCreated(ProductCreated),
+    ProductUpdated(ProductUpdated),
+    #[cynic(fallback)]
+    Other,
+}
+
+impl ProductChanged {
+    pub fn product(self) -> Option<BadgedProduct> {
+        match self {
+            Self::ProductCreated(ProductCreated { product }) | Self::ProductUpdated(ProductUpdated { product }) => product,
+            Self::Other => None,
+        }
+    }
+}
+
+pub fn product_changed_subscription() -> WebhookSubscription<ProductChanged> {
+    WebhookSubscription::new("Product badges", PRODUCT_CHANGED_PATH)
+        .with_async_event(SaleorAsyncWebhookEvent::ProductCreated)
+        .with_async_event(SaleorAsyncWebhookEvent::ProductUpdated)
+}
+
+#[derive(cynic::QueryVariables, Debug)]
+#[cynic(schema_module = "saleor_app::saleor::schema")]
+pub struct UpdateMetadataVariables {
+    pub id: cynic::Id,
+    pub input: Vec<MetadataInput>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Mutation", variables = "UpdateMetadataVariables", schema_module = "saleor_app::saleor::schema")]
+pub struct UpdateProductMetadata {
+    #[arguments(id: $id, input: $input)]
+    pub update_metadata: Option<UpdateMetadata>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(schema_module = "saleor_app::saleor::schema")]
+pub struct UpdateMetadata {
+    pub errors: Vec<MetadataError>,
+}
+
+/// Handles `POST /api/webhooks/product-changed`, badges products which don't carry the
+/// configured badge yet. Writing the metadata updates the product again, which then has the badge.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProductBadger;
+
+#[async_trait]
+impl WebhookHandler<ProductChanged> for ProductBadger {
+    type Response = Result<StatusCode, BadgeError>;
+
+    async fn handle(&self, context: &WebhookContext, payload: ProductChanged) -> Self::Response {
+        let Some(product) = payload.product() else {
+            return Ok(StatusCode::OK);
+        };
+        let settings = BadgeSettings::from_settings(context.settings.get_all().await.map_err(|e| BadgeError(e.to_string()))?);
+        let Some(label) = settings.label() else {
+            return Ok(StatusCode::OK);
+        };
+        if product.badge() == Some(label) {
+            return Ok(StatusCode::OK);
+        }
+
+        set_badge(&context.client, &context.auth_data, product.id, label).await?;
+        tracing::info!(parent: &context.span, product = %product.name, badge = label, "badged product");
+        Ok(StatusCode::OK)
+    }
+}
+
+async fn set_badge(client: &HttpClient, auth_data: &AuthData, product_id: cynic::Id, label: &str) -> Result<(), BadgeError> {
+    let operation = UpdateProductMetadata::build(UpdateMetadataVariables {
+        id: product_id,
+        input: vec![MetadataInput { key: BADGE_METADATA_KEY.to_string(), value: label.to_string() }],
+    });
+    let data = run_app_operation(client, auth_data, "UpdateProductMetadata", operation).await.map_err(|e| BadgeError(e.to_string()))?;
+    let errors = data
+        .update_metadata
+        .map(|update| update.errors)
+        .ok_or_else(|| BadgeError("no data in response".to_string()))?;
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(BadgeError(format!("unable to update product metadata: {:?}", errors))),
+    }
+}
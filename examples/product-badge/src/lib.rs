@@ -0,0 +1,52 @@
+//! A Saleor app putting a badge on every product, built on `saleor-app`.
+//!
+//! Products created or updated in Saleor are delivered to [`badges::ProductBadger`], which
+//! writes the configured label into the `badge` metadata of the product for storefronts to show.
+//! Staff set the label on the [`settings`] page in the dashboard's catalog navigation. The base
+//! app handles registration, authentication and sessions, this crate only adds its routes and
+//! replaces the webhooks and extensions of the manifest.
+
+use axum::{
+    routing::get,
+    Router,
+};
+use saleor_app::{
+    app,
+    config::AppConfig,
+    router_ext::{RouterExt, SaleorWebhooks},
+    saleor::{webhook_handler, SaleorAppPermission, SaleorAuthLayer, SaleorManifest, SaleorPermission},
+};
+
+pub mod badges;
+pub mod settings;
+
+pub const APP_ID: &str = "product-badge";
+pub const APP_NAME: &str = "Product badge";
+
+/// The routes of the app, served behind the middleware of the base app by
+/// [`RouterExt::saleor_app`].
+pub fn routes(config: &AppConfig) -> Router {
+    let webhooks = SaleorWebhooks::new().route(badges::PRODUCT_CHANGED_PATH, webhook_handler(badges::ProductBadger));
+
+    let api = Router::new()
+        .route(settings::SETTINGS_API_PATH, get(settings::get_settings).post(settings::post_settings))
+        .route_layer(SaleorAuthLayer::with_permissions(&[SaleorPermission::ManageProducts]).with_session_expiry(config.sessions.tenant_expiry));
+
+    Router::new()
+        .nest("/app", settings::pages().router(config.sessions.tenant_expiry))
+        .merge(api)
+        .saleor_webhooks(config, webhooks)
+}
+
+/// The manifest of the base app with the webhooks and extensions of this one.
+pub fn manifest(base_url: &str) -> SaleorManifest {
+    SaleorManifest {
+        id: APP_ID.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        name: APP_NAME.to_string(),
+        permissions: vec![SaleorAppPermission::ManageProducts],
+        extensions: Some(settings::pages().manifest_extensions()),
+        webhooks: Some(vec![badges::product_changed_subscription().manifest(base_url)]),
+        ..app::app_manifest(base_url)
+    }
+}
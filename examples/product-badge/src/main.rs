@@ -0,0 +1,32 @@
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use axum::{routing::get, Extension, Router};
+use product_badge::{manifest, routes};
+use saleor_app::{base_url::{BaseUrl, BaseUrlPolicy}, config::AppConfig, router_ext::RouterExt, saleor::FileAplStore, telemetry};
+use tracing::info;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = AppConfig::from_env()?;
+    telemetry::init_tracing(config.log_format);
+
+    let apl_store = FileAplStore;
+    // the base app handles registration, authentication and sessions, for its routes and ours
+    let base = routes(&config).saleor_app(&config, apl_store).await?;
+
+    let router = Router::new()
+        .route("/api/manifest", get(|BaseUrl(base_url): BaseUrl| async move { manifest(&base_url) }))
+        .layer(Extension(BaseUrlPolicy::from_config(&config)))
+        .fallback_service(base.router);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    info!("now listening on port {}", config.port);
+    base.health_checks.mark_started();
+    axum::Server::bind(&addr)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .context("error while starting server")?;
+
+    Ok(())
+}
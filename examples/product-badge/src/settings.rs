@@ -0,0 +1,99 @@
+//! The badge settings page, opened from the catalog navigation of the dashboard.
+
+use std::collections::HashMap;
+
+use askama::Template;
+use axum::{response::{IntoResponse, Response}, Form};
+use saleor_app::{
+    extensions::{ExtensionContext, ExtensionPage, ExtensionPages},
+    saleor::{SaleorAppExtensionMount, SaleorAppPermission, SettingsError, TenantSettings},
+    templating::{AppBridgeAction, HtmlTemplate, Page},
+};
+use serde::Deserialize;
+
+/// Where the form is loaded from and posted to.
+pub const SETTINGS_API_PATH: &str = "/api/badge";
+const BADGE_LABEL: &str = "badge_label";
+/// The label of installations which haven't saved one yet.
+pub const DEFAULT_BADGE_LABEL: &str = "New";
+const MAX_BADGE_LABEL_LEN: usize = 32;
+
+/// The settings of an installation, kept in the private metadata of the app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadgeSettings {
+    /// Empty when badges are turned off
+    pub label: String,
+}
+
+impl BadgeSettings {
+    pub fn from_settings(mut settings: HashMap<String, String>) -> Self {
+        Self { label: settings.remove(BADGE_LABEL).unwrap_or_else(|| DEFAULT_BADGE_LABEL.to_string()) }
+    }
+
+    /// The badge to put on products, `None` when badges are turned off.
+    pub fn label(&self) -> Option<&str> {
+        Some(self.label.as_str()).filter(|label| !label.is_empty())
+    }
+}
+
+/// The settings form as submitted.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct BadgeForm {
+    #[serde(default)]
+    pub label: String,
+}
+
+impl BadgeForm {
+    /// The error shown next to the field, if any.
+    pub fn validate(&self) -> Option<&'static str> {
+        (self.label.trim().chars().count() > MAX_BADGE_LABEL_LEN).then_some("Badges are at most 32 characters long")
+    }
+}
+
+#[derive(Template)]
+#[template(path = "badge_page.html")]
+pub struct BadgePage {
+    pub form: BadgeForm,
+    pub error: Option<&'static str>,
+}
+
+#[derive(Template)]
+#[template(path = "badge_form.html")]
+pub struct BadgeFormTemplate {
+    pub form: BadgeForm,
+    pub error: Option<&'static str>,
+}
+
+/// The extension pages of the app, served under `/app` and listed in the manifest.
+pub fn pages() -> ExtensionPages {
+    ExtensionPages::new().with_page(
+        ExtensionPage::new("/badge", "Product badge", SaleorAppExtensionMount::NavigationCatalog)
+            .with_permissions(&[SaleorAppPermission::ManageProducts]),
+        badge_page,
+    )
+}
+
+/// `GET /app/badge`, the settings form of the installation.
+pub async fn badge_page(extension: ExtensionContext, settings: TenantSettings) -> Result<impl IntoResponse, SettingsError> {
+    let settings = BadgeSettings::from_settings(settings.get_all().await?);
+    let content = BadgePage { form: BadgeForm { label: settings.label }, error: None };
+    Ok(Page::new(extension.ctx, extension.page.label, content))
+}
+
+/// `GET /api/badge`, renders the form again, e.g. to discard changes.
+pub async fn get_settings(settings: TenantSettings) -> Result<impl IntoResponse, SettingsError> {
+    let settings = BadgeSettings::from_settings(settings.get_all().await?);
+    Ok(HtmlTemplate(BadgeFormTemplate { form: BadgeForm { label: settings.label }, error: None }))
+}
+
+/// `POST /api/badge`, validates and saves the label, an empty one turns badges off.
+pub async fn post_settings(settings: TenantSettings, Form(mut form): Form<BadgeForm>) -> Result<Response, SettingsError> {
+    form.label = form.label.trim().to_string();
+    if let Some(error) = form.validate() {
+        return Ok(HtmlTemplate(BadgeFormTemplate { form, error: Some(error) }).into_response());
+    }
+
+    settings.set(HashMap::from([(BADGE_LABEL.to_string(), form.label.clone())])).await?;
+    let saved = AppBridgeAction::success("Badge saved");
+    Ok((saved, HtmlTemplate(BadgeFormTemplate { form, error: None })).into_response())
+}
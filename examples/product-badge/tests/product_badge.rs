@@ -0,0 +1,99 @@
+use axum::http::StatusCode;
+use product_badge::{badges::PRODUCT_CHANGED_PATH, manifest, routes};
+use saleor_app::{
+    config::AppConfig,
+    saleor::{manifest_problems, SaleorAppExtensionMount, SaleorAsyncWebhookEvent, SaleorPermission},
+    testing::TestApp,
+};
+use serde_json::{json, Value};
+
+async fn badge_app(private_metadata: Value) -> TestApp {
+    let config = AppConfig::default();
+    let app = TestApp::with_routes(config.clone(), routes(&config)).await;
+    app.saleor.respond_to("privateMetadata", json!({ "app": { "id": "QXBwOjE=", "privateMetadata": private_metadata } }));
+    app.saleor.respond_to("updateMetadata", json!({ "updateMetadata": { "errors": [] } }));
+    app.saleor.respond_to("updatePrivateMetadata", json!({ "updatePrivateMetadata": { "errors": [] } }));
+    app
+}
+
+fn product_event(event: &str, metadata: Value) -> Value {
+    json!({ "__typename": event, "product": { "id": "UHJvZHVjdDox", "name": "Apple Juice", "metadata": metadata } })
+}
+
+/// The variables of the mutations containing `operation` sent to Saleor.
+fn mutations(app: &TestApp, operation: &str) -> Vec<Value> {
+    app.saleor
+        .requests()
+        .into_iter()
+        .filter(|request| request["query"].as_str().unwrap().contains(operation))
+        .map(|request| request["variables"].clone())
+        .collect()
+}
+
+#[test]
+fn the_manifest_declares_the_webhook_and_the_page() {
+    let manifest = manifest("https://badge.example.com");
+    assert!(manifest_problems(&manifest).is_empty(), "{:?}", manifest_problems(&manifest));
+
+    let webhooks = manifest.webhooks.unwrap();
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].target_url, format!("https://badge.example.com{PRODUCT_CHANGED_PATH}"));
+    assert_eq!(webhooks[0].async_events, Some(vec![SaleorAsyncWebhookEvent::ProductCreated, SaleorAsyncWebhookEvent::ProductUpdated]));
+    assert!(webhooks[0].query.contains("... on ProductUpdated {"), "{}", webhooks[0].query);
+
+    let extensions = manifest.extensions.unwrap();
+    assert_eq!(extensions.len(), 1);
+    assert_eq!(extensions[0].url, "/app/badge");
+    assert_eq!(extensions[0].mount, SaleorAppExtensionMount::NavigationCatalog);
+}
+
+#[tokio::test]
+async fn changed_products_get_the_badge() {
+    let app = badge_app(json!([])).await;
+
+    let response = app.deliver_webhook(PRODUCT_CHANGED_PATH, "product_created", &product_event("ProductCreated", json!([]))).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(mutations(&app, "updateMetadata"), vec![json!({ "id": "UHJvZHVjdDox", "input": [{ "key": "badge", "value": "New" }] })]);
+
+    // the update writing the badge doesn't write it again
+    let badged = json!([{ "key": "badge", "value": "New" }]);
+    let response = app.deliver_webhook(PRODUCT_CHANGED_PATH, "product_updated", &product_event("ProductUpdated", badged)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(mutations(&app, "updateMetadata").len(), 1);
+}
+
+#[tokio::test]
+async fn badges_follow_the_settings() {
+    let app = badge_app(json!([{ "key": "badge_label", "value": "Sale" }])).await;
+    let response = app.deliver_webhook(PRODUCT_CHANGED_PATH, "product_updated", &product_event("ProductUpdated", json!([]))).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(mutations(&app, "updateMetadata")[0]["input"][0]["value"], "Sale");
+
+    let app = badge_app(json!([{ "key": "badge_label", "value": "" }])).await;
+    let response = app.deliver_webhook(PRODUCT_CHANGED_PATH, "product_updated", &product_event("ProductUpdated", json!([]))).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(mutations(&app, "updateMetadata").is_empty());
+}
+
+#[tokio::test]
+async fn staff_edit_the_badge_on_the_settings_page() {
+    let app = badge_app(json!([])).await;
+    let user = app.as_user(&[SaleorPermission::ManageProducts]);
+
+    let response = user.get("/app/badge").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(response.text().contains(r#"value="New""#), "{}", response.text());
+
+    let response = user.post_form("/api/badge", &[("label", " Sale ")]).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(response.text().contains(r#"value="Sale""#));
+    let saved = mutations(&app, "updatePrivateMetadata");
+    assert_eq!(saved[0]["input"], json!([{ "key": "badge_label", "value": "Sale" }]));
+
+    let response = user.post_form("/api/badge", &[("label", "x".repeat(33))]).await;
+    assert!(response.text().contains("at most 32 characters"));
+    assert_eq!(mutations(&app, "updatePrivateMetadata").len(), 1);
+
+    let response = app.as_user(&[SaleorPermission::ManageOrders]).post_form("/api/badge", &[("label", "Sale")]).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,35 @@
+hello-title = Hallo Welt
+hello-text = Das ist ein Test, das Dashboard wird in { $locale } angezeigt.
+hello-button = Klick mich
+hello-notification = Hallo gesagt
+nav-home = Start
+nav-config = Konfiguration
+nav-installations = Installationen
+loading = Lädt…
+extension-objects = Geöffnet für diese Produkte:
+extension-no-objects = Es wurden keine Produkte ausgewählt.
+widget-product-type = Produkttyp
+widget-category = Kategorie
+widget-variants = Varianten
+config-title = Konfiguration
+config-description = Einstellungen dieser Installation, gespeichert in den privaten Metadaten der App.
+config-notification-email = Benachrichtigungs-E-Mail
+config-low-stock-threshold = Schwelle für niedrigen Bestand
+config-save = Speichern
+config-saved = Konfiguration gespeichert
+config-error-email = Gib eine gültige E-Mail-Adresse ein.
+config-error-threshold = Gib eine ganze Zahl zwischen 0 und 100000 ein.
+installations-title = Installationen
+installations-description = Alle Saleor-Instanzen, in denen diese App registriert ist.
+installations-empty = Die App ist noch nirgends installiert.
+installations-api-url = Saleor-API-URL
+installations-domain = Domain
+installations-app-id = App-ID
+installations-registered-at = Registriert am
+installations-token-invalid = Token abgelehnt
+installations-token-invalid-hint = Saleor lehnt das App-Token dieser Installation ab, installiere die App erneut.
+installations-remove = Entfernen
+installations-remove-confirm = Installation von { $url } entfernen? Die App kann deren Anfragen danach nicht mehr authentifizieren.
+installations-removed = Installation entfernt
+bulk-metadata-finished = Getaggte Produkte: { $count }
+bulk-metadata-failed = Produkte, die nicht getaggt werden konnten: { $count }
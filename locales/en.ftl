@@ -0,0 +1,35 @@
+hello-title = Hello World
+hello-text = This is a test, the dashboard is displayed in { $locale }.
+hello-button = Click me
+hello-notification = Said hello
+nav-home = Home
+nav-config = Configuration
+nav-installations = Installations
+loading = Loading…
+extension-objects = Opened for these products:
+extension-no-objects = No products were selected.
+widget-product-type = Product type
+widget-category = Category
+widget-variants = Variants
+config-title = Configuration
+config-description = Settings of this installation, stored in the app's private metadata.
+config-notification-email = Notification email
+config-low-stock-threshold = Low stock threshold
+config-save = Save
+config-saved = Configuration saved
+config-error-email = Enter a valid email address.
+config-error-threshold = Enter a whole number between 0 and 100000.
+installations-title = Installations
+installations-description = Every Saleor instance this app is registered in.
+installations-empty = The app isn't installed anywhere yet.
+installations-api-url = Saleor API url
+installations-domain = Domain
+installations-app-id = App id
+installations-registered-at = Registered at
+installations-token-invalid = Token rejected
+installations-token-invalid-hint = Saleor rejects the app token of this installation, install the app again.
+installations-remove = Remove
+installations-remove-confirm = Remove the installation of { $url }? The app won't be able to authenticate its requests anymore.
+installations-removed = Installation removed
+bulk-metadata-finished = Tagged products: { $count }
+bulk-metadata-failed = Products that couldn't be tagged: { $count }
@@ -0,0 +1,174 @@
+//! Macros of `saleor-app`, use them through its re-exports.
+
+use heck::{ToKebabCase, ToUpperCamelCase};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse::Parser, punctuated::Punctuated, spanned::Spanned, Error, FnArg, Ident, ItemFn, LitStr, Meta, ReturnType, Token, Type,
+};
+
+/// What the attribute arguments declare.
+struct WebhookArgs {
+    async_events: Vec<LitStr>,
+    sync_events: Vec<LitStr>,
+    query_path: Option<LitStr>,
+    path: Option<LitStr>,
+    name: Option<LitStr>,
+}
+
+impl WebhookArgs {
+    fn parse(args: TokenStream) -> syn::Result<Self> {
+        let mut parsed = Self { async_events: vec![], sync_events: vec![], query_path: None, path: None, name: None };
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse(args)?;
+        for meta in metas {
+            let Meta::NameValue(name_value) = &meta else {
+                return Err(Error::new(meta.span(), "expected `key = \"value\"`"));
+            };
+            let value = match &name_value.value {
+                syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. }) => value.clone(),
+                other => return Err(Error::new(other.span(), "expected a string")),
+            };
+            let key = name_value.path.get_ident().map(Ident::to_string).unwrap_or_default();
+            let single = |slot: &mut Option<LitStr>| match slot.replace(value.clone()) {
+                Some(_) => Err(Error::new(name_value.span(), format!("`{key}` is given twice"))),
+                None => Ok(()),
+            };
+            match key.as_str() {
+                "event" => parsed.async_events.push(value),
+                "sync_event" => parsed.sync_events.push(value),
+                "query_path" => single(&mut parsed.query_path)?,
+                "path" => single(&mut parsed.path)?,
+                "name" => single(&mut parsed.name)?,
+                _ => return Err(Error::new(name_value.path.span(), "expected `event`, `sync_event`, `query_path`, `path` or `name`")),
+            }
+        }
+        if parsed.async_events.is_empty() && parsed.sync_events.is_empty() {
+            return Err(Error::new(Span::call_site(), "declare at least one `event` or `sync_event`"));
+        }
+        Ok(parsed)
+    }
+}
+
+/// The variant of an event in the syntax of the manifest, `ORDER_CREATED` is `OrderCreated`.
+fn event_variant(event: &LitStr) -> syn::Result<Ident> {
+    let value = event.value();
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+        return Err(Error::new(event.span(), "events are written like in the manifest, e.g. `ORDER_CREATED`"));
+    }
+    Ok(Ident::new(&value.to_upper_camel_case(), event.span()))
+}
+
+/// Declares a webhook handled by the function below it.
+///
+/// ```ignore
+/// #[saleor_webhook(event = "ORDER_CREATED", query_path = "graphql/order_created.graphql")]
+/// async fn order_created(context: &WebhookContext, payload: OrderCreatedPayload) -> StatusCode {
+///     StatusCode::OK
+/// }
+///
+/// let manifest_entry = OrderCreatedWebhook::manifest(base_url);
+/// let router = OrderCreatedWebhook.route(Router::new());
+/// ```
+///
+/// The function takes the payload, optionally behind a `&WebhookContext`, and returns a type
+/// implementing `IntoResponse`. It's kept as is, next to it a unit struct named after it with a
+/// `Webhook` suffix implements `WebhookHandler` and `DeclaredWebhook`, which build the manifest
+/// entry and route deliveries with verified signatures to the function.
+///
+/// * `event` and `sync_event`: the events subscribed to, like in the manifest, can be repeated
+/// * `query_path`: file of the subscription query, relative to the package's `Cargo.toml`
+/// * `path`: where deliveries are sent, `/api/webhooks/{function-name}` by default
+/// * `name`: name of the webhook in the manifest, the name of the function by default
+#[proc_macro_attribute]
+pub fn saleor_webhook(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match WebhookArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let function = syn::parse_macro_input!(item as ItemFn);
+    match expand(args, &function) {
+        Ok(expanded) => quote!(#function #expanded).into(),
+        Err(e) => {
+            let error = e.to_compile_error();
+            quote!(#function #error).into()
+        }
+    }
+}
+
+fn expand(args: WebhookArgs, function: &ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let signature = &function.sig;
+    if signature.asyncness.is_none() {
+        return Err(Error::new(signature.fn_token.span(), "webhook handlers have to be async"));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(Error::new(signature.generics.span(), "webhook handlers can't be generic"));
+    }
+    let Some(query_path) = &args.query_path else {
+        return Err(Error::new(Span::call_site(), "`query_path` names the file of the subscription query"));
+    };
+
+    let arguments: Vec<&Type> = signature
+        .inputs
+        .iter()
+        .map(|input| match input {
+            FnArg::Typed(argument) => Ok(&*argument.ty),
+            FnArg::Receiver(receiver) => Err(Error::new(receiver.span(), "webhook handlers are free functions")),
+        })
+        .collect::<syn::Result<_>>()?;
+    let payload = match arguments[..] {
+        [payload] | [_, payload] => payload,
+        _ => return Err(Error::new(signature.inputs.span(), "expected `(payload)` or `(context: &WebhookContext, payload)`")),
+    };
+    let response = match &signature.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ty) if matches!(**ty, Type::ImplTrait(_)) => {
+            return Err(Error::new(ty.span(), "name the response type, `impl Trait` can't be stored in `WebhookHandler::Response`"))
+        }
+        ReturnType::Type(_, ty) => quote!(#ty),
+    };
+
+    let function_name = &signature.ident;
+    let visibility = &function.vis;
+    let webhook = format_ident!("{}Webhook", function_name.to_string().to_upper_camel_case());
+    let name = args.name.map(|name| name.value()).unwrap_or_else(|| function_name.to_string());
+    let path = args.path.map(|path| path.value()).unwrap_or_else(|| format!("/api/webhooks/{}", function_name.to_string().to_kebab_case()));
+    let async_events = args.async_events.iter().map(event_variant).collect::<syn::Result<Vec<_>>>()?;
+    let sync_events = args.sync_events.iter().map(event_variant).collect::<syn::Result<Vec<_>>>()?;
+    let handle = match arguments.len() {
+        1 => quote!(#function_name(payload).await),
+        _ => quote!(#function_name(context, payload).await),
+    };
+    let doc = format!("The webhook handled by [`{function_name}`], declared with `#[saleor_webhook]`.");
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, Copy, Default)]
+        #visibility struct #webhook;
+
+        #[::saleor_app::__private::async_trait]
+        impl ::saleor_app::saleor::WebhookHandler<#payload> for #webhook {
+            type Response = #response;
+
+            #[allow(unused_variables)]
+            async fn handle(&self, context: &::saleor_app::saleor::WebhookContext, payload: #payload) -> Self::Response {
+                #handle
+            }
+        }
+
+        impl ::saleor_app::saleor::DeclaredWebhook for #webhook {
+            type Payload = #payload;
+            const NAME: &'static str = #name;
+            const PATH: &'static str = #path;
+            const QUERY: &'static str = ::core::include_str!(::core::concat!(::core::env!("CARGO_MANIFEST_DIR"), "/", #query_path));
+
+            fn async_events() -> ::std::vec::Vec<::saleor_app::saleor::SaleorAsyncWebhookEvent> {
+                ::std::vec![#(::saleor_app::saleor::SaleorAsyncWebhookEvent::#async_events),*]
+            }
+
+            fn sync_events() -> ::std::vec::Vec<::saleor_app::saleor::SaleorSyncWebhookEvent> {
+                ::std::vec![#(::saleor_app::saleor::SaleorSyncWebhookEvent::#sync_events),*]
+            }
+        }
+    })
+}
@@ -0,0 +1,105 @@
+//! How operators sign in to the admin routes, `/api/installations` and `/api/admin/*`.
+//!
+//! By default they're dashboard routes like the others, for staff with `MANAGE_APPS` of the
+//! installations in `ADMIN_SALEOR_API_URLS`. Deployments whose operators don't work in a Saleor
+//! dashboard pick another guard with `ADMIN_AUTH`:
+//!
+//! * `api-key`: the `x-operator-api-key` header with `OPERATOR_API_KEY`, like [`crate::operator_api`]
+//! * `oidc` (with the `oidc` feature): a login at the identity provider at `OIDC_ISSUER_URL` with
+//!   the client `OIDC_CLIENT_ID`, see [`oidc`]
+//!
+//! Handlers name who made a change with [`AdminOperator`], whatever the guard.
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts, response::Response, Extension, Router};
+
+use crate::{
+    config::AppConfig,
+    operator_api::OperatorAuthLayer,
+    saleor::{RequestTenant, SaleorAuthLayer, SaleorPermission, TenantAllowlist},
+    secrets::SecretString,
+};
+
+#[cfg(feature = "oidc")]
+pub mod oidc;
+
+/// The guard of the admin routes, configured by `ADMIN_AUTH`.
+#[derive(Debug, Clone, Default)]
+pub enum AdminAuth {
+    /// Dashboard tokens of the installations in `ADMIN_SALEOR_API_URLS`
+    #[default]
+    Dashboard,
+    /// The operator API key
+    ApiKey,
+    Oidc(OidcConfig),
+}
+
+/// Configured by `OIDC_ISSUER_URL`, `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET` and `OIDC_ALLOWED_EMAILS`.
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<SecretString>,
+    /// Emails let in, entries starting with `@` let in the whole domain
+    pub allowed_emails: Vec<String>,
+}
+
+impl OidcConfig {
+    /// Whether an operator with the verified `email` is let in.
+    pub fn allows(&self, email: &str) -> bool {
+        let email = email.to_ascii_lowercase();
+        self.allowed_emails.iter().any(|allowed| {
+            let allowed = allowed.to_ascii_lowercase();
+            match allowed.starts_with('@') {
+                true => email.ends_with(&allowed),
+                false => email == allowed,
+            }
+        })
+    }
+}
+
+/// Who made a request to an admin route, as recorded in the audit log: the installation of the
+/// dashboard, `operator api key` or the email of the operator signed in with OIDC.
+#[derive(Debug, Clone)]
+pub struct AdminOperator(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminOperator
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(operator) = parts.extensions.get::<AdminOperator>() {
+            return Ok(operator.clone());
+        }
+        let RequestTenant(tenant) = RequestTenant::from_request_parts(parts, state).await?;
+        Ok(Self(tenant))
+    }
+}
+
+/// Puts the guard configured by `ADMIN_AUTH` in front of the admin `router`, `admin_tenants` are
+/// the installations let in with [`AdminAuth::Dashboard`].
+pub fn guard(router: Router, config: &AppConfig, admin_tenants: TenantAllowlist) -> anyhow::Result<Router> {
+    match &config.admin_auth {
+        AdminAuth::Dashboard => Ok(router.route_layer(
+            SaleorAuthLayer::with_permissions(&[SaleorPermission::ManageApps])
+                .with_session_expiry(config.sessions.tenant_expiry)
+                .with_tenant_allowlist(admin_tenants),
+        )),
+        AdminAuth::ApiKey => {
+            let api_key = config.operator_api_key.clone().ok_or_else(|| anyhow::anyhow!("ADMIN_AUTH=api-key requires OPERATOR_API_KEY"))?;
+            Ok(router
+                .route_layer(Extension(AdminOperator("operator api key".to_string())))
+                .route_layer(OperatorAuthLayer::new(api_key)))
+        }
+        #[cfg(feature = "oidc")]
+        AdminAuth::Oidc(oidc_config) => {
+            let login = oidc::OidcLogin::new(oidc_config.clone(), config.sessions.tenant_expiry.absolute);
+            Ok(router.route_layer(oidc::OidcAuthLayer::new(login.clone())).merge(oidc::router(login)))
+        }
+        #[cfg(not(feature = "oidc"))]
+        AdminAuth::Oidc(_) => anyhow::bail!("ADMIN_AUTH=oidc requires the oidc feature"),
+    }
+}
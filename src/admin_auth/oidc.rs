@@ -0,0 +1,243 @@
+//! Operators signing in to the admin routes with an OpenID Connect provider.
+//!
+//! `GET /api/admin/login?next=/api/admin/webhook-status` sends the browser to the provider, which
+//! redirects back to `/api/admin/oidc/callback` (register it with the client, under the base url of
+//! the app). The authorization code is exchanged with PKCE, the ID token verified against the keys
+//! of the provider and operators whose verified email is in `OIDC_ALLOWED_EMAILS` are kept in the
+//! session for `SESSION_ABSOLUTE_TIMEOUT_SECS`. The provider is discovered on the first login.
+
+use std::{future::Future, pin::Pin, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{Request, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use openidconnect::{
+    core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
+    reqwest::async_http_client,
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use tower::{Layer, Service};
+use tower_sessions::Session;
+
+use crate::{base_url::BaseUrl, secrets::constant_time_eq};
+
+use super::{AdminOperator, OidcConfig};
+
+/// Starts the login, below `/api`.
+pub const LOGIN_PATH: &str = "/admin/login";
+/// Where the provider redirects to, below `/api`.
+pub const CALLBACK_PATH: &str = "/admin/oidc/callback";
+const PENDING_LOGIN_KEY: &str = "admin_oidc_login";
+const OPERATOR_KEY: &str = "admin_operator";
+
+/// A login waiting for the provider to redirect back.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    state: String,
+    nonce: String,
+    pkce_verifier: String,
+    redirect_url: String,
+    next: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignedInOperator {
+    email: String,
+    authenticated_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// The provider and how long operators stay signed in. Clones share the discovered provider.
+#[derive(Clone)]
+pub struct OidcLogin {
+    config: Arc<OidcConfig>,
+    provider: Arc<OnceCell<CoreProviderMetadata>>,
+    expiry: Duration,
+}
+
+impl OidcLogin {
+    pub fn new(config: OidcConfig, expiry: Duration) -> Self {
+        Self { config: Arc::new(config), provider: Arc::default(), expiry }
+    }
+
+    /// A client redirecting back to `redirect_url`, discovering the provider the first time.
+    async fn client(&self, redirect_url: &str) -> Result<CoreClient, String> {
+        let provider = self
+            .provider
+            .get_or_try_init(|| async {
+                let issuer_url = IssuerUrl::new(self.config.issuer_url.clone()).map_err(|e| e.to_string())?;
+                CoreProviderMetadata::discover_async(issuer_url, async_http_client)
+                    .await
+                    .map_err(|e| format!("unable to discover the OIDC provider: {e}"))
+            })
+            .await?;
+        let redirect_url = RedirectUrl::new(redirect_url.to_string()).map_err(|e| e.to_string())?;
+        let client_secret = self.config.client_secret.as_ref().map(|secret| ClientSecret::new(secret.expose().to_string()));
+        Ok(CoreClient::from_provider_metadata(provider.clone(), ClientId::new(self.config.client_id.clone()), client_secret).set_redirect_uri(redirect_url))
+    }
+
+    /// The operator signed in to `session`, `None` once the login expired.
+    fn operator(&self, session: &Session) -> Option<String> {
+        let operator = session.get::<SignedInOperator>(OPERATOR_KEY).ok().flatten()?;
+        if now().saturating_sub(operator.authenticated_at) > self.expiry.as_secs() {
+            session.remove_value(OPERATOR_KEY);
+            return None;
+        }
+        Some(operator.email)
+    }
+}
+
+/// The login routes, served without the guard.
+pub fn router(login: OidcLogin) -> Router {
+    Router::new()
+        .route(LOGIN_PATH, get(start_login))
+        .route(CALLBACK_PATH, get(finish_login))
+        .with_state(login)
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    next: Option<String>,
+}
+
+/// `GET /api/admin/login?next={path}`, redirects to the provider.
+async fn start_login(State(login): State<OidcLogin>, session: Session, BaseUrl(base_url): BaseUrl, Query(query): Query<LoginQuery>) -> Response {
+    let redirect_url = format!("{base_url}/api{CALLBACK_PATH}");
+    let client = match login.client(&redirect_url).await {
+        Ok(client) => client,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e).into_response(),
+    };
+    // only paths of this app, `//host` would leave it
+    let next = query.next.filter(|next| next.starts_with('/') && !next.starts_with("//")).unwrap_or_else(|| "/".to_string());
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let (authorize_url, state, nonce) = client
+        .authorize_url(CoreAuthenticationFlow::AuthorizationCode, CsrfToken::new_random, Nonce::new_random)
+        .add_scope(Scope::new("email".to_string()))
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+    let pending = PendingLogin {
+        state: state.secret().clone(),
+        nonce: nonce.secret().clone(),
+        pkce_verifier: pkce_verifier.secret().clone(),
+        redirect_url,
+        next,
+    };
+    if let Err(e) = session.insert(PENDING_LOGIN_KEY, pending) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    Redirect::to(authorize_url.as_str()).into_response()
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: String,
+    state: String,
+}
+
+/// `GET /api/admin/oidc/callback`, signs the operator in and redirects to where the login started.
+async fn finish_login(State(login): State<OidcLogin>, session: Session, Query(query): Query<CallbackQuery>) -> Response {
+    let pending = session.get::<PendingLogin>(PENDING_LOGIN_KEY).ok().flatten();
+    session.remove_value(PENDING_LOGIN_KEY);
+    let Some(pending) = pending.filter(|pending| constant_time_eq(pending.state.as_bytes(), query.state.as_bytes())) else {
+        return (StatusCode::BAD_REQUEST, "no login of this browser is waiting for the provider").into_response();
+    };
+    let client = match login.client(&pending.redirect_url).await {
+        Ok(client) => client,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e).into_response(),
+    };
+    let tokens = match client
+        .exchange_code(AuthorizationCode::new(query.code))
+        .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+        .request_async(async_http_client)
+        .await
+    {
+        Ok(tokens) => tokens,
+        Err(e) => return (StatusCode::BAD_GATEWAY, format!("unable to exchange the authorization code: {e}")).into_response(),
+    };
+    let Some(id_token) = tokens.id_token() else {
+        return (StatusCode::BAD_GATEWAY, "the provider didn't return an ID token").into_response();
+    };
+    let algorithms = login.provider.get().map(|provider| provider.id_token_signing_alg_values_supported().clone()).unwrap_or_default();
+    let verifier = client.id_token_verifier().set_allowed_algs(algorithms);
+    let claims = match id_token.claims(&verifier, &Nonce::new(pending.nonce)) {
+        Ok(claims) => claims,
+        Err(e) => return (StatusCode::UNAUTHORIZED, format!("invalid ID token: {e}")).into_response(),
+    };
+
+    let email = match (claims.email(), claims.email_verified()) {
+        (Some(email), Some(true)) => email.as_str().to_string(),
+        _ => return (StatusCode::FORBIDDEN, "the provider didn't verify an email of the operator").into_response(),
+    };
+    if !login.config.allows(&email) {
+        tracing::warn!(email, "OIDC login of an operator not in OIDC_ALLOWED_EMAILS");
+        return (StatusCode::FORBIDDEN, "operator is not allowed to access the admin routes").into_response();
+    }
+    if let Err(e) = session.insert(OPERATOR_KEY, SignedInOperator { email: email.clone(), authenticated_at: now() }) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    tracing::info!(email, "operator signed in");
+    Redirect::to(&pending.next).into_response()
+}
+
+/// Rejects requests of sessions without a signed in operator with `401 Unauthorized`.
+#[derive(Clone)]
+pub struct OidcAuthLayer {
+    login: OidcLogin,
+}
+
+impl OidcAuthLayer {
+    pub fn new(login: OidcLogin) -> Self {
+        Self { login }
+    }
+}
+
+impl<S> Layer<S> for OidcAuthLayer {
+    type Service = OidcAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OidcAuthService { inner, login: self.login.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct OidcAuthService<S> {
+    inner: S,
+    login: OidcLogin,
+}
+
+impl<S> Service<Request<Body>> for OidcAuthService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let operator = req.extensions().get::<Session>().and_then(|session| self.login.operator(session));
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let Some(operator) = operator else {
+                return Ok((StatusCode::UNAUTHORIZED, format!("sign in at /api{LOGIN_PATH}")).into_response());
+            };
+            req.extensions_mut().insert(AdminOperator(operator));
+            inner.call(req).await
+        })
+    }
+}
@@ -0,0 +1,569 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use axum::{Router, routing::{get, post}, response::{IntoResponse, Response}, http::{StatusCode, HeaderMap}, error_handling::HandleErrorLayer, BoxError, Extension, Json};
+use cynic::QueryBuilder;
+use reqwest::Url;
+use tower::ServiceBuilder;
+use tower_http::compression::{CompressionLayer, predicate::{DefaultPredicate, NotForContentType, Predicate}};
+use tower_sessions::Session;
+use tracing::info;
+
+use crate::{
+    admin_auth,
+    APP_ID, APP_VERSION, DEV_MODE,
+    app_settings,
+    assets::{self, AssetManifest, ASSETS_EMBEDDED},
+    audit::{self, AuditEvent, AuditEventKind, AuditLog, FileAuditSink, TracingAuditSink},
+    base_url::{BaseUrl, BaseUrlPolicy},
+    circuit_breaker::CircuitBreakers,
+    concurrency::TenantConcurrency,
+    config::{self, AppConfig},
+    load_shed::{LoadShedding, RouteGroup},
+    cors::DashboardOrigins,
+    email::Mailer,
+    emitter::EventEmitter,
+    events::{self, EventHub},
+    extensions::{ExtensionContext, ExtensionPage, ExtensionPages},
+    feature_flags,
+    error_reporting::{self, ErrorReportingLayer},
+    gdpr::{self, AuditLogDataSource, PersonalDataSources, SettingsDataSource},
+    graphql_cache::GraphqlCache,
+    health::{HealthChecks, Probe, AplHealthCheck, ConfigHealthCheck},
+    http_client::HttpClient,
+    installations,
+    openapi,
+    operator_api,
+    rate_limit::{ClientIp, RateLimitLayer},
+    registration::RegistrationGuard,
+    reload::{self, ConfigReloader},
+    request_id::RequestIdLayer,
+    router_ext::{self, WebhookLimits},
+    security_headers::SecurityHeadersLayer,
+    sessions,
+    telemetry::{self, HttpMetricsLayer, TenantLogContextLayer},
+    webhook_archive::{self, FileArchiveStore, WebhookArchive},
+    webhook_status::{self, WebhookStats},
+    webhooks,
+    ws::{self, WsCommands},
+    saleor::{self, AcceptsJson, bulk_metadata, orders, product_csv, widgets::{self, ProductDetails, Widget}, AplError, AplStore, SaleorManifest, SaleorAppPermission, ExtractRegisterRequest, AuthData, SaleorRegisterResponse, SaleorRegisterErrorCode, SaleorApl, SaleorClientAuthenticationRequest, SaleorAppExtension, SaleorAppExtensionMount, SaleorAppExtensionTarget, verify_jwt_with_jwks, run_operation, JwtValidation, MyId, SaleorAuthLayer, SaleorPermission, SaleorAplLayer, MigratingAplStore, TenantAllowlist, JwksCache, ManifestValidation, manifest_problems, TenantResolvers, TenantStrategy, UserTokens, TENANT_PATH_PREFIX},
+    templating::{self, AppBridgeAction, HtmlTemplate, Page, PageContext, ExtensionContent, HelloContent, ProductWidget, Localizer, Translations, APP_BRIDGE_SCRIPT_PATH},
+    uninstalled::{UninstalledTenants, UninstalledTenantsLayer},
+};
+
+/// The assembled application, `main` only adds process wide parts like the metrics exporter.
+pub struct App {
+    pub router: Router,
+    pub health_checks: HealthChecks,
+    pub events: EventHub,
+    /// Register the commands pages run over `/app/ws` on it, see [`crate::ws`]
+    pub ws_commands: WsCommands,
+    /// Add sinks to it to get the events handlers emit, see [`crate::emitter`]
+    pub emitter: EventEmitter,
+    /// Applies changes of the configuration while the app is running, see [`crate::reload`]
+    pub reloader: ConfigReloader,
+    /// The room of the dashboard and the webhooks of the base app, see [`crate::load_shed`]
+    pub load_shedding: LoadShedding,
+    /// Calls Saleor through the circuit breakers and concurrency budgets of this app, see
+    /// [`crate::circuit_breaker`] and [`crate::concurrency`]
+    pub http_client: HttpClient,
+}
+
+/// Builds the router with every route and middleware of the app.
+pub async fn build(config: &AppConfig, apl_store: impl AplStore) -> anyhow::Result<App> {
+    build_with_routes(config, apl_store, Router::new()).await
+}
+
+/// Like [`build`], serving the `routes` of an app built on this crate behind the same middleware,
+/// so they share its sessions, installations and http client. Their paths must not overlap the
+/// ones of this app.
+pub async fn build_with_routes(config: &AppConfig, apl_store: impl AplStore, routes: Router) -> anyhow::Result<App> {
+    check_manifest(config)?;
+
+    let session_service = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(|_: BoxError| async {
+            StatusCode::BAD_REQUEST
+        }))
+        .layer(sessions::session_layer(&config.sessions).await.context("unable to set up sessions")?);
+
+    let http_client = config.http_client.build().context("unable to set up the http client")?
+        .with_circuit_breakers(CircuitBreakers::new(config.circuit_breaker))
+        .with_concurrency(TenantConcurrency::new(config.concurrency));
+    let mailer = Mailer::from_config(config).context("unable to set up emails")?;
+    let emitter = EventEmitter::from_config(&config.emitter, &http_client);
+    let load_shedding = LoadShedding::new(config.load_shed);
+    saleor::set_graphql_debug(&config.graphql_debug);
+    if let Some(filter) = &config.log_filter {
+        telemetry::set_log_filter(filter)?;
+    }
+    let graphql_cache = GraphqlCache::connect(&config.graphql_cache).await.context("unable to set up the graphql cache")?;
+    let apl_store = MigratingAplStore::from_config(apl_store, config);
+    match apl_store.migrate().await {
+        Ok(0) => {}
+        Ok(migrated) => info!("moved {migrated} installations to the app id {}", config.app_id),
+        // the rest is moved once they're used
+        Err(e) => tracing::warn!("unable to move installations to the app id {}: {}", config.app_id, e),
+    }
+    let apl_layer = SaleorAplLayer::new(apl_store).with_app_id(&config.app_id);
+    http_client.app_tokens().set_apl(apl_layer.apl_store());
+    let health_checks = HealthChecks::new()
+        .with_check(Probe::Readiness, AplHealthCheck::new(apl_layer.apl_store()))
+        .with_check(Probe::Readiness, ConfigHealthCheck::new(config.clone()));
+    let api_auth_layer = |permissions: &[SaleorPermission]| {
+        let layer = SaleorAuthLayer::with_permissions(permissions).with_session_expiry(config.sessions.tenant_expiry);
+        match config.sessions.api_sessions {
+            true => layer,
+            false => layer.bearer_only(),
+        }
+    };
+
+    let registration_guard = RegistrationGuard::new(&config.registration);
+    let rate_limit_layer = RateLimitLayer::new(config.rate_limit.clone());
+    let register_router = Router::new()
+        .route("/register", post(register))
+        .layer(Extension(registration_guard.clone()))
+        .layer(config.limits.register.body_limit())
+        .layer(config.limits.register.timeout())
+        .layer(rate_limit_layer.clone());
+
+    let webhook_rate_limit_layer = RateLimitLayer::new(config.webhook_rate_limit.clone());
+    let webhook_limits = WebhookLimits { load_shed: load_shedding.layer(RouteGroup::Webhooks), rate_limit: webhook_rate_limit_layer.clone() };
+    let webhooks_router = router_ext::webhook_middleware(
+        Router::new().route(webhooks::PRODUCT_UPDATED_PATH, post(webhooks::product_updated)),
+        config,
+    );
+
+    let audit_log = match &config.audit_log_file {
+        Some(path) => AuditLog::new(FileAuditSink::new(path)),
+        None => AuditLog::new(TracingAuditSink),
+    };
+
+    let webhook_archive = match &config.webhook_archive_file {
+        Some(path) => WebhookArchive::new(FileArchiveStore::new(path), config.webhook_archive_retention),
+        None => WebhookArchive::disabled(),
+    };
+
+    let personal_data_sources = PersonalDataSources::new()
+        .with_source(SettingsDataSource)
+        .with_source(AuditLogDataSource::new(audit_log.clone()));
+
+    let error_reporting = error_reporting_layer(config, http_client.clone())?;
+    let events = EventHub::new();
+    let ws_commands = WsCommands::new();
+    let translations = Translations::bundled().context("unable to load translations")?;
+
+    let audit_router = Router::new()
+        .route("/audit", get(audit::audit_events))
+        .route_layer(api_auth_layer(&[SaleorPermission::ManageApps]));
+
+    let orders_router = Router::new()
+        .route(orders::ORDERS_PATH, get(orders::list_orders))
+        .route_layer(api_auth_layer(&[SaleorPermission::ManageOrders]));
+
+    // data subject requests of the installation's customers
+    let personal_data_router = Router::new()
+        .route("/personal-data", get(gdpr::export_personal_data).delete(gdpr::erase_personal_data))
+        .route_layer(api_auth_layer(&[SaleorPermission::ManageApps, SaleorPermission::ManageUsers]));
+
+    // operator pages are only available to the dashboards of ADMIN_SALEOR_API_URLS, unless ADMIN_AUTH picks another guard
+    let admin_tenants = TenantAllowlist::new(&config.admin_api_urls);
+    let admin_router = Router::new()
+        .route("/installations", get(installations::list_installations).delete(installations::remove_installation))
+        .route("/admin/webhooks", get(webhook_archive::search_webhooks))
+        .route("/admin/webhooks/:id/redeliver", post(webhook_archive::redeliver_webhook))
+        .route("/admin/webhook-status", get(webhook_status::webhook_status))
+        .route("/admin/feature-flags", get(feature_flags::get_feature_flags).put(feature_flags::set_feature_flag))
+        .route("/admin/reload", post(reload::reload_config));
+    let admin_router = admin_auth::guard(admin_router, config, admin_tenants.clone())?;
+
+    // support tooling outside of Saleor, only served with OPERATOR_API_KEY
+    if let Some(problem) = config::operator_api_key_problem(config.operator_api_key.as_ref()) {
+        anyhow::bail!(problem);
+    }
+    let operator_router = config.operator_api_key.clone().map(operator_api::router).unwrap_or_default();
+
+    let dashboard_origins = DashboardOrigins::new(&config.cors_origins);
+    let reloader = ConfigReloader::new(config, admin_tenants, dashboard_origins.clone(), rate_limit_layer, registration_guard, audit_log.clone(), http_client.clone())
+        .with_webhook_rate_limit(webhook_rate_limit_layer);
+    let manifest_definition = config.manifest.clone();
+    let manifest_app_id = config.app_id.clone();
+    let api_router = Router::new()
+        .route("/hello", get(api_hello))
+        .route("/config", get(app_settings::get_config).post(app_settings::post_config))
+        .route(product_csv::EXPORT_PATH, get(product_csv::export_products))
+        .route(product_csv::IMPORT_PATH, post(product_csv::import_products))
+        .route(bulk_metadata::BULK_METADATA_PATH, post(bulk_metadata::bulk_metadata))
+        .layer(api_auth_layer(&[SaleorPermission::ManageProducts]))
+        .merge(audit_router)
+        .merge(orders_router)
+        .merge(personal_data_router)
+        .route("/auth", post(auth))
+        .layer(UninstalledTenantsLayer)
+        .layer(load_shedding.layer(RouteGroup::Dashboard))
+        .route("/manifest", get(move |accepts_json: AcceptsJson, base_url: BaseUrl| {
+            let manifest_definition = manifest_definition.clone();
+            let id = manifest_app_id.clone();
+            async move { manifest_definition.apply(SaleorManifest { id, ..manifest(accepts_json, base_url).await }) }
+        }))
+        .merge(register_router)
+        .merge(admin_router)
+        .merge(operator_router)
+        .route("/logout", post(logout))
+        .route("/openapi.json", get(openapi::openapi_json))
+        .layer(dashboard_origins.layer())
+        .layer(dashboard_origins.installations_layer());
+
+    // EventSource and WebSocket can't send the token, the auth layer takes it from the session
+    let events_router = Router::new()
+        .route("/events", get(events::events))
+        .route("/ws", get(ws::socket))
+        .route_layer(SaleorAuthLayer::with_permissions(&[]).with_session_expiry(config.sessions.tenant_expiry))
+        .route_layer(UninstalledTenantsLayer);
+
+    let security_headers_layer = SecurityHeadersLayer::new(&config.frame_ancestors);
+    let app_router = Router::new()
+        .route("/", get(index))
+        .route("/config", get(app_settings::config_page))
+        .route("/installations", get(installations::installations_page))
+        .merge(extension_pages().router(config.sessions.tenant_expiry))
+        // event streams stay open, they'd hold on to their room for good
+        .layer(load_shedding.layer(RouteGroup::Dashboard))
+        .merge(events_router)
+        .layer(security_headers_layer.clone());
+
+    let assets_router = match (DEV_MODE, ASSETS_EMBEDDED) {
+        (true, _) => {
+            // assets change while developing, their urls stay plain
+            assets::set_served_assets(AssetManifest::default());
+            AssetManifest::uncached_router(&config.assets_dir)
+        }
+        (false, true) => {
+            let manifest = AssetManifest::embedded();
+            assets::set_served_assets(manifest.clone());
+            manifest.embedded_router()
+        }
+        (false, false) => {
+            let manifest = AssetManifest::scan(&config.assets_dir).context("unable to scan assets")?;
+            assets::set_served_assets(manifest.clone());
+            manifest.router(&config.assets_dir)
+        }
+    };
+    #[cfg(feature = "swagger-ui")]
+    let api_router = api_router.route("/docs", get(openapi::swagger_ui));
+    let router  = Router::new()
+        .route("/", get(index).layer(security_headers_layer.clone()))
+        .route(APP_BRIDGE_SCRIPT_PATH, get(templating::app_bridge_script))
+        .route(widgets::PRODUCT_DETAILS_WIDGET_PATH, post(product_widget).layer(security_headers_layer))
+        .nest("/app", app_router)
+        .nest("/api", api_router)
+        .merge(webhooks_router)
+        .merge(routes)
+        .layer(config.limits.default.body_limit())
+        .layer(config.limits.default.timeout())
+        .layer(HttpMetricsLayer)
+        .layer(TenantLogContextLayer)
+        .layer(Extension(translations))
+        .layer(Extension(events.clone()))
+        .layer(Extension(ws_commands.clone()))
+        .layer(Extension(audit_log))
+        .layer(Extension(reloader.clone()))
+        .layer(Extension(error_reporting.recent_errors()))
+        .layer(Extension(BaseUrlPolicy::from_config(config)))
+        .layer(Extension(personal_data_sources))
+        .layer(Extension(TenantResolvers::from_strategies(&config.tenant_strategies)))
+        .layer(Extension(http_client.clone()))
+        .layer(Extension(graphql_cache))
+        .layer(Extension(UserTokens::new(config.user_tokens)))
+        .layer(Extension(mailer))
+        .layer(Extension(emitter.clone()))
+        .layer(Extension(JwksCache::with_fetch_config(config.jwks_fetch)))
+        .layer(Extension(config.jwt_validation.clone()))
+        .layer(Extension(config.trusted_proxies.clone()))
+        .layer(Extension(UninstalledTenants::new(config.uninstalled_ttl)))
+        .layer(Extension(webhook_archive.clone()))
+        .layer(Extension(WebhookStats::new()))
+        .layer(Extension(webhook_limits))
+        .layer(Extension(dashboard_origins))
+        .layer(apl_layer)
+        .layer(session_service)
+        .merge(assets_router)
+        .merge(health_checks.clone().router())
+        .layer(error_reporting::catch_panic_layer())
+        .layer(error_reporting)
+        .layer(RequestIdLayer)
+        .layer(CompressionLayer::new().compress_when(
+            // compressed event streams are buffered instead of delivered right away
+            DefaultPredicate::new().and(NotForContentType::const_new("text/event-stream"))
+        ));
+
+    // the path prefix resolver needs every route to answer under `/t/{tenant}` as well
+    let path_prefixed = config.tenant_strategies.iter().any(|strategy| matches!(strategy, TenantStrategy::PathPrefix { .. }));
+    let router = match path_prefixed {
+        true => Router::new().nest(&format!("{TENANT_PATH_PREFIX}/:tenant"), router.clone()).merge(router),
+        false => router,
+    };
+
+    // redeliveries go through the whole router, like the original deliveries
+    if webhook_archive.is_enabled() {
+        webhook_archive.attach(router.clone());
+    }
+
+    info!("router initialized");
+
+    Ok(App { router, health_checks, events, ws_commands, emitter, reloader, load_shedding, http_client })
+}
+
+fn error_reporting_layer(config: &AppConfig, http_client: HttpClient) -> anyhow::Result<ErrorReportingLayer> {
+    if let Some(dsn) = &config.sentry_dsn {
+        info!("reporting errors to sentry");
+        return Ok(ErrorReportingLayer::new(error_reporting::SentryErrorReporter::new(http_client, dsn.expose())?));
+    }
+
+    Ok(ErrorReportingLayer::new(error_reporting::TracingErrorReporter))
+}
+
+#[utoipa::path(get, path = "/api/hello", tag = "app", security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "Greeting with an App Bridge notification", body = String),
+))]
+pub async fn api_hello(i18n: Localizer) -> impl IntoResponse {
+    (AppBridgeAction::success(i18n.t("hello-notification")), "Hello from the API")
+}
+
+pub async fn index(ctx: PageContext) -> impl IntoResponse {
+    let title = ctx.i18n.t("hello-title");
+    let content = HelloContent { i18n: ctx.i18n.clone() };
+    Page::new(ctx, title, content)
+}
+
+/// The pages the dashboard opens from its product pages, served under `/app` and listed in the manifest.
+pub fn extension_pages() -> ExtensionPages {
+    ExtensionPages::new()
+        .with_page(
+            ExtensionPage::new("/products/panel", "Product panel", SaleorAppExtensionMount::ProductDetailsMoreActions)
+                .with_permissions(&[SaleorAppPermission::ManageProducts]),
+            extension_page,
+        )
+        .with_page(
+            ExtensionPage::new("/products/bulk", "Bulk edit products", SaleorAppExtensionMount::ProductOverviewMoreActions)
+                .with_permissions(&[SaleorAppPermission::ManageProducts]),
+            extension_page,
+        )
+        .with_page(
+            ExtensionPage::new("/products/report", "Product report", SaleorAppExtensionMount::ProductDetailsMoreActions)
+                .with_permissions(&[SaleorAppPermission::ManageProducts])
+                .in_new_tab(),
+            extension_page,
+        )
+}
+
+/// `POST /app/widgets/product-details`, a summary of the product on its details page.
+pub async fn product_widget(ctx: PageContext, widget: Widget<ProductDetails>) -> impl IntoResponse {
+    HtmlTemplate(ProductWidget { ctx, product: widget.object })
+}
+
+/// Shows the objects an extension page was opened for.
+pub async fn extension_page(extension: ExtensionContext) -> impl IntoResponse {
+    let content = ExtensionContent { i18n: extension.ctx.i18n.clone(), object_ids: extension.object_ids };
+    Page::new(extension.ctx, extension.page.label, content)
+}
+
+/// `GET /api/manifest`, served at `APP_URL` or the allowed host of the request, see [`crate::base_url`].
+/// `HEAD` answers with the headers only.
+#[utoipa::path(get, path = "/api/manifest", tag = "app", responses(
+    (status = 200, description = "The manifest Saleor installs the app from", body = SaleorManifest, content_type = "application/json; charset=utf-8"),
+    (status = 400, description = "The host of the request is missing, malformed or not in `ALLOWED_HOSTS`"),
+    (status = 406, description = "The `Accept` header rules out JSON"),
+))]
+pub async fn manifest(_: AcceptsJson, BaseUrl(base_url): BaseUrl) -> SaleorManifest {
+    app_manifest(&base_url)
+}
+
+/// Applies [`AppConfig::manifest_validation`] to the manifest served at `APP_URL`, or at the port
+/// on localhost if it's unset.
+fn check_manifest(config: &AppConfig) -> anyhow::Result<()> {
+    let base_url = match &config.app_url {
+        Some(app_url) => app_url.trim_end_matches('/').to_string(),
+        None => format!("http://localhost:{}", config.port),
+    };
+    let problems = manifest_problems(&deployment_manifest(config, &base_url));
+    match config.manifest_validation {
+        ManifestValidation::Strict if !problems.is_empty() => anyhow::bail!("invalid manifest: {}", problems.join(", ")),
+        ManifestValidation::Strict => {}
+        ManifestValidation::Permissive => problems.iter().for_each(|problem| tracing::warn!("manifest: {problem}")),
+    }
+    Ok(())
+}
+
+/// [`app_manifest`] with the overrides of `MANIFEST_FILE`, what the app serves.
+pub fn deployment_manifest(config: &AppConfig, base_url: &str) -> SaleorManifest {
+    config.manifest.apply(SaleorManifest { id: config.app_id.clone(), ..app_manifest(base_url) })
+}
+
+/// The manifest of the app when it's reachable at `base_url`, with the default id [`APP_ID`].
+pub fn app_manifest(base_url: &str) -> SaleorManifest {
+    SaleorManifest {
+        id: APP_ID.to_string(),
+        version: APP_VERSION.to_string(),
+        required_saleor_version: None,
+        name: APP_ID.to_string(),
+        permissions: vec![SaleorAppPermission::ManageProducts],
+        app_url: base_url.to_string(),
+        token_target_url: format!("{}/api/register", base_url),
+        author: None,
+        about: None,
+        data_privacy_url: None,
+        homepage_url: None,
+        support_url: None,
+        extensions: Some(
+            [SaleorAppExtension {
+                label: "Example Extension".to_string(),
+                mount: SaleorAppExtensionMount::ProductOverviewMoreActions,
+                target: SaleorAppExtensionTarget::AppPage,
+                permissions: vec![],
+                url: "/app".to_string(),
+                options: None,
+            }]
+            .into_iter()
+            .chain(extension_pages().manifest_extensions())
+            .chain([widgets::extension::<ProductDetails>("Product summary", widgets::PRODUCT_DETAILS_WIDGET_PATH)])
+            .collect(),
+        ),
+        webhooks: Some(webhooks::manifest(base_url)),
+        brand: None,
+    }
+}
+
+#[utoipa::path(post, path = "/api/register", tag = "app", request_body = SaleorAuthToken, params(
+    ("saleor-domain" = String, Header, description = "Domain of the Saleor instance installing the app"),
+    ("saleor-api-url" = Option<String>, Header, description = "GraphQL API URL of the Saleor instance, derived from the domain for older Saleor versions"),
+), responses(
+    (status = 200, description = "The installation was stored", body = SaleorRegisterResponse),
+    (status = 400, description = "The API URL is invalid or the auth token is missing", body = SaleorRegisterResponse),
+    (status = 401, description = "The JWKS of the instance isn't available", body = SaleorRegisterResponse),
+    (status = 403, description = "The auth token was registered before or the API URL isn't http(s)", body = SaleorRegisterResponse),
+    (status = 429, description = "Too many registrations of the IP or domain", body = SaleorRegisterResponse),
+    (status = 503, description = "The APL is unavailable", body = SaleorRegisterResponse),
+))]
+// axum hands the extractors over as arguments
+#[allow(clippy::too_many_arguments)]
+pub async fn register(
+    apl: SaleorApl,
+    audit_log: AuditLog,
+    client: HttpClient,
+    jwks_cache: JwksCache,
+    guard: RegistrationGuard,
+    ClientIp(client_ip): ClientIp,
+    Extension(dashboard_origins): Extension<DashboardOrigins>,
+    ExtractRegisterRequest(request): ExtractRegisterRequest,
+) -> impl IntoResponse {
+    let reservation = match guard.check(client_ip.as_deref(), &request) {
+        Ok(reservation) => reservation,
+        Err(rejected) => {
+            tracing::warn!(saleor_domain = %request.saleor_domain, client_ip, "rejected registration: {:?}", rejected);
+            return rejected.into_response();
+        }
+    };
+    let Ok(api_url) = Url::parse(&request.saleor_api_url) else {
+        return SaleorRegisterResponse::api_url_parsing_failed();
+    };
+    if api_url.scheme() != "http" && api_url.scheme() != "https" {
+        return SaleorRegisterResponse::saleor_url_prohibited();
+    }
+    let installation = AuthData { app_id: apl.app_id().to_string(), ..AuthData::new(request.saleor_api_url.clone(), request.auth_token.clone()) };
+    let jwks = match jwks_cache.refresh(&client, &installation).await {
+        Ok(jwks) => jwks,
+        Err(e) => {
+            tracing::warn!(saleor_api_url = %request.saleor_api_url, "unable to register installation: {}", e);
+            return SaleorRegisterResponse::jwks_not_available();
+        }
+    };
+
+    let request_domain = request.saleor_domain.clone();
+    let apl_id = apl.id(&request.saleor_api_url);
+    // a rejected token noticed meanwhile must not mark the new one
+    let writes = client.app_tokens().lock_writes().await;
+    let previous = match apl.get(&apl_id).await {
+        Ok(previous) => previous,
+        Err(e) => return apl_unavailable(e),
+    };
+    let kind = match previous {
+        Some(_) => AuditEventKind::Reregistered,
+        None => AuditEventKind::Registered,
+    };
+    let registered_at = SystemTime::now().duration_since(UNIX_EPOCH).ok();
+    // starting from the time keeps generations increasing when an installation is removed and registered again
+    let generation = registered_at
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+        .max(previous.map_or(0, |previous| previous.generation + 1));
+    let auth_data = AuthData {
+        domain: Some(request.saleor_domain),
+        jwks: Some(jwks.raw().to_string()),
+        registered_at: registered_at.map(|since| since.as_secs()),
+        generation,
+        ..installation
+    };
+    let saleor_api_url = auth_data.saleor_api_url.clone();
+    if let Err(e) = apl.set(&apl_id, auth_data).await {
+        return apl_unavailable(e);
+    }
+    dashboard_origins.installations_changed().await;
+    drop(writes);
+    reservation.confirm();
+    audit_log.record(AuditEvent::new(&saleor_api_url, kind).with_detail(format!("domain {}", request_domain)));
+
+    SaleorRegisterResponse::success()
+}
+
+fn apl_unavailable(e: AplError) -> Response {
+    tracing::error!("unable to register installation: {}", e);
+    SaleorRegisterResponse::error(SaleorRegisterErrorCode::AplUnavailable, "unable to store the installation", StatusCode::SERVICE_UNAVAILABLE)
+}
+
+#[utoipa::path(post, path = "/api/auth", tag = "app", request_body = SaleorClientAuthenticationRequest, responses(
+    (status = 200, description = "The dashboard token was verified and stored in the session"),
+    (status = 401, description = "The token is invalid"),
+    (status = 500, description = "The token couldn't be stored in the session"),
+    (status = 503, description = "Saleor's JWKS couldn't be fetched, retry after `Retry-After` seconds"),
+))]
+pub async fn auth(session: Session, apl: SaleorApl, audit_log: AuditLog, client: HttpClient, jwks_cache: JwksCache, jwt_validation: JwtValidation, Json(auth_request): Json<SaleorClientAuthenticationRequest>) -> impl IntoResponse {
+    let auth_data = match apl.get(&apl.id(&auth_request.api_url)).await {
+        Ok(auth_data) => auth_data,
+        Err(e) => return e.into_response(),
+    };
+    telemetry::record_apl_lookup(auth_data.is_some());
+    let Some(auth_data) = auth_data else {
+        return (StatusCode::UNAUTHORIZED, "unknown installation").into_response();
+    };
+    let generation = Some(auth_data.generation);
+    let kid = saleor::jwt_kid(auth_request.token.expose());
+    let jwks = match jwks_cache.jwks_for(&client, &**apl, &auth_data, kid.as_deref()).await {
+        Ok(jwks) => jwks,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = verify_jwt_with_jwks(&jwks, auth_request.token.expose(), &[], &jwt_validation) {
+        audit_log.record(AuditEvent::new(&auth_request.api_url, AuditEventKind::JwtVerificationFailed).with_detail(e.to_string()));
+        return e.into_response();
+    }
+    if let Err(e) = sessions::store_dashboard_token(&session, &auth_request.api_url, &auth_request.token, generation) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    if let Err(e) = run_operation(&client, &auth_data, None, "MyId", MyId::build(())).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+    }
+
+    StatusCode::OK.into_response()
+}
+
+#[utoipa::path(post, path = "/api/logout", tag = "app", params(
+    ("saleor-api-url" = Option<String>, Header, description = "Installation to log out of, all of them if missing"),
+), responses(
+    (status = 204, description = "The session was cleared"),
+))]
+pub async fn logout(session: Session, headers: HeaderMap) -> impl IntoResponse {
+    let api_url = headers.get("saleor-api-url").and_then(|h| h.to_str().ok());
+    sessions::logout(&session, api_url);
+
+    StatusCode::NO_CONTENT
+}
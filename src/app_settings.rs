@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use askama::Template;
+use axum::{response::{IntoResponse, Response}, Form};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{saleor::{SettingsError, TenantSettings}, templating::{AppBridgeAction, HtmlTemplate, Localizer, Page, PageContext}};
+
+const NOTIFICATION_EMAIL: &str = "notification_email";
+const LOW_STOCK_THRESHOLD: &str = "low_stock_threshold";
+const MAX_LOW_STOCK_THRESHOLD: u32 = 100_000;
+
+/// The per installation settings edited on `/app/config`, as submitted by the form.
+#[derive(Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct AppSettingsForm {
+    #[serde(default)]
+    pub notification_email: String,
+    #[serde(default)]
+    pub low_stock_threshold: String,
+}
+
+impl AppSettingsForm {
+    pub fn from_settings(mut settings: HashMap<String, String>) -> Self {
+        Self {
+            notification_email: settings.remove(NOTIFICATION_EMAIL).unwrap_or_default(),
+            low_stock_threshold: settings.remove(LOW_STOCK_THRESHOLD).unwrap_or_default(),
+        }
+    }
+
+    pub fn into_settings(self) -> HashMap<String, String> {
+        HashMap::from([
+            (NOTIFICATION_EMAIL.to_string(), self.notification_email.trim().to_string()),
+            (LOW_STOCK_THRESHOLD.to_string(), self.low_stock_threshold.trim().to_string()),
+        ])
+    }
+
+    pub fn validate(&self) -> FieldErrors {
+        let mut errors = FieldErrors::default();
+
+        let email = self.notification_email.trim();
+        if !email.is_empty() && !is_email(email) {
+            errors.add(NOTIFICATION_EMAIL, "config-error-email");
+        }
+
+        let threshold = self.low_stock_threshold.trim();
+        if !threshold.is_empty() {
+            match threshold.parse::<u32>() {
+                Ok(threshold) if threshold <= MAX_LOW_STOCK_THRESHOLD => {},
+                _ => errors.add(LOW_STOCK_THRESHOLD, "config-error-threshold"),
+            }
+        }
+
+        errors
+    }
+}
+
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') && !value.contains(char::is_whitespace)
+}
+
+/// Validation errors by form field, the values are message ids translated by the template.
+#[derive(Debug, Clone, Default)]
+pub struct FieldErrors(HashMap<&'static str, &'static str>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &'static str, message_id: &'static str) {
+        self.0.insert(field, message_id);
+    }
+
+    pub fn get(&self, field: &str) -> Option<&'static str> {
+        self.0.get(field).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "pages/config.html")]
+pub struct ConfigContent {
+    pub i18n: Localizer,
+}
+
+#[derive(Template)]
+#[template(path = "components/config_form.html")]
+pub struct ConfigForm {
+    pub i18n: Localizer,
+    pub form: AppSettingsForm,
+    pub errors: FieldErrors,
+}
+
+/// `GET /app/config`, the form itself is loaded through [`get_config`] once the dashboard authenticated the page.
+pub async fn config_page(ctx: PageContext) -> impl IntoResponse {
+    let title = ctx.i18n.t("config-title");
+    let content = ConfigContent { i18n: ctx.i18n.clone() };
+    Page::new(ctx, title, content)
+}
+
+/// `GET /api/config`, renders the settings form of the current installation.
+#[utoipa::path(get, path = "/api/config", tag = "settings", security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The settings form", content_type = "text/html", body = String),
+))]
+pub async fn get_config(i18n: Localizer, settings: TenantSettings) -> Result<impl IntoResponse, SettingsError> {
+    let form = AppSettingsForm::from_settings(settings.get_all().await?);
+    Ok(HtmlTemplate(ConfigForm { i18n, form, errors: FieldErrors::default() }))
+}
+
+/// `POST /api/config`, validates and saves the settings, invalid forms are rendered again with their errors.
+#[utoipa::path(post, path = "/api/config", tag = "settings", security(("dashboard_token" = []), ("saleor_api_url" = [])), request_body(content = AppSettingsForm, content_type = "application/x-www-form-urlencoded"), responses(
+    (status = 200, description = "The saved settings, or the form with its errors", content_type = "text/html", body = String),
+))]
+pub async fn post_config(i18n: Localizer, settings: TenantSettings, Form(form): Form<AppSettingsForm>) -> Result<Response, SettingsError> {
+    let errors = form.validate();
+    if !errors.is_empty() {
+        return Ok(HtmlTemplate(ConfigForm { i18n, form, errors }).into_response());
+    }
+
+    settings.set(form.clone().into_settings()).await?;
+    let saved = AppBridgeAction::success(i18n.t("config-saved"));
+    Ok((saved, HtmlTemplate(ConfigForm { i18n, form, errors })).into_response())
+}
@@ -0,0 +1,194 @@
+use std::{collections::HashMap, future::Future, path::{Path, PathBuf}, pin::Pin, sync::{Arc, RwLock}};
+
+use axum::{Router, routing::get, extract::{Path as UrlPath, State}, http::{Request, HeaderValue, StatusCode, header::{CACHE_CONTROL, CONTENT_TYPE, ETAG}}, response::{Response, IntoResponse}, body::Body};
+use tower::{Layer, Service};
+use tower_http::services::ServeDir;
+
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+}
+
+/// Whether the binary was built with the `embed-assets` feature.
+pub const ASSETS_EMBEDDED: bool = cfg!(feature = "embed-assets");
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const REVALIDATE: &str = "no-cache";
+const NO_STORE: &str = "no-store";
+
+/// The manifest of the assets the app serves, `None` until the app is built.
+static SERVED_ASSETS: RwLock<Option<AssetManifest>> = RwLock::new(None);
+
+/// Makes [`asset_url`] build urls with the content hashes of `manifest`.
+pub fn set_served_assets(manifest: AssetManifest) {
+    *SERVED_ASSETS.write().unwrap() = Some(manifest);
+}
+
+/// Url of the served asset at `path` (relative to the assets directory) with its content hash, so
+/// it's cached forever and changes once the file does. Templates use it as `{{ "main.css"|asset }}`.
+pub fn asset_url(path: &str) -> String {
+    SERVED_ASSETS.read().unwrap().clone().unwrap_or_default().url(path)
+}
+
+/// Content hashes of every file in the assets directory, used to build cache busting urls.
+#[derive(Debug, Clone, Default)]
+pub struct AssetManifest {
+    hashes: Arc<HashMap<String, String>>,
+}
+
+impl AssetManifest {
+    pub fn scan(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let dir = dir.as_ref();
+        let mut hashes = HashMap::new();
+        if dir.is_dir() {
+            scan_dir(dir, dir, &mut hashes)?;
+        }
+
+        Ok(Self { hashes: Arc::new(hashes) })
+    }
+
+    /// Manifest of the assets compiled into the binary with the `embed-assets` feature.
+    pub fn embedded() -> Self {
+        let hashes = embedded::EMBEDDED_ASSETS
+            .iter()
+            .map(|(path, contents)| (path.to_string(), content_hash(contents)))
+            .collect();
+
+        Self { hashes: Arc::new(hashes) }
+    }
+
+    pub fn hash(&self, path: &str) -> Option<&str> {
+        self.hashes.get(path.trim_start_matches('/')).map(String::as_str)
+    }
+
+    /// Url of the asset including its content hash, falls back to the plain url for unknown assets.
+    pub fn url(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        match self.hash(path) {
+            Some(hash) => format!("/assets/{path}?v={hash}"),
+            None => format!("/assets/{path}"),
+        }
+    }
+
+    /// Serves the assets directory, responses requested with the current content hash are cached forever.
+    pub fn router(self, dir: impl Into<PathBuf>) -> Router {
+        Router::new()
+            .nest_service("/assets", ServeDir::new(dir.into()))
+            .layer(AssetCacheLayer { manifest: self, no_store: false })
+    }
+
+    /// Serves the assets directory without letting browsers cache anything, for development.
+    pub fn uncached_router(dir: impl Into<PathBuf>) -> Router {
+        Router::new()
+            .nest_service("/assets", ServeDir::new(dir.into()))
+            .layer(AssetCacheLayer { manifest: Self::default(), no_store: true })
+    }
+
+    /// Serves the assets compiled into the binary, see [`AssetManifest::embedded`].
+    pub fn embedded_router(self) -> Router {
+        Router::new()
+            .route("/assets/*path", get(serve_embedded))
+            .with_state(self.clone())
+            .layer(AssetCacheLayer { manifest: self, no_store: false })
+    }
+}
+
+async fn serve_embedded(State(manifest): State<AssetManifest>, UrlPath(path): UrlPath<String>) -> Response {
+    let Some((_, contents)) = embedded::EMBEDDED_ASSETS.iter().find(|(asset, _)| *asset == path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let content_type = mime_guess::from_path(&path).first_or_octet_stream().to_string();
+    let etag = manifest.hash(&path).map(|hash| format!("\"{hash}\"")).unwrap_or_default();
+
+    ([(CONTENT_TYPE, content_type), (ETAG, etag)], *contents).into_response()
+}
+
+fn content_hash(contents: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, contents);
+    digest.as_ref()[..8].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn scan_dir(root: &Path, dir: &Path, hashes: &mut HashMap<String, String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            scan_dir(root, &path, hashes)?;
+            continue;
+        }
+
+        let hash = content_hash(&std::fs::read(&path)?);
+        let relative = path
+            .strip_prefix(root)
+            .expect("scanned path is inside the assets directory")
+            .to_string_lossy()
+            .replace('\\', "/");
+        hashes.insert(relative, hash);
+    }
+
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct AssetCacheLayer {
+    manifest: AssetManifest,
+    no_store: bool,
+}
+
+impl<S> Layer<S> for AssetCacheLayer {
+    type Service = AssetCacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AssetCacheService {
+            inner,
+            manifest: self.manifest.clone(),
+            no_store: self.no_store,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AssetCacheService<S> {
+    inner: S,
+    manifest: AssetManifest,
+    no_store: bool,
+}
+
+impl<S> Service<Request<Body>> for AssetCacheService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let manifest = self.manifest.clone();
+        let no_store = self.no_store;
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let path = req.uri().path().trim_start_matches("/assets").trim_start_matches('/').to_string();
+            let requested_hash = req
+                .uri()
+                .query()
+                .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("v=")))
+                .map(|hash| hash.to_string());
+
+            let mut response: Response = inner.call(req).await?;
+            if response.status().is_success() {
+                let cache_control = match (requested_hash.as_deref(), manifest.hash(&path)) {
+                    _ if no_store => NO_STORE,
+                    (Some(requested), Some(current)) if requested == current => IMMUTABLE,
+                    _ => REVALIDATE,
+                };
+                response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+            }
+
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,286 @@
+use std::{path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, extract::{FromRequestParts, Query}, response::{IntoResponse, Response}, Json};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, sync::Mutex};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{saleor::RequestTenant, secrets};
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+pub use crate::secrets::REDACTED;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuditEventKind {
+    Registered,
+    /// The app was registered again for an installation that was already known
+    Reregistered,
+    Uninstalled,
+    JwtVerificationFailed,
+    PermissionDenied,
+    /// The data the app keeps about a customer was exported, see [`crate::gdpr`]
+    PersonalDataExported,
+    /// The data the app keeps about a customer was erased
+    PersonalDataErased,
+    /// A [`crate::feature_flags`] flag was set or unset by an operator
+    FeatureFlagChanged,
+    /// An APL entry was created or changed through the [`crate::operator_api`]
+    AplEntryChanged,
+    /// Settings changed by reloading the configuration, see [`crate::reload`]
+    ConfigReloaded,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    pub saleor_api_url: String,
+    pub kind: AuditEventKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(saleor_api_url: &str, kind: AuditEventKind) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            saleor_api_url: saleor_api_url.to_string(),
+            kind,
+            detail: None,
+            request_id: None,
+        }
+    }
+
+    /// Adds a detail, tokens in it are redacted since events end up in logs.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        let detail = detail.into();
+        self.detail = Some(secrets::redact_tokens(&detail).into_owned());
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: Option<&str>) -> Self {
+        self.request_id = request_id.map(str::to_string);
+        self
+    }
+}
+
+/// Where audit events end up, implement it to keep them in a database.
+#[async_trait]
+pub trait AuditSink: Send + Sync + 'static {
+    async fn record(&self, event: &AuditEvent);
+
+    /// The latest events of an installation, oldest first.
+    async fn query(&self, saleor_api_url: &str, limit: usize) -> Vec<AuditEvent>;
+
+    /// Replaces `text` in the details of the installation's events, returning how many were changed.
+    ///
+    /// Events are never removed, only personal data is taken out of them. Sinks that can't change
+    /// what they recorded keep the default.
+    async fn redact(&self, _saleor_api_url: &str, _text: &str) -> std::io::Result<usize> {
+        Ok(0)
+    }
+}
+
+/// Replaces every occurrence of `text` in `detail`, ignoring ASCII case like email addresses do.
+fn redact_detail(detail: &str, text: &str) -> Option<String> {
+    if text.is_empty() {
+        return None;
+    }
+    let haystack = detail.to_ascii_lowercase();
+    let needle = text.to_ascii_lowercase();
+    let mut redacted = String::with_capacity(detail.len());
+    let mut last = 0;
+    for (start, _) in haystack.match_indices(&needle) {
+        redacted.push_str(&detail[last..start]);
+        redacted.push_str(REDACTED);
+        last = start + needle.len();
+    }
+    if last == 0 {
+        return None;
+    }
+    redacted.push_str(&detail[last..]);
+    Some(redacted)
+}
+
+/// Logs audit events, they can't be queried afterwards.
+pub struct TracingAuditSink;
+
+#[async_trait]
+impl AuditSink for TracingAuditSink {
+    async fn record(&self, event: &AuditEvent) {
+        tracing::info!(
+            target: "audit",
+            saleor_api_url = %event.saleor_api_url,
+            kind = ?event.kind,
+            detail = event.detail.as_deref(),
+            request_id = event.request_id.as_deref(),
+            "audit event"
+        );
+    }
+
+    async fn query(&self, _saleor_api_url: &str, _limit: usize) -> Vec<AuditEvent> {
+        vec![]
+    }
+}
+
+/// Appends audit events to a file, one JSON object per line.
+pub struct FileAuditSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileAuditSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileAuditSink {
+    async fn record(&self, event: &AuditEvent) {
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        line.push('\n');
+
+        let _guard = self.lock.lock().await;
+        let file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await;
+        let result = match file {
+            Ok(mut file) => file.write_all(line.as_bytes()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("unable to write audit event to {}: {}", self.path.display(), e);
+        }
+    }
+
+    async fn query(&self, saleor_api_url: &str, limit: usize) -> Vec<AuditEvent> {
+        let _guard = self.lock.lock().await;
+        let Ok(file) = tokio::fs::File::open(&self.path).await else {
+            return vec![];
+        };
+
+        let mut events = std::collections::VecDeque::with_capacity(limit.min(DEFAULT_QUERY_LIMIT));
+        let mut lines = BufReader::new(file).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let Ok(event) = serde_json::from_str::<AuditEvent>(&line) else {
+                continue;
+            };
+            if event.saleor_api_url != saleor_api_url {
+                continue;
+            }
+            if events.len() == limit {
+                events.pop_front();
+            }
+            events.push_back(event);
+        }
+
+        events.into()
+    }
+
+    async fn redact(&self, saleor_api_url: &str, text: &str) -> std::io::Result<usize> {
+        let _guard = self.lock.lock().await;
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let mut redacted = 0;
+        let mut rewritten = String::with_capacity(content.len());
+        for line in content.lines() {
+            let event = serde_json::from_str::<AuditEvent>(line).ok().filter(|event| event.saleor_api_url == saleor_api_url);
+            let detail = event.as_ref().and_then(|event| redact_detail(event.detail.as_deref()?, text));
+            match (event, detail) {
+                (Some(event), Some(detail)) => {
+                    rewritten.push_str(&serde_json::to_string(&AuditEvent { detail: Some(detail), ..event })?);
+                    redacted += 1;
+                }
+                _ => rewritten.push_str(line),
+            }
+            rewritten.push('\n');
+        }
+        if redacted == 0 {
+            return Ok(0);
+        }
+
+        // like the file APL, a crash while writing must not lose the log
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        let result = async {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            file.write_all(rewritten.as_bytes()).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp_path, &self.path).await
+        }
+        .await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+        result.map(|_| redacted)
+    }
+}
+
+/// The append only audit log, provided to handlers and middlewares as a request extension.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<dyn AuditSink>,
+}
+
+impl AuditLog {
+    pub fn new(sink: impl AuditSink) -> Self {
+        Self { sink: Arc::new(sink) }
+    }
+
+    /// Records the event in the background, auditing never delays or fails a request.
+    pub fn record(&self, event: AuditEvent) {
+        let sink = self.sink.clone();
+        tokio::spawn(async move {
+            sink.record(&event).await;
+        });
+    }
+
+    pub async fn query(&self, saleor_api_url: &str, limit: usize) -> Vec<AuditEvent> {
+        self.sink.query(saleor_api_url, limit).await
+    }
+
+    pub async fn redact(&self, saleor_api_url: &str, text: &str) -> std::io::Result<usize> {
+        self.sink.redact(saleor_api_url, text).await
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuditLog
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<AuditLog>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "audit log not found in request extensions").into_response())
+    }
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct AuditQuery {
+    pub limit: Option<usize>,
+}
+
+/// `GET /api/audit`, the latest audit events of the current installation.
+#[utoipa::path(get, path = "/api/audit", tag = "admin", params(AuditQuery), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The latest audit events, newest first", body = [AuditEvent]),
+))]
+pub async fn audit_events(audit_log: AuditLog, RequestTenant(saleor_api_url): RequestTenant, Query(query): Query<AuditQuery>) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(1000);
+    Json(audit_log.query(&saleor_api_url, limit).await)
+}
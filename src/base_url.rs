@@ -0,0 +1,155 @@
+//! Where the app is reachable, for urls handed to Saleor and the dashboard like the ones of the
+//! manifest.
+//!
+//! `APP_URL` always wins. Without it the url is taken from the request, `x-forwarded-host` or
+//! `Host` and `x-forwarded-proto` (`https` if missing). The `x-forwarded-*` headers are only
+//! honoured when the connection comes from one of the [`TrustedProxies`], `Host` is used otherwise.
+//! Anyone can still send `Host`: set `ALLOWED_HOSTS` so a forged host can't end up in the urls
+//! Saleor calls. Requests with several or malformed host values are rejected instead of guessing.
+
+use std::{fmt, net::SocketAddr, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::HOST, request::Parts, uri::Authority, Extensions, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::AppConfig, rate_limit::TrustedProxies};
+
+const FORWARDED_HOST_HEADER: &str = "x-forwarded-host";
+const FORWARDED_PROTO_HEADER: &str = "x-forwarded-proto";
+
+#[derive(Debug)]
+pub struct BaseUrlError(pub String);
+
+impl fmt::Display for BaseUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "base url error: {}", self.0)
+    }
+}
+
+impl std::error::Error for BaseUrlError {}
+
+impl IntoResponse for BaseUrlError {
+    fn into_response(self) -> Response {
+        tracing::warn!("{}", self);
+        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+    }
+}
+
+/// How the base url of requests is determined, added to the request extensions by
+/// [`crate::app::build`]. Routers without it take any host.
+#[derive(Debug, Clone, Default)]
+pub struct BaseUrlPolicy {
+    canonical: Option<Arc<str>>,
+    allowed_hosts: Arc<[String]>,
+}
+
+impl BaseUrlPolicy {
+    /// `canonical` is used for every request, `allowed_hosts` limit the hosts taken from requests
+    /// without it, `*.example.com` allows every subdomain.
+    pub fn new(canonical: Option<&str>, allowed_hosts: &[String]) -> Self {
+        Self {
+            canonical: canonical.map(|url| url.trim_end_matches('/').into()),
+            allowed_hosts: allowed_hosts.iter().map(|host| host.to_ascii_lowercase()).collect(),
+        }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::new(config.app_url.as_deref(), &config.allowed_hosts)
+    }
+
+    pub fn is_allowed(&self, authority: &Authority) -> bool {
+        if self.allowed_hosts.is_empty() {
+            return true;
+        }
+        let authority = authority.as_str().to_ascii_lowercase();
+        let host = authority.rsplit_once(':').filter(|(_, port)| port.parse::<u16>().is_ok()).map_or(authority.as_str(), |(host, _)| host);
+        self.allowed_hosts.iter().any(|allowed| match allowed.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+            None => allowed == host || *allowed == authority,
+        })
+    }
+
+    /// The base url of a request with these headers and extensions, without a trailing slash.
+    pub fn base_url(&self, headers: &HeaderMap, extensions: &Extensions) -> Result<String, BaseUrlError> {
+        if let Some(canonical) = &self.canonical {
+            return Ok(canonical.to_string());
+        }
+
+        let proxied = from_trusted_proxy(extensions);
+        let forwarded = |name| match proxied {
+            true => single_value(headers, name),
+            false => Ok(None),
+        };
+        let host = match forwarded(FORWARDED_HOST_HEADER)? {
+            Some(host) => host,
+            None => single_value(headers, HOST.as_str())?.ok_or_else(|| BaseUrlError("missing host header".to_string()))?,
+        };
+        let authority = Authority::from_str(host)
+            .ok()
+            .filter(|authority| !authority.as_str().contains('@'))
+            .ok_or_else(|| BaseUrlError(format!("invalid host {host:?}")))?;
+        if !self.is_allowed(&authority) {
+            return Err(BaseUrlError(format!("host {host:?} is not in ALLOWED_HOSTS")));
+        }
+
+        let scheme = match forwarded(FORWARDED_PROTO_HEADER)?.map(str::to_ascii_lowercase).as_deref() {
+            None => "https",
+            Some("https") => "https",
+            Some("http") => "http",
+            Some(other) => return Err(BaseUrlError(format!("invalid {FORWARDED_PROTO_HEADER} {other:?}"))),
+        };
+        Ok(format!("{scheme}://{authority}"))
+    }
+
+    /// Like [`Self::base_url`] with the policy of the request extensions.
+    pub fn base_url_of(headers: &HeaderMap, extensions: &Extensions) -> Result<String, BaseUrlError> {
+        extensions.get::<BaseUrlPolicy>().cloned().unwrap_or_default().base_url(headers, extensions)
+    }
+}
+
+/// Whether the connection of the request comes from one of the [`TrustedProxies`] in its extensions.
+fn from_trusted_proxy(extensions: &Extensions) -> bool {
+    let Some(ConnectInfo(peer)) = extensions.get::<ConnectInfo<SocketAddr>>() else {
+        return false;
+    };
+    extensions.get::<TrustedProxies>().is_some_and(|trusted| trusted.contains(peer.ip()))
+}
+
+/// The only value of a header, proxies append theirs to `x-forwarded-*` headers as a comma separated
+/// list of which the first one is the client's. Several headers of the same name are rejected.
+fn single_value<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, BaseUrlError> {
+    let mut values = headers.get_all(name).iter();
+    let Some(value) = values.next() else {
+        return Ok(None);
+    };
+    if values.next().is_some() {
+        return Err(BaseUrlError(format!("several {name} headers")));
+    }
+    let value = value.to_str().map_err(|_| BaseUrlError(format!("{name} is not valid UTF-8")))?;
+    let value = match name.starts_with("x-forwarded-") {
+        true => value.split(',').next().unwrap_or_default().trim(),
+        false => value.trim(),
+    };
+    Ok(Some(value).filter(|value| !value.is_empty()))
+}
+
+/// Where the app is reachable for the client of the request, see the [module](self) docs.
+/// Requests whose host can't be trusted are rejected with `400 Bad Request`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseUrl(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for BaseUrl
+where
+    S: Send + Sync,
+{
+    type Rejection = BaseUrlError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        BaseUrlPolicy::base_url_of(&parts.headers, &parts.extensions).map(BaseUrl)
+    }
+}
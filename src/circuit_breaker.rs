@@ -0,0 +1,252 @@
+//! Per installation circuit breakers around the calls to Saleor.
+//!
+//! When the Saleor instance of an installation keeps failing, its breaker opens and calls fail right
+//! away for a while instead of piling up retries against an instance that is down. Afterwards a few
+//! probe calls are let through (half-open): the breaker closes once one succeeds and opens again
+//! when one fails. Every app has its own breakers, GraphQL requests and JWKS fetches go through the
+//! ones of its [`crate::http_client::HttpClient`], the state of every breaker is exported as the
+//! `saleor_circuit_breaker_state` gauge.
+//!
+//! Only installations found in the APL get a breaker, the api url of other requests can be
+//! anything. Breakers that closed again or weren't used for a while are dropped.
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use axum::{http::header::RETRY_AFTER, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+
+use crate::{saleor::AuthData, telemetry};
+
+/// How long a breaker that still counts failures is kept without calls.
+const IDLE_AFTER: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures opening the breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker fails calls before probing
+    pub open_for: Duration,
+    /// Probe calls let through at the same time while half-open
+    pub probes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_for: Duration::from_secs(30),
+            probes: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// A call was refused because the installation's breaker is open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitOpenError {
+    pub saleor_api_url: String,
+    /// When the breaker lets the next probe through
+    pub retry_after: Duration,
+}
+
+impl std::fmt::Display for CircuitOpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "circuit breaker error: {} is failing, calls are paused for {:?}", self.saleor_api_url, self.retry_after)
+    }
+}
+
+impl std::error::Error for CircuitOpenError {}
+
+impl IntoResponse for CircuitOpenError {
+    fn into_response(self) -> Response {
+        let retry_after = self.retry_after.as_secs().max(1).to_string();
+        (StatusCode::SERVICE_UNAVAILABLE, [(RETRY_AFTER, retry_after)], self.to_string()).into_response()
+    }
+}
+
+#[derive(Debug)]
+struct Breaker {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probes_in_flight: u32,
+    last_call: Instant,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self { consecutive_failures: 0, opened_at: None, probes_in_flight: 0, last_call: Instant::now() }
+    }
+}
+
+impl Breaker {
+    /// Whether the breaker is no different from a new one.
+    fn is_reset(&self) -> bool {
+        self.consecutive_failures == 0 && self.opened_at.is_none() && self.probes_in_flight == 0
+    }
+
+    fn is_idle(&self, config: &CircuitBreakerConfig) -> bool {
+        self.probes_in_flight == 0 && self.last_call.elapsed() > IDLE_AFTER.max(config.open_for)
+    }
+
+    fn state(&self, config: &CircuitBreakerConfig) -> BreakerState {
+        match self.opened_at {
+            None => BreakerState::Closed,
+            Some(opened_at) if opened_at.elapsed() < config.open_for => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    config: CircuitBreakerConfig,
+    breakers: HashMap<String, Breaker>,
+}
+
+/// The breakers of the installations of an app. Clones share them.
+#[derive(Clone, Default)]
+pub struct CircuitBreakers {
+    state: Arc<Mutex<State>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        let breakers = Self::default();
+        breakers.set_config(config);
+        breakers
+    }
+
+    pub fn set_config(&self, config: CircuitBreakerConfig) {
+        self.state.lock().unwrap().config = config;
+    }
+
+    pub fn state(&self, saleor_api_url: &str) -> BreakerState {
+        let state = self.state.lock().unwrap();
+        state.breakers.get(saleor_api_url).map_or(BreakerState::Closed, |breaker| breaker.state(&state.config))
+    }
+
+    /// Installations that have a breaker right now.
+    pub fn tracked(&self) -> usize {
+        self.state.lock().unwrap().breakers.len()
+    }
+
+    /// Asks to call the Saleor instance of an installation, the outcome is reported through the
+    /// returned [`BreakerCall`].
+    pub fn allow(&self, installation: &AuthData) -> Result<BreakerCall, CircuitOpenError> {
+        let saleor_api_url = installation.saleor_api_url.as_str();
+        let mut state = self.state.lock().unwrap();
+        let config = state.config;
+        if !state.breakers.contains_key(saleor_api_url) {
+            state.breakers.retain(|saleor_api_url, breaker| {
+                let idle = breaker.is_idle(&config);
+                if idle {
+                    telemetry::set_circuit_breaker_state(saleor_api_url, BreakerState::Closed);
+                }
+                !idle
+            });
+        }
+        let breaker = state.breakers.entry(saleor_api_url.to_string()).or_default();
+        breaker.last_call = Instant::now();
+        let probe = match breaker.state(&config) {
+            BreakerState::Closed => false,
+            BreakerState::HalfOpen if breaker.probes_in_flight < config.probes => {
+                breaker.probes_in_flight += 1;
+                telemetry::set_circuit_breaker_state(saleor_api_url, BreakerState::HalfOpen);
+                true
+            }
+            BreakerState::Open | BreakerState::HalfOpen => {
+                telemetry::record_circuit_breaker_rejection(saleor_api_url);
+                let open_for_more = breaker.opened_at.map_or(Duration::ZERO, |opened_at| config.open_for.saturating_sub(opened_at.elapsed()));
+                return Err(CircuitOpenError {
+                    saleor_api_url: saleor_api_url.to_string(),
+                    retry_after: open_for_more,
+                });
+            }
+        };
+        Ok(BreakerCall {
+            breakers: self.clone(),
+            saleor_api_url: saleor_api_url.to_string(),
+            probe,
+            recorded: false,
+        })
+    }
+
+    fn record(&self, saleor_api_url: &str, probe: bool, outcome: Option<bool>) {
+        let mut state = self.state.lock().unwrap();
+        let config = state.config;
+        // the breaker is gone if it was reset while the call was running
+        let breaker = state.breakers.entry(saleor_api_url.to_string()).or_default();
+        if probe {
+            breaker.probes_in_flight = breaker.probes_in_flight.saturating_sub(1);
+        }
+        let before = breaker.state(&config);
+        match outcome {
+            Some(true) => {
+                breaker.consecutive_failures = 0;
+                // calls that started before the breaker opened don't close it
+                if probe || before == BreakerState::Closed {
+                    breaker.opened_at = None;
+                }
+            }
+            Some(false) => {
+                breaker.consecutive_failures += 1;
+                if probe || (before == BreakerState::Closed && breaker.consecutive_failures >= config.failure_threshold) {
+                    breaker.opened_at = Some(Instant::now());
+                }
+            }
+            None => {}
+        }
+        let after = breaker.state(&config);
+        if before != after {
+            match after {
+                BreakerState::Open => tracing::warn!(saleor_api_url, "circuit breaker opened after {} failures", breaker.consecutive_failures),
+                _ => tracing::info!(saleor_api_url, "circuit breaker {}", after.as_str()),
+            }
+        }
+        telemetry::set_circuit_breaker_state(saleor_api_url, after);
+        if breaker.is_reset() {
+            state.breakers.remove(saleor_api_url);
+        }
+    }
+}
+
+/// A call let through by a breaker. Dropping it without reporting the outcome, like when the
+/// request was cancelled, doesn't count as a failure.
+pub struct BreakerCall {
+    breakers: CircuitBreakers,
+    saleor_api_url: String,
+    probe: bool,
+    recorded: bool,
+}
+
+impl BreakerCall {
+    /// Reports whether Saleor answered. Errors Saleor answered with, like GraphQL errors, are healthy.
+    pub fn record(mut self, healthy: bool) {
+        self.recorded = true;
+        self.breakers.record(&self.saleor_api_url, self.probe, Some(healthy));
+    }
+}
+
+impl Drop for BreakerCall {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breakers.record(&self.saleor_api_url, self.probe, None);
+        }
+    }
+}
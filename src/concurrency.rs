@@ -0,0 +1,301 @@
+//! Per installation limits of concurrent GraphQL requests and webhook handling.
+//!
+//! Every installation gets its own budget, so a busy Saleor instance, like one in the middle of a
+//! bulk import, waits for its own requests to finish instead of starving the other installations.
+//! The limits are configured per app (`MAX_CONCURRENT_GRAPHQL`, `MAX_CONCURRENT_WEBHOOKS`) and can
+//! be overridden per installation in its settings, see [`MAX_CONCURRENT_GRAPHQL_SETTING`]. Every app
+//! has its own budgets, calls to Saleor use the ones of its [`crate::http_client::HttpClient`]. The
+//! budget of an installation is dropped once none of its work is running or waiting.
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use axum::{body::Body, http::Request, response::Response};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{Layer, Service};
+
+use crate::{http_client::HttpClient, saleor::{request_tenant, MetadataSettingsManager, SaleorApl, SettingsManager, TenantRequest}};
+
+/// Setting overriding [`ConcurrencyLimits::graphql`] for an installation
+pub const MAX_CONCURRENT_GRAPHQL_SETTING: &str = "max_concurrent_graphql";
+/// Setting overriding [`ConcurrencyLimits::webhooks`] for an installation
+pub const MAX_CONCURRENT_WEBHOOKS_SETTING: &str = "max_concurrent_webhooks";
+/// How long the limits read from the settings of an installation are used before they're read again.
+const SETTINGS_REFRESH: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConcurrencyLimits {
+    /// GraphQL requests to the Saleor instance of an installation at the same time
+    pub graphql: usize,
+    /// Webhooks of an installation handled at the same time
+    pub webhooks: usize,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self { graphql: 8, webhooks: 16 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Workload {
+    Graphql,
+    Webhooks,
+}
+
+impl Workload {
+    fn limit(self, limits: &ConcurrencyLimits) -> usize {
+        match self {
+            Self::Graphql => limits.graphql,
+            Self::Webhooks => limits.webhooks,
+        }
+    }
+
+    fn setting(self) -> &'static str {
+        match self {
+            Self::Graphql => MAX_CONCURRENT_GRAPHQL_SETTING,
+            Self::Webhooks => MAX_CONCURRENT_WEBHOOKS_SETTING,
+        }
+    }
+}
+
+/// The room of an installation for a workload.
+struct Budget {
+    limit: usize,
+    semaphore: Arc<Semaphore>,
+    /// Work waiting for room
+    waiting: usize,
+    /// Work waiting for room or running
+    users: usize,
+}
+
+#[derive(Default)]
+struct State {
+    limits: ConcurrencyLimits,
+    /// Limits of installations that override the ones of the app
+    overrides: HashMap<(String, Workload), usize>,
+    /// When the settings of an installation were last read
+    loaded_at: HashMap<String, Instant>,
+    budgets: HashMap<(String, Workload), Budget>,
+}
+
+/// The budgets of every installation. Clones share them.
+#[derive(Clone, Default)]
+pub struct TenantConcurrency {
+    state: Arc<Mutex<State>>,
+}
+
+impl TenantConcurrency {
+    pub fn new(limits: ConcurrencyLimits) -> Self {
+        let concurrency = Self::default();
+        concurrency.set_limits(limits);
+        concurrency
+    }
+
+    /// Changes the limits of installations without their own.
+    pub fn set_limits(&self, limits: ConcurrencyLimits) {
+        self.state.lock().unwrap().limits = limits;
+    }
+
+    /// Overrides the limit of an installation, `None` goes back to the one of the app. Work that already
+    /// started is finished, new work waits until it fits the new limit.
+    pub fn set_tenant_limit(&self, saleor_api_url: &str, workload: Workload, limit: Option<usize>) {
+        let mut state = self.state.lock().unwrap();
+        let key = (saleor_api_url.to_string(), workload);
+        match limit {
+            Some(limit) => state.overrides.insert(key, limit.max(1)),
+            None => state.overrides.remove(&key),
+        };
+    }
+
+    /// Takes the overrides of an installation from its settings, invalid and missing ones are removed.
+    pub fn apply_settings(&self, saleor_api_url: &str, settings: &HashMap<String, String>) {
+        for workload in [Workload::Graphql, Workload::Webhooks] {
+            let limit = settings.get(workload.setting()).and_then(|limit| limit.trim().parse().ok()).filter(|limit| *limit > 0);
+            self.set_tenant_limit(saleor_api_url, workload, limit);
+        }
+        self.mark_loaded(saleor_api_url);
+    }
+
+    fn mark_loaded(&self, saleor_api_url: &str) {
+        let mut state = self.state.lock().unwrap();
+        // outdated entries are read again anyway
+        state.loaded_at.retain(|_, loaded_at| loaded_at.elapsed() <= SETTINGS_REFRESH);
+        state.loaded_at.insert(saleor_api_url.to_string(), Instant::now());
+    }
+
+    /// Forgets the overrides of something that isn't an installation (anymore).
+    fn forget(&self, saleor_api_url: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.loaded_at.remove(saleor_api_url);
+        state.overrides.retain(|(url, _), _| url != saleor_api_url);
+    }
+
+    /// Whether the settings of the installation should be read (again).
+    fn settings_outdated(&self, saleor_api_url: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .loaded_at
+            .get(saleor_api_url)
+            .is_none_or(|loaded_at| loaded_at.elapsed() > SETTINGS_REFRESH)
+    }
+
+    /// The current limit of the installation.
+    pub fn limit(&self, saleor_api_url: &str, workload: Workload) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .overrides
+            .get(&(saleor_api_url.to_string(), workload))
+            .copied()
+            .unwrap_or_else(|| workload.limit(&state.limits))
+    }
+
+    /// Waits until the installation has room for more work, it's done when the permit is dropped.
+    pub async fn acquire(&self, saleor_api_url: &str, workload: Workload) -> TenantPermit {
+        let limit = self.limit(saleor_api_url, workload);
+        let key = (saleor_api_url.to_string(), workload);
+        let semaphore = {
+            let mut state = self.state.lock().unwrap();
+            let budget = state.budgets.entry(key.clone()).or_insert_with(|| Budget {
+                limit,
+                semaphore: Arc::new(Semaphore::new(limit)),
+                waiting: 0,
+                users: 0,
+            });
+            // permits of the previous limit stay with the old semaphore
+            if budget.limit != limit {
+                budget.limit = limit;
+                budget.semaphore = Arc::new(Semaphore::new(limit));
+            }
+            budget.waiting += 1;
+            budget.users += 1;
+            budget.semaphore.clone()
+        };
+        let mut user = User { concurrency: self.clone(), key, waiting: true };
+        let permit = semaphore.acquire_owned().await.expect("tenant semaphores are never closed");
+        user.stop_waiting();
+        TenantPermit { _permit: permit, _user: user }
+    }
+
+    /// Work of the installation waiting for room right now.
+    pub fn waiting(&self, saleor_api_url: &str, workload: Workload) -> usize {
+        self.state.lock().unwrap().budgets.get(&(saleor_api_url.to_string(), workload)).map_or(0, |budget| budget.waiting)
+    }
+
+    /// Installations and workloads that have a budget right now.
+    pub fn tracked(&self) -> usize {
+        self.state.lock().unwrap().budgets.len()
+    }
+}
+
+/// Room for work of an installation, given back when dropped.
+pub struct TenantPermit {
+    _permit: OwnedSemaphorePermit,
+    _user: User,
+}
+
+/// Work counted in a budget until it's done or gave up waiting.
+struct User {
+    concurrency: TenantConcurrency,
+    key: (String, Workload),
+    waiting: bool,
+}
+
+impl User {
+    fn stop_waiting(&mut self) {
+        if let Some(budget) = self.concurrency.state.lock().unwrap().budgets.get_mut(&self.key) {
+            budget.waiting = budget.waiting.saturating_sub(1);
+        }
+        self.waiting = false;
+    }
+}
+
+impl Drop for User {
+    fn drop(&mut self) {
+        let mut state = self.concurrency.state.lock().unwrap();
+        let Some(budget) = state.budgets.get_mut(&self.key) else {
+            return;
+        };
+        if self.waiting {
+            budget.waiting = budget.waiting.saturating_sub(1);
+        }
+        budget.users = budget.users.saturating_sub(1);
+        if budget.users == 0 {
+            state.budgets.remove(&self.key);
+        }
+    }
+}
+
+/// Limits the webhooks of an installation handled at the same time with the budgets of
+/// the app's [`HttpClient`], see [`Workload::Webhooks`].
+///
+/// The installation is named by the request's [`crate::saleor::TenantResolvers`], webhooks without
+/// one pass through. Its settings are read through the [`MetadataSettingsManager`] in the background now
+/// and then, so overrides apply to its GraphQL requests too.
+#[derive(Clone, Default)]
+pub struct TenantConcurrencyLayer;
+
+impl<S> Layer<S> for TenantConcurrencyLayer {
+    type Service = TenantConcurrencyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantConcurrencyService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct TenantConcurrencyService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for TenantConcurrencyService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let Some(api_url) = request_tenant(&TenantRequest::from_request(&req)) else {
+                return inner.call(req).await;
+            };
+            // routers built without [`crate::app::build`] get budgets of their own
+            let client = req.extensions().get::<HttpClient>().cloned().unwrap_or_default();
+            let concurrency = client.concurrency().clone();
+            // read in the background, so webhooks don't wait for Saleor
+            if concurrency.settings_outdated(&api_url) {
+                concurrency.mark_loaded(&api_url);
+                let apl = req.extensions().get::<SaleorApl>().cloned();
+                tokio::spawn(load_settings(concurrency.clone(), api_url.clone(), apl, client));
+            }
+
+            let _permit = concurrency.acquire(&api_url, Workload::Webhooks).await;
+            inner.call(req).await
+        })
+    }
+}
+
+/// Applies the settings of an installation, keeping the current limits if they can't be read.
+async fn load_settings(concurrency: TenantConcurrency, saleor_api_url: String, apl: Option<SaleorApl>, client: HttpClient) {
+    let Some(apl) = apl else {
+        return;
+    };
+    let auth_data = match apl.get(&apl.id(&saleor_api_url)).await {
+        Ok(Some(auth_data)) => auth_data,
+        Ok(None) => return concurrency.forget(&saleor_api_url),
+        Err(_) => return,
+    };
+    match MetadataSettingsManager::new(client, auth_data).get_all().await {
+        Ok(settings) => concurrency.apply_settings(&saleor_api_url, &settings),
+        Err(e) => tracing::warn!(saleor_api_url, "unable to read the concurrency limits of the installation: {}", e),
+    }
+}
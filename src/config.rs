@@ -0,0 +1,770 @@
+use std::{collections::{BTreeMap, HashMap}, path::{Path, PathBuf}, time::Duration};
+
+use reqwest::Url;
+use tower_sessions::cookie::SameSite;
+
+use crate::{admin_auth::{AdminAuth, OidcConfig}, circuit_breaker::CircuitBreakerConfig, concurrency::ConcurrencyLimits, emitter::EmitterConfig, http_client::HttpClientConfig, graphql_cache::{GraphqlCacheBackend, GraphqlCacheConfig}, locks::LockBackend, saleor::{AplKeyring, GraphqlDebugConfig, JwksFetchConfig, JwtValidation, ManifestDefinition, ManifestValidation, TenantStrategy, UserTokenConfig, DEFAULT_DOMAIN_API_URL_TEMPLATE, DEFAULT_TENANT_API_URL_TEMPLATE}, search::SearchConfig, limits::{RequestLimits, RouteLimits}, load_shed::{GroupLimits, LoadShedLimits}, rate_limit::{RateLimitConfig, RateLimitKey, TrustedProxies}, registration::RegistrationConfig, secrets::{self, SecretString, REDACTED}, sessions::{SessionBackend, SessionConfig, SessionCookie, TenantSessionExpiry}, APP_ID};
+
+/// Long enough for Saleor to give up retrying the webhook deliveries of a removed installation.
+const DEFAULT_UNINSTALLED_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEFAULT_WEBHOOK_ARCHIVE_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Operator API keys shorter than this are too easy to guess.
+const MIN_OPERATOR_API_KEY_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub port: u16,
+    /// Id of the app in its manifest and the keys of its installations, see [`crate::app_id`]
+    pub app_id: String,
+    /// Ids the app had before, installations stored under them are moved to [`Self::app_id`]
+    pub previous_app_ids: Vec<String>,
+    pub app_url: Option<String>,
+    /// Hosts requests may name when `APP_URL` isn't set, any host is taken if empty, see [`crate::base_url`]
+    pub allowed_hosts: Vec<String>,
+    pub log_format: LogFormat,
+    /// Which logs are emitted, in the syntax of `RUST_LOG`, see [`crate::telemetry::init_tracing`]
+    pub log_filter: Option<String>,
+    pub sentry_dsn: Option<SecretString>,
+    pub rate_limit: RateLimitConfig,
+    /// Proxies whose `x-forwarded-for` names the client IP, it's the peer of the connection otherwise
+    pub trusted_proxies: TrustedProxies,
+    /// Limits of `POST /api/register` on top of [`Self::rate_limit`]
+    pub registration: RegistrationConfig,
+    /// Limits of webhooks, always per Saleor API url
+    pub webhook_rate_limit: RateLimitConfig,
+    pub limits: RequestLimits,
+    /// Directory assets are served from, unused when built with the `embed-assets` feature
+    pub assets_dir: PathBuf,
+    /// Origins allowed to embed the app pages besides the dashboard of the current installation
+    pub frame_ancestors: Vec<String>,
+    /// Origins allowed to call the API with credentials, the dashboards of registered installations
+    /// and `*` may call it without them
+    pub cors_origins: Vec<String>,
+    pub sessions: SessionConfig,
+    /// Installations whose dashboards may use the operator pages, like `/app/installations`
+    pub admin_api_urls: Vec<String>,
+    /// Key of the operator API, see [`crate::operator_api`], which is disabled if unset
+    pub operator_api_key: Option<SecretString>,
+    /// How operators sign in to the admin routes, see [`crate::admin_auth`]
+    pub admin_auth: AdminAuth,
+    /// File audit events are appended to, they are only logged if unset
+    pub audit_log_file: Option<PathBuf>,
+    /// Settings of the client shared by every outgoing request
+    pub http_client: HttpClientConfig,
+    /// SMTP server emails are sent through, they are only logged if unset
+    pub smtp_url: Option<String>,
+    /// Sender of emails for installations that didn't configure their own
+    pub email_from: Option<String>,
+    /// Search engine products are indexed in, see [`crate::saleor::search`]
+    pub search: Option<SearchConfig>,
+    /// How requests are mapped to installations, tried in order
+    pub tenant_strategies: Vec<TenantStrategy>,
+    /// Retries and fallback when fetching the JWKS of Saleor fails
+    pub jwks_fetch: JwksFetchConfig,
+    /// Algorithms, leeway and claims dashboard tokens are validated with
+    pub jwt_validation: JwtValidation,
+    /// How long requests of removed installations are answered with `410 Gone`
+    pub uninstalled_ttl: Duration,
+    /// Where the events of webhooks are re-emitted to, see [`crate::emitter`]
+    pub emitter: EmitterConfig,
+    /// Work of an installation done at the same time, see [`crate::concurrency`]
+    pub concurrency: ConcurrencyLimits,
+    /// Requests of the dashboard and webhooks handled at the same time, see [`crate::load_shed`]
+    pub load_shed: LoadShedLimits,
+    /// When calls to a failing Saleor instance are paused, see [`crate::circuit_breaker`]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Where verified webhook deliveries are archived, see [`crate::webhook_archive`]
+    pub webhook_archive_file: Option<PathBuf>,
+    /// How long archived webhook deliveries are kept
+    pub webhook_archive_retention: Duration,
+    /// Whether problems of the manifest stop the app from starting
+    pub manifest_validation: ManifestValidation,
+    /// Overrides of the compiled manifest, read from `MANIFEST_FILE`
+    pub manifest: ManifestDefinition,
+    /// Keys the tokens in the APL are encrypted with, they are stored in plain text if unset
+    pub apl_encryption: Option<AplKeyring>,
+    /// Where the locks shared between replicas are kept, see [`crate::locks`]
+    pub locks: LockBackend,
+    /// Caching of idempotent GraphQL queries, see [`crate::graphql_cache`]
+    pub graphql_cache: GraphqlCacheConfig,
+    /// Logging of every GraphQL call, see [`crate::saleor::set_graphql_debug`]
+    pub graphql_debug: GraphqlDebugConfig,
+    /// Access tokens handlers call Saleor with for dashboard users, see [`crate::saleor::UserClient`]
+    pub user_tokens: UserTokenConfig,
+}
+
+impl AppConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let env = ConfigVars::load()?;
+        let port = env.parse("PORT")?.unwrap_or(8008);
+        let app_id = env.var("SALEOR_APP_ID").ok().filter(|id| !id.is_empty()).unwrap_or_else(|| APP_ID.to_string());
+        let previous_app_ids = env.list("SALEOR_APP_PREVIOUS_IDS");
+        let app_url = env.var("APP_URL").ok().filter(|url| !url.is_empty());
+        let allowed_hosts = env.list("ALLOWED_HOSTS");
+        let log_format = match env.var("LOG_FORMAT").as_deref() {
+            Ok("json") => LogFormat::Json,
+            Ok("pretty") | Ok("") | Err(_) => LogFormat::Pretty,
+            Ok(other) => anyhow::bail!("invalid LOG_FORMAT {other:?}, expected pretty or json"),
+        };
+        let log_filter = env.var("RUST_LOG").ok().filter(|filter| !filter.is_empty());
+
+        let manifest_validation = match env.var("MANIFEST_VALIDATION").as_deref() {
+            Ok("strict") => ManifestValidation::Strict,
+            Ok("permissive") | Ok("") | Err(_) => ManifestValidation::Permissive,
+            Ok(other) => anyhow::bail!("invalid MANIFEST_VALIDATION {other:?}, expected strict or permissive"),
+        };
+
+        let manifest = match env.var("MANIFEST_FILE") {
+            Ok(path) if !path.is_empty() => ManifestDefinition::load(Path::new(&path))?,
+            _ => ManifestDefinition::default(),
+        };
+
+        let apl_encryption = match env.var("APL_ENCRYPTION_KEY") {
+            Ok(keys) if !keys.is_empty() => Some(AplKeyring::parse(&keys).map_err(|e| anyhow::anyhow!("invalid APL_ENCRYPTION_KEY: {}", e.0))?),
+            _ => None,
+        };
+
+        let sentry_dsn = env.var("SENTRY_DSN").ok().filter(|dsn| !dsn.is_empty()).map(SecretString::from);
+
+        let default_rate_limit = RateLimitConfig::default();
+        let rate_limit = RateLimitConfig {
+            capacity: env.parse("RATE_LIMIT_BURST")?.unwrap_or(default_rate_limit.capacity),
+            refill_per_second: env.parse("RATE_LIMIT_PER_SECOND")?.unwrap_or(default_rate_limit.refill_per_second),
+            key: match env.var("RATE_LIMIT_KEY").as_deref() {
+                Ok("ip") | Ok("") | Err(_) => RateLimitKey::Ip,
+                Ok("saleor-api-url") => RateLimitKey::SaleorApiUrl,
+                Ok("ip-and-saleor-api-url") => RateLimitKey::IpAndSaleorApiUrl,
+                Ok(other) => anyhow::bail!("invalid RATE_LIMIT_KEY {other:?}, expected ip, saleor-api-url or ip-and-saleor-api-url"),
+            },
+        };
+
+        let default_webhook_rate_limit = RateLimitConfig::webhooks();
+        let webhook_rate_limit = RateLimitConfig {
+            capacity: env.parse("WEBHOOK_RATE_LIMIT_BURST")?.unwrap_or(default_webhook_rate_limit.capacity),
+            refill_per_second: env.parse("WEBHOOK_RATE_LIMIT_PER_SECOND")?.unwrap_or(default_webhook_rate_limit.refill_per_second),
+            key: RateLimitKey::SaleorApiUrl,
+        };
+
+        let trusted_proxies = env
+            .list("TRUSTED_PROXIES")
+            .iter()
+            .map(|proxy| proxy.parse().map_err(|_| anyhow::anyhow!("invalid TRUSTED_PROXIES entry {proxy:?}, expected an IP address")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let trusted_proxies = TrustedProxies::new(&trusted_proxies);
+
+        let default_registration = RegistrationConfig::default();
+        let registration = RegistrationConfig {
+            burst: env.parse("REGISTER_RATE_LIMIT_BURST")?.unwrap_or(default_registration.burst),
+            per_minute: env.parse("REGISTER_RATE_LIMIT_PER_MINUTE")?.unwrap_or(default_registration.per_minute),
+            replay_window: env.parse("REGISTER_REPLAY_WINDOW_SECS")?.map(Duration::from_secs).unwrap_or(default_registration.replay_window),
+        };
+
+        let default_limits = RequestLimits::default();
+        let limits = RequestLimits {
+            default: route_limits_from_env(&env, "", default_limits.default)?,
+            register: route_limits_from_env(&env, "REGISTER_", default_limits.register)?,
+            webhooks: route_limits_from_env(&env, "WEBHOOK_", default_limits.webhooks)?,
+        };
+
+        let assets_dir = match env.var("ASSETS_DIR") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => default_assets_dir(),
+        };
+
+        let frame_ancestors = env.list("FRAME_ANCESTORS");
+        let cors_origins = env.list("CORS_ORIGINS");
+        let admin_api_urls = env.list("ADMIN_SALEOR_API_URLS");
+        let operator_api_key = env.var("OPERATOR_API_KEY").ok().filter(|key| !key.is_empty()).map(SecretString::from);
+        if let Some(problem) = operator_api_key_problem(operator_api_key.as_ref()) {
+            anyhow::bail!(problem);
+        }
+        let admin_auth = match env.var("ADMIN_AUTH").as_deref() {
+            Ok("dashboard") | Ok("") | Err(_) => AdminAuth::Dashboard,
+            Ok("api-key") => AdminAuth::ApiKey,
+            Ok("oidc") => AdminAuth::Oidc(OidcConfig {
+                issuer_url: env.var("OIDC_ISSUER_URL").map_err(|_| anyhow::anyhow!("ADMIN_AUTH=oidc requires OIDC_ISSUER_URL"))?,
+                client_id: env.var("OIDC_CLIENT_ID").map_err(|_| anyhow::anyhow!("ADMIN_AUTH=oidc requires OIDC_CLIENT_ID"))?,
+                client_secret: env.var("OIDC_CLIENT_SECRET").ok().filter(|secret| !secret.is_empty()).map(SecretString::from),
+                allowed_emails: env.list("OIDC_ALLOWED_EMAILS"),
+            }),
+            Ok(other) => anyhow::bail!("invalid ADMIN_AUTH {other:?}, expected dashboard, api-key or oidc"),
+        };
+        let audit_log_file = env.var("AUDIT_LOG_FILE").ok().filter(|path| !path.is_empty()).map(PathBuf::from);
+        let webhook_archive_file = env.var("WEBHOOK_ARCHIVE_FILE").ok().filter(|path| !path.is_empty()).map(PathBuf::from);
+        let webhook_archive_retention = env.parse("WEBHOOK_ARCHIVE_RETENTION_DAYS")?
+            .map(|days: u64| Duration::from_secs(days * 24 * 60 * 60))
+            .unwrap_or(DEFAULT_WEBHOOK_ARCHIVE_RETENTION);
+        let default_http_client = HttpClientConfig::default();
+        let http_client = HttpClientConfig {
+            timeout: env.parse("HTTP_TIMEOUT_SECS")?.map(Duration::from_secs).unwrap_or(default_http_client.timeout),
+            connect_timeout: env.parse("HTTP_CONNECT_TIMEOUT_SECS")?.map(Duration::from_secs).unwrap_or(default_http_client.connect_timeout),
+            proxy: env.var("OUTBOUND_PROXY").ok().filter(|proxy| !proxy.is_empty()),
+            user_agent: env.var("HTTP_USER_AGENT").ok().filter(|agent| !agent.is_empty()).unwrap_or(default_http_client.user_agent),
+        };
+        let smtp_url = env.var("SMTP_URL").ok().filter(|url| !url.is_empty());
+        let email_from = env.var("EMAIL_FROM").ok().filter(|address| !address.is_empty());
+        let search = match env.var("SEARCH_BACKEND").as_deref() {
+            Ok("") | Err(_) => None,
+            Ok("meilisearch") => Some(SearchConfig::Meilisearch {
+                url: env.var("MEILISEARCH_URL").map_err(|_| anyhow::anyhow!("SEARCH_BACKEND=meilisearch requires MEILISEARCH_URL"))?,
+                api_key: env.var("MEILISEARCH_API_KEY").ok().filter(|key| !key.is_empty()).map(SecretString::from),
+                index: env.var("MEILISEARCH_INDEX").unwrap_or_else(|_| "products".to_string()),
+            }),
+            Ok("algolia") => Some(SearchConfig::Algolia {
+                app_id: env.var("ALGOLIA_APP_ID").map_err(|_| anyhow::anyhow!("SEARCH_BACKEND=algolia requires ALGOLIA_APP_ID"))?,
+                api_key: env.var("ALGOLIA_API_KEY").map(SecretString::from).map_err(|_| anyhow::anyhow!("SEARCH_BACKEND=algolia requires ALGOLIA_API_KEY"))?,
+                index: env.var("ALGOLIA_INDEX").unwrap_or_else(|_| "products".to_string()),
+            }),
+            Ok(other) => anyhow::bail!("invalid SEARCH_BACKEND {other:?}, expected meilisearch or algolia"),
+        };
+        let api_url_template = env.var("TENANT_API_URL_TEMPLATE")
+            .ok()
+            .filter(|template| !template.is_empty())
+            .unwrap_or_else(|| DEFAULT_TENANT_API_URL_TEMPLATE.to_string());
+        let domain_api_url_template = env.var("SALEOR_DOMAIN_API_URL_TEMPLATE")
+            .ok()
+            .filter(|template| !template.is_empty())
+            .unwrap_or_else(|| DEFAULT_DOMAIN_API_URL_TEMPLATE.to_string());
+        let tenant_strategies = match env.list("TENANT_RESOLVERS") {
+            names if names.is_empty() => TenantStrategy::defaults_with_domain_template(&domain_api_url_template),
+            names => names
+                .iter()
+                .map(|name| match name.as_str() {
+                    "header" => Ok(TenantStrategy::Header),
+                    "domain" => Ok(TenantStrategy::Domain { api_url_template: domain_api_url_template.clone() }),
+                    "session" => Ok(TenantStrategy::Session),
+                    "path" => Ok(TenantStrategy::PathPrefix { api_url_template: api_url_template.clone() }),
+                    "subdomain" => Ok(TenantStrategy::Subdomain {
+                        base_domain: env.var("TENANT_BASE_DOMAIN").map_err(|_| anyhow::anyhow!("TENANT_RESOLVERS=subdomain requires TENANT_BASE_DOMAIN"))?,
+                        api_url_template: api_url_template.clone(),
+                    }),
+                    other => anyhow::bail!("invalid TENANT_RESOLVERS entry {other:?}, expected header, domain, session, path or subdomain"),
+                })
+                .collect::<anyhow::Result<_>>()?,
+        };
+        let default_jwks_fetch = JwksFetchConfig::default();
+        let jwks_fetch = JwksFetchConfig {
+            retries: env.parse("JWKS_FETCH_RETRIES")?.unwrap_or(default_jwks_fetch.retries),
+            backoff: env.parse("JWKS_FETCH_BACKOFF_MS")?.map(Duration::from_millis).unwrap_or(default_jwks_fetch.backoff),
+            ttl: env.parse("JWKS_CACHE_TTL_SECS")?.map(Duration::from_secs).unwrap_or(default_jwks_fetch.ttl),
+            grace_period: env.parse("JWKS_GRACE_PERIOD_SECS")?.map(Duration::from_secs).unwrap_or(default_jwks_fetch.grace_period),
+            retry_after: env.parse("JWKS_RETRY_AFTER_SECS")?.map(Duration::from_secs).unwrap_or(default_jwks_fetch.retry_after),
+        };
+        let mut jwt_validation = JwtValidation::default();
+        if let Ok(algorithms) = env.var("JWT_ALGORITHMS") {
+            jwt_validation = JwtValidation::parse_algorithms(&algorithms)
+                .and_then(|algorithms| jwt_validation.with_algorithms(&algorithms))
+                .map_err(|e| anyhow::anyhow!("invalid JWT_ALGORITHMS: {}", e.0))?;
+        }
+        if let Some(leeway) = env.parse("JWT_LEEWAY_SECS")? {
+            jwt_validation = jwt_validation.with_leeway(leeway);
+        }
+        if let Ok(claims) = env.var("JWT_REQUIRED_CLAIMS") {
+            let claims: Vec<&str> = claims.split(',').map(str::trim).filter(|claim| !claim.is_empty()).collect();
+            jwt_validation = jwt_validation.with_required_claims(&claims).map_err(|e| anyhow::anyhow!("invalid JWT_REQUIRED_CLAIMS: {}", e.0))?;
+        }
+        let uninstalled_ttl = env.parse("UNINSTALLED_TENANT_TTL_SECS")?.map(Duration::from_secs).unwrap_or(DEFAULT_UNINSTALLED_TTL);
+        let default_emitter = EmitterConfig::default();
+        let emitter = EmitterConfig {
+            url: env.var("EMIT_URL").ok().filter(|url| !url.is_empty()),
+            secret: env.var("EMIT_SECRET").ok().filter(|secret| !secret.is_empty()).map(SecretString::from),
+            max_attempts: env.parse("EMIT_MAX_ATTEMPTS")?.unwrap_or(default_emitter.max_attempts),
+            backoff: env.parse("EMIT_RETRY_BACKOFF_MS")?.map(Duration::from_millis).unwrap_or(default_emitter.backoff),
+            queue_capacity: env.parse("EMIT_QUEUE_CAPACITY")?.unwrap_or(default_emitter.queue_capacity),
+        };
+        let default_concurrency = ConcurrencyLimits::default();
+        let concurrency = ConcurrencyLimits {
+            graphql: env.parse("MAX_CONCURRENT_GRAPHQL")?.unwrap_or(default_concurrency.graphql),
+            webhooks: env.parse("MAX_CONCURRENT_WEBHOOKS")?.unwrap_or(default_concurrency.webhooks),
+        };
+        let default_load_shed = LoadShedLimits::default();
+        let load_shed = LoadShedLimits {
+            dashboard: group_limits_from_env(&env, "DASHBOARD_", default_load_shed.dashboard)?,
+            webhooks: group_limits_from_env(&env, "WEBHOOK_", default_load_shed.webhooks)?,
+            retry_after: env.parse("LOAD_SHED_RETRY_AFTER_SECS")?.map(Duration::from_secs).unwrap_or(default_load_shed.retry_after),
+        };
+        let default_circuit_breaker = CircuitBreakerConfig::default();
+        let circuit_breaker = CircuitBreakerConfig {
+            failure_threshold: env.parse("CIRCUIT_BREAKER_FAILURES")?.unwrap_or(default_circuit_breaker.failure_threshold),
+            open_for: env.parse("CIRCUIT_BREAKER_OPEN_SECS")?.map(Duration::from_secs).unwrap_or(default_circuit_breaker.open_for),
+            probes: env.parse("CIRCUIT_BREAKER_PROBES")?.unwrap_or(default_circuit_breaker.probes),
+        };
+        let sessions = SessionConfig {
+            backend: match env.var("SESSION_STORE").as_deref() {
+                Ok("memory") | Ok("") | Err(_) => SessionBackend::Memory,
+                Ok("cookie") => SessionBackend::Cookie(
+                    env.var("SESSION_COOKIE_KEY").map(SecretString::from).map_err(|_| anyhow::anyhow!("SESSION_STORE=cookie requires SESSION_COOKIE_KEY"))?,
+                ),
+                Ok("redis") => SessionBackend::Redis(
+                    env.var("REDIS_URL").map_err(|_| anyhow::anyhow!("SESSION_STORE=redis requires REDIS_URL"))?,
+                ),
+                Ok(other) => anyhow::bail!("invalid SESSION_STORE {other:?}, expected memory, redis or cookie"),
+            },
+            ttl: env.parse("SESSION_TTL_SECS")?.map(Duration::from_secs).unwrap_or(SessionConfig::default().ttl),
+            tenant_expiry: TenantSessionExpiry {
+                idle: env.parse("SESSION_IDLE_TIMEOUT_SECS")?.map(Duration::from_secs).unwrap_or(TenantSessionExpiry::default().idle),
+                absolute: env.parse("SESSION_ABSOLUTE_TIMEOUT_SECS")?.map(Duration::from_secs).unwrap_or(TenantSessionExpiry::default().absolute),
+            },
+            cookie: session_cookie_from_env(&env, app_url.as_deref())?,
+            api_sessions: env.parse("API_SESSIONS")?.unwrap_or(SessionConfig::default().api_sessions),
+        };
+        let locks = match env.var("LOCK_BACKEND").as_deref() {
+            Ok("memory") | Ok("") | Err(_) => LockBackend::Memory,
+            Ok("redis") => LockBackend::Redis(env.var("REDIS_URL").map_err(|_| anyhow::anyhow!("LOCK_BACKEND=redis requires REDIS_URL"))?),
+            Ok(other) => anyhow::bail!("invalid LOCK_BACKEND {other:?}, expected memory or redis"),
+        };
+        let graphql_cache = GraphqlCacheConfig {
+            backend: match env.var("GRAPHQL_CACHE").as_deref() {
+                Ok("off") | Ok("") | Err(_) => GraphqlCacheBackend::Disabled,
+                Ok("memory") => GraphqlCacheBackend::Memory,
+                Ok("redis") => GraphqlCacheBackend::Redis(env.var("REDIS_URL").map_err(|_| anyhow::anyhow!("GRAPHQL_CACHE=redis requires REDIS_URL"))?),
+                Ok(other) => anyhow::bail!("invalid GRAPHQL_CACHE {other:?}, expected off, memory or redis"),
+            },
+            ttl: env.parse("GRAPHQL_CACHE_TTL_SECS")?.map(Duration::from_secs).unwrap_or(GraphqlCacheConfig::default().ttl),
+        };
+        let graphql_debug = GraphqlDebugConfig {
+            enabled: env.parse("GRAPHQL_DEBUG")?.unwrap_or_default(),
+            redacted_fields: env.list("GRAPHQL_DEBUG_REDACT"),
+        };
+        let user_tokens = UserTokenConfig {
+            exchange: env.parse("USER_TOKEN_EXCHANGE")?.unwrap_or(UserTokenConfig::default().exchange),
+            refresh_before: env.parse("USER_TOKEN_REFRESH_BEFORE_SECS")?.map(Duration::from_secs).unwrap_or(UserTokenConfig::default().refresh_before),
+        };
+
+        Ok(Self { port, app_id, previous_app_ids, app_url, allowed_hosts, log_format, log_filter, sentry_dsn, rate_limit, trusted_proxies, registration, webhook_rate_limit, limits, assets_dir, frame_ancestors, cors_origins, sessions, admin_api_urls, operator_api_key, admin_auth, audit_log_file, http_client, smtp_url, email_from, search, tenant_strategies, jwks_fetch, jwt_validation, uninstalled_ttl, emitter, concurrency, load_shed, circuit_breaker, webhook_archive_file, webhook_archive_retention, manifest_validation, manifest, apl_encryption, locks, graphql_cache, graphql_debug, user_tokens })
+    }
+
+    /// Returns a list of human readable problems with this configuration, empty if it is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        // installations are keyed by `{app_id}:{saleor_api_url}`
+        for app_id in [&self.app_id].into_iter().chain(&self.previous_app_ids) {
+            if app_id.is_empty() || app_id.contains(':') || app_id.contains(char::is_whitespace) {
+                problems.push(format!("app ids can't be empty or contain colons or whitespace, got {app_id:?}"));
+            }
+        }
+
+        if let Some(app_url) = &self.app_url {
+            match Url::parse(app_url) {
+                Ok(url) if url.scheme() != "http" && url.scheme() != "https" => {
+                    problems.push(format!("APP_URL must use http or https, got {}", url.scheme()));
+                },
+                Ok(_) => {},
+                Err(e) => problems.push(format!("APP_URL is not a valid url: {e}")),
+            }
+        }
+
+        for host in &self.allowed_hosts {
+            if host.contains("://") || host.contains('/') {
+                problems.push(format!("ALLOWED_HOSTS takes hosts like app.example.com, got {host}"));
+            }
+        }
+
+        if let Some(Err(e)) = self.log_filter.as_deref().map(tracing_subscriber::EnvFilter::try_new) {
+            problems.push(format!("RUST_LOG is not a valid filter: {e}"));
+        }
+
+        problems.extend(operator_api_key_problem(self.operator_api_key.as_ref()));
+        if self.rate_limit.capacity == 0 {
+            problems.push("RATE_LIMIT_BURST must be at least 1".to_string());
+        }
+        if !is_positive(self.rate_limit.refill_per_second) {
+            problems.push("RATE_LIMIT_PER_SECOND must be positive".to_string());
+        }
+        if self.webhook_rate_limit.capacity == 0 {
+            problems.push("WEBHOOK_RATE_LIMIT_BURST must be at least 1".to_string());
+        }
+        if !is_positive(self.webhook_rate_limit.refill_per_second) {
+            problems.push("WEBHOOK_RATE_LIMIT_PER_SECOND must be positive".to_string());
+        }
+        if self.registration.burst == 0 {
+            problems.push("REGISTER_RATE_LIMIT_BURST must be at least 1".to_string());
+        }
+        if !is_positive(self.registration.per_minute) {
+            problems.push("REGISTER_RATE_LIMIT_PER_MINUTE must be positive".to_string());
+        }
+        if let Some(proxy) = &self.http_client.proxy {
+            if let Err(e) = reqwest::Proxy::all(proxy) {
+                problems.push(format!("OUTBOUND_PROXY is not a valid proxy url: {e}"));
+            }
+        }
+        for strategy in &self.tenant_strategies {
+            if let TenantStrategy::PathPrefix { api_url_template } | TenantStrategy::Subdomain { api_url_template, .. } = strategy {
+                if !api_url_template.contains("{tenant}") {
+                    problems.push("TENANT_API_URL_TEMPLATE must contain {tenant}".to_string());
+                    break;
+                }
+            }
+        }
+        for strategy in &self.tenant_strategies {
+            if let TenantStrategy::Domain { api_url_template } = strategy {
+                if !api_url_template.contains("{domain}") {
+                    problems.push("SALEOR_DOMAIN_API_URL_TEMPLATE must contain {domain}".to_string());
+                }
+            }
+        }
+        if let Some(url) = &self.emitter.url {
+            if let Err(e) = Url::parse(url) {
+                problems.push(format!("EMIT_URL is not a valid url: {e}"));
+            }
+            if self.emitter.secret.is_none() {
+                problems.push("EMIT_URL requires EMIT_SECRET to sign the events".to_string());
+            }
+        }
+        if self.emitter.max_attempts == 0 {
+            problems.push("EMIT_MAX_ATTEMPTS must be at least 1".to_string());
+        }
+        if self.emitter.queue_capacity == 0 {
+            problems.push("EMIT_QUEUE_CAPACITY must be at least 1".to_string());
+        }
+        if self.concurrency.graphql == 0 {
+            problems.push("MAX_CONCURRENT_GRAPHQL must be at least 1".to_string());
+        }
+        if self.concurrency.webhooks == 0 {
+            problems.push("MAX_CONCURRENT_WEBHOOKS must be at least 1".to_string());
+        }
+        if self.load_shed.dashboard.max_in_flight == 0 {
+            problems.push("DASHBOARD_MAX_IN_FLIGHT must be at least 1".to_string());
+        }
+        if self.load_shed.webhooks.max_in_flight == 0 {
+            problems.push("WEBHOOK_MAX_IN_FLIGHT must be at least 1".to_string());
+        }
+        if self.circuit_breaker.failure_threshold == 0 {
+            problems.push("CIRCUIT_BREAKER_FAILURES must be at least 1".to_string());
+        }
+        if self.circuit_breaker.probes == 0 {
+            problems.push("CIRCUIT_BREAKER_PROBES must be at least 1".to_string());
+        }
+        match &self.admin_auth {
+            AdminAuth::Dashboard => {}
+            AdminAuth::ApiKey if self.operator_api_key.is_none() => problems.push("ADMIN_AUTH=api-key requires OPERATOR_API_KEY".to_string()),
+            AdminAuth::ApiKey => {}
+            AdminAuth::Oidc(oidc) => {
+                if !cfg!(feature = "oidc") {
+                    problems.push("ADMIN_AUTH=oidc requires the oidc feature".to_string());
+                }
+                if let Err(e) = Url::parse(&oidc.issuer_url) {
+                    problems.push(format!("OIDC_ISSUER_URL is not a valid url: {e}"));
+                }
+                if oidc.allowed_emails.is_empty() {
+                    problems.push("ADMIN_AUTH=oidc requires OIDC_ALLOWED_EMAILS, nobody could sign in".to_string());
+                }
+            }
+        }
+        problems.extend(self.sessions.cookie.problems(self.app_url.as_deref()));
+        if self.smtp_url.is_some() && !cfg!(feature = "smtp") {
+            problems.push("SMTP_URL requires the smtp feature".to_string());
+        }
+
+        problems
+    }
+
+    /// The settings by variable, for support to look at: secrets only show whether they're set and
+    /// urls lose their credentials. Unset optional settings are left out.
+    pub fn redacted_settings(&self) -> BTreeMap<&'static str, String> {
+        let secret = |set: bool| set.then(|| REDACTED.to_string());
+        let url = |url: Option<&String>| url.map(|url| secrets::redact_url(url));
+        let list = |values: &[String]| (!values.is_empty()).then(|| values.join(","));
+        let secs = |duration: Duration| Some(duration.as_secs().to_string());
+        let redis_url = match (&self.sessions.backend, &self.locks, &self.graphql_cache.backend) {
+            (SessionBackend::Redis(url), _, _) | (_, LockBackend::Redis(url), _) | (_, _, GraphqlCacheBackend::Redis(url)) => Some(url),
+            _ => None,
+        };
+
+        let settings = [
+            ("PORT", Some(self.port.to_string())),
+            ("SALEOR_APP_ID", Some(self.app_id.clone())),
+            ("SALEOR_APP_PREVIOUS_IDS", list(&self.previous_app_ids)),
+            ("APP_URL", self.app_url.clone()),
+            ("ALLOWED_HOSTS", list(&self.allowed_hosts)),
+            ("LOG_FORMAT", Some(match self.log_format {
+                LogFormat::Pretty => "pretty".to_string(),
+                LogFormat::Json => "json".to_string(),
+            })),
+            ("RUST_LOG", self.log_filter.clone()),
+            ("SENTRY_DSN", secret(self.sentry_dsn.is_some())),
+            ("RATE_LIMIT_BURST", Some(self.rate_limit.capacity.to_string())),
+            ("RATE_LIMIT_PER_SECOND", Some(self.rate_limit.refill_per_second.to_string())),
+            ("RATE_LIMIT_KEY", Some(self.rate_limit.key.as_str().to_string())),
+            ("TRUSTED_PROXIES", (!self.trusted_proxies.proxies().is_empty()).then(|| {
+                self.trusted_proxies.proxies().iter().map(ToString::to_string).collect::<Vec<_>>().join(",")
+            })),
+            ("REGISTER_RATE_LIMIT_BURST", Some(self.registration.burst.to_string())),
+            ("REGISTER_RATE_LIMIT_PER_MINUTE", Some(self.registration.per_minute.to_string())),
+            ("REGISTER_REPLAY_WINDOW_SECS", secs(self.registration.replay_window)),
+            ("WEBHOOK_RATE_LIMIT_BURST", Some(self.webhook_rate_limit.capacity.to_string())),
+            ("WEBHOOK_RATE_LIMIT_PER_SECOND", Some(self.webhook_rate_limit.refill_per_second.to_string())),
+            ("ASSETS_DIR", Some(self.assets_dir.display().to_string())),
+            ("FRAME_ANCESTORS", list(&self.frame_ancestors)),
+            ("CORS_ORIGINS", list(&self.cors_origins)),
+            ("SESSION_STORE", Some(match self.sessions.backend {
+                SessionBackend::Memory => "memory".to_string(),
+                SessionBackend::Redis(_) => "redis".to_string(),
+                SessionBackend::Cookie(_) => "cookie".to_string(),
+            })),
+            ("SESSION_COOKIE_KEY", secret(matches!(self.sessions.backend, SessionBackend::Cookie(_)))),
+            ("SESSION_TTL_SECS", secs(self.sessions.ttl)),
+            ("API_SESSIONS", Some(self.sessions.api_sessions.to_string())),
+            ("REDIS_URL", url(redis_url)),
+            ("ADMIN_SALEOR_API_URLS", list(&self.admin_api_urls)),
+            ("OPERATOR_API_KEY", secret(self.operator_api_key.is_some())),
+            ("ADMIN_AUTH", Some(match self.admin_auth {
+                AdminAuth::Dashboard => "dashboard".to_string(),
+                AdminAuth::ApiKey => "api-key".to_string(),
+                AdminAuth::Oidc(_) => "oidc".to_string(),
+            })),
+            ("OIDC_ISSUER_URL", match &self.admin_auth {
+                AdminAuth::Oidc(oidc) => Some(oidc.issuer_url.clone()),
+                _ => None,
+            }),
+            ("OIDC_CLIENT_ID", match &self.admin_auth {
+                AdminAuth::Oidc(oidc) => Some(oidc.client_id.clone()),
+                _ => None,
+            }),
+            ("OIDC_CLIENT_SECRET", secret(matches!(&self.admin_auth, AdminAuth::Oidc(oidc) if oidc.client_secret.is_some()))),
+            ("OIDC_ALLOWED_EMAILS", match &self.admin_auth {
+                AdminAuth::Oidc(oidc) => list(&oidc.allowed_emails),
+                _ => None,
+            }),
+            ("AUDIT_LOG_FILE", self.audit_log_file.as_ref().map(|path| path.display().to_string())),
+            ("HTTP_TIMEOUT_SECS", secs(self.http_client.timeout)),
+            ("HTTP_CONNECT_TIMEOUT_SECS", secs(self.http_client.connect_timeout)),
+            ("OUTBOUND_PROXY", url(self.http_client.proxy.as_ref())),
+            ("HTTP_USER_AGENT", Some(self.http_client.user_agent.clone())),
+            ("SMTP_URL", url(self.smtp_url.as_ref())),
+            ("EMAIL_FROM", self.email_from.clone()),
+            ("SEARCH_BACKEND", self.search.as_ref().map(|search| match search {
+                SearchConfig::Meilisearch { .. } => "meilisearch".to_string(),
+                SearchConfig::Algolia { .. } => "algolia".to_string(),
+            })),
+            ("TENANT_RESOLVERS", Some(self.tenant_strategies.iter().map(|strategy| match strategy {
+                TenantStrategy::Header => "header",
+                TenantStrategy::Domain { .. } => "domain",
+                TenantStrategy::Session => "session",
+                TenantStrategy::PathPrefix { .. } => "path",
+                TenantStrategy::Subdomain { .. } => "subdomain",
+            }).collect::<Vec<_>>().join(","))),
+            ("UNINSTALLED_TENANT_TTL_SECS", secs(self.uninstalled_ttl)),
+            ("EMIT_URL", url(self.emitter.url.as_ref())),
+            ("EMIT_SECRET", secret(self.emitter.secret.is_some())),
+            ("MAX_CONCURRENT_GRAPHQL", Some(self.concurrency.graphql.to_string())),
+            ("MAX_CONCURRENT_WEBHOOKS", Some(self.concurrency.webhooks.to_string())),
+            ("DASHBOARD_MAX_IN_FLIGHT", Some(self.load_shed.dashboard.max_in_flight.to_string())),
+            ("DASHBOARD_MAX_QUEUED", Some(self.load_shed.dashboard.max_queued.to_string())),
+            ("WEBHOOK_MAX_IN_FLIGHT", Some(self.load_shed.webhooks.max_in_flight.to_string())),
+            ("WEBHOOK_MAX_QUEUED", Some(self.load_shed.webhooks.max_queued.to_string())),
+            ("LOAD_SHED_RETRY_AFTER_SECS", secs(self.load_shed.retry_after)),
+            ("CIRCUIT_BREAKER_FAILURES", Some(self.circuit_breaker.failure_threshold.to_string())),
+            ("CIRCUIT_BREAKER_OPEN_SECS", secs(self.circuit_breaker.open_for)),
+            ("CIRCUIT_BREAKER_PROBES", Some(self.circuit_breaker.probes.to_string())),
+            ("WEBHOOK_ARCHIVE_FILE", self.webhook_archive_file.as_ref().map(|path| path.display().to_string())),
+            ("WEBHOOK_ARCHIVE_RETENTION_DAYS", Some((self.webhook_archive_retention.as_secs() / (24 * 60 * 60)).to_string())),
+            ("MANIFEST_VALIDATION", Some(match self.manifest_validation {
+                ManifestValidation::Strict => "strict".to_string(),
+                ManifestValidation::Permissive => "permissive".to_string(),
+            })),
+            ("APL_ENCRYPTION_KEY", secret(self.apl_encryption.is_some())),
+            ("LOCK_BACKEND", Some(match self.locks {
+                LockBackend::Memory => "memory".to_string(),
+                LockBackend::Redis(_) => "redis".to_string(),
+            })),
+            ("GRAPHQL_CACHE", Some(match self.graphql_cache.backend {
+                GraphqlCacheBackend::Disabled => "disabled".to_string(),
+                GraphqlCacheBackend::Memory => "memory".to_string(),
+                GraphqlCacheBackend::Redis(_) => "redis".to_string(),
+            })),
+            ("GRAPHQL_CACHE_TTL_SECS", secs(self.graphql_cache.ttl)),
+            ("GRAPHQL_DEBUG", Some(self.graphql_debug.enabled.to_string())),
+            ("GRAPHQL_DEBUG_REDACT", list(&self.graphql_debug.redacted_fields)),
+            ("USER_TOKEN_EXCHANGE", Some(self.user_tokens.exchange.to_string())),
+            ("USER_TOKEN_REFRESH_BEFORE_SECS", secs(self.user_tokens.refresh_before)),
+        ];
+        settings.into_iter().filter_map(|(name, value)| Some((name, value?))).collect()
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            port: 8008,
+            app_id: APP_ID.to_string(),
+            previous_app_ids: vec![],
+            app_url: None,
+            allowed_hosts: vec![],
+            log_format: LogFormat::default(),
+            log_filter: None,
+            sentry_dsn: None,
+            rate_limit: RateLimitConfig::default(),
+            trusted_proxies: TrustedProxies::default(),
+            registration: RegistrationConfig::default(),
+            webhook_rate_limit: RateLimitConfig::webhooks(),
+            limits: RequestLimits::default(),
+            assets_dir: default_assets_dir(),
+            frame_ancestors: vec![],
+            cors_origins: vec![],
+            sessions: SessionConfig::default(),
+            admin_api_urls: vec![],
+            operator_api_key: None,
+            admin_auth: AdminAuth::default(),
+            audit_log_file: None,
+            http_client: HttpClientConfig::default(),
+            smtp_url: None,
+            email_from: None,
+            search: None,
+            tenant_strategies: TenantStrategy::defaults(),
+            jwks_fetch: JwksFetchConfig::default(),
+            jwt_validation: JwtValidation::default(),
+            uninstalled_ttl: DEFAULT_UNINSTALLED_TTL,
+            emitter: EmitterConfig::default(),
+            concurrency: ConcurrencyLimits::default(),
+            load_shed: LoadShedLimits::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            webhook_archive_file: None,
+            webhook_archive_retention: DEFAULT_WEBHOOK_ARCHIVE_RETENTION,
+            manifest_validation: ManifestValidation::default(),
+            manifest: ManifestDefinition::default(),
+            apl_encryption: None,
+            locks: LockBackend::default(),
+            graphql_cache: GraphqlCacheConfig::default(),
+            graphql_debug: GraphqlDebugConfig::default(),
+            user_tokens: UserTokenConfig::default(),
+        }
+    }
+}
+
+/// The variables the configuration is read from, the environment with the `KEY=value` lines of
+/// `CONFIG_FILE` on top. Unlike the environment, the file can change while the app is running,
+/// like a mounted Kubernetes ConfigMap, see [`crate::reload`].
+struct ConfigVars {
+    file: HashMap<String, String>,
+}
+
+impl ConfigVars {
+    fn load() -> anyhow::Result<Self> {
+        let file = match std::env::var("CONFIG_FILE") {
+            Ok(path) if !path.is_empty() => {
+                let content = std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("unable to read CONFIG_FILE {path}: {e}"))?;
+                parse_config_file(&content)
+            }
+            _ => HashMap::new(),
+        };
+        Ok(Self { file })
+    }
+
+    fn var(&self, name: &str) -> Result<String, std::env::VarError> {
+        match self.file.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => std::env::var(name),
+        }
+    }
+
+    fn parse<T>(&self, name: &str) -> anyhow::Result<Option<T>>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match self.var(name) {
+            Ok(value) if !value.is_empty() => value
+                .parse()
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("invalid {name} {value:?}: {e}")),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads a comma separated list, ignoring empty entries.
+    fn list(&self, name: &str) -> Vec<String> {
+        self.var(name)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Parses `KEY=value` lines like a `.env` file, skipping blank lines and `#` comments. Values can
+/// be quoted and lines can start with `export`.
+fn parse_config_file(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix("export ").unwrap_or(line).split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let unquoted = ['"', '\'']
+                .iter()
+                .find_map(|quote| value.strip_prefix(*quote).and_then(|value| value.strip_suffix(*quote)))
+                .unwrap_or(value);
+            (key.trim().to_string(), unquoted.to_string())
+        })
+        .collect()
+}
+
+/// Reads `{prefix}BODY_LIMIT_BYTES` and `{prefix}TIMEOUT_SECS`, falling back to the given defaults.
+/// Operator keys open every APL entry, short ones could be guessed.
+pub(crate) fn operator_api_key_problem(key: Option<&SecretString>) -> Option<String> {
+    key.filter(|key| key.expose().len() < MIN_OPERATOR_API_KEY_LEN)
+        .map(|_| format!("OPERATOR_API_KEY must be at least {MIN_OPERATOR_API_KEY_LEN} characters long"))
+}
+
+fn route_limits_from_env(env: &ConfigVars, prefix: &str, default: RouteLimits) -> anyhow::Result<RouteLimits> {
+    Ok(RouteLimits {
+        body_limit_bytes: env.parse(&format!("{prefix}BODY_LIMIT_BYTES"))?.unwrap_or(default.body_limit_bytes),
+        timeout: env.parse(&format!("{prefix}TIMEOUT_SECS"))?.map(Duration::from_secs).unwrap_or(default.timeout),
+    })
+}
+
+/// Reads `{prefix}MAX_IN_FLIGHT` and `{prefix}MAX_QUEUED`, falling back to the given defaults.
+fn group_limits_from_env(env: &ConfigVars, prefix: &str, default: GroupLimits) -> anyhow::Result<GroupLimits> {
+    Ok(GroupLimits {
+        max_in_flight: env.parse(&format!("{prefix}MAX_IN_FLIGHT"))?.unwrap_or(default.max_in_flight),
+        max_queued: env.parse(&format!("{prefix}MAX_QUEUED"))?.unwrap_or(default.max_queued),
+    })
+}
+
+/// The profile of `SESSION_COOKIE_PROFILE` (by default the one matching `app_url`), with
+/// `SESSION_COOKIE_SECURE` and `SESSION_COOKIE_SAME_SITE` overriding its attributes.
+fn session_cookie_from_env(env: &ConfigVars, app_url: Option<&str>) -> anyhow::Result<SessionCookie> {
+    let mut cookie = match env.var("SESSION_COOKIE_PROFILE").as_deref() {
+        Ok("production") => SessionCookie::production(),
+        Ok("development") => SessionCookie::development(),
+        Ok("") | Err(_) => app_url.map(SessionCookie::for_app_url).unwrap_or_default(),
+        Ok(other) => anyhow::bail!("invalid SESSION_COOKIE_PROFILE {other:?}, expected production or development"),
+    };
+    if let Some(secure) = env.parse("SESSION_COOKIE_SECURE")? {
+        cookie.secure = secure;
+    }
+    match env.var("SESSION_COOKIE_SAME_SITE").as_deref() {
+        Ok("none") => cookie.same_site = SameSite::None,
+        Ok("lax") => cookie.same_site = SameSite::Lax,
+        Ok("strict") => cookie.same_site = SameSite::Strict,
+        Ok("") | Err(_) => {},
+        Ok(other) => anyhow::bail!("invalid SESSION_COOKIE_SAME_SITE {other:?}, expected none, lax or strict"),
+    }
+    Ok(cookie)
+}
+
+/// `assets` next to the executable if it exists there, otherwise relative to the working directory.
+fn default_assets_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("assets")))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from("assets"))
+}
+
+/// Whether a rate is usable, `NaN` and infinite rates would switch the limit off.
+fn is_positive(rate: f64) -> bool {
+    rate.is_finite() && rate > 0.0
+}
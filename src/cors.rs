@@ -0,0 +1,170 @@
+use std::{collections::HashSet, future::Future, pin::Pin, sync::{Arc, RwLock}, time::{Duration, Instant}};
+
+use axum::{body::Body, http::{Method, HeaderName, Request, header::{AUTHORIZATION, CONTENT_TYPE, ORIGIN}}, response::Response};
+use reqwest::Url;
+use tower::{Layer, Service};
+use tower_http::cors::{AllowCredentials, AllowOrigin, CorsLayer};
+
+use crate::{request_id::REQUEST_ID_HEADER, saleor::{AuthData, SaleorApl}};
+
+/// How long the origins of installations are used before they're read from the APL again.
+const INSTALLED_ORIGINS_TTL: Duration = Duration::from_secs(60);
+
+/// Origins allowed to call the API, a configured list plus the dashboards of the installations in
+/// the APL.
+///
+/// Only the configured origins, apart from `*`, may send credentials like the session cookie.
+/// Anyone can register an installation, so its dashboard is allowed to call the API with the
+/// bearer token of its own tenant and nothing else. The origins of installations are read from
+/// the `domain` stored in the APL, so every replica knows them, and kept for a minute.
+#[derive(Clone, Default)]
+pub struct DashboardOrigins {
+    explicit: Arc<RwLock<Vec<String>>>,
+    installed: Arc<tokio::sync::Mutex<Option<InstalledOrigins>>>,
+}
+
+struct InstalledOrigins {
+    origins: HashSet<String>,
+    read_at: Instant,
+}
+
+/// Marks requests whose `Origin` is the dashboard of an installation, see [`DashboardOriginsLayer`].
+#[derive(Clone, Copy)]
+struct InstalledOrigin;
+
+impl DashboardOrigins {
+    pub fn new(explicit: &[String]) -> Self {
+        let origins = Self::default();
+        origins.set_explicit(explicit);
+        origins
+    }
+
+    /// Replaces the configured origins, the dashboards of installations stay allowed.
+    pub fn set_explicit(&self, explicit: &[String]) {
+        *self.explicit.write().unwrap() = explicit.iter().map(|origin| origin.trim_end_matches('/').to_string()).collect();
+    }
+
+    /// Reads the origins of installations from the APL with the next request, call it once an
+    /// installation was stored.
+    pub async fn installations_changed(&self) {
+        *self.installed.lock().await = None;
+    }
+
+    pub fn is_configured(&self, origin: &str) -> bool {
+        self.explicit.read().unwrap().iter().any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    /// Whether the configured origins name `origin` itself, `*` doesn't count.
+    pub fn allows_credentials(&self, origin: &str) -> bool {
+        self.explicit.read().unwrap().iter().any(|allowed| allowed == origin)
+    }
+
+    /// Whether `origin` is the dashboard of an installation in `apl`.
+    pub async fn is_installed(&self, apl: &SaleorApl, origin: &str) -> bool {
+        let mut installed = self.installed.lock().await;
+        if let Some(installed) = &*installed {
+            if installed.read_at.elapsed() < INSTALLED_ORIGINS_TTL {
+                return installed.origins.contains(origin);
+            }
+        }
+
+        let origins = match apl.get_all().await {
+            Ok(installations) => installations.iter().filter_map(installation_origin).collect(),
+            Err(e) => {
+                // tried again after the TTL, keeping the origins read last meanwhile
+                tracing::warn!("unable to read the dashboard origins of the installations: {}", e);
+                installed.take().map(|installed| installed.origins).unwrap_or_default()
+            }
+        };
+        let allowed = origins.contains(origin);
+        *installed = Some(InstalledOrigins { origins, read_at: Instant::now() });
+        allowed
+    }
+
+    /// The CORS layer, apply [`Self::installations_layer`] outside of it.
+    pub fn layer(&self) -> CorsLayer {
+        let origins = self.clone();
+        let credentials = self.clone();
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::predicate(move |origin, parts| {
+                parts.extensions.get::<InstalledOrigin>().is_some() || origin.to_str().map(|origin| origins.is_configured(origin)).unwrap_or(false)
+            }))
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
+            .allow_headers([
+                AUTHORIZATION,
+                CONTENT_TYPE,
+                HeaderName::from_static("saleor-api-url"),
+                HeaderName::from_static(REQUEST_ID_HEADER),
+            ])
+            .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+            .allow_credentials(AllowCredentials::predicate(move |origin, _| {
+                origin.to_str().map(|origin| credentials.allows_credentials(origin)).unwrap_or(false)
+            }))
+    }
+
+    /// Looks up whether the `Origin` of requests is the dashboard of an installation, for
+    /// [`Self::layer`].
+    pub fn installations_layer(&self) -> DashboardOriginsLayer {
+        DashboardOriginsLayer { origins: self.clone() }
+    }
+}
+
+/// The origin of the dashboard of `installation`, its `domain` with the scheme of its api url.
+fn installation_origin(installation: &AuthData) -> Option<String> {
+    let api_url = Url::parse(&installation.saleor_api_url).ok()?;
+    let url = match &installation.domain {
+        Some(domain) => Url::parse(&format!("{}://{}", api_url.scheme(), domain)).ok()?,
+        None => api_url,
+    };
+    Some(url.origin().ascii_serialization())
+}
+
+/// See [`DashboardOrigins::installations_layer`].
+#[derive(Clone)]
+pub struct DashboardOriginsLayer {
+    origins: DashboardOrigins,
+}
+
+impl<S> Layer<S> for DashboardOriginsLayer {
+    type Service = DashboardOriginsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DashboardOriginsService { inner, origins: self.origins.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct DashboardOriginsService<S> {
+    inner: S,
+    origins: DashboardOrigins,
+}
+
+impl<S> Service<Request<Body>> for DashboardOriginsService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let origins = self.origins.clone();
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let origin = req.headers().get(ORIGIN).and_then(|h| h.to_str().ok()).map(str::to_string);
+            let apl = req.extensions().get::<SaleorApl>().cloned();
+            if let (Some(origin), Some(apl)) = (origin, apl) {
+                if !origins.is_configured(&origin) && origins.is_installed(&apl, &origin).await {
+                    req.extensions_mut().insert(InstalledOrigin);
+                }
+            }
+            inner.call(req).await
+        })
+    }
+}
@@ -0,0 +1,108 @@
+use std::{convert::Infallible, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+
+use axum::{Router, routing::get, extract::State, http::header::{CACHE_CONTROL, CONTENT_TYPE}, response::{IntoResponse, sse::{Event, KeepAlive, Sse}}};
+use futures_util::{StreamExt, stream};
+use tokio::sync::watch;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Live reload of assets: the open pages reload when assets change or the server restarts
+/// (requires the `dev` feature).
+///
+/// Templates aren't reloaded, askama compiles them into the binary. Run the app with
+/// `cargo watch -w src -w templates -w locales -x 'run --features dev'` to rebuild it on template
+/// changes, pages reload once the new binary is up.
+#[derive(Clone)]
+pub struct DevReload {
+    /// Changes on every start, pages reload when they reconnect to a different server
+    server_id: String,
+    changes: watch::Receiver<u64>,
+}
+
+impl DevReload {
+    /// Polls the given directories for changes made after this call.
+    pub fn watch(dirs: Vec<PathBuf>) -> Self {
+        let (sender, changes) = watch::channel(0);
+        let mut last = fingerprint(&dirs);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let current = fingerprint(&dirs);
+                if current != last {
+                    tracing::debug!("watched files changed, reloading pages");
+                    last = current;
+                    sender.send_modify(|version| *version += 1);
+                }
+            }
+        });
+
+        Self {
+            server_id: uuid::Uuid::new_v4().to_string(),
+            changes,
+        }
+    }
+
+    /// Serves `/dev/reload`, the event stream, and `/dev/reload.js`, which the layout includes in dev builds.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/dev/reload", get(reload_events))
+            .route("/dev/reload.js", get(reload_script))
+            .with_state(self)
+    }
+}
+
+/// Latest modification time and number of files, enough to notice edits, new and removed files.
+fn fingerprint(dirs: &[PathBuf]) -> (Option<SystemTime>, usize) {
+    fn walk(dir: &Path, latest: &mut Option<SystemTime>, files: &mut usize) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path, latest, files);
+                continue;
+            }
+            *files += 1;
+            let modified = entry.metadata().and_then(|metadata| metadata.modified()).ok();
+            *latest = (*latest).max(modified);
+        }
+    }
+
+    let mut latest = None;
+    let mut files = 0;
+    for dir in dirs {
+        walk(dir, &mut latest, &mut files);
+    }
+    (latest, files)
+}
+
+async fn reload_events(State(dev): State<DevReload>) -> impl IntoResponse {
+    let hello = Event::default().event("hello").data(dev.server_id);
+    let mut changes = dev.changes;
+    changes.borrow_and_update();
+    let reloads = stream::unfold(changes, |mut changes| async move {
+        changes.changed().await.ok()?;
+        Some((Ok::<_, Infallible>(Event::default().event("reload").data("")), changes))
+    });
+
+    Sse::new(stream::iter([Ok(hello)]).chain(reloads)).keep_alive(KeepAlive::default())
+}
+
+async fn reload_script() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/javascript; charset=utf-8"), (CACHE_CONTROL, "no-store")],
+        r#"(() => {
+    let serverId = null;
+    const events = new EventSource("/dev/reload");
+    events.addEventListener("hello", (e) => {
+        if (serverId && serverId !== e.data) {
+            window.location.reload();
+        }
+        serverId = e.data;
+    });
+    events.addEventListener("reload", () => window.location.reload());
+})();
+"#,
+    )
+}
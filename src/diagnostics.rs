@@ -0,0 +1,128 @@
+//! A diagnostic bundle of a deployment, for support debugging self-hosted apps.
+//!
+//! `GET /api/admin/diagnostics` is served with the [`crate::operator_api`] and downloads a single
+//! JSON file with the versions and features of the app, its configuration with secrets and
+//! identifying settings (like `APP_URL` or `OIDC_ALLOWED_EMAILS`) redacted, the health of the APL,
+//! how many installations it holds and the latest server errors. Besides the Saleor instances of
+//! errors and the hosts of backing services like Redis, nothing in it names the deployment or the
+//! people running it, so it can be attached to a ticket.
+
+use std::collections::BTreeMap;
+
+use axum::{
+    http::header::CONTENT_DISPOSITION,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use utoipa::ToSchema;
+
+use crate::{
+    error_reporting::{ErrorSummary, RecentErrors},
+    reload::ConfigReloader,
+    saleor::SaleorApl,
+    secrets::REDACTED,
+    APP_VERSION,
+};
+
+/// Settings naming the hosts of the app, its operators or the Saleor instances they administer,
+/// redacted in the bundle like secrets.
+const IDENTIFYING_SETTINGS: &[&str] = &[
+    "APP_URL",
+    "ALLOWED_HOSTS",
+    "CORS_ORIGINS",
+    "ADMIN_SALEOR_API_URLS",
+    "OIDC_ISSUER_URL",
+    "OIDC_ALLOWED_EMAILS",
+    "EMAIL_FROM",
+];
+
+/// The cargo features the app was built with.
+fn enabled_features() -> Vec<&'static str> {
+    [
+        ("dev", cfg!(feature = "dev")),
+        ("embed-assets", cfg!(feature = "embed-assets")),
+        ("oidc", cfg!(feature = "oidc")),
+        ("prometheus", cfg!(feature = "prometheus")),
+        ("redis", cfg!(feature = "redis")),
+        ("smtp", cfg!(feature = "smtp")),
+        ("stripe", cfg!(feature = "stripe")),
+        ("swagger-ui", cfg!(feature = "swagger-ui")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub app: String,
+    pub version: String,
+    pub features: Vec<String>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AplDiagnostics {
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// How many installations the APL holds, missing if they couldn't be listed
+    pub installations: Option<usize>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticBundle {
+    /// RFC 3339 timestamp
+    pub generated_at: String,
+    pub versions: VersionInfo,
+    /// Settings by variable, see [`crate::config::AppConfig::redacted_settings`], without the
+    /// identifying ones like `APP_URL`
+    pub config: BTreeMap<String, String>,
+    /// Problems of the configuration, like the ones `saleor-app doctor` reports
+    pub config_problems: Vec<String>,
+    pub apl: AplDiagnostics,
+    /// The latest server errors, oldest first
+    pub recent_errors: Vec<ErrorSummary>,
+}
+
+impl DiagnosticBundle {
+    pub async fn collect(reloader: &ConfigReloader, apl: &SaleorApl, recent_errors: &RecentErrors) -> Self {
+        let config = reloader.current();
+        let health = apl.health().await;
+        let installations = apl.get_all().await.ok().map(|installations| installations.len());
+        Self {
+            generated_at: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            versions: VersionInfo {
+                app: config.app_id.clone(),
+                version: APP_VERSION.to_string(),
+                features: enabled_features().into_iter().map(str::to_string).collect(),
+            },
+            config: config
+                .redacted_settings()
+                .into_iter()
+                .map(|(name, value)| match IDENTIFYING_SETTINGS.contains(&name) {
+                    true => (name.to_string(), REDACTED.to_string()),
+                    false => (name.to_string(), value),
+                })
+                .collect(),
+            config_problems: config.validate(),
+            apl: AplDiagnostics { healthy: health.is_ok(), error: health.err(), installations },
+            recent_errors: recent_errors.summaries(),
+        }
+    }
+}
+
+/// `GET /api/admin/diagnostics`, the diagnostic bundle as a file download.
+#[utoipa::path(get, path = "/api/admin/diagnostics", tag = "operator", security(("operator_api_key" = [])), responses(
+    (status = 200, description = "The bundle, with secrets redacted", body = DiagnosticBundle),
+    (status = 401, description = "The operator API key is missing or wrong"),
+))]
+pub async fn diagnostic_bundle(reloader: ConfigReloader, apl: SaleorApl, Extension(recent_errors): Extension<RecentErrors>) -> Response {
+    let bundle = DiagnosticBundle::collect(&reloader, &apl, &recent_errors).await;
+    let filename = format!("attachment; filename=\"{}-diagnostics.json\"", apl.app_id());
+    ([(CONTENT_DISPOSITION, filename)], Json(bundle)).into_response()
+}
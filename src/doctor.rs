@@ -0,0 +1,240 @@
+//! Diagnostics for the setup of the app, run by `saleor-app doctor` and (partially) on startup.
+//!
+//! Misconfigurations otherwise only show up once Saleor tries to install the app, usually as a
+//! failed registration without much of an explanation.
+
+use std::fmt;
+
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::{config::AppConfig, http_client::HttpClient, saleor::{self, AplStore, AuthData, SaleorManifest}};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticStatus {
+    Pass,
+    /// Works, but likely not the way it's intended to
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub name: String,
+    pub status: DiagnosticStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// What to do about a warning or failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    fn pass(name: &str, message: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: DiagnosticStatus::Pass, message: Some(message.into()), hint: None }
+    }
+
+    fn warn(name: &str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: DiagnosticStatus::Warn, message: Some(message.into()), hint: Some(hint.into()) }
+    }
+
+    fn fail(name: &str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: DiagnosticStatus::Fail, message: Some(message.into()), hint: Some(hint.into()) }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            DiagnosticStatus::Pass => "ok",
+            DiagnosticStatus::Warn => "warn",
+            DiagnosticStatus::Fail => "FAIL",
+        };
+        write!(f, "[{status:>4}] {}", self.name)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {message}")?;
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct DoctorReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl DoctorReport {
+    pub fn has_failures(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.status == DiagnosticStatus::Fail)
+    }
+
+    /// Everything that isn't a pass, what startup logs.
+    pub fn problems(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.status != DiagnosticStatus::Pass)
+    }
+}
+
+/// The checks that don't need the app to be running: the configuration and the APL.
+pub async fn startup_checks(config: &AppConfig, apl: &impl AplStore) -> DoctorReport {
+    let mut diagnostics = vec![check_config(config)];
+    diagnostics.push(check_apl(apl).await.0);
+    DoctorReport { diagnostics }
+}
+
+/// Runs every check, `base_url` is where the app should be reachable (defaults to `APP_URL`).
+pub async fn run(config: &AppConfig, apl: &impl AplStore, base_url: Option<&str>) -> DoctorReport {
+    // an invalid proxy is reported by the config check
+    let client = config.http_client.build().unwrap_or_default();
+
+    let mut diagnostics = vec![check_config(config)];
+    let (apl_diagnostic, installations) = check_apl(apl).await;
+    diagnostics.push(apl_diagnostic);
+
+    match base_url.or(config.app_url.as_deref()) {
+        Some(base_url) => diagnostics.extend(check_base_url(&client, base_url.trim_end_matches('/')).await),
+        None => diagnostics.push(Diagnostic::warn(
+            "base url",
+            "not checked, APP_URL is not set",
+            "pass --base-url to check whether Saleor can reach the app",
+        )),
+    }
+
+    for auth_data in &installations {
+        diagnostics.push(check_schema(&client, auth_data).await);
+    }
+
+    DoctorReport { diagnostics }
+}
+
+pub fn check_config(config: &AppConfig) -> Diagnostic {
+    let problems = config.validate();
+    if !problems.is_empty() {
+        return Diagnostic::fail("config", problems.join(", "), "fix the environment variables above, see the README for their format");
+    }
+    if config.app_url.is_none() && config.allowed_hosts.is_empty() {
+        return Diagnostic::warn(
+            "config",
+            "APP_URL is not set, the manifest uses the host of each request",
+            "set APP_URL to the public url of the app, especially behind a proxy, or limit the hosts with ALLOWED_HOSTS",
+        );
+    }
+    Diagnostic::pass("config", "complete")
+}
+
+/// Checks that the APL is reachable, returning the installations to check further.
+pub async fn check_apl(apl: &impl AplStore) -> (Diagnostic, Vec<AuthData>) {
+    if let Err(e) = apl.health().await {
+        return (Diagnostic::fail("apl", e, "check the storage of the APL is running and accessible"), vec![]);
+    }
+    match apl.get_all().await {
+        Ok(installations) if installations.is_empty() => (
+            Diagnostic::warn("apl", "reachable, but there are no installations", "install the app with `saleor-app install` or from the dashboard"),
+            installations,
+        ),
+        Ok(installations) => (Diagnostic::pass("apl", format!("reachable, {} installations", installations.len())), installations),
+        Err(e) => (Diagnostic::fail("apl", e.to_string(), "check the storage of the APL is running and accessible"), vec![]),
+    }
+}
+
+/// Fetches the manifest from `base_url` the way Saleor does when installing the app.
+pub async fn check_base_url(client: &reqwest::Client, base_url: &str) -> Vec<Diagnostic> {
+    let url = match Url::parse(base_url) {
+        Ok(url) => url,
+        Err(e) => return vec![Diagnostic::fail("base url", format!("{base_url} is not a valid url: {e}"), "use the full url, like https://app.example.com")],
+    };
+
+    let mut diagnostics = vec![];
+    if matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "0.0.0.0" | "[::1]")) {
+        diagnostics.push(Diagnostic::warn(
+            "base url",
+            format!("{base_url} is only reachable from this machine"),
+            "use `saleor-app serve --tunnel cloudflared` to install into a Saleor instance elsewhere",
+        ));
+    }
+
+    let manifest_url = format!("{base_url}/api/manifest");
+    let response = match client.get(&manifest_url).send().await {
+        Ok(response) => response,
+        Err(e) if is_tls_error(&e) => {
+            diagnostics.push(Diagnostic::fail(
+                "tls",
+                format!("invalid certificate for {base_url}: {}", error_chain(&e)),
+                "the certificate is expired, self-signed or issued for another host, Saleor refuses to install the app",
+            ));
+            return diagnostics;
+        }
+        Err(e) => {
+            diagnostics.push(Diagnostic::fail(
+                "base url",
+                format!("unable to fetch {manifest_url}: {}", error_chain(&e)),
+                "is the app running and APP_URL the url it's publicly reachable at?",
+            ));
+            return diagnostics;
+        }
+    };
+
+    diagnostics.push(match url.scheme() {
+        "https" => Diagnostic::pass("tls", "certificate is valid"),
+        _ => Diagnostic::warn("tls", format!("{base_url} is served over plain http"), "Saleor Cloud only installs apps served over https"),
+    });
+
+    if !response.status().is_success() {
+        diagnostics.push(Diagnostic::fail(
+            "base url",
+            format!("{manifest_url} responded with {}", response.status()),
+            "is something else than the app running at APP_URL?",
+        ));
+        return diagnostics;
+    }
+    diagnostics.push(match response.json::<SaleorManifest>().await {
+        Ok(manifest) if !manifest.token_target_url.starts_with(base_url) => Diagnostic::warn(
+            "base url",
+            format!("the manifest points Saleor to {}", manifest.token_target_url),
+            "set APP_URL on the running app to the url it's reachable at",
+        ),
+        Ok(manifest) => {
+            let problems = saleor::manifest_problems(&manifest);
+            match problems.is_empty() {
+                true => Diagnostic::pass("base url", format!("{manifest_url} serves the manifest")),
+                false => Diagnostic::warn("manifest", problems.join(", "), "Saleor fails the installation without telling why, fix the manifest"),
+            }
+        }
+        Err(e) => Diagnostic::fail("base url", format!("{manifest_url} is not a valid manifest: {e}"), "is something else than the app running at APP_URL?"),
+    });
+    diagnostics
+}
+
+/// Checks the schema of an installation provides what the queries of the app select.
+pub async fn check_schema(client: &HttpClient, auth_data: &AuthData) -> Diagnostic {
+    let name = format!("schema of {}", auth_data.saleor_api_url);
+    match saleor::missing_schema_fields(client, auth_data).await {
+        Ok(missing) if missing.is_empty() => Diagnostic::pass(&name, "compatible with the compiled queries"),
+        Ok(missing) => Diagnostic::fail(
+            &name,
+            format!("missing {}", missing.join(", ")),
+            "download the schema of this Saleor version to schemas/saleor.graphql and adjust the queries, see the README",
+        ),
+        Err(e) => Diagnostic::warn(&name, format!("unable to introspect: {e}"), "is the instance reachable and the installation still valid?"),
+    }
+}
+
+fn is_tls_error(e: &reqwest::Error) -> bool {
+    let chain = error_chain(e).to_lowercase();
+    e.is_connect() && ["certificate", "tls", "ssl", "handshake"].iter().any(|needle| chain.contains(needle))
+}
+
+/// The error with its sources, reqwest's own message rarely says what went wrong.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
@@ -0,0 +1,162 @@
+//! Sending emails, like the notifications of [`crate::saleor::notifications`].
+//!
+//! Emails go through an [`EmailTransport`]: SMTP (`SMTP_URL`, with the `smtp` feature) or, by default,
+//! the log. Implement the trait for providers with their own API, like SES or SendGrid.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, extract::FromRequestParts, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+
+use crate::config::AppConfig;
+
+#[cfg(feature = "smtp")]
+mod smtp;
+
+#[cfg(feature = "smtp")]
+pub use smtp::SmtpTransport;
+
+/// Setting with the address emails of an installation are sent from
+pub const EMAIL_SENDER_ADDRESS_SETTING: &str = "email_sender_address";
+/// Setting with the name shown along with the sender address
+pub const EMAIL_SENDER_NAME_SETTING: &str = "email_sender_name";
+/// Setting with the address replies go to, if not the sender
+pub const EMAIL_REPLY_TO_SETTING: &str = "email_reply_to";
+
+#[derive(Debug)]
+pub struct EmailError(pub String);
+
+impl std::fmt::Display for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "email error: {}", self.0)
+    }
+}
+
+impl std::error::Error for EmailError {}
+
+impl IntoResponse for EmailError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+/// Who emails of an installation are sent from, configured in its settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderConfig {
+    pub address: String,
+    pub name: Option<String>,
+    pub reply_to: Option<String>,
+}
+
+impl SenderConfig {
+    /// The sender from the settings of an installation, `default_address` if it has none.
+    pub fn from_settings(settings: &HashMap<String, String>, default_address: Option<&str>) -> Option<Self> {
+        let setting = |key: &str| settings.get(key).map(|value| value.trim()).filter(|value| !value.is_empty()).map(str::to_string);
+        let address = setting(EMAIL_SENDER_ADDRESS_SETTING).or_else(|| default_address.map(str::to_string))?;
+        Some(Self { address, name: setting(EMAIL_SENDER_NAME_SETTING), reply_to: setting(EMAIL_REPLY_TO_SETTING) })
+    }
+
+    /// The `From` header, `Name <address>` if there is a name.
+    pub fn mailbox(&self) -> String {
+        match &self.name {
+            Some(name) => format!("\"{}\" <{}>", name.replace(['"', '\\'], ""), self.address),
+            None => self.address.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailMessage {
+    /// A mailbox, like `"Shop" <shop@example.com>`
+    pub from: String,
+    pub reply_to: Option<String>,
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+}
+
+impl EmailMessage {
+    pub fn new(sender: &SenderConfig, to: impl Into<String>, subject: impl Into<String>, html: impl Into<String>) -> Self {
+        Self {
+            from: sender.mailbox(),
+            reply_to: sender.reply_to.clone(),
+            to: to.into(),
+            subject: subject.into(),
+            html: html.into(),
+        }
+    }
+}
+
+/// Delivers emails, implement it for providers with their own API.
+#[async_trait]
+pub trait EmailTransport: Send + Sync + 'static {
+    async fn send(&self, message: &EmailMessage) -> Result<(), EmailError>;
+}
+
+/// Logs emails instead of sending them, the default without `SMTP_URL`.
+pub struct LogTransport;
+
+#[async_trait]
+impl EmailTransport for LogTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<(), EmailError> {
+        tracing::info!(target: "email", from = %message.from, to = %message.to, subject = %message.subject, "email not sent, SMTP_URL is not set");
+        Ok(())
+    }
+}
+
+/// Sends emails through the configured transport. Clones share the transport.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Arc<dyn EmailTransport>,
+    default_sender: Option<String>,
+}
+
+impl Mailer {
+    pub fn new(transport: impl EmailTransport) -> Self {
+        Self { transport: Arc::new(transport), default_sender: None }
+    }
+
+    /// SMTP if `SMTP_URL` is set, the log otherwise. Sends from `EMAIL_FROM` unless an installation
+    /// configured its own sender.
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Self> {
+        let mailer = match &config.smtp_url {
+            #[cfg(feature = "smtp")]
+            Some(url) => Self::new(SmtpTransport::new(url)?),
+            #[cfg(not(feature = "smtp"))]
+            Some(_) => anyhow::bail!("SMTP_URL requires the smtp feature"),
+            None => Self::new(LogTransport),
+        };
+        Ok(match &config.email_from {
+            Some(address) => mailer.with_default_sender(address),
+            None => mailer,
+        })
+    }
+
+    /// The sender address of installations that didn't configure one.
+    pub fn with_default_sender(mut self, address: impl Into<String>) -> Self {
+        self.default_sender = Some(address.into());
+        self
+    }
+
+    /// The sender of an installation with the given settings, `None` if neither it nor the mailer has one.
+    pub fn sender(&self, settings: &HashMap<String, String>) -> Option<SenderConfig> {
+        SenderConfig::from_settings(settings, self.default_sender.as_deref())
+    }
+
+    pub async fn send(&self, message: &EmailMessage) -> Result<(), EmailError> {
+        self.transport.send(message).await
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Mailer
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Mailer>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "mailer not found in request extensions").into_response())
+    }
+}
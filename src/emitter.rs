@@ -0,0 +1,325 @@
+//! Re-emitting the events of Saleor webhooks to the app's own services.
+//!
+//! Handlers hand events to the [`EventEmitter`], which delivers them to its [`EventSink`]s in the
+//! background: an HTTP endpoint (`EMIT_URL`, signed with `EMIT_SECRET`) or a channel consumed within
+//! the process. Events of an installation are delivered one after another in the order they were
+//! emitted, a failed delivery is retried with backoff before the next event of the installation.
+//! Installations have at most `EMIT_QUEUE_CAPACITY` events waiting, further ones are dropped, and
+//! their queue is dropped once it's idle.
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, extract::FromRequestParts, response::{IntoResponse, Response}};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{http_client::HttpClient, secrets::SecretString};
+
+/// `sha256=` followed by the hex HMAC-SHA256 of the body, keyed with the secret, see [`signature`]
+pub const EMIT_SIGNATURE_HEADER: &str = "x-app-signature";
+/// Name of the emitted event
+pub const EMIT_EVENT_HEADER: &str = "x-app-event";
+/// Id of the emitted event, the same for every attempt, so consumers can drop duplicates
+pub const EMIT_EVENT_ID_HEADER: &str = "x-app-event-id";
+
+/// Longest wait between two attempts of a delivery, however often it failed
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// Wait for further events before the queue of an installation is dropped
+const QUEUE_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct EmitterConfig {
+    /// Endpoint events are posted to, nothing is emitted if unset
+    pub url: Option<String>,
+    /// Key of the signature of posted events
+    pub secret: Option<SecretString>,
+    /// Attempts of a delivery before the event is dropped
+    pub max_attempts: u32,
+    /// Wait before the second attempt, doubled for every further one
+    pub backoff: Duration,
+    /// Events an installation can have waiting for delivery, further ones are dropped
+    pub queue_capacity: usize,
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            secret: None,
+            max_attempts: 5,
+            backoff: Duration::from_millis(500),
+            queue_capacity: 1_000,
+        }
+    }
+}
+
+/// An event as delivered to the sinks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EmittedEvent {
+    pub id: String,
+    pub name: String,
+    /// The installation the event happened in
+    pub saleor_api_url: String,
+    /// Unix timestamp in milliseconds
+    pub emitted_at: u64,
+    pub data: Value,
+}
+
+#[derive(Debug)]
+pub struct EmitError(pub String);
+
+impl std::fmt::Display for EmitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "emit error: {}", self.0)
+    }
+}
+
+impl std::error::Error for EmitError {}
+
+/// Receives emitted events, deliveries returning an error are retried.
+#[async_trait]
+pub trait EventSink: Send + Sync + 'static {
+    async fn deliver(&self, event: &EmittedEvent) -> Result<(), EmitError>;
+}
+
+/// The signature of an event body posted by [`HttpSink`], for consumers to compare with [`EMIT_SIGNATURE_HEADER`].
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let signature: String = hmac::sign(&key, body).as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", signature)
+}
+
+/// Posts events as JSON to an endpoint, answers other than a success are retried.
+pub struct HttpSink {
+    client: reqwest::Client,
+    url: String,
+    secret: String,
+}
+
+impl HttpSink {
+    pub fn new(client: reqwest::Client, url: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self { client, url: url.into(), secret: secret.into() }
+    }
+}
+
+#[async_trait]
+impl EventSink for HttpSink {
+    async fn deliver(&self, event: &EmittedEvent) -> Result<(), EmitError> {
+        let body = serde_json::to_vec(event).map_err(|e| EmitError(e.to_string()))?;
+        let response = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EMIT_SIGNATURE_HEADER, signature(&self.secret, &body))
+            .header(EMIT_EVENT_HEADER, &event.name)
+            .header(EMIT_EVENT_ID_HEADER, &event.id)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| EmitError(e.to_string()))?;
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(EmitError(format!("{} answered with status {}", self.url, response.status()))),
+        }
+    }
+}
+
+/// Sends events to a channel, for consumers running within the app.
+pub struct ChannelSink {
+    sender: mpsc::UnboundedSender<EmittedEvent>,
+}
+
+impl ChannelSink {
+    /// The sink and the receiving end of its channel.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<EmittedEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { sender }, receiver)
+    }
+}
+
+#[async_trait]
+impl EventSink for ChannelSink {
+    async fn deliver(&self, event: &EmittedEvent) -> Result<(), EmitError> {
+        self.sender.send(event.clone()).map_err(|_| EmitError("the receiver was dropped".to_string()))
+    }
+}
+
+/// Delivers events to its sinks, provided to handlers as a request extension. Clones share the
+/// sinks and the queues of the installations, so sinks can be added to [`crate::app::App::emitter`].
+#[derive(Clone)]
+pub struct EventEmitter {
+    sinks: Arc<Mutex<Vec<Arc<dyn EventSink>>>>,
+    max_attempts: u32,
+    backoff: Duration,
+    queue_capacity: usize,
+    queue_idle_timeout: Duration,
+    /// Events waiting for delivery, per installation
+    queues: Arc<Mutex<HashMap<String, mpsc::Sender<EmittedEvent>>>>,
+}
+
+impl EventEmitter {
+    /// An emitter without sinks, add them with [`Self::with_sink`].
+    pub fn new() -> Self {
+        let config = EmitterConfig::default();
+        Self {
+            sinks: Arc::default(),
+            max_attempts: config.max_attempts,
+            backoff: config.backoff,
+            queue_capacity: config.queue_capacity,
+            queue_idle_timeout: QUEUE_IDLE_TIMEOUT,
+            queues: Arc::default(),
+        }
+    }
+
+    /// Posts to `EMIT_URL` if it's set, otherwise events are dropped.
+    pub fn from_config(config: &EmitterConfig, client: &HttpClient) -> Self {
+        let emitter = Self::new()
+            .with_retries(config.max_attempts, config.backoff)
+            .with_queue_limits(config.queue_capacity, QUEUE_IDLE_TIMEOUT);
+        match (&config.url, &config.secret) {
+            (Some(url), Some(secret)) => emitter.with_sink(HttpSink::new(reqwest::Client::clone(client), url, secret.expose())),
+            _ => emitter,
+        }
+    }
+
+    pub fn with_sink(self, sink: impl EventSink) -> Self {
+        self.add_sink(sink);
+        self
+    }
+
+    /// Adds a sink to this emitter and its clones, it gets the events emitted from now on.
+    pub fn add_sink(&self, sink: impl EventSink) {
+        self.sinks.lock().unwrap().push(Arc::new(sink));
+    }
+
+    pub fn with_retries(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Keeps at most `capacity` events of an installation waiting, and drops its queue after
+    /// `idle_timeout` without events.
+    pub fn with_queue_limits(mut self, capacity: usize, idle_timeout: Duration) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self.queue_idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Installations with a queue, idle ones included until they're dropped.
+    pub fn queues(&self) -> usize {
+        self.queues.lock().unwrap().len()
+    }
+
+    /// Queues the event for delivery to every sink, it's delivered after the events the installation emitted before.
+    pub fn emit(&self, saleor_api_url: &str, name: &str, data: impl Serialize) {
+        if self.sinks.lock().unwrap().is_empty() {
+            return;
+        }
+        let event = EmittedEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            saleor_api_url: saleor_api_url.to_string(),
+            emitted_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_millis() as u64).unwrap_or_default(),
+            data: serde_json::to_value(data).unwrap_or_default(),
+        };
+
+        let mut queues = self.queues.lock().unwrap();
+        // a queue whose delivery stopped, like with a panicking sink, is replaced
+        let event = match queues.get(saleor_api_url) {
+            Some(queue) => match queue.try_send(event) {
+                Ok(()) => return,
+                Err(mpsc::error::TrySendError::Full(event)) => {
+                    tracing::warn!(event = %event.name, event_id = %event.id, saleor_api_url, "dropped event, {} events are waiting for delivery", self.queue_capacity);
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Closed(event)) => event,
+            },
+            None => event,
+        };
+        let (sender, receiver) = mpsc::channel(self.queue_capacity);
+        // the receiver was just created, sending can't fail
+        let _ = sender.try_send(event);
+        queues.insert(saleor_api_url.to_string(), sender);
+        let queue = Queue {
+            saleor_api_url: saleor_api_url.to_string(),
+            queues: self.queues.clone(),
+            idle_timeout: self.queue_idle_timeout,
+            receiver,
+        };
+        tokio::spawn(deliver_queue(self.sinks.clone(), self.max_attempts, self.backoff, queue));
+    }
+}
+
+/// The events of an installation, see [`deliver_queue`].
+struct Queue {
+    saleor_api_url: String,
+    queues: Arc<Mutex<HashMap<String, mpsc::Sender<EmittedEvent>>>>,
+    idle_timeout: Duration,
+    receiver: mpsc::Receiver<EmittedEvent>,
+}
+
+impl Queue {
+    /// The next event, or `None` once the queue was idle for its timeout and is dropped.
+    async fn next(&mut self) -> Option<EmittedEvent> {
+        if let Ok(event) = tokio::time::timeout(self.idle_timeout, self.receiver.recv()).await {
+            return event;
+        }
+        // events are only sent with the lock held, none can arrive once the sender is removed
+        let mut queues = self.queues.lock().unwrap();
+        match self.receiver.try_recv() {
+            Ok(event) => Some(event),
+            Err(_) => {
+                queues.remove(&self.saleor_api_url);
+                None
+            }
+        }
+    }
+}
+
+/// Delivers the events of an installation until its queue is idle.
+async fn deliver_queue(sinks: Arc<Mutex<Vec<Arc<dyn EventSink>>>>, max_attempts: u32, backoff: Duration, mut queue: Queue) {
+    while let Some(event) = queue.next().await {
+        let sinks = sinks.lock().unwrap().clone();
+        for sink in &sinks {
+            deliver(sink.as_ref(), &event, max_attempts, backoff).await;
+        }
+    }
+}
+
+async fn deliver(sink: &dyn EventSink, event: &EmittedEvent, max_attempts: u32, mut backoff: Duration) {
+    for attempt in 1..=max_attempts {
+        match sink.deliver(event).await {
+            Ok(()) => return,
+            Err(e) if attempt < max_attempts => {
+                tracing::warn!(event = %event.name, event_id = %event.id, "delivery attempt {} failed, retrying in {:?}: {}", attempt, backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+            }
+            Err(e) => tracing::error!(event = %event.name, event_id = %event.id, "dropped event after {} attempts: {}", attempt, e),
+        }
+    }
+}
+
+impl Default for EventEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for EventEmitter
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<EventEmitter>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "event emitter not found in request extensions").into_response())
+    }
+}
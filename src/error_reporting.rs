@@ -0,0 +1,300 @@
+use std::{any::Any, collections::VecDeque, future::Future, pin::Pin, sync::{Arc, Mutex}};
+
+use axum::{http::{Request, StatusCode}, response::{Response, IntoResponse}, body::{Body, boxed, Full}};
+use tower::{Layer, Service};
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tower_http::catch_panic::{CatchPanicLayer, ResponseForPanic};
+use utoipa::ToSchema;
+
+use crate::{http_client::HttpClient, request_id::RequestId, secrets};
+
+/// How many reports [`RecentErrors`] keeps.
+const MAX_RECENT_ERRORS: usize = 50;
+/// Messages of recent errors are cut off after this many characters.
+const MAX_SUMMARY_MESSAGE_LEN: usize = 200;
+
+/// Everything we know about a failed request at the time it is reported.
+///
+/// Queries carry auth tokens and codes, so only the path is kept, and tokens in the message are
+/// redacted.
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub message: String,
+    pub status: StatusCode,
+    pub method: String,
+    pub path: String,
+    pub request_id: Option<String>,
+    pub saleor_api_url: Option<String>,
+    pub panic: bool,
+}
+
+/// Receives server errors and panics, implement this to forward them to your error tracker.
+pub trait ErrorReporter: Send + Sync + 'static {
+    fn report(&self, report: &ErrorReport);
+}
+
+/// Logs reports through tracing, used when no error tracker is configured.
+pub struct TracingErrorReporter;
+
+impl ErrorReporter for TracingErrorReporter {
+    fn report(&self, report: &ErrorReport) {
+        tracing::error!(
+            status = report.status.as_u16(),
+            method = %report.method,
+            path = %report.path,
+            request_id = report.request_id.as_deref(),
+            saleor_api_url = report.saleor_api_url.as_deref(),
+            panic = report.panic,
+            "{}", report.message
+        );
+    }
+}
+
+/// Forwards reports to Sentry's store endpoint, configured through a regular Sentry DSN.
+pub struct SentryErrorReporter {
+    store_url: String,
+    auth_header: String,
+    client: HttpClient,
+}
+
+impl SentryErrorReporter {
+    pub fn new(client: HttpClient, dsn: &str) -> anyhow::Result<Self> {
+        let dsn = reqwest::Url::parse(dsn)?;
+        let public_key = dsn.username();
+        if public_key.is_empty() {
+            anyhow::bail!("sentry dsn is missing the public key");
+        }
+        let host = dsn.host_str().ok_or_else(|| anyhow::anyhow!("sentry dsn is missing the host"))?;
+        let port = dsn.port().map(|port| format!(":{port}")).unwrap_or_default();
+        let (path, project_id) = dsn
+            .path()
+            .rsplit_once('/')
+            .filter(|(_, project_id)| !project_id.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("sentry dsn is missing the project id"))?;
+
+        Ok(Self {
+            store_url: format!("{}://{host}{port}{path}/api/{project_id}/store/", dsn.scheme()),
+            auth_header: format!(
+                "Sentry sentry_version=7, sentry_key={public_key}, sentry_client={}/{}",
+                crate::APP_ID,
+                crate::APP_VERSION,
+            ),
+            client,
+        })
+    }
+
+    fn event(report: &ErrorReport) -> serde_json::Value {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+
+        serde_json::json!({
+            "event_id": uuid::Uuid::new_v4().simple().to_string(),
+            "timestamp": timestamp,
+            "level": "error",
+            "platform": "native",
+            "logger": crate::APP_ID,
+            "release": format!("{}@{}", crate::APP_ID, crate::APP_VERSION),
+            "message": { "formatted": report.message },
+            "request": { "method": report.method, "url": report.path },
+            "tags": {
+                "http.status_code": report.status.as_u16().to_string(),
+                "panic": report.panic.to_string(),
+                "request_id": report.request_id,
+                "tenant": report.saleor_api_url,
+            },
+        })
+    }
+}
+
+impl ErrorReporter for SentryErrorReporter {
+    fn report(&self, report: &ErrorReport) {
+        let request = self
+            .client
+            .post(&self.store_url)
+            .header("x-sentry-auth", &self.auth_header)
+            .json(&Self::event(report));
+        tokio::spawn(async move {
+            if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+                tracing::warn!("unable to send error report to sentry: {}", e);
+            }
+        });
+    }
+}
+
+/// Marker left on responses generated from a panic so the reporting layer can tell them apart.
+#[derive(Clone, Debug)]
+struct PanicMessage(String);
+
+#[derive(Clone, Copy)]
+pub struct PanicResponse;
+
+impl ResponseForPanic for PanicResponse {
+    type ResponseBody = axum::body::BoxBody;
+
+    fn response_for_panic(&mut self, err: Box<dyn Any + Send + 'static>) -> Response {
+        let message = match (err.downcast_ref::<String>(), err.downcast_ref::<&str>()) {
+            (Some(message), _) => message.clone(),
+            (_, Some(message)) => message.to_string(),
+            _ => "unknown panic".to_string(),
+        };
+        let mut response = (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response();
+        response.extensions_mut().insert(PanicMessage(message));
+        response
+    }
+}
+
+/// Turns handler panics into 500 responses, apply it inside [`ErrorReportingLayer`].
+pub fn catch_panic_layer() -> CatchPanicLayer<PanicResponse> {
+    CatchPanicLayer::custom(PanicResponse)
+}
+
+/// A report shortened for support.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorSummary {
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    pub status: u16,
+    pub method: String,
+    pub path: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saleor_api_url: Option<String>,
+    pub panic: bool,
+}
+
+impl From<&ErrorReport> for ErrorSummary {
+    fn from(report: &ErrorReport) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            status: report.status.as_u16(),
+            method: report.method.clone(),
+            path: report.path.clone(),
+            message: report.message.chars().take(MAX_SUMMARY_MESSAGE_LEN).collect(),
+            request_id: report.request_id.clone(),
+            saleor_api_url: report.saleor_api_url.clone(),
+            panic: report.panic,
+        }
+    }
+}
+
+/// The latest errors reported by an [`ErrorReportingLayer`], for the [`crate::diagnostics`] bundle.
+#[derive(Clone, Default)]
+pub struct RecentErrors(Arc<Mutex<VecDeque<ErrorSummary>>>);
+
+impl RecentErrors {
+    pub fn push(&self, report: &ErrorReport) {
+        let mut errors = self.0.lock().unwrap();
+        if errors.len() == MAX_RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(report.into());
+    }
+
+    /// Oldest first.
+    pub fn summaries(&self) -> Vec<ErrorSummary> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Reports every 5xx response (including caught panics) with its request context.
+#[derive(Clone)]
+pub struct ErrorReportingLayer {
+    reporter: Arc<dyn ErrorReporter>,
+    recent: RecentErrors,
+}
+
+impl ErrorReportingLayer {
+    pub fn new(reporter: impl ErrorReporter) -> Self {
+        Self { reporter: Arc::new(reporter), recent: RecentErrors::default() }
+    }
+
+    /// The errors reported by services of this layer.
+    pub fn recent_errors(&self) -> RecentErrors {
+        self.recent.clone()
+    }
+}
+
+impl<S> Layer<S> for ErrorReportingLayer {
+    type Service = ErrorReportingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ErrorReportingService {
+            inner,
+            reporter: self.reporter.clone(),
+            recent: self.recent.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ErrorReportingService<S> {
+    inner: S,
+    reporter: Arc<dyn ErrorReporter>,
+    recent: RecentErrors,
+}
+
+impl<S> Service<Request<Body>> for ErrorReportingService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let reporter = self.reporter.clone();
+        let recent = self.recent.clone();
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let method = req.method().to_string();
+            let path = req.uri().path().to_string();
+            let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+            let saleor_api_url = req
+                .headers()
+                .get("saleor-api-url")
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string());
+
+            let response: Response = inner.call(req).await?;
+            if !response.status().is_server_error() {
+                return Ok(response);
+            }
+
+            let status = response.status();
+            let (parts, body) = response.into_parts();
+            let (message, panic, body) = match parts.extensions.get::<PanicMessage>() {
+                Some(PanicMessage(message)) => (message.clone(), true, body),
+                None => {
+                    let bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+                    (String::from_utf8_lossy(&bytes).to_string(), false, boxed(Full::from(bytes)))
+                },
+            };
+
+            let report = ErrorReport {
+                message: secrets::redact_tokens(&message).into_owned(),
+                status,
+                method,
+                path,
+                request_id,
+                saleor_api_url,
+                panic,
+            };
+            recent.push(&report);
+            reporter.report(&report);
+
+            Ok(Response::from_parts(parts, body))
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use std::{collections::HashMap, convert::Infallible, sync::{Arc, Mutex}, time::Duration};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, extract::FromRequestParts, response::{IntoResponse, Response, sse::{Event, KeepAlive, Sse}}};
+use futures_util::{Stream, stream};
+use reqwest::StatusCode;
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tower_sessions::Session;
+
+use crate::{sessions, templating::{AppBridgeAction, NotificationStatus}};
+
+/// Events buffered per tenant before slow subscribers start missing them.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Name of [`AppEvent::progress`] events.
+pub const PROGRESS_EVENT: &str = "progress";
+/// Name of [`AppEvent::toast`] events.
+pub const TOAST_EVENT: &str = "toast";
+
+/// An event streamed to the dashboard pages of a tenant.
+#[derive(Serialize, Debug, Clone)]
+pub struct AppEvent {
+    /// Name of the event, pages listen for it with `saleorAppBridge.onEvent(name, listener)`
+    pub name: String,
+    pub data: serde_json::Value,
+}
+
+impl AppEvent {
+    pub fn new(name: impl Into<String>, data: impl Serialize) -> Self {
+        Self {
+            name: name.into(),
+            data: serde_json::to_value(data).unwrap_or_default(),
+        }
+    }
+
+    /// Progress of a long running operation, like an import.
+    pub fn progress(operation: &str, done: u64, total: u64) -> Self {
+        Self::new(PROGRESS_EVENT, serde_json::json!({
+            "operation": operation,
+            "done": done,
+            "total": total,
+        }))
+    }
+
+    /// A notification worth showing in the dashboard, like a finished import. Pages connected to
+    /// `/app/ws` dispatch it as an [`AppBridgeAction`].
+    pub fn toast(status: NotificationStatus, title: impl Into<String>) -> Self {
+        Self::new(TOAST_EVENT, AppBridgeAction::notification(status, title, None))
+    }
+}
+
+/// Broadcasts [`AppEvent`]s to the pages of a tenant, provided to handlers as a request extension.
+#[derive(Clone, Default)]
+pub struct EventHub {
+    channels: Arc<Mutex<HashMap<String, broadcast::Sender<AppEvent>>>>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends the event to every page of the given installation that is currently open.
+    ///
+    /// Returns the number of subscribers that received it.
+    pub fn publish(&self, saleor_api_url: &str, event: AppEvent) -> usize {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(sender) = channels.get(saleor_api_url) else {
+            return 0;
+        };
+
+        match sender.send(event) {
+            Ok(receivers) => receivers,
+            Err(_) => {
+                // nobody listens anymore
+                channels.remove(saleor_api_url);
+                0
+            }
+        }
+    }
+
+    pub fn subscribe(&self, saleor_api_url: &str) -> broadcast::Receiver<AppEvent> {
+        self.channels
+            .lock()
+            .unwrap()
+            .entry(saleor_api_url.to_string())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub fn stream(&self, saleor_api_url: &str) -> impl Stream<Item = Result<Event, Infallible>> {
+        stream::unfold(self.subscribe(saleor_api_url), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        let sse = Event::default().event(&event.name).json_data(&event.data).unwrap_or_default();
+                        return Some((Ok(sse), receiver));
+                    },
+                    Err(RecvError::Lagged(missed)) => tracing::warn!("event subscriber lagged behind, missed {missed} events"),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for EventHub
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<EventHub>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "event hub not found in request extensions").into_response())
+    }
+}
+
+/// `GET /app/events`, streams the events of the session's current tenant.
+///
+/// `EventSource` can't send headers, so this has to sit behind a `SaleorAuthLayer`, which falls
+/// back to the session for both the tenant and the token.
+pub async fn events(hub: EventHub, session: Session) -> Result<impl IntoResponse, Response> {
+    let Some(saleor_api_url) = sessions::current_tenant(&session) else {
+        return Err((StatusCode::BAD_REQUEST, "couldn't determine saleor api url").into_response());
+    };
+
+    Ok(Sse::new(hub.stream(&saleor_api_url)).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
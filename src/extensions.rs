@@ -0,0 +1,387 @@
+//! Dashboard extensions served as app pages.
+//!
+//! Each [`ExtensionPage`] is a route under `/app` and an entry of the manifest's `extensions`, so
+//! the two can't drift apart. The dashboard opens the page in its iframe before the AppBridge
+//! handshake authenticated the session, so document requests get a loading shell which loads the
+//! page through htmx once the dashboard is ready. Those requests, and requests with a dashboard
+//! token, go through a [`SaleorAuthLayer`] requiring the page's permissions, and the handler gets
+//! the verified user along with the objects the page was opened for as [`ExtensionContext`].
+//!
+//! Pages opened [`ExtensionPage::in_new_tab`] have no AppBridge to hand them a token. The dashboard
+//! posts the token to [`NEW_TAB_PATH`] instead, which verifies it, keeps it in the session and
+//! redirects to the page, so the session authenticates its requests like after `/api/auth`.
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use askama::Template;
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{FromRequest, FromRequestParts, OriginalUri, Query},
+    handler::Handler,
+    http::{header::AUTHORIZATION, request::Parts, Request, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Extension, Form, Router,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use tower::{Layer, Service};
+use tower_sessions::Session;
+
+use crate::{
+    audit::{AuditEvent, AuditEventKind, AuditLog},
+    http_client::HttpClient,
+    saleor::{
+        verify_jwt_with_jwks, Claims, JwksCache, JwtValidation, SaleorApl, SaleorAppExtension, SaleorAppExtensionMount, SaleorAppExtensionOptions,
+        SaleorAppExtensionTarget, SaleorAppPermission, SaleorAuthLayer, SaleorNewTabMethod, SaleorNewTabTarget, SaleorPermission, VerifyJwtError,
+    },
+    secrets::SecretString,
+    sessions::{self, TenantSessionExpiry},
+    templating::{Localizer, Page, PageContext, HX_BOOSTED_HEADER, HX_REQUEST_HEADER},
+    uninstalled::UninstalledTenantsLayer,
+};
+
+/// A page the dashboard opens at one of its mounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionPage {
+    /// Path below `/app`, like `/orders/panel`
+    pub path: String,
+    pub label: String,
+    pub mount: SaleorAppExtensionMount,
+    /// Permissions the user needs, also listed in the manifest so the dashboard hides the extension
+    pub permissions: Vec<SaleorAppPermission>,
+    /// [`SaleorAppExtensionTarget::AppPage`], or [`SaleorAppExtensionTarget::NewTab`] for pages
+    /// opened in a new tab
+    pub target: SaleorAppExtensionTarget,
+}
+
+/// Where the dashboard posts the token of pages opened in a new tab.
+pub const NEW_TAB_PATH: &str = "/app/new-tab";
+
+impl ExtensionPage {
+    pub fn new(path: impl Into<String>, label: impl Into<String>, mount: SaleorAppExtensionMount) -> Self {
+        Self {
+            path: path.into(),
+            label: label.into(),
+            mount,
+            permissions: vec![],
+            target: SaleorAppExtensionTarget::AppPage,
+        }
+    }
+
+    pub fn with_permissions(mut self, permissions: &[SaleorAppPermission]) -> Self {
+        self.permissions = permissions.to_vec();
+        self
+    }
+
+    /// Opens the page in a new browser tab, authenticated through [`NEW_TAB_PATH`].
+    pub fn in_new_tab(mut self) -> Self {
+        self.target = SaleorAppExtensionTarget::NewTab;
+        self
+    }
+
+    /// Url of the page.
+    pub fn url(&self) -> String {
+        format!("/app{}", self.path)
+    }
+
+    /// Url of the page in the manifest, the handoff redirecting to the page for new tabs.
+    pub fn manifest_url(&self) -> String {
+        match self.target {
+            SaleorAppExtensionTarget::NewTab => {
+                let query: String = Url::parse_with_params("http://localhost", [("to", self.url())])
+                    .map(|url| url.query().unwrap_or_default().to_string())
+                    .unwrap_or_default();
+                format!("{}?{}", NEW_TAB_PATH, query)
+            }
+            _ => self.url(),
+        }
+    }
+
+    /// Query parameter the dashboard passes the objects of the mount in, `None` for mounts that
+    /// aren't about particular objects, like the navigation.
+    pub fn object_id_param(&self) -> Option<&'static str> {
+        match self.mount {
+            SaleorAppExtensionMount::OrderDetailsMoreActions => Some("orderId"),
+            SaleorAppExtensionMount::ProductDetailsMoreActions => Some("productId"),
+            SaleorAppExtensionMount::CustomerDetailsMoreActions => Some("customerId"),
+            SaleorAppExtensionMount::OrderOverviewMoreActions => Some("orderIds"),
+            SaleorAppExtensionMount::ProductOverviewMoreActions => Some("productIds"),
+            SaleorAppExtensionMount::CustomerOverviewMoreActions => Some("customerIds"),
+            _ => None,
+        }
+    }
+
+    pub fn manifest_extension(&self) -> SaleorAppExtension {
+        SaleorAppExtension {
+            label: self.label.clone(),
+            mount: self.mount.clone(),
+            target: self.target.clone(),
+            permissions: self.permissions.clone(),
+            url: self.manifest_url(),
+            options: (self.target == SaleorAppExtensionTarget::NewTab).then(|| SaleorAppExtensionOptions {
+                new_tab_target: Some(SaleorNewTabTarget { method: SaleorNewTabMethod::Post }),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// The extension pages of the app, with their handlers.
+#[derive(Default)]
+pub struct ExtensionPages {
+    pages: Vec<(ExtensionPage, Router)>,
+}
+
+impl ExtensionPages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `page` with `handler`, which usually extracts an [`ExtensionContext`].
+    pub fn with_page<H, T>(mut self, page: ExtensionPage, handler: H) -> Self
+    where
+        H: Handler<T, (), Body>,
+        T: 'static,
+    {
+        let router = Router::new().route(&page.path, get(handler));
+        self.pages.push((page, router));
+        self
+    }
+
+    pub fn pages(&self) -> impl Iterator<Item = &ExtensionPage> {
+        self.pages.iter().map(|(page, _)| page)
+    }
+
+    /// The `extensions` of the manifest.
+    pub fn manifest_extensions(&self) -> Vec<SaleorAppExtension> {
+        self.pages().map(ExtensionPage::manifest_extension).collect()
+    }
+
+    /// The routes of the pages, to be nested under `/app`. Dashboard tokens expire from the session
+    /// after `session_expiry`, like for the API.
+    pub fn router(self, session_expiry: TenantSessionExpiry) -> Router {
+        let new_tab_pages: Vec<String> = self.pages().filter(|page| page.target == SaleorAppExtensionTarget::NewTab).map(ExtensionPage::url).collect();
+        let router = match new_tab_pages.is_empty() {
+            true => Router::new(),
+            false => {
+                let path = NEW_TAB_PATH.strip_prefix("/app").unwrap_or(NEW_TAB_PATH);
+                Router::new().route(path, post(new_tab_handoff).layer(Extension(NewTabPages(Arc::new(new_tab_pages)))))
+            }
+        };
+        self.pages.into_iter().fold(router, |router, (page, page_router)| {
+            let permissions: Vec<SaleorPermission> = page.permissions.iter().cloned().map(SaleorPermission::from).collect();
+            let page_router = page_router
+                .route_layer(SaleorAuthLayer::with_permissions(&permissions).with_session_expiry(session_expiry))
+                .route_layer(UninstalledTenantsLayer)
+                .route_layer(ExtensionShellLayer)
+                .layer(Extension(page));
+            router.merge(page_router)
+        })
+    }
+}
+
+/// What a handler of an [`ExtensionPage`] knows about the request.
+///
+/// Only available behind the auth layer of [`ExtensionPages::router`].
+#[derive(Clone)]
+pub struct ExtensionContext {
+    pub page: ExtensionPage,
+    pub ctx: PageContext,
+    /// The verified claims of the dashboard user
+    pub claims: Claims,
+    /// Ids of the objects the page was opened for, one on details pages and the selection on
+    /// overview pages, see [`ExtensionPage::object_id_param`]
+    pub object_ids: Vec<String>,
+}
+
+impl ExtensionContext {
+    /// The object of details pages.
+    pub fn object_id(&self) -> Option<&str> {
+        self.object_ids.first().map(String::as_str)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ExtensionContext
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let page = parts
+            .extensions
+            .get::<ExtensionPage>()
+            .cloned()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "extension page not found in request extensions").into_response())?;
+        let claims = parts
+            .extensions
+            .get::<Claims>()
+            .cloned()
+            .ok_or((StatusCode::UNAUTHORIZED, "extension pages need an authenticated user").into_response())?;
+        let object_ids = page.object_id_param().map(|param| object_ids(parts, param)).unwrap_or_default();
+        let Ok(ctx) = PageContext::from_request_parts(parts, state).await;
+
+        Ok(Self { page, ctx, claims, object_ids })
+    }
+}
+
+/// Values of `param`, repeated or comma separated.
+fn object_ids(parts: &Parts, param: &str) -> Vec<String> {
+    let Ok(url) = Url::parse(&format!("http://localhost{}", parts.uri)) else {
+        return vec![];
+    };
+    url.query_pairs()
+        .filter(|(key, _)| key == param)
+        .flat_map(|(_, value)| value.split(',').map(str::trim).filter(|id| !id.is_empty()).map(str::to_string).collect::<Vec<_>>())
+        .collect()
+}
+
+/// The urls [`new_tab_handoff`] redirects to.
+#[derive(Clone)]
+struct NewTabPages(Arc<Vec<String>>);
+
+#[derive(Deserialize)]
+struct NewTabTarget {
+    to: String,
+}
+
+/// The form the dashboard posts to [`NEW_TAB_PATH`], with a verified token.
+struct NewTabHandoff {
+    /// The page to redirect to
+    to: String,
+    saleor_api_url: String,
+    token: SecretString,
+    generation: u64,
+    /// The rest of the form, like the ids of the objects
+    context: Vec<(String, String)>,
+}
+
+#[async_trait]
+impl<S> FromRequest<S, Body> for NewTabHandoff
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = request.into_parts();
+        let Extension(NewTabPages(pages)) = Extension::<NewTabPages>::from_request_parts(&mut parts, state).await.map_err(IntoResponse::into_response)?;
+        let Query(NewTabTarget { to }) = Query::<NewTabTarget>::from_request_parts(&mut parts, state).await.map_err(IntoResponse::into_response)?;
+        if !pages.contains(&to) {
+            return Err((StatusCode::BAD_REQUEST, "not a page opened in a new tab").into_response());
+        }
+        let apl = SaleorApl::from_request_parts(&mut parts, state).await.map_err(IntoResponse::into_response)?;
+        let client = HttpClient::from_request_parts(&mut parts, state).await.map_err(IntoResponse::into_response)?;
+        let audit_log = AuditLog::from_request_parts(&mut parts, state).await.map_err(IntoResponse::into_response)?;
+        let Ok(jwks_cache) = JwksCache::from_request_parts(&mut parts, state).await;
+        let Ok(jwt_validation) = JwtValidation::from_request_parts(&mut parts, state).await;
+        let Form(form) = Form::<Vec<(String, String)>>::from_request(Request::from_parts(parts, body), state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let field = |name: &str| form.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+        let (Some(api_url), Some(token)) = (field("saleorApiUrl"), field("accessToken").map(SecretString::from)) else {
+            return Err((StatusCode::BAD_REQUEST, "saleorApiUrl and accessToken are required").into_response());
+        };
+        let auth_data = apl
+            .get(&apl.id(&api_url))
+            .await
+            .map_err(IntoResponse::into_response)?
+            .ok_or_else(|| (StatusCode::UNAUTHORIZED, "unknown installation").into_response())?;
+        let jwks = match &auth_data.jwks {
+            Some(jwks) => jwks_cache.get(&api_url, jwks).map_err(|e| VerifyJwtError::Invalid(e).into_response())?,
+            None => jwks_cache.fetch(&client, &auth_data).await.map_err(IntoResponse::into_response)?,
+        };
+        if let Err(e) = verify_jwt_with_jwks(&jwks, token.expose(), &[], &jwt_validation) {
+            audit_log.record(AuditEvent::new(&api_url, AuditEventKind::JwtVerificationFailed).with_detail(e.to_string()));
+            return Err(e.into_response());
+        }
+
+        // the token stays in the session, the rest is the context of the page
+        let context = form.into_iter().filter(|(key, _)| key != "accessToken").collect();
+        Ok(Self { to, saleor_api_url: auth_data.saleor_api_url, token, generation: auth_data.generation, context })
+    }
+}
+
+/// `POST /app/new-tab?to={page}`, the form the dashboard posts when it opens a page in a new tab.
+///
+/// The token is verified against the installation's JWKS and kept in the session, then the browser
+/// is redirected to the page with the rest of the form, like the ids of the objects, as query. Only
+/// pages opened in a new tab are redirected to.
+async fn new_tab_handoff(session: Session, handoff: NewTabHandoff) -> Response {
+    if let Err(e) = sessions::store_dashboard_token(&session, &handoff.saleor_api_url, &handoff.token, Some(handoff.generation)) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    let query = Url::parse_with_params("http://localhost", &handoff.context).map(|url| url.query().unwrap_or_default().to_string()).unwrap_or_default();
+    match query.is_empty() {
+        true => Redirect::to(&handoff.to).into_response(),
+        false => Redirect::to(&format!("{}?{}", handoff.to, query)).into_response(),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "pages/extension_shell.html")]
+struct ExtensionShellContent {
+    i18n: Localizer,
+    url: String,
+}
+
+/// Answers document requests of extension pages with a shell loading the page once the AppBridge
+/// handshake authenticated the session.
+///
+/// htmx requests and requests with a dashboard token are passed on.
+#[derive(Clone)]
+struct ExtensionShellLayer;
+
+impl<S> Layer<S> for ExtensionShellLayer {
+    type Service = ExtensionShellService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExtensionShellService { inner }
+    }
+}
+
+#[derive(Clone)]
+struct ExtensionShellService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for ExtensionShellService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let headers = req.headers();
+        let htmx = headers.contains_key(HX_REQUEST_HEADER) && !headers.contains_key(HX_BOOSTED_HEADER);
+        let authenticated = htmx || headers.contains_key(AUTHORIZATION);
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            if authenticated {
+                return inner.call(req).await;
+            }
+            let (mut parts, _) = req.into_parts();
+            // the shell loads the page with the query of the dashboard, under the prefixes it was nested in
+            let url = parts
+                .extensions
+                .get::<OriginalUri>()
+                .map_or_else(|| parts.uri.to_string(), |uri| uri.0.to_string());
+            let title = parts.extensions.get::<ExtensionPage>().map(|page| page.label.clone()).unwrap_or_default();
+            let Ok(ctx) = PageContext::from_request_parts(&mut parts, &()).await;
+            let content = ExtensionShellContent { i18n: ctx.i18n.clone(), url };
+            Ok(Page::new(ctx, title, content).into_response())
+        })
+    }
+}
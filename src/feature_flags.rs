@@ -0,0 +1,176 @@
+//! Per installation feature flags, for rolling out risky behavior one store at a time.
+//!
+//! Flags are kept with the other settings in the private metadata of the app, as
+//! `feature_flag.{name}` keys. Handlers extract [`FeatureFlags`] behind the auth layer and pass it
+//! on to templates that check flags themselves, operators toggle them for any installation through
+//! `GET`/`PUT /api/admin/feature-flags`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use axum::{extract::{FromRequestParts, Query}, http::request::Parts, response::{IntoResponse, Response}, Json};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    admin_auth::AdminOperator,
+    audit::{AuditEvent, AuditEventKind, AuditLog},
+    http_client::HttpClient,
+    saleor::{MetadataSettingsManager, SaleorApl, SettingsError, SettingsManager, TenantSettings},
+};
+
+/// Prefix of the settings keys flags are stored under.
+pub const FEATURE_FLAG_PREFIX: &str = "feature_flag.";
+
+/// The flags of an installation, flags that were never set are disabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureFlags {
+    flags: BTreeMap<String, String>,
+}
+
+impl FeatureFlags {
+    /// Picks the flags out of all settings of an installation, empty values are unset flags.
+    pub fn from_settings(settings: HashMap<String, String>) -> Self {
+        let flags = settings
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .filter_map(|(key, value)| Some((key.strip_prefix(FEATURE_FLAG_PREFIX)?.to_string(), value)))
+            .collect();
+        Self { flags }
+    }
+
+    pub async fn load(settings: &dyn SettingsManager) -> Result<Self, SettingsError> {
+        Ok(Self::from_settings(settings.get_all().await?))
+    }
+
+    /// Sets a flag, an empty value unsets it since metadata keys can't be removed.
+    pub async fn set(settings: &dyn SettingsManager, name: &str, value: &str) -> Result<(), SettingsError> {
+        settings.set(HashMap::from([(format!("{FEATURE_FLAG_PREFIX}{name}"), value.to_string())])).await
+    }
+
+    /// Whether a boolean flag is on, `true`, `1`, `on` and `yes` turn flags on.
+    pub fn enabled(&self, name: &str) -> bool {
+        self.value(name).is_some_and(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1" | "on" | "yes"))
+    }
+
+    /// The value of a string flag.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.flags.get(name).map(String::as_str)
+    }
+
+    pub fn all(&self) -> &BTreeMap<String, String> {
+        &self.flags
+    }
+}
+
+/// The flags of the installation the request is made for, loaded once per request.
+///
+/// Only use this behind the auth layer, like [`TenantSettings`].
+#[async_trait]
+impl<S> FromRequestParts<S> for FeatureFlags
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(flags) = parts.extensions.get::<FeatureFlags>() {
+            return Ok(flags.clone());
+        }
+        let settings = TenantSettings::from_request_parts(parts, state).await?;
+        let flags = FeatureFlags::load(settings.as_ref()).await.map_err(IntoResponse::into_response)?;
+        parts.extensions.insert(flags.clone());
+        Ok(flags)
+    }
+}
+
+/// Names are what follows [`FEATURE_FLAG_PREFIX`] in metadata keys.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagsQuery {
+    pub saleor_api_url: String,
+}
+
+/// The flags of an installation.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallationFeatureFlags {
+    pub saleor_api_url: String,
+    pub flags: BTreeMap<String, String>,
+}
+
+/// Body of `PUT /api/admin/feature-flags`.
+#[derive(Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagUpdate {
+    pub saleor_api_url: String,
+    pub name: String,
+    /// `true`/`false` for boolean flags, empty to unset the flag
+    pub value: String,
+}
+
+/// The settings of another installation than the one making the request.
+async fn installation_settings(apl: &SaleorApl, client: HttpClient, saleor_api_url: &str) -> Result<MetadataSettingsManager, Response> {
+    match apl.get(&apl.id(saleor_api_url)).await {
+        Ok(Some(auth_data)) => Ok(MetadataSettingsManager::new(client, auth_data)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "installation not found").into_response()),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+/// `GET /api/admin/feature-flags`, the flags of an installation.
+#[utoipa::path(get, path = "/api/admin/feature-flags", tag = "admin", params(FeatureFlagsQuery), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The flags that are set", body = InstallationFeatureFlags),
+    (status = 404, description = "The installation doesn't exist"),
+))]
+pub async fn get_feature_flags(apl: SaleorApl, client: HttpClient, Query(query): Query<FeatureFlagsQuery>) -> Response {
+    let settings = match installation_settings(&apl, client, &query.saleor_api_url).await {
+        Ok(settings) => settings,
+        Err(response) => return response,
+    };
+    match FeatureFlags::load(&settings).await {
+        Ok(flags) => Json(InstallationFeatureFlags { saleor_api_url: query.saleor_api_url, flags: flags.flags }).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `PUT /api/admin/feature-flags`, sets or unsets a flag of an installation.
+#[utoipa::path(put, path = "/api/admin/feature-flags", tag = "admin", request_body = FeatureFlagUpdate, security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The flags after the change", body = InstallationFeatureFlags),
+    (status = 400, description = "The flag name is invalid"),
+    (status = 404, description = "The installation doesn't exist"),
+))]
+pub async fn set_feature_flag(
+    apl: SaleorApl,
+    client: HttpClient,
+    audit_log: AuditLog,
+    AdminOperator(operator): AdminOperator,
+    Json(update): Json<FeatureFlagUpdate>,
+) -> Response {
+    if !is_valid_name(&update.name) {
+        return (StatusCode::BAD_REQUEST, "flag names consist of up to 64 letters, digits, '_', '-' and '.'").into_response();
+    }
+    let settings = match installation_settings(&apl, client, &update.saleor_api_url).await {
+        Ok(settings) => settings,
+        Err(response) => return response,
+    };
+    let value = update.value.trim();
+    if let Err(e) = FeatureFlags::set(&settings, &update.name, value).await {
+        return e.into_response();
+    }
+    tracing::info!(saleor_api_url = %update.saleor_api_url, flag = %update.name, value, "feature flag changed");
+    audit_log.record(
+        AuditEvent::new(&update.saleor_api_url, AuditEventKind::FeatureFlagChanged)
+            .with_detail(format!("{} set to {value:?} by {operator}", update.name)),
+    );
+
+    match FeatureFlags::load(&settings).await {
+        Ok(flags) => Json(InstallationFeatureFlags { saleor_api_url: update.saleor_api_url, flags: flags.flags }).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
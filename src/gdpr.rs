@@ -0,0 +1,298 @@
+//! Export and erasure of everything the app keeps about a customer, for data subject requests under the GDPR.
+//!
+//! A request names the customer by email and covers the installation it's made for. The data is
+//! collected from every [`PersonalDataSource`]:
+//!
+//! * [`SettingsDataSource`], settings of the installation mentioning the customer
+//! * [`AuditLogDataSource`], audit events mentioning the customer, erasing redacts their details
+//!
+//! The APL only holds the tokens of installations, the export names the installation but erasing
+//! leaves it alone. Jobs of the [`crate::scheduler`] keep no state between runs. Apps keeping
+//! customer data elsewhere, like a database or a job queue, register a source of their own with
+//! [`PersonalDataSources::with_source`] in [`crate::app::build`].
+
+use std::{collections::{BTreeMap, HashMap}, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, extract::{FromRequestParts, Query}, response::{IntoResponse, Response}, Json};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    audit::{AuditEvent, AuditEventKind, AuditLog},
+    http_client::HttpClient,
+    installations::Installation,
+    saleor::{AuthData, CurrentInstallation, MetadataSettingsManager, SettingsManager},
+};
+
+#[derive(Debug)]
+pub struct PersonalDataError(pub String);
+
+impl std::fmt::Display for PersonalDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "personal data error: {}", self.0)
+    }
+}
+
+impl std::error::Error for PersonalDataError {}
+
+/// A source couldn't be read or changed, the request can be repeated since erasing is idempotent.
+impl IntoResponse for PersonalDataError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+/// A customer's request for their data, made to a single installation.
+#[derive(Clone)]
+pub struct DataRequest {
+    /// Trimmed and lowercased
+    pub email: String,
+    pub auth_data: AuthData,
+    pub client: HttpClient,
+}
+
+impl DataRequest {
+    pub fn new(email: &str, auth_data: AuthData, client: HttpClient) -> Self {
+        Self {
+            email: email.trim().to_lowercase(),
+            auth_data,
+            client,
+        }
+    }
+
+    /// Whether `text` contains the email of the customer, ignoring case.
+    pub fn mentioned_in(&self, text: &str) -> bool {
+        !self.email.is_empty() && text.to_lowercase().contains(&self.email)
+    }
+}
+
+/// A place the app keeps customer data in.
+#[async_trait]
+pub trait PersonalDataSource: Send + Sync + 'static {
+    /// The key of the source in exports and erasure reports.
+    fn name(&self) -> &str;
+
+    /// Every record of the source about the customer.
+    async fn export(&self, request: &DataRequest) -> Result<Vec<Value>, PersonalDataError>;
+
+    /// Removes the customer from the source, returning how many records were erased or redacted.
+    async fn erase(&self, request: &DataRequest) -> Result<usize, PersonalDataError>;
+}
+
+/// Settings of the installation whose value mentions the customer, erasing empties them.
+pub struct SettingsDataSource;
+
+impl SettingsDataSource {
+    async fn matching(request: &DataRequest) -> Result<(MetadataSettingsManager, HashMap<String, String>), PersonalDataError> {
+        let manager = MetadataSettingsManager::new(request.client.clone(), request.auth_data.clone());
+        let mut settings = manager.get_all().await.map_err(|e| PersonalDataError(e.to_string()))?;
+        settings.retain(|_, value| request.mentioned_in(value));
+        Ok((manager, settings))
+    }
+}
+
+#[async_trait]
+impl PersonalDataSource for SettingsDataSource {
+    fn name(&self) -> &str {
+        "settings"
+    }
+
+    async fn export(&self, request: &DataRequest) -> Result<Vec<Value>, PersonalDataError> {
+        let (_, settings) = Self::matching(request).await?;
+        let settings: BTreeMap<String, String> = settings.into_iter().collect();
+        Ok(settings.into_iter().map(|(key, value)| json!({ "key": key, "value": value })).collect())
+    }
+
+    async fn erase(&self, request: &DataRequest) -> Result<usize, PersonalDataError> {
+        let (manager, settings) = Self::matching(request).await?;
+        if settings.is_empty() {
+            return Ok(0);
+        }
+        let erased = settings.len();
+        // metadata can only be overwritten, empty settings count as unset everywhere
+        let emptied = settings.into_keys().map(|key| (key, String::new())).collect();
+        manager.set(emptied).await.map_err(|e| PersonalDataError(e.to_string()))?;
+        Ok(erased)
+    }
+}
+
+/// Audit events of the installation whose detail mentions the customer.
+pub struct AuditLogDataSource {
+    audit_log: AuditLog,
+}
+
+impl AuditLogDataSource {
+    pub fn new(audit_log: AuditLog) -> Self {
+        Self { audit_log }
+    }
+}
+
+#[async_trait]
+impl PersonalDataSource for AuditLogDataSource {
+    fn name(&self) -> &str {
+        "auditLog"
+    }
+
+    async fn export(&self, request: &DataRequest) -> Result<Vec<Value>, PersonalDataError> {
+        let events = self.audit_log.query(&request.auth_data.saleor_api_url, usize::MAX).await;
+        events
+            .into_iter()
+            .filter(|event| event.detail.as_deref().is_some_and(|detail| request.mentioned_in(detail)))
+            .map(|event| serde_json::to_value(event).map_err(|e| PersonalDataError(e.to_string())))
+            .collect()
+    }
+
+    async fn erase(&self, request: &DataRequest) -> Result<usize, PersonalDataError> {
+        self.audit_log
+            .redact(&request.auth_data.saleor_api_url, &request.email)
+            .await
+            .map_err(|e| PersonalDataError(format!("unable to redact audit log: {}", e)))
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonalDataExport {
+    pub email: String,
+    /// RFC 3339 timestamp
+    pub exported_at: String,
+    pub installation: Installation,
+    /// The records of every source by its name
+    pub sources: BTreeMap<String, Vec<Value>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ErasureReport {
+    /// The number of records erased from every source by its name
+    pub erased: BTreeMap<String, usize>,
+}
+
+impl ErasureReport {
+    pub fn total(&self) -> usize {
+        self.erased.values().sum()
+    }
+}
+
+/// The sources requests are served from, provided to handlers as a request extension.
+#[derive(Clone, Default)]
+pub struct PersonalDataSources {
+    sources: Vec<Arc<dyn PersonalDataSource>>,
+}
+
+impl PersonalDataSources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_source(mut self, source: impl PersonalDataSource) -> Self {
+        self.sources.push(Arc::new(source));
+        self
+    }
+
+    pub async fn export(&self, request: &DataRequest) -> Result<PersonalDataExport, PersonalDataError> {
+        let mut sources = BTreeMap::new();
+        for source in &self.sources {
+            sources.insert(source.name().to_string(), source.export(request).await?);
+        }
+        Ok(PersonalDataExport {
+            email: request.email.clone(),
+            exported_at: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            installation: Installation::from(request.auth_data.clone()),
+            sources,
+        })
+    }
+
+    /// Erases the customer from every source, stopping at the first that fails.
+    pub async fn erase(&self, request: &DataRequest) -> Result<ErasureReport, PersonalDataError> {
+        let mut erased = BTreeMap::new();
+        for source in &self.sources {
+            erased.insert(source.name().to_string(), source.erase(request).await?);
+        }
+        Ok(ErasureReport { erased })
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PersonalDataSources
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<PersonalDataSources>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "personal data sources not found in request extensions").into_response())
+    }
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct PersonalDataQuery {
+    pub email: String,
+}
+
+fn data_request(auth_data: AuthData, client: HttpClient, email: &str) -> Result<DataRequest, (StatusCode, &'static str)> {
+    let email = email.trim();
+    if email.len() < 3 || !email.contains('@') {
+        return Err((StatusCode::BAD_REQUEST, "not an email address"));
+    }
+    Ok(DataRequest::new(email, auth_data, client))
+}
+
+/// `GET /api/personal-data?email=...`, everything the app keeps about the customer as JSON.
+#[utoipa::path(get, path = "/api/personal-data", tag = "admin", params(PersonalDataQuery), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "Everything the app keeps about the customer", body = PersonalDataExport),
+    (status = 400, description = "Not an email address"),
+))]
+pub async fn export_personal_data(
+    sources: PersonalDataSources,
+    audit_log: AuditLog,
+    client: HttpClient,
+    CurrentInstallation(auth_data): CurrentInstallation,
+    Query(query): Query<PersonalDataQuery>,
+) -> Response {
+    let saleor_api_url = auth_data.saleor_api_url.clone();
+    let request = match data_request(auth_data, client, &query.email) {
+        Ok(request) => request,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let export = match sources.export(&request).await {
+        Ok(export) => export,
+        Err(e) => return e.into_response(),
+    };
+    // the email stays out of the audit log, it would have to be erased again
+    let records: usize = export.sources.values().map(Vec::len).sum();
+    audit_log.record(AuditEvent::new(&saleor_api_url, AuditEventKind::PersonalDataExported).with_detail(format!("{records} records exported")));
+    Json(export).into_response()
+}
+
+/// `DELETE /api/personal-data?email=...`, erases the customer from every source.
+#[utoipa::path(delete, path = "/api/personal-data", tag = "admin", params(PersonalDataQuery), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "How many records were erased from every source", body = ErasureReport),
+    (status = 400, description = "Not an email address"),
+))]
+pub async fn erase_personal_data(
+    sources: PersonalDataSources,
+    audit_log: AuditLog,
+    client: HttpClient,
+    CurrentInstallation(auth_data): CurrentInstallation,
+    Query(query): Query<PersonalDataQuery>,
+) -> Response {
+    let saleor_api_url = auth_data.saleor_api_url.clone();
+    let request = match data_request(auth_data, client, &query.email) {
+        Ok(request) => request,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let report = match sources.erase(&request).await {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::warn!(saleor_api_url, "{}", e);
+            return e.into_response();
+        }
+    };
+    tracing::info!(saleor_api_url, erased = report.total(), "erased personal data");
+    audit_log.record(AuditEvent::new(&saleor_api_url, AuditEventKind::PersonalDataErased).with_detail(format!("{} records erased", report.total())));
+    Json(report).into_response()
+}
@@ -0,0 +1,369 @@
+//! Read-through cache of idempotent GraphQL queries, per installation.
+//!
+//! Data like the shop settings or the channels rarely changes, yet every page load of the dashboard
+//! asks Saleor for it again. [`GraphqlCache::fetch`] keeps answers keyed by installation, operation
+//! and variables for `GRAPHQL_CACHE_TTL_SECS`. Operations are dropped early with
+//! [`GraphqlCache::invalidate`], by the handler that changed the data or by
+//! [`GraphqlCacheInvalidationLayer`] once a webhook announcing the change was handled.
+//!
+//! [`MemoryGraphqlCacheStore`] keeps answers per process, [`RedisGraphqlCacheStore`] (with the
+//! `redis` feature) shares them between replicas. Without `GRAPHQL_CACHE` nothing is cached.
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use axum::{body::Body, extract::FromRequestParts, http::{request::Parts, Request}, response::Response};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tower::{Layer, Service};
+
+use crate::saleor::{SaleorAsyncWebhookEvent, SALEOR_API_URL_HEADER, SALEOR_EVENT_HEADER};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphqlCacheError(pub String);
+
+impl std::fmt::Display for GraphqlCacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "graphql cache error: {}", self.0)
+    }
+}
+
+impl std::error::Error for GraphqlCacheError {}
+
+/// Where cached answers are kept, grouped by installation and operation.
+#[async_trait]
+pub trait GraphqlCacheStore: Send + Sync + 'static {
+    async fn get(&self, tenant: &str, operation: &str, variables: &str) -> Result<Option<String>, GraphqlCacheError>;
+    async fn set(&self, tenant: &str, operation: &str, variables: &str, data: String, ttl: Duration) -> Result<(), GraphqlCacheError>;
+    /// Drops the answers of `operation` for every variable.
+    async fn invalidate(&self, tenant: &str, operation: &str) -> Result<(), GraphqlCacheError>;
+
+    /// Drops every answer of an installation once it was removed. Stores whose answers expire on
+    /// their own, like Redis, needn't do anything.
+    async fn remove_tenant(&self, _tenant: &str) -> Result<(), GraphqlCacheError> {
+        Ok(())
+    }
+}
+
+type Answers = HashMap<String, (String, Instant)>;
+
+/// Answers within this process. Clones share them, expired ones are dropped whenever an answer is
+/// added.
+#[derive(Clone, Default)]
+pub struct MemoryGraphqlCacheStore {
+    operations: Arc<Mutex<HashMap<(String, String), Answers>>>,
+}
+
+impl MemoryGraphqlCacheStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl GraphqlCacheStore for MemoryGraphqlCacheStore {
+    async fn get(&self, tenant: &str, operation: &str, variables: &str) -> Result<Option<String>, GraphqlCacheError> {
+        let operations = self.operations.lock().unwrap();
+        let answer = operations
+            .get(&(tenant.to_string(), operation.to_string()))
+            .and_then(|answers| answers.get(variables))
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(data, _)| data.clone());
+        Ok(answer)
+    }
+
+    async fn set(&self, tenant: &str, operation: &str, variables: &str, data: String, ttl: Duration) -> Result<(), GraphqlCacheError> {
+        let now = Instant::now();
+        let mut operations = self.operations.lock().unwrap();
+        operations.retain(|_, answers| {
+            answers.retain(|_, (_, expires_at)| *expires_at > now);
+            !answers.is_empty()
+        });
+        operations.entry((tenant.to_string(), operation.to_string())).or_default().insert(variables.to_string(), (data, now + ttl));
+        Ok(())
+    }
+
+    async fn invalidate(&self, tenant: &str, operation: &str) -> Result<(), GraphqlCacheError> {
+        self.operations.lock().unwrap().remove(&(tenant.to_string(), operation.to_string()));
+        Ok(())
+    }
+
+    async fn remove_tenant(&self, tenant: &str) -> Result<(), GraphqlCacheError> {
+        self.operations.lock().unwrap().retain(|(cached_tenant, _), _| cached_tenant != tenant);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum GraphqlCacheBackend {
+    /// Every query goes to Saleor
+    #[default]
+    Disabled,
+    /// Answers are cached per process
+    Memory,
+    /// Answers are kept in Redis at the given connection url (requires the `redis` feature)
+    Redis(String),
+}
+
+/// Configured by `GRAPHQL_CACHE` and `GRAPHQL_CACHE_TTL_SECS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphqlCacheConfig {
+    pub backend: GraphqlCacheBackend,
+    /// How long answers are used, unless they're invalidated before
+    pub ttl: Duration,
+}
+
+impl Default for GraphqlCacheConfig {
+    fn default() -> Self {
+        Self {
+            backend: GraphqlCacheBackend::default(),
+            ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The cache of the app, provided to handlers as a request extension. Clones share the store.
+///
+/// Extracting it never fails, routers built without [`crate::app::build`] get a disabled cache.
+#[derive(Clone, Default)]
+pub struct GraphqlCache {
+    store: Option<Arc<dyn GraphqlCacheStore>>,
+    ttl: Duration,
+    invalidations: Arc<HashMap<SaleorAsyncWebhookEvent, Vec<&'static str>>>,
+}
+
+impl GraphqlCache {
+    pub fn new(store: impl GraphqlCacheStore, ttl: Duration) -> Self {
+        Self {
+            store: Some(Arc::new(store)),
+            ttl,
+            invalidations: Arc::default(),
+        }
+    }
+
+    /// A cache passing every query on.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub async fn connect(config: &GraphqlCacheConfig) -> anyhow::Result<Self> {
+        match &config.backend {
+            GraphqlCacheBackend::Disabled => Ok(Self::disabled()),
+            GraphqlCacheBackend::Memory => Ok(Self::new(MemoryGraphqlCacheStore::new(), config.ttl)),
+            #[cfg(feature = "redis")]
+            GraphqlCacheBackend::Redis(url) => Ok(Self::new(RedisGraphqlCacheStore::connect(url).await?, config.ttl)),
+            #[cfg(not(feature = "redis"))]
+            GraphqlCacheBackend::Redis(_) => anyhow::bail!("the redis graphql cache requires the redis feature"),
+        }
+    }
+
+    /// Drops `operation` of an installation when a webhook of `event` was handled, see
+    /// [`GraphqlCacheInvalidationLayer`].
+    pub fn with_invalidation(mut self, event: SaleorAsyncWebhookEvent, operation: &'static str) -> Self {
+        Arc::make_mut(&mut self.invalidations).entry(event).or_default().push(operation);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.store.is_some()
+    }
+
+    /// The cached answer to `operation` with `variables`, otherwise the answer of `query`, which is
+    /// cached if it succeeded.
+    ///
+    /// Only use it for queries, mutations have to reach Saleor every time. The cache failing is
+    /// logged and `query` answers instead.
+    pub async fn fetch<T, E, F>(&self, tenant: &str, operation: &str, variables: &Value, query: F) -> Result<T, E>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Future<Output = Result<T, E>>,
+    {
+        let Some(store) = &self.store else {
+            return query.await;
+        };
+        let variables = variables.to_string();
+        match store.get(tenant, operation, &variables).await {
+            Ok(Some(data)) => match serde_json::from_str(&data) {
+                Ok(data) => return Ok(data),
+                // an answer of an older version of the app
+                Err(e) => tracing::debug!(operation, "ignoring cached answer: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => tracing::warn!(operation, "{}", e),
+        }
+
+        let data = query.await?;
+        let cached = serde_json::to_string(&data).map_err(|e| GraphqlCacheError(e.to_string()));
+        if let Err(e) = async { store.set(tenant, operation, &variables, cached?, self.ttl).await }.await {
+            tracing::warn!(operation, "{}", e);
+        }
+        Ok(data)
+    }
+
+    /// Drops the cached answers of `operation` for the installation.
+    pub async fn invalidate(&self, tenant: &str, operation: &str) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store.invalidate(tenant, operation).await {
+            tracing::warn!(operation, "{}", e);
+        }
+    }
+
+    /// Drops every cached answer of a removed installation.
+    pub async fn remove_tenant(&self, tenant: &str) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store.remove_tenant(tenant).await {
+            tracing::warn!(tenant, "{}", e);
+        }
+    }
+
+    /// Drops the operations [`Self::with_invalidation`] tied to `event`.
+    pub async fn invalidate_for(&self, tenant: &str, event: &SaleorAsyncWebhookEvent) {
+        for operation in self.invalidations.get(event).into_iter().flatten() {
+            self.invalidate(tenant, operation).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for GraphqlCache
+where
+    S: Sync + Send,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<GraphqlCache>().cloned().unwrap_or_default())
+    }
+}
+
+/// Invalidates the operations tied to the event of a webhook once it was handled successfully,
+/// which means its signature was verified.
+#[derive(Clone)]
+pub struct GraphqlCacheInvalidationLayer;
+
+impl<S> Layer<S> for GraphqlCacheInvalidationLayer {
+    type Service = GraphqlCacheInvalidationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GraphqlCacheInvalidationService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct GraphqlCacheInvalidationService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for GraphqlCacheInvalidationService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let cache = req.extensions().get::<GraphqlCache>().cloned().filter(GraphqlCache::is_enabled);
+        let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let tenant = header(SALEOR_API_URL_HEADER);
+        // the header names the event in lowercase, like `product_updated`
+        let event = header(SALEOR_EVENT_HEADER).and_then(|event| serde_json::from_value::<SaleorAsyncWebhookEvent>(Value::String(event.to_uppercase())).ok());
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let response = inner.call(req).await?;
+            if let (Some(cache), Some(tenant), Some(event)) = (cache, tenant, event) {
+                if response.status().is_success() {
+                    cache.invalidate_for(&tenant, &event).await;
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_cache::RedisGraphqlCacheStore;
+
+#[cfg(feature = "redis")]
+mod redis_cache {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use redis::aio::ConnectionManager;
+
+    use super::{GraphqlCacheError, GraphqlCacheStore};
+
+    /// Keeps the answers of an operation in a `graphql-cache:{tenant}:{operation}` hash, keyed by
+    /// the variables. Answers carry their expiry, the hash expires with its newest answer.
+    #[derive(Clone)]
+    pub struct RedisGraphqlCacheStore {
+        connection: ConnectionManager,
+    }
+
+    impl RedisGraphqlCacheStore {
+        pub async fn connect(url: &str) -> anyhow::Result<Self> {
+            let client = redis::Client::open(url)?;
+            let connection = client.get_connection_manager().await?;
+            Ok(Self { connection })
+        }
+
+        fn key(tenant: &str, operation: &str) -> String {
+            format!("graphql-cache:{}:{}", tenant, operation)
+        }
+    }
+
+    fn redis_error(e: redis::RedisError) -> GraphqlCacheError {
+        GraphqlCacheError(e.to_string())
+    }
+
+    fn now_millis() -> u128 {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_millis()
+    }
+
+    #[async_trait]
+    impl GraphqlCacheStore for RedisGraphqlCacheStore {
+        async fn get(&self, tenant: &str, operation: &str, variables: &str) -> Result<Option<String>, GraphqlCacheError> {
+            let answer: Option<String> = redis::cmd("HGET")
+                .arg(Self::key(tenant, operation))
+                .arg(variables)
+                .query_async(&mut self.connection.clone())
+                .await
+                .map_err(redis_error)?;
+            let answer = answer.and_then(|answer| {
+                let (expires_at, data) = answer.split_once('|')?;
+                (expires_at.parse::<u128>().ok()? > now_millis()).then(|| data.to_string())
+            });
+            Ok(answer)
+        }
+
+        async fn set(&self, tenant: &str, operation: &str, variables: &str, data: String, ttl: Duration) -> Result<(), GraphqlCacheError> {
+            let key = Self::key(tenant, operation);
+            let expires_at = now_millis() + ttl.as_millis();
+            redis::pipe()
+                .cmd("HSET").arg(&key).arg(variables).arg(format!("{expires_at}|{data}")).ignore()
+                .cmd("PEXPIRE").arg(&key).arg(ttl.as_millis().max(1) as u64).ignore()
+                .query_async::<_, ()>(&mut self.connection.clone())
+                .await
+                .map_err(redis_error)
+        }
+
+        async fn invalidate(&self, tenant: &str, operation: &str) -> Result<(), GraphqlCacheError> {
+            redis::cmd("DEL")
+                .arg(Self::key(tenant, operation))
+                .query_async::<_, i64>(&mut self.connection.clone())
+                .await
+                .map(|_| ())
+                .map_err(redis_error)
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+use async_trait::async_trait;
+use axum::{Router, routing::get, extract::State, response::{IntoResponse, Response}, http::StatusCode, Json};
+use serde::Serialize;
+
+use crate::{config::AppConfig, saleor::AplStore};
+
+/// A single check that is run when one of the probe endpoints is hit.
+///
+/// Implement this for anything your app depends on (database, message broker, ...)
+/// and register it with [`HealthChecks::with_check`].
+#[async_trait]
+pub trait HealthCheck: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    async fn check(&self) -> Result<(), String>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// `/healthz`, is the process alive at all
+    Liveness,
+    /// `/readyz`, can the app serve traffic
+    Readiness,
+    /// `/startupz`, has the app finished starting up
+    Startup,
+}
+
+#[derive(Clone, Default)]
+pub struct HealthChecks {
+    liveness: Vec<Arc<dyn HealthCheck>>,
+    readiness: Vec<Arc<dyn HealthCheck>>,
+    startup: Vec<Arc<dyn HealthCheck>>,
+    started: Arc<AtomicBool>,
+}
+
+impl HealthChecks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_check(mut self, probe: Probe, check: impl HealthCheck) -> Self {
+        let check: Arc<dyn HealthCheck> = Arc::new(check);
+        match probe {
+            Probe::Liveness => self.liveness.push(check),
+            Probe::Readiness => self.readiness.push(check),
+            Probe::Startup => self.startup.push(check),
+        }
+        self
+    }
+
+    /// Marks startup as finished, until this is called `/startupz` and `/readyz` report failure.
+    pub fn mark_started(&self) {
+        self.started.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .route("/startupz", get(startupz))
+            .with_state(self)
+    }
+
+    async fn run(&self, probe: Probe) -> HealthReport {
+        let checks = match probe {
+            Probe::Liveness => &self.liveness,
+            Probe::Readiness => &self.readiness,
+            Probe::Startup => &self.startup,
+        };
+
+        let mut results = vec![];
+        if probe != Probe::Liveness {
+            results.push(match self.is_started() {
+                true => CheckResult::pass("startup"),
+                false => CheckResult::fail("startup", "app is still starting up".to_string()),
+            });
+        }
+        for check in checks {
+            results.push(match check.check().await {
+                Ok(()) => CheckResult::pass(check.name()),
+                Err(e) => CheckResult::fail(check.name(), e),
+            });
+        }
+
+        let status = match results.iter().all(|r| r.status == CheckStatus::Pass) {
+            true => CheckStatus::Pass,
+            false => CheckStatus::Fail,
+        };
+        HealthReport { status, checks: results }
+    }
+}
+
+async fn healthz(State(checks): State<HealthChecks>) -> HealthReport {
+    checks.run(Probe::Liveness).await
+}
+
+async fn readyz(State(checks): State<HealthChecks>) -> HealthReport {
+    checks.run(Probe::Readiness).await
+}
+
+async fn startupz(State(checks): State<HealthChecks>) -> HealthReport {
+    checks.run(Probe::Startup).await
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl CheckResult {
+    fn pass(name: &str) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Pass, message: None }
+    }
+
+    fn fail(name: &str, message: String) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Fail, message: Some(message) }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthReport {
+    pub status: CheckStatus,
+    pub checks: Vec<CheckResult>,
+}
+
+impl IntoResponse for HealthReport {
+    fn into_response(self) -> Response {
+        let status_code = match self.status {
+            CheckStatus::Pass => StatusCode::OK,
+            CheckStatus::Fail => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status_code, Json(self)).into_response()
+    }
+}
+
+pub struct AplHealthCheck {
+    apl_store: Arc<dyn AplStore>,
+}
+
+impl AplHealthCheck {
+    pub fn new(apl_store: Arc<dyn AplStore>) -> Self {
+        Self { apl_store }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for AplHealthCheck {
+    fn name(&self) -> &str {
+        "apl"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.apl_store.health().await
+    }
+}
+
+/// Fails while [`AppConfig::validate`] finds problems.
+///
+/// The probes are public, so the failure doesn't name the problems, `saleor-app doctor` and the
+/// diagnostic bundle list them.
+pub struct ConfigHealthCheck {
+    config: AppConfig,
+}
+
+impl ConfigHealthCheck {
+    pub fn new(config: AppConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for ConfigHealthCheck {
+    fn name(&self) -> &str {
+        "config"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        match self.config.validate().is_empty() {
+            true => Ok(()),
+            false => Err("the configuration has problems, run `saleor-app doctor` for details".to_string()),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::{ops::Deref, time::Duration};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, extract::FromRequestParts, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+
+use crate::{circuit_breaker::CircuitBreakers, concurrency::TenantConcurrency, saleor::AppTokenHealth};
+
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    /// How long a whole request may take, including reading the body
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    /// Proxy every outgoing request goes through, otherwise `HTTP_PROXY` and `HTTPS_PROXY` are respected
+    pub proxy: Option<String>,
+    pub user_agent: String,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            proxy: None,
+            user_agent: format!("{}/{}", crate::APP_ID, crate::APP_VERSION),
+        }
+    }
+}
+
+impl HttpClientConfig {
+    pub fn build(&self) -> anyhow::Result<HttpClient> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .user_agent(&self.user_agent);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(HttpClient::from(builder.build()?))
+    }
+}
+
+/// The client for every request the app makes to Saleor and other services.
+///
+/// It's shared so connections and TLS sessions are reused, cloning it is cheap. Handlers get it from
+/// the request extensions, see [`crate::app::build`]. Calls to Saleor made with it go through the
+/// circuit breakers and concurrency budgets of the app it belongs to, which also notices rejected
+/// app tokens. Clients built on their own have their own.
+#[derive(Clone, Default)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    circuit_breakers: CircuitBreakers,
+    concurrency: TenantConcurrency,
+    app_tokens: AppTokenHealth,
+}
+
+impl HttpClient {
+    /// The same client with the breakers of an app.
+    pub fn with_circuit_breakers(self, circuit_breakers: CircuitBreakers) -> Self {
+        Self { circuit_breakers, ..self }
+    }
+
+    pub fn circuit_breakers(&self) -> &CircuitBreakers {
+        &self.circuit_breakers
+    }
+
+    /// The same client with the concurrency budgets of an app.
+    pub fn with_concurrency(self, concurrency: TenantConcurrency) -> Self {
+        Self { concurrency, ..self }
+    }
+
+    pub fn concurrency(&self) -> &TenantConcurrency {
+        &self.concurrency
+    }
+
+    /// The same client marking installations with rejected app tokens like an app.
+    pub fn with_app_tokens(self, app_tokens: AppTokenHealth) -> Self {
+        Self { app_tokens, ..self }
+    }
+
+    /// Set the [`crate::saleor::OnAuthInvalid`] hook of the app on it.
+    pub fn app_tokens(&self) -> &AppTokenHealth {
+        &self.app_tokens
+    }
+}
+
+impl From<reqwest::Client> for HttpClient {
+    fn from(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            circuit_breakers: CircuitBreakers::default(),
+            concurrency: TenantConcurrency::default(),
+            app_tokens: AppTokenHealth::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HttpClient").field(&self.client).finish()
+    }
+}
+
+impl Deref for HttpClient {
+    type Target = reqwest::Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for HttpClient
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<HttpClient>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "http client not found in request extensions").into_response())
+    }
+}
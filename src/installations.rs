@@ -0,0 +1,137 @@
+use askama::Template;
+use axum::{extract::Query, http::HeaderMap, response::{IntoResponse, Response}, Json};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{admin_auth::AdminOperator, audit::{AuditEvent, AuditEventKind, AuditLog}, graphql_cache::GraphqlCache, operator_api, saleor::{AplError, AuthData, SaleorApl}, templating::{AppBridgeAction, HtmlTemplate, Localizer, Page, PageContext, HX_REQUEST_HEADER}, uninstalled::UninstalledTenants};
+
+/// An installation as shown to operators, without its token.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Installation {
+    /// Id of the installation in the [`crate::operator_api`]
+    pub id: String,
+    pub saleor_api_url: String,
+    pub domain: Option<String>,
+    pub app_id: String,
+    /// RFC 3339 timestamp of the registration
+    pub registered_at: Option<String>,
+    /// Whether Saleor rejects the app token, the app has to be installed again
+    pub token_invalid: bool,
+}
+
+impl From<AuthData> for Installation {
+    fn from(auth_data: AuthData) -> Self {
+        Self {
+            registered_at: auth_data
+                .registered_at
+                .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp as i64).ok())
+                .and_then(|registered_at| registered_at.format(&Rfc3339).ok()),
+            id: operator_api::installation_id(&auth_data.saleor_api_url),
+            token_invalid: auth_data.token_invalid_since.is_some(),
+            saleor_api_url: auth_data.saleor_api_url,
+            domain: auth_data.domain,
+            app_id: auth_data.app_id,
+        }
+    }
+}
+
+async fn installations(apl: &SaleorApl) -> Result<Vec<Installation>, AplError> {
+    let mut installations: Vec<Installation> = apl.get_all().await?.into_iter().map(Installation::from).collect();
+    installations.sort_by(|a, b| a.saleor_api_url.cmp(&b.saleor_api_url));
+    Ok(installations)
+}
+
+#[derive(Template)]
+#[template(path = "pages/installations.html")]
+pub struct InstallationsContent {
+    pub i18n: Localizer,
+}
+
+#[derive(Template)]
+#[template(path = "components/installations_table.html")]
+pub struct InstallationsTable {
+    pub i18n: Localizer,
+    pub installations: Vec<Installation>,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveInstallation {
+    pub saleor_api_url: String,
+}
+
+fn is_htmx(headers: &HeaderMap) -> bool {
+    headers.contains_key(HX_REQUEST_HEADER)
+}
+
+/// `GET /app/installations`, the table is loaded through [`list_installations`] once the dashboard authenticated the page.
+pub async fn installations_page(ctx: PageContext) -> impl IntoResponse {
+    let title = ctx.i18n.t("installations-title");
+    let content = InstallationsContent { i18n: ctx.i18n.clone() };
+    Page::new(ctx, title, content)
+}
+
+/// `GET /api/installations`, every installation as JSON, or as table for htmx.
+#[utoipa::path(get, path = "/api/installations", tag = "admin", security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "Every installation in the APL", body = [Installation]),
+))]
+pub async fn list_installations(i18n: Localizer, apl: SaleorApl, headers: HeaderMap) -> Response {
+    let installations = match installations(&apl).await {
+        Ok(installations) => installations,
+        Err(e) => return e.into_response(),
+    };
+    match is_htmx(&headers) {
+        true => HtmlTemplate(InstallationsTable { i18n, installations }).into_response(),
+        false => Json(installations).into_response(),
+    }
+}
+
+/// `DELETE /api/installations?saleorApiUrl=...`, removes the installation from the APL.
+///
+/// The app stays installed in Saleor, but can't authenticate requests of that installation anymore,
+/// they're answered with `410 Gone` until it registers again, see [`UninstalledTenants`].
+#[utoipa::path(delete, path = "/api/installations", tag = "admin", params(RemoveInstallation), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 204, description = "The installation was removed from the APL"),
+    (status = 404, description = "The installation doesn't exist"),
+))]
+#[allow(clippy::too_many_arguments)]
+pub async fn remove_installation(
+    i18n: Localizer,
+    apl: SaleorApl,
+    audit_log: AuditLog,
+    uninstalled: UninstalledTenants,
+    graphql_cache: GraphqlCache,
+    AdminOperator(operator): AdminOperator,
+    headers: HeaderMap,
+    Query(query): Query<RemoveInstallation>,
+) -> Response {
+    let apl_id = apl.id(&query.saleor_api_url);
+    match apl.get(&apl_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "installation not found").into_response(),
+        Err(e) => return e.into_response(),
+    }
+    if let Err(e) = apl.remove(&apl_id).await {
+        return e.into_response();
+    }
+    uninstalled.mark(&apl_id);
+    graphql_cache.remove_tenant(&query.saleor_api_url).await;
+    tracing::info!(saleor_api_url = %query.saleor_api_url, "removed installation");
+    audit_log.record(
+        AuditEvent::new(&query.saleor_api_url, AuditEventKind::Uninstalled)
+            .with_detail(format!("removed from the installations page by {operator}")),
+    );
+
+    if !is_htmx(&headers) {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    let removed = AppBridgeAction::success(i18n.t("installations-removed"));
+    let installations = match installations(&apl).await {
+        Ok(installations) => installations,
+        Err(e) => return e.into_response(),
+    };
+    (removed, HtmlTemplate(InstallationsTable { i18n, installations })).into_response()
+}
@@ -0,0 +1,65 @@
+
+pub mod admin_auth;
+pub mod app;
+pub mod app_settings;
+pub mod assets;
+pub mod audit;
+pub mod base_url;
+pub mod circuit_breaker;
+pub mod concurrency;
+pub mod config;
+pub mod cors;
+pub mod diagnostics;
+#[cfg(feature = "dev")]
+pub mod dev;
+pub mod doctor;
+pub mod email;
+pub mod emitter;
+pub mod error_reporting;
+pub mod events;
+pub mod extensions;
+pub mod feature_flags;
+pub mod gdpr;
+pub mod graphql_cache;
+pub mod health;
+pub mod http_client;
+pub mod installations;
+pub mod limits;
+pub mod load_shed;
+pub mod locks;
+pub mod openapi;
+pub mod operator_api;
+pub mod rate_limit;
+pub mod registration;
+pub mod reload;
+pub mod request_id;
+pub mod router_ext;
+pub mod saleor;
+pub mod scaffold;
+pub mod scheduler;
+pub mod search;
+pub mod secrets;
+pub mod security_headers;
+pub mod sessions;
+pub mod telemetry;
+pub mod templating;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tunnel;
+pub mod uninstalled;
+pub mod webhook_archive;
+pub mod webhook_status;
+pub mod webhooks;
+pub mod ws;
+
+/// Name of the package, the id of the app unless `SALEOR_APP_ID` is set, see [`saleor::SaleorApl::app_id`].
+pub const APP_ID: &str = env!("CARGO_PKG_NAME");
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Whether the binary was built with the `dev` feature, see [`dev::DevReload`].
+pub const DEV_MODE: bool = cfg!(feature = "dev");
+
+/// Paths the code generated by the macros of `saleor-app-macros` relies on.
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use axum::extract::DefaultBodyLimit;
+use tower_http::timeout::TimeoutLayer;
+
+/// Body size limit and timeout applied to a group of routes.
+#[derive(Debug, Clone, Copy)]
+pub struct RouteLimits {
+    pub body_limit_bytes: usize,
+    pub timeout: Duration,
+}
+
+impl RouteLimits {
+    /// Rejects larger bodies with `413 Payload Too Large`, extractors like `Json` respect this.
+    pub fn body_limit(&self) -> DefaultBodyLimit {
+        DefaultBodyLimit::max(self.body_limit_bytes)
+    }
+
+    /// Aborts the request with `408 Request Timeout` once the timeout elapsed.
+    pub fn timeout(&self) -> TimeoutLayer {
+        TimeoutLayer::new(self.timeout)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    pub default: RouteLimits,
+    pub register: RouteLimits,
+    pub webhooks: RouteLimits,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            default: RouteLimits {
+                body_limit_bytes: 2 * 1024 * 1024,
+                timeout: Duration::from_secs(30),
+            },
+            register: RouteLimits {
+                body_limit_bytes: 16 * 1024,
+                timeout: Duration::from_secs(15),
+            },
+            webhooks: RouteLimits {
+                body_limit_bytes: 5 * 1024 * 1024,
+                timeout: Duration::from_secs(20),
+            },
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Sheds load per group of routes, so a flood of webhooks during a big sale can't take down the
+//! dashboard pages of the app.
+//!
+//! Each [`RouteGroup`] handles at most `max_in_flight` requests at the same time, up to `max_queued`
+//! more wait for room and the rest are answered right away with `503 Service Unavailable` and
+//! `Retry-After`, which Saleor retries webhooks on. The groups don't share their room, the limits
+//! are configured with `DASHBOARD_MAX_IN_FLIGHT`, `DASHBOARD_MAX_QUEUED`, `WEBHOOK_MAX_IN_FLIGHT`,
+//! `WEBHOOK_MAX_QUEUED` and `LOAD_SHED_RETRY_AFTER_SECS`.
+//!
+//! `tower`'s `LoadShed` in front of a `ConcurrencyLimit` can't queue: the limit waits for room in
+//! `poll_ready`, which `LoadShed` takes as overloaded. A `Buffer` in between would queue, but
+//! `Router::layer` wraps every route on its own, so each route would get a queue of its own. The
+//! room of a group is two semaphores instead, one for the requests it admits and one for those
+//! it handles, shared by every route the [`LoadShedLayer`] is applied to.
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use axum::{body::Body, http::{header::RETRY_AFTER, HeaderValue, Request, StatusCode}, response::{IntoResponse, Response}};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{Layer, Service};
+
+use crate::telemetry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupLimits {
+    /// Requests handled at the same time
+    pub max_in_flight: usize,
+    /// Requests waiting for room, later ones are shed
+    pub max_queued: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadShedLimits {
+    /// The pages and API of the app under `/app` and `/api`
+    pub dashboard: GroupLimits,
+    pub webhooks: GroupLimits,
+    /// `Retry-After` of shed requests
+    pub retry_after: Duration,
+}
+
+impl Default for LoadShedLimits {
+    fn default() -> Self {
+        Self {
+            dashboard: GroupLimits { max_in_flight: 128, max_queued: 256 },
+            webhooks: GroupLimits { max_in_flight: 256, max_queued: 512 },
+            retry_after: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Dashboard,
+    Webhooks,
+}
+
+impl RouteGroup {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Dashboard => "dashboard",
+            Self::Webhooks => "webhooks",
+        }
+    }
+}
+
+struct Room {
+    limits: GroupLimits,
+    /// Requests handled or waiting, the ones beyond are shed
+    admitted: Arc<Semaphore>,
+    in_flight: Arc<Semaphore>,
+}
+
+impl Room {
+    fn new(limits: GroupLimits) -> Self {
+        Self {
+            limits,
+            admitted: Arc::new(Semaphore::new(limits.max_in_flight + limits.max_queued)),
+            in_flight: Arc::new(Semaphore::new(limits.max_in_flight)),
+        }
+    }
+}
+
+/// A request of a group being handled, which makes room for the next one when dropped.
+pub struct LoadShedPermit {
+    _admitted: OwnedSemaphorePermit,
+    _in_flight: OwnedSemaphorePermit,
+    // dropped after the permits, so it records the room they make
+    _queued: RecordQueued,
+}
+
+/// Records the queue depth of the group when dropped, so finished and cancelled requests update it
+/// like admitted ones.
+struct RecordQueued {
+    load_shedding: LoadShedding,
+    group: RouteGroup,
+}
+
+impl Drop for RecordQueued {
+    fn drop(&mut self) {
+        self.load_shedding.record_queued(self.group);
+    }
+}
+
+/// The room of every group, [`crate::app::build`] makes one per app. Clones share it.
+#[derive(Clone)]
+pub struct LoadShedding {
+    dashboard: Arc<Room>,
+    webhooks: Arc<Room>,
+    retry_after: Duration,
+}
+
+impl Default for LoadShedding {
+    fn default() -> Self {
+        Self::new(LoadShedLimits::default())
+    }
+}
+
+impl LoadShedding {
+    pub fn new(limits: LoadShedLimits) -> Self {
+        Self {
+            dashboard: Arc::new(Room::new(limits.dashboard)),
+            webhooks: Arc::new(Room::new(limits.webhooks)),
+            retry_after: limits.retry_after,
+        }
+    }
+
+    fn room(&self, group: RouteGroup) -> &Room {
+        match group {
+            RouteGroup::Dashboard => &self.dashboard,
+            RouteGroup::Webhooks => &self.webhooks,
+        }
+    }
+
+    /// Sheds the requests of `group` with this room.
+    pub fn layer(&self, group: RouteGroup) -> LoadShedLayer {
+        LoadShedLayer { load_shedding: self.clone(), group }
+    }
+
+    /// Waits for room in the group unless too many requests wait already, then it's `Err` with how
+    /// long to wait before trying again.
+    pub async fn acquire(&self, group: RouteGroup) -> Result<LoadShedPermit, Duration> {
+        let room = self.room(group);
+        // declared before the permit, so requests cancelled while waiting drop it after giving it back
+        let queued = RecordQueued { load_shedding: self.clone(), group };
+        let admitted = room.admitted.clone().try_acquire_owned().map_err(|_| self.retry_after)?;
+        self.record_queued(group);
+        let in_flight = room.in_flight.clone().acquire_owned().await.expect("load shedding semaphores are never closed");
+        self.record_queued(group);
+        Ok(LoadShedPermit { _admitted: admitted, _in_flight: in_flight, _queued: queued })
+    }
+
+    /// Requests of the group waiting for room right now.
+    pub fn queued(&self, group: RouteGroup) -> usize {
+        let room = self.room(group);
+        let admitted = room.limits.max_in_flight + room.limits.max_queued - room.admitted.available_permits();
+        let in_flight = room.limits.max_in_flight - room.in_flight.available_permits();
+        admitted.saturating_sub(in_flight)
+    }
+
+    fn record_queued(&self, group: RouteGroup) {
+        if group == RouteGroup::Webhooks {
+            telemetry::set_webhook_queue_depth(self.queued(group));
+        }
+    }
+}
+
+/// Limits the requests of a group handled at the same time, shedding them with
+/// `503 Service Unavailable` once its queue is full, see [`LoadShedding::layer`].
+#[derive(Clone)]
+pub struct LoadShedLayer {
+    load_shedding: LoadShedding,
+    group: RouteGroup,
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShedService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadShedService { inner, load_shedding: self.load_shedding.clone(), group: self.group }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoadShedService<S> {
+    inner: S,
+    load_shedding: LoadShedding,
+    group: RouteGroup,
+}
+
+impl<S> Service<Request<Body>> for LoadShedService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let load_shedding = self.load_shedding.clone();
+        let group = self.group;
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let _permit = match load_shedding.acquire(group).await {
+                Ok(permit) => permit,
+                Err(retry_after) => {
+                    telemetry::record_shed_request(group.as_str());
+                    let mut response = (StatusCode::SERVICE_UNAVAILABLE, "too busy, try again later").into_response();
+                    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs().max(1)));
+                    return Ok(response);
+                }
+            };
+            inner.call(req).await
+        })
+    }
+}
@@ -0,0 +1,173 @@
+//! Locks shared between the replicas of the app.
+//!
+//! Per installation maintenance like [`crate::scheduler`] jobs and webhook reconciliation should run
+//! on a single replica at a time. A [`DistributedLock`] hands out keys for a time to live, which has
+//! to outlast the work since locks aren't renewed: a replica that dies while holding one only blocks
+//! the key until it expires. [`MemoryLock`] only covers a single process, [`RedisLock`] (with the
+//! `redis` feature) all replicas sharing a Redis. Implement the trait for other backends, like
+//! Postgres advisory locks.
+
+use std::{collections::HashMap, future::Future, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use ring::rand::{SecureRandom, SystemRandom};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockError(pub String);
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "lock error: {}", self.0)
+    }
+}
+
+impl std::error::Error for LockError {}
+
+#[async_trait]
+pub trait DistributedLock: Send + Sync + 'static {
+    /// Takes `key` for `ttl` unless it's held already, returns the token to release it with.
+    async fn try_acquire(&self, key: &str, ttl: Duration) -> Result<Option<String>, LockError>;
+    /// Releases `key` if it's still held with `token`, keys that expired in the meantime are left alone.
+    async fn release(&self, key: &str, token: &str) -> Result<(), LockError>;
+}
+
+/// Identifies a holder, so only it releases the key.
+fn lock_token() -> String {
+    let mut token = [0u8; 16];
+    SystemRandom::new().fill(&mut token).expect("unable to generate lock token");
+    token.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Runs `work` while holding `key`, `None` if it's held by someone else.
+pub async fn run_exclusive<T>(lock: &dyn DistributedLock, key: &str, ttl: Duration, work: impl Future<Output = T>) -> Result<Option<T>, LockError> {
+    let Some(token) = lock.try_acquire(key, ttl).await? else {
+        return Ok(None);
+    };
+    let result = work.await;
+    if let Err(e) = lock.release(key, &token).await {
+        // the key expires on its own
+        tracing::warn!(key, "{}", e);
+    }
+    Ok(Some(result))
+}
+
+/// Locks within this process. Clones share them.
+#[derive(Clone, Default)]
+pub struct MemoryLock {
+    held: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+}
+
+impl MemoryLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DistributedLock for MemoryLock {
+    async fn try_acquire(&self, key: &str, ttl: Duration) -> Result<Option<String>, LockError> {
+        let mut held = self.held.lock().unwrap();
+        if held.get(key).is_some_and(|(_, expires_at)| *expires_at > Instant::now()) {
+            return Ok(None);
+        }
+        let token = lock_token();
+        held.insert(key.to_string(), (token.clone(), Instant::now() + ttl));
+        Ok(Some(token))
+    }
+
+    async fn release(&self, key: &str, token: &str) -> Result<(), LockError> {
+        let mut held = self.held.lock().unwrap();
+        if held.get(key).is_some_and(|(holder, _)| holder == token) {
+            held.remove(key);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LockBackend {
+    /// Locks only cover this process, enough for a single replica
+    #[default]
+    Memory,
+    /// Locks are kept in Redis at the given connection url (requires the `redis` feature)
+    Redis(String),
+}
+
+impl LockBackend {
+    pub async fn connect(&self) -> anyhow::Result<Arc<dyn DistributedLock>> {
+        match self {
+            Self::Memory => Ok(Arc::new(MemoryLock::new())),
+            #[cfg(feature = "redis")]
+            Self::Redis(url) => Ok(Arc::new(RedisLock::connect(url).await?)),
+            #[cfg(not(feature = "redis"))]
+            Self::Redis(_) => anyhow::bail!("redis locks require the redis feature"),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_lock::RedisLock;
+
+#[cfg(feature = "redis")]
+mod redis_lock {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use redis::aio::ConnectionManager;
+
+    use super::{lock_token, DistributedLock, LockError};
+
+    /// Deletes the key only if it's still held with the token.
+    const RELEASE_SCRIPT: &str = r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("DEL", KEYS[1]) else return 0 end"#;
+
+    /// Keeps locks as `lock:{key}` keys, set only if they don't exist and expiring after their time to live.
+    #[derive(Clone)]
+    pub struct RedisLock {
+        connection: ConnectionManager,
+    }
+
+    impl RedisLock {
+        pub async fn connect(url: &str) -> anyhow::Result<Self> {
+            let client = redis::Client::open(url)?;
+            let connection = client.get_connection_manager().await?;
+            Ok(Self { connection })
+        }
+
+        fn key(key: &str) -> String {
+            format!("lock:{}", key)
+        }
+    }
+
+    fn redis_error(e: redis::RedisError) -> LockError {
+        LockError(e.to_string())
+    }
+
+    #[async_trait]
+    impl DistributedLock for RedisLock {
+        async fn try_acquire(&self, key: &str, ttl: Duration) -> Result<Option<String>, LockError> {
+            let token = lock_token();
+            let acquired: Option<String> = redis::cmd("SET")
+                .arg(Self::key(key))
+                .arg(&token)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl.as_millis().max(1) as u64)
+                .query_async(&mut self.connection.clone())
+                .await
+                .map_err(redis_error)?;
+            Ok(acquired.map(|_| token))
+        }
+
+        async fn release(&self, key: &str, token: &str) -> Result<(), LockError> {
+            redis::cmd("EVAL")
+                .arg(RELEASE_SCRIPT)
+                .arg(1)
+                .arg(Self::key(key))
+                .arg(token)
+                .query_async::<_, i64>(&mut self.connection.clone())
+                .await
+                .map(|_| ())
+                .map_err(redis_error)
+        }
+    }
+}
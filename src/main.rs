@@ -1,179 +1,363 @@
-use std::net::SocketAddr;
+use std::{io::Write, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::Context;
-use askama::Template;
-use axum::{Router, routing::{get, post}, response::{IntoResponse, Html}, http::{StatusCode, HeaderMap}, error_handling::HandleErrorLayer, BoxError, extract::Host, Json};
-use cynic::{QueryBuilder, http::ReqwestExt};
-use reqwest::Url;
-use saleor::{SaleorManifest, SaleorAppPermission, ExtractRegisterRequest, AuthData, AplId, SaleorRegisterResponse, SaleorApl, SaleorClientAuthenticationRequest, SaleorAppExtension, SaleorAppExtensionMount, SaleorAppExtensionTarget, verify_jwt, MyId};
-use templating::HtmlTemplate;
-use tower::ServiceBuilder;
-use tower_http::services::ServeDir;
-use tower_sessions::{MemoryStore, SessionManagerLayer, Session};
-use tracing::{info, instrument};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-use crate::saleor::{SaleorAuthLayer, FileAplStore, SaleorPermission, SaleorAplLayer};
-
-mod saleor;
-mod templating;
-
-const APP_ID: &str = env!("CARGO_PKG_NAME");
-const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+use clap::{Args, Parser, Subcommand};
+use saleor_app::{app, config::AppConfig, doctor, installations::Installation, locks, saleor::{self, AplId, AplStore, AppInstaller, AuthData, EncryptedAplStore, FileAplStore, MigratingAplStore, SaleorAsyncWebhookEvent, StaffCredentials}, scaffold::{self, AplBackend, ScaffoldOptions}, telemetry, tunnel::{Tunnel, TunnelProvider}};
+use tracing::{info, warn};
+
+/// How long an installation is locked while its webhooks are synced, syncs don't take longer.
+const WEBHOOK_SYNC_LOCK_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Saleor app server and operational tasks")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs the app server (the default)
+    Serve(ServeArgs),
+    /// Inspects the app manifest
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommand,
+    },
+    /// Manages the installations in the APL
+    Apl {
+        #[command(subcommand)]
+        command: AplCommand,
+    },
+    /// Manages the webhooks of installations
+    Webhooks {
+        #[command(subcommand)]
+        command: WebhooksCommand,
+    },
+    /// Manages the search index of products
+    Search {
+        #[command(subcommand)]
+        command: SearchCommand,
+    },
+    /// Installs the app into a Saleor instance, the app has to be running and reachable from it
+    Install(InstallArgs),
+    /// Generates a new app project built on this crate
+    New(NewArgs),
+    /// Checks the configuration, APL, reachability of the app and the schemas of installations
+    Doctor {
+        /// Where the app is reachable, defaults to APP_URL
+        #[arg(long)]
+        base_url: Option<String>,
+        /// Prints the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+struct NewArgs {
+    /// Directory of the project, its name is used as the crate name
+    path: PathBuf,
+    /// Name of the app in the dashboard, defaults to the crate name
+    #[arg(long)]
+    name: Option<String>,
+    /// Where installations are stored (file or memory)
+    #[arg(long, default_value = "file")]
+    apl: AplBackend,
+    /// Async event to generate a webhook handler for, like PRODUCT_CREATED, can be repeated
+    #[arg(long = "webhook", value_name = "EVENT", value_parser = scaffold::parse_webhook_event)]
+    webhooks: Vec<SaleorAsyncWebhookEvent>,
+    /// Depend on a local checkout of saleor-app instead of the git repository
+    #[arg(long, value_name = "PATH")]
+    saleor_app_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct InstallArgs {
+    /// GraphQL API of the Saleor instance, like https://example.saleor.cloud/graphql/
+    #[arg(long)]
+    saleor_url: String,
+    /// Staff user with MANAGE_APPS, used to log in together with --password
+    #[arg(long, requires = "password", conflicts_with = "token")]
+    email: Option<String>,
+    #[arg(long, requires = "email")]
+    password: Option<String>,
+    /// Staff token to use instead of logging in
+    #[arg(long, required_unless_present = "email")]
+    token: Option<String>,
+    /// Where the app is reachable, defaults to APP_URL
+    #[arg(long)]
+    base_url: Option<String>,
+    /// Name of the app in the dashboard, defaults to the manifest's name
+    #[arg(long)]
+    app_name: Option<String>,
+    /// Seconds to wait for Saleor to finish the installation
+    #[arg(long, default_value_t = 120)]
+    timeout: u64,
+}
+
+#[derive(Args, Debug, Default)]
+struct ServeArgs {
+    /// Exposes the local server through a tunnel (cloudflared or ngrok) and uses its url for the manifest
+    #[arg(long, value_name = "PROVIDER")]
+    tunnel: Option<TunnelProvider>,
+    /// Uses an already running tunnel instead of starting one
+    #[arg(long, value_name = "URL", conflicts_with = "tunnel")]
+    tunnel_url: Option<String>,
+    /// Dashboard to print the install link for, like https://example.saleor.cloud/dashboard/
+    #[arg(long, default_value = "http://localhost:9000/dashboard/")]
+    dashboard_url: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum ManifestCommand {
+    /// Prints the manifest JSON
+    Print {
+        /// Where the app is reachable, defaults to APP_URL
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AplCommand {
+    /// Lists every installation
+    List,
+    /// Shows a single installation
+    Get { saleor_api_url: String },
+    /// Removes an installation, the app can't authenticate its requests anymore
+    Remove { saleor_api_url: String },
+    /// Encrypts every token with the newest key of APL_ENCRYPTION_KEY, run it after adding a key version
+    Reencrypt,
+}
+
+#[derive(Subcommand, Debug)]
+enum WebhooksCommand {
+    /// Creates and updates the webhooks of installations to match the manifest
+    Sync {
+        /// Only sync this installation instead of every one
+        #[arg(long)]
+        saleor_api_url: Option<String>,
+        /// Where the app is reachable, defaults to APP_URL
+        #[arg(long)]
+        base_url: Option<String>,
+        /// Delete webhooks that aren't in the manifest anymore
+        #[arg(long)]
+        prune: bool,
+        /// Only print the changes
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SearchCommand {
+    /// Pushes every product of installations to the index of SEARCH_BACKEND
+    Reindex {
+        /// Only reindex this installation instead of every one
+        #[arg(long)]
+        saleor_api_url: Option<String>,
+    },
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| format!("{APP_ID}=debug").into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-    
+    let cli = Cli::parse();
+    let config = AppConfig::from_env()?;
+
+    match cli.command.unwrap_or(Command::Serve(ServeArgs::default())) {
+        Command::Serve(args) => serve(config, args).await,
+        Command::Manifest { command: ManifestCommand::Print { base_url } } => {
+            let base_url = base_url_or_app_url(base_url, &config)?;
+            print_json(&app::deployment_manifest(&config, &base_url))
+        }
+        Command::Apl { command } => apl(command, &config).await,
+        Command::Install(args) => install(args, &config).await,
+        Command::Doctor { base_url, json } => {
+            let report = doctor::run(&config, &apl_store(&config), base_url.as_deref()).await;
+            match json {
+                true => print_json(&report)?,
+                false => report.diagnostics.iter().for_each(|diagnostic| println!("{}", diagnostic)),
+            }
+            if report.has_failures() {
+                anyhow::bail!("found problems, see above");
+            }
+            Ok(())
+        }
+        Command::New(args) => {
+            let name = match args.name {
+                Some(name) => name,
+                None => args.path.file_name().context("the project directory needs a name")?.to_string_lossy().into_owned(),
+            };
+            let options = ScaffoldOptions { name, apl: args.apl, webhooks: args.webhooks, saleor_app_path: args.saleor_app_path };
+            for path in scaffold::scaffold(&args.path, &options)? {
+                eprintln!("created {}", path.display());
+            }
+            Ok(())
+        }
+        Command::Search { command: SearchCommand::Reindex { saleor_api_url } } => {
+            let client = config.http_client.build()?;
+            let index = config.search.as_ref().context("set SEARCH_BACKEND to meilisearch or algolia")?.build(client.clone());
+            for auth_data in installations(&MigratingAplStore::from_config(apl_store(&config), &config), &config, saleor_api_url).await? {
+                let indexed = saleor::search::reindex(&client, &auth_data, &index)
+                    .await
+                    .with_context(|| format!("unable to reindex products of {}", auth_data.saleor_api_url))?;
+                eprintln!("indexed {} products of {}", indexed, auth_data.saleor_api_url);
+            }
+            Ok(())
+        }
+        Command::Webhooks { command: WebhooksCommand::Sync { saleor_api_url, base_url, prune, dry_run } } => {
+            let manifest = app::deployment_manifest(&config, &base_url_or_app_url(base_url, &config)?);
+            let webhooks = manifest.webhooks.unwrap_or_default();
+            let client = config.http_client.build()?;
+            // replicas reconciling on startup or from a cron job mustn't sync the same installation twice
+            let lock = config.locks.connect().await?;
+            for auth_data in installations(&MigratingAplStore::from_config(apl_store(&config), &config), &config, saleor_api_url).await? {
+                let key = format!("webhook-sync:{}", auth_data.saleor_api_url);
+                let sync = saleor::sync_webhooks(&client, &auth_data, &webhooks, prune, dry_run);
+                let Some(changes) = locks::run_exclusive(lock.as_ref(), &key, WEBHOOK_SYNC_LOCK_TTL, sync).await? else {
+                    eprintln!("skipped {}, its webhooks are being synced elsewhere", auth_data.saleor_api_url);
+                    continue;
+                };
+                let changes = changes.with_context(|| format!("unable to sync webhooks of {}", auth_data.saleor_api_url))?;
+                print_json(&serde_json::json!({ "saleorApiUrl": auth_data.saleor_api_url, "changes": changes }))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn serve(mut config: AppConfig, args: ServeArgs) -> anyhow::Result<()> {
+    telemetry::init_tracing(config.log_format);
+
+    let tunnel = match (args.tunnel, args.tunnel_url) {
+        (Some(provider), _) => Some(Tunnel::start(provider, config.port).await?),
+        (None, Some(url)) => Some(Tunnel::external(&url)),
+        (None, None) => None,
+    };
+    if let Some(tunnel) = &tunnel {
+        config.app_url = Some(tunnel.url.clone());
+        info!("tunnel ready at {}", tunnel.url);
+        info!("manifest: {}", tunnel.manifest_url());
+        info!("install the app: {}", tunnel.install_url(&args.dashboard_url)?);
+    }
+
+    for problem in doctor::startup_checks(&config, &apl_store(&config)).await.problems() {
+        warn!("{}", problem);
+    }
+
     info!("initializing router");
 
-    let session_store = MemoryStore::default();
-    let session_service = ServiceBuilder::new()
-        .layer(HandleErrorLayer::new(|_: BoxError| async {
-            StatusCode::BAD_REQUEST
-        }))
-        .layer(SessionManagerLayer::new(session_store).with_secure(true).with_same_site(tower_sessions::cookie::SameSite::None));
-
-    let apl_layer = SaleorAplLayer::new(FileAplStore);
-    let auth_layer = SaleorAuthLayer::with_permissions(&[SaleorPermission::ManageProducts]);
-
-    let api_router = Router::new()
-        .route("/hello", get(api_hello))
-        .layer(auth_layer)
-        .route("/manifest", get(manifest))
-        .route("/register", post(register))
-        .route("/auth", post(auth));
-
-    let app_router = Router::new()
-        .route("/", get(index));
-
-    let assets_path = std::env::current_dir().unwrap();
-    let router  = Router::new()
-        .route("/", get(index))
-        .nest("/app", app_router)
-        .nest("/api", api_router)
-        .layer(apl_layer)
-        .layer(session_service)
-        .nest_service(
-            "/assets", 
-            ServeDir::new(format!("{}/assets", assets_path.display()))
-        );
-    let port = 8008;
+    let app::App { router, health_checks, reloader, .. } = app::build(&config, apl_store(&config)).await?;
+    // applies a changed CONFIG_FILE, like an updated ConfigMap
+    #[cfg(unix)]
+    reloader.reload_on_sighup().context("unable to listen for SIGHUP")?;
+    #[cfg(feature = "prometheus")]
+    let router = router.merge(telemetry::PrometheusMetrics::install()?.router());
+    #[cfg(feature = "dev")]
+    let router = router.merge(saleor_app::dev::DevReload::watch(vec![config.assets_dir.clone()]).router());
+    let port = config.port;
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
 
-    info!("router initialized, now listening on port {port}");
+    info!("now listening on port {port}");
 
-    axum::Server::bind(&addr)
-        .serve(router.into_make_service())
+    let server = axum::Server::bind(&addr)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>());
+    health_checks.mark_started();
+
+    server
         .await
         .context("error while starting server")?;
 
     Ok(())
 }
 
-async fn api_hello() -> impl IntoResponse {
-    "Hello from the API"
-}
-
-async fn index() -> impl IntoResponse {
-    HtmlTemplate(templating::ExamplePage)
-}
-
-pub async fn manifest(Host(host): Host, headers: HeaderMap) -> impl IntoResponse {
-    let scheme = headers.get("x-forwarded-proto").map(|h| h.to_str().unwrap()).unwrap_or("https");
-    let base_url = format!("{}://{}", scheme, host);
-
-    SaleorManifest {
-        id: APP_ID.to_string(),
-        version: APP_VERSION.to_string(),
-        required_saleor_version: None,
-        name: APP_ID.to_string(),
-        permissions: vec![SaleorAppPermission::ManageProducts],
-        app_url: base_url.clone(),
-        token_target_url: format!("{}/api/register", base_url),
-        author: None,
-        about: None,
-        data_privacy_url: None,
-        homepage_url: None,
-        support_url: None,
-        extensions: Some(vec![
-            SaleorAppExtension {
-                label: "Example Extension".to_string(),
-                mount: SaleorAppExtensionMount::ProductOverviewMoreActions,
-                target: SaleorAppExtensionTarget::AppPage,
-                permissions: vec![],
-                url: "/app".to_string(),
-            }
-        ]),
-        webhooks: None,
-        brand: None,
-    }
-}
-
-pub async fn register(apl: SaleorApl, ExtractRegisterRequest(request): ExtractRegisterRequest) -> impl IntoResponse {
-    let Ok(api_url) = Url::parse(&request.saleor_api_url) else {
-        return SaleorRegisterResponse::api_url_parsing_failed();
-    };
-    let jwks_url = format!("{}/.well-known/jwks.json", api_url.origin().ascii_serialization());
-    let Ok(response) = reqwest::get(&jwks_url).await else {
-        return SaleorRegisterResponse::jwks_not_available();
-    };
-    let Ok(jwks) = response.text().await else {
-        return SaleorRegisterResponse::jwks_not_available();
-    };
-
-    let auth_data = AuthData {
-        domain: Some(request.saleor_domain),
-        token: request.auth_token,
-        saleor_api_url: request.saleor_api_url,
-        app_id: APP_ID.to_string(),
-        jwks: Some(jwks),
+async fn install(args: InstallArgs, config: &AppConfig) -> anyhow::Result<()> {
+    let base_url = base_url_or_app_url(args.base_url, config)?;
+    let manifest = app::deployment_manifest(config, &base_url);
+    let credentials = match (args.token, args.email, args.password) {
+        (Some(token), _, _) => StaffCredentials::Token(token),
+        (None, Some(email), Some(password)) => StaffCredentials::Password { email, password },
+        _ => anyhow::bail!("pass --token or --email and --password"),
     };
-    apl.set(&Into::<AplId>::into(&auth_data), auth_data).await;
 
-    SaleorRegisterResponse::success()
+    let mut installer = AppInstaller::new(config.http_client.build()?, &args.saleor_url);
+    installer.timeout = Duration::from_secs(args.timeout);
+    let manifest_url = format!("{}/api/manifest", base_url);
+    eprintln!("installing {} into {}", manifest_url, args.saleor_url);
+    let installation = installer
+        .install(&credentials, args.app_name.as_deref().unwrap_or(&manifest.name), &manifest_url, &manifest.permissions)
+        .await?;
+    print_json(&installation)
 }
 
-pub async fn auth(session: Session, apl: SaleorApl, Json(auth_request): Json<SaleorClientAuthenticationRequest>) -> impl IntoResponse {
-    session.insert("token", &auth_request.token).expect("failed to insert token into session");
-    session.insert("saleor_api_url", &auth_request.api_url).expect("failed to insert saleor_api_url into session");
+/// The installations of the app, with encrypted tokens if `APL_ENCRYPTION_KEY` is set.
+fn apl_store(config: &AppConfig) -> Arc<dyn AplStore> {
+    match &config.apl_encryption {
+        Some(keyring) => Arc::new(EncryptedAplStore::new(FileAplStore, keyring.clone())),
+        None => Arc::new(FileAplStore),
+    }
+}
 
-    let jwks = match apl.get(&AplId::from_api_url(&auth_request.api_url)).await {
-        Some(auth_data) => {
-            match auth_data.jwks {
-                Some(jwks) => jwks,
-                None => {
-                    let jwks_url = format!("{}/.well-known/jwks.json", &auth_request.api_url);
-                    reqwest::get(&jwks_url).await.unwrap().text().await.unwrap()
-                }
+async fn apl(command: AplCommand, config: &AppConfig) -> anyhow::Result<()> {
+    let apl = MigratingAplStore::from_config(apl_store(config), config);
+    match command {
+        AplCommand::List => {
+            let installations: Vec<Installation> = apl.get_all().await?.into_iter().map(Installation::from).collect();
+            print_json(&installations)
+        }
+        AplCommand::Get { saleor_api_url } => {
+            let auth_data = apl
+                .get(&AplId::with_app_id(&config.app_id, &saleor_api_url))
+                .await?
+                .with_context(|| format!("{} is not installed", saleor_api_url))?;
+            print_json(&Installation::from(auth_data))
+        }
+        AplCommand::Remove { saleor_api_url } => {
+            let apl_id = AplId::with_app_id(&config.app_id, &saleor_api_url);
+            apl.get(&apl_id).await?.with_context(|| format!("{} is not installed", saleor_api_url))?;
+            apl.remove(&apl_id).await?;
+            eprintln!("removed {}", saleor_api_url);
+            Ok(())
+        }
+        AplCommand::Reencrypt => {
+            let keyring = config.apl_encryption.clone().context("set APL_ENCRYPTION_KEY to the keys to encrypt with")?;
+            let reencryption = EncryptedAplStore::new(FileAplStore, keyring).reencrypt().await?;
+            eprintln!("re-encrypted {} installations", reencryption.reencrypted);
+            for e in &reencryption.failed {
+                eprintln!("{}", e);
+            }
+            match reencryption.failed.len() {
+                0 => Ok(()),
+                failed => anyhow::bail!("{failed} installations couldn't be re-encrypted"),
             }
-        },
-        None => {
-            let jwks_url = format!("{}/.well-known/jwks.json", &auth_request.api_url);
-            reqwest::get(&jwks_url).await.unwrap().text().await.unwrap()
         }
-    };
-    if let Err(e) = verify_jwt(&jwks, &auth_request.token, &[]) {
-        return (StatusCode::UNAUTHORIZED, e).into_response();
     }
+}
 
-    let operation = MyId::build(());
-    let client = reqwest::Client::new();
-    let response = client.post(&auth_request.api_url).run_graphql(operation).await;
-    let response = match response {
-        Ok(response) => response,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    };
+/// The installation at `saleor_api_url`, or every one without it.
+async fn installations(apl: &impl AplStore, config: &AppConfig, saleor_api_url: Option<String>) -> anyhow::Result<Vec<AuthData>> {
+    Ok(match saleor_api_url {
+        Some(saleor_api_url) => vec![apl
+            .get(&AplId::with_app_id(&config.app_id, &saleor_api_url))
+            .await?
+            .with_context(|| format!("{} is not installed", saleor_api_url))?],
+        None => apl.get_all().await?,
+    })
+}
 
-    if response.data.is_none() {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "no data in response".to_string()).into_response();
-    }
+fn base_url_or_app_url(base_url: Option<String>, config: &AppConfig) -> anyhow::Result<String> {
+    base_url
+        .or_else(|| config.app_url.clone())
+        .map(|base_url| base_url.trim_end_matches('/').to_string())
+        .context("pass --base-url or set APP_URL")
+}
 
-    StatusCode::OK.into_response()
+fn print_json(value: &impl serde::Serialize) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    match writeln!(std::io::stdout().lock(), "{}", json) {
+        // piped into something like `head` that stopped reading
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
 }
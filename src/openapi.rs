@@ -0,0 +1,157 @@
+//! OpenAPI description of the app's HTTP API, served at `GET /api/openapi.json`.
+//!
+//! Handlers document themselves with `#[utoipa::path]`, [`ApiDoc`] collects them, so routes added to
+//! the app need to be listed there too. With the `swagger-ui` feature the spec can be browsed at
+//! `GET /api/docs`.
+
+use axum::{response::IntoResponse, Json};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{app, app_settings, audit, diagnostics, error_reporting, feature_flags, gdpr, installations, operator_api, reload, saleor, webhook_archive, webhook_status, webhooks};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Saleor App", description = "HTTP API of the app: installation, dashboard endpoints and webhooks."),
+    paths(
+        app::manifest,
+        app::register,
+        app::auth,
+        app::logout,
+        app::api_hello,
+        app_settings::get_config,
+        app_settings::post_config,
+        saleor::product_csv::export_products,
+        saleor::product_csv::import_products,
+        saleor::bulk_metadata::bulk_metadata,
+        saleor::orders::list_orders,
+        audit::audit_events,
+        installations::list_installations,
+        installations::remove_installation,
+        webhook_archive::search_webhooks,
+        webhook_archive::redeliver_webhook,
+        webhook_status::webhook_status,
+        feature_flags::get_feature_flags,
+        feature_flags::set_feature_flag,
+        reload::reload_config,
+        operator_api::get_apl_entry,
+        operator_api::put_apl_entry,
+        operator_api::delete_apl_entry,
+        diagnostics::diagnostic_bundle,
+        gdpr::export_personal_data,
+        gdpr::erase_personal_data,
+        webhooks::product_updated,
+    ),
+    components(schemas(
+        saleor::SaleorManifest,
+        saleor::SaleorAppExtension,
+        saleor::SaleorWebhookManifest,
+        saleor::SaleorBrand,
+        saleor::SaleorLogo,
+        saleor::SaleorAppPermission,
+        saleor::SaleorPermission,
+        saleor::SaleorAsyncWebhookEvent,
+        saleor::SaleorSyncWebhookEvent,
+        saleor::SaleorAppExtensionTarget,
+        saleor::SaleorAppExtensionMount,
+        saleor::SaleorAppExtensionOptions,
+        saleor::SaleorWidgetTarget,
+        saleor::SaleorWidgetMethod,
+        saleor::SaleorNewTabTarget,
+        saleor::SaleorNewTabMethod,
+        saleor::SaleorAuthToken,
+        saleor::SaleorRegisterResponse,
+        saleor::SaleorRegisterError,
+        saleor::SaleorClientAuthenticationRequest,
+        saleor::product_csv::ImportReport,
+        saleor::product_csv::RowError,
+        saleor::bulk_metadata::BulkMetadataRequest,
+        saleor::bulk_metadata::MetadataItem,
+        saleor::bulk_metadata::BulkMetadataJob,
+        saleor::bulk_metadata::BulkMetadataReport,
+        saleor::bulk_metadata::FailedProduct,
+        saleor::orders::OrdersPage,
+        saleor::orders::OrderSummary,
+        saleor::orders::OrderChannel,
+        app_settings::AppSettingsForm,
+        audit::AuditEvent,
+        audit::AuditEventKind,
+        installations::Installation,
+        webhook_archive::ArchivedWebhook,
+        webhook_status::WebhookStatus,
+        webhook_status::EventCounts,
+        feature_flags::InstallationFeatureFlags,
+        feature_flags::FeatureFlagUpdate,
+        reload::ReloadResult,
+        reload::ConfigChange,
+        operator_api::AplEntry,
+        operator_api::AplEntryUpdate,
+        diagnostics::DiagnosticBundle,
+        diagnostics::VersionInfo,
+        diagnostics::AplDiagnostics,
+        error_reporting::ErrorSummary,
+        gdpr::PersonalDataExport,
+        gdpr::ErasureReport,
+        webhooks::ProductUpdatedPayload,
+        webhooks::WebhookProduct,
+    )),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "app", description = "Manifest, installation and dashboard sessions"),
+        (name = "settings", description = "Settings of the current installation"),
+        (name = "products", description = "Catalog import and export, bulk metadata"),
+        (name = "orders", description = "Orders of the installation for the app's frontend, they need `MANAGE_ORDERS`"),
+        (name = "admin", description = "Audit log, installations, archived webhooks, feature flags, configuration reloads and personal data, they need `MANAGE_APPS`"),
+        (name = "operator", description = "Manual management of APL entries and diagnostics, authenticated with the operator API key"),
+        (name = "webhooks", description = "Deliveries of Saleor, signed with the installation's JWKS"),
+    ),
+)]
+pub struct ApiDoc;
+
+/// The ways requests authenticate, the dashboard token falls back to the one stored by `POST /api/auth`.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "dashboard_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+        components.add_security_scheme("saleor_api_url", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("saleor-api-url"))));
+        components.add_security_scheme("operator_api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(operator_api::OPERATOR_API_KEY_HEADER))));
+        components.add_security_scheme("saleor_signature", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("saleor-signature"))));
+    }
+}
+
+/// `GET /api/openapi.json`
+pub async fn openapi_json() -> impl IntoResponse {
+    let mut spec = ApiDoc::openapi();
+    spec.info.version = crate::APP_VERSION.to_string();
+    Json(spec)
+}
+
+#[cfg(feature = "swagger-ui")]
+const SWAGGER_UI_VERSION: &str = "5.11.0";
+
+/// `GET /api/docs`, Swagger UI for [`openapi_json`], loaded from a CDN.
+#[cfg(feature = "swagger-ui")]
+pub async fn swagger_ui() -> impl IntoResponse {
+    axum::response::Html(format!(r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Saleor App API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@{SWAGGER_UI_VERSION}/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@{SWAGGER_UI_VERSION}/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.ui = SwaggerUIBundle({{ url: "/api/openapi.json", dom_id: "#swagger-ui" }});
+  </script>
+</body>
+</html>"##))
+}
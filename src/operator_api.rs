@@ -0,0 +1,280 @@
+//! Manual management of APL entries for operators, outside of any Saleor dashboard.
+//!
+//! Support incidents sometimes leave an installation with a revoked token or outdated JWKS, which
+//! only Saleor could fix by registering the app again. `GET`/`PUT`/`DELETE /api/admin/installations/{id}`
+//! inspect, repair and remove entries instead. They authenticate with the `x-operator-api-key`
+//! header rather than a dashboard token, and are only served when `OPERATOR_API_KEY` is set.
+//!
+//! Ids are the `saleor_api_url` of the installation in url safe base64 without padding, they're
+//! listed with the entries. `GET /api/admin/diagnostics` downloads the [`crate::diagnostics`] bundle.
+
+use std::{future::Future, pin::Pin};
+
+use axum::{body::Body, extract::Path, http::Request, response::{IntoResponse, Response}, routing::get, Extension, Json, Router};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+use utoipa::ToSchema;
+
+use crate::{
+    audit::{AuditEvent, AuditEventKind, AuditLog},
+    diagnostics,
+    cors::DashboardOrigins,
+    graphql_cache::GraphqlCache,
+    http_client::HttpClient,
+    saleor::{AplId, AuthData, Jwks, SaleorApl},
+    secrets::{constant_time_eq, SecretString},
+    uninstalled::UninstalledTenants,
+};
+
+/// Header the operator API key is sent in.
+pub const OPERATOR_API_KEY_HEADER: &str = "x-operator-api-key";
+
+/// The id of an installation in `/api/admin/installations/{id}`.
+pub fn installation_id(saleor_api_url: &str) -> String {
+    URL_SAFE_NO_PAD.encode(saleor_api_url)
+}
+
+fn saleor_api_url(id: &str) -> Option<String> {
+    String::from_utf8(URL_SAFE_NO_PAD.decode(id).ok()?).ok()
+}
+
+/// The operator routes, guarded by `api_key`.
+pub fn router(api_key: SecretString) -> Router {
+    Router::new()
+        .route("/admin/installations/:id", get(get_apl_entry).put(put_apl_entry).delete(delete_apl_entry))
+        .route("/admin/diagnostics", get(diagnostics::diagnostic_bundle))
+        .route_layer(OperatorAuthLayer::new(api_key))
+}
+
+/// Rejects requests without the operator API key with `401 Unauthorized`.
+#[derive(Clone)]
+pub struct OperatorAuthLayer {
+    api_key: SecretString,
+}
+
+impl OperatorAuthLayer {
+    pub fn new(api_key: SecretString) -> Self {
+        Self { api_key }
+    }
+}
+
+impl<S> Layer<S> for OperatorAuthLayer {
+    type Service = OperatorAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OperatorAuthService { inner, api_key: self.api_key.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct OperatorAuthService<S> {
+    inner: S,
+    api_key: SecretString,
+}
+
+impl<S> Service<Request<Body>> for OperatorAuthService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let authorized = req
+            .headers()
+            .get(OPERATOR_API_KEY_HEADER)
+            .is_some_and(|key| constant_time_eq(key.as_bytes(), self.api_key.expose().as_bytes()));
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            if !authorized {
+                return Ok((StatusCode::UNAUTHORIZED, "missing or invalid operator api key").into_response());
+            }
+            inner.call(req).await
+        })
+    }
+}
+
+/// An APL entry as shown to operators, the token is redacted.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AplEntry {
+    pub id: String,
+    pub saleor_api_url: String,
+    pub domain: Option<String>,
+    pub app_id: String,
+    /// Always `[redacted]`
+    #[schema(value_type = String)]
+    pub token: SecretString,
+    pub jwks: Option<String>,
+    /// Unix timestamp of the registration
+    pub registered_at: Option<u64>,
+    pub generation: u64,
+    /// Unix timestamp of when Saleor first rejected the token, cleared by a new token
+    pub token_invalid_since: Option<u64>,
+}
+
+impl From<AuthData> for AplEntry {
+    fn from(auth_data: AuthData) -> Self {
+        Self {
+            id: installation_id(&auth_data.saleor_api_url),
+            saleor_api_url: auth_data.saleor_api_url,
+            domain: auth_data.domain,
+            app_id: auth_data.app_id,
+            token: auth_data.token,
+            jwks: auth_data.jwks,
+            registered_at: auth_data.registered_at,
+            generation: auth_data.generation,
+            token_invalid_since: auth_data.token_invalid_since,
+        }
+    }
+}
+
+/// Body of `PUT /api/admin/installations/{id}`, fields that are left out are kept.
+#[derive(Deserialize, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AplEntryUpdate {
+    /// Required when the entry doesn't exist yet
+    #[schema(value_type = Option<String>)]
+    pub token: Option<SecretString>,
+    pub domain: Option<String>,
+    pub jwks: Option<String>,
+    /// Drops the stored JWKS, it's fetched from Saleor on the next request
+    #[serde(default)]
+    pub clear_jwks: bool,
+}
+
+/// The installation of the id in the path, `None` if the id is invalid.
+fn apl_id(apl: &SaleorApl, id: &str) -> Option<(String, AplId)> {
+    let saleor_api_url = saleor_api_url(id)?;
+    let apl_id = apl.id(&saleor_api_url);
+    Some((saleor_api_url, apl_id))
+}
+
+/// `GET /api/admin/installations/{id}`, the APL entry of an installation.
+#[utoipa::path(get, path = "/api/admin/installations/{id}", tag = "operator", params(("id" = String, Path, description = "The saleor api url in url safe base64")), security(("operator_api_key" = [])), responses(
+    (status = 200, description = "The entry, with its token redacted", body = AplEntry),
+    (status = 401, description = "The operator API key is missing or wrong"),
+    (status = 404, description = "The installation doesn't exist"),
+))]
+pub async fn get_apl_entry(apl: SaleorApl, Path(id): Path<String>) -> Response {
+    let (_, apl_id) = match apl_id(&apl, &id) {
+        Some(apl_id) => apl_id,
+        None => return (StatusCode::NOT_FOUND, "installation not found").into_response(),
+    };
+    match apl.get(&apl_id).await {
+        Ok(Some(auth_data)) => Json(AplEntry::from(auth_data)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "installation not found").into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `PUT /api/admin/installations/{id}`, repairs an APL entry or seeds a missing one.
+///
+/// Sessions of the installation stay valid, unlike when Saleor registers the app again.
+#[utoipa::path(put, path = "/api/admin/installations/{id}", tag = "operator", params(("id" = String, Path, description = "The saleor api url in url safe base64")), request_body = AplEntryUpdate, security(("operator_api_key" = [])), responses(
+    (status = 200, description = "The entry after the change, with its token redacted", body = AplEntry),
+    (status = 400, description = "A new entry lacks a token"),
+    (status = 401, description = "The operator API key is missing or wrong"),
+    (status = 404, description = "The id is invalid"),
+    (status = 422, description = "The token is blank or the JWKS are invalid"),
+))]
+pub async fn put_apl_entry(
+    apl: SaleorApl,
+    audit_log: AuditLog,
+    uninstalled: UninstalledTenants,
+    client: HttpClient,
+    Extension(dashboard_origins): Extension<DashboardOrigins>,
+    Path(id): Path<String>,
+    Json(update): Json<AplEntryUpdate>,
+) -> Response {
+    let (saleor_api_url, apl_id) = match apl_id(&apl, &id) {
+        Some(apl_id) => apl_id,
+        None => return (StatusCode::NOT_FOUND, "installation not found").into_response(),
+    };
+    if let Some(Err(e)) = update.jwks.as_deref().map(Jwks::parse) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, format!("invalid jwks: {}", e)).into_response();
+    }
+
+    // like registrations, so rotated JWKS or a new registration aren't overwritten
+    let _writes = client.app_tokens().lock_writes().await;
+    let existing = match apl.get(&apl_id).await {
+        Ok(existing) => existing,
+        Err(e) => return e.into_response(),
+    };
+    let created = existing.is_none();
+    let mut auth_data = match (existing, &update.token) {
+        (Some(auth_data), _) => auth_data,
+        (None, Some(token)) if token.expose().trim().is_empty() => {
+            return (StatusCode::UNPROCESSABLE_ENTITY, "new installations need a token").into_response();
+        }
+        (None, Some(token)) => AuthData { app_id: apl.app_id().to_string(), ..AuthData::new(saleor_api_url.clone(), token.clone()) },
+        (None, None) => return (StatusCode::BAD_REQUEST, "new installations need a token").into_response(),
+    };
+
+    let mut changes = vec![];
+    if let Some(token) = update.token.filter(|token| !token.expose().trim().is_empty()) {
+        auth_data.token = token;
+        // a new token deserves another try
+        auth_data.token_invalid_since = None;
+        changes.push("token");
+    }
+    if let Some(domain) = update.domain {
+        auth_data.domain = Some(domain);
+        changes.push("domain");
+    }
+    if update.clear_jwks {
+        auth_data.jwks = None;
+        changes.push("jwks cleared");
+    } else if let Some(jwks) = update.jwks {
+        auth_data.jwks = Some(jwks);
+        changes.push("jwks");
+    }
+
+    if let Err(e) = apl.set(&apl_id, auth_data.clone()).await {
+        return e.into_response();
+    }
+    uninstalled.forget(&apl_id);
+    dashboard_origins.installations_changed().await;
+    let detail = match created {
+        true => "entry created through the operator api".to_string(),
+        false => format!("{} changed through the operator api", changes.join(", ")),
+    };
+    tracing::info!(saleor_api_url = %saleor_api_url, "{}", detail);
+    audit_log.record(AuditEvent::new(&saleor_api_url, AuditEventKind::AplEntryChanged).with_detail(detail));
+    Json(AplEntry::from(auth_data)).into_response()
+}
+
+/// `DELETE /api/admin/installations/{id}`, removes an APL entry like `DELETE /api/installations`.
+#[utoipa::path(delete, path = "/api/admin/installations/{id}", tag = "operator", params(("id" = String, Path, description = "The saleor api url in url safe base64")), security(("operator_api_key" = [])), responses(
+    (status = 204, description = "The installation was removed from the APL"),
+    (status = 401, description = "The operator API key is missing or wrong"),
+    (status = 404, description = "The installation doesn't exist"),
+))]
+pub async fn delete_apl_entry(apl: SaleorApl, audit_log: AuditLog, uninstalled: UninstalledTenants, graphql_cache: GraphqlCache, Path(id): Path<String>) -> Response {
+    let (saleor_api_url, apl_id) = match apl_id(&apl, &id) {
+        Some(apl_id) => apl_id,
+        None => return (StatusCode::NOT_FOUND, "installation not found").into_response(),
+    };
+    match apl.get(&apl_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "installation not found").into_response(),
+        Err(e) => return e.into_response(),
+    }
+    if let Err(e) = apl.remove(&apl_id).await {
+        return e.into_response();
+    }
+    uninstalled.mark(&apl_id);
+    graphql_cache.remove_tenant(&saleor_api_url).await;
+    tracing::info!(saleor_api_url = %saleor_api_url, "removed installation through the operator api");
+    audit_log.record(AuditEvent::new(&saleor_api_url, AuditEventKind::Uninstalled).with_detail("removed through the operator api"));
+    StatusCode::NO_CONTENT.into_response()
+}
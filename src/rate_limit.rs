@@ -0,0 +1,340 @@
+use std::{collections::HashMap, convert::Infallible, future::Future, net::{IpAddr, SocketAddr}, pin::Pin, sync::{Arc, Mutex, RwLock}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use axum::{http::{Extensions, HeaderMap, Request, HeaderValue, header::RETRY_AFTER, request::Parts}, response::{Response, IntoResponse}, body::Body, extract::{ConnectInfo, FromRequestParts}};
+use reqwest::StatusCode;
+use tower::{Layer, Service};
+
+use crate::saleor::{SaleorApl, SALEOR_API_URL_HEADER};
+
+/// What a rate limit bucket is keyed by.
+///
+/// Anyone can send any `saleor-api-url`, so it only keys the bucket of installations in the APL,
+/// other requests are keyed by their client IP instead. Api urls that weren't found in the APL
+/// lately take a token of the IP before the APL is asked, so floods of made up urls are limited
+/// without reaching it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    Ip,
+    SaleorApiUrl,
+    IpAndSaleorApiUrl,
+}
+
+impl RateLimitKey {
+    /// The value of `RATE_LIMIT_KEY` selecting this key.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::SaleorApiUrl => "saleor-api-url",
+            Self::IpAndSaleorApiUrl => "ip-and-saleor-api-url",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// How many requests a single key can burst
+    pub capacity: u32,
+    /// How many tokens are refilled per second
+    pub refill_per_second: f64,
+    pub key: RateLimitKey,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 20,
+            refill_per_second: 1.0,
+            key: RateLimitKey::Ip,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// The default limits of webhooks, per Saleor API url. Saleor sends them in bursts, like
+    /// when products are imported, so they're far higher than the ones of other requests.
+    pub fn webhooks() -> Self {
+        Self {
+            capacity: 200,
+            refill_per_second: 50.0,
+            key: RateLimitKey::SaleorApiUrl,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Buckets above this amount trigger a cleanup of buckets that are full again.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+/// The longest wait handed out, refill rates close to zero would ask for centuries.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(3600);
+/// How long an api url found in the APL keys its bucket before it's looked up again.
+const INSTALLED_TTL: Duration = Duration::from_secs(60);
+
+pub(crate) struct TokenBuckets {
+    config: RwLock<RateLimitConfig>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl TokenBuckets {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn config(&self) -> RateLimitConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Changes the limits, tokens already in the buckets are kept up to the new capacity.
+    pub(crate) fn set_config(&self, config: RateLimitConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Takes a token for the given key, returns how long to wait if there is none left.
+    pub(crate) fn acquire(&self, key: String) -> Result<(), Duration> {
+        let now = Instant::now();
+        let config = self.config();
+        let capacity = config.capacity as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            let refill = config.refill_per_second;
+            buckets.retain(|_, bucket| bucket.tokens + now.duration_since(bucket.last_refill).as_secs_f64() * refill < capacity);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket { tokens: capacity, last_refill: now });
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * config.refill_per_second).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let missing = 1.0 - bucket.tokens;
+        let wait = Duration::try_from_secs_f64(missing / config.refill_per_second).unwrap_or(MAX_RETRY_AFTER);
+        Err(wait.min(MAX_RETRY_AFTER))
+    }
+
+    /// Puts back a token taken with [`Self::acquire`], for requests another limit rejected.
+    pub(crate) fn refund(&self, key: &str) {
+        let capacity = self.config().capacity as f64;
+        if let Some(bucket) = self.buckets.lock().unwrap().get_mut(key) {
+            bucket.tokens = (bucket.tokens + 1.0).min(capacity);
+        }
+    }
+}
+
+/// Proxies whose `x-forwarded-*` headers are trusted, configured by `TRUSTED_PROXIES`.
+///
+/// Anyone can send `x-forwarded-for` and the like, so they're ignored unless the request comes
+/// from one of these.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Arc<[IpAddr]>);
+
+impl TrustedProxies {
+    pub fn new(proxies: &[IpAddr]) -> Self {
+        Self(proxies.into())
+    }
+
+    pub fn proxies(&self) -> &[IpAddr] {
+        &self.0
+    }
+
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        self.0.contains(&ip)
+    }
+}
+
+/// Token bucket rate limiting, answers with `429 Too Many Requests` and `Retry-After` when exhausted.
+///
+/// Client IPs are taken from the connection info, so serve the router with
+/// `into_make_service_with_connect_info::<SocketAddr>()`, or from `x-forwarded-for` when the
+/// connection comes from one of the [`TrustedProxies`] in the request extensions.
+///
+/// Webhooks all come from the few IPs of Saleor, so they're limited per Saleor API url instead
+/// (`WEBHOOK_RATE_LIMIT_*`), which stops retry storms of a single installation. Webhooks naming
+/// an api url that isn't installed share the bucket of their IP, see [`RateLimitKey`].
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    buckets: Arc<TokenBuckets>,
+    installed: Arc<InstalledApiUrls>,
+}
+
+impl RateLimitLayer {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            buckets: Arc::new(TokenBuckets::new(config)),
+            installed: Arc::default(),
+        }
+    }
+
+    /// Changes the limits of every service made by this layer, see [`crate::reload`].
+    pub fn set_config(&self, config: RateLimitConfig) {
+        self.buckets.set_config(config);
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            buckets: self.buckets.clone(),
+            installed: self.installed.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    buckets: Arc<TokenBuckets>,
+    installed: Arc<InstalledApiUrls>,
+}
+
+/// Saleor API urls found in the APL within the [`INSTALLED_TTL`], so requests of installations
+/// don't look them up every time.
+#[derive(Default)]
+struct InstalledApiUrls(Mutex<HashMap<String, Instant>>);
+
+impl InstalledApiUrls {
+    fn contains(&self, api_url: &str) -> bool {
+        self.0.lock().unwrap().get(api_url).is_some_and(|found| found.elapsed() < INSTALLED_TTL)
+    }
+
+    fn insert(&self, api_url: &str) {
+        let mut installed = self.0.lock().unwrap();
+        if installed.len() > MAX_IDLE_BUCKETS {
+            installed.retain(|_, found| found.elapsed() < INSTALLED_TTL);
+        }
+        installed.insert(api_url.to_string(), Instant::now());
+    }
+}
+
+pub(crate) fn client_ip<B>(req: &Request<B>) -> Option<String> {
+    client_ip_of(req.headers(), req.extensions())
+}
+
+fn client_ip_of(headers: &HeaderMap, extensions: &Extensions) -> Option<String> {
+    let peer = extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip())?;
+    let trusted = extensions.get::<TrustedProxies>().cloned().unwrap_or_default();
+    if !trusted.contains(peer) {
+        return Some(peer.to_string());
+    }
+
+    // every proxy appends the address it got the request from, the right-most one that isn't a
+    // trusted proxy is the client, the hops before it could be made up
+    let hops = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .collect::<Vec<_>>();
+    let mut client = peer.to_string();
+    for hop in hops.into_iter().rev() {
+        client = hop.to_string();
+        match hop.parse() {
+            Ok(ip) if trusted.contains(ip) => continue,
+            _ => break,
+        }
+    }
+    Some(client)
+}
+
+/// Extracts the client IP like [`RateLimitLayer`] does, `None` if it's unknown.
+pub struct ClientIp(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Sync + Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ClientIp(client_ip_of(&parts.headers, &parts.extensions)))
+    }
+}
+
+/// Whether `api_url` is installed in the APL of the request.
+async fn is_installed<B>(req: &Request<B>, api_url: &str) -> bool {
+    let Some(apl) = req.extensions().get::<SaleorApl>() else {
+        return false;
+    };
+    matches!(apl.get(&apl.id(api_url)).await, Ok(Some(_)))
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, "too many requests").into_response();
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+impl<S> Service<Request<Body>> for RateLimitService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let buckets = self.buckets.clone();
+        let installed = self.installed.clone();
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let key = buckets.config().key;
+            let ip = client_ip(&req).unwrap_or_else(|| "unknown".to_string());
+            let api_url = match key {
+                RateLimitKey::Ip => None,
+                _ => req.headers().get(SALEOR_API_URL_HEADER).and_then(|h| h.to_str().ok()).map(str::to_string),
+            };
+            let installed_api_url = match api_url {
+                Some(api_url) if installed.contains(&api_url) => Some(api_url),
+                Some(api_url) => {
+                    // the IP pays for asking the APL, installations get the token back
+                    if let Err(retry_after) = buckets.acquire(ip.clone()) {
+                        return Ok(too_many_requests(retry_after));
+                    }
+                    if !is_installed(&req, &api_url).await {
+                        return inner.call(req).await;
+                    }
+                    buckets.refund(&ip);
+                    installed.insert(&api_url);
+                    Some(api_url)
+                }
+                None => None,
+            };
+
+            let bucket = match (key, installed_api_url) {
+                (RateLimitKey::SaleorApiUrl, Some(api_url)) => api_url,
+                (_, Some(api_url)) => format!("{}|{}", ip, api_url),
+                (_, None) => ip,
+            };
+            if let Err(retry_after) = buckets.acquire(bucket) {
+                return Ok(too_many_requests(retry_after));
+            }
+
+            let response: Response = inner.call(req).await?;
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,164 @@
+//! Abuse protection of `POST /api/register`, whose URL is public through the manifest.
+//!
+//! Registrations are rate limited per client IP, which is only taken from `x-forwarded-for` behind
+//! [`TrustedProxies`](crate::rate_limit::TrustedProxies), and per Saleor domain, and auth tokens that were
+//! already registered are rejected, so a captured register request can't be replayed. Rejections
+//! answer with the error body Saleor expects from the register endpoint.
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::{header::RETRY_AFTER, request::Parts, HeaderValue}, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+
+use crate::{rate_limit::{RateLimitConfig, RateLimitKey, TokenBuckets}, saleor::{SaleorRegisterErrorCode, SaleorRegisterRequest, SaleorRegisterResponse}};
+
+/// Tokens above this amount trigger a cleanup of tokens outside the replay window.
+const MAX_SEEN_TOKENS: usize = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct RegistrationConfig {
+    /// How many registrations a single IP or domain can burst
+    pub burst: u32,
+    /// How many registrations are allowed per minute once the burst is used up
+    pub per_minute: f64,
+    /// How long registered auth tokens are remembered
+    pub replay_window: Duration,
+}
+
+impl Default for RegistrationConfig {
+    fn default() -> Self {
+        Self {
+            burst: 5,
+            per_minute: 2.0,
+            replay_window: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationRejected {
+    /// Too many registrations of the IP or domain, retry after the duration
+    RateLimited(Duration),
+    /// The auth token was registered before
+    TokenReused,
+}
+
+impl IntoResponse for RegistrationRejected {
+    fn into_response(self) -> Response {
+        match self {
+            Self::RateLimited(retry_after) => {
+                let mut response = SaleorRegisterResponse::error(SaleorRegisterErrorCode::RateLimited, "too many registrations", StatusCode::TOO_MANY_REQUESTS);
+                let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+                response
+            }
+            Self::TokenReused => SaleorRegisterResponse::error(SaleorRegisterErrorCode::AuthTokenReused, "auth token was already registered", StatusCode::FORBIDDEN),
+        }
+    }
+}
+
+/// Checks registrations before they're stored, provided to the register handler as a request extension.
+#[derive(Clone)]
+pub struct RegistrationGuard {
+    by_ip: Arc<TokenBuckets>,
+    by_domain: Arc<TokenBuckets>,
+    /// SHA-256 of registered tokens, only hashes are kept since the tokens grant access to Saleor
+    seen_tokens: Arc<Mutex<HashMap<Vec<u8>, Instant>>>,
+    replay_window: Duration,
+}
+
+impl RegistrationGuard {
+    pub fn new(config: &RegistrationConfig) -> Self {
+        let limit = rate_limit(config);
+        Self {
+            by_ip: Arc::new(TokenBuckets::new(limit.clone())),
+            by_domain: Arc::new(TokenBuckets::new(limit)),
+            seen_tokens: Arc::new(Mutex::new(HashMap::new())),
+            replay_window: config.replay_window,
+        }
+    }
+
+    /// Changes the rate limits, the replay window stays the one the guard was created with.
+    pub fn set_limits(&self, config: &RegistrationConfig) {
+        self.by_ip.set_config(rate_limit(config));
+        self.by_domain.set_config(rate_limit(config));
+    }
+
+    /// Takes a registration from the budgets of the IP and the domain, and reserves the token if it's
+    /// new. Registrations with the same token are rejected while it's reserved, the reservation is
+    /// released again unless it's [confirmed](TokenReservation::confirm).
+    pub fn check(&self, client_ip: Option<&str>, request: &SaleorRegisterRequest) -> Result<TokenReservation, RegistrationRejected> {
+        let reservation = self.reserve(request.auth_token.expose())?;
+        let client_ip = client_ip.unwrap_or("unknown");
+        self.by_ip.acquire(client_ip.to_string()).map_err(RegistrationRejected::RateLimited)?;
+        // a registration the domain rejects doesn't count against the IP
+        if let Err(wait) = self.by_domain.acquire(request.saleor_domain.to_lowercase()) {
+            self.by_ip.refund(client_ip);
+            return Err(RegistrationRejected::RateLimited(wait));
+        }
+        Ok(reservation)
+    }
+
+    fn reserve(&self, auth_token: &str) -> Result<TokenReservation, RegistrationRejected> {
+        let now = Instant::now();
+        let hash = token_hash(auth_token);
+        let mut seen_tokens = self.seen_tokens.lock().unwrap();
+        if seen_tokens.get(&hash).is_some_and(|seen_at| now.duration_since(*seen_at) < self.replay_window) {
+            return Err(RegistrationRejected::TokenReused);
+        }
+        if seen_tokens.len() > MAX_SEEN_TOKENS {
+            seen_tokens.retain(|_, seen_at| now.duration_since(*seen_at) < self.replay_window);
+        }
+        seen_tokens.insert(hash.clone(), now);
+        Ok(TokenReservation { seen_tokens: self.seen_tokens.clone(), hash, confirmed: false })
+    }
+}
+
+/// A token reserved by [`RegistrationGuard::check`], released when dropped unless the registration
+/// was stored, so failed registrations can be retried with it.
+#[must_use]
+pub struct TokenReservation {
+    seen_tokens: Arc<Mutex<HashMap<Vec<u8>, Instant>>>,
+    hash: Vec<u8>,
+    confirmed: bool,
+}
+
+impl TokenReservation {
+    /// Keeps the token for the replay window, the registration was stored.
+    pub fn confirm(mut self) {
+        self.confirmed = true;
+    }
+}
+
+impl Drop for TokenReservation {
+    fn drop(&mut self) {
+        if !self.confirmed {
+            self.seen_tokens.lock().unwrap().remove(&self.hash);
+        }
+    }
+}
+
+fn rate_limit(config: &RegistrationConfig) -> RateLimitConfig {
+    RateLimitConfig {
+        capacity: config.burst,
+        refill_per_second: config.per_minute / 60.0,
+        key: RateLimitKey::Ip,
+    }
+}
+
+fn token_hash(auth_token: &str) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, auth_token.as_bytes()).as_ref().to_vec()
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RegistrationGuard
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<RegistrationGuard>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "registration guard not found in request extensions").into_response())
+    }
+}
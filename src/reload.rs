@@ -0,0 +1,235 @@
+//! Reloading the configuration without a restart, on `SIGHUP` or `POST /api/admin/reload`.
+//!
+//! The environment of a running process can't change, so settings meant to be reloaded belong in
+//! `CONFIG_FILE`, like a mounted Kubernetes ConfigMap, whose values override the environment.
+//! Only settings read on every request are applied: the allowlists `ADMIN_SALEOR_API_URLS` and
+//! `CORS_ORIGINS`, the log filter `RUST_LOG`, rate limits, concurrency limits and the circuit
+//! breaker. Everything else, like the port or the session store, keeps its value until the app is
+//! restarted. Feature flags are kept per installation in Saleor and never need a reload.
+//!
+//! Every reload that changed something is recorded in the audit log of the admin installations,
+//! with what changed in the detail.
+
+use std::{fmt, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts, response::{IntoResponse, Response}, Json};
+use reqwest::StatusCode;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    admin_auth::AdminOperator,
+    audit::{AuditEvent, AuditEventKind, AuditLog},
+    config::AppConfig,
+    cors::DashboardOrigins,
+    http_client::HttpClient,
+    rate_limit::RateLimitLayer,
+    registration::RegistrationGuard,
+    saleor::{self, TenantAllowlist},
+    telemetry,
+};
+
+#[derive(Debug)]
+pub struct ReloadError(pub String);
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "reload error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ReloadError {}
+
+impl IntoResponse for ReloadError {
+    fn into_response(self) -> Response {
+        tracing::warn!("{}", self);
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// A setting that changed, with the values in the syntax of its variable.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ConfigChange {
+    /// Name of the variable, like `RATE_LIMIT_BURST`
+    #[schema(example = "RATE_LIMIT_BURST")]
+    pub setting: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:?} -> {:?}", self.setting, self.from, self.to)
+    }
+}
+
+/// The reloadable settings of a configuration, by variable.
+fn reloadable_settings(config: &AppConfig) -> Vec<(&'static str, String)> {
+    vec![
+        ("ADMIN_SALEOR_API_URLS", config.admin_api_urls.join(",")),
+        ("CORS_ORIGINS", config.cors_origins.join(",")),
+        ("RUST_LOG", config.log_filter.clone().unwrap_or_default()),
+        ("RATE_LIMIT_BURST", config.rate_limit.capacity.to_string()),
+        ("RATE_LIMIT_PER_SECOND", config.rate_limit.refill_per_second.to_string()),
+        ("RATE_LIMIT_KEY", config.rate_limit.key.as_str().to_string()),
+        ("REGISTER_RATE_LIMIT_BURST", config.registration.burst.to_string()),
+        ("REGISTER_RATE_LIMIT_PER_MINUTE", config.registration.per_minute.to_string()),
+        ("WEBHOOK_RATE_LIMIT_BURST", config.webhook_rate_limit.capacity.to_string()),
+        ("WEBHOOK_RATE_LIMIT_PER_SECOND", config.webhook_rate_limit.refill_per_second.to_string()),
+        ("MAX_CONCURRENT_GRAPHQL", config.concurrency.graphql.to_string()),
+        ("MAX_CONCURRENT_WEBHOOKS", config.concurrency.webhooks.to_string()),
+        ("CIRCUIT_BREAKER_FAILURES", config.circuit_breaker.failure_threshold.to_string()),
+        ("CIRCUIT_BREAKER_OPEN_SECS", config.circuit_breaker.open_for.as_secs().to_string()),
+        ("CIRCUIT_BREAKER_PROBES", config.circuit_breaker.probes.to_string()),
+        ("GRAPHQL_DEBUG", config.graphql_debug.enabled.to_string()),
+        ("GRAPHQL_DEBUG_REDACT", config.graphql_debug.redacted_fields.join(",")),
+    ]
+}
+
+/// The reloadable settings that differ between `old` and `new`.
+pub fn config_changes(old: &AppConfig, new: &AppConfig) -> Vec<ConfigChange> {
+    reloadable_settings(old)
+        .into_iter()
+        .zip(reloadable_settings(new))
+        .filter(|((_, from), (_, to))| from != to)
+        .map(|((setting, from), (_, to))| ConfigChange { setting: setting.to_string(), from, to })
+        .collect()
+}
+
+/// Applies new configurations to the running app, created by [`crate::app::build`].
+#[derive(Clone)]
+pub struct ConfigReloader {
+    current: Arc<Mutex<AppConfig>>,
+    admin_tenants: TenantAllowlist,
+    dashboard_origins: DashboardOrigins,
+    rate_limit: RateLimitLayer,
+    webhook_rate_limit: Option<RateLimitLayer>,
+    registration: RegistrationGuard,
+    audit_log: AuditLog,
+    http_client: HttpClient,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        config: &AppConfig,
+        admin_tenants: TenantAllowlist,
+        dashboard_origins: DashboardOrigins,
+        rate_limit: RateLimitLayer,
+        registration: RegistrationGuard,
+        audit_log: AuditLog,
+        http_client: HttpClient,
+    ) -> Self {
+        Self {
+            current: Arc::new(Mutex::new(config.clone())),
+            admin_tenants,
+            dashboard_origins,
+            rate_limit,
+            webhook_rate_limit: None,
+            registration,
+            audit_log,
+            http_client,
+        }
+    }
+
+    /// Also applies `WEBHOOK_RATE_LIMIT_*` to the webhooks limited by `layer`.
+    pub fn with_webhook_rate_limit(mut self, layer: RateLimitLayer) -> Self {
+        self.webhook_rate_limit = Some(layer);
+        self
+    }
+
+    /// The configuration applied last.
+    pub fn current(&self) -> AppConfig {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Reads the configuration again with [`AppConfig::from_env`] and applies it, `by` is recorded
+    /// as who asked for the reload.
+    pub fn reload(&self, by: &str) -> Result<Vec<ConfigChange>, ReloadError> {
+        let config = AppConfig::from_env().map_err(|e| ReloadError(e.to_string()))?;
+        self.apply(config, by)
+    }
+
+    /// Applies the reloadable settings of `config`, returning what changed. Configurations with
+    /// problems are rejected as a whole.
+    pub fn apply(&self, config: AppConfig, by: &str) -> Result<Vec<ConfigChange>, ReloadError> {
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(ReloadError(format!("invalid configuration: {}", problems.join(", "))));
+        }
+        if let Some(filter) = &config.log_filter {
+            telemetry::set_log_filter(filter).map_err(|e| ReloadError(e.to_string()))?;
+        }
+
+        let mut current = self.current.lock().unwrap();
+        let changes = config_changes(&current, &config);
+        self.admin_tenants.set(&config.admin_api_urls);
+        self.dashboard_origins.set_explicit(&config.cors_origins);
+        self.rate_limit.set_config(config.rate_limit.clone());
+        if let Some(webhook_rate_limit) = &self.webhook_rate_limit {
+            webhook_rate_limit.set_config(config.webhook_rate_limit.clone());
+        }
+        self.registration.set_limits(&config.registration);
+        self.http_client.concurrency().set_limits(config.concurrency);
+        self.http_client.circuit_breakers().set_config(config.circuit_breaker);
+        saleor::set_graphql_debug(&config.graphql_debug);
+
+        if changes.is_empty() {
+            tracing::info!(by, "configuration reloaded without changes");
+        } else {
+            let detail = changes.iter().map(ConfigChange::to_string).collect::<Vec<_>>().join(", ");
+            tracing::info!(by, changes = %detail, "configuration reloaded");
+            // operators removed from the allowlist still learn who removed them
+            let mut admins = current.admin_api_urls.clone();
+            admins.extend(config.admin_api_urls.iter().filter(|url| !current.admin_api_urls.contains(url)).cloned());
+            for saleor_api_url in admins {
+                self.audit_log.record(AuditEvent::new(&saleor_api_url, AuditEventKind::ConfigReloaded).with_detail(format!("{detail} by {by}")));
+            }
+        }
+        *current = config;
+
+        Ok(changes)
+    }
+
+    /// Reloads the configuration on every `SIGHUP` until the process exits.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(self) -> std::io::Result<()> {
+        let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                if let Err(e) = self.reload("SIGHUP") {
+                    tracing::error!("{}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ConfigReloader
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<ConfigReloader>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "config reloader not found in request extensions").into_response())
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ReloadResult {
+    /// The settings that changed, empty if the configuration is the same
+    pub changes: Vec<ConfigChange>,
+}
+
+/// `POST /api/admin/reload`, reads the configuration again like `SIGHUP` does.
+#[utoipa::path(post, path = "/api/admin/reload", tag = "admin", security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The configuration was applied", body = ReloadResult),
+    (status = 500, description = "The configuration is invalid, the previous one stays in place"),
+))]
+pub async fn reload_config(reloader: ConfigReloader, AdminOperator(operator): AdminOperator) -> Result<Json<ReloadResult>, ReloadError> {
+    let changes = reloader.reload(&operator)?;
+    Ok(Json(ReloadResult { changes }))
+}
@@ -0,0 +1,148 @@
+use std::{future::Future, pin::Pin, time::Instant};
+
+use async_trait::async_trait;
+use axum::{http::{Request, HeaderValue, header::CONTENT_TYPE, request::Parts}, response::{Response, IntoResponse}, body::{Body, boxed, Full}, extract::FromRequestParts};
+use reqwest::StatusCode;
+use tower::{Layer, Service};
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The id of the current request, either taken from the `x-request-id` header or generated.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= 128
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+        valid.then(|| Self(value.to_string()))
+    }
+
+    fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestId
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<RequestId>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "request id not found in request extensions").into_response())
+    }
+}
+
+/// Accepts or generates an `x-request-id`, records it on the request span and echoes it back
+/// in the response headers and in error bodies.
+#[derive(Clone, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestIdService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let request_id = req
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .and_then(RequestId::from_header)
+                .unwrap_or_else(RequestId::generate);
+            req.extensions_mut().insert(request_id.clone());
+
+            let span = tracing::info_span!(
+                "request",
+                request_id = %request_id.0,
+                method = %req.method(),
+                // queries carry auth tokens and codes, like the one of `/api/register`
+                path = %req.uri().path(),
+                route = tracing::field::Empty,
+                saleor_api_url = tracing::field::Empty,
+                installation_id = tracing::field::Empty,
+                app_id = tracing::field::Empty,
+                event_type = tracing::field::Empty,
+                status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+            );
+            let start = Instant::now();
+            let response: Response = inner.call(req).instrument(span.clone()).await?;
+
+            span.record("status", response.status().as_u16());
+            span.record("latency_ms", start.elapsed().as_millis() as u64);
+            span.in_scope(|| tracing::info!("request finished"));
+
+            let mut response = match response.status().is_client_error() || response.status().is_server_error() {
+                true => with_request_id_in_body(response, &request_id).await,
+                false => response,
+            };
+            if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// Adds the request id to plain text and JSON object error bodies, other bodies are left as is.
+async fn with_request_id_in_body(response: Response, request_id: &RequestId) -> Response {
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let is_json = content_type.starts_with("application/json");
+    if !is_json && !content_type.starts_with("text/plain") {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = hyper::body::to_bytes(body).await else {
+        return (parts.status, format!("request id: {}", request_id.0)).into_response();
+    };
+
+    let body = match is_json {
+        true => match serde_json::from_slice::<serde_json::Value>(&bytes) {
+            Ok(serde_json::Value::Object(mut object)) => {
+                object.insert("requestId".to_string(), request_id.0.clone().into());
+                serde_json::to_vec(&object).unwrap_or_else(|_| bytes.to_vec())
+            },
+            _ => bytes.to_vec(),
+        },
+        false => format!("{} (request id: {})", String::from_utf8_lossy(&bytes), request_id.0).into_bytes(),
+    };
+
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+    Response::from_parts(parts, boxed(Full::from(body)))
+}
@@ -0,0 +1,168 @@
+//! Building apps on this crate without wiring its middleware by hand.
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .route("/app/home", get(home))
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().with(OrderCreatedWebhook))
+//!     .saleor_app(&config, FileAplStore)
+//!     .await?;
+//! ```
+//!
+//! Routes added before [`RouterExt::saleor_app`] are served behind the sessions, APL and the rest
+//! of the middleware of the base app, so the order of the layers can't be gotten wrong.
+
+use std::{future::Future, pin::Pin};
+
+use async_trait::async_trait;
+use axum::{body::Body, http::{Request, StatusCode}, response::{IntoResponse, Response}, routing::MethodRouter, Extension, Router};
+use tower::{Layer, Service, ServiceExt};
+
+use crate::{
+    app::{self, App},
+    concurrency::TenantConcurrencyLayer,
+    config::AppConfig,
+    graphql_cache::GraphqlCacheInvalidationLayer,
+    load_shed::LoadShedLayer,
+    rate_limit::RateLimitLayer,
+    saleor::{AplStore, DeclaredWebhook, SaleorWebhookManifest, WebhookBodyLimit, WebhookHandler},
+    uninstalled::UninstalledTenantsLayer,
+    webhook_status::WebhookStatsLayer,
+};
+
+/// Webhooks served together by [`RouterExt::saleor_webhooks`].
+#[derive(Clone, Default)]
+pub struct SaleorWebhooks {
+    router: Router,
+    manifests: Vec<fn(&str) -> SaleorWebhookManifest>,
+}
+
+impl SaleorWebhooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a webhook declared with [`crate::saleor::saleor_webhook`].
+    pub fn with<W>(mut self, webhook: W) -> Self
+    where
+        W: DeclaredWebhook + WebhookHandler<W::Payload>,
+    {
+        self.router = webhook.route(self.router);
+        self.manifests.push(W::manifest);
+        self
+    }
+
+    /// Adds a handler that declares its manifest entry elsewhere, like one extracting
+    /// [`crate::saleor::SaleorWebhook`].
+    pub fn route(mut self, path: &str, handler: MethodRouter) -> Self {
+        self.router = self.router.route(path, handler);
+        self
+    }
+
+    /// Adds the webhooks of `router`, like the ones of [`crate::saleor::taxes::router`].
+    pub fn merge(mut self, router: Router) -> Self {
+        self.router = self.router.merge(router);
+        self
+    }
+
+    /// The manifest entries of the webhooks added with [`Self::with`], `base_url` is where the app
+    /// is reachable.
+    pub fn manifest(&self, base_url: &str) -> Vec<SaleorWebhookManifest> {
+        self.manifests.iter().map(|manifest| manifest(base_url)).collect()
+    }
+}
+
+/// The load shedding and webhook rate limit of the app, inserted by [`app::build_with_routes`]
+/// so every webhook route shares them, wherever it was added.
+#[derive(Clone)]
+pub(crate) struct WebhookLimits {
+    pub(crate) load_shed: LoadShedLayer,
+    pub(crate) rate_limit: RateLimitLayer,
+}
+
+/// The middleware every webhook route is served behind, in this order.
+pub(crate) fn webhook_middleware(router: Router, config: &AppConfig) -> Router {
+    router
+        .layer(TenantConcurrencyLayer)
+        .layer(UninstalledTenantsLayer)
+        .layer(GraphqlCacheInvalidationLayer)
+        .layer(Extension(WebhookBodyLimit(config.limits.webhooks.body_limit_bytes)))
+        .layer(config.limits.webhooks.body_limit())
+        .layer(AppLimitLayer { limit: |limits| &limits.load_shed })
+        .layer(config.limits.webhooks.timeout())
+        .layer(AppLimitLayer { limit: |limits| &limits.rate_limit })
+        .layer(WebhookStatsLayer)
+}
+
+/// Serves requests behind one of the [`WebhookLimits`] of the app, taken from the request
+/// extensions since the routes are built before the app.
+#[derive(Clone)]
+struct AppLimitLayer<L> {
+    limit: fn(&WebhookLimits) -> &L,
+}
+
+impl<S, L> Layer<S> for AppLimitLayer<L> {
+    type Service = AppLimitService<S, L>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AppLimitService { inner, limit: self.limit }
+    }
+}
+
+#[derive(Clone)]
+struct AppLimitService<S, L> {
+    inner: S,
+    limit: fn(&WebhookLimits) -> &L,
+}
+
+impl<S, L> Service<Request<Body>> for AppLimitService<S, L>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    L: Layer<S> + Clone + Send + 'static,
+    L::Service: Service<Request<Body>, Response = Response, Error = S::Error> + Send + 'static,
+    <L::Service as Service<Request<Body>>>::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limit = req.extensions().get::<WebhookLimits>().map(|limits| (self.limit)(limits).clone());
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            match limit {
+                Some(limit) => limit.layer(inner).oneshot(req).await,
+                None => Ok((StatusCode::INTERNAL_SERVER_ERROR, "webhook limits not found in request extensions").into_response()),
+            }
+        })
+    }
+}
+
+#[async_trait]
+pub trait RouterExt: Sized {
+    /// Adds `webhooks` with the limits of `WEBHOOK_*`, rate limits and load shedding, per installation concurrency, `410 Gone`
+    /// for removed installations and delivery stats, like the webhooks of the base app.
+    ///
+    /// The webhooks share the room of [`crate::load_shed`] and the rate limit of the app serving
+    /// them with its own webhooks, a reload changes their limits as well.
+    fn saleor_webhooks(self, config: &AppConfig, webhooks: SaleorWebhooks) -> Self;
+
+    /// Builds the base app serving these routes, see [`app::build_with_routes`].
+    async fn saleor_app(self, config: &AppConfig, apl_store: impl AplStore) -> anyhow::Result<App>;
+}
+
+#[async_trait]
+impl RouterExt for Router {
+    fn saleor_webhooks(self, config: &AppConfig, webhooks: SaleorWebhooks) -> Self {
+        self.merge(webhook_middleware(webhooks.router, config))
+    }
+
+    async fn saleor_app(self, config: &AppConfig, apl_store: impl AplStore) -> anyhow::Result<App> {
+        app::build_with_routes(config, apl_store, self).await
+    }
+}
@@ -1,16 +1,63 @@
 use async_trait::async_trait;
-use axum::{response::{IntoResponse, Response}, http::{StatusCode, Request}, extract::{FromRequest, Query}, Json, body::Body};
+use axum::{response::{IntoResponse, Response}, http::{header::{ACCEPT, CONTENT_TYPE}, request::Parts, HeaderValue, StatusCode, Request}, extract::{FromRequest, FromRequestParts, Query}, Json, body::Body};
 use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use crate::secrets::SecretString;
 
 mod enums;
+mod graphql;
+mod install;
+pub mod bulk_metadata;
+pub mod chat;
+pub mod fulfillments;
+pub mod invoices;
+pub mod money;
+pub mod notifications;
+pub mod orders;
+pub mod payments;
+pub mod product_csv;
+pub mod search;
+pub mod subscriptions;
+#[cfg(feature = "stripe")]
+pub mod stripe;
+pub mod taxes;
+pub mod widgets;
+mod jwks;
+mod jwt_validation;
 mod apl;
+mod app_token;
+mod manifest_check;
+mod manifest_file;
 mod queries;
+mod schema_check;
+mod settings;
+mod tenant;
+mod user_token;
+mod webhook;
+mod webhook_handler;
+mod webhook_sync;
 
 pub use enums::*;
+pub use install::*;
+pub use jwks::*;
+pub use jwt_validation::*;
 pub use apl::*;
+pub use app_token::*;
+pub use manifest_check::*;
+pub use manifest_file::*;
+pub use graphql::{run_app_operation, set_graphql_debug, GraphqlDebugConfig, GraphqlError, APP_TOKEN_CHECK_QUERY, DEFAULT_REDACTED_VARIABLES};
+pub(crate) use graphql::run_operation;
 pub use queries::*;
+pub use schema_check::*;
+pub use settings::*;
+pub use tenant::*;
+pub use user_token::*;
+pub use webhook::*;
+pub use webhook_handler::*;
+pub use webhook_sync::*;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SaleorManifest {
     pub id: String,
@@ -41,11 +88,72 @@ pub struct SaleorManifest {
 
 impl IntoResponse for SaleorManifest {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::OK, axum::Json(self)).into_response()
+        // spelled out, some proxies mangle the bare `application/json` of `Json`
+        match serde_json::to_vec(&self) {
+            Ok(body) => (StatusCode::OK, [(CONTENT_TYPE, HeaderValue::from_static(JSON_UTF8))], body).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("unable to serialize the manifest: {e}")).into_response(),
+        }
     }
 }
 
-#[derive(Serialize, Debug)]
+const JSON_UTF8: &str = "application/json; charset=utf-8";
+
+/// Rejects requests whose `Accept` header rules out JSON with `406 Not Acceptable`, requests
+/// without one accept anything.
+pub struct AcceptsJson;
+
+/// How specifically a single media range of an `Accept` header matches JSON, and its quality.
+fn json_match(media_range: &str) -> Option<(u8, f32)> {
+    let mut parts = media_range.split(';').map(str::trim);
+    let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let specificity = match media_type.as_str() {
+        "*/*" => 0,
+        "application/*" => 1,
+        "application/json" => 2,
+        _ if media_type.starts_with("application/") && media_type.ends_with("+json") => 2,
+        _ => return None,
+    };
+    let quality = parts
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+        .unwrap_or(1.0);
+    Some((specificity, quality))
+}
+
+/// Whether an `Accept` header allows JSON, the most specific matching media range deciding like
+/// in `application/json;q=0, */*`.
+fn accepts_json<'a>(media_ranges: impl Iterator<Item = &'a str>) -> bool {
+    media_ranges
+        .filter_map(json_match)
+        .max_by(|(a, q_a), (b, q_b)| a.cmp(b).then(q_a.total_cmp(q_b)))
+        .is_some_and(|(_, quality)| quality > 0.0)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AcceptsJson
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let mut media_ranges = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|accept| accept.to_str().ok())
+            .flat_map(|accept| accept.split(','))
+            .filter(|media_range| !media_range.trim().is_empty())
+            .peekable();
+        if media_ranges.peek().is_none() || accepts_json(media_ranges) {
+            return Ok(AcceptsJson);
+        }
+        Err((StatusCode::NOT_ACCEPTABLE, format!("only {JSON_UTF8} is available")).into_response())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SaleorAppExtension {
     pub label: String,
@@ -53,40 +161,68 @@ pub struct SaleorAppExtension {
     pub target: SaleorAppExtensionTarget,
     pub permissions: Vec<SaleorAppPermission>,
     pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<SaleorAppExtensionOptions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SaleorAppExtensionOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub widget_target: Option<SaleorWidgetTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_tab_target: Option<SaleorNewTabTarget>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SaleorWidgetTarget {
+    pub method: SaleorWidgetMethod,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SaleorNewTabTarget {
+    pub method: SaleorNewTabMethod,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SaleorWebhookManifest {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub async_events: Option<Vec<SaleorAsyncWebhookEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sync_events: Option<Vec<SaleorSyncWebhookEvent>>,
     pub query: String,
     pub target_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub is_active: Option<bool>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SaleorBrand {
     pub logo: SaleorLogo,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SaleorLogo {
     pub default: String,
 }
 
 pub struct SaleorRegisterRequest {
-    pub auth_token: String,
+    pub auth_token: SecretString,
     pub saleor_domain: String,
     pub saleor_api_url: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct SaleorAuthToken {
-    auth_token: String,
+/// Body of the register request Saleor sends when the app is installed.
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct SaleorAuthToken {
+    #[schema(value_type = String)]
+    pub auth_token: SecretString,
 }
 
 pub struct ExtractRegisterRequest(pub SaleorRegisterRequest);
@@ -101,21 +237,23 @@ where
     async fn from_request(req: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
         let saleor_domain = req
             .headers()
-            .get("saleor-domain")
-            .map(|h| h.to_str().unwrap().to_string())
-            .ok_or((StatusCode::BAD_REQUEST, "missing saleor-domain header").into_response())?;
-        let saleor_api_url = req
-            .headers()
-            .get("saleor-api-url")
-            .map(|h| h.to_str().unwrap().to_string())
-            .ok_or((StatusCode::BAD_REQUEST, "missing saleor-api-url header").into_response())?;
+            .get(SALEOR_DOMAIN_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string)
+            .ok_or((StatusCode::BAD_REQUEST, "missing or invalid saleor-domain header").into_response())?;
+        // older Saleor versions only send the domain, the tenant resolvers derive the API url from it
+        let saleor_api_url = match req.headers().get(SALEOR_API_URL_HEADER) {
+            Some(h) => h.to_str().ok().map(str::to_string),
+            None => request_tenant(&TenantRequest::from_request(&req)),
+        }
+        .ok_or((StatusCode::BAD_REQUEST, "missing or invalid saleor-api-url header").into_response())?;
 
         let query = Query::<SaleorAuthToken>::try_from_uri(req.uri());
         let auth_token = match query {
             Ok(query) => query.0.auth_token,
             Err(_) => {
-                let body = Json::<SaleorAuthToken>::from_request(req, state).await.map_err(IntoResponse::into_response)?;
-                body.auth_token.to_owned()
+                let body = Json::<SaleorAuthToken>::from_request(req, state).await.map_err(|_| SaleorRegisterResponse::token_missing())?;
+                body.auth_token.clone()
             }
         };
 
@@ -127,7 +265,7 @@ where
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct SaleorRegisterResponse {
     pub success: bool,
     pub error: Option<SaleorRegisterError>,
@@ -141,45 +279,48 @@ impl SaleorRegisterResponse {
         })).into_response()
     }
 
-    pub fn jwks_not_available() -> Response {
-        (StatusCode::UNAUTHORIZED, Json(Self {
+    pub fn error(code: SaleorRegisterErrorCode, message: &str, status_code: StatusCode) -> Response {
+        (status_code, Json(Self {
             success: false,
             error: Some(SaleorRegisterError {
-                code: "JWKS_NOT_AVAILABLE".to_string(),
-                message: "JWKS not available".to_string(),
+                code,
+                message: message.to_string(),
             }),
         })).into_response()
     }
 
+    pub fn token_missing() -> Response {
+        Self::error(SaleorRegisterErrorCode::TokenMissing, "auth token missing", StatusCode::BAD_REQUEST)
+    }
+
+    pub fn saleor_url_prohibited() -> Response {
+        Self::error(SaleorRegisterErrorCode::SaleorUrlProhibited, "Saleor URL prohibited", StatusCode::FORBIDDEN)
+    }
+
+    pub fn jwks_not_available() -> Response {
+        Self::error(SaleorRegisterErrorCode::JwksNotAvailable, "JWKS not available", StatusCode::UNAUTHORIZED)
+    }
+
     pub fn api_url_parsing_failed() -> Response {
-        (StatusCode::BAD_REQUEST, Json(Self {
-            success: false,
-            error: Some(SaleorRegisterError {
-                code: "API_URL_PARSING_FAILED".to_string(),
-                message: "API URL parsing failed".to_string(),
-            }),
-        })).into_response()
+        Self::error(SaleorRegisterErrorCode::ApiUrlParsingFailed, "API URL parsing failed", StatusCode::BAD_REQUEST)
     }
 
+    /// An error with a code of the app, the dashboard shows its message like for [`SaleorRegisterErrorCode::Unknown`].
     pub fn custom(code: &str, message: &str, status_code: StatusCode) -> Response {
-        (status_code, Json(Self {
-            success: false,
-            error: Some(SaleorRegisterError {
-                code: code.to_string(),
-                message: message.to_string(),
-            }),
-        })).into_response()
+        Self::error(SaleorRegisterErrorCode::Custom(code.to_string()), message, status_code)
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct SaleorRegisterError {
-    pub code: String,
+    #[schema(value_type = String, example = "JWKS_NOT_AVAILABLE")]
+    pub code: SaleorRegisterErrorCode,
     pub message: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct SaleorClientAuthenticationRequest {
     pub api_url: String,
-    pub token: String,
+    #[schema(value_type = String)]
+    pub token: SecretString,
 }
@@ -1,37 +1,135 @@
-use std::{sync::Arc, future::Future, pin::Pin, time::{SystemTime, Duration}, ops::Deref};
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock}, future::Future, pin::Pin, ops::Deref};
 
 use async_trait::async_trait;
-use axum::{http::{Request, HeaderMap, HeaderValue, request::Parts}, response::{Response, IntoResponse}, middleware::Next, body::Body, extract::FromRequestParts};
-use jsonwebtoken::{jwk::JwkSet, DecodingKey};
-use reqwest::{StatusCode, header::{HOST, AUTHORIZATION}};
+use axum::{http::{Request, request::Parts}, response::{Response, IntoResponse}, body::Body, extract::FromRequestParts};
+use reqwest::{StatusCode, header::{AUTHORIZATION, WWW_AUTHENTICATE}};
 use serde::{Serialize, Deserialize};
 use tower::{Layer, Service};
 use tower_sessions::Session;
 
-use super::SaleorPermission;
+use crate::{audit::{AuditEvent, AuditEventKind, AuditLog}, base_url::BaseUrlPolicy, http_client::HttpClient, request_id::RequestId, secrets::{self, SecretString}, sessions::{self, TenantSessionExpiry}, telemetry};
 
+use super::{request_tenant, Jwks, JwksCache, JwtValidation, SaleorPermission, TenantRequest, VerifiedDashboardToken};
+
+mod encrypted;
 mod file;
+mod memory;
+mod migrating;
 
+pub use encrypted::{AplKeyring, EncryptedAplStore, Reencryption};
 pub use file::FileAplStore;
+pub use memory::MemoryAplStore;
+pub use migrating::MigratingAplStore;
+
+/// The storage behind the APL failed, requests that need it answer with `503 Service Unavailable`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AplError(pub String);
+
+impl std::fmt::Display for AplError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "apl error: {}", self.0)
+    }
+}
+
+impl std::error::Error for AplError {}
+
+impl From<std::io::Error> for AplError {
+    fn from(e: std::io::Error) -> Self {
+        Self(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AplError {
+    fn from(e: serde_json::Error) -> Self {
+        Self(e.to_string())
+    }
+}
+
+impl IntoResponse for AplError {
+    fn into_response(self) -> Response {
+        tracing::error!("{}", self);
+        (StatusCode::SERVICE_UNAVAILABLE, "installation store unavailable").into_response()
+    }
+}
 
 #[async_trait]
 pub trait AplStore: Send + Sync + 'static {
-    async fn get(&self, apl_id: &AplId) -> Option<AuthData>;
-    async fn set(&self, apl_id: &AplId, auth_data: AuthData);
-    async fn remove(&self, apl_id: &AplId);
+    async fn get(&self, apl_id: &AplId) -> Result<Option<AuthData>, AplError>;
+    async fn set(&self, apl_id: &AplId, auth_data: AuthData) -> Result<(), AplError>;
+    async fn remove(&self, apl_id: &AplId) -> Result<(), AplError>;
+    /// Every installation in the store, used by the installations admin page.
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError>;
+
+    /// Checks whether the underlying storage is reachable, used by the readiness probe.
+    async fn health(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: AplStore + ?Sized> AplStore for Arc<T> {
+    async fn get(&self, apl_id: &AplId) -> Result<Option<AuthData>, AplError> {
+        (**self).get(apl_id).await
+    }
+
+    async fn set(&self, apl_id: &AplId, auth_data: AuthData) -> Result<(), AplError> {
+        (**self).set(apl_id, auth_data).await
+    }
+
+    async fn remove(&self, apl_id: &AplId) -> Result<(), AplError> {
+        (**self).remove(apl_id).await
+    }
+
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        (**self).get_all().await
+    }
+
+    async fn health(&self) -> Result<(), String> {
+        (**self).health().await
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthData {
     pub domain: Option<String>,
-    pub token: String,
+    #[serde(with = "secrets::exposed")]
+    pub token: SecretString,
     pub saleor_api_url: String,
     pub app_id: String,
     pub jwks: Option<String>,
+    /// Unix timestamp of the registration, missing for installations registered before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registered_at: Option<u64>,
+    /// Changes whenever the installation is registered, dashboard sessions of other generations are
+    /// invalidated by the [`SaleorAuthLayer`]
+    #[serde(default)]
+    pub generation: u64,
+    /// Unix timestamp of when Saleor first rejected the token, the installation is unhealthy until
+    /// it registers again, see [`super::AppTokenHealth`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_invalid_since: Option<u64>,
+}
+
+impl AuthData {
+    /// An installation of an app with the default id [`crate::APP_ID`] that wasn't registered by
+    /// Saleor, so without a domain, JWKS or registration time. Apps with `SALEOR_APP_ID` set
+    /// [`Self::app_id`] to [`SaleorApl::app_id`].
+    pub fn new(saleor_api_url: impl Into<String>, token: impl Into<SecretString>) -> Self {
+        Self {
+            domain: None,
+            token: token.into(),
+            saleor_api_url: saleor_api_url.into(),
+            app_id: crate::APP_ID.to_string(),
+            jwks: None,
+            registered_at: None,
+            generation: 0,
+            token_invalid_since: None,
+        }
+    }
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct AplId(String);
 
 impl AplId {
@@ -39,8 +137,19 @@ impl AplId {
         Self(format!("{}:{}", auth_data.app_id, auth_data.saleor_api_url))
     }
 
+    /// The id of the installation at `api_url` of an app with the default id [`crate::APP_ID`],
+    /// apps with `SALEOR_APP_ID` use [`SaleorApl::id`].
     pub fn from_api_url(api_url: &str) -> AplId {
-        Self(format!("{}:{}", crate::APP_ID, api_url))
+        Self::with_app_id(crate::APP_ID, api_url)
+    }
+
+    pub fn with_app_id(app_id: &str, api_url: &str) -> AplId {
+        Self(format!("{}:{}", app_id, api_url))
+    }
+
+    /// The app and API url the id is made of, app ids never contain colons.
+    pub fn parts(&self) -> (&str, &str) {
+        self.0.split_once(':').unwrap_or(("", &self.0))
     }
 }
 
@@ -56,55 +165,174 @@ impl AsRef<str> for AplId {
     }
 }
 
-fn get_base_url(headers: &HeaderMap<HeaderValue>) -> Option<String> {
-    let Some(host) = headers.get(HOST) else {
-        return None;
-    };
-    let forwarded_proto = headers.get("x-forwarded-proto").map(|h| h.to_str().unwrap()).unwrap_or("http");
+/// Claims of a verified dashboard token.
+///
+/// A [`SaleorAuthLayer`] adds them to the request extensions once the token is verified, handlers
+/// behind it can extract them, others are rejected with `500 Internal Server Error`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Claims {
+    pub app: String,
+    pub user_permissions: Vec<SaleorPermission>,
+}
+
+impl Claims {
+    /// Whether the user was granted the permission.
+    pub fn can(&self, permission: SaleorPermission) -> bool {
+        self.user_permissions.contains(&permission)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Claims
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
 
-    Some(format!("{}://{}", forwarded_proto, host.to_str().unwrap()))
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Claims>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "claims not found in request extensions").into_response())
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct Claims {
-    app: String,
-    user_permissions: Vec<SaleorPermission>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyJwtError {
+    /// The token couldn't be verified at all
+    Invalid(String),
+    /// The token is valid, but the user lacks a required permission
+    PermissionDenied(String),
 }
 
-pub fn verify_jwt(jwks: &str, token: &str, required_permissions: &[SaleorPermission]) -> Result<(), String> {
-    let jwks = serde_json::from_str::<'_, JwkSet>(jwks)
-        .map_err(|e| format!("unable to deserialize jwks: {}", e))?;
-    let header = jsonwebtoken::decode_header(token).map_err(|e| format!("unable to decode jwt header: {}", e))?;
+impl std::fmt::Display for VerifyJwtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) | Self::PermissionDenied(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for VerifyJwtError {}
+
+impl IntoResponse for VerifyJwtError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Invalid(e) => (StatusCode::UNAUTHORIZED, e).into_response(),
+            Self::PermissionDenied(e) => (StatusCode::FORBIDDEN, e).into_response(),
+        }
+    }
+}
+
+/// Verifies a dashboard token against the JWKS and returns its claims.
+pub fn verify_jwt(jwks: &str, token: &str, required_permissions: &[SaleorPermission], validation: &JwtValidation) -> Result<Claims, VerifyJwtError> {
+    let jwks = Jwks::parse(jwks).map_err(VerifyJwtError::Invalid)?;
+    verify_jwt_with_jwks(&jwks, token, required_permissions, validation)
+}
+
+/// The `kid` of a dashboard token, which key of the JWKS signed it. Not verified.
+pub fn jwt_kid(token: &str) -> Option<String> {
+    jsonwebtoken::decode_header(token).ok()?.kid
+}
+
+/// Like [`verify_jwt`] with an already parsed JWKS, see [`JwksCache`].
+pub fn verify_jwt_with_jwks(jwks: &Jwks, token: &str, required_permissions: &[SaleorPermission], validation: &JwtValidation) -> Result<Claims, VerifyJwtError> {
+    let invalid = VerifyJwtError::Invalid;
+    validation.check_algorithm(token).map_err(invalid)?;
+    let header = jsonwebtoken::decode_header(token).map_err(|e| invalid(format!("unable to decode jwt header: {}", e)))?;
     let kid = match header.kid {
         Some(kid) => kid,
-        None => return Err("missing kid in jwt header".to_string()),
+        None => return Err(invalid("missing kid in jwt header".to_string())),
     };
-    let jwk = jwks.find(&kid).ok_or_else(|| format!("unable to find jwk with kid {}", kid))?;
-    let validation = jsonwebtoken::Validation::new(header.alg);
-    let Ok(token) = jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_jwk(jwk).map_err(|e| format!("unable to create decoding key from jwk: {}", e))?, &validation) else {
-        return Err("unable to decode jwt".to_string());
+    let key = jwks.decoding_key(Some(&kid)).map_err(invalid)?;
+    let token = match jsonwebtoken::decode::<Claims>(token, &key, &validation.validation(header.alg)) {
+        Ok(token) => token,
+        Err(e) if matches!(e.kind(), jsonwebtoken::errors::ErrorKind::MissingRequiredClaim(_)) => return Err(invalid(format!("invalid jwt: {}", e))),
+        Err(_) => return Err(invalid("unable to decode jwt".to_string())),
     };
     
     if required_permissions.is_empty() {
-        return Ok(());
+        return Ok(token.claims);
     }
 
     if token.claims.user_permissions.is_empty() {
-        return Err("missing user permissions".to_string());
+        return Err(VerifyJwtError::PermissionDenied("missing user permissions".to_string()));
     }
 
     for required_permission in required_permissions {
         if !token.claims.user_permissions.contains(required_permission) {
-            return Err(format!("missing required permission {:?}", required_permission));
+            return Err(VerifyJwtError::PermissionDenied(format!("missing required permission {:?}", required_permission)));
         }
     }
 
-    Ok(())
+    Ok(token.claims)
+}
+
+/// Extracts the tenant like [`request_tenant`], rejecting requests without one.
+///
+/// It isn't authenticated on its own, only use it behind a [`SaleorAuthLayer`].
+pub struct RequestTenant(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestTenant
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        request_tenant(&TenantRequest::from_parts(parts))
+            .map(RequestTenant)
+            .ok_or((StatusCode::BAD_REQUEST, "couldn't determine saleor api url").into_response())
+    }
+}
+
+/// Extracts the [`AuthData`] of the installation the request is made for.
+///
+/// The tenant is named by the request's [`super::TenantResolvers`], by default that also covers
+/// webhooks since Saleor sends the `saleor-api-url` header with them. Requests without a tenant are
+/// rejected with `401 Unauthorized`, tenants missing from the APL with `404 Not Found`. Like
+/// [`RequestTenant`] it only identifies the installation, dashboard requests still need a
+/// [`SaleorAuthLayer`] and webhooks a signature check.
+#[derive(Debug, Clone)]
+pub struct CurrentInstallation(pub AuthData);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentInstallation
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let apl = SaleorApl::from_request_parts(parts, state).await?;
+        let Some(api_url) = request_tenant(&TenantRequest::from_parts(parts)) else {
+            return Err((StatusCode::UNAUTHORIZED, "couldn't determine saleor api url").into_response());
+        };
+
+        let auth_data = apl.get(&apl.id(&api_url)).await.map_err(IntoResponse::into_response)?;
+        telemetry::record_apl_lookup(auth_data.is_some());
+        auth_data
+            .map(CurrentInstallation)
+            .ok_or((StatusCode::NOT_FOUND, "app is not installed").into_response())
+    }
 }
 
+/// The APL of the app along with the id its installations are kept under, so apps built in one
+/// process with different `SALEOR_APP_ID`s don't read each other's entries.
 #[derive(Clone)]
 pub struct SaleorApl {
     inner: Arc<dyn AplStore>,
+    app_id: Arc<str>,
+}
+
+impl SaleorApl {
+    /// `SALEOR_APP_ID` or [`crate::APP_ID`].
+    pub fn app_id(&self) -> &str {
+        &self.app_id
+    }
+
+    /// The id of the installation at `api_url` of this app.
+    pub fn id(&self, api_url: &str) -> AplId {
+        AplId::with_app_id(&self.app_id, api_url)
+    }
 }
 
 impl Deref for SaleorApl {
@@ -130,7 +358,7 @@ where
 #[derive(Clone)]
 pub struct SaleorAplService<S> {
     inner: S,
-    apl_store: Arc<dyn AplStore>,
+    apl: SaleorApl,
 }
 
 impl<S> Service<Request<Body>> for SaleorAplService<S>
@@ -147,14 +375,14 @@ where
     }
 
     fn call(&mut self, mut req: Request<Body>) -> Self::Future {
-        let apl_store = self.apl_store.clone();
+        let apl = self.apl.clone();
         let clone = self.inner.clone();
         let mut inner = std::mem::replace(&mut self.inner, clone);
         Box::pin(async move {
             let extensions = req.extensions_mut();
             let already_set = extensions.get::<SaleorApl>().is_some();
             if !already_set {
-                extensions.insert(SaleorApl { inner: apl_store.clone() });
+                extensions.insert(apl);
             }
 
             let response: Response = inner.call(req).await?;
@@ -165,12 +393,27 @@ where
 
 #[derive(Clone)]
 pub struct SaleorAplLayer {
-    apl_store: Arc<dyn AplStore>,
+    apl: SaleorApl,
 }
 
 impl SaleorAplLayer {
+    /// The APL of an app with the default id [`crate::APP_ID`].
     pub fn new(apl_store: impl AplStore) -> Self {
-        Self { apl_store: Arc::new(apl_store) }
+        Self { apl: SaleorApl { inner: Arc::new(apl_store), app_id: crate::APP_ID.into() } }
+    }
+
+    /// Keeps the installations under `app_id`, like `SALEOR_APP_ID`.
+    pub fn with_app_id(mut self, app_id: &str) -> Self {
+        self.apl.app_id = app_id.into();
+        self
+    }
+
+    pub fn apl_store(&self) -> Arc<dyn AplStore> {
+        self.apl.inner.clone()
+    }
+
+    pub fn apl(&self) -> SaleorApl {
+        self.apl.clone()
     }
 }
 
@@ -180,22 +423,82 @@ impl<S> Layer<S> for SaleorAplLayer {
     fn layer(&self, inner: S) -> Self::Service {
         SaleorAplService {
             inner,
-            apl_store: self.apl_store.clone(),
+            apl: self.apl.clone(),
         }
     }
 }
 
+/// Installations allowed through a [`SaleorAuthLayer`], shared so they can change while the app
+/// is running, see [`crate::reload`].
+#[derive(Clone, Default)]
+pub struct TenantAllowlist(Arc<RwLock<Arc<[String]>>>);
+
+impl TenantAllowlist {
+    pub fn new(saleor_api_urls: &[String]) -> Self {
+        Self(Arc::new(RwLock::new(saleor_api_urls.into())))
+    }
+
+    pub fn set(&self, saleor_api_urls: &[String]) {
+        *self.0.write().unwrap() = saleor_api_urls.into();
+    }
+
+    pub fn contains(&self, saleor_api_url: &str) -> bool {
+        self.0.read().unwrap().iter().any(|allowed| allowed == saleor_api_url)
+    }
+}
+
+/// Whether a request reached a [`SaleorAuthMiddleware`] without a session, which is only logged once.
+static MISSING_SESSION_WARNED: AtomicBool = AtomicBool::new(false);
+
 #[derive(Clone)]
 pub struct SaleorAuthLayer {
-    required_permissions: Vec<SaleorPermission>,
+    required_permissions: Arc<[SaleorPermission]>,
+    session_expiry: TenantSessionExpiry,
+    allowed_tenants: Option<TenantAllowlist>,
+    jwt_validation: Option<JwtValidation>,
+    bearer_only: bool,
 }
 
 impl SaleorAuthLayer {
     pub fn with_permissions(permissions: &[SaleorPermission]) -> Self {
         Self {
-            required_permissions: permissions.to_vec(),
+            required_permissions: permissions.into(),
+            session_expiry: TenantSessionExpiry::default(),
+            allowed_tenants: None,
+            jwt_validation: None,
+            bearer_only: false,
         }
     }
+
+    /// Only accepts tokens sent as `Authorization: Bearer`, never looking at the session, for APIs
+    /// called by CLIs and other services and routers served without a session layer.
+    pub fn bearer_only(mut self) -> Self {
+        self.bearer_only = true;
+        self
+    }
+
+    /// Validates tokens with `jwt_validation` instead of the one in the request extensions, which
+    /// [`crate::app::build`] adds from `JWT_*` settings.
+    pub fn with_jwt_validation(mut self, jwt_validation: JwtValidation) -> Self {
+        self.jwt_validation = Some(jwt_validation);
+        self
+    }
+
+    pub fn with_session_expiry(mut self, session_expiry: TenantSessionExpiry) -> Self {
+        self.session_expiry = session_expiry;
+        self
+    }
+
+    /// Only accepts requests from the dashboards of the given installations, e.g. for operator pages.
+    pub fn with_allowed_tenants(self, saleor_api_urls: &[String]) -> Self {
+        self.with_tenant_allowlist(TenantAllowlist::new(saleor_api_urls))
+    }
+
+    /// Like [`Self::with_allowed_tenants`], with installations that can be changed later.
+    pub fn with_tenant_allowlist(mut self, allowlist: TenantAllowlist) -> Self {
+        self.allowed_tenants = Some(allowlist);
+        self
+    }
 }
 
 impl<S> Layer<S> for SaleorAuthLayer {
@@ -205,6 +508,10 @@ impl<S> Layer<S> for SaleorAuthLayer {
         SaleorAuthMiddleware {
             inner,
             required_permissions: self.required_permissions.clone(),
+            session_expiry: self.session_expiry,
+            allowed_tenants: self.allowed_tenants.clone(),
+            jwt_validation: self.jwt_validation.clone(),
+            bearer_only: self.bearer_only,
         }
     }
 }
@@ -212,7 +519,11 @@ impl<S> Layer<S> for SaleorAuthLayer {
 #[derive(Clone)]
 pub struct SaleorAuthMiddleware<S> {
     inner: S,
-    required_permissions: Vec<SaleorPermission>,
+    required_permissions: Arc<[SaleorPermission]>,
+    session_expiry: TenantSessionExpiry,
+    allowed_tenants: Option<TenantAllowlist>,
+    jwt_validation: Option<JwtValidation>,
+    bearer_only: bool,
 }
 
 impl<S> Service<Request<Body>> for SaleorAuthMiddleware<S>
@@ -228,67 +539,101 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, request: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
         let required_permissions = self.required_permissions.clone();
+        let session_expiry = self.session_expiry;
+        let allowed_tenants = self.allowed_tenants.clone();
+        let jwt_validation = self.jwt_validation.clone();
+        let bearer_only = self.bearer_only;
         let clone = self.inner.clone();
         let mut inner = std::mem::replace(&mut self.inner, clone);
         Box::pin(async move {
-            let apl_store = request
-                .extensions()
-                .get::<SaleorApl>()
-                .cloned()
-                .expect("apl store not found in request extensions");
-            let session = request
-                .extensions()
-                .get::<Session>()
-                .cloned()
-                .expect("tower-session not found in request extensions");
-
-            let Some(_) = get_base_url(request.headers()) else {
-                return Ok((StatusCode::BAD_REQUEST, "missing host header").into_response());
+            let Some(apl_store) = request.extensions().get::<SaleorApl>().cloned() else {
+                return Ok((StatusCode::INTERNAL_SERVER_ERROR, "apl store not found in request extensions").into_response());
             };
+            let session = match bearer_only {
+                true => None,
+                false => request.extensions().get::<Session>().cloned(),
+            };
+            if session.is_none() && !bearer_only && !MISSING_SESSION_WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!("no session layer in front of the auth middleware, only accepting bearer tokens");
+            }
+            // routers built without [`crate::app::build`] don't share a client or cache
+            let client = request.extensions().get::<HttpClient>().cloned().unwrap_or_default();
+            let jwks_cache = request.extensions().get::<JwksCache>().cloned().unwrap_or_default();
+            let jwt_validation = jwt_validation.or_else(|| request.extensions().get::<JwtValidation>().cloned()).unwrap_or_default();
+
+            if let Err(e) = BaseUrlPolicy::base_url_of(request.headers(), request.extensions()) {
+                return Ok(e.into_response());
+            }
         
-            let api_url = match request.headers().get("saleor-api-url") {
-                Some(api_url) => api_url.to_str().unwrap().to_string(),
-                None => {
-                    let Ok(Some(api_url)) = session.get::<String>("saleor_api_url") else {
-                        return Ok((StatusCode::BAD_REQUEST, "couldn't determine saleor api url").into_response());
-                    };
-        
-                    api_url
-                }
+            let Some(api_url) = request_tenant(&TenantRequest::from_request(&request)) else {
+                return Ok((StatusCode::BAD_REQUEST, "couldn't determine saleor api url").into_response());
             };
 
-            let jwks = match apl_store.get(&AplId::from_api_url(&api_url)).await {
-                Some(auth_data) => {
-                    match auth_data.jwks {
-                        Some(jwks) => jwks,
-                        None => {
-                            let jwks_url = format!("{}/.well-known/jwks.json", &api_url);
-                            reqwest::get(&jwks_url).await.unwrap().text().await.unwrap()
-                        }
-                    }
-                },
-                None => {
-                    let jwks_url = format!("{}/.well-known/jwks.json", &api_url);
-                    reqwest::get(&jwks_url).await.unwrap().text().await.unwrap()
+            telemetry::record_tenant(&api_url, apl_store.app_id());
+
+            if let Some(allowed_tenants) = &allowed_tenants {
+                if !allowed_tenants.contains(&api_url) {
+                    return Ok((StatusCode::FORBIDDEN, "installation is not allowed to access this resource").into_response());
                 }
+            }
+
+            let auth_data = match apl_store.get(&apl_store.id(&api_url)).await {
+                Ok(auth_data) => auth_data,
+                Err(e) => return Ok(e.into_response()),
             };
-        
+            telemetry::record_apl_lookup(auth_data.is_some());
+            // the api url can be anything, so nothing is fetched for it unless it's installed
+            let Some(auth_data) = auth_data else {
+                return Ok((StatusCode::UNAUTHORIZED, "unknown installation").into_response());
+            };
+            let generation = Some(auth_data.generation);
+
             let token = match request.headers().get(AUTHORIZATION) {
-                Some(token) => token.to_str().unwrap().replace("Bearer ", ""),
+                Some(token) => match token.to_str().ok().and_then(|token| token.strip_prefix("Bearer ")) {
+                    Some(token) => SecretString::from(token),
+                    None => return Ok((StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")], "malformed bearer token").into_response()),
+                },
                 None => {
-                    let Ok(Some(token)) = session.get::<String>("token") else {
+                    let Some(session) = &session else {
+                        return Ok((StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")], "missing bearer token").into_response());
+                    };
+                    // the installation was removed or registered again since the session authenticated
+                    if sessions::is_outdated(session, &api_url, generation) {
+                        sessions::logout(session, Some(&api_url));
+                        return Ok((StatusCode::UNAUTHORIZED, "installation changed, authenticate again").into_response());
+                    }
+                    let Some(token) = sessions::dashboard_token(session, &api_url, &session_expiry) else {
                         return Ok((StatusCode::BAD_REQUEST, "couldn't determine token").into_response());
                     };
         
                     token
                 }
             };
+
+            let kid = jwt_kid(token.expose());
+            let jwks = match jwks_cache.jwks_for(&client, &**apl_store, &auth_data, kid.as_deref()).await {
+                Ok(jwks) => jwks,
+                Err(e) => return Ok(e.into_response()),
+            };
         
-            if let Err(e) = verify_jwt(&jwks, &token, &required_permissions) {
-                return Ok((StatusCode::UNAUTHORIZED, e).into_response());
-            }
+            let claims = match verify_jwt_with_jwks(&jwks, token.expose(), &required_permissions, &jwt_validation) {
+                Ok(claims) => claims,
+                Err(e) => {
+                    if let Some(audit_log) = request.extensions().get::<AuditLog>() {
+                        let kind = match e {
+                            VerifyJwtError::Invalid(_) => AuditEventKind::JwtVerificationFailed,
+                            VerifyJwtError::PermissionDenied(_) => AuditEventKind::PermissionDenied,
+                        };
+                        let request_id = request.extensions().get::<RequestId>().map(|id| id.0.as_str());
+                        audit_log.record(AuditEvent::new(&api_url, kind).with_detail(e.to_string()).with_request_id(request_id));
+                    }
+                    return Ok(e.into_response());
+                }
+            };
+            request.extensions_mut().insert(claims);
+            request.extensions_mut().insert(VerifiedDashboardToken { saleor_api_url: api_url, token });
 
             let response: Response = inner.call(request).await?;
             Ok(response)
@@ -0,0 +1,217 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::{aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN}, rand::{SecureRandom, SystemRandom}};
+use tracing::warn;
+
+use super::{AplError, AplStore, AplId, AuthData};
+
+/// Prefix of encrypted tokens, followed by the key version, the wrapped data key and the token.
+const ENCRYPTED_PREFIX: &str = "enc:v";
+
+/// The keys of an [`EncryptedAplStore`] by version, configured by `APL_ENCRYPTION_KEY`.
+///
+/// New tokens are encrypted under the newest (highest) version, older versions are only kept to
+/// read the tokens encrypted before a rotation.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AplKeyring {
+    keys: BTreeMap<u32, [u8; 32]>,
+}
+
+impl AplKeyring {
+    /// Parses comma separated `{version}:{key}` entries, like `2:new-key,1:old-key`. A key without a
+    /// version is version 1. Keys are 32 bytes, encoded as url safe base64 without padding.
+    pub fn parse(value: &str) -> Result<Self, AplError> {
+        let mut keys = BTreeMap::new();
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (version, key) = match entry.split_once(':') {
+                Some((version, key)) => (version.parse().map_err(|_| AplError(format!("invalid key version {version:?}")))?, key),
+                None => (1, entry),
+            };
+            let key = URL_SAFE_NO_PAD
+                .decode(key)
+                .ok()
+                .and_then(|key| <[u8; 32]>::try_from(key).ok())
+                .ok_or_else(|| AplError(format!("key version {version} has to be 32 bytes of url safe base64")))?;
+            if keys.insert(version, key).is_some() {
+                return Err(AplError(format!("key version {version} is configured twice")));
+            }
+        }
+        match keys.is_empty() {
+            true => Err(AplError("no encryption keys configured".to_string())),
+            false => Ok(Self { keys }),
+        }
+    }
+
+    /// Generates a fresh key to add to `APL_ENCRYPTION_KEY` as a new version.
+    pub fn generate_key() -> String {
+        let mut key = [0u8; 32];
+        SystemRandom::new().fill(&mut key).expect("unable to generate random key");
+        URL_SAFE_NO_PAD.encode(key)
+    }
+
+    pub fn newest_version(&self) -> u32 {
+        *self.keys.keys().next_back().expect("keyrings have at least one key")
+    }
+
+    fn key(&self, version: u32) -> Option<LessSafeKey> {
+        let key = self.keys.get(&version)?;
+        Some(LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).ok()?))
+    }
+}
+
+impl std::fmt::Debug for AplKeyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AplKeyring").field("versions", &self.keys.keys().collect::<Vec<_>>()).finish()
+    }
+}
+
+fn seal(key: &LessSafeKey, aad: &str, mut data: Vec<u8>) -> Result<Vec<u8>, AplError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(|_| AplError("unable to generate nonce".to_string()))?;
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut data)
+        .map_err(|_| AplError("unable to encrypt".to_string()))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(data);
+    Ok(sealed)
+}
+
+fn open(key: &LessSafeKey, aad: &str, mut sealed: Vec<u8>) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let mut data = sealed.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&sealed).ok()?;
+    let len = key.open_in_place(nonce, Aad::from(aad), &mut data).ok()?.len();
+    data.truncate(len);
+    Some(data)
+}
+
+/// What [`EncryptedAplStore::reencrypt`] did.
+#[derive(Debug, Default)]
+pub struct Reencryption {
+    pub reencrypted: usize,
+    /// Entries that couldn't be decrypted, like ones of a key version that was dropped
+    pub failed: Vec<AplError>,
+}
+
+/// Encrypts the tokens of the installations in another [`AplStore`].
+///
+/// Every token gets its own data key (envelope encryption), which is stored next to it wrapped by
+/// the newest key of the [`AplKeyring`]. Tokens are bound to their [`AplId`], the app and the
+/// installation, so they can't be swapped between entries. Rotating means adding a new key version: entries are still read with
+/// the old one, [`Self::reencrypt`] moves them to the new one so the old key can be dropped.
+/// Unencrypted tokens of an existing APL are read as they are and encrypted on their next write.
+/// [`AplStore::get_all`] leaves out the entries it can't decrypt, so one of them doesn't hide the
+/// others.
+#[derive(Clone)]
+pub struct EncryptedAplStore<S> {
+    inner: S,
+    keyring: Arc<AplKeyring>,
+}
+
+impl<S: AplStore> EncryptedAplStore<S> {
+    pub fn new(inner: S, keyring: AplKeyring) -> Self {
+        Self { inner, keyring: Arc::new(keyring) }
+    }
+
+    fn encrypt(&self, auth_data: &AuthData) -> Result<String, AplError> {
+        let version = self.keyring.newest_version();
+        let key = self.keyring.key(version).ok_or_else(|| AplError(format!("invalid key version {version}")))?;
+        let mut data_key = [0u8; 32];
+        SystemRandom::new().fill(&mut data_key).map_err(|_| AplError("unable to generate data key".to_string()))?;
+        let token_key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &data_key).map_err(|_| AplError("invalid data key".to_string()))?);
+
+        let aad = AplId::from_auth_data(auth_data);
+        let wrapped_key = seal(&key, aad.as_ref(), data_key.to_vec())?;
+        let token = seal(&token_key, aad.as_ref(), auth_data.token.expose().as_bytes().to_vec())?;
+        Ok(format!("{ENCRYPTED_PREFIX}{version}:{}:{}", URL_SAFE_NO_PAD.encode(wrapped_key), URL_SAFE_NO_PAD.encode(token)))
+    }
+
+    fn decrypt(&self, mut auth_data: AuthData) -> Result<AuthData, AplError> {
+        let Some(encrypted) = auth_data.token.expose().strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(auth_data);
+        };
+        let invalid = || AplError(format!("unable to decrypt the token of {}", auth_data.saleor_api_url));
+        let mut parts = encrypted.splitn(3, ':');
+        let (Some(version), Some(wrapped_key), Some(token)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let version: u32 = version.parse().map_err(|_| invalid())?;
+        let key = self
+            .keyring
+            .key(version)
+            .ok_or_else(|| AplError(format!("the token of {} is encrypted with key version {version}, which isn't configured", auth_data.saleor_api_url)))?;
+
+        let aad = AplId::from_auth_data(&auth_data);
+        let aad = aad.as_ref();
+        let data_key = URL_SAFE_NO_PAD.decode(wrapped_key).ok().and_then(|wrapped_key| open(&key, aad, wrapped_key)).ok_or_else(invalid)?;
+        let token_key = UnboundKey::new(&AES_256_GCM, &data_key).map(LessSafeKey::new).map_err(|_| invalid())?;
+        let token = URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|token| open(&token_key, aad, token))
+            .and_then(|token| String::from_utf8(token).ok())
+            .ok_or_else(invalid)?;
+        auth_data.token = token.into();
+        Ok(auth_data)
+    }
+
+    /// The key version the stored token is encrypted with, `None` if it isn't encrypted.
+    fn key_version(stored: &AuthData) -> Option<u32> {
+        stored.token.expose().strip_prefix(ENCRYPTED_PREFIX)?.split(':').next()?.parse().ok()
+    }
+
+    /// Encrypts every entry that isn't encrypted with the newest key yet again. Entries that can't be
+    /// decrypted keep their encryption and are reported in [`Reencryption::failed`].
+    pub async fn reencrypt(&self) -> Result<Reencryption, AplError> {
+        let newest = self.keyring.newest_version();
+        let mut reencryption = Reencryption::default();
+        for stored in self.inner.get_all().await? {
+            if Self::key_version(&stored) == Some(newest) {
+                continue;
+            }
+            let apl_id = AplId::from_auth_data(&stored);
+            match self.decrypt(stored) {
+                Ok(auth_data) => {
+                    self.set(&apl_id, auth_data).await?;
+                    reencryption.reencrypted += 1;
+                }
+                Err(e) => reencryption.failed.push(e),
+            }
+        }
+        Ok(reencryption)
+    }
+}
+
+#[async_trait]
+impl<S: AplStore> AplStore for EncryptedAplStore<S> {
+    async fn get(&self, apl_id: &AplId) -> Result<Option<AuthData>, AplError> {
+        self.inner.get(apl_id).await?.map(|auth_data| self.decrypt(auth_data)).transpose()
+    }
+
+    async fn set(&self, apl_id: &AplId, mut auth_data: AuthData) -> Result<(), AplError> {
+        auth_data.token = self.encrypt(&auth_data)?.into();
+        self.inner.set(apl_id, auth_data).await
+    }
+
+    async fn remove(&self, apl_id: &AplId) -> Result<(), AplError> {
+        self.inner.remove(apl_id).await
+    }
+
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        let decrypted = self.inner.get_all().await?.into_iter().filter_map(|auth_data| match self.decrypt(auth_data) {
+            Ok(auth_data) => Some(auth_data),
+            Err(e) => {
+                warn!("leaving out an installation: {}", e);
+                None
+            }
+        });
+        Ok(decrypted.collect())
+    }
+
+    async fn health(&self) -> Result<(), String> {
+        self.inner.health().await
+    }
+}
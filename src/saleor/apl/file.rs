@@ -1,26 +1,71 @@
 use async_trait::async_trait;
-use tokio::io::AsyncWriteExt;
+use tokio::{io::AsyncWriteExt, sync::Mutex};
 
-use super::{AplStore, AplId, AuthData};
+use super::{AplError, AplStore, AplId, AuthData};
 
+const AUTH_FILE: &str = ".saleor-app-auth.json";
+
+/// Serializes writes, every `FileAplStore` of the process shares the same file.
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Keeps a single installation in `.saleor-app-auth.json` in the working directory.
+///
+/// Writes go to a temporary file that replaces the old one once it's complete, so a crash mid-write
+/// leaves the previous installation intact instead of a truncated file.
+#[derive(Clone)]
 pub struct FileAplStore;
 
+impl FileAplStore {
+    async fn read(&self) -> Result<Option<AuthData>, AplError> {
+        let file = match tokio::fs::read_to_string(AUTH_FILE).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(serde_json::from_str(&file)?))
+    }
+}
+
 #[async_trait]
 impl AplStore for FileAplStore {
-    async fn get(&self, apl_id: &AplId) -> Option<AuthData> {
-        let file = tokio::fs::read_to_string(".saleor-app-auth.json").await.unwrap();
-        let auth_data: AuthData = serde_json::from_str(&file).unwrap();
+    async fn get(&self, _apl_id: &AplId) -> Result<Option<AuthData>, AplError> {
+        self.read().await
+    }
+
+    async fn set(&self, _apl_id: &AplId, auth_data: AuthData) -> Result<(), AplError> {
+        let json = serde_json::to_string(&auth_data)?;
+        let _guard = WRITE_LOCK.lock().await;
+
+        // the process id keeps other processes (like the CLI next to the server) from sharing the file
+        let tmp_path = format!("{}.{}.tmp", AUTH_FILE, std::process::id());
+        let result = async {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            file.write_all(json.as_bytes()).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp_path, AUTH_FILE).await
+        }
+        .await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+        Ok(result?)
+    }
 
-        Some(auth_data)
+    async fn remove(&self, _apl_id: &AplId) -> Result<(), AplError> {
+        let _guard = WRITE_LOCK.lock().await;
+        match tokio::fs::remove_file(AUTH_FILE).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
     }
 
-    async fn set(&self, apl_id: &AplId, auth_data: AuthData) {
-        let json = serde_json::to_string(&auth_data).unwrap();
-        let mut file = tokio::fs::File::create(".saleor-app-auth.json").await.unwrap();
-        file.write_all(json.as_bytes()).await.unwrap();
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        // the file only ever holds a single installation
+        Ok(self.read().await?.into_iter().collect())
     }
 
-    async fn remove(&self, apl_id: &AplId) {
-        tokio::fs::remove_file(".saleor-app-auth.json").await.unwrap();
+    async fn health(&self) -> Result<(), String> {
+        self.read().await.map(|_| ()).map_err(|e| e.to_string())
     }
 }
@@ -0,0 +1,38 @@
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+
+use async_trait::async_trait;
+
+use super::{AplError, AplStore, AplId, AuthData};
+
+/// Keeps installations in memory, they are lost on restart. Clones share the same installations.
+#[derive(Clone, Default)]
+pub struct MemoryAplStore {
+    installations: Arc<RwLock<HashMap<String, AuthData>>>,
+}
+
+impl MemoryAplStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AplStore for MemoryAplStore {
+    async fn get(&self, apl_id: &AplId) -> Result<Option<AuthData>, AplError> {
+        Ok(self.installations.read().unwrap().get(apl_id.as_ref()).cloned())
+    }
+
+    async fn set(&self, apl_id: &AplId, auth_data: AuthData) -> Result<(), AplError> {
+        self.installations.write().unwrap().insert(apl_id.as_ref().to_string(), auth_data);
+        Ok(())
+    }
+
+    async fn remove(&self, apl_id: &AplId) -> Result<(), AplError> {
+        self.installations.write().unwrap().remove(apl_id.as_ref());
+        Ok(())
+    }
+
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        Ok(self.installations.read().unwrap().values().cloned().collect())
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::config::AppConfig;
+
+use super::{AplError, AplStore, AplId, AuthData};
+
+/// Finds the installations stored under previous ids of the app in another [`AplStore`].
+///
+/// Installations are keyed by the id of the app, so changing `SALEOR_APP_ID` or renaming the
+/// package would orphan every one of them. Entries only found under a previous id are moved to the
+/// current one the first time they're read, [`Self::migrate`] moves all of them at once.
+#[derive(Clone)]
+pub struct MigratingAplStore<S> {
+    inner: S,
+    app_id: Arc<str>,
+    previous_app_ids: Arc<[String]>,
+}
+
+impl<S: AplStore> MigratingAplStore<S> {
+    /// Moves installations of `previous_app_ids` to `app_id`.
+    pub fn new(inner: S, app_id: &str, previous_app_ids: &[String]) -> Self {
+        Self { inner, app_id: app_id.into(), previous_app_ids: previous_app_ids.into() }
+    }
+
+    /// Migrates from `SALEOR_APP_PREVIOUS_IDS` and the package name, if the app id isn't it.
+    pub fn from_config(inner: S, config: &AppConfig) -> Self {
+        let previous_app_ids: Vec<String> = config
+            .previous_app_ids
+            .iter()
+            .map(String::as_str)
+            .chain([crate::APP_ID])
+            .filter(|previous| *previous != config.app_id)
+            .map(str::to_string)
+            .collect();
+        Self::new(inner, &config.app_id, &previous_app_ids)
+    }
+
+    fn is_previous(&self, app_id: &str) -> bool {
+        app_id != &*self.app_id && self.previous_app_ids.iter().any(|previous| previous == app_id)
+    }
+
+    /// Moves every installation stored under a previous id to the current one, returns how many.
+    pub async fn migrate(&self) -> Result<usize, AplError> {
+        let mut migrated = 0;
+        if self.previous_app_ids.is_empty() {
+            return Ok(migrated);
+        }
+        for stored in self.inner.get_all().await?.into_iter().filter(|stored| self.is_previous(&stored.app_id)) {
+            let apl_id = AplId::with_app_id(&self.app_id, &stored.saleor_api_url);
+            if self.inner.get(&apl_id).await?.is_none() && self.get(&apl_id).await?.is_some() {
+                migrated += 1;
+            }
+        }
+        Ok(migrated)
+    }
+}
+
+#[async_trait]
+impl<S: AplStore> AplStore for MigratingAplStore<S> {
+    async fn get(&self, apl_id: &AplId) -> Result<Option<AuthData>, AplError> {
+        if let Some(auth_data) = self.inner.get(apl_id).await? {
+            return Ok(Some(auth_data));
+        }
+        let (app_id, api_url) = apl_id.parts();
+        for previous in self.previous_app_ids.iter().filter(|previous| *previous != app_id) {
+            let previous_id = AplId::with_app_id(previous, api_url);
+            let Some(mut auth_data) = self.inner.get(&previous_id).await? else {
+                continue;
+            };
+            auth_data.app_id = app_id.to_string();
+            self.inner.set(apl_id, auth_data.clone()).await?;
+            self.inner.remove(&previous_id).await?;
+            tracing::info!(saleor_api_url = api_url, from = previous, to = app_id, "moved installation to the new app id");
+            return Ok(Some(auth_data));
+        }
+        Ok(None)
+    }
+
+    async fn set(&self, apl_id: &AplId, auth_data: AuthData) -> Result<(), AplError> {
+        self.inner.set(apl_id, auth_data).await
+    }
+
+    /// Removes the installation under previous ids too, it would be found again otherwise.
+    async fn remove(&self, apl_id: &AplId) -> Result<(), AplError> {
+        let (app_id, api_url) = apl_id.parts();
+        for previous in self.previous_app_ids.iter().filter(|previous| *previous != app_id) {
+            self.inner.remove(&AplId::with_app_id(previous, api_url)).await?;
+        }
+        self.inner.remove(apl_id).await
+    }
+
+    /// Lists installations of previous ids under the current one, which they're moved to once read.
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        let (previous, mut installations): (Vec<_>, Vec<_>) = self.inner.get_all().await?.into_iter().partition(|auth_data| self.is_previous(&auth_data.app_id));
+        for mut auth_data in previous {
+            if !installations.iter().any(|installation| installation.saleor_api_url == auth_data.saleor_api_url) {
+                auth_data.app_id = self.app_id.to_string();
+                installations.push(auth_data);
+            }
+        }
+        Ok(installations)
+    }
+
+    async fn health(&self) -> Result<(), String> {
+        self.inner.health().await
+    }
+}
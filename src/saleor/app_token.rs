@@ -0,0 +1,107 @@
+//! Installations whose app token Saleor stopped accepting.
+//!
+//! When an app is removed and installed again without registering, or its token is revoked, every
+//! call with the stored token fails. Calls made through the app token notice, see
+//! [`GraphqlError::AppTokenInvalid`](super::GraphqlError): the installation is marked with
+//! `token_invalid_since` in the APL, so operators see it on the installations page, and the
+//! [`OnAuthInvalid`] hook of the app runs once. A later registration replaces the entry, and with it
+//! the mark. Every app has its own, see [`crate::http_client::HttpClient::app_tokens`].
+
+use std::{sync::{Arc, RwLock}, time::{SystemTime, UNIX_EPOCH}};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+
+use super::{AplId, AplStore, AuthData};
+
+/// Runs when Saleor rejects the app token of an installation for the first time, like alerting
+/// the operators or asking the merchant to reinstall.
+#[async_trait]
+pub trait OnAuthInvalid: Send + Sync + 'static {
+    async fn on_auth_invalid(&self, auth_data: &AuthData);
+}
+
+/// The APL rejected tokens are marked in and the hook of the app. [`crate::app::build`] sets the
+/// APL, [`Self::on_auth_invalid`] the hook. Clones share them.
+#[derive(Clone, Default)]
+pub struct AppTokenHealth {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    apl: RwLock<Option<Arc<dyn AplStore>>>,
+    hook: RwLock<Option<Arc<dyn OnAuthInvalid>>>,
+    /// Held by registrations and marks while they read and write an entry
+    writes: Mutex<()>,
+}
+
+impl AppTokenHealth {
+    pub fn set_apl(&self, apl_store: Arc<dyn AplStore>) {
+        *self.inner.apl.write().unwrap() = Some(apl_store);
+    }
+
+    /// Runs `hook` for installations whose token gets rejected from now on.
+    pub fn on_auth_invalid(&self, hook: impl OnAuthInvalid) {
+        *self.inner.hook.write().unwrap() = Some(Arc::new(hook));
+    }
+
+    /// Keeps marks from writing entries until the guard is dropped, registrations hold it so a mark
+    /// never overwrites the token they stored.
+    pub(crate) async fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.inner.writes.lock().await
+    }
+
+    /// Handles Saleor rejecting the token of `auth_data`. Returns the entry of the installation if it
+    /// registered a new token since, otherwise the installation is marked as unhealthy.
+    pub(crate) async fn rejected(&self, auth_data: &AuthData) -> Option<AuthData> {
+        let apl = self.inner.apl.read().unwrap().clone()?;
+        let apl_id = AplId::from_auth_data(auth_data);
+        let _writes = self.lock_writes().await;
+        let stored = match apl.get(&apl_id).await {
+            Ok(stored) => stored?,
+            Err(e) => {
+                tracing::warn!(saleor_api_url = %auth_data.saleor_api_url, "unable to look up installation with a rejected token: {}", e);
+                return None;
+            }
+        };
+        if stored.token != auth_data.token || stored.generation != auth_data.generation {
+            return Some(stored);
+        }
+        if stored.token_invalid_since.is_some() {
+            return None;
+        }
+
+        tracing::warn!(saleor_api_url = %auth_data.saleor_api_url, "Saleor rejected the app token, marking the installation as unhealthy");
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let marked = AuthData { token_invalid_since: Some(now), ..stored };
+        if let Err(e) = apl.set(&apl_id, marked.clone()).await {
+            tracing::warn!(saleor_api_url = %auth_data.saleor_api_url, "unable to mark installation as unhealthy: {}", e);
+        }
+        let hook = self.inner.hook.read().unwrap().clone();
+        if let Some(hook) = hook {
+            // the call that noticed fails right away, the hook may take its time
+            tokio::spawn(async move { hook.on_auth_invalid(&marked).await });
+        }
+        None
+    }
+
+    /// Clears the mark of an installation whose token Saleor accepts again.
+    pub(crate) async fn accepted(&self, auth_data: &AuthData) {
+        let Some(apl) = self.inner.apl.read().unwrap().clone() else {
+            return;
+        };
+        let apl_id = AplId::from_auth_data(auth_data);
+        let _writes = self.lock_writes().await;
+        let Ok(Some(stored)) = apl.get(&apl_id).await else {
+            return;
+        };
+        if stored.token == auth_data.token && stored.generation == auth_data.generation && stored.token_invalid_since.is_some() {
+            tracing::info!(saleor_api_url = %auth_data.saleor_api_url, "Saleor accepts the app token again");
+            if let Err(e) = apl.set(&apl_id, AuthData { token_invalid_since: None, ..stored }).await {
+                tracing::warn!(saleor_api_url = %auth_data.saleor_api_url, "unable to mark installation as healthy: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Tagging many products at once, the "more actions" of the product list.
+//!
+//! `POST /api/products/bulk-metadata` takes the ids of the products and the metadata to set on
+//! them, answering right away with the name of the job. The job sets the metadata with
+//! `updateMetadata` mutations, [`CHUNK_SIZE`] products per request, publishes its progress as
+//! [`AppEvent::progress`] (`saleorAppBridge.onJobProgress(job, listener)`) and ends with a
+//! [`BULK_METADATA_EVENT`] carrying the [`BulkMetadataReport`] and a toast. The route is part of
+//! the `/api` router and needs `MANAGE_PRODUCTS`.
+
+use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{events::{AppEvent, EventHub}, http_client::HttpClient, templating::{Localizer, NotificationStatus}};
+
+use super::{graphql::run_app_graphql, AuthData, CurrentInstallation};
+
+pub const BULK_METADATA_PATH: &str = "/products/bulk-metadata";
+
+/// Name of the event a job ends with, its data is the [`BulkMetadataReport`].
+pub const BULK_METADATA_EVENT: &str = "bulk-metadata";
+
+/// Products updated by a single request to Saleor
+pub const CHUNK_SIZE: usize = 25;
+/// The most products a job takes
+pub const MAX_PRODUCTS: usize = 1000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct MetadataItem {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkMetadataRequest {
+    /// At most 1000 `Product` global ids, like the `productIds` the dashboard hands the extension
+    pub product_ids: Vec<String>,
+    /// Set on every product, existing keys are overwritten
+    pub metadata: Vec<MetadataItem>,
+}
+
+impl BulkMetadataRequest {
+    fn validate(&self) -> Result<(), String> {
+        if self.product_ids.is_empty() {
+            return Err("no products given".to_string());
+        }
+        if self.product_ids.len() > MAX_PRODUCTS {
+            return Err(format!("at most {MAX_PRODUCTS} products can be tagged at once"));
+        }
+        // the mutations run with the app token, which reaches more than the products the user may manage
+        if let Some(id) = self.product_ids.iter().find(|id| !is_product_id(id)) {
+            return Err(format!("{id:?} is not the id of a product"));
+        }
+        if self.metadata.is_empty() {
+            return Err("no metadata given".to_string());
+        }
+        if self.metadata.iter().any(|item| item.key.trim().is_empty()) {
+            return Err("metadata keys can't be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Whether `id` is the global id of a product, `base64("Product:<pk>")`.
+fn is_product_id(id: &str) -> bool {
+    STANDARD.decode(id).ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .is_some_and(|decoded| decoded.strip_prefix("Product:").is_some_and(|pk| !pk.is_empty()))
+}
+
+/// The job started for a request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct BulkMetadataJob {
+    /// Name of the job its progress is published under
+    pub job: String,
+    pub total: usize,
+}
+
+/// What a job did, the data of its [`BULK_METADATA_EVENT`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct BulkMetadataReport {
+    pub job: String,
+    pub updated: usize,
+    pub failed: Vec<FailedProduct>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct FailedProduct {
+    pub id: String,
+    pub message: String,
+}
+
+/// One mutation updating every product of `chunk`, aliased by their index.
+fn chunk_mutation(chunk: &[String]) -> (String, Value) {
+    let ids = (0..chunk.len()).map(|index| format!(", $id{index}: ID!")).collect::<String>();
+    let updates = (0..chunk.len())
+        .map(|index| format!("  p{index}: updateMetadata(id: $id{index}, input: $input) {{ errors {{ field message }} }}\n"))
+        .collect::<String>();
+    let query = format!("mutation BulkMetadata($input: [MetadataInput!]!{ids}) {{\n{updates}}}");
+    let variables = chunk.iter().enumerate().map(|(index, id)| (format!("id{index}"), Value::String(id.clone()))).collect::<Map<_, _>>();
+    (query, Value::Object(variables))
+}
+
+async fn update_chunk(client: &HttpClient, auth_data: &AuthData, chunk: &[String], input: &Value, report: &mut BulkMetadataReport) {
+    let (query, mut variables) = chunk_mutation(chunk);
+    variables["input"] = input.clone();
+    let data: Map<String, Value> = match run_app_graphql(client, auth_data, "BulkMetadata", &query, variables).await {
+        Ok(data) => data,
+        Err(e) => {
+            let message = e.to_string();
+            report.failed.extend(chunk.iter().map(|id| FailedProduct { id: id.clone(), message: message.clone() }));
+            return;
+        }
+    };
+    for (index, id) in chunk.iter().enumerate() {
+        let errors = data.get(&format!("p{index}")).and_then(|result| result["errors"].as_array()).filter(|errors| !errors.is_empty());
+        match errors {
+            Some(errors) => {
+                let message = errors.iter().filter_map(|error| error["message"].as_str()).collect::<Vec<_>>().join(", ");
+                report.failed.push(FailedProduct { id: id.clone(), message });
+            }
+            None => report.updated += 1,
+        }
+    }
+}
+
+/// Sets the metadata on every product, publishing the progress of `job` to the pages of the installation.
+pub async fn run_job(client: &HttpClient, auth_data: &AuthData, events: &EventHub, job: &str, request: &BulkMetadataRequest) -> BulkMetadataReport {
+    let saleor_api_url = &auth_data.saleor_api_url;
+    let total = request.product_ids.len() as u64;
+    let input = serde_json::to_value(&request.metadata).unwrap_or_default();
+    let mut report = BulkMetadataReport { job: job.to_string(), ..Default::default() };
+    events.publish(saleor_api_url, AppEvent::progress(job, 0, total));
+    for chunk in request.product_ids.chunks(CHUNK_SIZE) {
+        update_chunk(client, auth_data, chunk, &input, &mut report).await;
+        events.publish(saleor_api_url, AppEvent::progress(job, (report.updated + report.failed.len()) as u64, total));
+    }
+    info!(saleor_api_url, job, updated = report.updated, failed = report.failed.len(), "tagged products");
+    report
+}
+
+/// `POST /api/products/bulk-metadata`, sets metadata on many products in the background.
+#[utoipa::path(post, path = "/api/products/bulk-metadata", tag = "products", security(("dashboard_token" = []), ("saleor_api_url" = [])), request_body = BulkMetadataRequest, responses(
+    (status = 202, description = "The job was started, its progress is published as events", body = BulkMetadataJob),
+    (status = 400, description = "No products or metadata, or too many products"),
+))]
+pub async fn bulk_metadata(
+    CurrentInstallation(auth_data): CurrentInstallation,
+    client: HttpClient,
+    events: EventHub,
+    i18n: Localizer,
+    Json(request): Json<BulkMetadataRequest>,
+) -> Response {
+    if let Err(e) = request.validate() {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+
+    let job = format!("{}-{}", BULK_METADATA_EVENT, uuid::Uuid::new_v4());
+    let started = BulkMetadataJob { job: job.clone(), total: request.product_ids.len() };
+    tokio::spawn(async move {
+        let report = run_job(&client, &auth_data, &events, &job, &request).await;
+        let toast = match report.failed.len() {
+            0 => AppEvent::toast(NotificationStatus::Success, i18n.t_with("bulk-metadata-finished", "count", report.updated)),
+            failed => AppEvent::toast(NotificationStatus::Warning, i18n.t_with("bulk-metadata-failed", "count", failed)),
+        };
+        events.publish(&auth_data.saleor_api_url, AppEvent::new(BULK_METADATA_EVENT, &report));
+        events.publish(&auth_data.saleor_api_url, toast);
+    });
+    (StatusCode::ACCEPTED, Json(started)).into_response()
+}
@@ -0,0 +1,228 @@
+//! Order messages posted to a Slack or Discord channel of the shop.
+//!
+//! Merge [`router`] into the app with [`crate::router_ext::SaleorWebhooks`] and add [`manifest`] to the
+//! webhooks of the manifest, the app needs the `MANAGE_ORDERS` permission. Every installation
+//! configures its chat in its settings:
+//!
+//! * `chat_webhook_url`, the incoming webhook of the channel, installations without one get no messages
+//! * `chat_provider`, `slack` or `discord`, guessed from the webhook url when missing
+//! * `chat_order_created_template` and `chat_order_fully_paid_template` replace the default
+//!   messages, see [`ChatMessage::render`] for the placeholders
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(chat::router()))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! ```
+
+use std::collections::HashMap;
+
+use axum::{Router, routing::post, response::{IntoResponse, Response}};
+use reqwest::{StatusCode, Url};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::http_client::HttpClient;
+
+use super::{notifications::{NotificationOrder, OrderNotification, ORDER_FRAGMENT}, MetadataSettingsManager, SaleorApl, SaleorAsyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest, SettingsManager};
+
+pub const ORDER_CREATED_PATH: &str = "/api/webhooks/chat/order-created";
+pub const ORDER_FULLY_PAID_PATH: &str = "/api/webhooks/chat/order-fully-paid";
+
+pub const CHAT_WEBHOOK_URL_SETTING: &str = "chat_webhook_url";
+pub const CHAT_PROVIDER_SETTING: &str = "chat_provider";
+pub const ORDER_CREATED_TEMPLATE_SETTING: &str = "chat_order_created_template";
+pub const ORDER_FULLY_PAID_TEMPLATE_SETTING: &str = "chat_order_fully_paid_template";
+
+pub const DEFAULT_ORDER_CREATED_TEMPLATE: &str = "New order #{number} from {customer} in {channel}: {total}\n{lines}\n{url}";
+pub const DEFAULT_ORDER_FULLY_PAID_TEMPLATE: &str = "Order #{number} is fully paid: {total}\n{url}";
+
+/// Subscription query of `ORDER_CREATED` and `ORDER_FULLY_PAID`, matching [`OrderNotification`].
+fn order_query(event: &str) -> String {
+    format!("subscription {{
+  event {{
+    ... on {event} {{
+      {ORDER_FRAGMENT}
+    }}
+  }}
+}}")
+}
+
+#[derive(Debug)]
+pub struct ChatError(pub String);
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "chat error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ChatError {}
+
+/// Saleor retries the webhook when it isn't answered with a success.
+impl IntoResponse for ChatError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+/// The chat an incoming webhook belongs to, they expect different payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatProvider {
+    Slack,
+    Discord,
+}
+
+impl ChatProvider {
+    /// The provider of `chat_provider`, or the one whose host the webhook url points to.
+    ///
+    /// Unknown hosts get Slack messages, which Mattermost and Rocket.Chat accept as well.
+    pub fn from_settings(settings: &HashMap<String, String>, webhook_url: &str) -> Self {
+        match settings.get(CHAT_PROVIDER_SETTING).map(|provider| provider.trim().to_lowercase()).as_deref() {
+            Some("discord") => return Self::Discord,
+            Some("slack") => return Self::Slack,
+            _ => {}
+        }
+        let host = Url::parse(webhook_url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_default();
+        match host.as_str() {
+            "discord.com" | "discordapp.com" | "ptb.discord.com" | "canary.discord.com" => Self::Discord,
+            _ => Self::Slack,
+        }
+    }
+
+    /// The JSON body posted to the webhook.
+    pub fn payload(self, text: &str) -> Value {
+        match self {
+            Self::Slack => json!({ "text": text }),
+            // discord ignores pings in webhook messages only when told so
+            Self::Discord => json!({ "content": text, "allowed_mentions": { "parse": [] } }),
+        }
+    }
+}
+
+/// An order rendered into a message template.
+pub struct ChatMessage<'a> {
+    pub order: &'a NotificationOrder,
+    /// The page of the order in the dashboard
+    pub url: Option<String>,
+}
+
+impl<'a> ChatMessage<'a> {
+    /// The dashboard of Saleor Cloud and the docker images is served next to the API under `/dashboard/`.
+    pub fn new(order: &'a NotificationOrder, saleor_api_url: &str) -> Self {
+        let url = Url::parse(saleor_api_url)
+            .ok()
+            .map(|url| format!("{}/dashboard/orders/{}", url.origin().ascii_serialization(), order.id));
+        Self { order, url }
+    }
+
+    /// Replaces `{number}`, `{customer}`, `{channel}`, `{total}`, `{lines}` (one `2 × Product (Variant)`
+    /// per line) and `{url}` in `template`, unknown placeholders are kept as they are. A literal `\n`
+    /// starts a new line, for templates edited in a single line input.
+    pub fn render(&self, template: &str) -> String {
+        let lines: Vec<String> = self.order.lines
+            .iter()
+            .map(|line| match line.variant_name.is_empty() {
+                true => format!("{} × {}", line.quantity, line.product_name),
+                false => format!("{} × {} ({})", line.quantity, line.product_name, line.variant_name),
+            })
+            .collect();
+        let values = [
+            ("{number}", self.order.number.clone()),
+            ("{customer}", self.order.user_email.clone().unwrap_or_else(|| "a guest".to_string())),
+            ("{channel}", self.order.channel.slug.clone()),
+            ("{total}", self.order.total.gross.to_string()),
+            ("{lines}", lines.join("\n")),
+            ("{url}", self.url.clone().unwrap_or_default()),
+        ];
+        let mut text = template.replace("\\n", "\n");
+        for (placeholder, value) in values {
+            text = text.replace(placeholder, &value);
+        }
+        text.trim().to_string()
+    }
+}
+
+/// Posts `text` to the incoming webhook at `webhook_url`.
+pub async fn post_message(client: &reqwest::Client, provider: ChatProvider, webhook_url: &str, text: &str) -> Result<(), ChatError> {
+    let response = client
+        .post(webhook_url)
+        .json(&provider.payload(text))
+        .send()
+        .await
+        .map_err(|e| ChatError(e.to_string()))?;
+    if response.status().is_success() {
+        return Ok(());
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(ChatError(format!("{}: {}", status, body)))
+}
+
+/// The webhook routes of the chat messages, they verify the signature of every delivery.
+///
+/// Settings are read and messages posted with the [`HttpClient`] from the request extensions.
+pub fn router() -> Router {
+    Router::new()
+        .route(ORDER_CREATED_PATH, post(order_created))
+        .route(ORDER_FULLY_PAID_PATH, post(order_fully_paid))
+}
+
+/// The webhooks of [`router`] for the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    let webhook = |name: &str, event: SaleorAsyncWebhookEvent, query: String, path: &str| SaleorWebhookManifest {
+        name: name.to_string(),
+        async_events: Some(vec![event]),
+        sync_events: None,
+        query,
+        target_url: format!("{}{}", base_url, path),
+        is_active: Some(true),
+    };
+    vec![
+        webhook("Order created chat message", SaleorAsyncWebhookEvent::OrderCreated, order_query("OrderCreated"), ORDER_CREATED_PATH),
+        webhook("Order fully paid chat message", SaleorAsyncWebhookEvent::OrderFullyPaid, order_query("OrderFullyPaid"), ORDER_FULLY_PAID_PATH),
+    ]
+}
+
+/// Posts the order to the chat of the installation, rendered with the template in `template_setting`.
+async fn notify(client: HttpClient, apl: SaleorApl, webhook: SaleorWebhook<OrderNotification>, template_setting: &str, default_template: &str) -> Response {
+    let Some(order) = webhook.payload.order else {
+        return StatusCode::OK.into_response();
+    };
+    let saleor_api_url = webhook.saleor_api_url.as_str();
+    let auth_data = match apl.get(&apl.id(saleor_api_url)).await {
+        Ok(Some(auth_data)) => auth_data,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(e) => return e.into_response(),
+    };
+    let settings = match MetadataSettingsManager::new(client.clone(), auth_data).get_all().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!(saleor_api_url, "unable to read chat settings: {}", e);
+            return e.into_response();
+        }
+    };
+    let Some(webhook_url) = settings.get(CHAT_WEBHOOK_URL_SETTING).map(|url| url.trim()).filter(|url| !url.is_empty()) else {
+        return StatusCode::OK.into_response();
+    };
+
+    let template = settings.get(template_setting).map(String::as_str).filter(|template| !template.trim().is_empty()).unwrap_or(default_template);
+    let text = ChatMessage::new(&order, saleor_api_url).render(template);
+    let provider = ChatProvider::from_settings(&settings, webhook_url);
+    match post_message(&client, provider, webhook_url, &text).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            warn!(saleor_api_url, "{}", e);
+            e.into_response()
+        }
+    }
+}
+
+async fn order_created(client: HttpClient, apl: SaleorApl, webhook: SaleorWebhook<OrderNotification>) -> Response {
+    notify(client, apl, webhook, ORDER_CREATED_TEMPLATE_SETTING, DEFAULT_ORDER_CREATED_TEMPLATE).await
+}
+
+async fn order_fully_paid(client: HttpClient, apl: SaleorApl, webhook: SaleorWebhook<OrderNotification>) -> Response {
+    notify(client, apl, webhook, ORDER_FULLY_PAID_TEMPLATE_SETTING, DEFAULT_ORDER_FULLY_PAID_TEMPLATE).await
+}
@@ -1,6 +1,7 @@
 use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SaleorPermission {
     ManageUsers,
@@ -29,7 +30,7 @@ pub enum SaleorPermission {
     ManageApps,
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SaleorAppPermission {
     ManageUsers,
@@ -56,11 +57,47 @@ pub enum SaleorAppPermission {
     ManageTranslations,
 }
 
-#[derive(Serialize, Debug)]
+/// Every permission an app can request is one users can have, e.g. for the permissions of extensions.
+impl From<SaleorAppPermission> for SaleorPermission {
+    fn from(permission: SaleorAppPermission) -> Self {
+        match permission {
+            SaleorAppPermission::ManageUsers => SaleorPermission::ManageUsers,
+            SaleorAppPermission::ManageStaff => SaleorPermission::ManageStaff,
+            SaleorAppPermission::ImpersonateUser => SaleorPermission::ImpersonateUser,
+            SaleorAppPermission::ManageObservability => SaleorPermission::ManageObservability,
+            SaleorAppPermission::ManageCheckouts => SaleorPermission::ManageCheckouts,
+            SaleorAppPermission::HandleCheckouts => SaleorPermission::HandleCheckouts,
+            SaleorAppPermission::HandleTaxes => SaleorPermission::HandleTaxes,
+            SaleorAppPermission::ManageTaxes => SaleorPermission::ManageTaxes,
+            SaleorAppPermission::ManageChannels => SaleorPermission::ManageChannels,
+            SaleorAppPermission::ManageDiscounts => SaleorPermission::ManageDiscounts,
+            SaleorAppPermission::ManageGiftCard => SaleorPermission::ManageGiftCard,
+            SaleorAppPermission::ManageMenus => SaleorPermission::ManageMenus,
+            SaleorAppPermission::ManageOrders => SaleorPermission::ManageOrders,
+            SaleorAppPermission::ManagePages => SaleorPermission::ManagePages,
+            SaleorAppPermission::ManagePageTypesAndAttributes => SaleorPermission::ManagePageTypesAndAttributes,
+            SaleorAppPermission::HandlePayments => SaleorPermission::HandlePayments,
+            SaleorAppPermission::ManagePlugins => SaleorPermission::ManagePlugins,
+            SaleorAppPermission::ManageProducts => SaleorPermission::ManageProducts,
+            SaleorAppPermission::ManageProductTypesAndAttributes => SaleorPermission::ManageProductTypesAndAttributes,
+            SaleorAppPermission::ManageShipping => SaleorPermission::ManageShipping,
+            SaleorAppPermission::ManageSettings => SaleorPermission::ManageSettings,
+            SaleorAppPermission::ManageTranslations => SaleorPermission::ManageTranslations,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, ToSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SaleorAsyncWebhookEvent {
+    AnyEvents,
     AccountConfirmationRequested,
+    AccountChangeEmailRequested,
+    AccountEmailChanged,
+    AccountSetPasswordRequested,
+    AccountConfirmed,
     AccountDeleteRequested,
+    AccountDeleted,
     AddressCreated,
     AddressUpdated,
     AddressDeleted,
@@ -81,12 +118,14 @@ pub enum SaleorAsyncWebhookEvent {
     ChannelUpdated,
     ChannelDeleted,
     ChannelStatusChanged,
+    ChannelMetadataUpdated,
     GiftCardCreated,
     GiftCardUpdated,
     GiftCardDeleted,
     GiftCardSent,
     GiftCardStatusChanged,
     GiftCardMetadataUpdated,
+    GiftCardExportCompleted,
     MenuCreated,
     MenuUpdated,
     MenuDeleted,
@@ -112,6 +151,14 @@ pub enum SaleorAsyncWebhookEvent {
     SaleUpdated,
     SaleDeleted,
     SaleToggle,
+    PromotionCreated,
+    PromotionUpdated,
+    PromotionDeleted,
+    PromotionStarted,
+    PromotionEnded,
+    PromotionRuleCreated,
+    PromotionRuleUpdated,
+    PromotionRuleDeleted,
     InvoiceRequested,
     InvoiceDeleted,
     InvoiceSent,
@@ -130,6 +177,7 @@ pub enum SaleorAsyncWebhookEvent {
     ProductMediaUpdated,
     ProductMediaDeleted,
     ProductMetadataUpdated,
+    ProductExportCompleted,
     ProductVariantCreated,
     ProductVariantUpdated,
     ProductVariantDeleted,
@@ -145,6 +193,7 @@ pub enum SaleorAsyncWebhookEvent {
     FulfillmentCanceled,
     FulfillmentApproved,
     FulfillmentMetadataUpdated,
+    FulfillmentTrackingNumberUpdated,
     NotifyUser,
     PageCreated,
     PageUpdated,
@@ -165,7 +214,7 @@ pub enum SaleorAsyncWebhookEvent {
     StaffCreated,
     StaffUpdated,
     StaffDeleted,
-    TransactionActionRequest,
+    StaffSetPasswordRequested,
     TransactionItemMetadataUpdated,
     TranslationCreated,
     TranslationUpdated,
@@ -179,11 +228,19 @@ pub enum SaleorAsyncWebhookEvent {
     VoucherMetadataUpdated,
     Observability,
     ThumbnailCreated,
+    ShopMetadataUpdated,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SaleorSyncWebhookEvent {
+    PaymentListGateways,
+    PaymentAuthorize,
+    PaymentCapture,
+    PaymentRefund,
+    PaymentVoid,
+    PaymentConfirm,
+    PaymentProcess,
     CheckoutCalculateTaxes,
     OrderCalculateTaxes,
     ShippingListMethodsForCheckout,
@@ -195,18 +252,30 @@ pub enum SaleorSyncWebhookEvent {
     PaymentGatewayInitializeSession,
     TransactionInitializeSession,
     TransactionProcessSession,
+    ListStoredPaymentMethods,
+    StoredPaymentMethodDeleteRequested,
+    PaymentGatewayInitializeTokenizationSession,
+    PaymentMethodInitializeTokenizationSession,
+    PaymentMethodProcessTokenizationSession,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SaleorAppExtensionTarget {
     Popup,
     AppPage,
+    /// Rendered in an iframe on a details page, see [`super::widgets`]
+    Widget,
+    /// Opened in a new browser tab, see [`crate::extensions::NEW_TAB_PATH`]
+    NewTab,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SaleorAppExtensionMount {
+    CustomerOverviewCreate,
+    CustomerOverviewMoreActions,
+    CustomerDetailsMoreActions,
     ProductDetailsMoreActions,
     ProductOverviewCreate,
     ProductOverviewMoreActions,
@@ -219,4 +288,100 @@ pub enum SaleorAppExtensionMount {
     OrderDetailsMoreActions,
     OrderOverviewCreate,
     OrderOverviewMoreActions,
-}
\ No newline at end of file
+    OrderDetailsWidgets,
+    ProductDetailsWidgets,
+}
+
+/// How the dashboard loads a [`SaleorAppExtensionTarget::Widget`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SaleorWidgetMethod {
+    /// The context is passed as query parameters, without a token
+    Get,
+    /// The context and a token are posted as form
+    Post,
+}
+
+/// How the dashboard opens a [`SaleorAppExtensionTarget::NewTab`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SaleorNewTabMethod {
+    /// The url is opened with the context as query parameters, without a token
+    Get,
+    /// The context and a token are posted as form to the url
+    Post,
+}
+/// The `code` of a failed register response.
+///
+/// The dashboard renders its own explanation for the codes it knows and shows the message of the
+/// app for the others, so prefer them over [`Self::Custom`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SaleorRegisterErrorCode {
+    /// The register request carries no auth token
+    TokenMissing,
+    /// The app doesn't accept installations from this Saleor instance
+    SaleorUrlProhibited,
+    /// The JWKS of the Saleor instance couldn't be fetched
+    JwksNotAvailable,
+    /// The `saleor-api-url` header isn't a url
+    ApiUrlParsingFailed,
+    Unknown,
+    /// Too many registrations of the IP or domain
+    RateLimited,
+    /// The auth token was registered before
+    AuthTokenReused,
+    /// The installation couldn't be stored
+    AplUnavailable,
+    /// A code of the app, serialized as is
+    Custom(String),
+}
+
+impl SaleorRegisterErrorCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::TokenMissing => "TOKEN_MISSING",
+            Self::SaleorUrlProhibited => "SALEOR_URL_PROHIBITED",
+            Self::JwksNotAvailable => "JWKS_NOT_AVAILABLE",
+            Self::ApiUrlParsingFailed => "API_URL_PARSING_FAILED",
+            Self::Unknown => "UNKNOWN",
+            Self::RateLimited => "RATE_LIMITED",
+            Self::AuthTokenReused => "AUTH_TOKEN_REUSED",
+            Self::AplUnavailable => "APL_UNAVAILABLE",
+            Self::Custom(code) => code,
+        }
+    }
+}
+
+impl From<&str> for SaleorRegisterErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "TOKEN_MISSING" => Self::TokenMissing,
+            "SALEOR_URL_PROHIBITED" => Self::SaleorUrlProhibited,
+            "JWKS_NOT_AVAILABLE" => Self::JwksNotAvailable,
+            "API_URL_PARSING_FAILED" => Self::ApiUrlParsingFailed,
+            "UNKNOWN" => Self::Unknown,
+            "RATE_LIMITED" => Self::RateLimited,
+            "AUTH_TOKEN_REUSED" => Self::AuthTokenReused,
+            "APL_UNAVAILABLE" => Self::AplUnavailable,
+            other => Self::Custom(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for SaleorRegisterErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for SaleorRegisterErrorCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SaleorRegisterErrorCode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|code| Self::from(code.as_str()))
+    }
+}
@@ -0,0 +1,281 @@
+//! Shipment tracking in both directions: fulfillment webhooks in, tracking numbers out.
+//!
+//! Implement [`FulfillmentTracker`] to hand new fulfillments to a carrier and follow tracking
+//! numbers staff enter in the dashboard, merge [`router`] into the app with
+//! [`crate::router_ext::SaleorWebhooks`] and add [`manifest`] to the webhooks of the manifest.
+//! Once the carrier assigned a tracking number, [`update_tracking`] writes it to the fulfillment.
+//! The app needs the `MANAGE_ORDERS` permission.
+//!
+//! ```ignore
+//! let tracker = CarrierTracker::new(carrier_api_key);
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(fulfillments::router(tracker)))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! ```
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{Router, routing::post, extract::State, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::http_client::HttpClient;
+
+use super::{graphql::{mutation_errors, run_app_graphql}, AuthData, SaleorApl, SaleorAsyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest};
+
+pub const FULFILLMENT_CREATED_PATH: &str = "/api/webhooks/fulfillments/fulfillment-created";
+pub const FULFILLMENT_TRACKING_NUMBER_UPDATED_PATH: &str = "/api/webhooks/fulfillments/tracking-number-updated";
+
+/// Subscription query of `FULFILLMENT_CREATED` and `FULFILLMENT_TRACKING_NUMBER_UPDATED`, matching [`FulfillmentEvent`].
+fn fulfillment_query(event: &str) -> String {
+    format!("subscription {{
+  event {{
+    ... on {event} {{
+      fulfillment {{
+        id
+        fulfillmentOrder
+        status
+        trackingNumber
+        created
+        warehouse {{ id name }}
+        lines {{ id quantity orderLine {{ productName variantName productSku }} }}
+      }}
+      order {{
+        id
+        number
+        userEmail
+        channel {{ slug }}
+        shippingMethodName
+        shippingAddress {{ firstName lastName companyName streetAddress1 streetAddress2 postalCode city countryArea country {{ code }} phone }}
+      }}
+    }}
+  }}
+}}")
+}
+
+const UPDATE_TRACKING_MUTATION: &str = "mutation FulfillmentUpdateTracking($id: ID!, $input: FulfillmentUpdateTrackingInput!) {
+  orderFulfillmentUpdateTracking(id: $id, input: $input) {
+    errors { field message }
+  }
+}";
+
+#[derive(Debug)]
+pub struct FulfillmentError(pub String);
+
+impl std::fmt::Display for FulfillmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fulfillment error: {}", self.0)
+    }
+}
+
+impl std::error::Error for FulfillmentError {}
+
+/// Saleor retries the webhook when it isn't answered with a success.
+impl IntoResponse for FulfillmentError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+/// `FulfillmentStatus` in the schema.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FulfillmentStatus {
+    Fulfilled,
+    Refunded,
+    Returned,
+    Replaced,
+    RefundedAndReturned,
+    Canceled,
+    WaitingForApproval,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FulfillmentWarehouse {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FulfillmentOrderLine {
+    pub product_name: String,
+    pub variant_name: String,
+    pub product_sku: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FulfillmentLine {
+    pub id: String,
+    pub quantity: u32,
+    /// Missing if the line was removed from the order
+    pub order_line: Option<FulfillmentOrderLine>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Fulfillment {
+    pub id: String,
+    /// The position of the fulfillment within its order, it's numbered `{order number}-{fulfillment order}`
+    pub fulfillment_order: u32,
+    pub status: FulfillmentStatus,
+    /// Empty until a tracking number was set
+    pub tracking_number: String,
+    pub created: String,
+    pub warehouse: Option<FulfillmentWarehouse>,
+    #[serde(default)]
+    pub lines: Vec<FulfillmentLine>,
+}
+
+impl Fulfillment {
+    pub fn tracking_number(&self) -> Option<&str> {
+        Some(self.tracking_number.as_str()).filter(|tracking_number| !tracking_number.is_empty())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FulfillmentCountry {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FulfillmentAddress {
+    pub first_name: String,
+    pub last_name: String,
+    pub company_name: String,
+    pub street_address_1: String,
+    pub street_address_2: String,
+    pub postal_code: String,
+    pub city: String,
+    pub country_area: String,
+    pub country: FulfillmentCountry,
+    pub phone: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FulfillmentChannel {
+    pub slug: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FulfillmentOrder {
+    pub id: String,
+    pub number: String,
+    pub user_email: Option<String>,
+    pub channel: FulfillmentChannel,
+    pub shipping_method_name: Option<String>,
+    /// Missing for orders that don't need shipping
+    pub shipping_address: Option<FulfillmentAddress>,
+}
+
+/// Payload of `FULFILLMENT_CREATED` and `FULFILLMENT_TRACKING_NUMBER_UPDATED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FulfillmentEvent {
+    pub fulfillment: Option<Fulfillment>,
+    pub order: Option<FulfillmentOrder>,
+}
+
+/// A carrier integration, one method per fulfillment webhook.
+///
+/// Both methods get the installation the webhook came from, pass it to [`update_tracking`] to
+/// report a tracking number back. Failures are answered with an error, so Saleor retries the webhook.
+#[async_trait]
+pub trait FulfillmentTracker: Send + Sync + 'static {
+    /// A fulfillment was created, either shipped right away or waiting for approval.
+    async fn fulfillment_created(&self, auth_data: &AuthData, event: FulfillmentEvent) -> Result<(), FulfillmentError>;
+
+    /// The tracking number was changed, by staff in the dashboard or through [`update_tracking`].
+    async fn tracking_number_updated(&self, _auth_data: &AuthData, _event: FulfillmentEvent) -> Result<(), FulfillmentError> {
+        Ok(())
+    }
+}
+
+/// Sets the tracking number of a fulfillment, `notify_customer` emails it to the customer.
+///
+/// This triggers `FULFILLMENT_TRACKING_NUMBER_UPDATED`, trackers should ignore numbers they set themselves.
+pub async fn update_tracking(client: &HttpClient, auth_data: &AuthData, fulfillment_id: &str, tracking_number: &str, notify_customer: bool) -> Result<(), FulfillmentError> {
+    let variables = json!({
+        "id": fulfillment_id,
+        "input": { "trackingNumber": tracking_number, "notifyCustomer": notify_customer },
+    });
+    let data: Value = run_app_graphql(client, auth_data, "FulfillmentUpdateTracking", UPDATE_TRACKING_MUTATION, variables)
+        .await
+        .map_err(|e| FulfillmentError(e.to_string()))?;
+    if let Some(errors) = mutation_errors(&data) {
+        return Err(FulfillmentError(format!("unable to update tracking of {}: {}", fulfillment_id, Value::from(errors.clone()))));
+    }
+    Ok(())
+}
+
+/// The webhook routes of the tracker, they verify the signature of every delivery.
+pub fn router(tracker: impl FulfillmentTracker) -> Router {
+    let tracker: Arc<dyn FulfillmentTracker> = Arc::new(tracker);
+    Router::new()
+        .route(FULFILLMENT_CREATED_PATH, post(fulfillment_created))
+        .route(FULFILLMENT_TRACKING_NUMBER_UPDATED_PATH, post(tracking_number_updated))
+        .with_state(tracker)
+}
+
+/// The webhooks of [`router`] for the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    let webhook = |name: &str, event: SaleorAsyncWebhookEvent, query: String, path: &str| SaleorWebhookManifest {
+        name: name.to_string(),
+        async_events: Some(vec![event]),
+        sync_events: None,
+        query,
+        target_url: format!("{}{}", base_url, path),
+        is_active: Some(true),
+    };
+    vec![
+        webhook("Fulfillment created", SaleorAsyncWebhookEvent::FulfillmentCreated, fulfillment_query("FulfillmentCreated"), FULFILLMENT_CREATED_PATH),
+        webhook(
+            "Fulfillment tracking number updated",
+            SaleorAsyncWebhookEvent::FulfillmentTrackingNumberUpdated,
+            fulfillment_query("FulfillmentTrackingNumberUpdated"),
+            FULFILLMENT_TRACKING_NUMBER_UPDATED_PATH,
+        ),
+    ]
+}
+
+type Tracker = State<Arc<dyn FulfillmentTracker>>;
+
+async fn installation(apl: &SaleorApl, saleor_api_url: &str) -> Result<AuthData, Response> {
+    match apl.get(&apl.id(saleor_api_url)).await {
+        Ok(Some(auth_data)) => Ok(auth_data),
+        Ok(None) => Err(StatusCode::UNAUTHORIZED.into_response()),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+fn respond(saleor_api_url: &str, result: Result<(), FulfillmentError>) -> Response {
+    match result {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            warn!(saleor_api_url, "{}", e);
+            e.into_response()
+        }
+    }
+}
+
+async fn fulfillment_created(State(tracker): Tracker, apl: SaleorApl, webhook: SaleorWebhook<FulfillmentEvent>) -> Response {
+    let auth_data = match installation(&apl, &webhook.saleor_api_url).await {
+        Ok(auth_data) => auth_data,
+        Err(response) => return response,
+    };
+    respond(&webhook.saleor_api_url, tracker.fulfillment_created(&auth_data, webhook.payload).await)
+}
+
+async fn tracking_number_updated(State(tracker): Tracker, apl: SaleorApl, webhook: SaleorWebhook<FulfillmentEvent>) -> Response {
+    let auth_data = match installation(&apl, &webhook.saleor_api_url).await {
+        Ok(auth_data) => auth_data,
+        Err(response) => return response,
+    };
+    respond(&webhook.saleor_api_url, tracker.tracking_number_updated(&auth_data, webhook.payload).await)
+}
@@ -0,0 +1,334 @@
+use std::{borrow::Cow, sync::{Arc, RwLock}, time::Instant};
+
+use axum::{http::StatusCode, response::{IntoResponse, Response}};
+use reqwest::multipart::{Form, Part};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{concurrency::Workload, http_client::HttpClient, secrets::REDACTED, telemetry};
+
+use super::AuthData;
+
+/// Variables that are never logged, whatever `GRAPHQL_DEBUG_REDACT` says.
+pub const DEFAULT_REDACTED_VARIABLES: &[&str] = &["password", "newPassword", "oldPassword", "token", "accessToken", "refreshToken", "secret", "apiKey"];
+
+/// Configured by `GRAPHQL_DEBUG` and `GRAPHQL_DEBUG_REDACT`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphqlDebugConfig {
+    /// Whether every GraphQL call is logged with its operation, variables, duration and errors
+    pub enabled: bool,
+    /// Variables redacted besides [`DEFAULT_REDACTED_VARIABLES`], at any depth and in any case
+    pub redacted_fields: Vec<String>,
+}
+
+impl GraphqlDebugConfig {
+    fn is_redacted(&self, field: &str) -> bool {
+        DEFAULT_REDACTED_VARIABLES
+            .iter()
+            .copied()
+            .chain(self.redacted_fields.iter().map(String::as_str))
+            .any(|redacted| redacted.eq_ignore_ascii_case(field))
+    }
+
+    /// `variables` with the values of redacted fields replaced, as they're logged.
+    pub fn redact(&self, variables: &Value) -> Value {
+        match variables {
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(field, value)| match self.is_redacted(field) {
+                        true => (field.clone(), Value::String(REDACTED.to_string())),
+                        false => (field.clone(), self.redact(value)),
+                    })
+                    .collect(),
+            ),
+            Value::Array(values) => Value::Array(values.iter().map(|value| self.redact(value)).collect()),
+            value => value.clone(),
+        }
+    }
+}
+
+/// The logging of GraphQL calls, `None` while it's disabled.
+static GRAPHQL_DEBUG: RwLock<Option<Arc<GraphqlDebugConfig>>> = RwLock::new(None);
+
+/// Turns the logging of GraphQL calls on or off, [`crate::app::build`] and configuration reloads
+/// call it.
+pub fn set_graphql_debug(config: &GraphqlDebugConfig) {
+    *GRAPHQL_DEBUG.write().unwrap() = config.enabled.then(|| Arc::new(config.clone()));
+}
+
+/// Codes of the errors Saleor answers calls with when it doesn't accept their token. Unknown app
+/// tokens are treated as anonymous, so their calls fail with `PermissionDenied`.
+const AUTH_ERROR_CODES: &[&str] = &["PermissionDenied", "InvalidTokenError", "ExpiredSignatureError", "JSONWebTokenError", "InvalidSignatureError"];
+
+/// Asks for the app of the token, `null` if Saleor doesn't know the token.
+pub const APP_TOKEN_CHECK_QUERY: &str = "query AppTokenCheck { app { id } }";
+
+/// Why a call made with the app token of an installation failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphqlError {
+    /// Saleor doesn't accept the app token anymore, the app was most likely removed or reinstalled
+    /// without registering again. See [`AppTokenHealth`](super::AppTokenHealth).
+    AppTokenInvalid { saleor_api_url: String },
+    Failed(String),
+}
+
+impl std::fmt::Display for GraphqlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AppTokenInvalid { saleor_api_url } => write!(f, "{} rejected the app token, the app has to be installed again", saleor_api_url),
+            Self::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for GraphqlError {}
+
+impl From<GraphqlError> for String {
+    fn from(e: GraphqlError) -> Self {
+        e.to_string()
+    }
+}
+
+impl IntoResponse for GraphqlError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::AppTokenInvalid { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Failed(_) => StatusCode::BAD_GATEWAY,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// A failed call, `rejected` if Saleor refused its token.
+struct CallFailure {
+    rejected: bool,
+    message: String,
+}
+
+impl CallFailure {
+    fn failed(message: String) -> Self {
+        Self { rejected: false, message }
+    }
+}
+
+/// Whether Saleor answered a call with an error about its token.
+fn is_auth_error(errors: &Value) -> bool {
+    errors.as_array().is_some_and(|errors| {
+        errors
+            .iter()
+            .filter_map(|error| error.pointer("/extensions/exception/code").and_then(Value::as_str))
+            .any(|code| AUTH_ERROR_CODES.contains(&code))
+    })
+}
+
+/// Runs a hand written GraphQL document, for operations cynic can't express with the types of this crate.
+///
+/// Fails on transport errors and GraphQL errors, returning the deserialized `data` otherwise. The
+/// `api_url` needn't be an installation, so these calls don't go through a circuit breaker.
+pub(crate) async fn run_graphql<T: DeserializeOwned>(
+    client: &HttpClient,
+    api_url: &str,
+    token: Option<&str>,
+    operation: &'static str,
+    query: &str,
+    variables: Value,
+) -> Result<T, String> {
+    call_graphql(client, None, api_url, token, operation, query, variables, None).await.map_err(|failure| failure.message)
+}
+
+/// Like [`run_graphql`] for operations built with cynic, through the circuit breaker of
+/// `installation`.
+pub(crate) async fn run_operation<T: DeserializeOwned, V: Serialize>(
+    client: &HttpClient,
+    installation: &AuthData,
+    token: Option<&str>,
+    operation: &'static str,
+    built: cynic::Operation<T, V>,
+) -> Result<T, String> {
+    let variables = serde_json::to_value(&built.variables).map_err(|e| format!("invalid variables of {}: {}", operation, e))?;
+    let api_url = installation.saleor_api_url.as_str();
+    call_graphql(client, Some(installation), api_url, token, operation, &built.query, variables, None).await.map_err(|failure| failure.message)
+}
+
+/// A file sent with a call as a GraphQL multipart request, in place of the variable `variable`.
+pub(crate) struct GraphqlUpload {
+    pub variable: &'static str,
+    pub file_name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+/// Like [`run_graphql`] with the app token of the installation. When Saleor rejects the token, calls
+/// are made again with the token the installation registered since, if any, otherwise the
+/// installation is marked as unhealthy and [`GraphqlError::AppTokenInvalid`] returned.
+pub(crate) async fn run_app_graphql<T: DeserializeOwned>(
+    client: &HttpClient,
+    auth_data: &AuthData,
+    operation: &'static str,
+    query: &str,
+    variables: Value,
+) -> Result<T, GraphqlError> {
+    call_app_graphql(client, auth_data, operation, query, variables, None).await
+}
+
+/// Like [`run_app_graphql`] for operations built with cynic, named `operation` in metrics and logs.
+/// Apps call Saleor with it, so their calls are logged with `GRAPHQL_DEBUG` and notice rejected
+/// tokens like the ones of this crate.
+pub async fn run_app_operation<T: DeserializeOwned, V: Serialize>(
+    client: &HttpClient,
+    auth_data: &AuthData,
+    operation: &'static str,
+    built: cynic::Operation<T, V>,
+) -> Result<T, GraphqlError> {
+    let variables = serde_json::to_value(&built.variables).map_err(|e| GraphqlError::Failed(format!("invalid variables of {}: {}", operation, e)))?;
+    call_app_graphql(client, auth_data, operation, &built.query, variables, None).await
+}
+
+/// Like [`run_app_graphql`], sending `upload` along, see [`GraphqlUpload`].
+pub(crate) async fn run_app_graphql_upload<T: DeserializeOwned>(
+    client: &HttpClient,
+    auth_data: &AuthData,
+    operation: &'static str,
+    query: &str,
+    variables: Value,
+    upload: &GraphqlUpload,
+) -> Result<T, GraphqlError> {
+    call_app_graphql(client, auth_data, operation, query, variables, Some(upload)).await
+}
+
+async fn call_app_graphql<T: DeserializeOwned>(
+    client: &HttpClient,
+    auth_data: &AuthData,
+    operation: &'static str,
+    query: &str,
+    variables: Value,
+    upload: Option<&GraphqlUpload>,
+) -> Result<T, GraphqlError> {
+    let api_url = auth_data.saleor_api_url.as_str();
+    let mut auth_data = Cow::Borrowed(auth_data);
+    let mut retried = false;
+    loop {
+        let failure = match call_graphql(client, Some(&auth_data), api_url, Some(auth_data.token.expose()), operation, query, variables.clone(), upload).await {
+            Ok(data) => {
+                if auth_data.token_invalid_since.is_some() {
+                    client.app_tokens().accepted(&auth_data).await;
+                }
+                return Ok(data);
+            }
+            Err(failure) => failure,
+        };
+        // permission errors are only about the token if Saleor doesn't know it at all
+        if !failure.rejected || is_known_app_token(client, &auth_data).await {
+            return Err(GraphqlError::Failed(failure.message));
+        }
+        match client.app_tokens().rejected(&auth_data).await {
+            Some(registered) if !retried => {
+                tracing::info!(saleor_api_url = api_url, operation, "app token was replaced, calling again with the new one");
+                auth_data = Cow::Owned(registered);
+                retried = true;
+            }
+            _ => return Err(GraphqlError::AppTokenInvalid { saleor_api_url: api_url.to_string() }),
+        }
+    }
+}
+
+/// Whether Saleor knows the app `token`, transport errors count as known since they say nothing
+/// about the token.
+async fn is_known_app_token(client: &HttpClient, auth_data: &AuthData) -> bool {
+    #[derive(Deserialize)]
+    struct Data {
+        app: Option<Value>,
+    }
+    let (api_url, token) = (auth_data.saleor_api_url.as_str(), auth_data.token.expose());
+    match call_graphql::<Data>(client, Some(auth_data), api_url, Some(token), "AppTokenCheck", APP_TOKEN_CHECK_QUERY, json!({}), None).await {
+        Ok(data) => data.app.is_some(),
+        Err(failure) => !failure.rejected,
+    }
+}
+
+/// Calls made for an `installation` go through its circuit breaker.
+#[allow(clippy::too_many_arguments)]
+async fn call_graphql<T: DeserializeOwned>(
+    client: &HttpClient,
+    installation: Option<&AuthData>,
+    api_url: &str,
+    token: Option<&str>,
+    operation: &'static str,
+    query: &str,
+    variables: Value,
+    upload: Option<&GraphqlUpload>,
+) -> Result<T, CallFailure> {
+    // redacted before the request takes the variables
+    let debug = GRAPHQL_DEBUG.read().unwrap().clone().map(|debug| debug.redact(&variables));
+    let body = json!({ "query": query, "variables": variables });
+    let mut request = match upload {
+        Some(upload) => {
+            let part = Part::bytes(upload.content.clone())
+                .file_name(upload.file_name.clone())
+                .mime_str(&upload.content_type)
+                .map_err(|e| CallFailure::failed(format!("invalid content type of the upload: {}", e)))?;
+            let form = Form::new()
+                .text("operations", body.to_string())
+                .text("map", json!({ "0": [format!("variables.{}", upload.variable)] }).to_string())
+                .part("0", part);
+            client.post(api_url).multipart(form)
+        }
+        None => client.post(api_url).json(&body),
+    };
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let call = installation
+        .map(|installation| client.circuit_breakers().allow(installation))
+        .transpose()
+        .map_err(|e| CallFailure::failed(e.to_string()))?;
+    let _permit = client.concurrency().acquire(api_url, Workload::Graphql).await;
+    let start = Instant::now();
+    let mut unauthorized = false;
+    let response = match request.send().await {
+        Ok(response) => {
+            unauthorized = response.status() == StatusCode::UNAUTHORIZED;
+            response.json::<Value>().await
+        }
+        Err(e) => Err(e),
+    };
+    telemetry::record_graphql_call(operation, start.elapsed(), response.is_ok());
+    if let Some(call) = call {
+        call.record(response.is_ok());
+    }
+
+    let errors = match &response {
+        Ok(response) => response.get("errors").filter(|errors| errors.as_array().is_some_and(|errors| !errors.is_empty())).cloned(),
+        Err(e) => Some(Value::String(e.to_string())),
+    };
+    // the token is left out, only the operation and what Saleor answered are logged
+    if let Some(variables) = debug {
+        let mutation_errors = response.as_ref().ok().and_then(|response| mutation_errors(&response["data"])).map(|errors| Value::Array(errors.clone()));
+        tracing::info!(
+            api_url,
+            operation,
+            %variables,
+            duration_ms = start.elapsed().as_millis() as u64,
+            errors = errors.as_ref().map(tracing::field::display),
+            mutation_errors = mutation_errors.as_ref().map(tracing::field::display),
+            "graphql call",
+        );
+    }
+
+    let rejected = token.is_some() && (unauthorized || errors.as_ref().is_some_and(is_auth_error));
+    let mut response = response.map_err(|e| CallFailure { rejected, message: e.to_string() })?;
+    if let Some(errors) = errors {
+        return Err(CallFailure { rejected, message: format!("{} failed: {}", operation, errors) });
+    }
+    serde_json::from_value(response["data"].take()).map_err(|e| CallFailure::failed(format!("unexpected response to {}: {}", operation, e)))
+}
+
+/// The `errors` a mutation reported in its payload, the first field of `data`.
+pub(crate) fn mutation_errors(data: &Value) -> Option<&Vec<Value>> {
+    data.as_object()
+        .and_then(|data| data.values().next())
+        .and_then(|result| result.get("errors"))
+        .and_then(Value::as_array)
+        .filter(|errors| !errors.is_empty())
+}
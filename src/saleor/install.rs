@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::http_client::HttpClient;
+
+use super::{graphql::{mutation_errors, run_graphql}, SaleorAppPermission};
+
+const TOKEN_CREATE_MUTATION: &str = r#"mutation TokenCreate($email: String!, $password: String!) {
+  tokenCreate(email: $email, password: $password) { token errors { field message } }
+}"#;
+
+const APP_INSTALL_MUTATION: &str = r#"mutation AppInstall($input: AppInstallInput!) {
+  appInstall(input: $input) {
+    appInstallation { id status message }
+    errors { field message code }
+  }
+}"#;
+
+const APPS_INSTALLATIONS_QUERY: &str = r#"query AppsInstallations {
+  appsInstallations { id status message }
+}"#;
+
+#[derive(Debug)]
+pub struct InstallError(String);
+
+impl std::fmt::Display for InstallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "app installation failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for InstallError {}
+
+/// How to authenticate as a staff user with `MANAGE_APPS`.
+#[derive(Debug, Clone)]
+pub enum StaffCredentials {
+    Token(String),
+    Password { email: String, password: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JobStatus {
+    Pending,
+    Success,
+    Failed,
+    Deleted,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AppInstallation {
+    pub id: String,
+    pub status: JobStatus,
+    pub message: Option<String>,
+}
+
+/// Installs an app into a Saleor instance the way the dashboard does.
+pub struct AppInstaller {
+    client: HttpClient,
+    saleor_api_url: String,
+    /// Time between checks of a pending installation
+    pub poll_interval: Duration,
+    /// How long to wait for a pending installation
+    pub timeout: Duration,
+}
+
+impl AppInstaller {
+    pub fn new(client: HttpClient, saleor_api_url: &str) -> Self {
+        Self {
+            client,
+            saleor_api_url: saleor_api_url.to_string(),
+            poll_interval: Duration::from_secs(2),
+            timeout: Duration::from_secs(120),
+        }
+    }
+
+    async fn staff_token(&self, credentials: &StaffCredentials) -> Result<String, InstallError> {
+        let (email, password) = match credentials {
+            StaffCredentials::Token(token) => return Ok(token.clone()),
+            StaffCredentials::Password { email, password } => (email, password),
+        };
+        let variables = json!({ "email": email, "password": password });
+        let data: Value = run_graphql(&self.client, &self.saleor_api_url, None, "TokenCreate", TOKEN_CREATE_MUTATION, variables)
+            .await
+            .map_err(InstallError)?;
+        if let Some(errors) = mutation_errors(&data) {
+            return Err(InstallError(format!("unable to log in: {}", Value::from(errors.clone()))));
+        }
+        data.pointer("/tokenCreate/token")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| InstallError("unable to log in: no token returned".to_string()))
+    }
+
+    /// Starts the installation of the app behind `manifest_url` and waits until Saleor finished it.
+    ///
+    /// Saleor fetches the manifest and registers the app through its `tokenTargetUrl` in the
+    /// background, so the app has to be reachable from the Saleor instance.
+    pub async fn install(
+        &self,
+        credentials: &StaffCredentials,
+        app_name: &str,
+        manifest_url: &str,
+        permissions: &[SaleorAppPermission],
+    ) -> Result<AppInstallation, InstallError> {
+        let token = self.staff_token(credentials).await?;
+        let variables = json!({
+            "input": {
+                "appName": app_name,
+                "manifestUrl": manifest_url,
+                "permissions": permissions,
+                "activateAfterInstallation": true,
+            },
+        });
+        let data: Value = run_graphql(&self.client, &self.saleor_api_url, Some(&token), "AppInstall", APP_INSTALL_MUTATION, variables)
+            .await
+            .map_err(InstallError)?;
+        if let Some(errors) = mutation_errors(&data) {
+            return Err(InstallError(Value::from(errors.clone()).to_string()));
+        }
+        let mut installation: AppInstallation = data
+            .pointer("/appInstall/appInstallation")
+            .cloned()
+            .and_then(|installation| serde_json::from_value(installation).ok())
+            .ok_or_else(|| InstallError("no installation returned".to_string()))?;
+
+        let start = Instant::now();
+        while installation.status == JobStatus::Pending {
+            if start.elapsed() > self.timeout {
+                return Err(InstallError(format!("still pending after {}s", self.timeout.as_secs())));
+            }
+            tokio::time::sleep(self.poll_interval).await;
+
+            let data: Value = run_graphql(&self.client, &self.saleor_api_url, Some(&token), "AppsInstallations", APPS_INSTALLATIONS_QUERY, json!({}))
+                .await
+                .map_err(InstallError)?;
+            let installations: Vec<AppInstallation> = serde_json::from_value(data["appsInstallations"].clone())
+                .map_err(|e| InstallError(format!("unexpected installations: {}", e)))?;
+            installation = match installations.into_iter().find(|pending| pending.id == installation.id) {
+                Some(installation) => installation,
+                // successful installations are removed from the list
+                None => AppInstallation {
+                    status: JobStatus::Success,
+                    ..installation
+                },
+            };
+        }
+
+        match installation.status {
+            JobStatus::Success => Ok(installation),
+            _ => Err(InstallError(installation.message.unwrap_or_else(|| format!("installation {:?}", installation.status)))),
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Invoice generation for `INVOICE_REQUESTED`, sent when a staff user generates an invoice of an order.
+//!
+//! Merge [`router`] into the app with [`crate::router_ext::SaleorWebhooks`] and add [`manifest`] to the
+//! webhooks of the manifest. Every request renders the invoice with an [`InvoiceRenderer`]
+//! ([`PdfInvoiceRenderer`] unless you bring your own), uploads the file to Saleor, attaches it to
+//! the invoice and sends it to the customer. The app needs the `MANAGE_ORDERS` permission.
+//!
+//! ```ignore
+//! let renderer = PdfInvoiceRenderer::new().with_issuer(&["ACME Inc.", "1 Main Street"]);
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(invoices::router(renderer)))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! ```
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{Router, routing::post, extract::State, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::http_client::HttpClient;
+
+use super::{graphql::{mutation_errors, run_app_graphql, run_app_graphql_upload, GraphqlUpload}, money::{Money, TaxedMoney}, AuthData, SaleorApl, SaleorAsyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest};
+
+mod pdf;
+
+pub const INVOICE_REQUESTED_PATH: &str = "/api/webhooks/invoices/invoice-requested";
+
+/// Subscription query of `INVOICE_REQUESTED`, matching [`InvoiceRequested`].
+const INVOICE_REQUESTED_QUERY: &str = "subscription {
+  event {
+    ... on InvoiceRequested {
+      invoice { id number }
+      order {
+        id
+        number
+        created
+        userEmail
+        channel { slug }
+        billingAddress { firstName lastName companyName streetAddress1 streetAddress2 postalCode city country { code country } }
+        lines {
+          productName
+          variantName
+          productSku
+          quantity
+          taxRate
+          unitPrice { net { amount currency } gross { amount currency } tax { amount currency } }
+          totalPrice { net { amount currency } gross { amount currency } tax { amount currency } }
+        }
+        shippingMethodName
+        shippingPrice { net { amount currency } gross { amount currency } tax { amount currency } }
+        total { net { amount currency } gross { amount currency } tax { amount currency } }
+      }
+    }
+  }
+}";
+
+const FILE_UPLOAD_MUTATION: &str = "mutation FileUpload($file: Upload!) {
+  fileUpload(file: $file) {
+    uploadedFile { url }
+    errors { field message }
+  }
+}";
+
+const INVOICE_UPDATE_MUTATION: &str = "mutation InvoiceUpdate($id: ID!, $input: UpdateInvoiceInput!) {
+  invoiceUpdate(id: $id, input: $input) {
+    errors { field message }
+  }
+}";
+
+const INVOICE_SEND_NOTIFICATION_MUTATION: &str = "mutation InvoiceSendNotification($id: ID!) {
+  invoiceSendNotification(id: $id) {
+    errors { field message }
+  }
+}";
+
+#[derive(Debug)]
+pub struct InvoiceError(pub String);
+
+impl std::fmt::Display for InvoiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invoice error: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvoiceError {}
+
+/// Saleor retries the webhook when it isn't answered with a success.
+impl IntoResponse for InvoiceError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceCountry {
+    pub code: String,
+    pub country: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceAddress {
+    pub first_name: String,
+    pub last_name: String,
+    pub company_name: String,
+    pub street_address_1: String,
+    pub street_address_2: String,
+    pub postal_code: String,
+    pub city: String,
+    pub country: InvoiceCountry,
+}
+
+impl InvoiceAddress {
+    /// The address as it's printed, without empty lines.
+    pub fn lines(&self) -> Vec<String> {
+        let name = format!("{} {}", self.first_name, self.last_name);
+        let city = format!("{} {}", self.postal_code, self.city);
+        [self.company_name.as_str(), name.trim(), &self.street_address_1, &self.street_address_2, city.trim(), &self.country.country]
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceChannel {
+    pub slug: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceLine {
+    pub product_name: String,
+    pub variant_name: String,
+    pub product_sku: Option<String>,
+    pub quantity: u32,
+    /// A fraction, like 0.19 for 19%
+    #[serde(with = "rust_decimal::serde::float")]
+    pub tax_rate: Decimal,
+    pub unit_price: TaxedMoney,
+    pub total_price: TaxedMoney,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceOrder {
+    pub id: String,
+    pub number: String,
+    /// RFC 3339
+    pub created: String,
+    pub user_email: Option<String>,
+    pub channel: InvoiceChannel,
+    pub billing_address: Option<InvoiceAddress>,
+    pub lines: Vec<InvoiceLine>,
+    pub shipping_method_name: Option<String>,
+    pub shipping_price: TaxedMoney,
+    pub total: TaxedMoney,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WebhookInvoice {
+    pub id: String,
+    pub number: Option<String>,
+}
+
+/// Payload of `INVOICE_REQUESTED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceRequested {
+    pub invoice: Option<WebhookInvoice>,
+    pub order: InvoiceOrder,
+}
+
+/// A rendered invoice, ready to be uploaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceFile {
+    pub file_name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+/// Turns an order into an invoice document.
+#[async_trait]
+pub trait InvoiceRenderer: Send + Sync + 'static {
+    /// The number of the invoice, the one requested by the staff user if there is one.
+    fn number(&self, request: &InvoiceRequested) -> String {
+        request.invoice.as_ref().and_then(|invoice| invoice.number.clone()).unwrap_or_else(|| request.order.number.clone())
+    }
+
+    async fn render(&self, number: &str, order: &InvoiceOrder) -> Result<InvoiceFile, InvoiceError>;
+}
+
+/// Renders invoices as plain PDFs: the issuer, billing address, a table of the lines and the totals.
+#[derive(Clone, Debug, Default)]
+pub struct PdfInvoiceRenderer {
+    issuer: Vec<String>,
+}
+
+impl PdfInvoiceRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lines printed at the top, usually the name and address of the shop.
+    pub fn with_issuer(mut self, issuer: &[&str]) -> Self {
+        self.issuer = issuer.iter().map(|line| line.to_string()).collect();
+        self
+    }
+
+    /// The text of the invoice, line by line.
+    pub fn lines(&self, number: &str, order: &InvoiceOrder) -> Vec<String> {
+        let width = pdf::LINE_WIDTH;
+        let row = |quantity: &str, description: &str, rate: &str, unit: &str, total: &str| {
+            let description: String = description.chars().take(width - 51).collect();
+            format!("{quantity:>4}  {description:<w$}  {rate:>6}  {unit:>16}  {total:>16}", w = width - 51)
+        };
+
+        let mut lines = self.issuer.clone();
+        lines.push(String::new());
+        lines.push(format!("Invoice {number}"));
+        lines.push(format!("Order #{} of {}", order.number, order.created.get(..10).unwrap_or(&order.created)));
+        lines.push(String::new());
+        if let Some(address) = &order.billing_address {
+            lines.push("Bill to:".to_string());
+            lines.extend(address.lines());
+            lines.push(String::new());
+        }
+
+        lines.push(row("Qty", "Description", "Tax", "Unit price", "Total"));
+        lines.push("-".repeat(width));
+        for line in &order.lines {
+            let description = match line.variant_name.is_empty() {
+                true => line.product_name.clone(),
+                false => format!("{} ({})", line.product_name, line.variant_name),
+            };
+            let rate = format!("{}%", (line.tax_rate * Decimal::ONE_HUNDRED).normalize());
+            lines.push(row(&line.quantity.to_string(), &description, &rate, &line.unit_price.gross.to_string(), &line.total_price.gross.to_string()));
+        }
+        if let Some(shipping) = &order.shipping_method_name {
+            lines.push(row("", &format!("Shipping ({shipping})"), "", "", &order.shipping_price.gross.to_string()));
+        }
+        lines.push("-".repeat(width));
+
+        let total = |label: &str, money: &Money| format!("{label:>w$}{:>16}", money.to_string(), w = width - 16);
+        lines.push(total("Net ", &order.total.net));
+        lines.push(total("Tax ", &order.total.tax));
+        lines.push(total("Total ", &order.total.gross));
+        lines
+    }
+}
+
+#[async_trait]
+impl InvoiceRenderer for PdfInvoiceRenderer {
+    async fn render(&self, number: &str, order: &InvoiceOrder) -> Result<InvoiceFile, InvoiceError> {
+        let file_name: String = number.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+        Ok(InvoiceFile {
+            file_name: format!("invoice-{file_name}.pdf"),
+            content_type: "application/pdf".to_string(),
+            content: pdf::write(&self.lines(number, order)),
+        })
+    }
+}
+
+/// Renders the requested invoice, uploads it and attaches it to the invoice in Saleor, which then sends it
+/// to the customer.
+pub async fn generate_invoice(client: &HttpClient, auth_data: &AuthData, renderer: &dyn InvoiceRenderer, request: InvoiceRequested) -> Result<(), InvoiceError> {
+    let invoice_id = match &request.invoice {
+        Some(invoice) => invoice.id.clone(),
+        None => return Err(InvoiceError("the webhook has no invoice".to_string())),
+    };
+    let number = renderer.number(&request);
+    let file = renderer.render(&number, &request.order).await?;
+    let url = upload_file(client, auth_data, file).await?;
+
+    let variables = json!({ "id": invoice_id, "input": { "number": number, "url": url } });
+    let data: Value = run_app_graphql(client, auth_data, "InvoiceUpdate", INVOICE_UPDATE_MUTATION, variables).await.map_err(|e| InvoiceError(e.to_string()))?;
+    if let Some(errors) = mutation_errors(&data) {
+        return Err(InvoiceError(format!("unable to update invoice {}: {}", invoice_id, Value::from(errors.clone()))));
+    }
+
+    let variables = json!({ "id": invoice_id });
+    let data: Value = run_app_graphql(client, auth_data, "InvoiceSendNotification", INVOICE_SEND_NOTIFICATION_MUTATION, variables).await.map_err(|e| InvoiceError(e.to_string()))?;
+    if let Some(errors) = mutation_errors(&data) {
+        return Err(InvoiceError(format!("unable to send invoice {}: {}", invoice_id, Value::from(errors.clone()))));
+    }
+
+    info!(invoice_id, number, order_id = %request.order.id, "invoice generated");
+    Ok(())
+}
+
+/// Uploads `file` with a GraphQL multipart request, returning its url.
+async fn upload_file(client: &HttpClient, auth_data: &AuthData, file: InvoiceFile) -> Result<String, InvoiceError> {
+    let upload = GraphqlUpload { variable: "file", file_name: file.file_name, content_type: file.content_type, content: file.content };
+    let variables = json!({ "file": null });
+    let data: Value = run_app_graphql_upload(client, auth_data, "FileUpload", FILE_UPLOAD_MUTATION, variables, &upload)
+        .await
+        .map_err(|e| InvoiceError(format!("unable to upload invoice: {}", e)))?;
+    if let Some(errors) = mutation_errors(&data) {
+        return Err(InvoiceError(format!("unable to upload invoice: {}", Value::from(errors.clone()))));
+    }
+    data["fileUpload"]["uploadedFile"]["url"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| InvoiceError("the upload returned no url".to_string()))
+}
+
+/// The webhook route of invoice generation, it verifies the signature of every delivery.
+///
+/// Saleor is called with the [`HttpClient`] from the request extensions.
+pub fn router(renderer: impl InvoiceRenderer) -> Router {
+    let renderer: Arc<dyn InvoiceRenderer> = Arc::new(renderer);
+    Router::new()
+        .route(INVOICE_REQUESTED_PATH, post(invoice_requested))
+        .with_state(renderer)
+}
+
+/// The webhook of [`router`] for the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    vec![SaleorWebhookManifest {
+        name: "Invoice requested".to_string(),
+        async_events: Some(vec![SaleorAsyncWebhookEvent::InvoiceRequested]),
+        sync_events: None,
+        query: INVOICE_REQUESTED_QUERY.to_string(),
+        target_url: format!("{}{}", base_url, INVOICE_REQUESTED_PATH),
+        is_active: Some(true),
+    }]
+}
+
+async fn invoice_requested(
+    State(renderer): State<Arc<dyn InvoiceRenderer>>,
+    client: HttpClient,
+    apl: SaleorApl,
+    webhook: SaleorWebhook<InvoiceRequested>,
+) -> Result<StatusCode, Response> {
+    let auth_data = match apl.get(&apl.id(&webhook.saleor_api_url)).await {
+        Ok(Some(auth_data)) => auth_data,
+        Ok(None) => return Err(StatusCode::UNAUTHORIZED.into_response()),
+        Err(e) => return Err(e.into_response()),
+    };
+    let order_id = webhook.payload.order.id.clone();
+    match generate_invoice(&client, &auth_data, renderer.as_ref(), webhook.payload).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(e) => {
+            warn!(saleor_api_url = %webhook.saleor_api_url, order_id, "{}", e);
+            Err(e.into_response())
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Just enough of PDF to put lines of monospaced text on A4 pages.
+
+const PAGE_WIDTH: u32 = 595;
+const PAGE_HEIGHT: u32 = 842;
+const MARGIN: u32 = 50;
+const FONT_SIZE: u32 = 9;
+const LEADING: u32 = 13;
+
+/// Characters that fit on a line, Courier glyphs are 0.6 of the font size wide.
+pub const LINE_WIDTH: usize = ((PAGE_WIDTH - 2 * MARGIN) * 10 / (FONT_SIZE * 6)) as usize;
+const LINES_PER_PAGE: usize = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize;
+
+/// A PDF document with `lines` in Courier, starting a new page whenever one is full.
+pub fn write(lines: &[String]) -> Vec<u8> {
+    let pages: Vec<&[String]> = match lines.is_empty() {
+        true => vec![&[]],
+        false => lines.chunks(LINES_PER_PAGE).collect(),
+    };
+
+    // 1 is the catalog, 2 the page tree, 3 the font, then a page and its contents per page
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 4 + 2 * i).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".as_bytes().to_vec(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids.iter().map(|id| format!("{id} 0 R")).collect::<Vec<_>>().join(" "),
+            pages.len(),
+        )
+        .into_bytes(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".as_bytes().to_vec(),
+    ];
+    for (page, id) in pages.iter().zip(&page_ids) {
+        objects.push(
+            format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>", id + 1)
+                .into_bytes(),
+        );
+
+        let mut content = format!("BT /F1 {FONT_SIZE} Tf {LEADING} TL {MARGIN} {} Td\n", PAGE_HEIGHT - MARGIN).into_bytes();
+        for line in page.iter() {
+            content.push(b'(');
+            content.extend(encode(line));
+            content.extend_from_slice(b") Tj T*\n");
+        }
+        content.extend_from_slice(b"ET");
+
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", i + 1).into_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{offset:010} 00000 n \n").into_bytes());
+    }
+    pdf.extend(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n", objects.len() + 1).into_bytes());
+    pdf
+}
+
+/// The line as a string literal in WinAnsiEncoding, characters it doesn't have become `?`.
+fn encode(line: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '(' | ')' | '\\' => encoded.extend_from_slice(&[b'\\', c as u8]),
+            '€' => encoded.push(0x80),
+            ' '..='~' | '\u{a0}'..='\u{ff}' => encoded.push(c as u32 as u8),
+            _ => encoded.push(b'?'),
+        }
+    }
+    encoded
+}
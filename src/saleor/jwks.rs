@@ -0,0 +1,371 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::{header::RETRY_AFTER, request::Parts, HeaderValue, StatusCode}, response::{IntoResponse, Response}};
+use jsonwebtoken::{jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet}, Algorithm, DecodingKey};
+use reqwest::Url;
+
+use crate::{http_client::HttpClient, telemetry};
+
+use super::{AplId, AplStore, AuthData};
+
+/// A parsed JWKS along with the decoding keys built from it so far.
+pub struct Jwks {
+    /// The JWKS as Saleor serves it
+    raw: Box<str>,
+    jwk_set: JwkSet,
+    /// Keyed by kid, the key used without a kid is stored under an empty one
+    decoding_keys: Mutex<HashMap<String, Arc<DecodingKey>>>,
+}
+
+impl Jwks {
+    pub fn parse(jwks: &str) -> Result<Self, String> {
+        let jwk_set = serde_json::from_str::<JwkSet>(jwks).map_err(|e| format!("unable to deserialize jwks: {}", e))?;
+        Ok(Self { raw: jwks.into(), jwk_set, decoding_keys: Mutex::default() })
+    }
+
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Whether the JWKS has the key [`Self::decoding_key`] looks for.
+    pub fn has_key(&self, kid: Option<&str>) -> bool {
+        self.jwk(kid).is_ok()
+    }
+
+    /// The key with the given `kid`, or the first one without a `kid`. Keys are built once and then reused.
+    pub fn decoding_key(&self, kid: Option<&str>) -> Result<Arc<DecodingKey>, String> {
+        let cache_key = kid.unwrap_or_default();
+        if let Some(key) = self.decoding_keys.lock().unwrap().get(cache_key) {
+            return Ok(key.clone());
+        }
+
+        let jwk = self.jwk(kid)?;
+        let key = Arc::new(DecodingKey::from_jwk(jwk).map_err(|e| format!("unable to create decoding key from jwk: {}", e))?);
+        self.decoding_keys.lock().unwrap().insert(cache_key.to_string(), key.clone());
+        Ok(key)
+    }
+
+    /// Like [`Self::decoding_key`], but only if the key is of the family `algorithm` belongs to.
+    ///
+    /// The algorithm of a detached JWS comes from its unauthenticated header, verifying it with a
+    /// key of another family either panics in `jsonwebtoken` or, for `HS*`, uses the public key as
+    /// a shared secret.
+    pub fn decoding_key_for(&self, kid: Option<&str>, algorithm: Algorithm) -> Result<Arc<DecodingKey>, String> {
+        let matches = match &self.jwk(kid)?.algorithm {
+            AlgorithmParameters::RSA(_) => matches!(
+                algorithm,
+                Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512
+            ),
+            AlgorithmParameters::EllipticCurve(params) => matches!(
+                (&params.curve, algorithm),
+                (EllipticCurve::P256, Algorithm::ES256) | (EllipticCurve::P384, Algorithm::ES384)
+            ),
+            AlgorithmParameters::OctetKeyPair(params) => params.curve == EllipticCurve::Ed25519 && algorithm == Algorithm::EdDSA,
+            AlgorithmParameters::OctetKey(_) => false,
+        };
+        if !matches {
+            return Err(format!("{:?} doesn't match the key type of the jwk", algorithm));
+        }
+        self.decoding_key(kid)
+    }
+
+    fn jwk(&self, kid: Option<&str>) -> Result<&Jwk, String> {
+        match kid {
+            Some(kid) => self.jwk_set.find(kid).ok_or_else(|| format!("unable to find jwk with kid {}", kid)),
+            None => self.jwk_set.keys.first().ok_or_else(|| "jwks has no keys".to_string()),
+        }
+    }
+}
+
+
+/// How JWKS that aren't stored in the APL are fetched from Saleor, see [`JwksCache::fetch`].
+#[derive(Debug, Clone, Copy)]
+pub struct JwksFetchConfig {
+    /// Attempts after the first one when Saleor can't be reached or answers with a server error
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub backoff: Duration,
+    /// How long fetched JWKS are used before they're fetched again
+    pub ttl: Duration,
+    /// How long the last fetched JWKS is still used while Saleor can't be reached
+    pub grace_period: Duration,
+    /// `Retry-After` of the `503 Service Unavailable` once the grace period is over
+    pub retry_after: Duration,
+}
+
+impl Default for JwksFetchConfig {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(200),
+            ttl: Duration::from_secs(5 * 60),
+            grace_period: Duration::from_secs(60 * 60),
+            retry_after: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwksFetchError {
+    /// Saleor answered, but not with a usable JWKS
+    Invalid(String),
+    /// Saleor couldn't be reached and no JWKS fetched within the grace period is cached
+    Unavailable { retry_after: Duration },
+}
+
+impl std::fmt::Display for JwksFetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => write!(f, "jwks error: {}", e),
+            Self::Unavailable { .. } => write!(f, "jwks error: saleor is unavailable"),
+        }
+    }
+}
+
+impl std::error::Error for JwksFetchError {}
+
+/// Invalid JWKS reject the token with `401 Unauthorized`, unavailable ones answer with `503 Service Unavailable`.
+impl IntoResponse for JwksFetchError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Invalid(e) => (StatusCode::UNAUTHORIZED, e).into_response(),
+            Self::Unavailable { retry_after } => {
+                let mut response = (StatusCode::SERVICE_UNAVAILABLE, "unable to fetch jwks, try again later").into_response();
+                let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+                response
+            }
+        }
+    }
+}
+
+/// Fetched JWKS kept at most, apps rarely have that many installations.
+const MAX_FETCHED: usize = 1_000;
+/// Largest JWKS read from Saleor, it serves a handful of keys.
+const MAX_JWKS_SIZE: usize = 64 * 1024;
+
+struct FetchedJwks {
+    jwks: Arc<Jwks>,
+    fetched_at: Instant,
+}
+
+/// Parsed JWKS of every installation, so tokens and webhooks are verified without deserializing the
+/// JWKS or rebuilding its keys each time.
+///
+/// Entries are keyed by Saleor API url and replaced, decoding keys included, as soon as the JWKS
+/// stored for it changes. Clones share their entries.
+#[derive(Clone, Default)]
+pub struct JwksCache {
+    /// The parsed JWKS stored in the APL, a different one means Saleor rotated its keys
+    entries: Arc<Mutex<HashMap<String, Arc<Jwks>>>>,
+    /// JWKS last fetched from Saleor, kept to bridge outages of its JWKS endpoint
+    fetched: Arc<Mutex<HashMap<String, FetchedJwks>>>,
+    fetch_config: JwksFetchConfig,
+}
+
+impl JwksCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_fetch_config(fetch_config: JwksFetchConfig) -> Self {
+        Self {
+            fetch_config,
+            ..Self::default()
+        }
+    }
+
+    /// The parsed `jwks` of the installation at `saleor_api_url`.
+    pub fn get(&self, saleor_api_url: &str, jwks: &str) -> Result<Arc<Jwks>, String> {
+        if let Some(cached) = self.entries.lock().unwrap().get(saleor_api_url) {
+            if cached.raw() == jwks {
+                return Ok(cached.clone());
+            }
+        }
+
+        let parsed = Arc::new(Jwks::parse(jwks)?);
+        self.entries.lock().unwrap().insert(saleor_api_url.to_string(), parsed.clone());
+        Ok(parsed)
+    }
+
+    /// The JWKS to verify a token or webhook of `installation` with, signed by the key `kid`.
+    ///
+    /// The JWKS stored in the APL are used as long as they have the key. Otherwise Saleor may have
+    /// rotated its keys, they're fetched with [`Self::fetch`] and stored in `apl` if the new ones
+    /// have it. Installations without stored JWKS always use fetched ones.
+    pub async fn jwks_for(&self, client: &HttpClient, apl: &dyn AplStore, installation: &AuthData, kid: Option<&str>) -> Result<Arc<Jwks>, JwksFetchError> {
+        let saleor_api_url = installation.saleor_api_url.as_str();
+        let Some(stored) = &installation.jwks else {
+            return self.fetch(client, installation).await;
+        };
+        // stored JWKS that don't parse are replaced like rotated ones
+        let stored = match self.get(saleor_api_url, stored) {
+            Ok(stored) if stored.has_key(kid) => {
+                telemetry::record_jwks_lookup(true);
+                return Ok(stored);
+            }
+            Ok(stored) => Some(stored),
+            Err(e) => {
+                tracing::warn!(saleor_api_url, "the stored jwks are invalid, fetching them: {}", e);
+                None
+            }
+        };
+        // the stored JWKS reject the token or webhook if Saleor doesn't know the key either
+        let fetched = match (self.fetch(client, installation).await, stored) {
+            (Ok(fetched), Some(stored)) if !fetched.has_key(kid) => return Ok(stored),
+            (Ok(fetched), _) => fetched,
+            (Err(e), Some(stored)) => {
+                tracing::debug!(saleor_api_url, "unable to refresh the jwks: {}", e);
+                return Ok(stored);
+            }
+            (Err(e), None) => return Err(e),
+        };
+        self.store_rotated(client, apl, installation, &fetched).await;
+        Ok(fetched)
+    }
+
+    /// Replaces the JWKS in the APL entry of `installation`, unless it was registered again since.
+    async fn store_rotated(&self, client: &HttpClient, apl: &dyn AplStore, installation: &AuthData, jwks: &Arc<Jwks>) {
+        let saleor_api_url = installation.saleor_api_url.as_str();
+        let apl_id = AplId::from_auth_data(installation);
+        // registrations store their own JWKS
+        let _writes = client.app_tokens().lock_writes().await;
+        let stored = match apl.get(&apl_id).await {
+            Ok(Some(stored)) if stored.token == installation.token && stored.generation == installation.generation => stored,
+            Ok(_) => return,
+            Err(e) => {
+                tracing::warn!(saleor_api_url, "unable to look up the installation to store its rotated jwks: {}", e);
+                return;
+            }
+        };
+        match apl.set(&apl_id, AuthData { jwks: Some(jwks.raw().to_string()), ..stored }).await {
+            Ok(()) => {
+                tracing::info!(saleor_api_url, "Saleor rotated its jwks, stored the new ones");
+                self.entries.lock().unwrap().insert(saleor_api_url.to_string(), jwks.clone());
+            }
+            Err(e) => tracing::warn!(saleor_api_url, "unable to store the rotated jwks: {}", e),
+        }
+    }
+
+    /// Fetches the JWKS of `installation` from its `/.well-known/jwks.json`, JWKS fetched within the
+    /// `ttl` are used without asking Saleor again.
+    ///
+    /// Connection failures and server errors are retried with backoff. If Saleor still can't be
+    /// reached, the JWKS fetched last is used as long as it's within the grace period. Only
+    /// installations found in the APL are fetched, the api url of a request can be anything.
+    pub async fn fetch(&self, client: &HttpClient, installation: &AuthData) -> Result<Arc<Jwks>, JwksFetchError> {
+        if let Some(fetched) = self.fetched.lock().unwrap().get(&installation.saleor_api_url) {
+            if fetched.fetched_at.elapsed() < self.fetch_config.ttl {
+                telemetry::record_jwks_lookup(true);
+                return Ok(fetched.jwks.clone());
+            }
+        }
+        self.refresh(client, installation).await
+    }
+
+    /// Like [`Self::fetch`], without using JWKS fetched within the `ttl`, for registrations storing
+    /// the JWKS Saleor serves right now.
+    pub async fn refresh(&self, client: &HttpClient, installation: &AuthData) -> Result<Arc<Jwks>, JwksFetchError> {
+        telemetry::record_jwks_lookup(false);
+        let saleor_api_url = installation.saleor_api_url.as_str();
+        // Saleor serves its JWKS at the root of the host, not below the api path
+        let origin = Url::parse(saleor_api_url).map_err(|e| JwksFetchError::Invalid(format!("invalid saleor api url: {}", e)))?.origin();
+        let jwks_url = format!("{}/.well-known/jwks.json", origin.ascii_serialization());
+        let mut backoff = self.fetch_config.backoff;
+        let mut attempt = 0;
+        let mut retry_after = self.fetch_config.retry_after;
+        let error = loop {
+            let call = match client.circuit_breakers().allow(installation) {
+                Ok(call) => call,
+                Err(e) => {
+                    retry_after = retry_after.max(e.retry_after);
+                    break e.to_string();
+                }
+            };
+            let fetched = fetch_jwks(client, &jwks_url).await;
+            call.record(!matches!(fetched, Err(FetchFailure::Unavailable(_))));
+            match fetched {
+                Ok(jwks) => {
+                    let jwks = Arc::new(Jwks::parse(&jwks).map_err(JwksFetchError::Invalid)?);
+                    self.keep_fetched(saleor_api_url, jwks.clone());
+                    return Ok(jwks);
+                }
+                Err(FetchFailure::Rejected(e)) => return Err(JwksFetchError::Invalid(e)),
+                Err(FetchFailure::Unavailable(e)) if attempt < self.fetch_config.retries => {
+                    tracing::debug!("unable to fetch {}, retrying in {:?}: {}", jwks_url, backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(FetchFailure::Unavailable(e)) => break e,
+            }
+        };
+
+        if let Some(fetched) = self.fetched.lock().unwrap().get(saleor_api_url) {
+            if fetched.fetched_at.elapsed() <= self.fetch_config.grace_period {
+                tracing::warn!("unable to fetch {}, using the jwks fetched {:?} ago: {}", jwks_url, fetched.fetched_at.elapsed(), error);
+                return Ok(fetched.jwks.clone());
+            }
+        }
+        tracing::error!("unable to fetch {}: {}", jwks_url, error);
+        Err(JwksFetchError::Unavailable { retry_after })
+    }
+
+    /// Keeps `jwks` to bridge outages, dropping the ones outside the grace period and the oldest
+    /// ones above [`MAX_FETCHED`].
+    fn keep_fetched(&self, saleor_api_url: &str, jwks: Arc<Jwks>) {
+        let mut fetched = self.fetched.lock().unwrap();
+        fetched.retain(|_, fetched| fetched.fetched_at.elapsed() <= self.fetch_config.grace_period);
+        if fetched.len() >= MAX_FETCHED && !fetched.contains_key(saleor_api_url) {
+            if let Some(oldest) = fetched.iter().min_by_key(|(_, fetched)| fetched.fetched_at).map(|(url, _)| url.clone()) {
+                fetched.remove(&oldest);
+            }
+        }
+        fetched.insert(saleor_api_url.to_string(), FetchedJwks { jwks, fetched_at: Instant::now() });
+    }
+
+    /// Installations whose fetched JWKS are kept to bridge outages.
+    pub fn fallbacks(&self) -> usize {
+        self.fetched.lock().unwrap().len()
+    }
+}
+
+enum FetchFailure {
+    /// Saleor couldn't be reached or failed, worth retrying
+    Unavailable(String),
+    /// Saleor answered that there is no JWKS
+    Rejected(String),
+}
+
+async fn fetch_jwks(client: &reqwest::Client, jwks_url: &str) -> Result<String, FetchFailure> {
+    let mut response = client.get(jwks_url).send().await.map_err(|e| FetchFailure::Unavailable(e.to_string()))?;
+    let status = response.status();
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(FetchFailure::Unavailable(format!("status {}", status)));
+    }
+    if !status.is_success() {
+        return Err(FetchFailure::Rejected(format!("unable to fetch jwks: status {}", status)));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| FetchFailure::Unavailable(e.to_string()))? {
+        if body.len() + chunk.len() > MAX_JWKS_SIZE {
+            return Err(FetchFailure::Rejected(format!("jwks are larger than {} bytes", MAX_JWKS_SIZE)));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    String::from_utf8(body).map_err(|e| FetchFailure::Rejected(format!("jwks are not utf-8: {}", e)))
+}
+
+/// The cache of [`crate::app::build`], routers built without it get one of their own.
+#[async_trait]
+impl<S> FromRequestParts<S> for JwksCache
+where
+    S: Sync + Send,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<JwksCache>().cloned().unwrap_or_default())
+    }
+}
@@ -0,0 +1,147 @@
+use std::{convert::Infallible, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, Validation};
+
+/// Algorithms of asymmetric keys, which Saleor signs dashboard tokens with.
+const ASYMMETRIC_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// The claims `jsonwebtoken` checks for presence, others can't be required.
+const SPEC_CLAIMS: [&str; 5] = ["exp", "nbf", "aud", "iss", "sub"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtValidationError(pub String);
+
+impl std::fmt::Display for JwtValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "jwt validation error: {}", self.0)
+    }
+}
+
+impl std::error::Error for JwtValidationError {}
+
+/// How dashboard tokens are validated, configured by `JWT_ALGORITHMS`, `JWT_LEEWAY_SECS` and
+/// `JWT_REQUIRED_CLAIMS`.
+///
+/// By default tokens signed with any asymmetric algorithm are accepted, with a minute of leeway for
+/// clocks that drift apart and an `exp` claim. `none` and the HMAC (`HS*`) algorithms are always
+/// rejected: the keys in the JWKS are public, anyone could sign tokens with them as a shared secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtValidation {
+    algorithms: Arc<[Algorithm]>,
+    leeway: u64,
+    required_claims: Arc<[String]>,
+}
+
+impl Default for JwtValidation {
+    fn default() -> Self {
+        Self {
+            algorithms: ASYMMETRIC_ALGORITHMS.into(),
+            leeway: 60,
+            required_claims: ["exp".to_string()].into(),
+        }
+    }
+}
+
+impl JwtValidation {
+    /// Only accepts tokens signed with one of `algorithms`.
+    pub fn with_algorithms(mut self, algorithms: &[Algorithm]) -> Result<Self, JwtValidationError> {
+        if algorithms.is_empty() {
+            return Err(JwtValidationError("no algorithms allowed".to_string()));
+        }
+        if let Some(algorithm) = algorithms.iter().find(|algorithm| !ASYMMETRIC_ALGORITHMS.contains(algorithm)) {
+            return Err(JwtValidationError(format!("{algorithm:?} is a symmetric algorithm, which can't be verified with a JWKS")));
+        }
+        self.algorithms = algorithms.into();
+        Ok(self)
+    }
+
+    /// Parses comma separated algorithm names, like `RS256,ES256`.
+    pub fn parse_algorithms(value: &str) -> Result<Vec<Algorithm>, JwtValidationError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| name.parse().map_err(|_| JwtValidationError(format!("unknown algorithm {name:?}"))))
+            .collect()
+    }
+
+    /// Seconds `exp` and `nbf` may be off by.
+    pub fn with_leeway(mut self, leeway_secs: u64) -> Self {
+        self.leeway = leeway_secs;
+        self
+    }
+
+    /// Rejects tokens missing any of `claims`, which are among `exp`, `nbf`, `aud`, `iss` and `sub`.
+    pub fn with_required_claims<T: AsRef<str>>(mut self, claims: &[T]) -> Result<Self, JwtValidationError> {
+        if let Some(claim) = claims.iter().map(AsRef::as_ref).find(|claim| !SPEC_CLAIMS.contains(claim)) {
+            return Err(JwtValidationError(format!("{claim:?} can't be required, only {}", SPEC_CLAIMS.join(", "))));
+        }
+        self.required_claims = claims.iter().map(|claim| claim.as_ref().to_string()).collect();
+        Ok(self)
+    }
+
+    pub fn algorithms(&self) -> &[Algorithm] {
+        &self.algorithms
+    }
+
+    pub fn leeway(&self) -> u64 {
+        self.leeway
+    }
+
+    pub fn required_claims(&self) -> &[String] {
+        &self.required_claims
+    }
+
+    /// Checks the algorithm named in the header of `token`, before the header is decoded.
+    ///
+    /// `none` doesn't even decode, it still deserves a clearer answer than an invalid header.
+    pub(crate) fn check_algorithm(&self, token: &str) -> Result<(), String> {
+        let Some(name) = token
+            .split('.')
+            .next()
+            .and_then(|header| URL_SAFE_NO_PAD.decode(header).ok())
+            .and_then(|header| serde_json::from_slice::<serde_json::Value>(&header).ok())
+            .and_then(|header| header.get("alg")?.as_str().map(str::to_string))
+        else {
+            return Ok(());
+        };
+        match name.parse::<Algorithm>() {
+            Ok(algorithm) if self.algorithms.contains(&algorithm) => Ok(()),
+            _ => Err(format!("jwt algorithm {name} is not allowed")),
+        }
+    }
+
+    pub(crate) fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway;
+        validation.validate_nbf = self.required_claims.iter().any(|claim| claim == "nbf");
+        validation.set_required_spec_claims(&self.required_claims);
+        validation
+    }
+}
+
+/// The validation [`crate::app::build`] configured, the default one for routers built without it.
+#[async_trait]
+impl<S> FromRequestParts<S> for JwtValidation
+where
+    S: Sync + Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<JwtValidation>().cloned().unwrap_or_default())
+    }
+}
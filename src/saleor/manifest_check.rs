@@ -0,0 +1,116 @@
+use reqwest::Url;
+
+use super::{SaleorAppExtensionMount, SaleorAppExtensionTarget, SaleorManifest};
+
+/// What to do about [`manifest_problems`] at startup, configured by `MANIFEST_VALIDATION`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ManifestValidation {
+    /// The app refuses to start
+    Strict,
+    /// The problems are logged as warnings
+    #[default]
+    Permissive,
+}
+
+/// Checks the manifest for mistakes Saleor only answers with "installation failed".
+///
+/// Returns the problems in human readable form, empty if the manifest is fine.
+pub fn manifest_problems(manifest: &SaleorManifest) -> Vec<String> {
+    let mut problems = vec![];
+    for (field, value) in [("id", &manifest.id), ("name", &manifest.name), ("version", &manifest.version)] {
+        if value.trim().is_empty() {
+            problems.push(format!("{field} is empty"));
+        }
+    }
+
+    let urls = [
+        ("appUrl", Some(&manifest.app_url)),
+        ("tokenTargetUrl", Some(&manifest.token_target_url)),
+        ("dataPrivacyUrl", manifest.data_privacy_url.as_ref()),
+        ("homepageUrl", manifest.homepage_url.as_ref()),
+        ("supportUrl", manifest.support_url.as_ref()),
+        ("brand.logo.default", manifest.brand.as_ref().map(|brand| &brand.logo.default)),
+    ];
+    for (field, url) in urls {
+        if let Some(url) = url.filter(|url| !is_absolute(url)) {
+            problems.push(format!("{field} {url:?} is not an absolute http(s) url"));
+        }
+    }
+
+    for extension in manifest.extensions.iter().flatten() {
+        let label = &extension.label;
+        for permission in &extension.permissions {
+            if !manifest.permissions.contains(permission) {
+                problems.push(format!("extension {label:?} requires {permission:?}, which the app doesn't request"));
+            }
+        }
+        match extension.target {
+            // app pages are opened inside the dashboard, relative to appUrl
+            SaleorAppExtensionTarget::AppPage if !extension.url.starts_with('/') => {
+                problems.push(format!("extension {label:?} targets APP_PAGE, its url {:?} has to be a path", extension.url));
+            }
+            SaleorAppExtensionTarget::Popup if !extension.url.starts_with('/') && !is_absolute(&extension.url) => {
+                problems.push(format!("extension {label:?} url {:?} is neither a path nor an absolute http(s) url", extension.url));
+            }
+            SaleorAppExtensionTarget::Popup if is_navigation(&extension.mount) => {
+                problems.push(format!("extension {label:?} is mounted in the navigation, which only opens APP_PAGE targets"));
+            }
+            SaleorAppExtensionTarget::Widget | SaleorAppExtensionTarget::NewTab if !extension.url.starts_with('/') && !is_absolute(&extension.url) => {
+                problems.push(format!("extension {label:?} url {:?} is neither a path nor an absolute http(s) url", extension.url));
+            }
+            _ => {}
+        }
+        // widgets are only rendered on details pages, which render nothing else
+        match (&extension.target, is_widget_mount(&extension.mount)) {
+            (SaleorAppExtensionTarget::Widget, false) => {
+                problems.push(format!("extension {label:?} targets WIDGET, which is only mounted at *_WIDGETS"));
+            }
+            (SaleorAppExtensionTarget::AppPage | SaleorAppExtensionTarget::Popup | SaleorAppExtensionTarget::NewTab, true) => {
+                problems.push(format!("extension {label:?} is mounted at {:?}, which only renders WIDGET targets", extension.mount));
+            }
+            _ => {}
+        }
+        let options = extension.options.as_ref();
+        if options.is_some_and(|options| options.new_tab_target.is_some()) && extension.target != SaleorAppExtensionTarget::NewTab {
+            problems.push(format!("extension {label:?} has a newTabTarget, but targets {:?}", extension.target));
+        }
+    }
+
+    let webhooks = manifest.webhooks.as_deref().unwrap_or_default();
+    if !webhooks.is_empty() && manifest.permissions.is_empty() {
+        problems.push("permissions are empty, the webhook queries can't read anything".to_string());
+    }
+    for webhook in webhooks {
+        let name = &webhook.name;
+        if webhook.query.trim().is_empty() {
+            problems.push(format!("webhook {name:?} has an empty query"));
+        }
+        if webhook.async_events.as_deref().unwrap_or_default().is_empty() && webhook.sync_events.as_deref().unwrap_or_default().is_empty() {
+            problems.push(format!("webhook {name:?} subscribes to no events"));
+        }
+        if !is_absolute(&webhook.target_url) {
+            problems.push(format!("webhook {name:?} targetUrl {:?} is not an absolute http(s) url", webhook.target_url));
+        }
+    }
+    problems
+}
+
+fn is_absolute(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+}
+
+fn is_widget_mount(mount: &SaleorAppExtensionMount) -> bool {
+    matches!(mount, SaleorAppExtensionMount::OrderDetailsWidgets | SaleorAppExtensionMount::ProductDetailsWidgets)
+}
+
+fn is_navigation(mount: &SaleorAppExtensionMount) -> bool {
+    matches!(
+        mount,
+        SaleorAppExtensionMount::NavigationCatalog
+            | SaleorAppExtensionMount::NavigationOrders
+            | SaleorAppExtensionMount::NavigationCustomers
+            | SaleorAppExtensionMount::NavigationDiscounts
+            | SaleorAppExtensionMount::NavigationTranslations
+            | SaleorAppExtensionMount::NavigationPages
+    )
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::{SaleorAppExtension, SaleorAppPermission, SaleorBrand, SaleorManifest};
+
+/// Fields of the manifest a deployment overrides without recompiling, read from `MANIFEST_FILE`.
+///
+/// The urls Saleor calls and the webhooks are bound to the handlers of the app and can't be
+/// overridden, everything else replaces the compiled value when set.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ManifestDefinition {
+    pub id: Option<String>,
+    pub version: Option<String>,
+    pub required_saleor_version: Option<String>,
+    pub name: Option<String>,
+    pub permissions: Option<Vec<SaleorAppPermission>>,
+    pub author: Option<String>,
+    pub about: Option<String>,
+    pub data_privacy_url: Option<String>,
+    pub homepage_url: Option<String>,
+    pub support_url: Option<String>,
+    pub extensions: Option<Vec<SaleorAppExtension>>,
+    pub brand: Option<SaleorBrand>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestFileError(pub String);
+
+impl std::fmt::Display for ManifestFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "manifest file error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ManifestFileError {}
+
+impl ManifestDefinition {
+    /// Reads a `.toml` or `.json` file, using the same field names as the manifest.
+    pub fn load(path: &Path) -> Result<Self, ManifestFileError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| ManifestFileError(format!("unable to read {}: {e}", path.display())))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => basic_toml::from_str(&contents).map_err(|e| ManifestFileError(format!("invalid {}: {e}", path.display()))),
+            Some("json") => serde_json::from_str(&contents).map_err(|e| ManifestFileError(format!("invalid {}: {e}", path.display()))),
+            _ => Err(ManifestFileError(format!("{} is neither a .toml nor a .json file", path.display()))),
+        }
+    }
+
+    /// The manifest with the fields of this definition that are set.
+    pub fn apply(&self, mut manifest: SaleorManifest) -> SaleorManifest {
+        let overrides = self.clone();
+        manifest.id = overrides.id.unwrap_or(manifest.id);
+        manifest.version = overrides.version.unwrap_or(manifest.version);
+        manifest.name = overrides.name.unwrap_or(manifest.name);
+        manifest.permissions = overrides.permissions.unwrap_or(manifest.permissions);
+        manifest.required_saleor_version = overrides.required_saleor_version.or(manifest.required_saleor_version);
+        manifest.author = overrides.author.or(manifest.author);
+        manifest.about = overrides.about.or(manifest.about);
+        manifest.data_privacy_url = overrides.data_privacy_url.or(manifest.data_privacy_url);
+        manifest.homepage_url = overrides.homepage_url.or(manifest.homepage_url);
+        manifest.support_url = overrides.support_url.or(manifest.support_url);
+        manifest.extensions = overrides.extensions.or(manifest.extensions);
+        manifest.brand = overrides.brand.or(manifest.brand);
+        manifest
+    }
+}
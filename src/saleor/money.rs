@@ -0,0 +1,240 @@
+//! Amounts of money as Saleor's GraphQL API and webhooks describe them.
+//!
+//! [`Money`] is an exact [`Decimal`] with its currency, serialized as Saleor's `Money` type
+//! (`{"amount": 12.5, "currency": "EUR"}`), [`TaxedMoney`] its `TaxedMoney` type. Arithmetic is
+//! exact and only [`Money::round`] rounds, to the precision of the currency and halves away from
+//! zero like Saleor does. Amounts in different currencies are never mixed, adding them fails with a
+//! [`MoneyError`], as does arithmetic that overflows and taxing at a rate of -100% or less.
+//!
+//! ```ignore
+//! let line = Money::new(dec!(9.99), "EUR").checked_mul(Decimal::from(3))?;
+//! let total = line.checked_add(&shipping)?.round();
+//! let taxed = TaxedMoney::from_net(total, dec!(19))?;
+//! ```
+
+use std::ops::Neg;
+
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::taxes::TaxedAmount;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoneyError(pub String);
+
+impl std::fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "money error: {}", self.0)
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// The number of decimal places amounts in `currency` (ISO 4217) are rounded to, and the exponent
+/// of its minor units, like in Stripe amounts.
+pub fn currency_decimal_places(currency: &str) -> u32 {
+    match currency.to_ascii_uppercase().as_str() {
+        // MGA has two decimals in ISO 4217, but Stripe charges it in whole units
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "MGA" | "PYG" | "RWF" | "UGX" | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+/// The sum of `amounts`, `None` if it overflows.
+pub(crate) fn checked_sum(amounts: impl IntoIterator<Item = Decimal>) -> Option<Decimal> {
+    amounts.into_iter().try_fold(Decimal::ZERO, |sum, amount| sum.checked_add(amount))
+}
+
+/// Rounds `amount` to the precision of `currency`, halves away from zero like Saleor does.
+pub fn round_money(amount: Decimal, currency: &str) -> Decimal {
+    amount.round_dp_with_strategy(currency_decimal_places(currency), RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// An amount in a currency, Saleor's `Money`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Money {
+    #[serde(with = "rust_decimal::serde::float")]
+    pub amount: Decimal,
+    /// ISO 4217 code, like `EUR`, always in uppercase
+    #[serde(deserialize_with = "deserialize_currency")]
+    pub currency: String,
+}
+
+fn deserialize_currency<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(String::deserialize(deserializer)?.to_ascii_uppercase())
+}
+
+impl Money {
+    /// `currency` is stored in uppercase, like Saleor sends it.
+    pub fn new(amount: Decimal, currency: impl Into<String>) -> Self {
+        Self { amount, currency: currency.into().to_ascii_uppercase() }
+    }
+
+    pub fn zero(currency: impl Into<String>) -> Self {
+        Self::new(Decimal::ZERO, currency)
+    }
+
+    /// `amount` given in the smallest unit of `currency`, like cents.
+    pub fn from_minor_units(amount: i64, currency: impl Into<String>) -> Self {
+        let currency = currency.into();
+        Self::new(Decimal::new(amount, currency_decimal_places(&currency)), currency)
+    }
+
+    /// The amount in the smallest unit of the currency, `None` if it doesn't fit.
+    pub fn minor_units(&self) -> Option<i64> {
+        let places = currency_decimal_places(&self.currency);
+        round_money(self.amount, &self.currency).checked_mul(Decimal::from(10i64.pow(places)))?.to_i64()
+    }
+
+    /// Rounded to the precision of the currency, see [`round_money`].
+    pub fn round(&self) -> Self {
+        Self::new(round_money(self.amount, &self.currency), self.currency.clone())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.amount.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.amount.is_sign_negative() && !self.amount.is_zero()
+    }
+
+    pub fn same_currency(&self, other: &Money) -> bool {
+        self.currency.eq_ignore_ascii_case(&other.currency)
+    }
+
+    fn check_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        match self.same_currency(other) {
+            true => Ok(()),
+            false => Err(MoneyError(format!("unable to combine {} with {}", self.currency, other.currency))),
+        }
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.check_currency(other)?;
+        let amount = self.amount.checked_add(other.amount).ok_or_else(|| self.overflow("adding", other.amount))?;
+        Ok(Self::new(amount, self.currency.clone()))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.check_currency(other)?;
+        let amount = self.amount.checked_sub(other.amount).ok_or_else(|| self.overflow("subtracting", other.amount))?;
+        Ok(Self::new(amount, self.currency.clone()))
+    }
+
+    /// Exact, [`Money::round`] rounds the result.
+    pub fn checked_mul(&self, factor: Decimal) -> Result<Money, MoneyError> {
+        let amount = self.amount.checked_mul(factor).ok_or_else(|| self.overflow("multiplying by", factor))?;
+        Ok(Self::new(amount, self.currency.clone()))
+    }
+
+    fn overflow(&self, operation: &str, operand: Decimal) -> MoneyError {
+        MoneyError(format!("{operation} {operand} overflows {self}"))
+    }
+
+    /// The sum of `amounts`, which all have to be in `currency`.
+    pub fn sum<'a>(currency: &str, amounts: impl IntoIterator<Item = &'a Money>) -> Result<Money, MoneyError> {
+        amounts.into_iter().try_fold(Self::zero(currency), |total, amount| total.checked_add(amount))
+    }
+
+    /// Splits the amount into parts proportional to `ratios`, rounded to the currency.
+    ///
+    /// The rounding difference goes to the largest part, so the parts add up to exactly the rounded
+    /// amount. Without ratios there are no parts; when they add up to zero the whole amount is the
+    /// first part.
+    pub fn allocate(&self, ratios: &[Decimal]) -> Result<Vec<Money>, MoneyError> {
+        let overflow = || MoneyError(format!("allocating {self} overflows"));
+        let total = self.round();
+        let sum = checked_sum(ratios.iter().copied()).ok_or_else(overflow)?;
+        if sum.is_zero() {
+            let mut parts: Vec<Money> = ratios.iter().map(|_| Self::zero(self.currency.clone())).collect();
+            if let Some(first) = parts.first_mut() {
+                *first = total;
+            }
+            return Ok(parts);
+        }
+
+        let mut parts = ratios
+            .iter()
+            .map(|ratio| {
+                let amount = total.amount.checked_mul(*ratio).and_then(|amount| amount.checked_div(sum)).ok_or_else(overflow)?;
+                Ok(Self::new(amount, self.currency.clone()).round())
+            })
+            .collect::<Result<Vec<Money>, MoneyError>>()?;
+        let remainder = checked_sum(parts.iter().map(|part| part.amount)).and_then(|allocated| total.amount.checked_sub(allocated)).ok_or_else(overflow)?;
+        if let Some(largest) = parts.iter_mut().max_by_key(|part| part.amount.abs()) {
+            largest.amount = largest.amount.checked_add(remainder).ok_or_else(overflow)?;
+        }
+        Ok(parts)
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let places = currency_decimal_places(&self.currency) as usize;
+        write!(f, "{:.*} {}", places, self.amount, self.currency)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Self::new(-self.amount, self.currency)
+    }
+}
+
+/// A net and a gross amount with the tax between them, Saleor's `TaxedMoney`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TaxedMoney {
+    pub net: Money,
+    pub gross: Money,
+    pub tax: Money,
+}
+
+impl TaxedMoney {
+    /// `amount` in `currency`, the tax being the difference of its net and gross amounts.
+    pub fn from_amount(amount: TaxedAmount, currency: impl Into<String>) -> Self {
+        let currency = currency.into();
+        Self {
+            net: Money::new(amount.net, currency.clone()),
+            gross: Money::new(amount.gross, currency.clone()),
+            tax: Money::new(amount.tax(), currency),
+        }
+    }
+
+    /// Adds the tax at `rate` (a percentage, like 19 for 19%) to a net amount.
+    pub fn from_net(net: Money, rate: Decimal) -> Result<Self, MoneyError> {
+        Ok(Self::from_amount(TaxedAmount::from_net(net.amount, rate, &net.currency)?, net.currency))
+    }
+
+    /// Takes the tax at `rate` (a percentage) out of a gross amount.
+    pub fn from_gross(gross: Money, rate: Decimal) -> Result<Self, MoneyError> {
+        Ok(Self::from_amount(TaxedAmount::from_gross(gross.amount, rate, &gross.currency)?, gross.currency))
+    }
+
+    /// An amount without tax.
+    pub fn untaxed(amount: Money) -> Self {
+        let amount = amount.round();
+        Self { net: amount.clone(), gross: amount.clone(), tax: Money::zero(amount.currency) }
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.gross.currency
+    }
+
+    pub fn checked_add(&self, other: &TaxedMoney) -> Result<TaxedMoney, MoneyError> {
+        Ok(Self {
+            net: self.net.checked_add(&other.net)?,
+            gross: self.gross.checked_add(&other.gross)?,
+            tax: self.tax.checked_add(&other.tax)?,
+        })
+    }
+}
+
+impl From<&TaxedMoney> for TaxedAmount {
+    fn from(money: &TaxedMoney) -> Self {
+        Self { net: money.net.amount, gross: money.gross.amount }
+    }
+}
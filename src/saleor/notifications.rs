@@ -0,0 +1,354 @@
+//! Emails to customers for orders and account requests, sent with a [`Mailer`].
+//!
+//! Merge [`router`] into the app with [`crate::router_ext::SaleorWebhooks`] and add [`manifest`] to the
+//! webhooks of the manifest. The sender of every installation is configured in its settings (see
+//! [`crate::email::SenderConfig`]), installations without one (and without `EMAIL_FROM`) get no
+//! emails. The account events replace `NOTIFY_USER` and need the `MANAGE_USERS` permission, the
+//! order events `MANAGE_ORDERS`.
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(notifications::router(Mailer::from_config(&config)?)))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! ```
+
+use askama::Template;
+use axum::{Router, routing::post, extract::State, response::{IntoResponse, Response}};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{email::{EmailMessage, Mailer}, http_client::HttpClient};
+
+use super::{money::Money, MetadataSettingsManager, SaleorApl, SaleorAsyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest, SettingsManager};
+
+pub const ORDER_CONFIRMED_PATH: &str = "/api/webhooks/notifications/order-confirmed";
+pub const FULFILLMENT_CREATED_PATH: &str = "/api/webhooks/notifications/fulfillment-created";
+pub const ACCOUNT_CONFIRMATION_REQUESTED_PATH: &str = "/api/webhooks/notifications/account-confirmation-requested";
+pub const ACCOUNT_SET_PASSWORD_REQUESTED_PATH: &str = "/api/webhooks/notifications/account-set-password-requested";
+pub const ACCOUNT_CHANGE_EMAIL_REQUESTED_PATH: &str = "/api/webhooks/notifications/account-change-email-requested";
+pub const ACCOUNT_DELETE_REQUESTED_PATH: &str = "/api/webhooks/notifications/account-delete-requested";
+
+/// The order selected for [`NotificationOrder`], also posted to chats by [`super::chat`].
+pub(super) const ORDER_FRAGMENT: &str = "order {
+        id
+        number
+        userEmail
+        channel { slug }
+        lines { productName variantName quantity totalPrice { gross { amount currency } } }
+        total { gross { amount currency } }
+      }";
+
+/// Subscription query of `ORDER_CONFIRMED`, matching [`OrderNotification`].
+fn order_confirmed_query() -> String {
+    format!("subscription {{
+  event {{
+    ... on OrderConfirmed {{
+      {ORDER_FRAGMENT}
+    }}
+  }}
+}}")
+}
+
+/// Subscription query of `FULFILLMENT_CREATED`, matching [`FulfillmentNotification`].
+fn fulfillment_created_query() -> String {
+    format!("subscription {{
+  event {{
+    ... on FulfillmentCreated {{
+      fulfillment {{ id trackingNumber lines {{ quantity orderLine {{ productName variantName }} }} }}
+      {ORDER_FRAGMENT}
+    }}
+  }}
+}}")
+}
+
+/// Subscription query of the `ACCOUNT_*_REQUESTED` events, matching [`AccountNotification`].
+fn account_query(event: &str) -> String {
+    let new_email = if event == "AccountChangeEmailRequested" { "newEmail" } else { "" };
+    format!("subscription {{
+  event {{
+    ... on {event} {{
+      redirectUrl
+      token
+      user {{ email firstName }}
+      channel {{ slug }}
+      shop {{ name }}
+      {new_email}
+    }}
+  }}
+}}")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NotificationChannel {
+    pub slug: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NotificationGrossMoney {
+    pub gross: Money,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationOrderLine {
+    pub product_name: String,
+    pub variant_name: String,
+    pub quantity: u32,
+    pub total_price: NotificationGrossMoney,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationOrder {
+    pub id: String,
+    pub number: String,
+    /// Missing for orders without a customer, which get no emails
+    pub user_email: Option<String>,
+    pub channel: NotificationChannel,
+    pub lines: Vec<NotificationOrderLine>,
+    pub total: NotificationGrossMoney,
+}
+
+/// Payload of `ORDER_CONFIRMED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrderNotification {
+    pub order: Option<NotificationOrder>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FulfilledOrderLine {
+    pub product_name: String,
+    pub variant_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationFulfillmentLine {
+    pub quantity: u32,
+    pub order_line: Option<FulfilledOrderLine>,
+}
+
+impl NotificationFulfillmentLine {
+    pub fn product_name(&self) -> String {
+        match &self.order_line {
+            Some(line) if line.variant_name.is_empty() => line.product_name.clone(),
+            Some(line) => format!("{} ({})", line.product_name, line.variant_name),
+            None => "Removed product".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationFulfillment {
+    pub id: String,
+    /// Empty if the shipment can't be tracked
+    pub tracking_number: String,
+    #[serde(default)]
+    pub lines: Vec<NotificationFulfillmentLine>,
+}
+
+/// Payload of `FULFILLMENT_CREATED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FulfillmentNotification {
+    pub fulfillment: Option<NotificationFulfillment>,
+    pub order: Option<NotificationOrder>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationUser {
+    pub email: String,
+    pub first_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NotificationShop {
+    pub name: String,
+}
+
+/// Payload of `ACCOUNT_CONFIRMATION_REQUESTED`, `ACCOUNT_SET_PASSWORD_REQUESTED`,
+/// `ACCOUNT_CHANGE_EMAIL_REQUESTED` and `ACCOUNT_DELETE_REQUESTED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountNotification {
+    /// The storefront page that completes the request, it gets the email and token as query params
+    pub redirect_url: Option<String>,
+    pub token: Option<String>,
+    pub user: Option<NotificationUser>,
+    pub channel: Option<NotificationChannel>,
+    pub shop: Option<NotificationShop>,
+    /// Only sent with `ACCOUNT_CHANGE_EMAIL_REQUESTED`, the confirmation goes there
+    pub new_email: Option<String>,
+}
+
+impl AccountNotification {
+    /// The `redirect_url` with the `email` and `token` Saleor expects back.
+    pub fn link(&self, email: &str) -> Option<String> {
+        let mut url = Url::parse(self.redirect_url.as_deref()?).ok()?;
+        url.query_pairs_mut().append_pair("email", email).append_pair("token", self.token.as_deref()?);
+        Some(url.into())
+    }
+}
+
+#[derive(Template)]
+#[template(path = "emails/order_confirmed.html")]
+struct OrderConfirmedEmail<'a> {
+    shop: &'a str,
+    subject: &'a str,
+    order: &'a NotificationOrder,
+}
+
+#[derive(Template)]
+#[template(path = "emails/fulfillment_created.html")]
+struct FulfillmentCreatedEmail<'a> {
+    shop: &'a str,
+    subject: &'a str,
+    order: &'a NotificationOrder,
+    fulfillment: &'a NotificationFulfillment,
+}
+
+#[derive(Template)]
+#[template(path = "emails/account_action.html")]
+struct AccountActionEmail<'a> {
+    shop: &'a str,
+    subject: &'a str,
+    text: &'a str,
+    action: &'a str,
+    link: &'a str,
+}
+
+/// The webhook routes of the notifications, they verify the signature of every delivery.
+///
+/// Settings are read with the [`HttpClient`] from the request extensions.
+pub fn router(mailer: Mailer) -> Router {
+    Router::new()
+        .route(ORDER_CONFIRMED_PATH, post(order_confirmed))
+        .route(FULFILLMENT_CREATED_PATH, post(fulfillment_created))
+        .route(ACCOUNT_CONFIRMATION_REQUESTED_PATH, post(account_requested))
+        .route(ACCOUNT_SET_PASSWORD_REQUESTED_PATH, post(account_requested))
+        .route(ACCOUNT_CHANGE_EMAIL_REQUESTED_PATH, post(account_requested))
+        .route(ACCOUNT_DELETE_REQUESTED_PATH, post(account_requested))
+        .with_state(mailer)
+}
+
+/// The webhooks of [`router`] for the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    let webhook = |name: &str, event: SaleorAsyncWebhookEvent, query: String, path: &str| SaleorWebhookManifest {
+        name: name.to_string(),
+        async_events: Some(vec![event]),
+        sync_events: None,
+        query,
+        target_url: format!("{}{}", base_url, path),
+        is_active: Some(true),
+    };
+    vec![
+        webhook("Order confirmed email", SaleorAsyncWebhookEvent::OrderConfirmed, order_confirmed_query(), ORDER_CONFIRMED_PATH),
+        webhook("Fulfillment created email", SaleorAsyncWebhookEvent::FulfillmentCreated, fulfillment_created_query(), FULFILLMENT_CREATED_PATH),
+        webhook("Account confirmation email", SaleorAsyncWebhookEvent::AccountConfirmationRequested, account_query("AccountConfirmationRequested"), ACCOUNT_CONFIRMATION_REQUESTED_PATH),
+        webhook("Set password email", SaleorAsyncWebhookEvent::AccountSetPasswordRequested, account_query("AccountSetPasswordRequested"), ACCOUNT_SET_PASSWORD_REQUESTED_PATH),
+        webhook("Change email confirmation", SaleorAsyncWebhookEvent::AccountChangeEmailRequested, account_query("AccountChangeEmailRequested"), ACCOUNT_CHANGE_EMAIL_REQUESTED_PATH),
+        webhook("Account deletion email", SaleorAsyncWebhookEvent::AccountDeleteRequested, account_query("AccountDeleteRequested"), ACCOUNT_DELETE_REQUESTED_PATH),
+    ]
+}
+
+/// Renders an email with the shop name, returning its subject and HTML.
+type RenderEmail<'a> = Box<dyn FnOnce(&str) -> Result<(String, String), askama::Error> + Send + 'a>;
+
+/// Sends the email rendered by `render` to `to` from the sender of the installation.
+///
+/// Emails that can't be sent because of the installation's configuration are skipped, failures of
+/// Saleor or the transport are answered with an error so Saleor retries the webhook.
+async fn send(mailer: &Mailer, client: HttpClient, apl: &SaleorApl, saleor_api_url: &str, to: &str, shop: Option<&str>, render: RenderEmail<'_>) -> Response {
+    let auth_data = match apl.get(&apl.id(saleor_api_url)).await {
+        Ok(Some(auth_data)) => auth_data,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(e) => return e.into_response(),
+    };
+    let settings = match MetadataSettingsManager::new(client, auth_data).get_all().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!(saleor_api_url, "unable to read email settings: {}", e);
+            return e.into_response();
+        }
+    };
+    let Some(sender) = mailer.sender(&settings) else {
+        warn!(saleor_api_url, "email not sent, the installation has no sender address");
+        return StatusCode::OK.into_response();
+    };
+
+    let shop = shop.or(sender.name.as_deref()).unwrap_or(&sender.address).to_string();
+    let (subject, html) = match render(&shop) {
+        Ok(email) => email,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("unable to render email: {}", e)).into_response(),
+    };
+    match mailer.send(&EmailMessage::new(&sender, to, subject, html)).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            warn!(saleor_api_url, "{}", e);
+            e.into_response()
+        }
+    }
+}
+
+async fn order_confirmed(State(mailer): State<Mailer>, client: HttpClient, apl: SaleorApl, webhook: SaleorWebhook<OrderNotification>) -> Response {
+    let Some(order) = webhook.payload.order else {
+        return StatusCode::OK.into_response();
+    };
+    let Some(to) = order.user_email.clone() else {
+        return StatusCode::OK.into_response();
+    };
+    let render: RenderEmail = Box::new(|shop| {
+        let subject = format!("Order #{} confirmed", order.number);
+        let html = OrderConfirmedEmail { shop, subject: &subject, order: &order }.render()?;
+        Ok((subject, html))
+    });
+    send(&mailer, client, &apl, &webhook.saleor_api_url, &to, None, render).await
+}
+
+async fn fulfillment_created(State(mailer): State<Mailer>, client: HttpClient, apl: SaleorApl, webhook: SaleorWebhook<FulfillmentNotification>) -> Response {
+    let (Some(order), Some(fulfillment)) = (webhook.payload.order, webhook.payload.fulfillment) else {
+        return StatusCode::OK.into_response();
+    };
+    let Some(to) = order.user_email.clone() else {
+        return StatusCode::OK.into_response();
+    };
+    let render: RenderEmail = Box::new(|shop| {
+        let subject = format!("Order #{} shipped", order.number);
+        let html = FulfillmentCreatedEmail { shop, subject: &subject, order: &order, fulfillment: &fulfillment }.render()?;
+        Ok((subject, html))
+    });
+    send(&mailer, client, &apl, &webhook.saleor_api_url, &to, None, render).await
+}
+
+/// Every `ACCOUNT_*_REQUESTED` event, the `saleor-event` header tells them apart.
+async fn account_requested(State(mailer): State<Mailer>, client: HttpClient, apl: SaleorApl, webhook: SaleorWebhook<AccountNotification>) -> Response {
+    let payload = webhook.payload;
+    let Some(user) = &payload.user else {
+        return StatusCode::OK.into_response();
+    };
+    let (to, subject, text, action) = match webhook.event.as_str() {
+        "account_confirmation_requested" => (&user.email, "Confirm your account", "Please confirm your email address to activate your account.", "Confirm account"),
+        "account_set_password_requested" => (&user.email, "Set your password", "Use the link below to set a new password for your account.", "Set password"),
+        "account_change_email_requested" => match &payload.new_email {
+            Some(new_email) => (new_email, "Confirm your new email address", "Please confirm this is the new email address of your account.", "Confirm email address"),
+            None => return StatusCode::OK.into_response(),
+        },
+        "account_delete_requested" => (&user.email, "Delete your account", "Use the link below to confirm you want your account deleted. This can't be undone.", "Delete account"),
+        event => return (StatusCode::BAD_REQUEST, format!("unexpected event {}", event)).into_response(),
+    };
+    let Some(link) = payload.link(&user.email) else {
+        warn!(saleor_api_url = %webhook.saleor_api_url, event = %webhook.event, "email not sent, the request has no redirect url or token");
+        return StatusCode::OK.into_response();
+    };
+
+    let shop = payload.shop.as_ref().map(|shop| shop.name.as_str());
+    let render: RenderEmail = Box::new(|shop| {
+        let html = AccountActionEmail { shop, subject, text, action, link: &link }.render()?;
+        Ok((subject.to_string(), html))
+    });
+    send(&mailer, client, &apl, &webhook.saleor_api_url, to, shop, render).await
+}
@@ -0,0 +1,253 @@
+//! Orders of the current installation for the app's frontend, `GET /api/orders`.
+//!
+//! The embedded UI lists orders without writing GraphQL: filters are query parameters, the orders
+//! are fetched with the permissions of the dashboard user (see [`UserClient`]) and returned as an
+//! [`OrdersPage`], whose shape stays the same when Saleor's schema changes. The next page is asked
+//! for with the `endCursor` of the previous one as `after`. The route needs `MANAGE_ORDERS`.
+
+use axum::{extract::Query, http::StatusCode, response::{IntoResponse, Response}, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use time::{format_description::well_known::Iso8601, Date};
+use utoipa::{IntoParams, ToSchema};
+
+use super::{money::Money, UserClient, UserTokenError};
+
+pub const ORDERS_PATH: &str = "/orders";
+
+/// Orders per page unless `first` asks for another number
+const DEFAULT_PAGE_SIZE: u32 = 20;
+/// The most orders a page has, Saleor refuses more
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// The statuses orders can be filtered by, Saleor's `OrderStatusFilter`.
+pub const ORDER_STATUS_FILTERS: &[&str] = &[
+    "READY_TO_FULFILL",
+    "READY_TO_CAPTURE",
+    "UNCONFIRMED",
+    "UNFULFILLED",
+    "PARTIALLY_FULFILLED",
+    "FULFILLED",
+    "CANCELED",
+];
+
+const ORDERS_QUERY: &str = "query AppOrders($first: Int!, $after: String, $filter: OrderFilterInput) {
+  orders(first: $first, after: $after, filter: $filter, sortBy: { field: CREATED_AT, direction: DESC }) {
+    totalCount
+    pageInfo { hasNextPage endCursor }
+    edges {
+      node {
+        id
+        number
+        status
+        created
+        userEmail
+        channel { id slug name }
+        total { gross { amount currency } }
+      }
+    }
+  }
+}";
+
+#[derive(Debug)]
+pub enum OrdersError {
+    InvalidQuery(String),
+    Saleor(UserTokenError),
+}
+
+impl std::fmt::Display for OrdersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidQuery(e) => write!(f, "invalid query: {}", e),
+            Self::Saleor(e) => write!(f, "unable to fetch orders: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OrdersError {}
+
+impl IntoResponse for OrdersError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            Self::Saleor(_) => StatusCode::BAD_GATEWAY,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct OrdersQuery {
+    /// Comma separated statuses, like `UNFULFILLED,PARTIALLY_FULFILLED`, see [`ORDER_STATUS_FILTERS`]
+    pub status: Option<String>,
+    /// `YYYY-MM-DD`, orders created on or after the day
+    pub created_from: Option<String>,
+    /// `YYYY-MM-DD`, orders created on or before the day
+    pub created_to: Option<String>,
+    /// Id of the channel the orders were placed in
+    pub channel: Option<String>,
+    /// Orders per page, 20 by default and at most 100
+    pub first: Option<u32>,
+    /// The `endCursor` of the previous page
+    pub after: Option<String>,
+}
+
+fn parse_date(name: &str, date: Option<&str>) -> Result<Option<String>, OrdersError> {
+    date.filter(|date| !date.is_empty())
+        .map(|date| {
+            Date::parse(date, &Iso8601::DATE)
+                .map(|_| date.to_string())
+                .map_err(|e| OrdersError::InvalidQuery(format!("invalid {name}: {e}")))
+        })
+        .transpose()
+}
+
+impl OrdersQuery {
+    /// The variables of the orders query.
+    fn variables(&self) -> Result<Value, OrdersError> {
+        let first = self.first.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&first) {
+            return Err(OrdersError::InvalidQuery(format!("first has to be between 1 and {MAX_PAGE_SIZE}")));
+        }
+
+        let mut filter = Map::new();
+        if let Some(status) = self.status.as_deref().filter(|status| !status.is_empty()) {
+            let statuses = status
+                .split(',')
+                .map(|status| status.trim().to_ascii_uppercase())
+                .map(|status| match ORDER_STATUS_FILTERS.contains(&status.as_str()) {
+                    true => Ok(Value::String(status)),
+                    false => Err(OrdersError::InvalidQuery(format!("unknown status {status}"))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            filter.insert("status".to_string(), Value::Array(statuses));
+        }
+        let created_from = parse_date("createdFrom", self.created_from.as_deref())?;
+        let created_to = parse_date("createdTo", self.created_to.as_deref())?;
+        if created_from.is_some() || created_to.is_some() {
+            filter.insert("created".to_string(), json!({ "gte": created_from, "lte": created_to }));
+        }
+        if let Some(channel) = self.channel.as_deref().filter(|channel| !channel.is_empty()) {
+            filter.insert("channels".to_string(), json!([channel]));
+        }
+
+        Ok(json!({
+            "first": first,
+            "after": self.after.as_deref().filter(|after| !after.is_empty()),
+            "filter": filter,
+        }))
+    }
+}
+
+/// A page of orders, newest first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrdersPage {
+    pub orders: Vec<OrderSummary>,
+    /// Orders matching the filters on every page
+    pub total_count: Option<u64>,
+    pub has_next_page: bool,
+    /// Pass it as `after` to get the next page
+    pub end_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderSummary {
+    pub id: String,
+    pub number: String,
+    /// Saleor's `OrderStatus`, like `UNFULFILLED`
+    pub status: String,
+    /// When the order was placed, RFC 3339
+    pub created: String,
+    pub user_email: Option<String>,
+    pub channel: OrderChannel,
+    /// Gross total, `{"amount": 12.5, "currency": "EUR"}`
+    #[schema(value_type = Object)]
+    pub total: Money,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct OrderChannel {
+    pub id: String,
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+struct OrdersData {
+    orders: OrderConnection,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderConnection {
+    total_count: Option<u64>,
+    page_info: PageInfo,
+    edges: Vec<OrderEdge>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OrderEdge {
+    node: OrderNode,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderNode {
+    id: String,
+    number: String,
+    status: String,
+    created: String,
+    user_email: Option<String>,
+    channel: OrderChannel,
+    total: OrderTotal,
+}
+
+#[derive(Deserialize)]
+struct OrderTotal {
+    gross: Money,
+}
+
+impl From<OrderConnection> for OrdersPage {
+    fn from(connection: OrderConnection) -> Self {
+        Self {
+            orders: connection
+                .edges
+                .into_iter()
+                .map(|edge| OrderSummary {
+                    id: edge.node.id,
+                    number: edge.node.number,
+                    status: edge.node.status,
+                    created: edge.node.created,
+                    user_email: edge.node.user_email,
+                    channel: edge.node.channel,
+                    total: edge.node.total.gross,
+                })
+                .collect(),
+            total_count: connection.total_count,
+            has_next_page: connection.page_info.has_next_page,
+            end_cursor: connection.page_info.end_cursor,
+        }
+    }
+}
+
+/// `GET /api/orders`, a page of the orders of the current installation.
+#[utoipa::path(get, path = "/api/orders", tag = "orders", params(OrdersQuery), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The matching orders, newest first", body = OrdersPage),
+    (status = 400, description = "A filter is invalid"),
+    (status = 502, description = "Saleor couldn't be asked for the orders"),
+))]
+pub async fn list_orders(client: UserClient, Query(query): Query<OrdersQuery>) -> Result<Json<OrdersPage>, OrdersError> {
+    let variables = query.variables()?;
+    let data: OrdersData = client.query("AppOrders", ORDERS_QUERY, variables).await.map_err(OrdersError::Saleor)?;
+    Ok(Json(data.orders.into()))
+}
@@ -0,0 +1,572 @@
+//! Building blocks of payment apps, which handle the transaction sync webhooks of Saleor.
+//!
+//! Implement [`PaymentGateway`] for the PSP and merge [`router`] into the app with
+//! [`crate::router_ext::SaleorWebhooks`], its webhooks are listed by [`manifest`]. The app needs the
+//! `HANDLE_PAYMENTS` permission.
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(payments::router(MyPsp::new())))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! let webhooks = [webhooks::manifest(base_url), payments::manifest(base_url)].concat();
+//! ```
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{Router, routing::post, extract::State, response::{IntoResponse, Response}, Json};
+use reqwest::StatusCode;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::http_client::HttpClient;
+
+use super::{graphql::{mutation_errors, run_app_graphql}, money::Money, AuthData, SaleorSyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest};
+
+pub const PAYMENT_GATEWAY_INITIALIZE_SESSION_PATH: &str = "/api/webhooks/payments/gateway-initialize-session";
+pub const TRANSACTION_INITIALIZE_SESSION_PATH: &str = "/api/webhooks/payments/transaction-initialize-session";
+pub const TRANSACTION_PROCESS_SESSION_PATH: &str = "/api/webhooks/payments/transaction-process-session";
+pub const TRANSACTION_CHARGE_REQUESTED_PATH: &str = "/api/webhooks/payments/transaction-charge-requested";
+pub const TRANSACTION_REFUND_REQUESTED_PATH: &str = "/api/webhooks/payments/transaction-refund-requested";
+pub const TRANSACTION_CANCELATION_REQUESTED_PATH: &str = "/api/webhooks/payments/transaction-cancelation-requested";
+
+const SOURCE_OBJECT_FRAGMENT: &str = "sourceObject {
+        __typename
+        ... on Checkout { id channel { slug } }
+        ... on Order { id channel { slug } }
+      }";
+
+/// Subscription query of `PAYMENT_GATEWAY_INITIALIZE_SESSION`, matching [`PaymentGatewayInitializeSession`].
+fn payment_gateway_initialize_session_query() -> String {
+    format!("subscription {{
+  event {{
+    ... on PaymentGatewayInitializeSession {{
+      {SOURCE_OBJECT_FRAGMENT}
+      amount
+      data
+    }}
+  }}
+}}")
+}
+
+/// Subscription query of `TRANSACTION_INITIALIZE_SESSION` and `TRANSACTION_PROCESS_SESSION`, matching [`TransactionSession`].
+fn transaction_session_query(event: &str) -> String {
+    format!("subscription {{
+  event {{
+    ... on {event} {{
+      {SOURCE_OBJECT_FRAGMENT}
+      transaction {{ id pspReference }}
+      action {{ amount currency actionType }}
+      data
+      merchantReference
+      customerIpAddress
+    }}
+  }}
+}}")
+}
+
+/// Subscription query of the `TRANSACTION_*_REQUESTED` events, matching [`TransactionActionRequest`].
+fn transaction_action_query(event: &str) -> String {
+    format!("subscription {{
+  event {{
+    ... on {event} {{
+      transaction {{ id pspReference }}
+      action {{ amount currency actionType }}
+    }}
+  }}
+}}")
+}
+
+const TRANSACTION_EVENT_REPORT_MUTATION: &str = "mutation TransactionEventReport(
+  $id: ID!
+  $pspReference: String!
+  $type: TransactionEventTypeEnum!
+  $amount: PositiveDecimal!
+  $time: DateTime
+  $externalUrl: String
+  $message: String
+  $availableActions: [TransactionActionEnum!]
+) {
+  transactionEventReport(
+    id: $id
+    pspReference: $pspReference
+    type: $type
+    amount: $amount
+    time: $time
+    externalUrl: $externalUrl
+    message: $message
+    availableActions: $availableActions
+  ) {
+    alreadyProcessed
+    errors { field message code }
+  }
+}";
+
+#[derive(Debug)]
+pub struct PaymentError(pub String);
+
+impl std::fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "payment error: {}", self.0)
+    }
+}
+
+impl std::error::Error for PaymentError {}
+
+/// PSPs retry notifications that aren't answered with a success.
+impl IntoResponse for PaymentError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+/// How a payment is taken, `TransactionFlowStrategyEnum` in the schema.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionFlowStrategy {
+    Authorization,
+    Charge,
+}
+
+/// What can be done with a transaction afterwards, `TransactionActionEnum` in the schema.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionActionKind {
+    Charge,
+    Refund,
+    Cancel,
+}
+
+/// The result of `TRANSACTION_INITIALIZE_SESSION` and `TRANSACTION_PROCESS_SESSION`.
+///
+/// `*_REQUEST` means the PSP is still processing, `*_ACTION_REQUIRED` that the customer has to do
+/// something first (like 3-D Secure), followed by `TRANSACTION_PROCESS_SESSION`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionSessionResult {
+    ChargeSuccess,
+    ChargeFailure,
+    ChargeRequest,
+    ChargeActionRequired,
+    AuthorizationSuccess,
+    AuthorizationFailure,
+    AuthorizationRequest,
+    AuthorizationActionRequired,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionChargeResult {
+    ChargeSuccess,
+    ChargeFailure,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionRefundResult {
+    RefundSuccess,
+    RefundFailure,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionCancelResult {
+    CancelSuccess,
+    CancelFailure,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PaymentChannel {
+    pub slug: String,
+}
+
+/// The checkout or order that is being paid.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "__typename")]
+pub enum SourceObject {
+    Checkout { id: String, channel: PaymentChannel },
+    Order { id: String, channel: PaymentChannel },
+}
+
+impl SourceObject {
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Checkout { id, .. } | Self::Order { id, .. } => id,
+        }
+    }
+
+    pub fn channel(&self) -> &PaymentChannel {
+        match self {
+            Self::Checkout { channel, .. } | Self::Order { channel, .. } => channel,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionItem {
+    pub id: String,
+    pub psp_reference: Option<String>,
+}
+
+/// The payment the storefront asks for in a transaction session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionSessionAction {
+    #[serde(with = "rust_decimal::serde::float")]
+    pub amount: Decimal,
+    pub currency: String,
+    pub action_type: TransactionFlowStrategy,
+}
+
+impl TransactionSessionAction {
+    pub fn money(&self) -> Money {
+        Money::new(self.amount, self.currency.clone())
+    }
+}
+
+/// What a staff user or Saleor asks to be done with an existing transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionRequestedAction {
+    /// Missing for cancelations and when the whole remaining amount is meant
+    #[serde(default, with = "rust_decimal::serde::float_option")]
+    pub amount: Option<Decimal>,
+    pub currency: String,
+    pub action_type: TransactionActionKind,
+}
+
+impl TransactionRequestedAction {
+    /// `None` when the whole remaining amount is meant.
+    pub fn money(&self) -> Option<Money> {
+        self.amount.map(|amount| Money::new(amount, self.currency.clone()))
+    }
+}
+
+/// Payload of `PAYMENT_GATEWAY_INITIALIZE_SESSION`, sent when the storefront prepares the payment form.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentGatewayInitializeSession {
+    pub source_object: SourceObject,
+    #[serde(default, with = "rust_decimal::serde::float_option")]
+    pub amount: Option<Decimal>,
+    /// Whatever the storefront passed along
+    pub data: Option<Value>,
+}
+
+/// Payload of `TRANSACTION_INITIALIZE_SESSION` and `TRANSACTION_PROCESS_SESSION`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionSession {
+    pub source_object: SourceObject,
+    pub transaction: TransactionItem,
+    pub action: TransactionSessionAction,
+    /// Whatever the storefront passed along
+    pub data: Option<Value>,
+    /// Identifies the transaction towards the PSP, e.g. as its idempotency key
+    pub merchant_reference: String,
+    pub customer_ip_address: Option<String>,
+}
+
+/// Payload of `TRANSACTION_CHARGE_REQUESTED`, `TRANSACTION_REFUND_REQUESTED` and `TRANSACTION_CANCELATION_REQUESTED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionActionRequest {
+    pub transaction: Option<TransactionItem>,
+    pub action: TransactionRequestedAction,
+}
+
+impl TransactionActionRequest {
+    /// The PSP reference of the transaction, empty if it hasn't got one.
+    pub fn psp_reference(&self) -> &str {
+        self.transaction.as_ref().and_then(|transaction| transaction.psp_reference.as_deref()).unwrap_or_default()
+    }
+}
+
+/// Response to `PAYMENT_GATEWAY_INITIALIZE_SESSION`, `data` is handed to the storefront as is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct PaymentGatewayInitializeSessionResponse {
+    pub data: Option<Value>,
+}
+
+/// Response to `TRANSACTION_INITIALIZE_SESSION` and `TRANSACTION_PROCESS_SESSION`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionSessionResponse {
+    /// Required unless the result is a failure or requires an action
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psp_reference: Option<String>,
+    pub result: TransactionSessionResult,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub amount: Decimal,
+    /// Handed to the storefront, e.g. what it needs for an action
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    /// RFC 3339 time of the event, Saleor uses the time of the response otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// Link to the transaction in the dashboard of the PSP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Actions the dashboard offers for the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions: Option<Vec<TransactionActionKind>>,
+}
+
+impl TransactionSessionResponse {
+    pub fn new(result: TransactionSessionResult, amount: Decimal) -> Self {
+        Self { psp_reference: None, result, amount, data: None, time: None, external_url: None, message: None, actions: None }
+    }
+
+    pub fn with_psp_reference(mut self, psp_reference: impl Into<String>) -> Self {
+        self.psp_reference = Some(psp_reference.into());
+        self
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn with_external_url(mut self, external_url: impl Into<String>) -> Self {
+        self.external_url = Some(external_url.into());
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_actions(mut self, actions: &[TransactionActionKind]) -> Self {
+        self.actions = Some(actions.to_vec());
+        self
+    }
+}
+
+/// Response to the `TRANSACTION_*_REQUESTED` events, `R` is the result type of the event.
+///
+/// Without a result Saleor waits for the outcome to be reported with `transactionEventReport`,
+/// for PSPs that process the request asynchronously.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionActionResponse<R> {
+    pub psp_reference: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<R>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "rust_decimal::serde::float_option")]
+    pub amount: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions: Option<Vec<TransactionActionKind>>,
+}
+
+impl<R> TransactionActionResponse<R> {
+    pub fn new(psp_reference: impl Into<String>, result: R, amount: Option<Decimal>) -> Self {
+        Self { result: Some(result), amount, ..Self::pending(psp_reference) }
+    }
+
+    /// The PSP accepted the request and reports the outcome later.
+    pub fn pending(psp_reference: impl Into<String>) -> Self {
+        Self { psp_reference: psp_reference.into(), result: None, amount: None, time: None, external_url: None, message: None, actions: None }
+    }
+
+    pub fn with_external_url(mut self, external_url: impl Into<String>) -> Self {
+        self.external_url = Some(external_url.into());
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_actions(mut self, actions: &[TransactionActionKind]) -> Self {
+        self.actions = Some(actions.to_vec());
+        self
+    }
+}
+
+/// Everything that can happen to a transaction, `TransactionEventTypeEnum` in the schema.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionEventType {
+    AuthorizationSuccess,
+    AuthorizationFailure,
+    AuthorizationAdjustment,
+    AuthorizationRequest,
+    AuthorizationActionRequired,
+    ChargeActionRequired,
+    ChargeSuccess,
+    ChargeFailure,
+    ChargeBack,
+    ChargeRequest,
+    RefundSuccess,
+    RefundFailure,
+    RefundReverse,
+    RefundRequest,
+    CancelSuccess,
+    CancelFailure,
+    CancelRequest,
+    Info,
+}
+
+/// An outcome the PSP notified about after the webhook was answered, see [`report_transaction_event`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionEventReport {
+    /// Saleor ignores events it already got with the same reference, type and amount
+    pub psp_reference: String,
+    #[serde(rename = "type")]
+    pub event_type: TransactionEventType,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub amount: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_actions: Option<Vec<TransactionActionKind>>,
+}
+
+impl TransactionEventReport {
+    pub fn new(psp_reference: impl Into<String>, event_type: TransactionEventType, amount: Decimal) -> Self {
+        Self { psp_reference: psp_reference.into(), event_type, amount, time: None, external_url: None, message: None, available_actions: None }
+    }
+
+    pub fn with_external_url(mut self, external_url: impl Into<String>) -> Self {
+        self.external_url = Some(external_url.into());
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_actions(mut self, actions: &[TransactionActionKind]) -> Self {
+        self.available_actions = Some(actions.to_vec());
+        self
+    }
+}
+
+/// Reports an event of the transaction `transaction_id` with `transactionEventReport`, the app needs
+/// the `HANDLE_PAYMENTS` permission. Returns whether Saleor already knew the event.
+pub async fn report_transaction_event(client: &HttpClient, auth_data: &AuthData, transaction_id: &str, event: &TransactionEventReport) -> Result<bool, PaymentError> {
+    let mut variables = serde_json::to_value(event).map_err(|e| PaymentError(e.to_string()))?;
+    variables["id"] = Value::from(transaction_id);
+    let data: Value = run_app_graphql(client, auth_data, "TransactionEventReport", TRANSACTION_EVENT_REPORT_MUTATION, variables)
+        .await
+        .map_err(|e| PaymentError(e.to_string()))?;
+    if let Some(errors) = mutation_errors(&data) {
+        return Err(PaymentError(format!("unable to report event of transaction {}: {}", transaction_id, Value::from(errors.clone()))));
+    }
+    Ok(data["transactionEventReport"]["alreadyProcessed"].as_bool().unwrap_or_default())
+}
+
+pub type TransactionChargeResponse = TransactionActionResponse<TransactionChargeResult>;
+pub type TransactionRefundResponse = TransactionActionResponse<TransactionRefundResult>;
+pub type TransactionCancelResponse = TransactionActionResponse<TransactionCancelResult>;
+
+/// A payment service provider, one method per transaction webhook.
+///
+/// Every method gets the Saleor API url of the installation the webhook came from. Failures are
+/// answered with a `*_FAILURE` result and a message, Saleor shows it to the staff user or customer.
+#[async_trait]
+pub trait PaymentGateway: Send + Sync + 'static {
+    /// Returns what the storefront needs to render the payment form, like a public key of the PSP.
+    async fn initialize_gateway(&self, _saleor_api_url: &str, _payload: PaymentGatewayInitializeSession) -> PaymentGatewayInitializeSessionResponse {
+        PaymentGatewayInitializeSessionResponse::default()
+    }
+
+    /// Starts a payment for the checkout or order.
+    async fn initialize_transaction(&self, saleor_api_url: &str, payload: TransactionSession) -> TransactionSessionResponse;
+
+    /// Continues a payment that required an action of the customer.
+    async fn process_transaction(&self, saleor_api_url: &str, payload: TransactionSession) -> TransactionSessionResponse;
+
+    async fn charge(&self, _saleor_api_url: &str, payload: TransactionActionRequest) -> TransactionChargeResponse {
+        TransactionActionResponse::new(payload.psp_reference(), TransactionChargeResult::ChargeFailure, payload.action.amount)
+            .with_message("charging is not supported")
+    }
+
+    async fn refund(&self, _saleor_api_url: &str, payload: TransactionActionRequest) -> TransactionRefundResponse {
+        TransactionActionResponse::new(payload.psp_reference(), TransactionRefundResult::RefundFailure, payload.action.amount)
+            .with_message("refunds are not supported")
+    }
+
+    async fn cancel(&self, _saleor_api_url: &str, payload: TransactionActionRequest) -> TransactionCancelResponse {
+        TransactionActionResponse::new(payload.psp_reference(), TransactionCancelResult::CancelFailure, payload.action.amount)
+            .with_message("cancelation is not supported")
+    }
+}
+
+/// The webhook routes of a payment app, they verify the signature of every delivery.
+pub fn router(gateway: impl PaymentGateway) -> Router {
+    let gateway: Arc<dyn PaymentGateway> = Arc::new(gateway);
+    Router::new()
+        .route(PAYMENT_GATEWAY_INITIALIZE_SESSION_PATH, post(payment_gateway_initialize_session))
+        .route(TRANSACTION_INITIALIZE_SESSION_PATH, post(transaction_initialize_session))
+        .route(TRANSACTION_PROCESS_SESSION_PATH, post(transaction_process_session))
+        .route(TRANSACTION_CHARGE_REQUESTED_PATH, post(transaction_charge_requested))
+        .route(TRANSACTION_REFUND_REQUESTED_PATH, post(transaction_refund_requested))
+        .route(TRANSACTION_CANCELATION_REQUESTED_PATH, post(transaction_cancelation_requested))
+        .with_state(gateway)
+}
+
+/// The webhooks of [`router`] for the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    let webhook = |name: &str, event: SaleorSyncWebhookEvent, query: String, path: &str| SaleorWebhookManifest {
+        name: name.to_string(),
+        async_events: None,
+        sync_events: Some(vec![event]),
+        query,
+        target_url: format!("{}{}", base_url, path),
+        is_active: Some(true),
+    };
+    vec![
+        webhook("Payment gateway initialize session", SaleorSyncWebhookEvent::PaymentGatewayInitializeSession, payment_gateway_initialize_session_query(), PAYMENT_GATEWAY_INITIALIZE_SESSION_PATH),
+        webhook("Transaction initialize session", SaleorSyncWebhookEvent::TransactionInitializeSession, transaction_session_query("TransactionInitializeSession"), TRANSACTION_INITIALIZE_SESSION_PATH),
+        webhook("Transaction process session", SaleorSyncWebhookEvent::TransactionProcessSession, transaction_session_query("TransactionProcessSession"), TRANSACTION_PROCESS_SESSION_PATH),
+        webhook("Transaction charge requested", SaleorSyncWebhookEvent::TransactionChargeRequested, transaction_action_query("TransactionChargeRequested"), TRANSACTION_CHARGE_REQUESTED_PATH),
+        webhook("Transaction refund requested", SaleorSyncWebhookEvent::TransactionRefundRequested, transaction_action_query("TransactionRefundRequested"), TRANSACTION_REFUND_REQUESTED_PATH),
+        webhook("Transaction cancelation requested", SaleorSyncWebhookEvent::TransactionCancelationRequested, transaction_action_query("TransactionCancelationRequested"), TRANSACTION_CANCELATION_REQUESTED_PATH),
+    ]
+}
+
+type Gateway = State<Arc<dyn PaymentGateway>>;
+
+async fn payment_gateway_initialize_session(State(gateway): Gateway, webhook: SaleorWebhook<PaymentGatewayInitializeSession>) -> Json<PaymentGatewayInitializeSessionResponse> {
+    Json(gateway.initialize_gateway(&webhook.saleor_api_url, webhook.payload).await)
+}
+
+async fn transaction_initialize_session(State(gateway): Gateway, webhook: SaleorWebhook<TransactionSession>) -> Json<TransactionSessionResponse> {
+    Json(gateway.initialize_transaction(&webhook.saleor_api_url, webhook.payload).await)
+}
+
+async fn transaction_process_session(State(gateway): Gateway, webhook: SaleorWebhook<TransactionSession>) -> Json<TransactionSessionResponse> {
+    Json(gateway.process_transaction(&webhook.saleor_api_url, webhook.payload).await)
+}
+
+async fn transaction_charge_requested(State(gateway): Gateway, webhook: SaleorWebhook<TransactionActionRequest>) -> Json<TransactionChargeResponse> {
+    Json(gateway.charge(&webhook.saleor_api_url, webhook.payload).await)
+}
+
+async fn transaction_refund_requested(State(gateway): Gateway, webhook: SaleorWebhook<TransactionActionRequest>) -> Json<TransactionRefundResponse> {
+    Json(gateway.refund(&webhook.saleor_api_url, webhook.payload).await)
+}
+
+async fn transaction_cancelation_requested(State(gateway): Gateway, webhook: SaleorWebhook<TransactionActionRequest>) -> Json<TransactionCancelResponse> {
+    Json(gateway.cancel(&webhook.saleor_api_url, webhook.payload).await)
+}
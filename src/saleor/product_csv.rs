@@ -0,0 +1,447 @@
+//! Product import and export as CSV, for merchandising teams editing the catalog in a spreadsheet.
+//!
+//! `GET /api/export/products.csv` streams every product of the installation, fetching them page by
+//! page while the download is running. `POST /api/import/products.csv` takes a file in the same
+//! format as body: rows without an `id` are created with `productBulkCreate`, rows with one update
+//! that product. Rows are applied independently, the response reports the errors of every row by
+//! its line in the file. Both routes are part of the `/api` router and need `MANAGE_PRODUCTS`.
+//!
+//! Columns that can't be written back, like `product_type` and `updated_at`, are only exported to
+//! make the file readable and ignored on import.
+
+use std::time::Instant;
+
+use axum::{body::{Bytes, StreamBody}, http::header::{CONTENT_DISPOSITION, CONTENT_TYPE}, response::{IntoResponse, Response}, Json};
+use futures_util::stream::{self, Stream};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::http_client::HttpClient;
+
+use super::{graphql::run_app_graphql, AuthData, CurrentInstallation};
+
+pub const EXPORT_PATH: &str = "/export/products.csv";
+pub const IMPORT_PATH: &str = "/import/products.csv";
+
+/// Products are fetched in pages of this size while exporting
+const EXPORT_PAGE_SIZE: usize = 100;
+/// Rows sent in a single mutation while importing
+const IMPORT_BATCH_SIZE: usize = 50;
+
+/// The columns of the file, in order.
+pub const COLUMNS: [&str; 11] = [
+    "id",
+    "name",
+    "slug",
+    "product_type_id",
+    "product_type",
+    "category_id",
+    "category",
+    "seo_title",
+    "seo_description",
+    "external_reference",
+    "updated_at",
+];
+
+const PRODUCTS_QUERY: &str = "query ProductsExport($first: Int!, $after: String) {
+  products(first: $first, after: $after) {
+    pageInfo { hasNextPage endCursor }
+    edges {
+      node {
+        id
+        name
+        slug
+        seoTitle
+        seoDescription
+        externalReference
+        updatedAt
+        productType { id name }
+        category { id name }
+      }
+    }
+  }
+}";
+
+const PRODUCT_BULK_CREATE_MUTATION: &str = "mutation ProductBulkCreate($products: [ProductBulkCreateInput!]!) {
+  productBulkCreate(products: $products, errorPolicy: REJECT_FAILED_ROWS) {
+    results {
+      product { id }
+      errors { path message code }
+    }
+    errors { path message code }
+  }
+}";
+
+#[derive(Debug)]
+pub struct ProductCsvError(pub String);
+
+impl std::fmt::Display for ProductCsvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "product csv error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ProductCsvError {}
+
+impl IntoResponse for ProductCsvError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Named {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExportedProduct {
+    id: String,
+    name: String,
+    slug: String,
+    seo_title: Option<String>,
+    seo_description: Option<String>,
+    external_reference: Option<String>,
+    updated_at: String,
+    product_type: Named,
+    category: Option<Named>,
+}
+
+impl ExportedProduct {
+    fn record(self) -> [String; COLUMNS.len()] {
+        let (category_id, category) = self.category.map(|category| (category.id, category.name)).unwrap_or_default();
+        [
+            self.id,
+            self.name,
+            self.slug,
+            self.product_type.id,
+            self.product_type.name,
+            category_id,
+            category,
+            self.seo_title.unwrap_or_default(),
+            self.seo_description.unwrap_or_default(),
+            self.external_reference.unwrap_or_default(),
+            self.updated_at,
+        ]
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProductEdge {
+    node: ExportedProduct,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ProductConnection {
+    page_info: PageInfo,
+    edges: Vec<ProductEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProductsPage {
+    products: ProductConnection,
+}
+
+fn write_records<R: AsRef<[u8]>>(records: impl IntoIterator<Item = impl IntoIterator<Item = R>>) -> Result<Bytes, ProductCsvError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in records {
+        writer.write_record(record).map_err(|e| ProductCsvError(e.to_string()))?;
+    }
+    writer.into_inner().map(Bytes::from).map_err(|e| ProductCsvError(e.to_string()))
+}
+
+enum ExportState {
+    Header,
+    Page(Option<String>),
+    Done,
+}
+
+/// The CSV of every product, one chunk per page of products.
+///
+/// A failing page ends the stream with an error, which aborts the download instead of leaving a
+/// truncated file that looks complete.
+pub fn export_stream(client: HttpClient, auth_data: AuthData) -> impl Stream<Item = Result<Bytes, ProductCsvError>> {
+    stream::unfold(ExportState::Header, move |state| {
+        let client = client.clone();
+        let auth_data = auth_data.clone();
+        async move {
+            let after = match state {
+                ExportState::Header => return Some((write_records([COLUMNS]), ExportState::Page(None))),
+                ExportState::Page(after) => after,
+                ExportState::Done => return None,
+            };
+            let variables = json!({ "first": EXPORT_PAGE_SIZE, "after": after });
+            let page: ProductsPage = match run_app_graphql(&client, &auth_data, "ProductsExport", PRODUCTS_QUERY, variables).await {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!(saleor_api_url = %auth_data.saleor_api_url, "product export failed: {}", e);
+                    return Some((Err(ProductCsvError(e.to_string())), ExportState::Done));
+                }
+            };
+            let next = match page.products.page_info {
+                PageInfo { has_next_page: true, end_cursor: Some(cursor) } => ExportState::Page(Some(cursor)),
+                _ => ExportState::Done,
+            };
+            Some((write_records(page.products.edges.into_iter().map(|edge| edge.node.record())), next))
+        }
+    })
+}
+
+/// `GET /api/export/products.csv`, downloads the catalog of the current installation.
+#[utoipa::path(get, path = "/api/export/products.csv", tag = "products", security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The catalog of the installation", content_type = "text/csv", body = String),
+))]
+pub async fn export_products(CurrentInstallation(auth_data): CurrentInstallation, client: HttpClient) -> Response {
+    (
+        [(CONTENT_TYPE, "text/csv; charset=utf-8"), (CONTENT_DISPOSITION, "attachment; filename=\"products.csv\"")],
+        StreamBody::new(export_stream(client, auth_data)),
+    ).into_response()
+}
+
+/// A row of an imported file.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProductRow {
+    /// The line of the row in the file, the header is line 1
+    #[serde(skip)]
+    pub line: usize,
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub slug: String,
+    #[serde(default)]
+    pub product_type_id: String,
+    #[serde(default)]
+    pub category_id: String,
+    #[serde(default)]
+    pub seo_title: String,
+    #[serde(default)]
+    pub seo_description: String,
+    #[serde(default)]
+    pub external_reference: String,
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value.trim()).filter(|value| !value.is_empty())
+}
+
+impl ProductRow {
+    /// The fields shared by `ProductBulkCreateInput` and `ProductInput`, empty cells are left out.
+    fn input(&self) -> Map<String, Value> {
+        let mut input = Map::new();
+        for (field, value) in [("name", &self.name), ("slug", &self.slug), ("category", &self.category_id), ("externalReference", &self.external_reference)] {
+            if let Some(value) = non_empty(value) {
+                input.insert(field.to_string(), value.into());
+            }
+        }
+        let mut seo = Map::new();
+        for (field, value) in [("title", &self.seo_title), ("description", &self.seo_description)] {
+            if let Some(value) = non_empty(value) {
+                seo.insert(field.to_string(), value.into());
+            }
+        }
+        if !seo.is_empty() {
+            input.insert("seo".to_string(), seo.into());
+        }
+        input
+    }
+
+    fn create_input(&self) -> Result<Value, RowError> {
+        let mut input = self.input();
+        let Some(product_type) = non_empty(&self.product_type_id) else {
+            return Err(RowError::new(Some("product_type_id"), "required to create a product"));
+        };
+        if !input.contains_key("name") {
+            return Err(RowError::new(Some("name"), "required to create a product"));
+        }
+        input.insert("productType".to_string(), product_type.into());
+        Ok(input.into())
+    }
+}
+
+/// What went wrong with a row, it wasn't applied.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RowError {
+    /// The line of the row in the file, the header is line 1
+    pub line: usize,
+    /// The column or input field Saleor complained about
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl RowError {
+    fn new(field: Option<&str>, message: &str) -> Self {
+        Self { line: 0, field: field.map(str::to_string), message: message.to_string() }
+    }
+
+    fn at(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+}
+
+/// The outcome of an import, returned as JSON.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct ImportReport {
+    pub created: usize,
+    pub updated: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// Reads the rows of `csv` with their line, rows that can't be read are reported right away.
+///
+/// Fails when the header lacks both `id` and `name`, the file is most likely not a product export.
+pub fn parse_rows(csv: &[u8]) -> Result<(Vec<ProductRow>, Vec<RowError>), String> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(csv);
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    if !headers.iter().any(|header| header == "id" || header == "name") {
+        return Err("the file needs at least an id or a name column".to_string());
+    }
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|position| position.line() as usize).unwrap_or_default();
+                errors.push(RowError::new(None, &e.to_string()).at(line));
+                continue;
+            }
+        };
+        let line = record.position().map(|position| position.line() as usize).unwrap_or_default();
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        match record.deserialize::<ProductRow>(Some(&headers)) {
+            Ok(row) => rows.push(ProductRow { line, ..row }),
+            Err(e) => errors.push(RowError::new(None, &e.to_string()).at(line)),
+        }
+    }
+    Ok((rows, errors))
+}
+
+/// The errors Saleor reported in a bulk result or mutation payload.
+fn saleor_errors(errors: Option<&Value>, field_key: &str) -> Vec<RowError> {
+    errors
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|error| {
+            let message = error["message"].as_str().or(error["code"].as_str()).unwrap_or("invalid");
+            RowError::new(error[field_key].as_str(), message)
+        })
+        .collect()
+}
+
+async fn create_products(client: &HttpClient, auth_data: &AuthData, rows: &[ProductRow], report: &mut ImportReport) {
+    let mut inputs = Vec::new();
+    let mut lines = Vec::new();
+    for row in rows {
+        match row.create_input() {
+            Ok(input) => {
+                inputs.push(input);
+                lines.push(row.line);
+            }
+            Err(error) => report.errors.push(error.at(row.line)),
+        }
+    }
+    if inputs.is_empty() {
+        return;
+    }
+
+    let variables = json!({ "products": inputs });
+    let data: Value = match run_app_graphql(client, auth_data, "ProductBulkCreate", PRODUCT_BULK_CREATE_MUTATION, variables).await {
+        Ok(data) => data,
+        Err(e) => {
+            report.errors.extend(lines.iter().map(|line| RowError::new(None, &e.to_string()).at(*line)));
+            return;
+        }
+    };
+    let results = data.pointer("/productBulkCreate/results").and_then(Value::as_array).cloned().unwrap_or_default();
+    for (index, line) in lines.into_iter().enumerate() {
+        let result = results.get(index);
+        let errors = saleor_errors(result.and_then(|result| result.get("errors")), "path");
+        match result.and_then(|result| result["product"]["id"].as_str()) {
+            Some(_) if errors.is_empty() => report.created += 1,
+            Some(_) => report.errors.extend(errors.into_iter().map(|error| error.at(line))),
+            None if errors.is_empty() => report.errors.push(RowError::new(None, "the product wasn't created").at(line)),
+            None => report.errors.extend(errors.into_iter().map(|error| error.at(line))),
+        }
+    }
+}
+
+/// `productUpdate` has no bulk variant, the rows are sent as aliased mutations of one document instead.
+async fn update_products(client: &HttpClient, auth_data: &AuthData, rows: &[ProductRow], report: &mut ImportReport) {
+    let mut parameters = Vec::new();
+    let mut mutations = Vec::new();
+    let mut variables = Map::new();
+    for (index, row) in rows.iter().enumerate() {
+        parameters.push(format!("$id{index}: ID!, $input{index}: ProductInput!"));
+        mutations.push(format!("  row{index}: productUpdate(id: $id{index}, input: $input{index}) {{ errors {{ field message code }} }}"));
+        variables.insert(format!("id{index}"), row.id.trim().into());
+        variables.insert(format!("input{index}"), row.input().into());
+    }
+    let document = format!("mutation ProductsUpdate({}) {{\n{}\n}}", parameters.join(", "), mutations.join("\n"));
+
+    let data: Value = match run_app_graphql(client, auth_data, "ProductsUpdate", &document, variables.into()).await {
+        Ok(data) => data,
+        Err(e) => {
+            report.errors.extend(rows.iter().map(|row| RowError::new(None, &e.to_string()).at(row.line)));
+            return;
+        }
+    };
+    for (index, row) in rows.iter().enumerate() {
+        let errors = saleor_errors(data.get(format!("row{index}")).and_then(|result| result.get("errors")), "field");
+        match errors.is_empty() {
+            true => report.updated += 1,
+            false => report.errors.extend(errors.into_iter().map(|error| error.at(row.line))),
+        }
+    }
+}
+
+/// Applies the rows of `csv` to the catalog of the installation.
+pub async fn import(client: &HttpClient, auth_data: &AuthData, csv: &[u8]) -> Result<ImportReport, String> {
+    let (rows, errors) = parse_rows(csv)?;
+    let mut report = ImportReport { errors, ..Default::default() };
+    let (updates, creates): (Vec<_>, Vec<_>) = rows.into_iter().partition(|row| !row.id.is_empty());
+
+    for batch in creates.chunks(IMPORT_BATCH_SIZE) {
+        create_products(client, auth_data, batch, &mut report).await;
+    }
+    for batch in updates.chunks(IMPORT_BATCH_SIZE) {
+        update_products(client, auth_data, batch, &mut report).await;
+    }
+    report.errors.sort_by_key(|error| error.line);
+    Ok(report)
+}
+
+/// `POST /api/import/products.csv`, the body is the file itself.
+#[utoipa::path(post, path = "/api/import/products.csv", tag = "products", security(("dashboard_token" = []), ("saleor_api_url" = [])), request_body(content = String, content_type = "text/csv"), responses(
+    (status = 200, description = "What was imported, rows that failed are listed with their errors", body = ImportReport),
+))]
+pub async fn import_products(CurrentInstallation(auth_data): CurrentInstallation, client: HttpClient, csv: Bytes) -> Response {
+    let saleor_api_url = &auth_data.saleor_api_url;
+    let start = Instant::now();
+    match import(&client, &auth_data, &csv).await {
+        Ok(report) => {
+            info!(saleor_api_url, created = report.created, updated = report.updated, failed = report.errors.len(), elapsed = ?start.elapsed(), "imported products");
+            Json(report).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
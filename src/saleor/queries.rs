@@ -1,5 +1,6 @@
+/// Types of `schemas/saleor.graphql`, the `schema_module` of fragments defined outside this module.
 #[cynic::schema("saleor")]
-mod schema {}
+pub mod schema {}
 
 #[derive(cynic::QueryFragment, Debug)]
 #[cynic(graphql_type = "Query")]
@@ -12,3 +13,52 @@ pub struct MyId {
 pub struct MeId {
     pub id: cynic::Id,
 }
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Query")]
+pub struct AppPrivateMetadata {
+    pub app: Option<AppWithPrivateMetadata>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "App")]
+pub struct AppWithPrivateMetadata {
+    pub id: cynic::Id,
+    pub private_metadata: Vec<MetadataItem>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct MetadataItem {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(cynic::InputObject, Debug)]
+pub struct MetadataInput {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(cynic::QueryVariables, Debug)]
+pub struct UpdatePrivateMetadataVariables {
+    pub id: cynic::Id,
+    pub input: Vec<MetadataInput>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Mutation", variables = "UpdatePrivateMetadataVariables")]
+pub struct UpdateAppPrivateMetadata {
+    #[arguments(id: $id, input: $input)]
+    pub update_private_metadata: Option<UpdatePrivateMetadata>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct UpdatePrivateMetadata {
+    pub errors: Vec<MetadataError>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct MetadataError {
+    pub field: Option<String>,
+    pub message: Option<String>,
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::http_client::HttpClient;
+
+use super::{graphql::run_app_graphql, AuthData};
+
+/// Fields the compiled queries and the hand written documents of this crate select, by GraphQL type.
+///
+/// cynic checks the queries against `schemas/saleor.graphql` at compile time, this list is what an
+/// installation's schema has to provide at runtime. Keep it in sync when adding queries.
+pub const REQUIRED_SCHEMA_FIELDS: &[(&str, &[&str])] = &[
+    ("Query", &["me", "app", "appsInstallations"]),
+    ("Mutation", &["updatePrivateMetadata", "webhookCreate", "webhookUpdate", "webhookDelete", "appInstall", "tokenCreate"]),
+    ("User", &["id"]),
+    ("App", &["id", "privateMetadata", "webhooks"]),
+    ("MetadataItem", &["key", "value"]),
+    ("UpdatePrivateMetadata", &["errors"]),
+    ("MetadataError", &["field", "message"]),
+    ("Webhook", &["id", "name", "targetUrl", "isActive", "subscriptionQuery", "asyncEvents", "syncEvents"]),
+];
+
+#[derive(Deserialize, Debug)]
+struct IntrospectedType {
+    fields: Option<Vec<IntrospectedField>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct IntrospectedField {
+    name: String,
+}
+
+/// Looks up [`REQUIRED_SCHEMA_FIELDS`] in the schema of an installation.
+///
+/// Returns the missing ones as `Type.field` (or just `Type` if the type doesn't exist), empty if the
+/// schema is compatible. Fails if the schema can't be introspected.
+pub async fn missing_schema_fields(client: &HttpClient, auth_data: &AuthData) -> Result<Vec<String>, String> {
+    let selections = REQUIRED_SCHEMA_FIELDS
+        .iter()
+        .enumerate()
+        .map(|(i, (type_name, _))| format!("  t{}: __type(name: {:?}) {{ fields {{ name }} }}\n", i, type_name))
+        .collect::<String>();
+    let query = format!("query SchemaCheck {{\n{}}}", selections);
+
+    let types: HashMap<String, Option<IntrospectedType>> = run_app_graphql(client, auth_data, "SchemaCheck", &query, json!({})).await?;
+
+    let mut missing = vec![];
+    for (i, (type_name, fields)) in REQUIRED_SCHEMA_FIELDS.iter().enumerate() {
+        let Some(Some(introspected)) = types.get(&format!("t{}", i)) else {
+            missing.push(type_name.to_string());
+            continue;
+        };
+        let available = introspected.fields.as_deref().unwrap_or_default();
+        for field in *fields {
+            if !available.iter().any(|available| available.name == *field) {
+                missing.push(format!("{}.{}", type_name, field));
+            }
+        }
+    }
+
+    Ok(missing)
+}
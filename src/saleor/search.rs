@@ -0,0 +1,295 @@
+//! Keeps a [`SearchIndex`] in sync with the products of every installation.
+//!
+//! Merge [`router`] into the app with [`crate::router_ext::SaleorWebhooks`] and add [`manifest`]
+//! to the webhooks of the manifest, product and variant changes are then pushed to the index as
+//! they happen. [`reindex`] (`saleor-app search reindex`) fills the index with the whole catalog, like
+//! after installing the app or changing what's indexed.
+//!
+//! ```ignore
+//! let index = config.search.as_ref().context("SEARCH_BACKEND is not set")?.build(http_client);
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(search::router(index)))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! ```
+
+use std::sync::Arc;
+
+use axum::{Router, routing::post, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::{http_client::HttpClient, search::{ProductDocument, SearchError, SearchIndex}};
+
+use super::{graphql::run_app_graphql, AuthData, SaleorAsyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest};
+
+pub const PRODUCT_CHANGED_PATH: &str = "/api/webhooks/search/product-changed";
+pub const PRODUCT_DELETED_PATH: &str = "/api/webhooks/search/product-deleted";
+pub const PRODUCT_VARIANT_CHANGED_PATH: &str = "/api/webhooks/search/product-variant-changed";
+
+/// Products are fetched in pages of this size when reindexing
+const REINDEX_PAGE_SIZE: usize = 100;
+
+/// The fields of a product that end up in its [`ProductDocument`], matching [`IndexedProduct`].
+const PRODUCT_FIELDS: &str = "id
+        name
+        slug
+        seoDescription
+        updatedAt
+        category { name }
+        productType { name }
+        thumbnail { url }
+        channelListings { isPublished channel { slug } }
+        variants { name sku }";
+
+fn product_query(event: &str) -> String {
+    format!("subscription {{
+  event {{
+    ... on {event} {{
+      product {{
+        {PRODUCT_FIELDS}
+      }}
+    }}
+  }}
+}}")
+}
+
+fn product_variant_query() -> String {
+    let fragment = |event: &str| format!("    ... on {event} {{
+      productVariant {{
+        product {{
+          {PRODUCT_FIELDS}
+        }}
+      }}
+    }}");
+    format!(
+        "subscription {{\n  event {{\n{}\n{}\n{}\n  }}\n}}",
+        fragment("ProductVariantCreated"),
+        fragment("ProductVariantUpdated"),
+        fragment("ProductVariantDeleted"),
+    )
+}
+
+fn products_query() -> String {
+    format!("query ProductsPage($first: Int!, $after: String) {{
+  products(first: $first, after: $after) {{
+    pageInfo {{ hasNextPage endCursor }}
+    edges {{
+      node {{
+        {PRODUCT_FIELDS}
+      }}
+    }}
+  }}
+}}")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedName {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedChannel {
+    pub slug: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedChannelListing {
+    pub is_published: bool,
+    pub channel: IndexedChannel,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedVariant {
+    pub name: String,
+    pub sku: Option<String>,
+}
+
+/// A product as selected by the webhooks and [`reindex`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedProduct {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub seo_description: Option<String>,
+    pub updated_at: String,
+    pub category: Option<IndexedName>,
+    pub product_type: IndexedName,
+    pub thumbnail: Option<IndexedImage>,
+    #[serde(default)]
+    pub channel_listings: Option<Vec<IndexedChannelListing>>,
+    #[serde(default)]
+    pub variants: Option<Vec<IndexedVariant>>,
+}
+
+impl IndexedProduct {
+    pub fn into_document(self, saleor_api_url: &str) -> ProductDocument {
+        let variants = self.variants.unwrap_or_default();
+        ProductDocument {
+            saleor_api_url: saleor_api_url.to_string(),
+            id: self.id,
+            name: self.name,
+            slug: self.slug,
+            description: self.seo_description.filter(|description| !description.is_empty()),
+            category: self.category.map(|category| category.name),
+            product_type: self.product_type.name,
+            thumbnail: self.thumbnail.map(|thumbnail| thumbnail.url),
+            skus: variants.iter().filter_map(|variant| variant.sku.clone()).collect(),
+            variants: variants.into_iter().map(|variant| variant.name).filter(|name| !name.is_empty()).collect(),
+            channels: self.channel_listings
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|listing| listing.is_published)
+                .map(|listing| listing.channel.slug)
+                .collect(),
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+/// Payload of `PRODUCT_CREATED`, `PRODUCT_UPDATED` and `PRODUCT_DELETED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProductEvent {
+    pub product: Option<IndexedProduct>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedProductVariant {
+    pub product: Option<IndexedProduct>,
+}
+
+/// Payload of `PRODUCT_VARIANT_CREATED`, `PRODUCT_VARIANT_UPDATED` and `PRODUCT_VARIANT_DELETED`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductVariantEvent {
+    pub product_variant: Option<IndexedProductVariant>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProductEdge {
+    node: IndexedProduct,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ProductConnection {
+    page_info: PageInfo,
+    edges: Vec<ProductEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProductsPage {
+    products: ProductConnection,
+}
+
+/// Pushes every product of the installation to `index`, page by page, returning how many there were.
+///
+/// Products deleted while the webhooks weren't delivered stay in the index, clear it first to get rid
+/// of them.
+pub async fn reindex(client: &HttpClient, auth_data: &AuthData, index: &dyn SearchIndex) -> Result<usize, SearchError> {
+    let query = products_query();
+    let mut after: Option<String> = None;
+    let mut indexed = 0;
+    loop {
+        let variables = json!({ "first": REINDEX_PAGE_SIZE, "after": after });
+        let page: ProductsPage = run_app_graphql(client, auth_data, "ProductsPage", &query, variables)
+            .await
+            .map_err(|e| SearchError(e.to_string()))?;
+
+        let documents: Vec<ProductDocument> = page.products.edges
+            .into_iter()
+            .map(|edge| edge.node.into_document(&auth_data.saleor_api_url))
+            .collect();
+        if !documents.is_empty() {
+            index.upsert(&documents).await?;
+        }
+        indexed += documents.len();
+
+        match page.products.page_info {
+            PageInfo { has_next_page: true, end_cursor: Some(cursor) } => after = Some(cursor),
+            _ => return Ok(indexed),
+        }
+    }
+}
+
+/// The webhook routes keeping the index up to date, they verify the signature of every delivery.
+pub fn router(index: impl SearchIndex) -> Router {
+    let index: Arc<dyn SearchIndex> = Arc::new(index);
+    Router::new()
+        .route(PRODUCT_CHANGED_PATH, post(product_changed))
+        .route(PRODUCT_DELETED_PATH, post(product_deleted))
+        .route(PRODUCT_VARIANT_CHANGED_PATH, post(product_variant_changed))
+        .with_state(index)
+}
+
+/// The webhooks of [`router`] for the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    let webhook = |name: &str, events: Vec<SaleorAsyncWebhookEvent>, query: String, path: &str| SaleorWebhookManifest {
+        name: name.to_string(),
+        async_events: Some(events),
+        sync_events: None,
+        query,
+        target_url: format!("{}{}", base_url, path),
+        is_active: Some(true),
+    };
+    vec![
+        webhook("Search: product created", vec![SaleorAsyncWebhookEvent::ProductCreated], product_query("ProductCreated"), PRODUCT_CHANGED_PATH),
+        webhook("Search: product updated", vec![SaleorAsyncWebhookEvent::ProductUpdated], product_query("ProductUpdated"), PRODUCT_CHANGED_PATH),
+        webhook("Search: product deleted", vec![SaleorAsyncWebhookEvent::ProductDeleted], product_query("ProductDeleted"), PRODUCT_DELETED_PATH),
+        webhook(
+            "Search: product variants changed",
+            vec![SaleorAsyncWebhookEvent::ProductVariantCreated, SaleorAsyncWebhookEvent::ProductVariantUpdated, SaleorAsyncWebhookEvent::ProductVariantDeleted],
+            product_variant_query(),
+            PRODUCT_VARIANT_CHANGED_PATH,
+        ),
+    ]
+}
+
+type Index = State<Arc<dyn SearchIndex>>;
+
+async fn upsert(index: &dyn SearchIndex, saleor_api_url: &str, product: Option<IndexedProduct>) -> Result<StatusCode, SearchError> {
+    let Some(product) = product else {
+        return Ok(StatusCode::OK);
+    };
+    index.upsert(&[product.into_document(saleor_api_url)]).await.map_err(|e| {
+        warn!(saleor_api_url, "{}", e);
+        e
+    })?;
+    Ok(StatusCode::OK)
+}
+
+async fn product_changed(State(index): Index, webhook: SaleorWebhook<ProductEvent>) -> Result<StatusCode, SearchError> {
+    upsert(index.as_ref(), &webhook.saleor_api_url, webhook.payload.product).await
+}
+
+/// Variants are part of the document of their product, which is indexed again.
+async fn product_variant_changed(State(index): Index, webhook: SaleorWebhook<ProductVariantEvent>) -> Result<StatusCode, SearchError> {
+    let product = webhook.payload.product_variant.and_then(|variant| variant.product);
+    upsert(index.as_ref(), &webhook.saleor_api_url, product).await
+}
+
+async fn product_deleted(State(index): Index, webhook: SaleorWebhook<Value>) -> Result<StatusCode, SearchError> {
+    let Some(product_id) = webhook.payload.pointer("/product/id").and_then(Value::as_str) else {
+        return Ok(StatusCode::OK);
+    };
+    index.delete(&webhook.saleor_api_url, &[product_id.to_string()]).await.map_err(|e| {
+        warn!(saleor_api_url = %webhook.saleor_api_url, "{}", e);
+        e
+    })?;
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, ops::Deref, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, response::{Response, IntoResponse}, extract::FromRequestParts};
+use cynic::{MutationBuilder, QueryBuilder};
+use reqwest::StatusCode;
+
+use crate::{graphql_cache::GraphqlCache, http_client::HttpClient};
+
+use super::{graphql::run_app_operation, AuthData, CurrentInstallation, AppPrivateMetadata, GraphqlError, UpdateAppPrivateMetadata, UpdatePrivateMetadataVariables, MetadataInput};
+
+/// Why settings couldn't be read or written, `503` if Saleor rejected the app token and `502`
+/// otherwise.
+#[derive(Debug)]
+pub struct SettingsError(GraphqlError);
+
+impl SettingsError {
+    fn failed(message: String) -> Self {
+        Self(GraphqlError::Failed(message))
+    }
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "settings error: {}", self.0)
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<GraphqlError> for SettingsError {
+    fn from(e: GraphqlError) -> Self {
+        Self(e)
+    }
+}
+
+impl IntoResponse for SettingsError {
+    fn into_response(self) -> Response {
+        let status = match &self.0 {
+            GraphqlError::AppTokenInvalid { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            GraphqlError::Failed(_) => StatusCode::BAD_GATEWAY,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// Reads and writes the settings of a single installation.
+#[async_trait]
+pub trait SettingsManager: Send + Sync + 'static {
+    async fn get_all(&self) -> Result<HashMap<String, String>, SettingsError>;
+    async fn set(&self, settings: HashMap<String, String>) -> Result<(), SettingsError>;
+
+    async fn get(&self, key: &str) -> Result<Option<String>, SettingsError> {
+        Ok(self.get_all().await?.remove(key))
+    }
+}
+
+/// Operation the private metadata is read with, cached by [`MetadataSettingsManager::with_cache`].
+pub const APP_PRIVATE_METADATA_OPERATION: &str = "AppPrivateMetadata";
+
+/// Keeps settings in the private metadata of the app inside Saleor, so no database is needed.
+pub struct MetadataSettingsManager {
+    client: HttpClient,
+    auth_data: AuthData,
+    cache: GraphqlCache,
+}
+
+impl MetadataSettingsManager {
+    pub fn new(client: HttpClient, auth_data: AuthData) -> Self {
+        Self { client, auth_data, cache: GraphqlCache::disabled() }
+    }
+
+    /// Reads settings through `cache`, writing them invalidates it.
+    pub fn with_cache(mut self, cache: GraphqlCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    async fn fetch(&self) -> Result<super::AppWithPrivateMetadata, SettingsError> {
+        let data = run_app_operation(&self.client, &self.auth_data, APP_PRIVATE_METADATA_OPERATION, AppPrivateMetadata::build(())).await?;
+        data.app.ok_or_else(|| SettingsError::failed("app not found, is the token still valid?".to_string()))
+    }
+}
+
+#[async_trait]
+impl SettingsManager for MetadataSettingsManager {
+    async fn get_all(&self) -> Result<HashMap<String, String>, SettingsError> {
+        let fetch = async {
+            let app = self.fetch().await?;
+            Ok(app.private_metadata.into_iter().map(|item| (item.key, item.value)).collect())
+        };
+        self.cache.fetch(&self.auth_data.saleor_api_url, APP_PRIVATE_METADATA_OPERATION, &serde_json::Value::Null, fetch).await
+    }
+
+    async fn set(&self, settings: HashMap<String, String>) -> Result<(), SettingsError> {
+        let app = self.fetch().await?;
+        let operation = UpdateAppPrivateMetadata::build(UpdatePrivateMetadataVariables {
+            id: app.id,
+            input: settings.into_iter().map(|(key, value)| MetadataInput { key, value }).collect(),
+        });
+
+        let data = run_app_operation(&self.client, &self.auth_data, "UpdateAppPrivateMetadata", operation).await?;
+        let errors = data.update_private_metadata
+            .map(|update| update.errors)
+            .ok_or_else(|| SettingsError::failed("no data in response".to_string()))?;
+        if !errors.is_empty() {
+            return Err(SettingsError::failed(format!("unable to update app metadata: {:?}", errors)));
+        }
+        self.cache.invalidate(&self.auth_data.saleor_api_url, APP_PRIVATE_METADATA_OPERATION).await;
+        Ok(())
+    }
+}
+
+/// The settings of the installation the request is made for, determined like in [`super::SaleorAuthLayer`].
+///
+/// Only use this behind the auth layer, it doesn't authenticate the request itself.
+#[derive(Clone)]
+pub struct TenantSettings {
+    pub saleor_api_url: String,
+    manager: Arc<dyn SettingsManager>,
+}
+
+impl TenantSettings {
+    pub fn new(saleor_api_url: String, manager: impl SettingsManager) -> Self {
+        Self {
+            saleor_api_url,
+            manager: Arc::new(manager),
+        }
+    }
+}
+
+impl Deref for TenantSettings {
+    type Target = Arc<dyn SettingsManager>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.manager
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for TenantSettings
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(settings) = parts.extensions.get::<TenantSettings>() {
+            return Ok(settings.clone());
+        }
+
+        let CurrentInstallation(auth_data) = CurrentInstallation::from_request_parts(parts, state).await?;
+        let client = HttpClient::from_request_parts(parts, state).await?;
+        let Ok(cache) = GraphqlCache::from_request_parts(parts, state).await;
+
+        Ok(Self::new(auth_data.saleor_api_url.clone(), MetadataSettingsManager::new(client, auth_data).with_cache(cache)))
+    }
+}
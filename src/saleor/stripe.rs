@@ -0,0 +1,561 @@
+//! A complete payment app on Stripe PaymentIntents, behind the `stripe` feature.
+//!
+//! [`StripeGateway`] implements [`PaymentGateway`] and [`router`] adds the endpoint Stripe
+//! notifies about payments at, which reports their outcome to Saleor with `transactionEventReport`.
+//! Point a webhook of the Stripe account at [`STRIPE_WEBHOOK_PATH`] with the `payment_intent.*`,
+//! `refund.created` and `refund.updated` events and list [`super::payments::manifest`] in the
+//! manifest, the app needs the `HANDLE_PAYMENTS` permission.
+//!
+//! ```ignore
+//! let stripe = StripeConfig::new(secret_key, publishable_key, webhook_secret);
+//! let gateway = StripeGateway::new(http_client, stripe);
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(stripe::router(gateway)))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! ```
+//!
+//! Every installation shares the Stripe account, payment intents and refunds carry the Saleor API
+//! url and transaction in their metadata so notifications find their way back. The storefront
+//! confirms the payment with Stripe.js and the `clientSecret` it gets in the transaction `data`,
+//! or passes a `paymentMethod` (and `returnUrl`) in the data to confirm it right away.
+
+use std::{collections::HashMap, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use async_trait::async_trait;
+use axum::{Router, routing::post, body::Bytes, extract::State, http::HeaderMap, response::{IntoResponse, Response}};
+use reqwest::{Method, StatusCode};
+use ring::hmac;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::{http_client::HttpClient, secrets::SecretString};
+
+use super::{
+    money::Money,
+    payments::{
+        self, report_transaction_event, PaymentGateway, PaymentGatewayInitializeSession, PaymentGatewayInitializeSessionResponse, TransactionActionKind,
+        TransactionActionRequest, TransactionActionResponse, TransactionCancelResponse, TransactionCancelResult, TransactionChargeResponse, TransactionChargeResult,
+        TransactionEventReport, TransactionEventType, TransactionFlowStrategy, TransactionRefundResponse, TransactionRefundResult, TransactionSession,
+        TransactionSessionResponse, TransactionSessionResult,
+    },
+    SaleorApl,
+};
+
+pub const STRIPE_WEBHOOK_PATH: &str = "/api/webhooks/stripe";
+pub const STRIPE_API_URL: &str = "https://api.stripe.com";
+pub const STRIPE_SIGNATURE_HEADER: &str = "stripe-signature";
+
+/// How old a notification may be, older ones are rejected as replays.
+const SIGNATURE_TOLERANCE_SECS: u64 = 300;
+
+const SALEOR_API_URL_METADATA: &str = "saleor_api_url";
+const SALEOR_TRANSACTION_METADATA: &str = "saleor_transaction_id";
+const SALEOR_SOURCE_OBJECT_METADATA: &str = "saleor_source_object_id";
+
+#[derive(Debug)]
+pub struct StripeError(pub String);
+
+impl std::fmt::Display for StripeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stripe error: {}", self.0)
+    }
+}
+
+impl std::error::Error for StripeError {}
+
+#[derive(Clone)]
+pub struct StripeConfig {
+    /// `sk_...` or a restricted key allowed to write payment intents and refunds
+    pub secret_key: SecretString,
+    /// `pk_...`, handed to the storefront for Stripe.js
+    pub publishable_key: String,
+    /// `whsec_...`, signs the notifications of the Stripe webhook
+    pub webhook_secret: SecretString,
+    pub api_url: String,
+}
+
+impl StripeConfig {
+    pub fn new(secret_key: impl Into<SecretString>, publishable_key: impl Into<String>, webhook_secret: impl Into<SecretString>) -> Self {
+        Self {
+            secret_key: secret_key.into(),
+            publishable_key: publishable_key.into(),
+            webhook_secret: webhook_secret.into(),
+            api_url: STRIPE_API_URL.to_string(),
+        }
+    }
+
+    /// Talks to another API than Stripe's, like stripe-mock in tests.
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = api_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The `Stripe-Signature` header of `payload`, sent at the unix timestamp `timestamp`.
+    pub fn sign(&self, timestamp: u64, payload: &[u8]) -> String {
+        let signature = hmac::sign(&self.signing_key(), &signed_payload(timestamp, payload));
+        format!("t={},v1={}", timestamp, to_hex(signature.as_ref()))
+    }
+
+    /// Checks the `Stripe-Signature` header of a notification, any of its `v1` signatures may match.
+    pub fn verify(&self, signature_header: &str, payload: &[u8], now: u64) -> Result<(), StripeError> {
+        let mut timestamp = None;
+        let mut signatures = vec![];
+        for part in signature_header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<u64>().ok(),
+                Some(("v1", value)) => signatures.extend(from_hex(value)),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp.ok_or_else(|| StripeError("signature has no timestamp".to_string()))?;
+        if now.abs_diff(timestamp) > SIGNATURE_TOLERANCE_SECS {
+            return Err(StripeError("signature is too old".to_string()));
+        }
+        let signed = signed_payload(timestamp, payload);
+        match signatures.iter().any(|signature| hmac::verify(&self.signing_key(), &signed, signature).is_ok()) {
+            true => Ok(()),
+            false => Err(StripeError("signature doesn't match".to_string())),
+        }
+    }
+
+    fn signing_key(&self) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, self.webhook_secret.expose().as_bytes())
+    }
+}
+
+fn signed_payload(timestamp: u64, payload: &[u8]) -> Vec<u8> {
+    let mut signed = format!("{}.", timestamp).into_bytes();
+    signed.extend_from_slice(payload);
+    signed
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    // an odd length leaves half a byte at the end, which `get` doesn't find
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentIntentStatus {
+    RequiresPaymentMethod,
+    RequiresConfirmation,
+    RequiresAction,
+    Processing,
+    RequiresCapture,
+    Canceled,
+    Succeeded,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LastPaymentError {
+    pub message: Option<String>,
+}
+
+/// The parts of a PaymentIntent the gateway looks at.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PaymentIntent {
+    pub id: String,
+    pub status: PaymentIntentStatus,
+    pub amount: i64,
+    #[serde(default)]
+    pub amount_capturable: i64,
+    #[serde(default)]
+    pub amount_received: i64,
+    pub currency: String,
+    /// `automatic`, `automatic_async` or `manual`
+    pub capture_method: String,
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub livemode: bool,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub last_payment_error: Option<LastPaymentError>,
+}
+
+impl PaymentIntent {
+    pub fn flow(&self) -> TransactionFlowStrategy {
+        match self.capture_method.as_str() {
+            "manual" => TransactionFlowStrategy::Authorization,
+            _ => TransactionFlowStrategy::Charge,
+        }
+    }
+
+    pub fn external_url(&self) -> String {
+        dashboard_url(self.livemode, &format!("payments/{}", self.id))
+    }
+
+    fn error_message(&self) -> Option<String> {
+        self.last_payment_error.as_ref().and_then(|error| error.message.clone())
+    }
+
+    /// Checks that the intent was created for `payload`, the storefront may pass the id of any intent.
+    fn check_session(&self, saleor_api_url: &str, payload: &TransactionSession) -> Result<(), StripeError> {
+        let metadata = |key: &str| self.metadata.get(key).map(String::as_str);
+        if metadata(SALEOR_API_URL_METADATA) != Some(saleor_api_url) || metadata(SALEOR_TRANSACTION_METADATA) != Some(&payload.transaction.id) {
+            return Err(StripeError(format!("payment intent {} belongs to another transaction", self.id)));
+        }
+        let amount = Money::new(payload.action.amount, &payload.action.currency).minor_units();
+        if !self.currency.eq_ignore_ascii_case(&payload.action.currency) || amount != Some(self.amount) {
+            return Err(StripeError(format!("payment intent {} is not for {} {}", self.id, payload.action.amount, payload.action.currency)));
+        }
+        Ok(())
+    }
+}
+
+/// The parts of a Refund the gateway looks at.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Refund {
+    pub id: String,
+    pub amount: i64,
+    pub currency: String,
+    /// `pending`, `requires_action`, `succeeded`, `failed` or `canceled`
+    pub status: String,
+    pub payment_intent: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub failure_reason: Option<String>,
+}
+
+/// A notification of the Stripe webhook.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StripeEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub livemode: bool,
+    pub data: StripeEventData,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StripeEventData {
+    pub object: Value,
+}
+
+fn dashboard_url(livemode: bool, path: &str) -> String {
+    match livemode {
+        true => format!("https://dashboard.stripe.com/{}", path),
+        false => format!("https://dashboard.stripe.com/test/{}", path),
+    }
+}
+
+/// What Saleor is told about a payment intent after a transaction session.
+pub fn session_response(intent: &PaymentIntent) -> TransactionSessionResponse {
+    use TransactionSessionResult::*;
+
+    let authorization = intent.flow() == TransactionFlowStrategy::Authorization;
+    let amount = Money::from_minor_units(intent.amount, &intent.currency).amount;
+    let response = match intent.status {
+        PaymentIntentStatus::RequiresPaymentMethod | PaymentIntentStatus::RequiresConfirmation | PaymentIntentStatus::RequiresAction => {
+            let result = if authorization { AuthorizationActionRequired } else { ChargeActionRequired };
+            let response = TransactionSessionResponse::new(result, amount)
+                .with_data(json!({ "paymentIntentId": intent.id, "clientSecret": intent.client_secret }));
+            match intent.error_message() {
+                Some(message) => response.with_message(message),
+                None => response,
+            }
+        }
+        PaymentIntentStatus::Processing => TransactionSessionResponse::new(if authorization { AuthorizationRequest } else { ChargeRequest }, amount),
+        PaymentIntentStatus::RequiresCapture => TransactionSessionResponse::new(AuthorizationSuccess, Money::from_minor_units(intent.amount_capturable, &intent.currency).amount)
+            .with_actions(&[TransactionActionKind::Charge, TransactionActionKind::Cancel]),
+        PaymentIntentStatus::Succeeded => TransactionSessionResponse::new(ChargeSuccess, Money::from_minor_units(intent.amount_received, &intent.currency).amount)
+            .with_actions(&[TransactionActionKind::Refund]),
+        PaymentIntentStatus::Canceled => TransactionSessionResponse::new(if authorization { AuthorizationFailure } else { ChargeFailure }, amount)
+            .with_message(intent.error_message().unwrap_or_else(|| "the payment was canceled".to_string())),
+    };
+    response.with_psp_reference(&intent.id).with_external_url(intent.external_url())
+}
+
+/// [`PaymentGateway`] on Stripe PaymentIntents, amounts are converted with [`Money::minor_units`].
+#[derive(Clone)]
+pub struct StripeGateway {
+    client: HttpClient,
+    config: Arc<StripeConfig>,
+}
+
+impl StripeGateway {
+    pub fn new(client: HttpClient, config: StripeConfig) -> Self {
+        Self { client, config: Arc::new(config) }
+    }
+
+    /// Calls the Stripe API, `params` are form encoded like Stripe expects.
+    async fn request<T: DeserializeOwned>(&self, method: Method, path: &str, params: &[(String, String)], idempotency_key: Option<&str>) -> Result<T, StripeError> {
+        let mut request = self.client
+            .request(method, format!("{}{}", self.config.api_url, path))
+            .bearer_auth(self.config.secret_key.expose());
+        if !params.is_empty() {
+            request = request.form(params);
+        }
+        if let Some(idempotency_key) = idempotency_key {
+            request = request.header("idempotency-key", idempotency_key);
+        }
+        let response = request.send().await.map_err(|e| StripeError(e.to_string()))?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|e| StripeError(format!("{}: {}", status, e)))?;
+        if !status.is_success() {
+            let message = body["error"]["message"].as_str().unwrap_or("request failed");
+            return Err(StripeError(format!("{}: {}", status, message)));
+        }
+        serde_json::from_value(body).map_err(|e| StripeError(format!("unexpected response to {}: {}", path, e)))
+    }
+
+    pub async fn payment_intent(&self, id: &str) -> Result<PaymentIntent, StripeError> {
+        self.request(Method::GET, &format!("/v1/payment_intents/{}", id), &[], None).await
+    }
+
+    async fn create_payment_intent(&self, saleor_api_url: &str, payload: &TransactionSession) -> Result<PaymentIntent, StripeError> {
+        let currency = &payload.action.currency;
+        let amount = Money::new(payload.action.amount, currency).minor_units().ok_or_else(|| StripeError(format!("unable to charge {} {}", payload.action.amount, currency)))?;
+        let capture_method = match payload.action.action_type {
+            TransactionFlowStrategy::Authorization => "manual",
+            TransactionFlowStrategy::Charge => "automatic",
+        };
+        let mut params = vec![
+            ("amount".to_string(), amount.to_string()),
+            ("currency".to_string(), currency.to_lowercase()),
+            ("capture_method".to_string(), capture_method.to_string()),
+            (format!("metadata[{}]", SALEOR_API_URL_METADATA), saleor_api_url.to_string()),
+            (format!("metadata[{}]", SALEOR_TRANSACTION_METADATA), payload.transaction.id.clone()),
+            (format!("metadata[{}]", SALEOR_SOURCE_OBJECT_METADATA), payload.source_object.id().to_string()),
+        ];
+        let data = payload.data.as_ref();
+        match data.and_then(|data| data["paymentMethod"].as_str()) {
+            Some(payment_method) => {
+                params.push(("payment_method".to_string(), payment_method.to_string()));
+                params.push(("confirm".to_string(), "true".to_string()));
+                if let Some(return_url) = data.and_then(|data| data["returnUrl"].as_str()) {
+                    params.push(("return_url".to_string(), return_url.to_string()));
+                }
+            }
+            None => params.push(("automatic_payment_methods[enabled]".to_string(), "true".to_string())),
+        }
+        // retried webhooks must not create a second payment
+        self.request(Method::POST, "/v1/payment_intents", &params, Some(&payload.merchant_reference)).await
+    }
+}
+
+#[async_trait]
+impl PaymentGateway for StripeGateway {
+    async fn initialize_gateway(&self, _saleor_api_url: &str, _payload: PaymentGatewayInitializeSession) -> PaymentGatewayInitializeSessionResponse {
+        PaymentGatewayInitializeSessionResponse {
+            data: Some(json!({ "publishableKey": self.config.publishable_key })),
+        }
+    }
+
+    async fn initialize_transaction(&self, saleor_api_url: &str, payload: TransactionSession) -> TransactionSessionResponse {
+        match self.create_payment_intent(saleor_api_url, &payload).await {
+            Ok(intent) => session_response(&intent),
+            Err(e) => {
+                warn!(saleor_api_url, transaction_id = payload.transaction.id, "{}", e);
+                let result = match payload.action.action_type {
+                    TransactionFlowStrategy::Authorization => TransactionSessionResult::AuthorizationFailure,
+                    TransactionFlowStrategy::Charge => TransactionSessionResult::ChargeFailure,
+                };
+                TransactionSessionResponse::new(result, payload.action.amount).with_message(e.to_string())
+            }
+        }
+    }
+
+    async fn process_transaction(&self, saleor_api_url: &str, payload: TransactionSession) -> TransactionSessionResponse {
+        let id = payload.transaction.psp_reference.clone()
+            .or_else(|| payload.data.as_ref().and_then(|data| data["paymentIntentId"].as_str()).map(str::to_string))
+            .unwrap_or_default();
+        let intent = self.payment_intent(&id).await
+            .and_then(|intent| intent.check_session(saleor_api_url, &payload).map(|_| intent));
+        match intent {
+            Ok(intent) => session_response(&intent),
+            Err(e) => {
+                warn!(saleor_api_url, transaction_id = payload.transaction.id, "{}", e);
+                let result = match payload.action.action_type {
+                    TransactionFlowStrategy::Authorization => TransactionSessionResult::AuthorizationFailure,
+                    TransactionFlowStrategy::Charge => TransactionSessionResult::ChargeFailure,
+                };
+                TransactionSessionResponse::new(result, payload.action.amount).with_message(e.to_string())
+            }
+        }
+    }
+
+    async fn charge(&self, saleor_api_url: &str, payload: TransactionActionRequest) -> TransactionChargeResponse {
+        let id = payload.psp_reference().to_string();
+        let mut params = vec![];
+        if let Some(amount) = payload.action.amount.and_then(|amount| Money::new(amount, &payload.action.currency).minor_units()) {
+            params.push(("amount_to_capture".to_string(), amount.to_string()));
+        }
+        let intent: Result<PaymentIntent, _> = self.request(Method::POST, &format!("/v1/payment_intents/{}/capture", id), &params, None).await;
+        match intent {
+            Ok(intent) if intent.status == PaymentIntentStatus::Succeeded => {
+                let amount = Money::from_minor_units(intent.amount_received, &intent.currency).amount;
+                TransactionActionResponse::new(&intent.id, TransactionChargeResult::ChargeSuccess, Some(amount))
+                    .with_external_url(intent.external_url())
+                    .with_actions(&[TransactionActionKind::Refund])
+            }
+            // the outcome is reported once Stripe notifies about it
+            Ok(intent) => TransactionActionResponse::pending(&intent.id).with_external_url(intent.external_url()),
+            Err(e) => {
+                warn!(saleor_api_url, psp_reference = id, "{}", e);
+                TransactionActionResponse::new(id, TransactionChargeResult::ChargeFailure, payload.action.amount).with_message(e.to_string())
+            }
+        }
+    }
+
+    async fn refund(&self, saleor_api_url: &str, payload: TransactionActionRequest) -> TransactionRefundResponse {
+        let id = payload.psp_reference().to_string();
+        let mut params = vec![
+            ("payment_intent".to_string(), id.clone()),
+            (format!("metadata[{}]", SALEOR_API_URL_METADATA), saleor_api_url.to_string()),
+        ];
+        if let Some(transaction) = &payload.transaction {
+            params.push((format!("metadata[{}]", SALEOR_TRANSACTION_METADATA), transaction.id.clone()));
+        }
+        if let Some(amount) = payload.action.amount.and_then(|amount| Money::new(amount, &payload.action.currency).minor_units()) {
+            params.push(("amount".to_string(), amount.to_string()));
+        }
+        let refund: Result<Refund, _> = self.request(Method::POST, "/v1/refunds", &params, None).await;
+        match refund {
+            Ok(refund) => {
+                let amount = Money::from_minor_units(refund.amount, &refund.currency).amount;
+                match refund.status.as_str() {
+                    "succeeded" => TransactionActionResponse::new(&refund.id, TransactionRefundResult::RefundSuccess, Some(amount)),
+                    "failed" | "canceled" => TransactionActionResponse::new(&refund.id, TransactionRefundResult::RefundFailure, Some(amount))
+                        .with_message(refund.failure_reason.unwrap_or(refund.status)),
+                    _ => TransactionActionResponse::pending(&refund.id),
+                }
+            }
+            Err(e) => {
+                warn!(saleor_api_url, psp_reference = id, "{}", e);
+                TransactionActionResponse::new(id, TransactionRefundResult::RefundFailure, payload.action.amount).with_message(e.to_string())
+            }
+        }
+    }
+
+    async fn cancel(&self, saleor_api_url: &str, payload: TransactionActionRequest) -> TransactionCancelResponse {
+        let id = payload.psp_reference().to_string();
+        let intent: Result<PaymentIntent, _> = self.request(Method::POST, &format!("/v1/payment_intents/{}/cancel", id), &[], None).await;
+        match intent {
+            Ok(intent) => {
+                let amount = Money::from_minor_units(intent.amount, &intent.currency).amount;
+                TransactionActionResponse::new(&intent.id, TransactionCancelResult::CancelSuccess, Some(amount)).with_external_url(intent.external_url())
+            }
+            Err(e) => {
+                warn!(saleor_api_url, psp_reference = id, "{}", e);
+                TransactionActionResponse::new(id, TransactionCancelResult::CancelFailure, payload.action.amount).with_message(e.to_string())
+            }
+        }
+    }
+}
+
+/// The transaction event of a Stripe notification, `None` for notifications Saleor doesn't care about.
+pub fn transaction_event(event: &StripeEvent) -> Result<Option<TransactionEventReport>, StripeError> {
+    use TransactionEventType::*;
+
+    if let Some(kind) = event.event_type.strip_prefix("payment_intent.") {
+        let intent: PaymentIntent = serde_json::from_value(event.data.object.clone()).map_err(|e| StripeError(format!("unexpected payment intent: {}", e)))?;
+        let authorization = intent.flow() == TransactionFlowStrategy::Authorization;
+        let (event_type, amount, actions): (_, _, &[TransactionActionKind]) = match kind {
+            "succeeded" => (ChargeSuccess, intent.amount_received, &[TransactionActionKind::Refund]),
+            "amount_capturable_updated" => (AuthorizationSuccess, intent.amount_capturable, &[TransactionActionKind::Charge, TransactionActionKind::Cancel]),
+            "processing" => (if authorization { AuthorizationRequest } else { ChargeRequest }, intent.amount, &[]),
+            "payment_failed" => (if authorization { AuthorizationFailure } else { ChargeFailure }, intent.amount, &[]),
+            "canceled" => (CancelSuccess, intent.amount, &[]),
+            _ => return Ok(None),
+        };
+        let report = TransactionEventReport::new(&intent.id, event_type, Money::from_minor_units(amount, &intent.currency).amount)
+            .with_external_url(intent.external_url())
+            .with_actions(actions);
+        return Ok(Some(match intent.error_message() {
+            Some(message) if matches!(event_type, ChargeFailure | AuthorizationFailure) => report.with_message(message),
+            _ => report,
+        }));
+    }
+
+    if matches!(event.event_type.as_str(), "refund.created" | "refund.updated" | "charge.refund.updated") {
+        let refund: Refund = serde_json::from_value(event.data.object.clone()).map_err(|e| StripeError(format!("unexpected refund: {}", e)))?;
+        let amount = Money::from_minor_units(refund.amount, &refund.currency).amount;
+        let report = match refund.status.as_str() {
+            "succeeded" => TransactionEventReport::new(&refund.id, RefundSuccess, amount),
+            "failed" | "canceled" => TransactionEventReport::new(&refund.id, RefundFailure, amount).with_message(refund.failure_reason.unwrap_or(refund.status)),
+            _ => TransactionEventReport::new(&refund.id, RefundRequest, amount),
+        };
+        return Ok(Some(report.with_external_url(dashboard_url(event.livemode, &format!("payments/{}", refund.payment_intent.unwrap_or_default())))));
+    }
+
+    Ok(None)
+}
+
+/// The payment webhooks of Saleor and the notifications of Stripe.
+///
+/// Notifications are reported with the [`HttpClient`] from the request extensions.
+pub fn router(gateway: StripeGateway) -> Router {
+    payments::router(gateway.clone()).merge(
+        Router::new()
+            .route(STRIPE_WEBHOOK_PATH, post(stripe_webhook))
+            .with_state(gateway),
+    )
+}
+
+/// The Saleor API url and transaction a notification is about, refunds made in the Stripe
+/// dashboard only know their payment intent.
+async fn saleor_transaction(gateway: &StripeGateway, event: &StripeEvent) -> Result<Option<(String, String)>, StripeError> {
+    let object = &event.data.object;
+    let metadata = |object: &Value| -> Option<(String, String)> {
+        let saleor_api_url = object["metadata"][SALEOR_API_URL_METADATA].as_str()?;
+        let transaction_id = object["metadata"][SALEOR_TRANSACTION_METADATA].as_str()?;
+        Some((saleor_api_url.to_string(), transaction_id.to_string()))
+    };
+    if let Some(transaction) = metadata(object) {
+        return Ok(Some(transaction));
+    }
+    let Some(payment_intent) = object["payment_intent"].as_str() else {
+        return Ok(None);
+    };
+    let intent = gateway.payment_intent(payment_intent).await?;
+    Ok(intent.metadata.get(SALEOR_API_URL_METADATA).cloned().zip(intent.metadata.get(SALEOR_TRANSACTION_METADATA).cloned()))
+}
+
+async fn stripe_webhook(State(gateway): State<StripeGateway>, client: HttpClient, apl: SaleorApl, headers: HeaderMap, body: Bytes) -> Response {
+    let signature = headers.get(STRIPE_SIGNATURE_HEADER).and_then(|signature| signature.to_str().ok()).unwrap_or_default();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or_default();
+    if let Err(e) = gateway.config.verify(signature, &body, now) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    let event: StripeEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let report = match transaction_event(&event) {
+        Ok(Some(report)) => report,
+        Ok(None) => return StatusCode::OK.into_response(),
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let (saleor_api_url, transaction_id) = match saleor_transaction(&gateway, &event).await {
+        Ok(Some(transaction)) => transaction,
+        Ok(None) => {
+            info!(stripe_event = event.id, "ignoring notification about a payment of another app");
+            return StatusCode::OK.into_response();
+        }
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    let auth_data = match apl.get(&apl.id(&saleor_api_url)).await {
+        Ok(Some(auth_data)) => auth_data,
+        Ok(None) => {
+            warn!(saleor_api_url, stripe_event = event.id, "the installation of the payment is gone");
+            return StatusCode::OK.into_response();
+        }
+        Err(e) => return e.into_response(),
+    };
+
+    match report_transaction_event(&client, &auth_data, &transaction_id, &report).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => {
+            warn!(saleor_api_url, stripe_event = event.id, "{}", e);
+            e.into_response()
+        }
+    }
+}
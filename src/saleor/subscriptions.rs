@@ -0,0 +1,151 @@
+//! Webhook subscriptions built from cynic fragments.
+//!
+//! The subscription query of a webhook decides the payload Saleor sends, so writing both by hand
+//! lets them drift apart. Describe the payload as a [`cynic::QueryFragment`] on the event type
+//! instead, [`WebhookSubscription`] wraps it into `subscription { event { ... on Event { .. } } }`
+//! for the manifest, and the same type deserializes the payload, e.g. with
+//! [`super::SaleorWebhook`]. cynic checks the fragment against `schemas/saleor.graphql` at compile
+//! time, fragments of nested objects are reused like in any other query.
+//!
+//! ```ignore
+//! #[derive(cynic::QueryFragment, Debug)]
+//! #[cynic(graphql_type = "OrderCreated", schema_module = "saleor_app::saleor::schema")]
+//! pub struct OrderCreated {
+//!     pub order: Option<OrderFragment>,
+//! }
+//!
+//! let subscription = WebhookSubscription::<OrderCreated>::new("Order created", "/api/webhooks/order-created")
+//!     .with_async_event(SaleorAsyncWebhookEvent::OrderCreated);
+//! let webhooks = vec![subscription.manifest(base_url)];
+//! async fn order_created(webhook: SaleorWebhook<OrderCreated>) -> StatusCode { .. }
+//! ```
+//!
+//! Webhooks of several events select them as an enum deriving [`cynic::InlineFragments`] on
+//! `Event`, with a variant per event.
+
+use std::marker::PhantomData;
+
+use cynic::{
+    queries::SelectionBuilder,
+    schema::{HasSubtype, NamedType},
+    OperationBuilder, QueryFragment,
+};
+use serde::de::DeserializeOwned;
+
+use super::{schema, SaleorAsyncWebhookEvent, SaleorSyncWebhookEvent, SaleorWebhookManifest};
+
+/// The subscription document selecting `P` on the event, like `subscription { event { ... on
+/// ProductUpdated { product { id } } } }`.
+pub fn subscription_query<P>() -> String
+where
+    P: QueryFragment<VariablesFields = ()>,
+    P::SchemaType: NamedType,
+    schema::Event: HasSubtype<P::SchemaType>,
+{
+    let operation = OperationBuilder::<EventSubscription<P>, ()>::subscription()
+        .with_variables(())
+        .build()
+        .expect("subscriptions of fragments without variables always build");
+    // cynic leaves room for the name of the operation
+    operation.query.trim().replacen("subscription  {", "subscription {", 1)
+}
+
+/// The `Subscription` root of [`subscription_query`].
+struct EventSubscription<P>(PhantomData<P>);
+
+impl<P> QueryFragment for EventSubscription<P>
+where
+    P: QueryFragment<VariablesFields = ()>,
+    P::SchemaType: NamedType,
+    schema::Event: HasSubtype<P::SchemaType>,
+{
+    type SchemaType = schema::Subscription;
+    type VariablesFields = ();
+
+    const TYPE: Option<&'static str> = Some("Subscription");
+
+    fn query(mut builder: SelectionBuilder<'_, Self::SchemaType, Self::VariablesFields>) {
+        let mut event = builder.select_field::<schema::__fields::Subscription::event, Option<schema::Event>>();
+        <Option<EventFragment<P>> as QueryFragment>::query(event.select_children());
+    }
+}
+
+/// The `event` of [`EventSubscription`], narrowed to the type of `P`.
+struct EventFragment<P>(PhantomData<P>);
+
+impl<P> QueryFragment for EventFragment<P>
+where
+    P: QueryFragment<VariablesFields = ()>,
+    P::SchemaType: NamedType,
+    schema::Event: HasSubtype<P::SchemaType>,
+{
+    type SchemaType = schema::Event;
+    type VariablesFields = ();
+
+    const TYPE: Option<&'static str> = Some("Event");
+
+    fn query(mut builder: SelectionBuilder<'_, Self::SchemaType, Self::VariablesFields>) {
+        let mut fragment = builder.inline_fragment().on::<P::SchemaType>();
+        P::query(fragment.select_children());
+    }
+}
+
+/// A webhook whose payload is `P`, declared in the manifest with the query selecting it.
+#[derive(Debug, Clone)]
+pub struct WebhookSubscription<P> {
+    pub name: String,
+    /// Path the webhook is delivered to, below the base url of the app
+    pub path: String,
+    pub async_events: Vec<SaleorAsyncWebhookEvent>,
+    pub sync_events: Vec<SaleorSyncWebhookEvent>,
+    payload: PhantomData<fn() -> P>,
+}
+
+impl<P> WebhookSubscription<P>
+where
+    P: QueryFragment<VariablesFields = ()> + DeserializeOwned,
+    P::SchemaType: NamedType,
+    schema::Event: HasSubtype<P::SchemaType>,
+{
+    pub fn new(name: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            path: path.into(),
+            async_events: vec![],
+            sync_events: vec![],
+            payload: PhantomData,
+        }
+    }
+
+    pub fn with_async_event(mut self, event: SaleorAsyncWebhookEvent) -> Self {
+        self.async_events.push(event);
+        self
+    }
+
+    pub fn with_sync_event(mut self, event: SaleorSyncWebhookEvent) -> Self {
+        self.sync_events.push(event);
+        self
+    }
+
+    /// The subscription query, see [`subscription_query`].
+    pub fn query(&self) -> String {
+        subscription_query::<P>()
+    }
+
+    /// Parses a payload delivered for this subscription.
+    pub fn parse(&self, payload: &[u8]) -> Result<P, serde_json::Error> {
+        serde_json::from_slice(payload)
+    }
+
+    /// The manifest entry, `base_url` is where the app is reachable.
+    pub fn manifest(&self, base_url: &str) -> SaleorWebhookManifest {
+        SaleorWebhookManifest {
+            name: self.name.clone(),
+            async_events: (!self.async_events.is_empty()).then(|| self.async_events.clone()),
+            sync_events: (!self.sync_events.is_empty()).then(|| self.sync_events.clone()),
+            query: self.query(),
+            target_url: format!("{}{}", base_url, self.path),
+            is_active: Some(true),
+        }
+    }
+}
@@ -0,0 +1,380 @@
+//! Building blocks of tax apps, which answer the `CHECKOUT_CALCULATE_TAXES` and
+//! `ORDER_CALCULATE_TAXES` sync webhooks of Saleor.
+//!
+//! Implement [`TaxCalculator`] and merge [`router`] into the app with
+//! [`crate::router_ext::SaleorWebhooks`], its webhooks are listed by [`manifest`]. The app needs the
+//! `HANDLE_TAXES` permission and has to be selected as tax app of the channel in the dashboard.
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .saleor_webhooks(&config, SaleorWebhooks::new().merge(taxes::router(MyTaxes::new())))
+//!     .saleor_app(&config, apl_store)
+//!     .await?;
+//! let webhooks = [webhooks::manifest(base_url), taxes::manifest(base_url)].concat();
+//! ```
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{Router, routing::post, extract::State, Json, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{SaleorSyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest};
+
+use super::money::{checked_sum, MoneyError};
+
+pub use super::money::{currency_decimal_places, round_money};
+
+pub const CHECKOUT_CALCULATE_TAXES_PATH: &str = "/api/webhooks/taxes/checkout-calculate-taxes";
+pub const ORDER_CALCULATE_TAXES_PATH: &str = "/api/webhooks/taxes/order-calculate-taxes";
+
+/// Subscription query of both events, matching [`CalculateTaxes`].
+const CALCULATE_TAXES_QUERY: &str = "subscription {
+  event {
+    ... on CalculateTaxes {
+      taxBase {
+        sourceObject {
+          __typename
+          ... on Checkout { id }
+          ... on Order { id }
+        }
+        pricesEnteredWithTax
+        currency
+        channel { slug }
+        shippingPrice { amount }
+        address { country { code } countryArea city postalCode streetAddress1 streetAddress2 }
+        discounts { name amount { amount } }
+        lines {
+          sourceLine {
+            __typename
+            ... on CheckoutLine { id }
+            ... on OrderLine { id }
+          }
+          quantity
+          chargeTaxes
+          productName
+          variantName
+          productSku
+          unitPrice { amount }
+          totalPrice { amount }
+        }
+      }
+    }
+  }
+}";
+
+#[derive(Debug)]
+pub struct TaxError(pub String);
+
+impl std::fmt::Display for TaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tax calculation error: {}", self.0)
+    }
+}
+
+impl std::error::Error for TaxError {}
+
+impl From<MoneyError> for TaxError {
+    fn from(e: MoneyError) -> Self {
+        Self(e.to_string())
+    }
+}
+
+/// Saleor treats any other response than the taxes as a failed calculation.
+impl IntoResponse for TaxError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TaxMoney {
+    #[serde(with = "rust_decimal::serde::float")]
+    pub amount: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TaxChannel {
+    pub slug: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TaxCountry {
+    pub code: String,
+}
+
+/// Where the goods are shipped to, or the billing address without shipping.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxAddress {
+    pub country: TaxCountry,
+    pub country_area: String,
+    pub city: String,
+    pub postal_code: String,
+    pub street_address_1: String,
+    pub street_address_2: String,
+}
+
+/// The checkout or order taxes are calculated for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "__typename")]
+pub enum TaxSourceObject {
+    Checkout { id: String },
+    Order { id: String },
+}
+
+impl TaxSourceObject {
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Checkout { id } | Self::Order { id } => id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "__typename")]
+pub enum TaxSourceLine {
+    CheckoutLine { id: String },
+    OrderLine { id: String },
+}
+
+impl TaxSourceLine {
+    pub fn id(&self) -> &str {
+        match self {
+            Self::CheckoutLine { id } | Self::OrderLine { id } => id,
+        }
+    }
+}
+
+/// A discount on the whole checkout or order, like an entire order voucher.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TaxDiscount {
+    pub name: Option<String>,
+    pub amount: TaxMoney,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxableLine {
+    pub source_line: TaxSourceLine,
+    pub quantity: u32,
+    /// `false` for products that aren't taxed at all
+    pub charge_taxes: bool,
+    pub product_name: String,
+    pub variant_name: String,
+    pub product_sku: Option<String>,
+    pub unit_price: TaxMoney,
+    /// The unit price times the quantity, before the [`TaxBase::discounts`]
+    pub total_price: TaxMoney,
+}
+
+/// What taxes are calculated on. Prices are net, or gross if `prices_entered_with_tax` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxBase {
+    pub source_object: TaxSourceObject,
+    pub prices_entered_with_tax: bool,
+    pub currency: String,
+    pub channel: TaxChannel,
+    pub shipping_price: TaxMoney,
+    pub address: Option<TaxAddress>,
+    pub discounts: Vec<TaxDiscount>,
+    pub lines: Vec<TaxableLine>,
+}
+
+impl TaxBase {
+    /// The total of every line after spreading the discounts over them proportionally to their total.
+    ///
+    /// The amounts are rounded to the currency, the rounding difference goes to the largest line so the
+    /// discounted totals add up to exactly the undiscounted ones minus the discounts. Discounts larger
+    /// than the lines bring every line down to zero.
+    pub fn discounted_line_totals(&self) -> Result<Vec<Decimal>, MoneyError> {
+        let overflow = || MoneyError(format!("discounting the lines overflows {}", self.currency));
+        let totals: Vec<Decimal> = self.lines.iter().map(|line| line.total_price.amount).collect();
+        let subtotal = checked_sum(totals.iter().copied()).ok_or_else(overflow)?;
+        let discount = checked_sum(self.discounts.iter().map(|discount| discount.amount.amount)).ok_or_else(overflow)?.min(subtotal);
+        if discount <= Decimal::ZERO || subtotal.is_zero() {
+            return Ok(totals);
+        }
+
+        let mut discounted = totals
+            .iter()
+            .map(|total| {
+                let share = discount.checked_mul(*total).and_then(|share| share.checked_div(subtotal)).ok_or_else(overflow)?;
+                Ok(round_money(total.checked_sub(share).ok_or_else(overflow)?, &self.currency))
+            })
+            .collect::<Result<Vec<Decimal>, MoneyError>>()?;
+        let remainder = checked_sum(discounted.iter().copied()).and_then(|sum| (subtotal - discount).checked_sub(sum)).ok_or_else(overflow)?;
+        if let Some(largest) = discounted.iter_mut().max_by_key(|total| **total) {
+            *largest = largest.checked_add(remainder).ok_or_else(overflow)?;
+        }
+        Ok(discounted)
+    }
+}
+
+/// Payload of `CHECKOUT_CALCULATE_TAXES` and `ORDER_CALCULATE_TAXES`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CalculateTaxes {
+    pub tax_base: TaxBase,
+}
+
+/// A net amount and its gross amount at a tax rate, both rounded to the currency.
+///
+/// Rates of -100% or less are rejected, taking them out of a gross amount would divide by zero or
+/// flip its sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaxedAmount {
+    pub net: Decimal,
+    pub gross: Decimal,
+}
+
+impl TaxedAmount {
+    /// Adds the tax at `rate` (a percentage, like 19 for 19%) to a net amount.
+    pub fn from_net(net: Decimal, rate: Decimal, currency: &str) -> Result<Self, MoneyError> {
+        let factor = tax_factor(rate)?;
+        let net = round_money(net, currency);
+        let gross = net.checked_mul(factor).and_then(|gross| gross.checked_div(Decimal::ONE_HUNDRED));
+        let gross = gross.ok_or_else(|| MoneyError(format!("taxing {net} {currency} at {rate}% overflows")))?;
+        Ok(Self { net, gross: round_money(gross, currency) })
+    }
+
+    /// Takes the tax at `rate` (a percentage) out of a gross amount.
+    pub fn from_gross(gross: Decimal, rate: Decimal, currency: &str) -> Result<Self, MoneyError> {
+        let factor = tax_factor(rate)?;
+        let gross = round_money(gross, currency);
+        let net = gross.checked_mul(Decimal::ONE_HUNDRED).and_then(|net| net.checked_div(factor));
+        let net = net.ok_or_else(|| MoneyError(format!("untaxing {gross} {currency} at {rate}% overflows")))?;
+        Ok(Self { net: round_money(net, currency), gross })
+    }
+
+    /// Taxes `amount` of the [`TaxBase`], which is gross if prices are entered with tax and net otherwise.
+    pub fn from_base(tax_base: &TaxBase, amount: Decimal, rate: Decimal) -> Result<Self, MoneyError> {
+        match tax_base.prices_entered_with_tax {
+            true => Self::from_gross(amount, rate, &tax_base.currency),
+            false => Self::from_net(amount, rate, &tax_base.currency),
+        }
+    }
+
+    /// Net and gross have the same sign, so their difference can't overflow.
+    pub fn tax(&self) -> Decimal {
+        self.gross - self.net
+    }
+}
+
+/// `100 + rate`, what a net amount is multiplied with (in percent) to get the gross amount.
+fn tax_factor(rate: Decimal) -> Result<Decimal, MoneyError> {
+    match Decimal::ONE_HUNDRED.checked_add(rate) {
+        Some(factor) if factor > Decimal::ZERO => Ok(factor),
+        _ => Err(MoneyError(format!("unable to tax at {rate}%"))),
+    }
+}
+
+/// The taxes of a single line, in the order of [`TaxBase::lines`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LineTaxes {
+    #[serde(with = "rust_decimal::serde::float")]
+    pub total_gross_amount: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub total_net_amount: Decimal,
+    /// A percentage, like 19 for 19%
+    #[serde(with = "rust_decimal::serde::float")]
+    pub tax_rate: Decimal,
+}
+
+impl LineTaxes {
+    pub fn new(total: TaxedAmount, tax_rate: Decimal) -> Self {
+        Self { total_gross_amount: total.gross, total_net_amount: total.net, tax_rate }
+    }
+}
+
+/// Response to `CHECKOUT_CALCULATE_TAXES` and `ORDER_CALCULATE_TAXES`, unlike the payloads in snake case.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CalculateTaxesResponse {
+    #[serde(with = "rust_decimal::serde::float")]
+    pub shipping_price_gross_amount: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub shipping_price_net_amount: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub shipping_tax_rate: Decimal,
+    /// One per line of the tax base, in the same order
+    pub lines: Vec<LineTaxes>,
+}
+
+impl CalculateTaxesResponse {
+    pub fn new(shipping: TaxedAmount, shipping_tax_rate: Decimal) -> Self {
+        Self {
+            shipping_price_gross_amount: shipping.gross,
+            shipping_price_net_amount: shipping.net,
+            shipping_tax_rate,
+            lines: vec![],
+        }
+    }
+
+    pub fn with_line(mut self, line: LineTaxes) -> Self {
+        self.lines.push(line);
+        self
+    }
+
+    /// Taxes every line (after discounts) and the shipping at the same `rate`, lines that don't
+    /// charge taxes at 0%.
+    pub fn flat_rate(tax_base: &TaxBase, rate: Decimal) -> Result<Self, MoneyError> {
+        let shipping = TaxedAmount::from_base(tax_base, tax_base.shipping_price.amount, rate)?;
+        let mut response = Self::new(shipping, rate);
+        for (line, total) in tax_base.lines.iter().zip(tax_base.discounted_line_totals()?) {
+            let rate = if line.charge_taxes { rate } else { Decimal::ZERO };
+            response = response.with_line(LineTaxes::new(TaxedAmount::from_base(tax_base, total, rate)?, rate));
+        }
+        Ok(response)
+    }
+}
+
+/// Calculates the taxes of checkouts and orders, e.g. through the API of a tax provider.
+///
+/// Errors are logged and answered with a `502`, Saleor then keeps the checkout or order without
+/// taxes (depending on the tax configuration of the channel).
+#[async_trait]
+pub trait TaxCalculator: Send + Sync + 'static {
+    /// Calculates the taxes of `tax_base` for the installation at `saleor_api_url`, the source object tells
+    /// whether it's a checkout or an order.
+    async fn calculate_taxes(&self, saleor_api_url: &str, tax_base: TaxBase) -> Result<CalculateTaxesResponse, TaxError>;
+}
+
+/// The webhook routes of a tax app, they verify the signature of every delivery.
+pub fn router(calculator: impl TaxCalculator) -> Router {
+    let calculator: Arc<dyn TaxCalculator> = Arc::new(calculator);
+    Router::new()
+        .route(CHECKOUT_CALCULATE_TAXES_PATH, post(calculate_taxes))
+        .route(ORDER_CALCULATE_TAXES_PATH, post(calculate_taxes))
+        .with_state(calculator)
+}
+
+/// The webhooks of [`router`] for the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    let webhook = |name: &str, event: SaleorSyncWebhookEvent, path: &str| SaleorWebhookManifest {
+        name: name.to_string(),
+        async_events: None,
+        sync_events: Some(vec![event]),
+        query: CALCULATE_TAXES_QUERY.to_string(),
+        target_url: format!("{}{}", base_url, path),
+        is_active: Some(true),
+    };
+    vec![
+        webhook("Checkout calculate taxes", SaleorSyncWebhookEvent::CheckoutCalculateTaxes, CHECKOUT_CALCULATE_TAXES_PATH),
+        webhook("Order calculate taxes", SaleorSyncWebhookEvent::OrderCalculateTaxes, ORDER_CALCULATE_TAXES_PATH),
+    ]
+}
+
+async fn calculate_taxes(State(calculator): State<Arc<dyn TaxCalculator>>, webhook: SaleorWebhook<CalculateTaxes>) -> Result<Json<CalculateTaxesResponse>, TaxError> {
+    let source_id = webhook.payload.tax_base.source_object.id().to_string();
+    match calculator.calculate_taxes(&webhook.saleor_api_url, webhook.payload.tax_base).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            warn!(saleor_api_url = %webhook.saleor_api_url, source_id, "{}", e);
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use axum::{extract::OriginalUri, http::{header::HOST, request::Parts, Extensions, HeaderMap, Request, Uri}};
+use tower_sessions::Session;
+
+use crate::sessions;
+
+/// Prefix of the paths [`PathPrefixTenantResolver`] takes the tenant from, `/t/{tenant}/...`.
+pub const TENANT_PATH_PREFIX: &str = "/t";
+/// Turns the tenant of a path or subdomain into the API url of a Saleor Cloud instance.
+pub const DEFAULT_TENANT_API_URL_TEMPLATE: &str = "https://{tenant}.saleor.cloud/graphql/";
+/// Turns the `saleor-domain` header of older Saleor versions into the API url, see [`DomainTenantResolver`].
+pub const DEFAULT_DOMAIN_API_URL_TEMPLATE: &str = "https://{domain}/graphql/";
+
+/// What a [`TenantResolver`] gets to look at.
+pub struct TenantRequest<'a> {
+    pub headers: &'a HeaderMap,
+    /// The uri before nested routers stripped their prefix
+    pub uri: &'a Uri,
+    pub extensions: &'a Extensions,
+}
+
+impl<'a> TenantRequest<'a> {
+    pub fn from_parts(parts: &'a Parts) -> Self {
+        Self::new(&parts.headers, &parts.uri, &parts.extensions)
+    }
+
+    pub fn from_request<B>(request: &'a Request<B>) -> Self {
+        Self::new(request.headers(), request.uri(), request.extensions())
+    }
+
+    fn new(headers: &'a HeaderMap, uri: &'a Uri, extensions: &'a Extensions) -> Self {
+        let uri = extensions.get::<OriginalUri>().map(|OriginalUri(uri)| uri).unwrap_or(uri);
+        Self { headers, uri, extensions }
+    }
+
+    pub fn session(&self) -> Option<&'a Session> {
+        self.extensions.get::<Session>()
+    }
+}
+
+/// Maps a request to the Saleor API url of the installation it's made for.
+///
+/// Resolvers only name the tenant, they don't authenticate anything: dashboard requests still go
+/// through a [`super::SaleorAuthLayer`] and webhooks are verified against the tenant's JWKS.
+pub trait TenantResolver: Send + Sync + 'static {
+    fn resolve(&self, request: &TenantRequest<'_>) -> Option<String>;
+}
+
+/// The `saleor-api-url` header, sent by the dashboard's requests and with every webhook.
+pub struct HeaderTenantResolver;
+
+impl TenantResolver for HeaderTenantResolver {
+    fn resolve(&self, request: &TenantRequest<'_>) -> Option<String> {
+        request
+            .headers
+            .get(super::SALEOR_API_URL_HEADER)
+            .and_then(|api_url| api_url.to_str().ok())
+            .map(str::to_string)
+    }
+}
+
+/// The `saleor-domain` header, for older Saleor versions that don't send `saleor-api-url` yet.
+pub struct DomainTenantResolver {
+    api_url_template: String,
+}
+
+impl DomainTenantResolver {
+    /// `api_url_template` turns the domain into the API url, `{domain}` is replaced with it, like
+    /// [`DEFAULT_DOMAIN_API_URL_TEMPLATE`].
+    pub fn new(api_url_template: &str) -> Self {
+        Self { api_url_template: api_url_template.to_string() }
+    }
+
+    /// The API url of an instance reached under `domain`, with or without a port.
+    pub fn api_url(&self, domain: &str) -> Option<String> {
+        let valid = !domain.is_empty() && domain.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | ':'));
+        valid.then(|| self.api_url_template.replace("{domain}", domain))
+    }
+}
+
+impl TenantResolver for DomainTenantResolver {
+    fn resolve(&self, request: &TenantRequest<'_>) -> Option<String> {
+        let domain = request.headers.get(super::SALEOR_DOMAIN_HEADER)?.to_str().ok()?;
+        self.api_url(domain)
+    }
+}
+
+/// The installation the session was last authenticated for, see [`sessions::current_tenant`].
+pub struct SessionTenantResolver;
+
+impl TenantResolver for SessionTenantResolver {
+    fn resolve(&self, request: &TenantRequest<'_>) -> Option<String> {
+        request.session().and_then(sessions::current_tenant)
+    }
+}
+
+/// Fills `{tenant}` of a template like [`DEFAULT_TENANT_API_URL_TEMPLATE`] in.
+fn api_url(template: &str, tenant: &str) -> Option<String> {
+    let valid = !tenant.is_empty() && !tenant.starts_with('.') && !tenant.ends_with('.') && tenant.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    valid.then(|| template.replace("{tenant}", tenant))
+}
+
+/// The first segment after [`TENANT_PATH_PREFIX`], like `demo` of `/t/demo/api/config`.
+///
+/// The app answers under the prefix once it's in the [`TenantResolvers`] of [`crate::app::build`].
+pub struct PathPrefixTenantResolver {
+    api_url_template: String,
+}
+
+impl PathPrefixTenantResolver {
+    /// `api_url_template` turns the segment into the API url, `{tenant}` is replaced with it.
+    pub fn new(api_url_template: &str) -> Self {
+        Self { api_url_template: api_url_template.to_string() }
+    }
+}
+
+impl TenantResolver for PathPrefixTenantResolver {
+    fn resolve(&self, request: &TenantRequest<'_>) -> Option<String> {
+        let path = request.uri.path().strip_prefix(TENANT_PATH_PREFIX)?.strip_prefix('/')?;
+        let tenant = path.split('/').next()?;
+        api_url(&self.api_url_template, tenant)
+    }
+}
+
+/// The subdomain the app is reached under, like `demo` of `demo.apps.example.com`.
+pub struct SubdomainTenantResolver {
+    base_domain: String,
+    api_url_template: String,
+}
+
+impl SubdomainTenantResolver {
+    /// `base_domain` is the domain below the subdomains, like `apps.example.com`, `api_url_template`
+    /// turns the subdomain into the API url, `{tenant}` is replaced with it.
+    pub fn new(base_domain: &str, api_url_template: &str) -> Self {
+        Self {
+            base_domain: base_domain.trim_matches('.').to_lowercase(),
+            api_url_template: api_url_template.to_string(),
+        }
+    }
+}
+
+impl TenantResolver for SubdomainTenantResolver {
+    fn resolve(&self, request: &TenantRequest<'_>) -> Option<String> {
+        let host = request.headers.get(HOST)?.to_str().ok()?;
+        let host = host.split(':').next()?.to_lowercase();
+        let subdomain = host.strip_suffix(&self.base_domain)?.strip_suffix('.')?;
+        api_url(&self.api_url_template, subdomain)
+    }
+}
+
+/// A resolver as configured by `TENANT_RESOLVERS`, see [`TenantResolvers::from_strategies`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TenantStrategy {
+    Header,
+    /// The legacy `saleor-domain` header
+    Domain { api_url_template: String },
+    Session,
+    PathPrefix { api_url_template: String },
+    Subdomain { base_domain: String, api_url_template: String },
+}
+
+impl TenantStrategy {
+    /// The strategies of the app unless configured otherwise.
+    pub fn defaults() -> Vec<TenantStrategy> {
+        Self::defaults_with_domain_template(DEFAULT_DOMAIN_API_URL_TEMPLATE)
+    }
+
+    /// Like [`Self::defaults`], with the `saleor-domain` header turned into API urls by the template.
+    pub fn defaults_with_domain_template(api_url_template: &str) -> Vec<TenantStrategy> {
+        vec![
+            TenantStrategy::Header,
+            TenantStrategy::Domain { api_url_template: api_url_template.to_string() },
+            TenantStrategy::Session,
+        ]
+    }
+}
+
+/// The resolvers of the app, the first one naming a tenant wins.
+///
+/// [`crate::app::build`] provides them as a request extension, without it [`Self::default`] is used:
+/// the `saleor-api-url` header, the `saleor-domain` header of older Saleor versions, then the session.
+#[derive(Clone)]
+pub struct TenantResolvers {
+    resolvers: Vec<Arc<dyn TenantResolver>>,
+}
+
+impl TenantResolvers {
+    /// No resolvers at all, add them with [`Self::with_resolver`].
+    pub fn new() -> Self {
+        Self { resolvers: vec![] }
+    }
+
+    pub fn with_resolver(mut self, resolver: impl TenantResolver) -> Self {
+        self.resolvers.push(Arc::new(resolver));
+        self
+    }
+
+    /// Tries the strategies in order.
+    pub fn from_strategies(strategies: &[TenantStrategy]) -> Self {
+        strategies.iter().fold(Self::new(), |resolvers, strategy| match strategy {
+            TenantStrategy::Header => resolvers.with_resolver(HeaderTenantResolver),
+            TenantStrategy::Domain { api_url_template } => resolvers.with_resolver(DomainTenantResolver::new(api_url_template)),
+            TenantStrategy::Session => resolvers.with_resolver(SessionTenantResolver),
+            TenantStrategy::PathPrefix { api_url_template } => resolvers.with_resolver(PathPrefixTenantResolver::new(api_url_template)),
+            TenantStrategy::Subdomain { base_domain, api_url_template } => {
+                resolvers.with_resolver(SubdomainTenantResolver::new(base_domain, api_url_template))
+            }
+        })
+    }
+
+    /// The resolvers of the request's extensions, or the default ones.
+    pub fn of(extensions: &Extensions) -> Self {
+        extensions.get::<TenantResolvers>().cloned().unwrap_or_default()
+    }
+}
+
+impl Default for TenantResolvers {
+    fn default() -> Self {
+        Self::from_strategies(&TenantStrategy::defaults())
+    }
+}
+
+impl TenantResolver for TenantResolvers {
+    fn resolve(&self, request: &TenantRequest<'_>) -> Option<String> {
+        self.resolvers.iter().find_map(|resolver| resolver.resolve(request))
+    }
+}
+
+/// The Saleor API url a request is made for, as named by the request's [`TenantResolvers`].
+pub fn request_tenant(request: &TenantRequest<'_>) -> Option<String> {
+    TenantResolvers::of(request.extensions).resolve(request)
+}
+
@@ -0,0 +1,205 @@
+//! GraphQL calls on behalf of the dashboard user of a request.
+//!
+//! The dashboard hands the app a token of the user, which [`super::SaleorAuthLayer`] verifies and
+//! keeps in the session. Handlers call Saleor with a [`UserClient`] instead of that token: with
+//! `USER_TOKEN_EXCHANGE=true` it's exchanged for an access token of the app acting for the user
+//! (`app.accessToken`, Saleor 3.1+), cached per installation and user until
+//! `USER_TOKEN_REFRESH_BEFORE_SECS` before it expires. Without it the dashboard token is used.
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::{request::Parts, StatusCode}, response::{IntoResponse, Response}};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{http_client::HttpClient, secrets::SecretString};
+
+use super::graphql::run_graphql;
+
+/// Asks for an access token of the app for the user of the token the query is sent with.
+pub const APP_ACCESS_TOKEN_QUERY: &str = "query AppAccessToken($id: ID!) { app(id: $id) { accessToken } }";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserTokenError(pub String);
+
+impl std::fmt::Display for UserTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "user token error: {}", self.0)
+    }
+}
+
+impl std::error::Error for UserTokenError {}
+
+impl IntoResponse for UserTokenError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+/// Configured by `USER_TOKEN_EXCHANGE` and `USER_TOKEN_REFRESH_BEFORE_SECS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserTokenConfig {
+    /// Whether dashboard tokens are exchanged for access tokens of the app
+    pub exchange: bool,
+    /// How long before they expire exchanged tokens are replaced
+    pub refresh_before: Duration,
+}
+
+impl Default for UserTokenConfig {
+    fn default() -> Self {
+        Self {
+            exchange: false,
+            refresh_before: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The claims of a verified dashboard or access token this module needs.
+#[derive(Deserialize)]
+struct TokenClaims {
+    app: String,
+    #[serde(default)]
+    user_id: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    exp: Option<u64>,
+}
+
+impl TokenClaims {
+    /// Reads the claims without verifying the token, only use this for tokens that were already verified.
+    fn read(token: &str) -> Option<Self> {
+        let claims = token.split('.').nth(1)?;
+        let claims = URL_SAFE_NO_PAD.decode(claims.trim_end_matches('=')).ok()?;
+        serde_json::from_slice(&claims).ok()
+    }
+}
+
+struct CachedToken {
+    token: SecretString,
+    /// Unix timestamp, tokens without `exp` are kept until the dashboard token expires
+    expires_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Exchanged tokens by installation and user, provided to handlers as a request extension. Clones
+/// share them.
+///
+/// Routers built without [`crate::app::build`] don't exchange tokens.
+#[derive(Clone, Default)]
+pub struct UserTokens {
+    config: UserTokenConfig,
+    tokens: Arc<Mutex<HashMap<(String, String), CachedToken>>>,
+}
+
+impl UserTokens {
+    pub fn new(config: UserTokenConfig) -> Self {
+        Self { config, tokens: Arc::default() }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.exchange
+    }
+
+    /// The token to call the API at `saleor_api_url` with for the user of `dashboard_token`, which
+    /// has to be verified already.
+    pub async fn token(&self, client: &HttpClient, saleor_api_url: &str, dashboard_token: &SecretString) -> Result<SecretString, UserTokenError> {
+        if !self.config.exchange {
+            return Ok(dashboard_token.clone());
+        }
+        let claims = TokenClaims::read(dashboard_token.expose()).ok_or_else(|| UserTokenError("the dashboard token has no claims".to_string()))?;
+        let user = claims.user_id.or(claims.email).ok_or_else(|| UserTokenError("the dashboard token has no user".to_string()))?;
+        let key = (saleor_api_url.to_string(), user);
+        let refresh_at = now() + self.config.refresh_before.as_secs();
+        if let Some(cached) = self.tokens.lock().unwrap().get(&key).filter(|cached| cached.expires_at > refresh_at) {
+            return Ok(cached.token.clone());
+        }
+
+        #[derive(Deserialize)]
+        struct Data {
+            app: Option<App>,
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct App {
+            access_token: Option<String>,
+        }
+        let data: Data = run_graphql(client, saleor_api_url, Some(dashboard_token.expose()), "AppAccessToken", APP_ACCESS_TOKEN_QUERY, json!({ "id": claims.app }))
+            .await
+            .map_err(UserTokenError)?;
+        let token = data
+            .app
+            .and_then(|app| app.access_token)
+            .ok_or_else(|| UserTokenError("Saleor didn't hand out an access token for the user".to_string()))?;
+        let expires_at = TokenClaims::read(&token).and_then(|claims| claims.exp).or(claims.exp).unwrap_or_default();
+        let token = SecretString::from(token);
+
+        let mut tokens = self.tokens.lock().unwrap();
+        let now = now();
+        tokens.retain(|_, cached| cached.expires_at > now);
+        tokens.insert(key, CachedToken { token: token.clone(), expires_at });
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for UserTokens
+where
+    S: Sync + Send,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<UserTokens>().cloned().unwrap_or_default())
+    }
+}
+
+/// The dashboard token [`super::SaleorAuthLayer`] verified for a request, kept out of handlers.
+#[derive(Clone)]
+pub(crate) struct VerifiedDashboardToken {
+    pub(crate) saleor_api_url: String,
+    pub(crate) token: SecretString,
+}
+
+/// Calls the GraphQL API of the installation with the permissions of the dashboard user.
+///
+/// Only extractable behind a [`super::SaleorAuthLayer`], other requests are rejected with
+/// `500 Internal Server Error`.
+#[derive(Clone)]
+pub struct UserClient {
+    pub saleor_api_url: String,
+    client: HttpClient,
+    token: SecretString,
+}
+
+impl UserClient {
+    /// Runs a GraphQL document, returning its `data`.
+    pub async fn query<T: DeserializeOwned>(&self, operation: &'static str, query: &str, variables: Value) -> Result<T, UserTokenError> {
+        run_graphql(&self.client, &self.saleor_api_url, Some(self.token.expose()), operation, query, variables)
+            .await
+            .map_err(UserTokenError)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for UserClient
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Some(verified) = parts.extensions.get::<VerifiedDashboardToken>().cloned() else {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "dashboard token not found in request extensions").into_response());
+        };
+        let client = HttpClient::from_request_parts(parts, state).await?;
+        let tokens = parts.extensions.get::<UserTokens>().cloned().unwrap_or_default();
+        let token = tokens.token(&client, &verified.saleor_api_url, &verified.token).await.map_err(IntoResponse::into_response)?;
+        Ok(Self { saleor_api_url: verified.saleor_api_url, client, token })
+    }
+}
@@ -0,0 +1,259 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use axum::{http::{Request, header::CONTENT_LENGTH}, response::{Response, IntoResponse}, body::{Body, Bytes}, extract::{FromRequest, FromRequestParts}};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hyper::body::HttpBody;
+use jsonwebtoken::Algorithm;
+use reqwest::StatusCode;
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{http_client::HttpClient, telemetry, webhook_archive::WebhookArchive};
+
+use super::{request_tenant, Jwks, JwksCache, JwksFetchError, JwtValidation, SaleorApl, TenantRequest};
+
+pub const SALEOR_SIGNATURE_HEADER: &str = "saleor-signature";
+pub const SALEOR_EVENT_HEADER: &str = "saleor-event";
+pub const SALEOR_API_URL_HEADER: &str = "saleor-api-url";
+/// Sent instead of `saleor-api-url` by older Saleor versions, see [`super::DomainTenantResolver`].
+pub const SALEOR_DOMAIN_HEADER: &str = "saleor-domain";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    MissingHeader(&'static str),
+    /// The webhook was sent by an installation that isn't in the APL
+    NotInstalled,
+    InvalidSignature(String),
+    InvalidPayload(String),
+    /// The payload is larger than the [`WebhookBodyLimit`]
+    PayloadTooLarge(usize),
+}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingHeader(header) => write!(f, "missing {} header", header),
+            Self::NotInstalled => write!(f, "app is not installed"),
+            Self::InvalidSignature(e) => write!(f, "invalid webhook signature: {}", e),
+            Self::InvalidPayload(e) => write!(f, "invalid webhook payload: {}", e),
+            Self::PayloadTooLarge(limit) => write!(f, "webhook payload is larger than {} bytes", limit),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::MissingHeader(_) | Self::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            Self::NotInstalled | Self::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SignatureHeader {
+    alg: String,
+    kid: Option<String>,
+    /// `false` when the payload is signed as is instead of base64 encoded (RFC 7797)
+    b64: Option<bool>,
+}
+
+/// The detached JWS of the `saleor-signature` header, parsed before the payload is read.
+pub struct DetachedJws<'a> {
+    header_b64: &'a str,
+    signature_b64: &'a str,
+    header: SignatureHeader,
+    algorithm: Algorithm,
+}
+
+impl<'a> DetachedJws<'a> {
+    /// Parses `signature`, rejecting algorithms `validation` doesn't allow, which never include the
+    /// symmetric `HS*` ones.
+    pub fn parse(signature: &'a str, validation: &JwtValidation) -> Result<Self, WebhookError> {
+        let invalid = |e: String| WebhookError::InvalidSignature(e);
+
+        let (header_b64, signature_b64) = match signature.split('.').collect::<Vec<_>>()[..] {
+            [header, "", signature] => (header, signature),
+            _ => return Err(invalid("expected a detached jws".to_string())),
+        };
+        let header = URL_SAFE_NO_PAD
+            .decode(header_b64)
+            .map_err(|e| invalid(format!("unable to decode jws header: {}", e)))?;
+        let header = serde_json::from_slice::<SignatureHeader>(&header).map_err(|e| invalid(format!("unable to deserialize jws header: {}", e)))?;
+        let algorithm = Algorithm::from_str(&header.alg).map_err(|_| invalid(format!("unsupported algorithm {}", header.alg)))?;
+        if !validation.algorithms().contains(&algorithm) {
+            return Err(invalid(format!("algorithm {} is not allowed", header.alg)));
+        }
+
+        Ok(Self { header_b64, signature_b64, header, algorithm })
+    }
+
+    /// The key the payload was signed with, if the header names one.
+    pub fn kid(&self) -> Option<&str> {
+        self.header.kid.as_deref()
+    }
+
+    /// What the signing input starts with, the payload follows it when it's signed unencoded.
+    pub fn signing_prefix(&self) -> String {
+        format!("{}.", self.header_b64)
+    }
+
+    /// Verifies the signature of `payload`.
+    pub fn verify(&self, jwks: &Jwks, payload: &[u8]) -> Result<(), WebhookError> {
+        let mut message = self.signing_prefix().into_bytes();
+        match self.header.b64 {
+            Some(false) => message.extend_from_slice(payload),
+            _ => message.extend_from_slice(URL_SAFE_NO_PAD.encode(payload).as_bytes()),
+        }
+        self.verify_signing_input(jwks, &message)
+    }
+
+    /// Verifies a payload that was read right after [`Self::signing_prefix`], without copying it.
+    ///
+    /// Falls back to [`Self::verify`] if the payload is signed base64 encoded.
+    pub fn verify_prefixed(&self, jwks: &Jwks, prefixed_payload: &[u8]) -> Result<(), WebhookError> {
+        match self.header.b64 {
+            Some(false) => self.verify_signing_input(jwks, prefixed_payload),
+            _ => self.verify(jwks, &prefixed_payload[self.header_b64.len() + 1..]),
+        }
+    }
+
+    fn verify_signing_input(&self, jwks: &Jwks, message: &[u8]) -> Result<(), WebhookError> {
+        let invalid = |e: String| WebhookError::InvalidSignature(e);
+        let key = jwks.decoding_key_for(self.header.kid.as_deref(), self.algorithm).map_err(invalid)?;
+        match jsonwebtoken::crypto::verify(self.signature_b64, message, &key, self.algorithm) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(invalid("signature doesn't match the payload".to_string())),
+            Err(e) => Err(invalid(e.to_string())),
+        }
+    }
+}
+
+/// Verifies the detached JWS Saleor sends in the `saleor-signature` header against the installation's JWKS.
+pub fn verify_webhook_signature(jwks: &str, signature: &str, payload: &[u8]) -> Result<(), WebhookError> {
+    let jwks = Jwks::parse(jwks).map_err(WebhookError::InvalidSignature)?;
+    verify_webhook_signature_with_jwks(&jwks, signature, payload)
+}
+
+/// Like [`verify_webhook_signature`] with an already parsed JWKS, see [`JwksCache`].
+pub fn verify_webhook_signature_with_jwks(jwks: &Jwks, signature: &str, payload: &[u8]) -> Result<(), WebhookError> {
+    DetachedJws::parse(signature, &JwtValidation::default())?.verify(jwks, payload)
+}
+
+/// The most a webhook body may contain, taken from the request extensions by [`SaleorWebhook`].
+///
+/// Defaults to the webhook limit of [`crate::limits::RequestLimits`] when not set.
+#[derive(Debug, Clone, Copy)]
+pub struct WebhookBodyLimit(pub usize);
+
+impl Default for WebhookBodyLimit {
+    fn default() -> Self {
+        Self(crate::limits::RequestLimits::default().webhooks.body_limit_bytes)
+    }
+}
+
+/// Reads `body` into a single buffer after `prefix`, rejecting it as soon as it exceeds `limit`.
+async fn read_prefixed_body(prefix: &str, content_length: Option<usize>, mut body: Body, limit: usize) -> Result<Bytes, WebhookError> {
+    if content_length.is_some_and(|length| length > limit) {
+        return Err(WebhookError::PayloadTooLarge(limit));
+    }
+
+    let mut buffer = Vec::with_capacity(prefix.len() + content_length.unwrap_or_default());
+    buffer.extend_from_slice(prefix.as_bytes());
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| WebhookError::InvalidPayload(e.to_string()))?;
+        if buffer.len() - prefix.len() + chunk.len() > limit {
+            return Err(WebhookError::PayloadTooLarge(limit));
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(buffer))
+}
+
+/// A webhook delivery of an installation, with its signature verified and payload deserialized.
+///
+/// The JWKS of the installation is taken from the APL, so only installed Saleor instances can deliver webhooks.
+#[derive(Debug)]
+pub struct SaleorWebhook<T> {
+    pub saleor_api_url: String,
+    /// The `saleor-event` header, e.g. `product_updated`
+    pub event: String,
+    pub payload: T,
+}
+
+#[async_trait]
+impl<S, T> FromRequest<S, Body> for SaleorWebhook<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = req.into_parts();
+        let header = |name: &'static str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+                .ok_or(WebhookError::MissingHeader(name))
+        };
+        // the tenant names the JWKS the signature is checked against, so any resolver is safe here
+        let saleor_api_url = request_tenant(&TenantRequest::from_parts(&parts))
+            .ok_or(WebhookError::MissingHeader(SALEOR_API_URL_HEADER))
+            .map_err(IntoResponse::into_response)?;
+        let event = header(SALEOR_EVENT_HEADER).map_err(IntoResponse::into_response)?;
+        let signature = header(SALEOR_SIGNATURE_HEADER).map_err(IntoResponse::into_response)?;
+        let apl = SaleorApl::from_request_parts(&mut parts, state).await?;
+        telemetry::record_tenant(&saleor_api_url, apl.app_id());
+        telemetry::record_event_type(&event);
+
+        let auth_data = apl.get(&apl.id(&saleor_api_url)).await.map_err(IntoResponse::into_response)?;
+        telemetry::record_apl_lookup(auth_data.is_some());
+        // JWKS an operator cleared are fetched again
+        let auth_data = auth_data.ok_or_else(|| WebhookError::NotInstalled.into_response())?;
+
+        let validation = parts.extensions.get::<JwtValidation>().cloned().unwrap_or_default();
+        let jws = DetachedJws::parse(&signature, &validation).map_err(IntoResponse::into_response)?;
+        let client = parts.extensions.get::<HttpClient>().cloned().unwrap_or_default();
+        let jwks = parts
+            .extensions
+            .get::<JwksCache>()
+            .cloned()
+            .unwrap_or_default()
+            .jwks_for(&client, &**apl, &auth_data, jws.kid())
+            .await
+            .map_err(|e| match e {
+                JwksFetchError::Invalid(e) => WebhookError::InvalidSignature(e).into_response(),
+                e => e.into_response(),
+            })?;
+
+        // the payload is read right behind the signing prefix, so it's verified and deserialized without a copy
+        let prefix = jws.signing_prefix();
+        let limit = parts.extensions.get::<WebhookBodyLimit>().copied().unwrap_or_default();
+        let content_length = parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse().ok());
+        let body = read_prefixed_body(&prefix, content_length, body, limit.0).await.map_err(IntoResponse::into_response)?;
+        jws.verify_prefixed(&jwks, &body).map_err(IntoResponse::into_response)?;
+        // archived before deserializing, payloads the handler can't read are the interesting ones
+        if let Some(archive) = parts.extensions.get::<WebhookArchive>() {
+            archive.record(&parts, &saleor_api_url, &event, &body[prefix.len()..]);
+        }
+        let payload = serde_json::from_slice(&body[prefix.len()..])
+            .map_err(|e| WebhookError::InvalidPayload(e.to_string()).into_response())?;
+
+        Ok(Self {
+            saleor_api_url,
+            event,
+            payload,
+        })
+    }
+}
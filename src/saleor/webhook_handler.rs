@@ -0,0 +1,136 @@
+use async_trait::async_trait;
+use axum::{
+    response::IntoResponse,
+    routing::{post, MethodRouter},
+    Router,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{graphql_cache::GraphqlCache, http_client::HttpClient};
+
+use super::{
+    AuthData, MetadataSettingsManager, SaleorApl, SaleorAsyncWebhookEvent, SaleorSyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest, SettingsManager,
+    TenantSettings, WebhookError,
+};
+
+pub use saleor_app_macros::saleor_webhook;
+
+/// Everything a [`WebhookHandler`] gets besides the payload.
+#[derive(Clone)]
+pub struct WebhookContext {
+    /// The installation that sent the webhook
+    pub auth_data: AuthData,
+    /// Client for the GraphQL API of the installation and any other outgoing request
+    pub client: HttpClient,
+    /// Settings of the installation, kept in the private metadata of the app
+    pub settings: TenantSettings,
+    /// The `saleor-event` header, e.g. `product_updated`
+    pub event: String,
+    /// Span of the delivery, tagged with the installation and event
+    pub span: tracing::Span,
+}
+
+impl WebhookContext {
+    /// A context for `auth_data` with its settings in Saleor, for calling handlers outside of a
+    /// request like in tests.
+    pub fn new(auth_data: AuthData, client: HttpClient) -> Self {
+        let settings = TenantSettings::new(auth_data.saleor_api_url.clone(), MetadataSettingsManager::new(client.clone(), auth_data.clone()));
+        Self { auth_data, client, settings, event: String::new(), span: tracing::Span::none() }
+    }
+
+    pub fn with_settings(mut self, settings: impl SettingsManager) -> Self {
+        self.settings = TenantSettings::new(self.auth_data.saleor_api_url.clone(), settings);
+        self
+    }
+
+    pub fn with_event(mut self, event: impl Into<String>) -> Self {
+        self.event = event.into();
+        self
+    }
+}
+
+/// Handles the webhook deliveries of the subscription with the payload `E`, routed with
+/// [`webhook_handler`].
+///
+/// Implementations are plain structs holding what they need beyond the [`WebhookContext`], they
+/// can be called without a request:
+///
+/// ```ignore
+/// #[derive(Clone)]
+/// struct OrderNotifier { mailer: Mailer }
+///
+/// #[async_trait]
+/// impl WebhookHandler<OrderCreated> for OrderNotifier {
+///     type Response = Result<StatusCode, NotifyError>;
+///
+///     async fn handle(&self, context: &WebhookContext, payload: OrderCreated) -> Self::Response {
+///         // ...
+///     }
+/// }
+///
+/// Router::new().route(ORDER_CREATED_PATH, webhook_handler(OrderNotifier { mailer }))
+/// ```
+#[async_trait]
+pub trait WebhookHandler<E>: Clone + Send + Sync + 'static {
+    type Response: IntoResponse;
+
+    async fn handle(&self, context: &WebhookContext, payload: E) -> Self::Response;
+}
+
+/// Routes `POST` deliveries to `handler` once their signature is verified like [`SaleorWebhook`]
+/// does.
+pub fn webhook_handler<E, H, S>(handler: H) -> MethodRouter<S>
+where
+    E: DeserializeOwned + Send + 'static,
+    H: WebhookHandler<E>,
+    S: Clone + Send + Sync + 'static,
+{
+    post(move |apl: SaleorApl, client: HttpClient, cache: GraphqlCache, webhook: SaleorWebhook<E>| async move {
+        // verified deliveries come from installations, unless they were removed in the meantime
+        let auth_data = match apl.get(&apl.id(&webhook.saleor_api_url)).await {
+            Ok(Some(auth_data)) => auth_data,
+            Ok(None) => return WebhookError::NotInstalled.into_response(),
+            Err(e) => return e.into_response(),
+        };
+        let settings = TenantSettings::new(auth_data.saleor_api_url.clone(), MetadataSettingsManager::new(client.clone(), auth_data.clone()).with_cache(cache));
+        let context = WebhookContext { auth_data, client, settings, event: webhook.event, span: tracing::Span::current() };
+        handler.handle(&context, webhook.payload).await.into_response()
+    })
+}
+
+/// A webhook whose handler, manifest entry and route are declared in one place, usually by
+/// [`saleor_webhook`].
+pub trait DeclaredWebhook: Sized {
+    type Payload: DeserializeOwned + Send + 'static;
+    /// Name of the webhook in the manifest
+    const NAME: &'static str;
+    /// Path the webhook is delivered to, below the base url of the app
+    const PATH: &'static str;
+    /// The subscription query
+    const QUERY: &'static str;
+
+    fn async_events() -> Vec<SaleorAsyncWebhookEvent>;
+    fn sync_events() -> Vec<SaleorSyncWebhookEvent>;
+
+    /// The manifest entry, `base_url` is where the app is reachable.
+    fn manifest(base_url: &str) -> SaleorWebhookManifest {
+        let (async_events, sync_events) = (Self::async_events(), Self::sync_events());
+        SaleorWebhookManifest {
+            name: Self::NAME.to_string(),
+            async_events: (!async_events.is_empty()).then_some(async_events),
+            sync_events: (!sync_events.is_empty()).then_some(sync_events),
+            query: Self::QUERY.to_string(),
+            target_url: format!("{}{}", base_url, Self::PATH),
+            is_active: Some(true),
+        }
+    }
+
+    /// Adds the route of the webhook to `router`, see [`webhook_handler`].
+    fn route<S>(self, router: Router<S>) -> Router<S>
+    where
+        Self: WebhookHandler<Self::Payload>,
+        S: Clone + Send + Sync + 'static,
+    {
+        router.route(Self::PATH, webhook_handler(self))
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::http_client::HttpClient;
+
+use super::{graphql::{mutation_errors, run_app_graphql}, AuthData, SaleorAsyncWebhookEvent, SaleorSyncWebhookEvent, SaleorWebhookManifest};
+
+// written by hand, cynic's enums can't share the serde impls of the manifest enums
+const APP_WEBHOOKS_QUERY: &str = r#"query AppWebhooks {
+  app {
+    webhooks {
+      id
+      name
+      targetUrl
+      isActive
+      subscriptionQuery
+      asyncEvents { eventType }
+      syncEvents { eventType }
+    }
+  }
+}"#;
+
+const WEBHOOK_CREATE_MUTATION: &str = r#"mutation WebhookCreate($input: WebhookCreateInput!) {
+  webhookCreate(input: $input) { errors { field message } }
+}"#;
+
+const WEBHOOK_UPDATE_MUTATION: &str = r#"mutation WebhookUpdate($id: ID!, $input: WebhookUpdateInput!) {
+  webhookUpdate(id: $id, input: $input) { errors { field message } }
+}"#;
+
+const WEBHOOK_DELETE_MUTATION: &str = r#"mutation WebhookDelete($id: ID!) {
+  webhookDelete(id: $id) { errors { field message } }
+}"#;
+
+#[derive(Debug)]
+pub struct WebhookSyncError(String);
+
+impl std::fmt::Display for WebhookSyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "webhook sync error: {}", self.0)
+    }
+}
+
+impl std::error::Error for WebhookSyncError {}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookChangeKind {
+    Created,
+    Updated,
+    Deleted,
+    Unchanged,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WebhookChange {
+    pub name: String,
+    pub kind: WebhookChangeKind,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AsyncEvent {
+    event_type: SaleorAsyncWebhookEvent,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SyncEvent {
+    event_type: SaleorSyncWebhookEvent,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct InstalledWebhook {
+    id: String,
+    name: Option<String>,
+    target_url: String,
+    is_active: bool,
+    subscription_query: Option<String>,
+    async_events: Vec<AsyncEvent>,
+    sync_events: Vec<SyncEvent>,
+}
+
+impl InstalledWebhook {
+    fn matches(&self, webhook: &SaleorWebhookManifest) -> bool {
+        let async_events: Vec<_> = self.async_events.iter().map(|event| event.event_type.clone()).collect();
+        let sync_events: Vec<_> = self.sync_events.iter().map(|event| event.event_type.clone()).collect();
+        self.target_url == webhook.target_url
+            && self.is_active == webhook.is_active.unwrap_or(true)
+            && self.subscription_query.as_deref() == Some(webhook.query.as_str())
+            && async_events == webhook.async_events.clone().unwrap_or_default()
+            && sync_events == webhook.sync_events.clone().unwrap_or_default()
+    }
+}
+
+fn webhook_input(webhook: &SaleorWebhookManifest) -> Value {
+    json!({
+        "name": webhook.name,
+        "targetUrl": webhook.target_url,
+        "asyncEvents": webhook.async_events.clone().unwrap_or_default(),
+        "syncEvents": webhook.sync_events.clone().unwrap_or_default(),
+        "query": webhook.query,
+        "isActive": webhook.is_active.unwrap_or(true),
+    })
+}
+
+/// Brings the webhooks of an installation in line with the manifest, webhooks are matched by name.
+///
+/// Saleor only reads the manifest on installation, so changed webhooks never reach existing
+/// installations otherwise. Webhooks missing from the manifest are only deleted with `prune`, with
+/// `dry_run` the changes are returned without applying them.
+pub async fn sync_webhooks(client: &HttpClient, auth_data: &AuthData, webhooks: &[SaleorWebhookManifest], prune: bool, dry_run: bool) -> Result<Vec<WebhookChange>, WebhookSyncError> {
+    let data: Value = run_app_graphql(client, auth_data, "AppWebhooks", APP_WEBHOOKS_QUERY, json!({}))
+        .await
+        .map_err(|e| WebhookSyncError(e.to_string()))?;
+    let installed: Vec<InstalledWebhook> = match data.pointer("/app/webhooks") {
+        Some(webhooks) => serde_json::from_value(webhooks.clone()).map_err(|e| WebhookSyncError(format!("unexpected webhooks: {}", e)))?,
+        None => return Err(WebhookSyncError("app not found, is the token still valid?".to_string())),
+    };
+
+    let mut changes = vec![];
+    for webhook in webhooks {
+        let existing = installed.iter().find(|installed| installed.name.as_deref() == Some(webhook.name.as_str()));
+        let kind = match existing {
+            Some(existing) if existing.matches(webhook) => WebhookChangeKind::Unchanged,
+            Some(existing) => {
+                if !dry_run {
+                    let variables = json!({ "id": existing.id, "input": webhook_input(webhook) });
+                    run_mutation(client, auth_data, "WebhookUpdate", WEBHOOK_UPDATE_MUTATION, variables).await?;
+                }
+                WebhookChangeKind::Updated
+            }
+            None => {
+                if !dry_run {
+                    let variables = json!({ "input": webhook_input(webhook) });
+                    run_mutation(client, auth_data, "WebhookCreate", WEBHOOK_CREATE_MUTATION, variables).await?;
+                }
+                WebhookChangeKind::Created
+            }
+        };
+        changes.push(WebhookChange { name: webhook.name.clone(), kind });
+    }
+
+    if prune {
+        let stale = installed
+            .iter()
+            .filter(|installed| !webhooks.iter().any(|webhook| installed.name.as_deref() == Some(webhook.name.as_str())));
+        for webhook in stale {
+            if !dry_run {
+                run_mutation(client, auth_data, "WebhookDelete", WEBHOOK_DELETE_MUTATION, json!({ "id": webhook.id })).await?;
+            }
+            changes.push(WebhookChange {
+                name: webhook.name.clone().unwrap_or_else(|| webhook.id.clone()),
+                kind: WebhookChangeKind::Deleted,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Runs a webhook mutation, failing on the errors it reports.
+async fn run_mutation(client: &HttpClient, auth_data: &AuthData, operation: &'static str, mutation: &str, variables: Value) -> Result<(), WebhookSyncError> {
+    let data: Value = run_app_graphql(client, auth_data, operation, mutation, variables)
+        .await
+        .map_err(|e| WebhookSyncError(e.to_string()))?;
+    match mutation_errors(&data) {
+        Some(errors) => Err(WebhookSyncError(format!("{} failed: {}", operation, Value::from(errors.clone())))),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,279 @@
+//! Widgets on the order and product details pages of the dashboard.
+//!
+//! Extensions with the `WIDGET` target are rendered in an iframe on the details page. With the
+//! `POST` method the dashboard posts a form with the installation, a token of the user and the id
+//! of the object. [`Widget`] verifies the token like [`super::SaleorAuthLayer`], fetches the object
+//! with the app's token and hands the handler a [`WidgetObject`] ready to render, like
+//! [`OrderDetails`] or [`ProductDetails`]. Routes need a [`super::SaleorAplLayer`] and an
+//! [`HttpClient`] extension, and [`extension`] declares the widget in the manifest.
+//!
+//! ```ignore
+//! async fn product_widget(widget: Widget<ProductDetails>) -> impl IntoResponse {
+//!     format!("{} has {} variants", widget.object.name, widget.object.variants.len())
+//! }
+//!
+//! let router = Router::new().route(PRODUCT_DETAILS_WIDGET_PATH, post(product_widget));
+//! ```
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{FromRequest, FromRequestParts},
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+    Form,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{http_client::HttpClient, secrets::SecretString};
+
+use super::{
+    graphql::run_app_graphql, money::Money, GraphqlError, verify_jwt_with_jwks, Claims, JwksCache, JwtValidation, SaleorApl, SaleorAppExtension,
+    SaleorAppExtensionMount, SaleorAppExtensionOptions, SaleorAppExtensionTarget, SaleorAppPermission, SaleorPermission, SaleorWidgetMethod,
+    SaleorWidgetTarget, VerifyJwtError,
+};
+
+pub const ORDER_DETAILS_WIDGET_PATH: &str = "/app/widgets/order-details";
+pub const PRODUCT_DETAILS_WIDGET_PATH: &str = "/app/widgets/product-details";
+
+/// The form the dashboard posts to `POST` widgets.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WidgetPayload {
+    pub saleor_api_url: String,
+    /// Token of the dashboard user, verified against the installation's JWKS
+    pub access_token: SecretString,
+    /// Id of the app inside the installing Saleor instance
+    pub app_id: Option<String>,
+    pub order_id: Option<String>,
+    pub product_id: Option<String>,
+}
+
+/// An object a widget is shown for, fetched with the app's token.
+pub trait WidgetObject: DeserializeOwned + Send + 'static {
+    /// The mount showing the widget
+    const MOUNT: SaleorAppExtensionMount;
+    /// The permissions the user needs to see the widget
+    const PERMISSIONS: &'static [SaleorAppPermission];
+    const OPERATION: &'static str;
+    /// Query taking the id as `$id`, answering with the object as `object`
+    const QUERY: &'static str;
+
+    /// The id in the form of the mount.
+    fn object_id(payload: &WidgetPayload) -> Option<&str>;
+}
+
+/// Rejections of [`Widget`].
+#[derive(Debug)]
+pub enum WidgetError {
+    /// The form lacks fields or the id of the object
+    InvalidPayload(String),
+    /// The installation isn't registered
+    UnknownInstallation,
+    Unauthorized(VerifyJwtError),
+    /// The object doesn't exist, or the app can't see it
+    NotFound,
+    Graphql(GraphqlError),
+    /// A routing mistake, like missing extensions
+    Internal(String),
+}
+
+impl std::fmt::Display for WidgetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPayload(e) => write!(f, "invalid widget payload: {}", e),
+            Self::UnknownInstallation => write!(f, "unknown installation"),
+            Self::Unauthorized(e) => write!(f, "{}", e),
+            Self::NotFound => write!(f, "object not found"),
+            Self::Graphql(e) => write!(f, "unable to fetch the object: {}", e),
+            Self::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for WidgetError {}
+
+impl IntoResponse for WidgetError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            Self::UnknownInstallation => StatusCode::UNAUTHORIZED,
+            Self::Unauthorized(e) => return e.clone().into_response(),
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Graphql(GraphqlError::AppTokenInvalid { .. }) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Graphql(_) => StatusCode::BAD_GATEWAY,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// A verified widget request with the object it is shown for.
+pub struct Widget<T> {
+    pub payload: WidgetPayload,
+    /// Claims of the dashboard user
+    pub claims: Claims,
+    pub object: T,
+}
+
+#[async_trait]
+impl<S, T> FromRequest<S, Body> for Widget<T>
+where
+    S: Send + Sync,
+    T: WidgetObject,
+{
+    type Rejection = WidgetError;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = request.into_parts();
+        let apl = SaleorApl::from_request_parts(&mut parts, state).await.map_err(|_| WidgetError::Internal("apl store not found in request extensions".to_string()))?;
+        let client = HttpClient::from_request_parts(&mut parts, state).await.map_err(|_| WidgetError::Internal("http client not found in request extensions".to_string()))?;
+        let Ok(jwks_cache) = JwksCache::from_request_parts(&mut parts, state).await;
+        let Ok(jwt_validation) = JwtValidation::from_request_parts(&mut parts, state).await;
+        let Form(payload) = Form::<WidgetPayload>::from_request(Request::from_parts(parts, body), state)
+            .await
+            .map_err(|e| WidgetError::InvalidPayload(e.body_text()))?;
+        let object_id = T::object_id(&payload)
+            .ok_or_else(|| WidgetError::InvalidPayload(format!("missing the object of {:?}", T::MOUNT)))?
+            .to_string();
+
+        let auth_data = apl
+            .get(&apl.id(&payload.saleor_api_url))
+            .await
+            .map_err(|e| WidgetError::Internal(e.to_string()))?
+            .ok_or(WidgetError::UnknownInstallation)?;
+        let jwks = match &auth_data.jwks {
+            Some(jwks) => jwks_cache.get(&auth_data.saleor_api_url, jwks).map_err(|e| WidgetError::Unauthorized(VerifyJwtError::Invalid(e)))?,
+            None => jwks_cache
+                .fetch(&client, &auth_data)
+                .await
+                .map_err(|e| WidgetError::Unauthorized(VerifyJwtError::Invalid(e.to_string())))?,
+        };
+        let permissions: Vec<SaleorPermission> = T::PERMISSIONS.iter().cloned().map(SaleorPermission::from).collect();
+        let claims = verify_jwt_with_jwks(&jwks, payload.access_token.expose(), &permissions, &jwt_validation).map_err(WidgetError::Unauthorized)?;
+
+        #[derive(Deserialize)]
+        struct Data<T> {
+            object: Option<T>,
+        }
+        let data: Data<T> = run_app_graphql(&client, &auth_data, T::OPERATION, T::QUERY, json!({ "id": object_id }))
+            .await
+            .map_err(WidgetError::Graphql)?;
+        let object = data.object.ok_or(WidgetError::NotFound)?;
+
+        Ok(Self { payload, claims, object })
+    }
+}
+
+/// The manifest entry of a `POST` widget of `T`, `url` being a path or an absolute url.
+pub fn extension<T: WidgetObject>(label: &str, url: &str) -> SaleorAppExtension {
+    SaleorAppExtension {
+        label: label.to_string(),
+        mount: T::MOUNT,
+        target: SaleorAppExtensionTarget::Widget,
+        permissions: T::PERMISSIONS.to_vec(),
+        url: url.to_string(),
+        options: Some(SaleorAppExtensionOptions {
+            widget_target: Some(SaleorWidgetTarget { method: SaleorWidgetMethod::Post }),
+            ..Default::default()
+        }),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WidgetNamed {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WidgetTotal {
+    pub gross: Money,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WidgetLine {
+    pub id: String,
+    pub quantity: u32,
+}
+
+/// The order of an `ORDER_DETAILS_WIDGETS` widget, the app needs `MANAGE_ORDERS`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderDetails {
+    pub id: String,
+    pub number: String,
+    pub status: String,
+    pub created: String,
+    pub user_email: Option<String>,
+    pub total: WidgetTotal,
+    pub lines: Vec<WidgetLine>,
+}
+
+impl WidgetObject for OrderDetails {
+    const MOUNT: SaleorAppExtensionMount = SaleorAppExtensionMount::OrderDetailsWidgets;
+    const PERMISSIONS: &'static [SaleorAppPermission] = &[SaleorAppPermission::ManageOrders];
+    const OPERATION: &'static str = "WidgetOrder";
+    const QUERY: &'static str = "query WidgetOrder($id: ID!) {
+  object: order(id: $id) {
+    id
+    number
+    status
+    created
+    userEmail
+    total { gross { amount currency } }
+    lines { id quantity }
+  }
+}";
+
+    fn object_id(payload: &WidgetPayload) -> Option<&str> {
+        payload.order_id.as_deref()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WidgetVariant {
+    pub id: String,
+    pub name: String,
+    pub sku: Option<String>,
+}
+
+/// The product of a `PRODUCT_DETAILS_WIDGETS` widget, the app needs `MANAGE_PRODUCTS`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductDetails {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub updated_at: String,
+    pub product_type: WidgetNamed,
+    pub category: Option<WidgetNamed>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub variants: Vec<WidgetVariant>,
+}
+
+impl WidgetObject for ProductDetails {
+    const MOUNT: SaleorAppExtensionMount = SaleorAppExtensionMount::ProductDetailsWidgets;
+    const PERMISSIONS: &'static [SaleorAppPermission] = &[SaleorAppPermission::ManageProducts];
+    const OPERATION: &'static str = "WidgetProduct";
+    const QUERY: &'static str = "query WidgetProduct($id: ID!) {
+  object: product(id: $id) {
+    id
+    name
+    slug
+    updatedAt
+    productType { name }
+    category { name }
+    variants { id name sku }
+  }
+}";
+
+    fn object_id(payload: &WidgetPayload) -> Option<&str> {
+        payload.product_id.as_deref()
+    }
+}
+
+/// `variants` is nullable in the schema.
+fn null_as_empty<'de, D: serde::Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Vec<T>, D::Error> {
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
+}
@@ -0,0 +1,206 @@
+use std::{fmt::Write, path::{Path, PathBuf}, str::FromStr};
+
+use anyhow::{bail, Context};
+
+use crate::saleor::SaleorAsyncWebhookEvent;
+
+const CARGO_TOML: &str = include_str!("../scaffold/Cargo.toml.tmpl");
+const MAIN_RS: &str = include_str!("../scaffold/src/main.rs.tmpl");
+const HOME_HTML: &str = include_str!("../scaffold/templates/home.html.tmpl");
+const README: &str = include_str!("../scaffold/README.md.tmpl");
+const ENV_EXAMPLE: &str = include_str!("../scaffold/env.tmpl");
+const GITIGNORE: &str = include_str!("../scaffold/gitignore.tmpl");
+
+const SALEOR_APP_GIT: &str = "https://github.com/cozyGalvinism/saleor-app-rust-axum";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AplBackend {
+    /// [`FileAplStore`](crate::saleor::FileAplStore), a single installation in a local file
+    #[default]
+    File,
+    /// [`MemoryAplStore`](crate::saleor::MemoryAplStore), installations are lost on restart
+    Memory,
+}
+
+impl FromStr for AplBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(Self::File),
+            "memory" => Ok(Self::Memory),
+            other => Err(format!("unknown apl {other:?}, expected file or memory")),
+        }
+    }
+}
+
+impl AplBackend {
+    fn store_type(self) -> &'static str {
+        match self {
+            Self::File => "FileAplStore",
+            Self::Memory => "MemoryAplStore",
+        }
+    }
+
+    fn store(self) -> &'static str {
+        match self {
+            Self::File => "FileAplStore",
+            Self::Memory => "MemoryAplStore::new()",
+        }
+    }
+}
+
+/// What `saleor-app new` generates.
+#[derive(Debug, Clone)]
+pub struct ScaffoldOptions {
+    /// Name of the app in the dashboard
+    pub name: String,
+    pub apl: AplBackend,
+    /// Async events that get a webhook with a stub handler
+    pub webhooks: Vec<SaleorAsyncWebhookEvent>,
+    /// Depend on a local checkout of this crate instead of the git repository
+    pub saleor_app_path: Option<PathBuf>,
+}
+
+/// Parses an event like `PRODUCT_CREATED`.
+pub fn parse_webhook_event(event: &str) -> Result<SaleorAsyncWebhookEvent, String> {
+    serde_json::from_value(serde_json::Value::String(event.to_uppercase())).map_err(|_| format!("unknown async event {event:?}"))
+}
+
+/// Generates a new app project in `dir`, which must not exist yet. Returns the written files.
+pub fn scaffold(dir: &Path, options: &ScaffoldOptions) -> anyhow::Result<Vec<PathBuf>> {
+    if dir.exists() {
+        bail!("{} already exists", dir.display());
+    }
+    if options.webhooks.contains(&SaleorAsyncWebhookEvent::AnyEvents) {
+        bail!("ANY_EVENTS can't be subscribed to, pick the individual events");
+    }
+    let crate_name = crate_name(dir)?;
+    let saleor_app_dependency = match &options.saleor_app_path {
+        Some(path) => format!("{{ path = {:?} }}", path.display().to_string()),
+        None => format!("{{ git = \"{}\" }}", SALEOR_APP_GIT),
+    };
+    let render = |template: &str| {
+        template
+            .replace("{{crate_name}}", &crate_name)
+            .replace("{{app_name}}", &options.name)
+            .replace("{{apl_store_type}}", options.apl.store_type())
+            .replace("{{apl_store}}", options.apl.store())
+            .replace("{{saleor_app_dependency}}", &saleor_app_dependency)
+    };
+
+    let files = [
+        ("Cargo.toml", render(CARGO_TOML)),
+        ("src/main.rs", render(MAIN_RS)),
+        ("src/webhooks.rs", webhooks_module(&options.webhooks)),
+        ("templates/home.html", HOME_HTML.to_string()),
+        ("README.md", render(README)),
+        (".env.example", ENV_EXAMPLE.to_string()),
+        (".gitignore", GITIGNORE.to_string()),
+    ];
+    let mut written = vec![];
+    for (path, content) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, content).with_context(|| format!("unable to write {}", path.display()))?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+fn crate_name(dir: &Path) -> anyhow::Result<String> {
+    let name = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("the project directory needs a name")?
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        bail!("{name:?} isn't a valid crate name, it has to start with a letter");
+    }
+    Ok(name)
+}
+
+/// `src/webhooks.rs` with a route, manifest entry and stub handler per event.
+fn webhooks_module(events: &[SaleorAsyncWebhookEvent]) -> String {
+    let mut module = match events {
+        [] => "use saleor_app::{router_ext::SaleorWebhooks, saleor::SaleorWebhookManifest};\n".to_string(),
+        _ => "use axum::{http::StatusCode, response::IntoResponse, routing::post};\n\
+              use saleor_app::{\n    router_ext::SaleorWebhooks,\n    saleor::{SaleorAsyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest},\n};\n"
+            .to_string(),
+    };
+    let mut routes = String::new();
+    let mut manifests = String::new();
+    for event in events {
+        // the GraphQL type of an event is the variant name, like `ProductCreated` for PRODUCT_CREATED
+        let type_name = format!("{:?}", event);
+        let snake_name = snake_case(&type_name);
+        let const_name = snake_name.to_uppercase();
+        let path = format!("/api/webhooks/{}", snake_name.replace('_', "-"));
+        let _ = write!(
+            module,
+            r#"
+pub const {const_name}_PATH: &str = "{path}";
+
+/// Subscription query of [`{snake_name}`], add the fields the handler needs.
+pub const {const_name}_QUERY: &str = r"subscription {{
+  event {{
+    ... on {type_name} {{
+      __typename
+    }}
+  }}
+}}";
+
+/// `POST {path}`
+pub async fn {snake_name}(webhook: SaleorWebhook<serde_json::Value>) -> impl IntoResponse {{
+    tracing::info!(saleor_api_url = %webhook.saleor_api_url, payload = %webhook.payload, "{type_name} received");
+    StatusCode::OK
+}}
+"#
+        );
+        let _ = write!(routes, "\n        .route({const_name}_PATH, post({snake_name}))");
+        let _ = write!(
+            manifests,
+            r#"
+        SaleorWebhookManifest {{
+            name: "{type_name}".to_string(),
+            async_events: Some(vec![SaleorAsyncWebhookEvent::{type_name}]),
+            sync_events: None,
+            query: {const_name}_QUERY.to_string(),
+            target_url: format!("{{}}{{}}", base_url, {const_name}_PATH),
+            is_active: Some(true),
+        }},"#
+        );
+    }
+    let base_url = if events.is_empty() { "_base_url" } else { "base_url" };
+    let _ = write!(
+        module,
+        r#"
+/// The webhooks served by `main`, behind the webhook middleware of the base app.
+pub fn webhooks() -> SaleorWebhooks {{
+    SaleorWebhooks::new(){routes}
+}}
+
+/// The webhooks declared in the manifest, `base_url` is where the app is reachable.
+pub fn manifest({base_url}: &str) -> Vec<SaleorWebhookManifest> {{
+    vec![{manifests}
+    ]
+}}
+"#
+    );
+    module
+}
+
+fn snake_case(pascal_case: &str) -> String {
+    let mut snake_case = String::new();
+    for (i, c) in pascal_case.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            snake_case.push('_');
+        }
+        snake_case.push(c.to_ascii_lowercase());
+    }
+    snake_case
+}
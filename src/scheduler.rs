@@ -0,0 +1,439 @@
+//! Background jobs running on a schedule for every installation.
+//!
+//! Jobs are registered on a [`Scheduler`] with a [`Schedule`] and run for each installation in the
+//! APL when it's due, like a nightly catalog re-sync or reconciling webhooks. A run that is still
+//! going when the job is due again is skipped for that installation, and [`Scheduler::with_jitter`]
+//! spreads the installations over a window instead of hitting every Saleor at once. With several
+//! replicas, [`Scheduler::with_lock`] makes sure only one of them runs a job for an installation.
+//!
+//! ```ignore
+//! let scheduler = Scheduler::new(Arc::new(FileAplStore), http_client)
+//!     .with_jitter(Duration::from_secs(300))
+//!     .with_job("reindex", Schedule::cron("0 3 * * *")?, move |ctx: JobContext| {
+//!         let index = index.clone();
+//!         async move {
+//!             saleor::search::reindex(&ctx.client, &ctx.auth_data, index.as_ref()).await.map_err(|e| JobError(e.to_string()))?;
+//!             Ok(())
+//!         }
+//!     });
+//! // the jobs stop when the handle is dropped
+//! let _jobs = scheduler.start();
+//! ```
+
+use std::{collections::{hash_map::DefaultHasher, HashSet}, future::Future, hash::{Hash, Hasher}, str::FromStr, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use time::{Date, OffsetDateTime, Time};
+use tokio::task::JoinHandle;
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::{http_client::HttpClient, locks::DistributedLock, saleor::{AplStore, AuthData}, telemetry};
+
+/// Minutes a cron schedule is searched ahead for its next run, a bit more than four years.
+const CRON_SEARCH_MINUTES: usize = 4 * 366 * 24 * 60;
+
+#[derive(Debug)]
+pub struct JobError(pub String);
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "job error: {}", self.0)
+    }
+}
+
+impl std::error::Error for JobError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleError(pub String);
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid schedule: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// When a job runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every interval, starting one interval after the scheduler started
+    Every(Duration),
+    /// At the minutes matching a cron expression, in UTC
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    pub fn every(interval: Duration) -> Self {
+        Self::Every(interval)
+    }
+
+    /// A classic five field cron expression (`minute hour day-of-month month day-of-week`) or one
+    /// of `@hourly`, `@daily`, `@weekly` and `@monthly`, see [`CronSchedule`].
+    pub fn cron(expression: &str) -> Result<Self, ScheduleError> {
+        expression.parse().map(Self::Cron)
+    }
+
+    /// The next time the job is due after `now`, `None` if it never is again.
+    pub fn next_after(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        match self {
+            Self::Every(interval) => Some(now + *interval),
+            Self::Cron(cron) => cron.next_after(now),
+        }
+    }
+}
+
+/// The minutes of a cron expression, each field is a set of allowed values.
+///
+/// Fields are `*`, a value, a range (`1-5`) or a list of those (`1,15`), optionally stepped
+/// (`*/15`, `8-18/2`). Days of the week go from 0 (Sunday) to 6, 7 is Sunday as well. Like in cron
+/// a day matches if either the day of the month or of the week does when both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    days_of_week: u8,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+/// The bitset of the values of `field` between `min` and `max`.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, ScheduleError> {
+    let invalid = || ScheduleError(format!("{field:?} isn't a value between {min} and {max}"));
+    let mut values = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(invalid)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse().map_err(|_| invalid())?, end.parse().map_err(|_| invalid())?),
+                // a single value with a step runs from there to the end, like `5/15`
+                None if part.contains('/') => (range.parse().map_err(|_| invalid())?, max),
+                None => {
+                    let value = range.parse().map_err(|_| invalid())?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            values |= 1 << value;
+        }
+    }
+    Ok(values)
+}
+
+impl FromStr for CronSchedule {
+    type Err = ScheduleError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(ScheduleError(format!("{expression:?} doesn't have five fields")));
+        };
+        let mut days_of_week_values = parse_field(days_of_week, 0, 7)?;
+        if days_of_week_values & (1 << 7) != 0 {
+            days_of_week_values |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)? as u32,
+            days_of_month: parse_field(days_of_month, 1, 31)? as u32,
+            months: parse_field(months, 1, 12)? as u16,
+            days_of_week: (days_of_week_values & 0x7f) as u8,
+            any_day_of_month: days_of_month == "*",
+            any_day_of_week: days_of_week == "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    fn matches_day(&self, date: Date) -> bool {
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << date.weekday().number_days_from_sunday()) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+
+    /// The first matching minute after `now`, skipping whole months, days and hours that don't match.
+    pub fn next_after(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        let now = now.to_offset(time::UtcOffset::UTC);
+        let mut next = now.replace_time(Time::from_hms(now.hour(), now.minute(), 0).ok()?) + time::Duration::minutes(1);
+        for _ in 0..CRON_SEARCH_MINUTES {
+            if self.months & (1 << next.month() as u8) == 0 {
+                let (year, month) = match next.month() {
+                    time::Month::December => (next.year() + 1, time::Month::January),
+                    month => (next.year(), month.next()),
+                };
+                next = Date::from_calendar_date(year, month, 1).ok()?.midnight().assume_utc();
+            } else if !self.matches_day(next.date()) {
+                next = next.date().next_day()?.midnight().assume_utc();
+            } else if self.hours & (1 << next.hour()) == 0 {
+                next = next.replace_time(Time::from_hms(next.hour(), 0, 0).ok()?) + time::Duration::hours(1);
+            } else if self.minutes & (1 << next.minute()) == 0 {
+                next += time::Duration::minutes(1);
+            } else {
+                return Some(next);
+            }
+        }
+        None
+    }
+}
+
+/// What a job gets for the installation it runs for.
+#[derive(Clone)]
+pub struct JobContext {
+    pub auth_data: AuthData,
+    pub client: HttpClient,
+}
+
+/// Work done for a single installation at a time.
+#[async_trait]
+pub trait Job: Send + Sync + 'static {
+    async fn run(&self, ctx: JobContext) -> Result<(), JobError>;
+}
+
+#[async_trait]
+impl<F, Fut> Job for F
+where
+    F: Fn(JobContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), JobError>> + Send,
+{
+    async fn run(&self, ctx: JobContext) -> Result<(), JobError> {
+        self(ctx).await
+    }
+}
+
+/// How the installations fared in a run of a job.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobReport {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Installations whose previous run was still going, here or on another replica
+    pub skipped: usize,
+}
+
+struct ScheduledJob {
+    name: String,
+    schedule: Schedule,
+    job: Box<dyn Job>,
+    /// Installations the job is running for right now
+    running: Mutex<HashSet<String>>,
+}
+
+/// Removes the installation from the running ones when its run ends, even if the run panicked.
+struct RunningGuard<'a> {
+    running: &'a Mutex<HashSet<String>>,
+    saleor_api_url: String,
+}
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.saleor_api_url);
+    }
+}
+
+/// The lock a run holds for its installation, so other replicas skip it.
+#[derive(Clone)]
+struct JobLock {
+    lock: Arc<dyn DistributedLock>,
+    ttl: Duration,
+}
+
+enum Outcome {
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// Runs registered jobs for every installation of the APL on their schedule.
+pub struct Scheduler {
+    apl: Arc<dyn AplStore>,
+    client: HttpClient,
+    jitter: Duration,
+    lock: Option<JobLock>,
+    jobs: Vec<Arc<ScheduledJob>>,
+}
+
+impl Scheduler {
+    pub fn new(apl: Arc<dyn AplStore>, client: HttpClient) -> Self {
+        Self { apl, client, jitter: Duration::ZERO, lock: None, jobs: Vec::new() }
+    }
+
+    /// Delays the scheduled runs of every installation by up to `jitter`.
+    ///
+    /// The delay is derived from the job and the installation, so each installation keeps its slot
+    /// from one run to the next. Runs started with [`Self::run_now`] aren't delayed.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Takes a lock per job and installation for each run, for replicas sharing the APL.
+    ///
+    /// An installation whose lock is held by another replica is skipped. Locks aren't renewed, so
+    /// `ttl` has to outlast the slowest run of any job, plus the jitter.
+    pub fn with_lock(mut self, lock: Arc<dyn DistributedLock>, ttl: Duration) -> Self {
+        self.lock = Some(JobLock { lock, ttl });
+        self
+    }
+
+    /// Registers `job` under `name`, which shows up in logs and metrics.
+    pub fn with_job(mut self, name: &str, schedule: Schedule, job: impl Job) -> Self {
+        self.jobs.push(Arc::new(ScheduledJob {
+            name: name.to_string(),
+            schedule,
+            job: Box::new(job),
+            running: Mutex::default(),
+        }));
+        self
+    }
+
+    /// Runs the job `name` for every installation right away, `None` if there's no such job.
+    pub async fn run_now(&self, name: &str) -> Option<Result<JobReport, JobError>> {
+        let job = self.jobs.iter().find(|job| job.name == name)?;
+        Some(run_job(job, self.apl.as_ref(), &self.client, self.lock.as_ref(), Duration::ZERO).await)
+    }
+
+    /// Spawns a task per job running it whenever it's due, until the handle is dropped.
+    pub fn start(self) -> SchedulerHandle {
+        let tasks = self.jobs
+            .iter()
+            .map(|job| {
+                let job = job.clone();
+                let apl = self.apl.clone();
+                let client = self.client.clone();
+                let lock = self.lock.clone();
+                let jitter = self.jitter;
+                tokio::spawn(async move {
+                    loop {
+                        let now = OffsetDateTime::now_utc();
+                        let Some(next) = job.schedule.next_after(now) else {
+                            warn!(job = %job.name, "job is never due again");
+                            return;
+                        };
+                        tokio::time::sleep((next - now).try_into().unwrap_or_default()).await;
+
+                        // a slow run mustn't delay the next one, the installations it's still busy with are skipped then
+                        let (job, apl, client, lock) = (job.clone(), apl.clone(), client.clone(), lock.clone());
+                        tokio::spawn(async move {
+                            if let Err(e) = run_job(&job, apl.as_ref(), &client, lock.as_ref(), jitter).await {
+                                warn!(job = %job.name, "{}", e);
+                            }
+                        });
+                    }
+                })
+            })
+            .collect();
+        SchedulerHandle { tasks }
+    }
+}
+
+/// Stops the scheduled jobs when dropped, runs that already started are finished.
+pub struct SchedulerHandle {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// The delay of the installation within the jitter window, stable across runs.
+fn jitter_for(job: &str, saleor_api_url: &str, jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return Duration::ZERO;
+    }
+    let mut hasher = DefaultHasher::new();
+    (job, saleor_api_url).hash(&mut hasher);
+    Duration::from_millis(hasher.finish() % jitter.as_millis().max(1) as u64)
+}
+
+async fn run_job(job: &ScheduledJob, apl: &dyn AplStore, client: &HttpClient, lock: Option<&JobLock>, jitter: Duration) -> Result<JobReport, JobError> {
+    let installations = apl.get_all().await.map_err(|e| JobError(format!("unable to list installations: {}", e)))?;
+    let runs = installations.into_iter().map(|auth_data| {
+        let span = info_span!("job", job = %job.name, saleor_api_url = %auth_data.saleor_api_url);
+        run_for_installation(job, client, lock, auth_data, jitter).instrument(span)
+    });
+
+    let mut report = JobReport::default();
+    for outcome in join_all(runs).await {
+        match outcome {
+            Outcome::Succeeded => report.succeeded += 1,
+            Outcome::Failed => report.failed += 1,
+            Outcome::Skipped => report.skipped += 1,
+        }
+    }
+    Ok(report)
+}
+
+async fn run_for_installation(job: &ScheduledJob, client: &HttpClient, lock: Option<&JobLock>, auth_data: AuthData, jitter: Duration) -> Outcome {
+    let saleor_api_url = auth_data.saleor_api_url.clone();
+    if !job.running.lock().unwrap().insert(saleor_api_url.clone()) {
+        warn!("skipped, the previous run is still going");
+        telemetry::record_job_run(&job.name, None, "skipped");
+        return Outcome::Skipped;
+    }
+    let _guard = RunningGuard { running: &job.running, saleor_api_url: saleor_api_url.clone() };
+
+    tokio::time::sleep(jitter_for(&job.name, &saleor_api_url, jitter)).await;
+    let lock_key = format!("job:{}:{}", job.name, saleor_api_url);
+    let lock_token = match lock {
+        Some(JobLock { lock, ttl }) => match lock.try_acquire(&lock_key, *ttl).await {
+            Ok(Some(token)) => Some(token),
+            Ok(None) => {
+                info!("skipped, another replica is running it");
+                telemetry::record_job_run(&job.name, None, "skipped");
+                return Outcome::Skipped;
+            }
+            Err(e) => {
+                warn!("{}", e);
+                telemetry::record_job_run(&job.name, None, "error");
+                return Outcome::Failed;
+            }
+        },
+        None => None,
+    };
+
+    let start = Instant::now();
+    let result = job.job.run(JobContext { auth_data, client: client.clone() }).await;
+    let elapsed = start.elapsed();
+    if let (Some(JobLock { lock, .. }), Some(token)) = (lock, lock_token) {
+        if let Err(e) = lock.release(&lock_key, &token).await {
+            // the lock expires on its own
+            warn!("{}", e);
+        }
+    }
+    match result {
+        Ok(()) => {
+            info!(?elapsed, "job finished");
+            telemetry::record_job_run(&job.name, Some(elapsed), "success");
+            Outcome::Succeeded
+        }
+        Err(e) => {
+            warn!(?elapsed, "{}", e);
+            telemetry::record_job_run(&job.name, Some(elapsed), "error");
+            Outcome::Failed
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Search indexes products are pushed to by [`crate::saleor::search`].
+//!
+//! Meilisearch and Algolia are built in and configured with `SEARCH_BACKEND`, implement
+//! [`SearchIndex`] for other engines. One index can serve several installations, documents carry
+//! the Saleor API url they belong to.
+
+use async_trait::async_trait;
+use axum::response::{IntoResponse, Response};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{http_client::HttpClient, secrets::SecretString};
+
+mod algolia;
+mod meilisearch;
+
+pub use algolia::AlgoliaIndex;
+pub use meilisearch::MeilisearchIndex;
+
+#[derive(Debug)]
+pub struct SearchError(pub String);
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "search index error: {}", self.0)
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// Saleor retries the webhook when it isn't answered with a success.
+impl IntoResponse for SearchError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+    }
+}
+
+/// A product as it's indexed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductDocument {
+    pub saleor_api_url: String,
+    /// The Saleor ID of the product
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub product_type: String,
+    pub thumbnail: Option<String>,
+    pub variants: Vec<String>,
+    pub skus: Vec<String>,
+    /// Slugs of the channels the product is published in
+    pub channels: Vec<String>,
+    /// RFC 3339
+    pub updated_at: String,
+}
+
+impl ProductDocument {
+    pub fn key(&self) -> String {
+        document_key(&self.saleor_api_url, &self.id)
+    }
+}
+
+/// The key of a product in the index, unique across installations and made of characters every engine accepts.
+pub fn document_key(saleor_api_url: &str, product_id: &str) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}#{}", saleor_api_url, product_id))
+}
+
+/// A search engine index of products.
+#[async_trait]
+pub trait SearchIndex: Send + Sync + 'static {
+    /// Adds the documents or replaces the ones with the same key.
+    async fn upsert(&self, documents: &[ProductDocument]) -> Result<(), SearchError>;
+
+    /// Removes products of the installation at `saleor_api_url`, missing ones are ignored.
+    async fn delete(&self, saleor_api_url: &str, product_ids: &[String]) -> Result<(), SearchError>;
+}
+
+/// The search engine of `SEARCH_BACKEND`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchConfig {
+    /// `MEILISEARCH_URL`, `MEILISEARCH_API_KEY` and `MEILISEARCH_INDEX`
+    Meilisearch { url: String, api_key: Option<SecretString>, index: String },
+    /// `ALGOLIA_APP_ID`, `ALGOLIA_API_KEY` and `ALGOLIA_INDEX`
+    Algolia { app_id: String, api_key: SecretString, index: String },
+}
+
+impl SearchConfig {
+    pub fn build(&self, client: HttpClient) -> SearchBackend {
+        match self {
+            Self::Meilisearch { url, api_key, index } => SearchBackend::Meilisearch(MeilisearchIndex::new(client, url, api_key.as_ref().map(SecretString::expose), index)),
+            Self::Algolia { app_id, api_key, index } => SearchBackend::Algolia(AlgoliaIndex::new(client, app_id, api_key.expose(), index)),
+        }
+    }
+}
+
+/// One of the built in indexes, as configured by [`SearchConfig`].
+pub enum SearchBackend {
+    Meilisearch(MeilisearchIndex),
+    Algolia(AlgoliaIndex),
+}
+
+#[async_trait]
+impl SearchIndex for SearchBackend {
+    async fn upsert(&self, documents: &[ProductDocument]) -> Result<(), SearchError> {
+        match self {
+            Self::Meilisearch(index) => index.upsert(documents).await,
+            Self::Algolia(index) => index.upsert(documents).await,
+        }
+    }
+
+    async fn delete(&self, saleor_api_url: &str, product_ids: &[String]) -> Result<(), SearchError> {
+        match self {
+            Self::Meilisearch(index) => index.delete(saleor_api_url, product_ids).await,
+            Self::Algolia(index) => index.delete(saleor_api_url, product_ids).await,
+        }
+    }
+}
+
+/// Fails with the body of unsuccessful responses, engines explain what's wrong in it.
+async fn check_response(response: Result<reqwest::Response, reqwest::Error>) -> Result<(), SearchError> {
+    let response = response.map_err(|e| SearchError(e.to_string()))?;
+    if response.status().is_success() {
+        return Ok(());
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(SearchError(format!("{}: {}", status, body)))
+}
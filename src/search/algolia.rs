@@ -0,0 +1,65 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::http_client::HttpClient;
+
+use super::{check_response, document_key, ProductDocument, SearchError, SearchIndex};
+
+/// An Algolia index, documents are stored with their key as `objectID`.
+pub struct AlgoliaIndex {
+    client: HttpClient,
+    batch_url: String,
+    app_id: String,
+    api_key: String,
+}
+
+impl AlgoliaIndex {
+    /// `api_key` needs the `addObject` and `deleteObject` ACLs.
+    pub fn new(client: HttpClient, app_id: &str, api_key: &str, index: &str) -> Self {
+        Self::with_host(client, &format!("https://{}.algolia.net", app_id), app_id, api_key, index)
+    }
+
+    /// Talks to Algolia at `host` instead of the default host of the application.
+    pub fn with_host(client: HttpClient, host: &str, app_id: &str, api_key: &str, index: &str) -> Self {
+        Self {
+            client,
+            batch_url: format!("{}/1/indexes/{}/batch", host.trim_end_matches('/'), index),
+            app_id: app_id.to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    async fn batch(&self, requests: Vec<Value>) -> Result<(), SearchError> {
+        let response = self.client
+            .post(&self.batch_url)
+            .header("X-Algolia-Application-Id", &self.app_id)
+            .header("X-Algolia-API-Key", &self.api_key)
+            .json(&json!({ "requests": requests }))
+            .send()
+            .await;
+        check_response(response).await
+    }
+}
+
+#[async_trait]
+impl SearchIndex for AlgoliaIndex {
+    async fn upsert(&self, documents: &[ProductDocument]) -> Result<(), SearchError> {
+        let requests = documents
+            .iter()
+            .map(|document| {
+                let mut body = serde_json::to_value(document).unwrap_or_default();
+                body["objectID"] = document.key().into();
+                json!({ "action": "updateObject", "body": body })
+            })
+            .collect();
+        self.batch(requests).await
+    }
+
+    async fn delete(&self, saleor_api_url: &str, product_ids: &[String]) -> Result<(), SearchError> {
+        let requests = product_ids
+            .iter()
+            .map(|id| json!({ "action": "deleteObject", "body": { "objectID": document_key(saleor_api_url, id) } }))
+            .collect();
+        self.batch(requests).await
+    }
+}
@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::http_client::HttpClient;
+
+use super::{check_response, document_key, ProductDocument, SearchError, SearchIndex};
+
+/// A Meilisearch index, the primary key of its documents is `key`.
+pub struct MeilisearchIndex {
+    client: HttpClient,
+    documents_url: String,
+    api_key: Option<String>,
+}
+
+impl MeilisearchIndex {
+    /// `url` is where Meilisearch is reachable, the index is created with the first documents.
+    pub fn new(client: HttpClient, url: &str, api_key: Option<&str>, index: &str) -> Self {
+        Self {
+            client,
+            documents_url: format!("{}/indexes/{}/documents", url.trim_end_matches('/'), index),
+            api_key: api_key.map(str::to_string),
+        }
+    }
+
+    fn post(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.post(url);
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl SearchIndex for MeilisearchIndex {
+    async fn upsert(&self, documents: &[ProductDocument]) -> Result<(), SearchError> {
+        let documents: Vec<Value> = documents
+            .iter()
+            .map(|document| {
+                let mut json = serde_json::to_value(document).unwrap_or_default();
+                json["key"] = document.key().into();
+                json
+            })
+            .collect();
+        check_response(self.post(&format!("{}?primaryKey=key", self.documents_url)).json(&documents).send().await).await
+    }
+
+    async fn delete(&self, saleor_api_url: &str, product_ids: &[String]) -> Result<(), SearchError> {
+        let keys: Vec<String> = product_ids.iter().map(|id| document_key(saleor_api_url, id)).collect();
+        check_response(self.post(&format!("{}/delete-batch", self.documents_url)).json(&keys).send().await).await
+    }
+}
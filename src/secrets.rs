@@ -0,0 +1,144 @@
+//! Tokens and keys that mustn't end up in logs.
+//!
+//! [`SecretString`] shows up as `[redacted]` in `Debug` output and when serialized, code that needs
+//! the value asks for it with [`SecretString::expose`]. Storage that has to keep the value, like the
+//! APL or the session, opts in with `#[serde(with = "secrets::exposed")]`.
+
+use std::borrow::Cow;
+
+use reqwest::Url;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// What secrets, and personal data taken out of the audit log, are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// Compares two byte strings in time depending only on their length, for tokens and signatures.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+/// A string that is redacted from `Debug` output and serialization. Comparisons take constant time.
+#[derive(Clone, Default)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    /// The secret itself, only pass it on to where it's used.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_exposed(self) -> String {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_string())
+    }
+}
+
+impl std::fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(self.0.as_bytes(), other.0.as_bytes())
+    }
+}
+
+impl Eq for SecretString {}
+
+impl PartialEq<str> for SecretString {
+    fn eq(&self, other: &str) -> bool {
+        constant_time_eq(self.0.as_bytes(), other.as_bytes())
+    }
+}
+
+impl PartialEq<&str> for SecretString {
+    fn eq(&self, other: &&str) -> bool {
+        constant_time_eq(self.0.as_bytes(), other.as_bytes())
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+/// Serializes the value of a [`SecretString`] field instead of redacting it.
+pub mod exposed {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::SecretString;
+
+    pub fn serialize<S: Serializer>(secret: &SecretString, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(secret.expose())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SecretString, D::Error> {
+        SecretString::deserialize(deserializer)
+    }
+}
+
+/// `url` without its credentials and query, which can carry keys, like the password of `REDIS_URL`.
+/// Urls that can't be parsed are redacted entirely.
+pub fn redact_url(url: &str) -> String {
+    let Ok(mut url) = Url::parse(url) else {
+        return REDACTED.to_string();
+    };
+    if !url.username().is_empty() || url.password().is_some() {
+        let _ = url.set_username("redacted");
+        let _ = url.set_password(None);
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+    url.to_string()
+}
+
+/// Replaces what looks like a JWT in `text`, like a dashboard token echoed in an error message.
+pub fn redact_tokens(text: &str) -> Cow<'_, str> {
+    if !text.contains("eyJ") {
+        return Cow::Borrowed(text);
+    }
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '=');
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("eyJ") {
+        let end = rest[start..].find(|c| !is_token_char(c)).map_or(rest.len(), |len| start + len);
+        let candidate = &rest[start..end];
+        redacted.push_str(&rest[..start]);
+        // headers and claims are base64 encoded JSON objects, a token has at least both of them
+        match candidate.contains('.') {
+            true => redacted.push_str(REDACTED),
+            false => redacted.push_str(candidate),
+        }
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    Cow::Owned(redacted)
+}
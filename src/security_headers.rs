@@ -0,0 +1,96 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use axum::{http::{Request, HeaderValue, header::{CONTENT_SECURITY_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS}}, response::Response, body::Body};
+use reqwest::Url;
+use tower::{Layer, Service};
+use tower_sessions::Session;
+
+use crate::{saleor::SaleorApl, sessions};
+
+/// Sets `Content-Security-Policy: frame-ancestors` and related headers on dashboard pages.
+///
+/// The dashboard of the installation the page is opened for (`saleorApiUrl` query parameter or the
+/// session) is allowed to embed the page, as long as the installation exists in the APL. Additional
+/// origins, like the Saleor Cloud dashboard, can be allowed explicitly.
+#[derive(Clone, Default)]
+pub struct SecurityHeadersLayer {
+    extra_frame_ancestors: Arc<[String]>,
+}
+
+impl SecurityHeadersLayer {
+    pub fn new(extra_frame_ancestors: &[String]) -> Self {
+        Self { extra_frame_ancestors: extra_frame_ancestors.into() }
+    }
+}
+
+impl<S> Layer<S> for SecurityHeadersLayer {
+    type Service = SecurityHeadersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SecurityHeadersService {
+            inner,
+            extra_frame_ancestors: self.extra_frame_ancestors.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SecurityHeadersService<S> {
+    inner: S,
+    extra_frame_ancestors: Arc<[String]>,
+}
+
+fn query_api_url<B>(req: &Request<B>) -> Option<String> {
+    let query = req.uri().query()?;
+    Url::parse(&format!("http://localhost/?{query}"))
+        .ok()?
+        .query_pairs()
+        .find(|(key, _)| key == "saleorApiUrl")
+        .map(|(_, value)| value.to_string())
+}
+
+async fn tenant_origin<B>(req: &Request<B>) -> Option<String> {
+    let api_url = match query_api_url(req) {
+        Some(api_url) => api_url,
+        None => sessions::current_tenant(req.extensions().get::<Session>()?)?,
+    };
+    let apl = req.extensions().get::<SaleorApl>()?;
+    apl.get(&apl.id(&api_url)).await.ok()??;
+
+    Some(Url::parse(&api_url).ok()?.origin().ascii_serialization())
+}
+
+impl<S> Service<Request<Body>> for SecurityHeadersService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let extra_frame_ancestors = self.extra_frame_ancestors.clone();
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let mut frame_ancestors = vec!["'self'".to_string()];
+            frame_ancestors.extend(tenant_origin(&req).await);
+            frame_ancestors.extend(extra_frame_ancestors.iter().cloned());
+
+            let mut response: Response = inner.call(req).await?;
+            let headers = response.headers_mut();
+            if let Ok(csp) = HeaderValue::from_str(&format!("frame-ancestors {}", frame_ancestors.join(" "))) {
+                headers.insert(CONTENT_SECURITY_POLICY, csp);
+            }
+            headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+            headers.insert(REFERRER_POLICY, HeaderValue::from_static("strict-origin-when-cross-origin"));
+
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Url;
+use tower::util::Either;
+use tower_sessions::{MemoryStore, Session, SessionManagerLayer, SessionStore, cookie::SameSite, session::Id};
+
+use crate::secrets::SecretString;
+
+mod cookie;
+mod tenant;
+
+pub use cookie::CookieSessionLayer;
+pub use tenant::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionBackend {
+    /// Sessions live in memory, they are lost on restart and not shared between replicas
+    Memory,
+    /// Sessions are stored in Redis at the given connection url (requires the `redis` feature)
+    Redis(String),
+    /// Sessions are encrypted into a cookie with the given key, see [`CookieSessionLayer`]
+    Cookie(SecretString),
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub backend: SessionBackend,
+    /// How long a session stays valid without activity
+    pub ttl: Duration,
+    /// How long the dashboard token of a single installation stays valid inside the session
+    pub tenant_expiry: TenantSessionExpiry,
+    /// Attributes of the session cookie
+    pub cookie: SessionCookie,
+    /// Whether `/api` routes accept the dashboard token kept in the session, without it they're
+    /// only called with bearer tokens, like by CLIs and other services
+    pub api_sessions: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            backend: SessionBackend::Memory,
+            ttl: Duration::from_secs(60 * 60 * 24),
+            tenant_expiry: TenantSessionExpiry::default(),
+            cookie: SessionCookie::production(),
+            api_sessions: true,
+        }
+    }
+}
+
+impl SessionConfig {
+    pub fn expiry(&self) -> tower_sessions::Expiry {
+        tower_sessions::Expiry::OnInactivity(time::Duration::seconds(self.ttl.as_secs() as i64))
+    }
+}
+
+/// The `Secure` and `SameSite` attributes of the session cookie.
+///
+/// The dashboard embeds the app in an iframe on another site, so in production the cookie has to
+/// be `SameSite=None`, which browsers only accept together with `Secure`. Secure cookies aren't
+/// stored over plain http though, so local development uses `SameSite=Lax` without `Secure`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionCookie {
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
+impl SessionCookie {
+    /// `Secure` and `SameSite=None`, for apps served over https.
+    pub fn production() -> Self {
+        Self { secure: true, same_site: SameSite::None }
+    }
+
+    /// `SameSite=Lax` without `Secure`, for apps served over http, like on localhost.
+    pub fn development() -> Self {
+        Self { secure: false, same_site: SameSite::Lax }
+    }
+
+    /// The development profile for `http` urls, the production profile otherwise.
+    pub fn for_app_url(app_url: &str) -> Self {
+        match app_url.starts_with("http://") {
+            true => Self::development(),
+            false => Self::production(),
+        }
+    }
+
+    /// Problems with these attributes for an app served at `app_url`, empty if browsers keep the
+    /// cookie.
+    pub fn problems(&self, app_url: Option<&str>) -> Vec<String> {
+        let mut problems = vec![];
+        if self.same_site == SameSite::None && !self.secure {
+            problems.push("SESSION_COOKIE_SAME_SITE=none requires SESSION_COOKIE_SECURE=true, browsers reject it otherwise".to_string());
+        }
+        let Some(url) = app_url.and_then(|url| Url::parse(url).ok()) else {
+            return problems;
+        };
+        // browsers treat localhost as secure even over http
+        let local = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+        if self.secure && url.scheme() == "http" && !local {
+            problems.push("SESSION_COOKIE_SECURE=true requires an https APP_URL, browsers don't store secure cookies over http".to_string());
+        }
+        problems
+    }
+}
+
+impl Default for SessionCookie {
+    fn default() -> Self {
+        Self::production()
+    }
+}
+
+#[derive(Debug)]
+pub struct SessionStoreError(String);
+
+impl std::fmt::Display for SessionStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "session store error: {}", self.0)
+    }
+}
+
+impl std::error::Error for SessionStoreError {}
+
+/// The session store selected through [`SessionConfig`].
+#[derive(Clone)]
+pub enum AppSessionStore {
+    Memory(MemoryStore),
+    #[cfg(feature = "redis")]
+    Redis(RedisSessionStore),
+}
+
+impl AppSessionStore {
+    pub async fn connect(config: &SessionConfig) -> anyhow::Result<Self> {
+        match &config.backend {
+            SessionBackend::Memory => Ok(Self::Memory(MemoryStore::default())),
+            #[cfg(feature = "redis")]
+            SessionBackend::Redis(url) => Ok(Self::Redis(RedisSessionStore::connect(url).await?)),
+            #[cfg(not(feature = "redis"))]
+            SessionBackend::Redis(_) => anyhow::bail!("redis sessions require the redis feature"),
+            SessionBackend::Cookie(_) => anyhow::bail!("cookie sessions don't use a session store"),
+        }
+    }
+}
+
+pub type SessionLayer = Either<SessionManagerLayer<AppSessionStore>, CookieSessionLayer>;
+
+/// Builds the session layer for the configured backend, both provide the [`Session`] extension.
+pub async fn session_layer(config: &SessionConfig) -> anyhow::Result<SessionLayer> {
+    if let SessionBackend::Cookie(key) = &config.backend {
+        return Ok(Either::B(CookieSessionLayer::new(key.expose(), config.expiry(), config.cookie)?));
+    }
+
+    let store = AppSessionStore::connect(config).await?;
+    Ok(Either::A(
+        SessionManagerLayer::new(store)
+            .with_expiry(config.expiry())
+            .with_secure(config.cookie.secure)
+            .with_same_site(config.cookie.same_site),
+    ))
+}
+
+#[async_trait]
+impl SessionStore for AppSessionStore {
+    type Error = SessionStoreError;
+
+    async fn save(&self, session: &Session) -> Result<(), Self::Error> {
+        match self {
+            Self::Memory(store) => store.save(session).await.map_err(|e| SessionStoreError(e.to_string())),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.save(session).await,
+        }
+    }
+
+    async fn load(&self, session_id: &Id) -> Result<Option<Session>, Self::Error> {
+        match self {
+            Self::Memory(store) => store.load(session_id).await.map_err(|e| SessionStoreError(e.to_string())),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.load(session_id).await,
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> Result<(), Self::Error> {
+        match self {
+            Self::Memory(store) => store.delete(session_id).await.map_err(|e| SessionStoreError(e.to_string())),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.delete(session_id).await,
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_store::RedisSessionStore;
+
+#[cfg(feature = "redis")]
+mod redis_store {
+    use async_trait::async_trait;
+    use redis::{AsyncCommands, aio::ConnectionManager};
+    use tower_sessions::{Session, SessionStore, session::Id};
+
+    use super::SessionStoreError;
+
+    /// Stores sessions as JSON under `session:{id}`, expiring together with the session.
+    #[derive(Clone)]
+    pub struct RedisSessionStore {
+        connection: ConnectionManager,
+    }
+
+    impl RedisSessionStore {
+        pub async fn connect(url: &str) -> anyhow::Result<Self> {
+            let client = redis::Client::open(url)?;
+            let connection = client.get_connection_manager().await?;
+            Ok(Self { connection })
+        }
+
+        fn key(session_id: &Id) -> String {
+            format!("session:{}", session_id)
+        }
+    }
+
+    fn redis_error(e: redis::RedisError) -> SessionStoreError {
+        SessionStoreError(e.to_string())
+    }
+
+    #[async_trait]
+    impl SessionStore for RedisSessionStore {
+        type Error = SessionStoreError;
+
+        async fn save(&self, session: &Session) -> Result<(), Self::Error> {
+            let json = serde_json::to_string(session).map_err(|e| SessionStoreError(e.to_string()))?;
+            redis::cmd("SET")
+                .arg(Self::key(session.id()))
+                .arg(json)
+                .arg("EXAT")
+                .arg(session.expiry_date().unix_timestamp())
+                .query_async::<_, ()>(&mut self.connection.clone())
+                .await
+                .map_err(redis_error)
+        }
+
+        async fn load(&self, session_id: &Id) -> Result<Option<Session>, Self::Error> {
+            let json: Option<String> = self.connection.clone().get(Self::key(session_id)).await.map_err(redis_error)?;
+            json.map(|json| serde_json::from_str(&json).map_err(|e| SessionStoreError(e.to_string())))
+                .transpose()
+        }
+
+        async fn delete(&self, session_id: &Id) -> Result<(), Self::Error> {
+            self.connection.clone().del(Self::key(session_id)).await.map_err(redis_error)
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use axum::{http::{Request, HeaderValue, header::{COOKIE, SET_COOKIE}}, response::Response, body::Body};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::{aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN}, rand::{SecureRandom, SystemRandom}};
+use tower::{Layer, Service};
+use tower_sessions::{Expiry, Session, cookie::{Cookie, SameSite}, session::Deletion};
+
+use super::SessionCookie;
+
+const COOKIE_NAME: &str = "saleor_app_session";
+/// Browsers drop cookies larger than this.
+const MAX_COOKIE_SIZE: usize = 4096;
+
+/// Encrypts the whole session into a cookie, so no session backend is needed.
+///
+/// Provides the same [`Session`] request extension as tower-sessions' `SessionManagerLayer`,
+/// handlers don't need to know which one is used.
+#[derive(Clone)]
+pub struct CookieSessionLayer {
+    inner: Arc<CookieSessionConfig>,
+}
+
+struct CookieSessionConfig {
+    key: LessSafeKey,
+    rng: SystemRandom,
+    expiry: Expiry,
+    secure: bool,
+    same_site: SameSite,
+}
+
+impl CookieSessionLayer {
+    /// `key` has to be 32 bytes, encoded as url safe base64 without padding.
+    pub fn new(key: &str, expiry: Expiry, cookie: SessionCookie) -> anyhow::Result<Self> {
+        let key = URL_SAFE_NO_PAD.decode(key.trim())?;
+        let key = UnboundKey::new(&AES_256_GCM, &key)
+            .map_err(|_| anyhow::anyhow!("session cookie key has to be 32 bytes"))?;
+
+        Ok(Self {
+            inner: Arc::new(CookieSessionConfig {
+                key: LessSafeKey::new(key),
+                rng: SystemRandom::new(),
+                expiry,
+                secure: cookie.secure,
+                same_site: cookie.same_site,
+            }),
+        })
+    }
+
+    /// Generates a fresh key suitable for [`CookieSessionLayer::new`].
+    pub fn generate_key() -> String {
+        let mut key = [0u8; 32];
+        SystemRandom::new().fill(&mut key).expect("unable to generate random key");
+        URL_SAFE_NO_PAD.encode(key)
+    }
+}
+
+impl CookieSessionConfig {
+    fn encrypt(&self, session: &Session) -> Option<String> {
+        let mut data = serde_json::to_vec(session).ok()?;
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).ok()?;
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(COOKIE_NAME), &mut data)
+            .ok()?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend(data);
+        Some(URL_SAFE_NO_PAD.encode(payload))
+    }
+
+    fn decrypt(&self, value: &str) -> Option<Session> {
+        let mut payload = URL_SAFE_NO_PAD.decode(value).ok()?;
+        if payload.len() < NONCE_LEN {
+            return None;
+        }
+        let mut data = payload.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&payload).ok()?;
+        let data = self.key.open_in_place(nonce, Aad::from(COOKIE_NAME), &mut data).ok()?;
+        let session: Session = serde_json::from_slice(data).ok()?;
+
+        (session.expiry_date() > time::OffsetDateTime::now_utc()).then_some(session)
+    }
+
+    fn session_from_request<B>(&self, req: &Request<B>) -> Session {
+        req.headers()
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|header| header.split(';'))
+            .filter_map(|cookie| Cookie::parse(cookie.trim().to_string()).ok())
+            .find(|cookie| cookie.name() == COOKIE_NAME)
+            .and_then(|cookie| self.decrypt(cookie.value()))
+            .unwrap_or_else(|| Session::new(Some(self.expiry.clone())))
+    }
+
+    fn cookie(&self, value: String, session: Option<&Session>) -> Cookie<'static> {
+        let max_age = match session {
+            Some(session) => session.expiry_age(),
+            None => time::Duration::ZERO,
+        };
+        Cookie::build(COOKIE_NAME, value)
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .path("/")
+            .max_age(max_age)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for CookieSessionLayer {
+    type Service = CookieSessionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CookieSessionService {
+            inner,
+            config: self.inner.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CookieSessionService<S> {
+    inner: S,
+    config: Arc<CookieSessionConfig>,
+}
+
+impl<S> Service<Request<Body>> for CookieSessionService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let config = self.config.clone();
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let session = config.session_from_request(&req);
+            req.extensions_mut().insert(session.clone());
+
+            let mut response: Response = inner.call(req).await?;
+
+            let cookie = match session.deleted() {
+                Some(Deletion::Deleted) => Some(config.cookie(String::new(), None)),
+                _ if session.is_modified() => config.encrypt(&session).map(|value| config.cookie(value, Some(&session))),
+                _ => None,
+            };
+            if let Some(cookie) = cookie {
+                let cookie = cookie.to_string();
+                if cookie.len() > MAX_COOKIE_SIZE {
+                    tracing::warn!("session cookie is {} bytes, browsers might drop it", cookie.len());
+                }
+                if let Ok(value) = HeaderValue::from_str(&cookie) {
+                    response.headers_mut().append(SET_COOKIE, value);
+                }
+            }
+
+            Ok(response)
+        })
+    }
+}
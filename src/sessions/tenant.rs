@@ -0,0 +1,144 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{Serialize, Deserialize};
+use tower_sessions::Session;
+
+use crate::{saleor::SaleorPermission, secrets::{self, SecretString}};
+
+const CURRENT_TENANT_KEY: &str = "saleor_api_url";
+const TENANT_KEY_PREFIX: &str = "tenant:";
+
+/// How long a dashboard token stays usable in the session.
+#[derive(Debug, Clone, Copy)]
+pub struct TenantSessionExpiry {
+    /// Expires the token if the session wasn't used for this long
+    pub idle: Duration,
+    /// Expires the token this long after it was stored, regardless of activity
+    pub absolute: Duration,
+}
+
+impl Default for TenantSessionExpiry {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(60 * 60),
+            absolute: Duration::from_secs(60 * 60 * 12),
+        }
+    }
+}
+
+/// The dashboard user a token was issued for.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DashboardUser {
+    pub email: String,
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub user_permissions: Vec<SaleorPermission>,
+}
+
+impl DashboardUser {
+    /// Whether the user was granted the permission when the token was issued.
+    pub fn can(&self, permission: SaleorPermission) -> bool {
+        self.user_permissions.contains(&permission)
+    }
+
+    /// Reads the user from the claims of a dashboard token without verifying it,
+    /// only use this for tokens that were already verified.
+    pub fn from_token(token: &str) -> Option<Self> {
+        let claims = token.split('.').nth(1)?;
+        let claims = URL_SAFE_NO_PAD.decode(claims.trim_end_matches('=')).ok()?;
+        serde_json::from_slice(&claims).ok()
+    }
+}
+
+/// Dashboard token of a single installation, stored under `tenant:{saleor_api_url}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TenantSession {
+    #[serde(with = "secrets::exposed")]
+    token: SecretString,
+    authenticated_at: u64,
+    last_seen: u64,
+    #[serde(default)]
+    user: Option<DashboardUser>,
+    /// [`crate::saleor::AuthData::generation`] at authentication, `None` if the app wasn't installed
+    #[serde(default)]
+    generation: Option<u64>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+fn tenant_key(saleor_api_url: &str) -> String {
+    format!("{TENANT_KEY_PREFIX}{saleor_api_url}")
+}
+
+/// Stores the dashboard token for the given installation and makes it the current tenant.
+///
+/// `generation` is the one of the installation the token was verified against, see [`is_outdated`].
+pub fn store_dashboard_token(session: &Session, saleor_api_url: &str, token: &SecretString, generation: Option<u64>) -> Result<(), tower_sessions::session::Error> {
+    let now = now();
+    session.insert(&tenant_key(saleor_api_url), TenantSession {
+        token: token.clone(),
+        authenticated_at: now,
+        last_seen: now,
+        user: DashboardUser::from_token(token.expose()),
+        generation,
+    })?;
+    session.insert(CURRENT_TENANT_KEY, saleor_api_url)
+}
+
+/// The installation the session was last authenticated for.
+pub fn current_tenant(session: &Session) -> Option<String> {
+    session.get::<String>(CURRENT_TENANT_KEY).ok().flatten()
+}
+
+/// Returns the dashboard token for the given installation, removing it if it expired.
+pub fn dashboard_token(session: &Session, saleor_api_url: &str, expiry: &TenantSessionExpiry) -> Option<SecretString> {
+    let key = tenant_key(saleor_api_url);
+    let mut tenant = session.get::<TenantSession>(&key).ok().flatten()?;
+
+    let now = now();
+    let idle_expired = now.saturating_sub(tenant.last_seen) > expiry.idle.as_secs();
+    let absolute_expired = now.saturating_sub(tenant.authenticated_at) > expiry.absolute.as_secs();
+    if idle_expired || absolute_expired {
+        logout(session, Some(saleor_api_url));
+        return None;
+    }
+
+    tenant.last_seen = now;
+    let token = tenant.token.clone();
+    session.insert(&key, tenant).ok()?;
+    Some(token)
+}
+
+/// Whether the session holds a dashboard token of the given installation that was stored for
+/// another generation of it, `generation` is `None` if the installation doesn't exist anymore.
+pub fn is_outdated(session: &Session, saleor_api_url: &str, generation: Option<u64>) -> bool {
+    session
+        .get::<TenantSession>(&tenant_key(saleor_api_url))
+        .ok()
+        .flatten()
+        .is_some_and(|tenant| tenant.generation != generation)
+}
+
+/// The user the dashboard token of the given installation belongs to.
+///
+/// Doesn't check whether the token expired, use [`dashboard_token`] for anything that needs authentication.
+pub fn dashboard_user(session: &Session, saleor_api_url: &str) -> Option<DashboardUser> {
+    session.get::<TenantSession>(&tenant_key(saleor_api_url)).ok().flatten()?.user
+}
+
+/// Forgets the dashboard token of the given installation, or of every installation if none is given.
+pub fn logout(session: &Session, saleor_api_url: Option<&str>) {
+    match saleor_api_url {
+        Some(saleor_api_url) => {
+            session.remove_value(&tenant_key(saleor_api_url));
+            if current_tenant(session).as_deref() == Some(saleor_api_url) {
+                session.remove_value(CURRENT_TENANT_KEY);
+            }
+        },
+        None => session.clear(),
+    }
+}
@@ -0,0 +1,260 @@
+use std::{future::Future, pin::Pin, sync::OnceLock, time::{Duration, Instant}};
+
+use axum::{http::Request, response::Response, body::Body, extract::MatchedPath};
+use tower::{Layer, Service};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::{APP_ID, circuit_breaker::BreakerState, config::LogFormat, operator_api, saleor::{request_tenant, SaleorApl, TenantRequest}};
+
+pub const HTTP_REQUEST_DURATION: &str = "http_request_duration_seconds";
+pub const HTTP_RESPONSES: &str = "http_responses_total";
+pub const APL_LOOKUPS: &str = "apl_lookups_total";
+pub const JWKS_LOOKUPS: &str = "jwks_lookups_total";
+pub const GRAPHQL_REQUEST_DURATION: &str = "graphql_request_duration_seconds";
+pub const WEBHOOK_QUEUE_DEPTH: &str = "webhook_queue_depth";
+pub const JOB_RUNS: &str = "job_runs_total";
+pub const JOB_RUN_DURATION: &str = "job_run_duration_seconds";
+pub const CIRCUIT_BREAKER_STATE: &str = "saleor_circuit_breaker_state";
+pub const CIRCUIT_BREAKER_REJECTIONS: &str = "saleor_circuit_breaker_rejections_total";
+pub const SHED_REQUESTS: &str = "shed_requests_total";
+
+/// Changes the filter of the subscriber installed by [`init_tracing`].
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Installs the global tracing subscriber, emitting either human readable or JSON lines.
+///
+/// The filter is taken from `RUST_LOG` and can be changed afterwards with [`set_log_filter`].
+pub fn init_tracing(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| format!("{}=debug", APP_ID.replace('-', "_")).into());
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match format {
+        LogFormat::Pretty => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(tracing_subscriber::fmt::layer().json().with_current_span(true).with_span_list(false))
+            .init(),
+    }
+    let _ = LOG_FILTER.set(handle);
+}
+
+/// Replaces the filter of the logs, like a new `RUST_LOG` would. Does nothing if tracing wasn't
+/// initialized with [`init_tracing`].
+pub fn set_log_filter(filter: &str) -> anyhow::Result<()> {
+    let Some(handle) = LOG_FILTER.get() else {
+        return Ok(());
+    };
+    let filter = EnvFilter::try_new(filter).map_err(|e| anyhow::anyhow!("invalid log filter {filter:?}: {e}"))?;
+    handle.reload(filter).map_err(|e| anyhow::anyhow!("unable to change the log filter: {e}"))
+}
+
+/// Tags the current request span with the tenant the request belongs to, its installation id in
+/// the [`crate::operator_api`] and the app id its installation is kept under.
+pub fn record_tenant(saleor_api_url: &str, app_id: &str) {
+    let span = tracing::Span::current();
+    span.record("saleor_api_url", saleor_api_url);
+    span.record("installation_id", operator_api::installation_id(saleor_api_url).as_str());
+    span.record("app_id", app_id);
+}
+
+/// Tags the current request span with the webhook event that is being handled.
+pub fn record_event_type(event_type: &str) {
+    tracing::Span::current().record("event_type", event_type);
+}
+
+fn hit_label(hit: bool) -> &'static str {
+    match hit {
+        true => "hit",
+        false => "miss",
+    }
+}
+
+/// Records whether an APL lookup found an installation.
+pub fn record_apl_lookup(hit: bool) {
+    metrics::counter!(APL_LOOKUPS, "result" => hit_label(hit)).increment(1);
+}
+
+/// Records whether the JWKS could be served from the APL or the fetched ones within their TTL, or had
+/// to be fetched from Saleor, see [`crate::saleor::JwksCache`].
+pub fn record_jwks_lookup(hit: bool) {
+    metrics::counter!(JWKS_LOOKUPS, "result" => hit_label(hit)).increment(1);
+}
+
+pub fn record_graphql_call(operation: &'static str, duration: Duration, success: bool) {
+    let outcome = match success {
+        true => "success",
+        false => "error",
+    };
+    metrics::histogram!(GRAPHQL_REQUEST_DURATION, "operation" => operation, "outcome" => outcome)
+        .record(duration.as_secs_f64());
+}
+
+/// Records a run of a scheduled job for an installation, skipped runs have no duration.
+pub fn record_job_run(job: &str, duration: Option<Duration>, outcome: &'static str) {
+    metrics::counter!(JOB_RUNS, "job" => job.to_string(), "outcome" => outcome).increment(1);
+    if let Some(duration) = duration {
+        metrics::histogram!(JOB_RUN_DURATION, "job" => job.to_string(), "outcome" => outcome).record(duration.as_secs_f64());
+    }
+}
+
+/// Records the state of an installation's circuit breaker: 0 closed, 1 half-open and 2 open.
+pub fn set_circuit_breaker_state(saleor_api_url: &str, state: BreakerState) {
+    let value = match state {
+        BreakerState::Closed => 0.0,
+        BreakerState::HalfOpen => 1.0,
+        BreakerState::Open => 2.0,
+    };
+    metrics::gauge!(CIRCUIT_BREAKER_STATE, "saleor_api_url" => saleor_api_url.to_string()).set(value);
+}
+
+/// Records a call to Saleor refused by an open circuit breaker.
+pub fn record_circuit_breaker_rejection(saleor_api_url: &str) {
+    metrics::counter!(CIRCUIT_BREAKER_REJECTIONS, "saleor_api_url" => saleor_api_url.to_string()).increment(1);
+}
+
+/// Records a request answered with `503` because its route group was too busy, see [`crate::load_shed`].
+pub fn record_shed_request(group: &'static str) {
+    metrics::counter!(SHED_REQUESTS, "group" => group).increment(1);
+}
+
+/// Records the webhooks waiting for room in their [`crate::load_shed`] group.
+pub fn set_webhook_queue_depth(depth: usize) {
+    metrics::gauge!(WEBHOOK_QUEUE_DEPTH).set(depth as f64);
+}
+
+/// Records the latency and status code of every request, labeled by the matched route.
+#[derive(Clone, Default)]
+pub struct HttpMetricsLayer;
+
+impl<S> Layer<S> for HttpMetricsLayer {
+    type Service = HttpMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpMetricsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpMetricsService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for HttpMetricsService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let method = req.method().to_string();
+            let route = req
+                .extensions()
+                .get::<MatchedPath>()
+                .map(|path| path.as_str().to_string())
+                .unwrap_or_else(|| "unmatched".to_string());
+            tracing::Span::current().record("route", route.as_str());
+            let start = Instant::now();
+
+            let response: Response = inner.call(req).await?;
+
+            let status = response.status().as_u16().to_string();
+            metrics::histogram!(HTTP_REQUEST_DURATION, "method" => method.clone(), "route" => route.clone())
+                .record(start.elapsed().as_secs_f64());
+            metrics::counter!(HTTP_RESPONSES, "method" => method, "route" => route, "status" => status)
+                .increment(1);
+
+            Ok(response)
+        })
+    }
+}
+
+/// Tags the request span with the tenant as soon as it's resolved, see [`record_tenant`], so every
+/// line logged while handling the request names the installation, not only those logged behind an
+/// auth layer or webhook extractor.
+#[derive(Clone, Default)]
+pub struct TenantLogContextLayer;
+
+impl<S> Layer<S> for TenantLogContextLayer {
+    type Service = TenantLogContextService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantLogContextService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct TenantLogContextService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for TenantLogContextService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let saleor_api_url = request_tenant(&TenantRequest::from_request(&req));
+        let app_id = req.extensions().get::<SaleorApl>().map_or_else(|| APP_ID.to_string(), |apl| apl.app_id().to_string());
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            // recorded within the request span, which isn't entered yet when the service is called
+            if let Some(saleor_api_url) = saleor_api_url {
+                record_tenant(&saleor_api_url, &app_id);
+            }
+            inner.call(req).await
+        })
+    }
+}
+
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusMetrics;
+
+#[cfg(feature = "prometheus")]
+mod prometheus {
+    use axum::{Router, routing::get, extract::State};
+    use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+    #[derive(Clone)]
+    pub struct PrometheusMetrics {
+        handle: PrometheusHandle,
+    }
+
+    impl PrometheusMetrics {
+        /// Installs the prometheus recorder as the global metrics recorder.
+        pub fn install() -> anyhow::Result<Self> {
+            let handle = PrometheusBuilder::new().install_recorder()?;
+            Ok(Self { handle })
+        }
+
+        /// Router serving the rendered metrics at `/metrics`.
+        pub fn router(self) -> Router {
+            Router::new()
+                .route("/metrics", get(render))
+                .with_state(self)
+        }
+    }
+
+    async fn render(State(metrics): State<PrometheusMetrics>) -> String {
+        metrics.handle.render()
+    }
+}
@@ -1,5 +1,18 @@
 use askama::Template;
-use axum::{response::{IntoResponse, Html}, http::StatusCode};
+use async_trait::async_trait;
+use axum::{response::{IntoResponse, Html}, http::{StatusCode, request::Parts}, extract::FromRequestParts};
+use tower_sessions::Session;
+
+use crate::{saleor::{Claims, SaleorPermission}, sessions::{self, DashboardUser}};
+
+mod actions;
+mod app_bridge;
+pub mod filters;
+mod i18n;
+
+pub use actions::*;
+pub use app_bridge::*;
+pub use i18n::*;
 
 pub struct HtmlTemplate<T>(pub T);
 
@@ -18,6 +31,118 @@ where
     }
 }
 
+/// Context every page needs, available to the layout and its components as `ctx`.
+#[derive(Clone)]
+pub struct PageContext {
+    pub app_name: &'static str,
+    pub app_bridge: AppBridgeContext,
+    pub i18n: Localizer,
+    /// Saleor API url of the installation the page is shown for
+    pub tenant: Option<String>,
+    /// Dashboard user of the current tenant, known once the AppBridge handshake authenticated them
+    pub user: Option<DashboardUser>,
+    /// Permissions of the user, from the [`Claims`] of requests behind a [`crate::saleor::SaleorAuthLayer`],
+    /// otherwise from the session's [`Self::user`]
+    pub permissions: Vec<SaleorPermission>,
+    /// Whether htmx requested the page, in which case only the content is rendered
+    pub htmx: bool,
+    /// Includes the live reload script, see [`crate::DEV_MODE`]
+    pub dev_mode: bool,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PageContext
+where
+    S: Sync + Send,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_bridge = AppBridgeContext::from_request_parts(parts, state).await?;
+        let i18n = Localizer::from_request_parts(parts, state).await?;
+        let session = parts.extensions.get::<Session>();
+        let tenant = app_bridge
+            .saleor_api_url
+            .clone()
+            .or_else(|| session.and_then(sessions::current_tenant));
+        let user = session.zip(tenant.as_deref()).and_then(|(session, tenant)| sessions::dashboard_user(session, tenant));
+        let permissions = match parts.extensions.get::<Claims>() {
+            Some(claims) => claims.user_permissions.clone(),
+            None => user.as_ref().map(|user| user.user_permissions.clone()).unwrap_or_default(),
+        };
+        let htmx = parts.headers.contains_key(HX_REQUEST_HEADER) && !parts.headers.contains_key(HX_BOOSTED_HEADER);
+
+        Ok(Self {
+            app_name: crate::APP_ID,
+            app_bridge,
+            i18n,
+            tenant,
+            user,
+            permissions,
+            htmx,
+            dev_mode: crate::DEV_MODE,
+        })
+    }
+}
+
+impl PageContext {
+    /// Whether the user may perform actions needing the permission, like
+    /// `{% if ctx.can(SaleorPermission::ManageApps) %}`, so pages can hide what the API would reject.
+    pub fn can(&self, permission: SaleorPermission) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
+/// A full page, the content template rendered inside `layouts/page.html`.
+///
+/// Content templates are plain fragments, they don't extend a layout themselves, so htmx requests
+/// can get just the content.
+#[derive(Template)]
+#[template(path = "layouts/page.html")]
+pub struct Page<T: Template> {
+    pub ctx: PageContext,
+    pub title: String,
+    pub content: T,
+}
+
+impl<T: Template> Page<T> {
+    pub fn new(ctx: PageContext, title: impl Into<String>, content: T) -> Self {
+        Self {
+            ctx,
+            title: title.into(),
+            content,
+        }
+    }
+}
+
+impl<T: Template> IntoResponse for Page<T> {
+    fn into_response(self) -> axum::response::Response {
+        match self.ctx.htmx {
+            true => HtmlTemplate(self.content).into_response(),
+            false => HtmlTemplate(self).into_response(),
+        }
+    }
+}
+
 #[derive(Template)]
 #[template(path = "pages/hello.html")]
-pub struct ExamplePage;
+pub struct HelloContent {
+    pub i18n: Localizer,
+}
+
+/// Content of the example [`crate::extensions::ExtensionPage`]s.
+#[derive(Template)]
+#[template(path = "pages/extension.html")]
+pub struct ExtensionContent {
+    pub i18n: Localizer,
+    /// Ids of the products the page was opened for
+    pub object_ids: Vec<String>,
+}
+
+/// The widget on product details pages, a document of its own inside the widget's iframe.
+#[derive(Template)]
+#[template(path = "widgets/product.html")]
+pub struct ProductWidget {
+    pub ctx: PageContext,
+    pub product: crate::saleor::widgets::ProductDetails,
+}
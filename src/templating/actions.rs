@@ -0,0 +1,132 @@
+use axum::{http::HeaderValue, response::{IntoResponse, IntoResponseParts, Response, ResponseParts}, Json};
+use serde::Serialize;
+
+use crate::saleor::SaleorAppPermission;
+
+/// Set by htmx on every request it makes.
+pub const HX_REQUEST_HEADER: &str = "hx-request";
+/// Set by htmx on requests of boosted links and forms, which swap the whole page.
+pub const HX_BOOSTED_HEADER: &str = "hx-boosted";
+/// Header htmx turns into client side events, `/app-bridge.js` forwards the event to the dashboard.
+pub const HX_TRIGGER_HEADER: &str = "hx-trigger";
+/// Name of the event carrying an [`AppBridgeAction`] inside [`HX_TRIGGER_HEADER`].
+pub const APP_BRIDGE_ACTION_EVENT: &str = "appBridgeAction";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationStatus {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+/// An action the embedded frontend dispatches to the dashboard.
+///
+/// Used as a response part, it is sent as an htmx trigger next to the actual response:
+///
+/// ```ignore
+/// async fn save() -> impl IntoResponse {
+///     (AppBridgeAction::success("Saved successfully"), HtmlTemplate(SettingsForm { .. }))
+/// }
+/// ```
+///
+/// Used as the whole response, it is returned as JSON for `saleorAppBridge.dispatchAction`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase")]
+pub enum AppBridgeAction {
+    #[serde(rename_all = "camelCase")]
+    Notification {
+        status: NotificationStatus,
+        title: Option<String>,
+        text: Option<String>,
+        api_message: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Redirect {
+        to: String,
+        /// Opens the target in a new browser tab
+        new_context: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    RequestPermissions {
+        permissions: Vec<SaleorAppPermission>,
+        /// Dashboard path to return to once the permissions were granted
+        redirect_path: String,
+    },
+}
+
+impl AppBridgeAction {
+    pub fn notification(status: NotificationStatus, title: impl Into<String>, text: Option<String>) -> Self {
+        Self::Notification {
+            status,
+            title: Some(title.into()),
+            text,
+            api_message: None,
+        }
+    }
+
+    pub fn success(title: impl Into<String>) -> Self {
+        Self::notification(NotificationStatus::Success, title, None)
+    }
+
+    pub fn error(title: impl Into<String>) -> Self {
+        Self::notification(NotificationStatus::Error, title, None)
+    }
+
+    pub fn redirect(to: impl Into<String>) -> Self {
+        Self::Redirect {
+            to: to.into(),
+            new_context: false,
+        }
+    }
+
+    pub fn request_permissions(permissions: Vec<SaleorAppPermission>, redirect_path: impl Into<String>) -> Self {
+        Self::RequestPermissions {
+            permissions,
+            redirect_path: redirect_path.into(),
+        }
+    }
+
+    /// The value of the [`HX_TRIGGER_HEADER`] dispatching this action.
+    pub fn hx_trigger(&self) -> Option<HeaderValue> {
+        let trigger = serde_json::json!({ APP_BRIDGE_ACTION_EVENT: self });
+        HeaderValue::from_str(&ascii_json(&trigger.to_string())).ok()
+    }
+}
+
+/// Escapes non ASCII characters, header values can't carry them.
+fn ascii_json(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+            continue;
+        }
+        let mut units = [0u16; 2];
+        for unit in c.encode_utf16(&mut units) {
+            escaped.push_str(&format!("\\u{:04x}", unit));
+        }
+    }
+    escaped
+}
+
+impl IntoResponseParts for AppBridgeAction {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        match self.hx_trigger() {
+            Some(trigger) => {
+                res.headers_mut().insert(HX_TRIGGER_HEADER, trigger);
+            },
+            None => tracing::warn!("unable to encode app bridge action {:?}", self),
+        }
+        Ok(res)
+    }
+}
+
+impl IntoResponse for AppBridgeAction {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
@@ -0,0 +1,211 @@
+// Minimal AppBridge client for server rendered pages embedded in the Saleor dashboard.
+(() => {
+    const params = new URL(window.location.href).searchParams;
+    const state = {
+        token: null,
+        saleorApiUrl: params.get("saleorApiUrl"),
+        id: params.get("id"),
+        locale: params.get("locale") || "en",
+        theme: params.get("theme") || "light",
+        ready: false,
+    };
+    const listeners = [];
+    const dashboardOrigin = document.referrer ? new URL(document.referrer).origin : "*";
+
+    function notify() {
+        listeners.forEach((listener) => listener(state));
+    }
+
+    async function authenticate(token) {
+        state.token = token;
+        await fetch("/api/auth", {
+            method: "POST",
+            body: JSON.stringify({
+                api_url: state.saleorApiUrl,
+                token,
+            }),
+            headers: {
+                "Content-Type": "application/json",
+            },
+        });
+        state.ready = true;
+        notify();
+        document.dispatchEvent(new CustomEvent("appBridgeReady"));
+    }
+
+    function dispatch(type, payload) {
+        const actionId = crypto.randomUUID ? crypto.randomUUID() : String(Date.now());
+        window.parent.postMessage({ type, payload: { actionId, ...payload } }, dashboardOrigin);
+        return actionId;
+    }
+
+    window.addEventListener("message", async (e) => {
+        const { type, payload } = e.data || {};
+        switch (type) {
+            case "handshake":
+            case "tokenRefresh":
+                await authenticate(payload.token);
+                break;
+            case "theme":
+                state.theme = payload.theme;
+                document.documentElement.dataset.theme = state.theme;
+                notify();
+                break;
+            case "localeChanged":
+                state.locale = payload.locale;
+                notify();
+                break;
+        }
+    });
+
+    document.addEventListener("htmx:configRequest", (e) => {
+        if (state.saleorApiUrl) {
+            e.detail.headers["saleor-api-url"] = state.saleorApiUrl;
+        }
+        // Boosted navigation replaces the page, keep the dashboard context for the next one
+        if (e.detail.headers["HX-Boosted"] && e.detail.verb === "get") {
+            const { saleorApiUrl, id, locale, theme } = state;
+            Object.entries({ saleorApiUrl, id, locale, theme })
+                .filter(([key, value]) => value && !(key in e.detail.parameters))
+                .forEach(([key, value]) => (e.detail.parameters[key] = value));
+        }
+    });
+
+    // Actions sent by the server, see `AppBridgeAction`
+    function dispatchAction(action) {
+        return dispatch(action.type, action.payload);
+    }
+
+    document.addEventListener("appBridgeAction", (e) => {
+        dispatchAction(e.detail);
+    });
+
+    // Server sent events of the current installation, see `EventHub`
+    const eventListeners = [];
+    let events = null;
+
+    function connectEvents() {
+        // a failed connection, e.g. before the handshake authenticated the session, isn't retried by the browser
+        if (events && events.readyState !== EventSource.CLOSED) {
+            return;
+        }
+        events = new EventSource("/app/events");
+        eventListeners.forEach(([name, handler]) => events.addEventListener(name, handler));
+    }
+
+    function onEvent(name, listener) {
+        const entry = [name, (e) => listener(JSON.parse(e.data))];
+        eventListeners.push(entry);
+        if (events) {
+            events.addEventListener(...entry);
+        }
+        connectEvents();
+        return () => {
+            eventListeners.splice(eventListeners.indexOf(entry), 1);
+            events.removeEventListener(...entry);
+        };
+    }
+
+    document.addEventListener("appBridgeReady", () => {
+        if (eventListeners.length) {
+            connectEvents();
+        }
+        if (jobListeners.length || queuedMessages.length) {
+            connectSocket();
+        }
+    });
+
+    // WebSocket of the current installation for job progress, toasts and commands, see `crate::ws`
+    const jobListeners = [];
+    const pendingCommands = new Map();
+    const queuedMessages = [];
+    let nextCommandId = 0;
+    let socket = null;
+
+    function send(message) {
+        if (socket && socket.readyState === WebSocket.OPEN) {
+            socket.send(JSON.stringify(message));
+        } else {
+            queuedMessages.push(message);
+            connectSocket();
+        }
+    }
+
+    function connectSocket() {
+        if (socket && socket.readyState <= WebSocket.OPEN) {
+            return;
+        }
+        const url = new URL("/app/ws", window.location.href);
+        url.protocol = url.protocol === "https:" ? "wss:" : "ws:";
+        socket = new WebSocket(url);
+        socket.addEventListener("open", () => {
+            new Set(jobListeners.map(([job]) => job)).forEach((job) => socket.send(JSON.stringify({ type: "subscribe", job })));
+            queuedMessages.splice(0).forEach((message) => socket.send(JSON.stringify(message)));
+        });
+        socket.addEventListener("message", (e) => {
+            const message = JSON.parse(e.data);
+            switch (message.type) {
+                case "progress":
+                    jobListeners
+                        .filter(([job]) => job === message.job || job === "*")
+                        .forEach(([, listener]) => listener(message));
+                    break;
+                case "toast":
+                    dispatchAction(message.action);
+                    break;
+                case "result":
+                case "error": {
+                    const pending = pendingCommands.get(message.id);
+                    if (pending) {
+                        pendingCommands.delete(message.id);
+                        message.type === "result" ? pending.resolve(message.data) : pending.reject(new Error(message.message));
+                    }
+                    break;
+                }
+            }
+        });
+        socket.addEventListener("close", () => {
+            pendingCommands.forEach(({ reject }) => reject(new Error("connection closed")));
+            pendingCommands.clear();
+        });
+    }
+
+    function onJobProgress(job, listener) {
+        const entry = [job, listener];
+        jobListeners.push(entry);
+        if (socket && socket.readyState === WebSocket.OPEN) {
+            socket.send(JSON.stringify({ type: "subscribe", job }));
+        } else {
+            connectSocket();
+        }
+        return () => {
+            jobListeners.splice(jobListeners.indexOf(entry), 1);
+            if (!jobListeners.some(([other]) => other === job)) {
+                send({ type: "unsubscribe", job });
+            }
+        };
+    }
+
+    function command(name, args = {}) {
+        const id = String(++nextCommandId);
+        return new Promise((resolve, reject) => {
+            pendingCommands.set(id, { resolve, reject });
+            send({ type: "command", id, name, args });
+        });
+    }
+
+    window.saleorAppBridge = {
+        state,
+        dispatch,
+        dispatchAction,
+        onEvent,
+        onJobProgress,
+        command,
+        subscribe(listener) {
+            listeners.push(listener);
+            return () => listeners.splice(listeners.indexOf(listener), 1);
+        },
+    };
+
+    dispatch("notifyReady", {});
+})();
@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use axum::{http::{request::Parts, header::{CACHE_CONTROL, CONTENT_TYPE}}, extract::{FromRequestParts, Query}, response::IntoResponse};
+use serde::Deserialize;
+
+/// JavaScript shim performing the AppBridge handshake, served at [`APP_BRIDGE_SCRIPT_PATH`].
+pub const APP_BRIDGE_SCRIPT: &str = include_str!("app-bridge.js");
+pub const APP_BRIDGE_SCRIPT_PATH: &str = "/app-bridge.js";
+
+pub async fn app_bridge_script() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/javascript; charset=utf-8"), (CACHE_CONTROL, "no-cache")],
+        APP_BRIDGE_SCRIPT,
+    )
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl std::fmt::Display for Theme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Theme::Light => write!(f, "light"),
+            Theme::Dark => write!(f, "dark"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct AppBridgeQuery {
+    saleor_api_url: Option<String>,
+    domain: Option<String>,
+    id: Option<String>,
+    locale: Option<String>,
+    theme: Option<Theme>,
+}
+
+/// The query parameters the dashboard passes to the app iframe.
+///
+/// Extracting it never fails, missing parameters fall back to sensible defaults.
+#[derive(Debug, Clone)]
+pub struct AppBridgeContext {
+    pub saleor_api_url: Option<String>,
+    pub domain: Option<String>,
+    /// Id of the app inside the installing Saleor instance
+    pub id: Option<String>,
+    pub locale: String,
+    pub theme: Theme,
+}
+
+impl Default for AppBridgeContext {
+    fn default() -> Self {
+        Self {
+            saleor_api_url: None,
+            domain: None,
+            id: None,
+            locale: "en".to_string(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl AppBridgeContext {
+    pub fn is_dark(&self) -> bool {
+        self.theme == Theme::Dark
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AppBridgeContext
+where
+    S: Sync + Send,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = Query::<AppBridgeQuery>::try_from_uri(&parts.uri)
+            .map(|query| query.0)
+            .unwrap_or_default();
+        let default = Self::default();
+
+        Ok(Self {
+            saleor_api_url: query.saleor_api_url,
+            domain: query.domain,
+            id: query.id,
+            locale: query.locale.filter(|locale| !locale.is_empty()).unwrap_or(default.locale),
+            theme: query.theme.unwrap_or(default.theme),
+        })
+    }
+}
@@ -0,0 +1,10 @@
+//! Askama filters, available to the templates of the structs in [`crate::templating`].
+
+use std::fmt::Display;
+
+use crate::assets;
+
+/// `{{ "main.css"|asset }}`, the content hashed url of an asset, see [`assets::asset_url`].
+pub fn asset<T: Display>(path: T) -> askama::Result<String> {
+    Ok(assets::asset_url(&path.to_string()))
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{http::request::Parts, extract::FromRequestParts};
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue, concurrent::FluentBundle};
+use unic_langid::LanguageIdentifier;
+
+use super::AppBridgeContext;
+
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Translations compiled into the binary, one Fluent file per locale in `locales/`.
+const BUNDLED_LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../../locales/en.ftl")),
+    ("de", include_str!("../../locales/de.ftl")),
+];
+
+/// Fluent translations of all supported locales, provided to handlers as a request extension.
+#[derive(Clone, Default)]
+pub struct Translations {
+    bundles: Arc<HashMap<String, FluentBundle<FluentResource>>>,
+}
+
+impl Translations {
+    /// The translations shipped with the app.
+    pub fn bundled() -> anyhow::Result<Self> {
+        BUNDLED_LOCALES
+            .iter()
+            .try_fold(Self::default(), |translations, (locale, source)| translations.with_locale(locale, source))
+    }
+
+    /// Adds the messages of a Fluent file, overriding messages already known for the locale.
+    pub fn with_locale(mut self, locale: &str, source: &str) -> anyhow::Result<Self> {
+        let langid: LanguageIdentifier = locale.parse()?;
+        let resource = FluentResource::try_new(source.to_string())
+            .map_err(|(_, errors)| anyhow::anyhow!("invalid translations for {locale}: {errors:?}"))?;
+
+        let bundles = Arc::get_mut(&mut self.bundles)
+            .ok_or_else(|| anyhow::anyhow!("translations can't be extended once they are in use"))?;
+        let bundle = bundles.entry(langid.to_string()).or_insert_with(|| {
+            let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+            // Unicode isolation marks would end up verbatim in the rendered HTML
+            bundle.set_use_isolating(false);
+            bundle
+        });
+        bundle.add_resource_overriding(resource);
+
+        Ok(self)
+    }
+
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.bundles.keys().map(String::as_str)
+    }
+
+    /// Returns the best matching supported locale, falling back to [`DEFAULT_LOCALE`].
+    pub fn negotiate(&self, requested: &str) -> String {
+        let Ok(langid) = requested.replace('_', "-").parse::<LanguageIdentifier>() else {
+            return DEFAULT_LOCALE.to_string();
+        };
+        let language = langid.language.to_string();
+        [langid.to_string(), language]
+            .into_iter()
+            .find(|locale| self.bundles.contains_key(locale))
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+    }
+
+    pub fn localizer(&self, requested: &str) -> Localizer {
+        Localizer {
+            translations: self.clone(),
+            locale: self.negotiate(requested),
+        }
+    }
+
+    fn format(&self, locale: &str, id: &str, args: Option<&FluentArgs>) -> Option<String> {
+        let bundle = self.bundles.get(locale)?;
+        let pattern = bundle.get_message(id)?.value()?;
+        let mut errors = vec![];
+        let message = bundle.format_pattern(pattern, args, &mut errors);
+        if !errors.is_empty() {
+            tracing::warn!("unable to format message {id} for {locale}: {errors:?}");
+        }
+        Some(message.into_owned())
+    }
+}
+
+/// Translates messages into the locale the dashboard is displayed in.
+///
+/// Falls back to [`DEFAULT_LOCALE`] for missing messages and to the message id if that fails too,
+/// so a missing translation never breaks a page.
+#[derive(Clone)]
+pub struct Localizer {
+    translations: Translations,
+    locale: String,
+}
+
+impl Localizer {
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Translates the message with the given id, usable in templates as `{{ i18n.t("some-id") }}`.
+    pub fn t(&self, id: &str) -> String {
+        self.translate(id, None)
+    }
+
+    /// Translates a message with a single variable, `{{ i18n.t_with("greeting", "name", user.name) }}`.
+    pub fn t_with(&self, id: &str, name: &str, value: impl ToString) -> String {
+        let mut args = FluentArgs::new();
+        args.set(name.to_string(), FluentValue::from(value.to_string()));
+        self.translate(id, Some(&args))
+    }
+
+    pub fn translate(&self, id: &str, args: Option<&FluentArgs>) -> String {
+        self.translations
+            .format(&self.locale, id, args)
+            .or_else(|| self.translations.format(DEFAULT_LOCALE, id, args))
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Localizer
+where
+    S: Sync + Send,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_bridge = AppBridgeContext::from_request_parts(parts, state).await?;
+        let translations = parts.extensions.get::<Translations>().cloned().unwrap_or_else(|| {
+            tracing::warn!("no translations available, is the Translations extension missing?");
+            Translations::default()
+        });
+        Ok(translations.localizer(&app_bridge.locale))
+    }
+}
@@ -0,0 +1,448 @@
+//! Helpers for end-to-end tests of the app (requires the `testing` feature).
+//!
+//! ```ignore
+//! let app = TestApp::new().await;
+//! let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+//! assert_eq!(response.status, StatusCode::OK);
+//!
+//! let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+//! assert_eq!(response.status, StatusCode::OK);
+//! ```
+
+use std::{collections::HashSet, path::PathBuf, net::{SocketAddr, TcpListener}, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::{Router, routing::{get, post}, body::{Body, Bytes}, extract::{ConnectInfo, State}, http::{HeaderMap, Request, StatusCode, header::{AUTHORIZATION, CONTENT_TYPE, HOST}}, Json};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use ring::{rand::SystemRandom, signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING}};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use crate::{config::AppConfig, emitter::EventEmitter, events::EventHub, http_client::HttpClient, load_shed::LoadShedding, reload::ConfigReloader, router_ext::RouterExt, ws::WsCommands, saleor::{AuthData, SaleorPermission, APP_TOKEN_CHECK_QUERY, SALEOR_API_URL_HEADER, SALEOR_EVENT_HEADER, SALEOR_SIGNATURE_HEADER}};
+
+mod apl;
+
+pub use apl::*;
+
+const KEY_ID: &str = "test-key";
+pub const TEST_APP_TOKEN: &str = "test-app-token";
+pub const TEST_USER_EMAIL: &str = "staff@example.com";
+
+/// Computes the `data` of a response from the `variables` of the request.
+type Responder = Arc<dyn Fn(&Value) -> Value + Send + Sync>;
+
+/// A fake Saleor instance on a random local port, serving a JWKS and canned GraphQL responses.
+///
+/// Tokens for dashboard users are signed with a key generated for every instance.
+#[derive(Clone)]
+pub struct MockSaleor {
+    addr: SocketAddr,
+    pkcs8: Arc<[u8]>,
+    jwks: Arc<str>,
+    jwks_down: Arc<AtomicBool>,
+    jwks_fetches: Arc<AtomicUsize>,
+    responses: Arc<Mutex<Vec<(String, Responder)>>>,
+    requests: Arc<Mutex<Vec<Value>>>,
+    rejected_tokens: Arc<Mutex<HashSet<String>>>,
+}
+
+impl MockSaleor {
+    pub async fn start() -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).expect("unable to generate test key");
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).expect("unable to read test key");
+        // uncompressed point, 0x04 followed by both coordinates
+        let public_key = key_pair.public_key().as_ref();
+        let jwks = json!({
+            "keys": [{
+                "kty": "EC",
+                "crv": "P-256",
+                "x": URL_SAFE_NO_PAD.encode(&public_key[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&public_key[33..65]),
+                "kid": KEY_ID,
+                "use": "sig",
+                "alg": "ES256",
+            }],
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("unable to bind mock saleor");
+        let addr = listener.local_addr().expect("mock saleor has no address");
+        let saleor = Self {
+            addr,
+            pkcs8: pkcs8.as_ref().into(),
+            jwks: jwks.to_string().into(),
+            jwks_down: Default::default(),
+            jwks_fetches: Default::default(),
+            responses: Default::default(),
+            requests: Default::default(),
+            rejected_tokens: Default::default(),
+        };
+        saleor.respond_to("me", json!({ "me": { "id": "VXNlcjox" } }));
+        saleor.respond_to(APP_TOKEN_CHECK_QUERY, json!({ "app": { "id": "QXBwOjE=" } }));
+
+        let router = Router::new()
+            .route("/.well-known/jwks.json", get(serve_jwks))
+            .route("/graphql/", post(graphql))
+            .with_state(saleor.clone());
+        let server = axum::Server::from_tcp(listener).expect("unable to start mock saleor").serve(router.into_make_service());
+        tokio::spawn(server);
+
+        saleor
+    }
+
+    pub fn api_url(&self) -> String {
+        format!("http://{}/graphql/", self.addr)
+    }
+
+    pub fn domain(&self) -> String {
+        self.addr.to_string()
+    }
+
+    pub fn jwks(&self) -> &str {
+        &self.jwks
+    }
+
+    /// Makes the JWKS endpoint answer with `503 Service Unavailable` until it's brought back up.
+    pub fn set_jwks_down(&self, down: bool) {
+        self.jwks_down.store(down, Ordering::Relaxed);
+    }
+
+    /// How often the JWKS were requested so far, including while they were down.
+    pub fn jwks_fetches(&self) -> usize {
+        self.jwks_fetches.load(Ordering::Relaxed)
+    }
+
+    /// Answers GraphQL documents containing `fragment` with the given data, newer responses take precedence.
+    pub fn respond_to(&self, fragment: &str, data: Value) {
+        self.respond_with(fragment, move |_| data.clone());
+    }
+
+    /// Like [`Self::respond_to`], with data depending on the variables, like the pages of a paginated query.
+    pub fn respond_with(&self, fragment: &str, responder: impl Fn(&Value) -> Value + Send + Sync + 'static) {
+        self.responses.lock().unwrap().insert(0, (fragment.to_string(), Arc::new(responder)));
+    }
+
+    /// Treats requests with `token` as anonymous from now on, like Saleor does with the token of a
+    /// removed app: they fail with `PermissionDenied` and the app of the token is `null`.
+    pub fn reject_token(&self, token: &str) {
+        self.rejected_tokens.lock().unwrap().insert(token.to_string());
+    }
+
+    /// The GraphQL requests (`query` and `variables`) received so far, oldest first.
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The installation of the app in this Saleor, like [`TestApp::register`] stores it.
+    pub fn auth_data(&self) -> AuthData {
+        AuthData {
+            domain: Some(self.domain()),
+            jwks: Some(self.jwks().to_string()),
+            ..AuthData::new(self.api_url(), TEST_APP_TOKEN)
+        }
+    }
+
+    /// A dashboard token of a staff user with the given permissions, as AppBridge would hand it to the app.
+    pub fn token(&self, permissions: &[SaleorPermission]) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let claims = json!({
+            "iss": self.api_url(),
+            "iat": now,
+            "exp": now + 3600,
+            "type": "thirdparty",
+            "app": "QXBwOjE=",
+            "email": TEST_USER_EMAIL,
+            "user_id": "VXNlcjox",
+            "is_staff": true,
+            "user_permissions": permissions,
+        });
+        self.sign_token(&claims)
+    }
+
+    /// Signs any claims with the key of the JWKS, for tokens [`Self::token`] doesn't cover, like expired ones.
+    pub fn sign_token(&self, claims: &Value) -> String {
+        let header = Header {
+            kid: Some(KEY_ID.to_string()),
+            ..Header::new(Algorithm::ES256)
+        };
+        jsonwebtoken::encode(&header, claims, &EncodingKey::from_ec_der(&self.pkcs8)).expect("unable to sign test token")
+    }
+
+    /// The `saleor-signature` Saleor sends along with a webhook, a detached JWS over the unencoded payload.
+    pub fn sign_webhook(&self, payload: &[u8]) -> String {
+        let header = json!({ "alg": "ES256", "kid": KEY_ID, "b64": false, "crit": ["b64"] });
+        let header = URL_SAFE_NO_PAD.encode(header.to_string());
+        let mut message = format!("{}.", header).into_bytes();
+        message.extend_from_slice(payload);
+        let signature = jsonwebtoken::crypto::sign(&message, &EncodingKey::from_ec_der(&self.pkcs8), Algorithm::ES256)
+            .expect("unable to sign test webhook");
+        format!("{}..{}", header, signature)
+    }
+}
+
+/// Loads a sample webhook payload from `tests/fixtures/webhooks/<name>.json`.
+///
+/// Fixtures are the JSON Saleor sends for the subscription query of the webhook, copy them from the
+/// webhook deliveries in the dashboard or write them by hand.
+pub fn webhook_fixture(name: &str) -> Value {
+    let dir = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    let path = dir.join("tests/fixtures/webhooks").join(format!("{}.json", name));
+    let fixture = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("unable to read webhook fixture {}: {}", path.display(), e));
+    serde_json::from_str(&fixture).unwrap_or_else(|e| panic!("webhook fixture {} isn't valid json: {}", path.display(), e))
+}
+
+async fn serve_jwks(State(saleor): State<MockSaleor>) -> Result<String, StatusCode> {
+    saleor.jwks_fetches.fetch_add(1, Ordering::Relaxed);
+    match saleor.jwks_down.load(Ordering::Relaxed) {
+        true => Err(StatusCode::SERVICE_UNAVAILABLE),
+        false => Ok(saleor.jwks.to_string()),
+    }
+}
+
+async fn graphql(State(saleor): State<MockSaleor>, headers: HeaderMap, body: Bytes) -> Json<Value> {
+    let is_multipart = headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).is_some_and(|value| value.starts_with("multipart/form-data"));
+    let request = match is_multipart {
+        true => multipart_operations(&body),
+        false => serde_json::from_slice(&body).ok(),
+    };
+    let Some(request) = request else {
+        return Json(json!({ "data": null, "errors": [{ "message": "invalid graphql request" }] }));
+    };
+    saleor.requests.lock().unwrap().push(request.clone());
+
+    let query = request["query"].as_str().unwrap_or_default();
+    let token = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+    if token.is_some_and(|token| saleor.rejected_tokens.lock().unwrap().contains(token)) {
+        return match query == APP_TOKEN_CHECK_QUERY {
+            true => Json(json!({ "data": { "app": null } })),
+            false => Json(json!({ "data": null, "errors": [{ "message": "You need one of the following permissions", "extensions": { "exception": { "code": "PermissionDenied" } } }] })),
+        };
+    }
+    let responses = saleor.responses.lock().unwrap();
+    match responses.iter().find(|(fragment, _)| query.contains(fragment.as_str())) {
+        Some((_, responder)) => Json(json!({ "data": responder(&request["variables"]) })),
+        None => Json(json!({ "data": null, "errors": [{ "message": format!("no mock response for {query}") }] })),
+    }
+}
+
+/// The `operations` field of a GraphQL multipart request (file uploads), the files themselves are ignored.
+fn multipart_operations(body: &[u8]) -> Option<Value> {
+    let body = String::from_utf8_lossy(body);
+    let part = body.split("name=\"operations\"").nth(1)?;
+    let (_, content) = part.split_once("\r\n\r\n")?;
+    let (operations, _) = content.split_once("\r\n--")?;
+    serde_json::from_str(operations).ok()
+}
+
+/// A response with its body already read.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| panic!("response is not the expected json ({e}): {}", self.text()))
+    }
+}
+
+/// The full app router with an in-memory APL ([`MockAplStore`]) and session store, talking to a [`MockSaleor`].
+pub struct TestApp {
+    router: Router,
+    pub saleor: MockSaleor,
+    pub apl: MockAplStore,
+    /// Subscribe to it to see what handlers publish to the pages
+    pub events: EventHub,
+    /// Register commands on it to run them over `/app/ws`
+    pub ws_commands: WsCommands,
+    /// Add a [`crate::emitter::ChannelSink`] to it to see what handlers emit
+    pub emitter: EventEmitter,
+    /// Apply configurations to it to test reloads
+    pub reloader: ConfigReloader,
+    /// Take its permits to test requests that are shed
+    pub load_shedding: LoadShedding,
+    /// Look at its circuit breakers to test how Saleor is called
+    pub http_client: HttpClient,
+}
+
+impl TestApp {
+    /// An app already registered in its mock Saleor.
+    pub async fn new() -> Self {
+        Self::with_config(AppConfig::default()).await
+    }
+
+    pub async fn with_config(config: AppConfig) -> Self {
+        let app = Self::unregistered(config).await;
+        let response = app.register().await;
+        assert!(response.status.is_success(), "registering the test app failed: {}", response.text());
+        app
+    }
+
+    /// Like [`Self::with_config`], for configs naming the mock Saleor, like `admin_api_urls`.
+    pub async fn with_saleor_config(config: impl FnOnce(&MockSaleor) -> AppConfig) -> Self {
+        let saleor = MockSaleor::start().await;
+        let app = Self::build(&config(&saleor), saleor, Router::new()).await;
+        let response = app.register().await;
+        assert!(response.status.is_success(), "registering the test app failed: {}", response.text());
+        app
+    }
+
+    /// Like [`Self::with_config`], with the `routes` of an app built on this crate, see
+    /// [`RouterExt::saleor_app`].
+    pub async fn with_routes(config: AppConfig, routes: Router) -> Self {
+        let app = Self::build(&config, MockSaleor::start().await, routes).await;
+        let response = app.register().await;
+        assert!(response.status.is_success(), "registering the test app failed: {}", response.text());
+        app
+    }
+
+    pub async fn unregistered(config: AppConfig) -> Self {
+        Self::build(&config, MockSaleor::start().await, Router::new()).await
+    }
+
+    async fn build(config: &AppConfig, saleor: MockSaleor, routes: Router) -> Self {
+        let apl = MockAplStore::new();
+        let app = routes.saleor_app(config, apl.clone()).await.expect("unable to build the app");
+
+        Self {
+            router: app.router,
+            saleor,
+            apl,
+            events: app.events,
+            ws_commands: app.ws_commands,
+            emitter: app.emitter,
+            reloader: app.reloader,
+            load_shedding: app.load_shedding,
+            http_client: app.http_client,
+        }
+    }
+
+    /// Registers the app like Saleor does when it gets installed.
+    pub async fn register(&self) -> TestResponse {
+        self.register_with_token(TEST_APP_TOKEN).await
+    }
+
+    /// Registers like Saleor would when the app is installed again, with a new token.
+    pub async fn register_with_token(&self, auth_token: &str) -> TestResponse {
+        let request = Request::post("/api/register")
+            .header("saleor-domain", self.saleor.domain())
+            .header("saleor-api-url", self.saleor.api_url())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({ "auth_token": auth_token }).to_string()))
+            .unwrap();
+        self.request(request).await
+    }
+
+    pub async fn request(&self, mut request: Request<Body>) -> TestResponse {
+        let headers = request.headers_mut();
+        if !headers.contains_key(HOST) {
+            headers.insert(HOST, "localhost".parse().unwrap());
+        }
+        if request.extensions().get::<ConnectInfo<SocketAddr>>().is_none() {
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+        }
+
+        let response = self.router.clone().oneshot(request).await.expect("router is infallible");
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await.expect("unable to read response body");
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+
+    /// Serves the app on a local port, for clients that need a real connection, like WebSockets.
+    pub fn serve(&self) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("unable to bind the test app");
+        let addr = listener.local_addr().expect("listener has an address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("unable to start the test app")
+            .serve(self.router.clone().into_make_service_with_connect_info::<SocketAddr>());
+        tokio::spawn(server);
+        addr
+    }
+
+    /// An anonymous request, without a token or tenant.
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.request(Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    /// Delivers a webhook like the mock Saleor would, signed with its key.
+    pub async fn deliver_webhook(&self, path: &str, event: &str, payload: &impl Serialize) -> TestResponse {
+        let payload = serde_json::to_vec(payload).unwrap();
+        let signature = self.saleor.sign_webhook(&payload);
+        self.deliver_signed_webhook(path, event, payload, &signature).await
+    }
+
+    /// Delivers the fixture `name`, see [`webhook_fixture`].
+    pub async fn deliver_webhook_fixture(&self, path: &str, event: &str, name: &str) -> TestResponse {
+        self.deliver_webhook(path, event, &webhook_fixture(name)).await
+    }
+
+    /// Delivers a webhook with the given signature, to test how tampered or foreign deliveries are handled.
+    pub async fn deliver_signed_webhook(&self, path: &str, event: &str, payload: impl Into<Body>, signature: &str) -> TestResponse {
+        let request = Request::post(path)
+            .header(SALEOR_API_URL_HEADER, self.saleor.api_url())
+            .header(SALEOR_EVENT_HEADER, event)
+            .header(SALEOR_SIGNATURE_HEADER, signature)
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload.into())
+            .unwrap();
+        self.request(request).await
+    }
+
+    /// Sends requests as a dashboard user of the registered installation.
+    pub fn as_user(&self, permissions: &[SaleorPermission]) -> TestUser<'_> {
+        TestUser {
+            app: self,
+            token: self.saleor.token(permissions),
+        }
+    }
+}
+
+pub struct TestUser<'a> {
+    app: &'a TestApp,
+    pub token: String,
+}
+
+impl TestUser<'_> {
+    /// Adds the token and tenant headers the embedded frontend sends.
+    pub async fn request(&self, mut request: Request<Body>) -> TestResponse {
+        let headers = request.headers_mut();
+        headers.insert(AUTHORIZATION, format!("Bearer {}", self.token).parse().unwrap());
+        headers.insert("saleor-api-url", self.app.saleor.api_url().parse().unwrap());
+        self.app.request(request).await
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.request(Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    pub async fn delete(&self, uri: &str) -> TestResponse {
+        self.request(Request::delete(uri).body(Body::empty()).unwrap()).await
+    }
+
+    pub async fn post_json(&self, uri: &str, body: &impl Serialize) -> TestResponse {
+        let request = Request::post(uri)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(body).unwrap()))
+            .unwrap();
+        self.request(request).await
+    }
+
+    pub async fn post_form(&self, uri: &str, body: &impl Serialize) -> TestResponse {
+        let request = Request::post(uri)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(serde_urlencoded::to_string(body).unwrap()))
+            .unwrap();
+        self.request(request).await
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, Mutex}, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::saleor::{AplError, AplId, AplStore, AuthData, MemoryAplStore};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AplCall {
+    Get,
+    Set,
+    Remove,
+    GetAll,
+    Health,
+}
+
+/// What a scripted call of a [`MockAplStore`] does instead of just using the stored installations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AplBehavior {
+    /// Waits before answering from the stored installations
+    Delay(Duration),
+    /// Acts as if nothing is stored, writes are dropped
+    NotFound,
+    Error(String),
+}
+
+/// An in-memory APL whose calls can be scripted to be slow, find nothing or fail.
+///
+/// Scripted behaviors are used up one call at a time, once none are left calls are answered
+/// from the installations in memory. Clones share their installations and script.
+///
+/// ```ignore
+/// app.apl.script(AplCall::Get, AplBehavior::Error("connection reset".into()));
+/// let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+/// assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+/// ```
+#[derive(Clone, Default)]
+pub struct MockAplStore {
+    store: MemoryAplStore,
+    script: Arc<Mutex<HashMap<AplCall, VecDeque<AplBehavior>>>>,
+    calls: Arc<Mutex<Vec<AplCall>>>,
+}
+
+impl MockAplStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a behavior for the next `call` that hasn't got one yet.
+    pub fn script(&self, call: AplCall, behavior: AplBehavior) -> &Self {
+        self.script.lock().unwrap().entry(call).or_default().push_back(behavior);
+        self
+    }
+
+    /// Queues the behavior for the next `times` calls.
+    pub fn script_times(&self, call: AplCall, behavior: AplBehavior, times: usize) -> &Self {
+        for _ in 0..times {
+            self.script(call, behavior.clone());
+        }
+        self
+    }
+
+    /// Every call made so far, in order.
+    pub fn calls(&self) -> Vec<AplCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The installations in memory, without going through the script.
+    pub fn store(&self) -> &MemoryAplStore {
+        &self.store
+    }
+
+    /// Records the call and applies its next scripted behavior, `Some` when the call is answered by the script.
+    async fn next<T>(&self, call: AplCall, not_found: T) -> Option<Result<T, AplError>> {
+        self.calls.lock().unwrap().push(call);
+        let behavior = self.script.lock().unwrap().get_mut(&call).and_then(VecDeque::pop_front);
+        match behavior? {
+            AplBehavior::Delay(delay) => {
+                tokio::time::sleep(delay).await;
+                None
+            }
+            AplBehavior::NotFound => Some(Ok(not_found)),
+            AplBehavior::Error(e) => Some(Err(AplError(e))),
+        }
+    }
+}
+
+#[async_trait]
+impl AplStore for MockAplStore {
+    async fn get(&self, apl_id: &AplId) -> Result<Option<AuthData>, AplError> {
+        match self.next(AplCall::Get, None).await {
+            Some(result) => result,
+            None => self.store.get(apl_id).await,
+        }
+    }
+
+    async fn set(&self, apl_id: &AplId, auth_data: AuthData) -> Result<(), AplError> {
+        match self.next(AplCall::Set, ()).await {
+            Some(result) => result,
+            None => self.store.set(apl_id, auth_data).await,
+        }
+    }
+
+    async fn remove(&self, apl_id: &AplId) -> Result<(), AplError> {
+        match self.next(AplCall::Remove, ()).await {
+            Some(result) => result,
+            None => self.store.remove(apl_id).await,
+        }
+    }
+
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        match self.next(AplCall::GetAll, vec![]).await {
+            Some(result) => result,
+            None => self.store.get_all().await,
+        }
+    }
+
+    async fn health(&self) -> Result<(), String> {
+        match self.next(AplCall::Health, ()).await {
+            Some(result) => result.map_err(|e| e.to_string()),
+            None => self.store.health().await,
+        }
+    }
+}
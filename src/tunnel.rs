@@ -0,0 +1,121 @@
+use std::{process::Stdio, str::FromStr, time::Duration};
+
+use anyhow::Context;
+use reqwest::Url;
+use tokio::{io::{AsyncBufReadExt, AsyncRead, BufReader}, process::{Child, Command}, sync::mpsc};
+
+/// How long to wait for the tunnel to report its public url.
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunnelProvider {
+    /// `cloudflared tunnel --url`, a quick tunnel on trycloudflare.com without an account
+    Cloudflared,
+    /// `ngrok http`, needs a configured authtoken
+    Ngrok,
+}
+
+impl FromStr for TunnelProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cloudflared" => Ok(Self::Cloudflared),
+            "ngrok" => Ok(Self::Ngrok),
+            other => Err(format!("unknown tunnel {other:?}, expected cloudflared or ngrok")),
+        }
+    }
+}
+
+/// A public url forwarding to the local server, so a Saleor instance in the cloud can install the app.
+///
+/// The tunnel process is stopped when this is dropped.
+pub struct Tunnel {
+    pub url: String,
+    _process: Option<Child>,
+}
+
+impl Tunnel {
+    /// Starts the tunnel process for the local `port` and waits for its public url.
+    pub async fn start(provider: TunnelProvider, port: u16) -> anyhow::Result<Self> {
+        let local_url = format!("http://localhost:{port}");
+        let (program, mut command) = match provider {
+            TunnelProvider::Cloudflared => ("cloudflared", Command::new("cloudflared")),
+            TunnelProvider::Ngrok => ("ngrok", Command::new("ngrok")),
+        };
+        match provider {
+            TunnelProvider::Cloudflared => command.args(["tunnel", "--no-autoupdate", "--url", &local_url]),
+            TunnelProvider::Ngrok => command.args(["http", &port.to_string(), "--log", "stdout", "--log-format", "json"]),
+        };
+        let mut process = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("unable to start {program}, is it installed and in PATH?"))?;
+
+        // cloudflared logs to stderr, ngrok to stdout
+        let (sender, mut urls) = mpsc::channel(1);
+        tokio::spawn(scan_for_url(process.stdout.take(), provider, sender.clone()));
+        tokio::spawn(scan_for_url(process.stderr.take(), provider, sender));
+
+        let url = tokio::time::timeout(START_TIMEOUT, urls.recv())
+            .await
+            .ok()
+            .flatten()
+            .with_context(|| format!("{program} didn't report a public url within {}s", START_TIMEOUT.as_secs()))?;
+
+        Ok(Self {
+            url,
+            _process: Some(process),
+        })
+    }
+
+    /// A tunnel started outside of the app, like a named cloudflared tunnel.
+    pub fn external(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            _process: None,
+        }
+    }
+
+    pub fn manifest_url(&self) -> String {
+        format!("{}/api/manifest", self.url)
+    }
+
+    /// The dashboard page installing the app, `dashboard_url` like `https://example.saleor.cloud/dashboard/`.
+    pub fn install_url(&self, dashboard_url: &str) -> anyhow::Result<String> {
+        let mut dashboard_url = Url::parse(dashboard_url).context("invalid dashboard url")?;
+        if !dashboard_url.path().ends_with('/') {
+            dashboard_url.set_path(&format!("{}/", dashboard_url.path()));
+        }
+        let mut install_url = dashboard_url.join("apps/install")?;
+        install_url.query_pairs_mut().append_pair("manifestUrl", &self.manifest_url());
+        Ok(install_url.to_string())
+    }
+}
+
+/// Forwards the first public url in the output, keeps draining it afterwards so the process doesn't block.
+async fn scan_for_url(output: Option<impl AsyncRead + Unpin>, provider: TunnelProvider, sender: mpsc::Sender<String>) {
+    let Some(output) = output else {
+        return;
+    };
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::debug!(target: "tunnel", "{}", line);
+        let url = match provider {
+            TunnelProvider::Cloudflared => line
+                .split_whitespace()
+                .find(|word| word.starts_with("https://") && word.ends_with(".trycloudflare.com"))
+                .map(str::to_string),
+            TunnelProvider::Ngrok => serde_json::from_str::<serde_json::Value>(&line)
+                .ok()
+                .and_then(|entry| entry["url"].as_str().map(str::to_string))
+                .filter(|url| url.starts_with("https://")),
+        };
+        if let Some(url) = url {
+            let _ = sender.try_send(url);
+        }
+    }
+}
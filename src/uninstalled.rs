@@ -0,0 +1,122 @@
+//! Deny-list of installations that were removed recently.
+//!
+//! Saleor keeps retrying webhook deliveries that failed, and open dashboards keep sending requests,
+//! for installations the app doesn't know anymore. Instead of the generic errors of a missing
+//! installation they get `410 Gone`, which tells Saleor the app is gone for good. Entries expire
+//! after a while and are dropped once the installation is found in the APL again.
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use axum::{body::Body, extract::FromRequestParts, http::{request::Parts, Request}, response::{IntoResponse, Response}};
+use reqwest::StatusCode;
+use tower::{Layer, Service};
+
+use crate::saleor::{request_tenant, AplId, SaleorApl, TenantRequest};
+
+/// Recently uninstalled installations, provided to handlers and [`UninstalledTenantsLayer`] as a
+/// request extension. Clones share their entries.
+#[derive(Clone)]
+pub struct UninstalledTenants {
+    uninstalled_at: Arc<Mutex<HashMap<AplId, Instant>>>,
+    ttl: Duration,
+}
+
+impl UninstalledTenants {
+    /// Installations are denied for `ttl` after they were uninstalled.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            uninstalled_at: Arc::default(),
+            ttl,
+        }
+    }
+
+    pub fn mark(&self, apl_id: &AplId) {
+        let now = Instant::now();
+        let mut uninstalled_at = self.uninstalled_at.lock().unwrap();
+        uninstalled_at.retain(|_, at| now.duration_since(*at) < self.ttl);
+        uninstalled_at.insert(apl_id.clone(), now);
+    }
+
+    /// Lifts the denial, like when the installation registered again.
+    pub fn forget(&self, apl_id: &AplId) {
+        self.uninstalled_at.lock().unwrap().remove(apl_id);
+    }
+
+    pub fn contains(&self, apl_id: &AplId) -> bool {
+        self.uninstalled_at
+            .lock()
+            .unwrap()
+            .get(apl_id)
+            .is_some_and(|at| at.elapsed() < self.ttl)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for UninstalledTenants
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<UninstalledTenants>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "uninstalled tenants not found in request extensions").into_response())
+    }
+}
+
+/// Answers requests of recently uninstalled installations with `410 Gone`.
+///
+/// The tenant is named by the request's [`crate::saleor::TenantResolvers`], requests pass through
+/// if there is none or the [`UninstalledTenants`] extension is missing. Denied tenants are looked up
+/// in the APL, so installations that registered again are let through.
+#[derive(Clone, Default)]
+pub struct UninstalledTenantsLayer;
+
+impl<S> Layer<S> for UninstalledTenantsLayer {
+    type Service = UninstalledTenantsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UninstalledTenantsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct UninstalledTenantsService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for UninstalledTenantsService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let uninstalled = req.extensions().get::<UninstalledTenants>().zip(req.extensions().get::<SaleorApl>()).zip(request_tenant(&TenantRequest::from_request(&req)));
+            if let Some(((uninstalled, apl), api_url)) = uninstalled {
+                let apl_id = apl.id(&api_url);
+                if uninstalled.contains(&apl_id) {
+                    match apl.get(&apl_id).await {
+                        Ok(Some(_)) => uninstalled.forget(&apl_id),
+                        _ => {
+                            tracing::info!(saleor_api_url = %api_url, "rejected request of an uninstalled installation");
+                            return Ok((StatusCode::GONE, "app was uninstalled from this saleor instance").into_response());
+                        }
+                    }
+                }
+            }
+
+            inner.call(req).await
+        })
+    }
+}
@@ -0,0 +1,401 @@
+//! Archive of verified webhook deliveries, for answering "we missed an order" reports.
+//!
+//! Every webhook that passed [`crate::saleor::SaleorWebhook`]'s signature check is kept with its
+//! headers and raw payload, so operators can search the deliveries of an installation and deliver
+//! one to the app's handler again. Redeliveries replay the original request through the router, the
+//! signature is checked again and they aren't archived a second time. Deliveries older than the
+//! retention are pruned while new ones are archived.
+//!
+//! Archiving is enabled with `WEBHOOK_ARCHIVE_FILE`, implement [`ArchiveStore`] to keep deliveries in
+//! S3, Postgres and the like.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::{Arc, Mutex, OnceLock}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{FromRequestParts, OriginalUri, Path, Query},
+    http::{header::{AUTHORIZATION, CONTENT_LENGTH, COOKIE}, request::Parts, Request},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, sync::Mutex as AsyncMutex};
+use tower::ServiceExt;
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+/// How often deliveries older than the retention are pruned.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A webhook delivery as it was received.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedWebhook {
+    pub id: String,
+    pub saleor_api_url: String,
+    /// The `saleor-event` header, e.g. `product_updated`
+    pub event: String,
+    /// Path the webhook was delivered to
+    pub path: String,
+    /// RFC 3339 timestamp
+    pub received_at: String,
+    /// Headers of the delivery, without credentials
+    pub headers: BTreeMap<String, String>,
+    /// The payload as signed by Saleor
+    pub payload: String,
+}
+
+impl ArchivedWebhook {
+    fn received_at(&self) -> Option<OffsetDateTime> {
+        OffsetDateTime::parse(&self.received_at, &Rfc3339).ok()
+    }
+}
+
+/// Which deliveries [`ArchiveStore::search`] returns.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveFilter {
+    pub saleor_api_url: Option<String>,
+    pub event: Option<String>,
+    /// Received at or after
+    pub since: Option<OffsetDateTime>,
+    /// Received before
+    pub until: Option<OffsetDateTime>,
+    pub limit: usize,
+}
+
+impl ArchiveFilter {
+    pub fn matches(&self, webhook: &ArchivedWebhook) -> bool {
+        let received_at = webhook.received_at();
+        self.saleor_api_url.as_ref().is_none_or(|saleor_api_url| *saleor_api_url == webhook.saleor_api_url)
+            && self.event.as_ref().is_none_or(|event| *event == webhook.event)
+            && self.since.is_none_or(|since| received_at.is_some_and(|received_at| received_at >= since))
+            && self.until.is_none_or(|until| received_at.is_some_and(|received_at| received_at < until))
+    }
+}
+
+/// Where archived deliveries are kept.
+#[async_trait]
+pub trait ArchiveStore: Send + Sync + 'static {
+    async fn store(&self, webhook: &ArchivedWebhook) -> std::io::Result<()>;
+
+    /// The latest deliveries matching the filter, newest first.
+    async fn search(&self, filter: &ArchiveFilter) -> std::io::Result<Vec<ArchivedWebhook>>;
+
+    async fn get(&self, id: &str) -> std::io::Result<Option<ArchivedWebhook>>;
+
+    /// Removes the deliveries received before `received_before`, returning how many were removed.
+    async fn prune(&self, received_before: OffsetDateTime) -> std::io::Result<usize>;
+}
+
+/// Appends deliveries to a file, one JSON object per line.
+pub struct FileArchiveStore {
+    path: PathBuf,
+    lock: AsyncMutex<()>,
+}
+
+impl FileArchiveStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: AsyncMutex::new(()),
+        }
+    }
+
+    /// Every archived delivery, oldest first.
+    async fn read_all(&self) -> std::io::Result<Vec<ArchivedWebhook>> {
+        let file = match tokio::fs::File::open(&self.path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut webhooks = vec![];
+        let mut lines = BufReader::new(file).lines();
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(webhook) = serde_json::from_str(&line) {
+                webhooks.push(webhook);
+            }
+        }
+        Ok(webhooks)
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for FileArchiveStore {
+    async fn store(&self, webhook: &ArchivedWebhook) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(webhook)?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(line.as_bytes()).await
+    }
+
+    async fn search(&self, filter: &ArchiveFilter) -> std::io::Result<Vec<ArchivedWebhook>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.read_all().await?.into_iter().rev().filter(|webhook| filter.matches(webhook)).take(filter.limit).collect())
+    }
+
+    async fn get(&self, id: &str) -> std::io::Result<Option<ArchivedWebhook>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.read_all().await?.into_iter().find(|webhook| webhook.id == id))
+    }
+
+    async fn prune(&self, received_before: OffsetDateTime) -> std::io::Result<usize> {
+        let _guard = self.lock.lock().await;
+        let webhooks = self.read_all().await?;
+        let total = webhooks.len();
+        let kept: Vec<_> = webhooks
+            .into_iter()
+            .filter(|webhook| webhook.received_at().is_none_or(|received_at| received_at >= received_before))
+            .collect();
+        if kept.len() == total {
+            return Ok(0);
+        }
+
+        let mut content = String::new();
+        for webhook in &kept {
+            content.push_str(&serde_json::to_string(webhook)?);
+            content.push('\n');
+        }
+        // like the audit log, a crash while writing must not lose the archive
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        let result = async {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            file.write_all(content.as_bytes()).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp_path, &self.path).await
+        }
+        .await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+        result.map(|_| total - kept.len())
+    }
+}
+
+/// Marks requests replayed by [`WebhookArchive::redeliver`], so they aren't archived again.
+#[derive(Debug, Clone)]
+pub struct RedeliveredWebhook(pub String);
+
+/// The archive, provided to [`crate::saleor::SaleorWebhook`] and handlers as a request extension.
+/// Without a store nothing is archived. Clones share the store.
+#[derive(Clone)]
+pub struct WebhookArchive {
+    store: Option<Arc<dyn ArchiveStore>>,
+    retention: Duration,
+    last_pruned: Arc<Mutex<Option<Instant>>>,
+    /// What redeliveries are sent to, set by [`crate::app::build`]
+    router: Arc<OnceLock<Mutex<Router>>>,
+}
+
+impl WebhookArchive {
+    /// Deliveries are kept for `retention`.
+    pub fn new(store: impl ArchiveStore, retention: Duration) -> Self {
+        Self {
+            store: Some(Arc::new(store)),
+            retention,
+            ..Self::disabled()
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            store: None,
+            retention: Duration::ZERO,
+            last_pruned: Arc::default(),
+            router: Arc::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.store.is_some()
+    }
+
+    /// Sets the router redeliveries are sent to, only the first one is kept.
+    pub fn attach(&self, router: Router) {
+        let _ = self.router.set(Mutex::new(router));
+    }
+
+    /// Archives a verified delivery in the background, archiving never delays or fails a webhook.
+    pub fn record(&self, parts: &Parts, saleor_api_url: &str, event: &str, payload: &[u8]) {
+        let Some(store) = self.store.clone() else {
+            return;
+        };
+        if parts.extensions.get::<RedeliveredWebhook>().is_some() {
+            return;
+        }
+        let path = parts.extensions.get::<OriginalUri>().map(|OriginalUri(uri)| uri).unwrap_or(&parts.uri).path().to_string();
+        let headers = parts
+            .headers
+            .iter()
+            .filter(|(name, _)| ![AUTHORIZATION, COOKIE, CONTENT_LENGTH].contains(name))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let webhook = ArchivedWebhook {
+            id: uuid::Uuid::new_v4().to_string(),
+            saleor_api_url: saleor_api_url.to_string(),
+            event: event.to_string(),
+            path,
+            received_at: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            headers,
+            payload: String::from_utf8_lossy(payload).into_owned(),
+        };
+
+        let prune_before = self.prune_due().then(|| OffsetDateTime::now_utc() - self.retention);
+        tokio::spawn(async move {
+            if let Err(e) = store.store(&webhook).await {
+                tracing::error!(webhook_id = %webhook.id, "unable to archive webhook: {}", e);
+            }
+            if let Some(prune_before) = prune_before {
+                match store.prune(prune_before).await {
+                    Ok(pruned) => tracing::debug!("pruned {} archived webhooks", pruned),
+                    Err(e) => tracing::error!("unable to prune archived webhooks: {}", e),
+                }
+            }
+        });
+    }
+
+    fn prune_due(&self) -> bool {
+        let mut last_pruned = self.last_pruned.lock().unwrap();
+        if last_pruned.is_some_and(|last_pruned| last_pruned.elapsed() < PRUNE_INTERVAL) {
+            return false;
+        }
+        *last_pruned = Some(Instant::now());
+        true
+    }
+
+    pub async fn search(&self, filter: &ArchiveFilter) -> Result<Vec<ArchivedWebhook>, ArchiveError> {
+        self.store()?.search(filter).await.map_err(|e| ArchiveError::Store(e.to_string()))
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<ArchivedWebhook>, ArchiveError> {
+        self.store()?.get(id).await.map_err(|e| ArchiveError::Store(e.to_string()))
+    }
+
+    /// Delivers the webhook to the app's handler again, returning the handler's response.
+    pub async fn redeliver(&self, webhook: &ArchivedWebhook) -> Result<Response, ArchiveError> {
+        let router = self.router.get().map(|router| router.lock().unwrap().clone()).ok_or(ArchiveError::Store("no router to redeliver to".to_string()))?;
+        let mut request = Request::post(&webhook.path);
+        for (name, value) in &webhook.headers {
+            request = request.header(name, value);
+        }
+        let request = request
+            .extension(RedeliveredWebhook(webhook.id.clone()))
+            .body(Body::from(webhook.payload.clone()))
+            .map_err(|e| ArchiveError::Store(format!("unable to rebuild the delivery: {}", e)))?;
+        tracing::info!(webhook_id = %webhook.id, saleor_api_url = %webhook.saleor_api_url, "redelivering archived webhook");
+        Ok(router.oneshot(request).await.into_response())
+    }
+
+    fn store(&self) -> Result<&Arc<dyn ArchiveStore>, ArchiveError> {
+        self.store.as_ref().ok_or(ArchiveError::Disabled)
+    }
+}
+
+impl Default for WebhookArchive {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WebhookArchive
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<WebhookArchive>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "webhook archive not found in request extensions").into_response())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// `WEBHOOK_ARCHIVE_FILE` isn't set
+    Disabled,
+    InvalidQuery(String),
+    Store(String),
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "webhook archive error: archiving is disabled"),
+            Self::InvalidQuery(e) => write!(f, "webhook archive error: {}", e),
+            Self::Store(e) => write!(f, "webhook archive error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl IntoResponse for ArchiveError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Disabled => StatusCode::NOT_FOUND,
+            Self::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            Self::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveQuery {
+    pub saleor_api_url: Option<String>,
+    /// Like `product_updated`
+    pub event: Option<String>,
+    /// RFC 3339 timestamp, deliveries received at or after it
+    pub since: Option<String>,
+    /// RFC 3339 timestamp, deliveries received before it
+    pub until: Option<String>,
+    pub limit: Option<usize>,
+}
+
+fn parse_timestamp(name: &str, timestamp: Option<&str>) -> Result<Option<OffsetDateTime>, ArchiveError> {
+    timestamp
+        .map(|timestamp| OffsetDateTime::parse(timestamp, &Rfc3339).map_err(|e| ArchiveError::InvalidQuery(format!("invalid {name}: {e}"))))
+        .transpose()
+}
+
+/// `GET /api/admin/webhooks`, the latest archived deliveries of every installation.
+#[utoipa::path(get, path = "/api/admin/webhooks", tag = "admin", params(ArchiveQuery), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The latest matching deliveries, newest first", body = [ArchivedWebhook]),
+    (status = 400, description = "A timestamp is invalid"),
+    (status = 404, description = "Archiving is disabled"),
+))]
+pub async fn search_webhooks(archive: WebhookArchive, Query(query): Query<ArchiveQuery>) -> Result<Json<Vec<ArchivedWebhook>>, ArchiveError> {
+    let filter = ArchiveFilter {
+        since: parse_timestamp("since", query.since.as_deref())?,
+        until: parse_timestamp("until", query.until.as_deref())?,
+        saleor_api_url: query.saleor_api_url,
+        event: query.event,
+        limit: query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(1000),
+    };
+    archive.search(&filter).await.map(Json)
+}
+
+/// `POST /api/admin/webhooks/{id}/redeliver`, delivers an archived webhook to its handler again.
+#[utoipa::path(post, path = "/api/admin/webhooks/{id}/redeliver", tag = "admin", params(
+    ("id" = String, Path, description = "Id of the archived delivery"),
+), security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "The handler accepted the delivery, other statuses are passed on from the handler"),
+    (status = 404, description = "The delivery doesn't exist or archiving is disabled"),
+))]
+pub async fn redeliver_webhook(archive: WebhookArchive, Path(id): Path<String>) -> Response {
+    let webhook = match archive.get(&id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return (StatusCode::NOT_FOUND, "archived webhook not found").into_response(),
+        Err(e) => return e.into_response(),
+    };
+    match archive.redeliver(&webhook).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
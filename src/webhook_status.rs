@@ -0,0 +1,181 @@
+//! Webhook health per installation, answering "are we still receiving events from store X?".
+//!
+//! [`WebhookStatsLayer`] counts the deliveries of every installation by event and outcome since the
+//! app started. `GET /api/admin/webhook-status` reports them for every installation in the APL,
+//! together with the webhooks waiting for a [`crate::concurrency`] permit. Installations without
+//! deliveries since the start take their last one from the [`crate::webhook_archive`] if it's enabled.
+
+use std::{collections::{BTreeMap, HashMap}, future::Future, pin::Pin, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use axum::{body::Body, extract::FromRequestParts, http::{request::Parts, Request}, response::{IntoResponse, Response}, Json};
+use reqwest::StatusCode;
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tower::{Layer, Service};
+use utoipa::ToSchema;
+
+use crate::{
+    concurrency::Workload,
+    http_client::HttpClient,
+    saleor::{request_tenant, SaleorApl, TenantRequest, SALEOR_EVENT_HEADER},
+    webhook_archive::{ArchiveFilter, WebhookArchive},
+};
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+pub struct EventCounts {
+    pub received: u64,
+    /// Deliveries answered with something else than a success
+    pub failed: u64,
+}
+
+#[derive(Debug, Default)]
+struct TenantStats {
+    last_received_at: Option<OffsetDateTime>,
+    events: BTreeMap<String, EventCounts>,
+}
+
+/// Deliveries per installation since the app started, provided to handlers and
+/// [`WebhookStatsLayer`] as a request extension. Clones share them.
+#[derive(Clone, Default)]
+pub struct WebhookStats {
+    tenants: Arc<Mutex<HashMap<String, TenantStats>>>,
+}
+
+impl WebhookStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, saleor_api_url: &str, event: &str, success: bool) {
+        let mut tenants = self.tenants.lock().unwrap();
+        let stats = tenants.entry(saleor_api_url.to_string()).or_default();
+        stats.last_received_at = Some(OffsetDateTime::now_utc());
+        let counts = stats.events.entry(event.to_string()).or_default();
+        counts.received += 1;
+        if !success {
+            counts.failed += 1;
+        }
+    }
+
+    fn snapshot(&self, saleor_api_url: &str) -> (Option<OffsetDateTime>, BTreeMap<String, EventCounts>) {
+        self.tenants
+            .lock()
+            .unwrap()
+            .get(saleor_api_url)
+            .map(|stats| (stats.last_received_at, stats.events.clone()))
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WebhookStats
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<WebhookStats>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "webhook stats not found in request extensions").into_response())
+    }
+}
+
+/// Counts the deliveries of the routes it wraps in the [`WebhookStats`] extension.
+///
+/// Deliveries are attributed to the installation named by the request's [`crate::saleor::TenantResolvers`]
+/// and the `saleor-event` header, others aren't counted.
+#[derive(Clone, Default)]
+pub struct WebhookStatsLayer;
+
+impl<S> Layer<S> for WebhookStatsLayer {
+    type Service = WebhookStatsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WebhookStatsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct WebhookStatsService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for WebhookStatsService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send +'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let event = req.headers().get(SALEOR_EVENT_HEADER).and_then(|event| event.to_str().ok()).map(str::to_string);
+            let delivery = req.extensions().get::<WebhookStats>().cloned().zip(request_tenant(&TenantRequest::from_request(&req))).zip(event);
+
+            let response = inner.call(req).await?;
+            if let Some(((stats, saleor_api_url), event)) = delivery {
+                stats.record(&saleor_api_url, &event, response.status().is_success());
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// The webhook health of an installation.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookStatus {
+    pub saleor_api_url: String,
+    /// RFC 3339 timestamp of the last delivery
+    pub last_received_at: Option<String>,
+    /// Deliveries since the app started, by `saleor-event`
+    pub events: BTreeMap<String, EventCounts>,
+    /// Failed deliveries since the app started
+    pub failures: u64,
+    /// Webhooks waiting to be handled right now
+    pub backlog: usize,
+}
+
+/// `GET /api/admin/webhook-status`, the webhook health of every installation.
+#[utoipa::path(get, path = "/api/admin/webhook-status", tag = "admin", security(("dashboard_token" = []), ("saleor_api_url" = [])), responses(
+    (status = 200, description = "Every installation in the APL, the ones without deliveries for the longest first", body = [WebhookStatus]),
+))]
+pub async fn webhook_status(apl: SaleorApl, stats: WebhookStats, archive: WebhookArchive, client: HttpClient) -> Response {
+    let installations = match apl.get_all().await {
+        Ok(installations) => installations,
+        Err(e) => return e.into_response(),
+    };
+
+    let mut statuses = Vec::with_capacity(installations.len());
+    for auth_data in installations {
+        let saleor_api_url = auth_data.saleor_api_url;
+        let (mut last_received_at, events) = stats.snapshot(&saleor_api_url);
+        if last_received_at.is_none() && archive.is_enabled() {
+            let filter = ArchiveFilter { saleor_api_url: Some(saleor_api_url.clone()), limit: 1, ..Default::default() };
+            last_received_at = archive
+                .search(&filter)
+                .await
+                .ok()
+                .and_then(|webhooks| webhooks.into_iter().next())
+                .and_then(|webhook| OffsetDateTime::parse(&webhook.received_at, &Rfc3339).ok());
+        }
+        statuses.push((last_received_at, WebhookStatus {
+            last_received_at: last_received_at.and_then(|at| at.format(&Rfc3339).ok()),
+            failures: events.values().map(|counts| counts.failed).sum(),
+            events,
+            backlog: client.concurrency().waiting(&saleor_api_url, Workload::Webhooks),
+            saleor_api_url,
+        }));
+    }
+    // installations that went quiet are the interesting ones
+    statuses.sort_by(|(a, a_status), (b, b_status)| a.cmp(b).then_with(|| a_status.saleor_api_url.cmp(&b_status.saleor_api_url)));
+    Json(statuses.into_iter().map(|(_, status)| status).collect::<Vec<_>>()).into_response()
+}
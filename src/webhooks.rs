@@ -0,0 +1,49 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{emitter::EventEmitter, events::{AppEvent, EventHub}, saleor::{subscriptions::WebhookSubscription, SaleorAsyncWebhookEvent, SaleorWebhook, SaleorWebhookManifest}};
+
+pub const PRODUCT_UPDATED_PATH: &str = "/api/webhooks/product-updated";
+
+#[derive(cynic::QueryFragment, Serialize, Debug, Clone, ToSchema)]
+#[cynic(graphql_type = "Product", schema_module = "crate::saleor::schema")]
+pub struct WebhookProduct {
+    #[schema(value_type = String)]
+    pub id: cynic::Id,
+    pub name: String,
+}
+
+/// Payload of [`product_updated`], it also makes up the subscription query.
+#[derive(cynic::QueryFragment, Debug, ToSchema)]
+#[cynic(graphql_type = "ProductUpdated", schema_module = "crate::saleor::schema")]
+pub struct ProductUpdatedPayload {
+    pub product: Option<WebhookProduct>,
+}
+
+pub fn product_updated_subscription() -> WebhookSubscription<ProductUpdatedPayload> {
+    WebhookSubscription::new("Product updated", PRODUCT_UPDATED_PATH).with_async_event(SaleorAsyncWebhookEvent::ProductUpdated)
+}
+
+/// The webhooks declared in the manifest, `base_url` is where the app is reachable.
+pub fn manifest(base_url: &str) -> Vec<SaleorWebhookManifest> {
+    vec![product_updated_subscription().manifest(base_url)]
+}
+
+/// `POST /api/webhooks/product-updated`, tells the open pages of the installation and the
+/// [`EventEmitter`]'s sinks about the change.
+#[utoipa::path(post, path = "/api/webhooks/product-updated", tag = "webhooks", request_body = ProductUpdatedPayload, security(("saleor_signature" = [])), params(
+    ("saleor-api-url" = String, Header, description = "GraphQL API URL of the installation"),
+    ("saleor-event" = String, Header, description = "The event, `product_updated`"),
+), responses(
+    (status = 200, description = "The webhook was handled"),
+    (status = 401, description = "The signature is invalid"),
+))]
+pub async fn product_updated(hub: EventHub, emitter: EventEmitter, webhook: SaleorWebhook<ProductUpdatedPayload>) -> impl IntoResponse {
+    if let Some(product) = webhook.payload.product {
+        tracing::info!(product_id = %product.id.inner(), "product updated");
+        emitter.emit(&webhook.saleor_api_url, "productUpdated", &product);
+        hub.publish(&webhook.saleor_api_url, AppEvent::new("productUpdated", product));
+    }
+    StatusCode::OK
+}
@@ -0,0 +1,246 @@
+//! `GET /app/ws`, a WebSocket between the dashboard pages of a tenant and the app.
+//!
+//! `/app/events` streams [`AppEvent`]s one way, pages that also talk back open a socket instead.
+//! Messages are JSON objects tagged with their `type`:
+//!
+//! - pages send [`ClientMessage`]s: `subscribe` and `unsubscribe` to the progress of a `job` (`*`
+//!   for every job), `command` to run a [`WsCommand`] and `ping`
+//! - the app sends [`ServerMessage`]s: the `progress` of subscribed jobs, `toast`s to show as
+//!   dashboard notifications, every other `event` of the tenant, the `result` or `error` of commands
+//!   and `pong`
+//!
+//! The sockets of a tenant share its [`EventHub`] channel, commands run for the tenant the socket
+//! was opened for.
+
+use std::{collections::{HashMap, HashSet}, sync::{Arc, RwLock}};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, FromRequestParts},
+    http::{header::ORIGIN, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{broadcast::error::RecvError, mpsc, Semaphore};
+
+use crate::{base_url::BaseUrl, cors::DashboardOrigins, events::{AppEvent, EventHub, PROGRESS_EVENT, TOAST_EVENT}, saleor::RequestTenant};
+
+/// Subscribes to the progress of every job.
+pub const ALL_JOBS: &str = "*";
+
+/// Command replies waiting for the socket before commands have to wait.
+const REPLY_CAPACITY: usize = 16;
+
+/// Commands of a socket running at the same time, more are answered with an `error` right away.
+const MAX_RUNNING_COMMANDS: usize = 8;
+
+/// A message pages send over the socket.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Follows the progress of `job`, the `operation` of [`AppEvent::progress`]
+    Subscribe { job: String },
+    Unsubscribe { job: String },
+    /// Runs the command registered as `name`, answered with a `result` or an `error` with the same `id`
+    Command {
+        id: String,
+        name: String,
+        #[serde(default)]
+        args: Value,
+    },
+    Ping,
+}
+
+/// A message the app sends over the socket.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Progress { job: String, done: u64, total: u64 },
+    /// An [`crate::templating::AppBridgeAction`] notification, `/app-bridge.js` dispatches it
+    Toast { action: Value },
+    Event { name: String, data: Value },
+    Result { id: String, data: Value },
+    /// A failed command, or a message that couldn't be read if there's no `id`
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        message: String,
+    },
+    Pong,
+}
+
+impl ServerMessage {
+    /// How `event` reaches a socket subscribed to `jobs`, `None` for the progress of other jobs.
+    fn from_event(event: AppEvent, jobs: &HashSet<String>) -> Option<Self> {
+        match event.name.as_str() {
+            PROGRESS_EVENT => {
+                let job = event.data["operation"].as_str().unwrap_or_default();
+                if !jobs.contains(job) && !jobs.contains(ALL_JOBS) {
+                    return None;
+                }
+                Some(Self::Progress {
+                    job: job.to_string(),
+                    done: event.data["done"].as_u64().unwrap_or_default(),
+                    total: event.data["total"].as_u64().unwrap_or_default(),
+                })
+            }
+            TOAST_EVENT => Some(Self::Toast { action: event.data }),
+            _ => Some(Self::Event { name: event.name, data: event.data }),
+        }
+    }
+}
+
+/// What a [`WsCommand`] runs for.
+#[derive(Clone)]
+pub struct WsContext {
+    pub saleor_api_url: String,
+    /// Publish the progress of jobs the command starts to it
+    pub events: EventHub,
+}
+
+/// A command pages run over the socket, like starting an import.
+///
+/// Commands run next to the socket, so a long one doesn't hold up the events of the tenant. Jobs
+/// that outlive the command report their progress through [`WsContext::events`].
+#[async_trait]
+pub trait WsCommand: Send + Sync + 'static {
+    /// The `data` of the `result`, or the message of the `error` the page gets.
+    async fn run(&self, context: &WsContext, args: Value) -> Result<Value, String>;
+}
+
+/// The commands pages can run, provided to the socket as a request extension. Register commands on
+/// [`crate::app::App::ws_commands`].
+#[derive(Clone, Default)]
+pub struct WsCommands {
+    commands: Arc<RwLock<HashMap<String, Arc<dyn WsCommand>>>>,
+}
+
+impl WsCommands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets pages run `command` as `name`, replacing the command registered as `name` before.
+    pub fn register(&self, name: impl Into<String>, command: impl WsCommand) -> &Self {
+        self.commands.write().unwrap().insert(name.into(), Arc::new(command));
+        self
+    }
+
+    pub async fn run(&self, context: &WsContext, name: &str, args: Value) -> Result<Value, String> {
+        let command = self.commands.read().unwrap().get(name).cloned();
+        match command {
+            Some(command) => command.run(context, args).await,
+            None => Err(format!("unknown command {}", name)),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WsCommands
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<WsCommands>().cloned().ok_or((StatusCode::INTERNAL_SERVER_ERROR, "websocket commands not found in request extensions").into_response())
+    }
+}
+
+/// `GET /app/ws`, upgrades to a socket of the request's tenant.
+///
+/// Browsers can't send headers with WebSockets either, so this sits behind the same
+/// `SaleorAuthLayer` as `/app/events`. They send the session cookie with sockets of any site
+/// though, so only the app's own pages and the configured dashboard origins may open one.
+pub async fn socket(
+    upgrade: WebSocketUpgrade,
+    headers: HeaderMap,
+    BaseUrl(base_url): BaseUrl,
+    Extension(dashboard_origins): Extension<DashboardOrigins>,
+    RequestTenant(saleor_api_url): RequestTenant,
+    events: EventHub,
+    commands: WsCommands,
+) -> Response {
+    // clients without an `Origin` aren't browsers, they can't be made to use someone's session
+    let origin = headers.get(ORIGIN).map(|origin| origin.to_str().unwrap_or_default());
+    if let Some(origin) = origin {
+        let own_origin = Url::parse(&base_url).map(|url| url.origin().ascii_serialization()).ok();
+        if own_origin.as_deref() != Some(origin) && !dashboard_origins.allows_credentials(origin) {
+            tracing::info!(origin, "rejected a websocket of another origin");
+            return (StatusCode::FORBIDDEN, "websockets can only be opened by the pages of the app").into_response();
+        }
+    }
+
+    let context = WsContext { saleor_api_url, events };
+    upgrade.on_upgrade(move |socket| serve(socket, context, commands))
+}
+
+async fn serve(socket: WebSocket, context: WsContext, commands: WsCommands) {
+    let (mut sender, mut receiver) = socket.split();
+    let (reply_sender, mut replies) = mpsc::channel(REPLY_CAPACITY);
+    let running = Arc::new(Semaphore::new(MAX_RUNNING_COMMANDS));
+    let mut events = context.events.subscribe(&context.saleor_api_url);
+    let mut jobs = HashSet::new();
+
+    loop {
+        let message = tokio::select! {
+            incoming = receiver.next() => match incoming {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ClientMessage::Subscribe { job }) => {
+                        jobs.insert(job);
+                        continue;
+                    }
+                    Ok(ClientMessage::Unsubscribe { job }) => {
+                        jobs.remove(&job);
+                        continue;
+                    }
+                    Ok(ClientMessage::Command { id, name, args }) => match running.clone().try_acquire_owned() {
+                        Ok(permit) => {
+                            let (context, commands, reply_sender) = (context.clone(), commands.clone(), reply_sender.clone());
+                            tokio::spawn(async move {
+                                let _permit = permit;
+                                let reply = match commands.run(&context, &name, args).await {
+                                    Ok(data) => ServerMessage::Result { id, data },
+                                    Err(message) => ServerMessage::Error { id: Some(id), message },
+                                };
+                                // the page may be gone by now
+                                let _ = reply_sender.send(reply).await;
+                            });
+                            continue;
+                        }
+                        Err(_) => ServerMessage::Error {
+                            id: Some(id),
+                            message: format!("more than {MAX_RUNNING_COMMANDS} commands are running, wait for their results"),
+                        },
+                    },
+                    Ok(ClientMessage::Ping) => ServerMessage::Pong,
+                    Err(e) => ServerMessage::Error { id: None, message: format!("invalid message: {}", e) },
+                },
+                // pings of the protocol are answered by axum
+                Some(Ok(Message::Binary(_) | Message::Ping(_) | Message::Pong(_))) => continue,
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            },
+            event = events.recv() => match event {
+                Ok(event) => match ServerMessage::from_event(event, &jobs) {
+                    Some(message) => message,
+                    None => continue,
+                },
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("websocket lagged behind, missed {missed} events");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            Some(reply) = replies.recv() => reply,
+        };
+
+        let text = serde_json::to_string(&message).expect("messages serialize");
+        if sender.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+}
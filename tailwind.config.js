@@ -2,6 +2,8 @@ const { fontFamily } = require('tailwindcss/defaultTheme');
 
 /** @type {import('tailwindcss').Config} */
 module.exports = {
+  // The dashboard theme is applied as data-theme by app-bridge.js
+  darkMode: ['class', '[data-theme="dark"]'],
   content: [
     './templates/**/*.html',
   ],
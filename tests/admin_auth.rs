@@ -0,0 +1,68 @@
+use axum::{body::Body, http::{Request, StatusCode}};
+use saleor_app::{
+    admin_auth::{AdminAuth, OidcConfig},
+    config::AppConfig,
+    operator_api::OPERATOR_API_KEY_HEADER,
+    saleor::SaleorPermission,
+    secrets::SecretString,
+    testing::TestApp,
+};
+use serde_json::Value;
+
+const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+#[tokio::test]
+async fn api_key_guard_replaces_dashboard_tokens() {
+    let app = TestApp::with_saleor_config(|saleor| AppConfig {
+        admin_api_urls: vec![saleor.api_url()],
+        operator_api_key: Some(SecretString::from(API_KEY.to_string())),
+        admin_auth: AdminAuth::ApiKey,
+        ..Default::default()
+    })
+    .await;
+
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/installations").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let request = Request::get("/api/installations").header(OPERATOR_API_KEY_HEADER, API_KEY).body(Body::empty()).unwrap();
+    let response = app.request(request).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<Vec<Value>>().len(), 1);
+}
+
+#[tokio::test]
+async fn dashboard_guard_is_the_default() {
+    let app = TestApp::with_saleor_config(|saleor| AppConfig { admin_api_urls: vec![saleor.api_url()], ..Default::default() }).await;
+
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/installations").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[test]
+fn guards_report_missing_settings() {
+    let config = AppConfig { admin_auth: AdminAuth::ApiKey, ..Default::default() };
+    assert!(config.validate().contains(&"ADMIN_AUTH=api-key requires OPERATOR_API_KEY".to_string()));
+
+    let oidc = OidcConfig {
+        issuer_url: "https://accounts.example.com".to_string(),
+        client_id: "saleor-app".to_string(),
+        client_secret: None,
+        allowed_emails: vec![],
+    };
+    let config = AppConfig { admin_auth: AdminAuth::Oidc(oidc), ..Default::default() };
+    assert!(config.validate().iter().any(|problem| problem.contains("OIDC_ALLOWED_EMAILS")));
+}
+
+#[test]
+fn oidc_allows_listed_emails_and_domains() {
+    let oidc = OidcConfig {
+        issuer_url: "https://accounts.example.com".to_string(),
+        client_id: "saleor-app".to_string(),
+        client_secret: None,
+        allowed_emails: vec!["ops@example.com".to_string(), "@support.example.com".to_string()],
+    };
+    assert!(oidc.allows("Ops@Example.com"));
+    assert!(oidc.allows("jane@support.example.com"));
+    assert!(!oidc.allows("jane@example.com"));
+    assert!(!oidc.allows("jane@evilsupport.example.com"));
+}
\ No newline at end of file
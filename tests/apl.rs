@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use saleor_app::{
+    config::AppConfig,
+    saleor::{AplId, AplKeyring, AplStore, AuthData, EncryptedAplStore, MemoryAplStore, SaleorPermission},
+    testing::{AplBehavior, AplCall, TestApp},
+    webhooks::PRODUCT_UPDATED_PATH,
+};
+use serde_json::Value;
+
+#[tokio::test]
+async fn registration_reports_failed_write() {
+    let app = TestApp::unregistered(AppConfig::default()).await;
+    app.apl.script(AplCall::Set, AplBehavior::Error("disk full".to_string()));
+
+    let response = app.register().await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = response.json();
+    assert_eq!(body["success"], false);
+    assert_eq!(body["error"]["code"], "APL_UNAVAILABLE");
+    assert!(app.apl.store().get_all().await.unwrap().is_empty());
+
+    // Saleor retries the installation
+    let response = app.register().await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(app.apl.store().get(&AplId::from_api_url(&app.saleor.api_url())).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn registration_reports_failed_lookup() {
+    let app = TestApp::unregistered(AppConfig::default()).await;
+    app.apl.script(AplCall::Get, AplBehavior::Error("connection reset".to_string()));
+
+    let response = app.register().await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(app.apl.calls(), vec![AplCall::Get]);
+}
+
+#[tokio::test]
+async fn auth_layer_reports_unavailable_apl() {
+    let app = TestApp::new().await;
+    app.apl.script(AplCall::Get, AplBehavior::Error("connection reset".to_string()));
+
+    let user = app.as_user(&[SaleorPermission::ManageProducts]);
+    let response = user.get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+
+    // only the scripted call fails
+    let response = user.get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn auth_layer_rejects_missing_installation() {
+    let app = TestApp::new().await;
+    app.apl.script(AplCall::Get, AplBehavior::NotFound);
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn current_installation_rejects_missing_installation() {
+    let app = TestApp::new().await;
+    // the auth layer finds the installation, the settings' lookup doesn't
+    app.apl.script(AplCall::Get, AplBehavior::Delay(Duration::ZERO)).script(AplCall::Get, AplBehavior::NotFound);
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/config").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn slow_apl_times_out() {
+    let mut config = AppConfig::default();
+    config.limits.default.timeout = Duration::from_millis(100);
+    let app = TestApp::with_config(config).await;
+    app.apl.script(AplCall::Get, AplBehavior::Delay(Duration::from_secs(5)));
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::REQUEST_TIMEOUT);
+}
+
+#[tokio::test]
+async fn webhooks_report_unavailable_apl() {
+    let app = TestApp::new().await;
+    // the rate limit finds the installation, the webhook's lookup doesn't
+    app.apl.script(AplCall::Get, AplBehavior::Delay(Duration::ZERO)).script(AplCall::Get, AplBehavior::Error("connection reset".to_string()));
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+fn auth_data(saleor_api_url: &str) -> AuthData {
+    AuthData::new(saleor_api_url, "app-token")
+}
+
+#[tokio::test]
+async fn tokens_are_encrypted_and_survive_key_rotation() {
+    let (old_key, new_key) = (AplKeyring::generate_key(), AplKeyring::generate_key());
+    let memory = MemoryAplStore::new();
+    let shop = "https://shop.saleor.cloud/graphql/";
+    let legacy = "https://legacy.saleor.cloud/graphql/";
+    // written before encryption was turned on
+    memory.set(&AplId::from_api_url(legacy), auth_data(legacy)).await.unwrap();
+
+    let encrypted = EncryptedAplStore::new(memory.clone(), AplKeyring::parse(&old_key).unwrap());
+    encrypted.set(&AplId::from_api_url(shop), auth_data(shop)).await.unwrap();
+    let stored = memory.get(&AplId::from_api_url(shop)).await.unwrap().unwrap();
+    assert!(stored.token.expose().starts_with("enc:v1:"), "{}", stored.token.expose());
+    assert_eq!(encrypted.get(&AplId::from_api_url(shop)).await.unwrap().unwrap().token, "app-token");
+
+    let rotated = EncryptedAplStore::new(memory.clone(), AplKeyring::parse(&format!("2:{new_key},1:{old_key}")).unwrap());
+    assert_eq!(rotated.get_all().await.unwrap().iter().map(|auth_data| auth_data.token.expose()).collect::<Vec<_>>(), ["app-token", "app-token"]);
+    assert_eq!(rotated.reencrypt().await.unwrap().reencrypted, 2);
+    assert_eq!(rotated.reencrypt().await.unwrap().reencrypted, 0);
+
+    // the old key can be dropped once everything is re-encrypted
+    let new_only = EncryptedAplStore::new(memory.clone(), AplKeyring::parse(&format!("2:{new_key}")).unwrap());
+    for saleor_api_url in [shop, legacy] {
+        assert_eq!(new_only.get(&AplId::from_api_url(saleor_api_url)).await.unwrap().unwrap().token, "app-token");
+    }
+    assert!(encrypted.get(&AplId::from_api_url(shop)).await.is_err(), "the old key can't read the new entries");
+}
+
+#[tokio::test]
+async fn undecryptable_entries_do_not_hide_the_others() {
+    let (old_key, new_key) = (AplKeyring::generate_key(), AplKeyring::generate_key());
+    let memory = MemoryAplStore::new();
+    let (shop, other) = ("https://shop.saleor.cloud/graphql/", "https://other.saleor.cloud/graphql/");
+    EncryptedAplStore::new(memory.clone(), AplKeyring::parse(&old_key).unwrap()).set(&AplId::from_api_url(shop), auth_data(shop)).await.unwrap();
+
+    // the old key was dropped before the entry of shop was re-encrypted
+    let encrypted = EncryptedAplStore::new(memory.clone(), AplKeyring::parse(&format!("2:{new_key}")).unwrap());
+    encrypted.set(&AplId::from_api_url(other), auth_data(other)).await.unwrap();
+    let installations = encrypted.get_all().await.unwrap();
+    assert_eq!(installations.iter().map(|auth_data| auth_data.saleor_api_url.as_str()).collect::<Vec<_>>(), [other]);
+
+    let reencryption = encrypted.reencrypt().await.unwrap();
+    assert_eq!(reencryption.reencrypted, 0);
+    assert_eq!(reencryption.failed.len(), 1);
+    assert!(reencryption.failed[0].0.contains(shop), "{}", reencryption.failed[0]);
+}
+
+#[tokio::test]
+async fn tokens_are_bound_to_their_installation() {
+    let memory = MemoryAplStore::new();
+    let encrypted = EncryptedAplStore::new(memory.clone(), AplKeyring::parse(&AplKeyring::generate_key()).unwrap());
+    let (shop, other) = ("https://shop.saleor.cloud/graphql/", "https://other.saleor.cloud/graphql/");
+    encrypted.set(&AplId::from_api_url(shop), auth_data(shop)).await.unwrap();
+
+    let mut swapped = memory.get(&AplId::from_api_url(shop)).await.unwrap().unwrap();
+    swapped.saleor_api_url = other.to_string();
+    memory.set(&AplId::from_api_url(other), swapped).await.unwrap();
+    assert!(encrypted.get(&AplId::from_api_url(other)).await.is_err());
+
+    // nor between apps installed in the same Saleor
+    let mut swapped = memory.get(&AplId::from_api_url(shop)).await.unwrap().unwrap();
+    swapped.app_id = "other-app".to_string();
+    memory.set(&AplId::with_app_id("other-app", shop), swapped).await.unwrap();
+    assert!(encrypted.get(&AplId::with_app_id("other-app", shop)).await.is_err());
+
+    assert!(AplKeyring::parse("1:too-short").is_err());
+    assert!(AplKeyring::parse("").is_err());
+}
@@ -0,0 +1,495 @@
+use axum::{body::Body, http::{header::ORIGIN, HeaderValue, Request, StatusCode}, routing::get, Router};
+use saleor_app::{
+    config::AppConfig,
+    rate_limit::{RateLimitConfig, TrustedProxies},
+    registration::RegistrationConfig,
+    saleor::{AplId, AplStore, AuthData, SaleorAplLayer, SaleorAuthLayer, SaleorPermission, SaleorRegisterErrorCode, SaleorRegisterResponse, SALEOR_API_URL_HEADER},
+    sessions::{SessionConfig, SessionCookie},
+    testing::{AplBehavior, AplCall, MockAplStore, MockSaleor, TestApp, TEST_APP_TOKEN},
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn register_stores_installation() {
+    let app = TestApp::new().await;
+
+    let auth_data = app.apl.get(&AplId::from_api_url(&app.saleor.api_url())).await.unwrap().expect("installation not stored");
+    assert_eq!(auth_data.token, TEST_APP_TOKEN);
+    assert_eq!(auth_data.jwks.as_deref(), Some(app.saleor.jwks()));
+    assert!(auth_data.registered_at.is_some());
+}
+
+#[tokio::test]
+async fn register_rejects_reused_token() {
+    let app = TestApp::new().await;
+
+    let response = app.register().await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let body: Value = response.json();
+    assert_eq!(body["error"]["code"], "AUTH_TOKEN_REUSED");
+
+    // installing the app again comes with a new token
+    let response = app.register_with_token("reinstalled-app-token").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn concurrent_registrations_with_one_token_install_once() {
+    let app = TestApp::unregistered(AppConfig::default()).await;
+
+    let (first, second) = tokio::join!(app.register(), app.register());
+    let mut statuses = [first.status, second.status];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::FORBIDDEN]);
+}
+
+#[tokio::test]
+async fn failed_registrations_can_be_retried_with_their_token() {
+    let app = TestApp::unregistered(AppConfig::default()).await;
+    app.apl.script(AplCall::Set, AplBehavior::Error("connection reset".into()));
+
+    let response = app.register().await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(app.register().await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn register_errors_use_codes_of_the_dashboard() {
+    let app = TestApp::new().await;
+    let register = |api_url: &str, body: &str| {
+        Request::post("/api/register")
+            .header("saleor-domain", app.saleor.domain())
+            .header("saleor-api-url", api_url)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app.request(register(&app.saleor.api_url(), "{}")).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<Value>()["error"]["code"], "TOKEN_MISSING");
+
+    let response = app.request(register("file:///graphql/", r#"{"auth_token": "token"}"#)).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let body: SaleorRegisterResponse = response.json();
+    assert_eq!(body.error.unwrap().code, SaleorRegisterErrorCode::SaleorUrlProhibited);
+
+    assert_eq!(SaleorRegisterErrorCode::from("OUT_OF_STOCK"), SaleorRegisterErrorCode::Custom("OUT_OF_STOCK".to_string()));
+    assert_eq!(serde_json::to_value(SaleorRegisterErrorCode::JwksNotAvailable).unwrap(), "JWKS_NOT_AVAILABLE");
+}
+
+#[tokio::test]
+async fn register_rejects_headers_that_are_not_utf8() {
+    let app = TestApp::new().await;
+    let non_utf8 = HeaderValue::from_bytes(b"saleor\xff.example.com").unwrap();
+
+    for header in ["saleor-domain", "saleor-api-url"] {
+        let mut request = Request::post("/api/register")
+            .header("saleor-domain", app.saleor.domain())
+            .header("saleor-api-url", app.saleor.api_url())
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "auth_token": "another-token" }).to_string()))
+            .unwrap();
+        request.headers_mut().insert(header, non_utf8.clone());
+        let response = app.request(request).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{header}");
+    }
+}
+
+#[tokio::test]
+async fn register_is_rate_limited() {
+    let config = AppConfig {
+        registration: RegistrationConfig { burst: 2, ..RegistrationConfig::default() },
+        ..AppConfig::default()
+    };
+    let app = TestApp::with_config(config).await;
+
+    let response = app.register_with_token("second-token").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.register_with_token("third-token").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers.contains_key("retry-after"));
+    let body: Value = response.json();
+    assert_eq!(body["error"]["code"], "RATE_LIMITED");
+}
+
+#[tokio::test]
+async fn forged_forwarded_for_does_not_reset_the_registration_budget() {
+    let config = AppConfig {
+        registration: RegistrationConfig { burst: 1, ..RegistrationConfig::default() },
+        ..AppConfig::default()
+    };
+    let app = TestApp::unregistered(config).await;
+    // other domains, so only the budget of the IP runs out
+    let register = |domain: &str, forwarded_for: &str| {
+        Request::post("/api/register")
+            .header("saleor-domain", domain)
+            .header("saleor-api-url", app.saleor.api_url())
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(json!({ "auth_token": format!("{domain}-token") }).to_string()))
+            .unwrap()
+    };
+
+    let response = app.request(register("first.example.com", "203.0.113.1")).await;
+    assert_ne!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    let response = app.request(register("second.example.com", "203.0.113.2")).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.json::<Value>()["error"]["code"], "RATE_LIMITED");
+}
+
+#[tokio::test]
+async fn registrations_the_domain_rejects_keep_the_budget_of_the_ip() {
+    let config = AppConfig {
+        registration: RegistrationConfig { burst: 1, ..RegistrationConfig::default() },
+        trusted_proxies: TrustedProxies::new(&["127.0.0.1".parse().unwrap()]),
+        ..AppConfig::default()
+    };
+    let app = TestApp::unregistered(config).await;
+    let register = |domain: &str, forwarded_for: &str| {
+        Request::post("/api/register")
+            .header("saleor-domain", domain)
+            .header("saleor-api-url", app.saleor.api_url())
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(json!({ "auth_token": format!("{domain}-{forwarded_for}-token") }).to_string()))
+            .unwrap()
+    };
+
+    let response = app.request(register("first.example.com", "203.0.113.1")).await;
+    assert_ne!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    let response = app.request(register("first.example.com", "203.0.113.2")).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    let response = app.request(register("second.example.com", "203.0.113.2")).await;
+    assert_ne!(response.status, StatusCode::TOO_MANY_REQUESTS, "{}", response.text());
+}
+
+#[tokio::test]
+async fn forwarded_for_is_only_trusted_from_proxies() {
+    let register = |app: &TestApp, forwarded_for: &str| {
+        Request::post("/api/register")
+            .header("saleor-domain", app.saleor.domain())
+            .header("saleor-api-url", app.saleor.api_url())
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(json!({ "auth_token": "another-token" }).to_string()))
+            .unwrap()
+    };
+
+    let rate_limit = RateLimitConfig { capacity: 1, ..RateLimitConfig::default() };
+    let app = TestApp::unregistered(AppConfig { rate_limit: rate_limit.clone(), ..AppConfig::default() }).await;
+    assert_ne!(app.request(register(&app, "203.0.113.1")).await.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(app.request(register(&app, "203.0.113.2")).await.status, StatusCode::TOO_MANY_REQUESTS);
+
+    let trusted_proxies = TrustedProxies::new(&["127.0.0.1".parse().unwrap(), "10.0.0.1".parse().unwrap()]);
+    let app = TestApp::unregistered(AppConfig { rate_limit, trusted_proxies, ..AppConfig::default() }).await;
+    assert_ne!(app.request(register(&app, "203.0.113.1, 10.0.0.1")).await.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_ne!(app.request(register(&app, "203.0.113.2, 10.0.0.1")).await.status, StatusCode::TOO_MANY_REQUESTS);
+    // hops before the right-most untrusted one could be made up by the client
+    assert_eq!(app.request(register(&app, "198.51.100.7, 203.0.113.2, 10.0.0.1")).await.status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn manifest_uses_request_host() {
+    let app = TestApp::new().await;
+
+    let response = app.get("/api/manifest").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let manifest: Value = response.json();
+    assert_eq!(manifest["tokenTargetUrl"], "https://localhost/api/register");
+}
+
+#[tokio::test]
+async fn manifest_is_only_served_as_json() {
+    let app = TestApp::new().await;
+    let manifest = |method: &str, accept: Option<&str>| {
+        let request = Request::builder().method(method).uri("/api/manifest");
+        match accept {
+            Some(accept) => request.header("accept", accept),
+            None => request,
+        }
+        .body(Body::empty())
+        .unwrap()
+    };
+
+    for accept in [None, Some("application/json"), Some("*/*"), Some("text/html, application/*;q=0.8"), Some("application/manifest+json"), Some("*/*;q=0, application/json")] {
+        let response = app.request(manifest("GET", accept)).await;
+        assert_eq!(response.status, StatusCode::OK, "{accept:?}");
+        assert_eq!(response.headers["content-type"], "application/json; charset=utf-8");
+        assert_eq!(response.json::<Value>()["tokenTargetUrl"], "https://localhost/api/register");
+    }
+    for accept in ["text/html", "application/xml", "application/json;q=0", "application/json;q=0, */*", "application/*;q=0, */*;q=0.5"] {
+        let response = app.request(manifest("GET", Some(accept))).await;
+        assert_eq!(response.status, StatusCode::NOT_ACCEPTABLE, "{accept}");
+    }
+
+    let response = app.request(manifest("HEAD", None)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.headers["content-type"], "application/json; charset=utf-8");
+    assert!(response.body.is_empty());
+}
+
+/// The CORS headers answering a request from `origin`, if it's allowed.
+async fn cors_headers(app: &TestApp, origin: &str) -> Option<(String, bool)> {
+    let request = Request::get("/api/manifest").header(ORIGIN, origin).body(Body::empty()).unwrap();
+    let headers = app.request(request).await.headers;
+    let allowed = headers.get("access-control-allow-origin")?.to_str().unwrap().to_string();
+    Some((allowed, headers.contains_key("access-control-allow-credentials")))
+}
+
+#[tokio::test]
+async fn only_configured_origins_send_credentials() {
+    let app = TestApp::with_config(AppConfig { cors_origins: vec!["https://dashboard.example.com".to_string()], ..Default::default() }).await;
+    let installed = format!("http://{}", app.saleor.domain());
+
+    assert_eq!(cors_headers(&app, "https://dashboard.example.com").await, Some(("https://dashboard.example.com".to_string(), true)));
+    assert_eq!(cors_headers(&app, &installed).await, Some((installed.clone(), false)));
+    assert_eq!(cors_headers(&app, "https://evil.example.com").await, None);
+
+    let app = TestApp::with_config(AppConfig { cors_origins: vec!["*".to_string()], ..Default::default() }).await;
+    assert_eq!(cors_headers(&app, "https://evil.example.com").await, Some(("https://evil.example.com".to_string(), false)));
+}
+
+#[tokio::test]
+async fn dashboard_origins_come_from_the_apl() {
+    let app = TestApp::unregistered(AppConfig::default()).await;
+    // stored by another replica
+    let other = AuthData { domain: Some("shop.example.com".to_string()), ..AuthData::new("https://api.example.com/graphql/", "app-token") };
+    app.apl.store().set(&AplId::from_auth_data(&other), other).await.unwrap();
+
+    assert!(cors_headers(&app, "https://shop.example.com").await.is_some());
+    assert!(cors_headers(&app, "https://api.example.com").await.is_none());
+
+    // failed registrations don't allow their dashboard
+    app.apl.script(AplCall::Set, AplBehavior::Error("connection reset".to_string()));
+    assert!(!app.register().await.status.is_success());
+    assert!(cors_headers(&app, &format!("http://{}", app.saleor.domain())).await.is_none());
+}
+
+#[tokio::test]
+async fn openapi_spec_documents_api() {
+    let app = TestApp::new().await;
+
+    let response = app.get("/api/openapi.json").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let spec: Value = response.json();
+    for path in ["/api/manifest", "/api/register", "/api/auth", "/api/config", "/api/installations", "/api/webhooks/product-updated"] {
+        assert!(spec["paths"][path].is_object(), "{path} is missing");
+    }
+    assert_eq!(spec["paths"]["/api/register"]["post"]["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/SaleorAuthToken");
+    assert!(spec["components"]["schemas"]["SaleorManifest"].is_object());
+    assert!(spec["components"]["securitySchemes"]["dashboard_token"].is_object());
+}
+
+#[tokio::test]
+async fn api_requires_token() {
+    let app = TestApp::new().await;
+
+    let response = app.get("/api/hello").await;
+    assert!(response.status.is_client_error(), "unexpected status {}", response.status);
+}
+
+#[tokio::test]
+async fn api_checks_permissions() {
+    let app = TestApp::new().await;
+
+    let response = app.as_user(&[SaleorPermission::ManageOrders]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.text(), "Hello from the API");
+}
+
+#[tokio::test]
+async fn unregistered_app_rejects_users() {
+    let app = TestApp::unregistered(Default::default()).await;
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert!(response.status.is_client_error(), "unexpected status {}", response.status);
+}
+
+#[tokio::test]
+async fn rotated_jwks_replaces_cached_keys() {
+    let app = TestApp::new().await;
+    let user = app.as_user(&[SaleorPermission::ManageProducts]);
+    assert_eq!(user.get("/api/hello").await.status, StatusCode::OK);
+
+    // Saleor switched to another key, tokens of the old one mustn't be accepted from the cache
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    let mut auth_data = app.apl.get(&apl_id).await.unwrap().unwrap();
+    auth_data.jwks = Some(MockSaleor::start().await.jwks().to_string());
+    app.apl.set(&apl_id, auth_data).await.unwrap();
+
+    assert_eq!(user.get("/api/hello").await.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn registering_again_invalidates_sessions() {
+    let app = TestApp::new().await;
+    app.saleor.respond_to("me", json!({ "me": { "id": "VXNlcjox" } }));
+    let token = app.saleor.token(&[SaleorPermission::ManageProducts]);
+
+    let auth = Request::post("/api/auth")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "api_url": app.saleor.api_url(), "token": token }).to_string()))
+        .unwrap();
+    let response = app.request(auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let cookie = response.headers["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+    let hello = || Request::get("/api/hello").header("cookie", &cookie).body(Body::empty()).unwrap();
+    assert_eq!(app.request(hello()).await.status, StatusCode::OK);
+
+    let response = app.register_with_token("rotated-app-token").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(app.request(hello()).await.status, StatusCode::UNAUTHORIZED);
+    // the outdated token was removed from the session
+    assert_eq!(app.request(hello()).await.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn pages_only_show_permitted_actions() {
+    let app = TestApp::new().await;
+    app.saleor.respond_to("me", json!({ "me": { "id": "VXNlcjox" } }));
+
+    for (permissions, shown) in [(vec![SaleorPermission::ManageApps], true), (vec![SaleorPermission::ManageProducts], false)] {
+        let auth = Request::post("/api/auth")
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "api_url": app.saleor.api_url(), "token": app.saleor.token(&permissions) }).to_string()))
+            .unwrap();
+        let response = app.request(auth).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let cookie = response.headers["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+
+        let page = app.request(Request::get("/app").header("cookie", &cookie).body(Body::empty()).unwrap()).await;
+        assert_eq!(page.status, StatusCode::OK);
+        assert_eq!(page.text().contains("href=\"/app/installations\""), shown, "{permissions:?}");
+    }
+}
+
+#[tokio::test]
+async fn pages_may_be_framed_by_the_dashboard_of_the_session() {
+    let app = TestApp::new().await;
+    app.saleor.respond_to("me", json!({ "me": { "id": "VXNlcjox" } }));
+
+    let auth = Request::post("/api/auth")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "api_url": app.saleor.api_url(), "token": app.saleor.token(&[]) }).to_string()))
+        .unwrap();
+    let response = app.request(auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let cookie = response.headers["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+
+    let page = app.request(Request::get("/app").header("cookie", &cookie).body(Body::empty()).unwrap()).await;
+    assert_eq!(page.status, StatusCode::OK);
+    let policy = page.headers["content-security-policy"].to_str().unwrap();
+    let origin = app.saleor.api_url().trim_end_matches("/graphql/").to_string();
+    assert!(policy.contains(&origin), "{policy}");
+}
+
+#[tokio::test]
+async fn session_cookies_follow_the_profile() {
+    for (cookie, same_site) in [(SessionCookie::production(), "SameSite=None"), (SessionCookie::development(), "SameSite=Lax")] {
+        let config = AppConfig { sessions: SessionConfig { cookie, ..Default::default() }, ..Default::default() };
+        let app = TestApp::with_config(config).await;
+        app.saleor.respond_to("me", json!({ "me": { "id": "VXNlcjox" } }));
+
+        let auth = Request::post("/api/auth")
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "api_url": app.saleor.api_url(), "token": app.saleor.token(&[]) }).to_string()))
+            .unwrap();
+        let response = app.request(auth).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let set_cookie = response.headers["set-cookie"].to_str().unwrap();
+        assert!(set_cookie.contains(same_site), "{set_cookie}");
+        assert_eq!(set_cookie.contains("Secure"), cookie.secure, "{set_cookie}");
+    }
+}
+
+#[tokio::test]
+async fn api_routes_can_require_bearer_tokens() {
+    let config = AppConfig { sessions: SessionConfig { api_sessions: false, ..Default::default() }, ..Default::default() };
+    let app = TestApp::with_config(config).await;
+    app.saleor.respond_to("me", json!({ "me": { "id": "VXNlcjox" } }));
+
+    let auth = Request::post("/api/auth")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "api_url": app.saleor.api_url(), "token": app.saleor.token(&[SaleorPermission::ManageProducts]) }).to_string()))
+        .unwrap();
+    let response = app.request(auth).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let cookie = response.headers["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+    let hello = Request::get("/api/hello").header("cookie", &cookie).header("saleor-api-url", app.saleor.api_url()).body(Body::empty()).unwrap();
+    let response = app.request(hello).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers["www-authenticate"], "Bearer");
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[tokio::test]
+async fn routers_without_sessions_accept_bearer_tokens() {
+    let saleor = MockSaleor::start().await;
+    let apl = MockAplStore::new();
+    let auth_data = saleor.auth_data();
+    apl.set(&AplId::from_auth_data(&auth_data), auth_data).await.unwrap();
+    // no session layer or http client in front of the auth layer
+    let router = Router::new()
+        .route("/hello", get(|| async { "Hello" }))
+        .layer(SaleorAuthLayer::with_permissions(&[SaleorPermission::ManageProducts]))
+        .layer(SaleorAplLayer::new(apl));
+    let hello = |token: Option<String>| {
+        let request = Request::get("/hello").header("host", "localhost").header(SALEOR_API_URL_HEADER, saleor.api_url());
+        match token {
+            Some(token) => request.header("authorization", format!("Bearer {token}")),
+            None => request,
+        }
+        .body(Body::empty())
+        .unwrap()
+    };
+
+    let response = router.clone().oneshot(hello(Some(saleor.token(&[SaleorPermission::ManageProducts])))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = router.oneshot(hello(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn malformed_bearer_tokens_are_rejected() {
+    let saleor = MockSaleor::start().await;
+    let apl = MockAplStore::new();
+    let auth_data = saleor.auth_data();
+    apl.set(&AplId::from_auth_data(&auth_data), auth_data).await.unwrap();
+    let router = Router::new()
+        .route("/hello", get(|| async { "Hello" }))
+        .layer(SaleorAuthLayer::with_permissions(&[SaleorPermission::ManageProducts]))
+        .layer(SaleorAplLayer::new(apl));
+    let hello = |authorization: HeaderValue| {
+        Request::get("/hello")
+            .header("host", "localhost")
+            .header(SALEOR_API_URL_HEADER, saleor.api_url())
+            .header("authorization", authorization)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let token = saleor.token(&[SaleorPermission::ManageProducts]);
+    let response = router.clone().oneshot(hello(HeaderValue::from_str(&format!("Basic {token}")).unwrap())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    let response = router.oneshot(hello(HeaderValue::from_bytes(b"Bearer \xff").unwrap())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+}
+
+#[tokio::test]
+async fn auth_layer_without_apl_fails() {
+    let saleor = MockSaleor::start().await;
+    let router = Router::new().route("/hello", get(|| async { "Hello" })).layer(SaleorAuthLayer::with_permissions(&[]));
+    let hello = Request::get("/hello").header("host", "localhost").header(SALEOR_API_URL_HEADER, saleor.api_url()).body(Body::empty()).unwrap();
+
+    let response = router.oneshot(hello).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
@@ -0,0 +1,83 @@
+use axum::http::StatusCode;
+use saleor_app::{
+    config::AppConfig,
+    saleor::{AplId, AplStore, AuthData, MemoryAplStore, MigratingAplStore, SaleorPermission},
+    testing::TestApp,
+    APP_ID,
+};
+use serde_json::Value;
+
+const APP_ID_OVERRIDE: &str = "renamed-app";
+
+fn config() -> AppConfig {
+    AppConfig { app_id: APP_ID_OVERRIDE.to_string(), previous_app_ids: vec!["old-app".to_string()], ..Default::default() }
+}
+
+fn auth_data(app_id: &str, saleor_api_url: &str) -> AuthData {
+    AuthData {
+        app_id: app_id.to_string(),
+        ..AuthData::new(saleor_api_url, "token")
+    }
+}
+
+#[tokio::test]
+async fn installations_of_previous_ids_are_moved_to_the_configured_one() {
+    let app = TestApp::with_config(config()).await;
+    let manifest: Value = app.get("/api/manifest").await.json();
+    assert_eq!(manifest["id"], APP_ID_OVERRIDE);
+
+    let api_url = app.saleor.api_url();
+    let current_id = AplId::with_app_id(APP_ID_OVERRIDE, &api_url);
+    let previous_id = AplId::with_app_id("old-app", &api_url);
+    let mut stored = app.apl.get(&current_id).await.unwrap().expect("registered under the configured id");
+    assert_eq!(stored.app_id, APP_ID_OVERRIDE);
+    // as if it was registered before the app was renamed
+    stored.app_id = "old-app".to_string();
+    app.apl.remove(&current_id).await.unwrap();
+    app.apl.set(&previous_id, stored).await.unwrap();
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(app.apl.get(&previous_id).await.unwrap().is_none());
+    assert_eq!(app.apl.get(&current_id).await.unwrap().unwrap().app_id, APP_ID_OVERRIDE);
+}
+
+#[tokio::test]
+async fn apps_in_one_process_keep_their_own_ids() {
+    let renamed = TestApp::with_config(config()).await;
+    let default = TestApp::new().await;
+
+    let stored = renamed.apl.get(&AplId::with_app_id(APP_ID_OVERRIDE, &renamed.saleor.api_url())).await.unwrap().expect("registered under its id");
+    assert_eq!(stored.app_id, APP_ID_OVERRIDE);
+    let stored = default.apl.get(&AplId::with_app_id(APP_ID, &default.saleor.api_url())).await.unwrap().expect("registered under the package name");
+    assert_eq!(stored.app_id, APP_ID);
+    let response = default.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[tokio::test]
+async fn the_package_name_is_migrated_from_too() {
+    let memory = MemoryAplStore::new();
+    memory.set(&AplId::with_app_id(APP_ID, "https://a.example.com/graphql/"), auth_data(APP_ID, "https://a.example.com/graphql/")).await.unwrap();
+    memory.set(&AplId::with_app_id("old-app", "https://b.example.com/graphql/"), auth_data("old-app", "https://b.example.com/graphql/")).await.unwrap();
+    memory.set(&AplId::with_app_id("unrelated", "https://c.example.com/graphql/"), auth_data("unrelated", "https://c.example.com/graphql/")).await.unwrap();
+    let apl = MigratingAplStore::from_config(memory.clone(), &config());
+
+    let listed = apl.get_all().await.unwrap();
+    assert_eq!(listed.iter().filter(|auth_data| auth_data.app_id == APP_ID_OVERRIDE).count(), 2);
+    assert_eq!(apl.migrate().await.unwrap(), 2);
+    assert_eq!(apl.migrate().await.unwrap(), 0);
+    assert!(memory.get(&AplId::with_app_id(APP_ID_OVERRIDE, "https://a.example.com/graphql/")).await.unwrap().is_some());
+    assert!(memory.get(&AplId::with_app_id("unrelated", "https://c.example.com/graphql/")).await.unwrap().is_some());
+
+    // removing an installation that wasn't moved yet doesn't bring it back
+    memory.set(&AplId::with_app_id("old-app", "https://d.example.com/graphql/"), auth_data("old-app", "https://d.example.com/graphql/")).await.unwrap();
+    apl.remove(&AplId::with_app_id(APP_ID_OVERRIDE, "https://d.example.com/graphql/")).await.unwrap();
+    assert!(apl.get(&AplId::with_app_id(APP_ID_OVERRIDE, "https://d.example.com/graphql/")).await.unwrap().is_none());
+}
+
+#[test]
+fn app_ids_are_validated() {
+    let problems = AppConfig { app_id: "my app:v2".to_string(), ..Default::default() }.validate();
+    assert!(problems.iter().any(|problem| problem.contains("my app:v2")), "{problems:?}");
+}
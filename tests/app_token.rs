@@ -0,0 +1,87 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use saleor_app::{
+    saleor::{fulfillments::update_tracking, AplId, AplStore, AuthData, OnAuthInvalid, SaleorPermission},
+    testing::{TestApp, TEST_APP_TOKEN},
+};
+use serde_json::json;
+
+#[derive(Clone, Default)]
+struct CountingHook(Arc<AtomicUsize>);
+
+#[async_trait]
+impl OnAuthInvalid for CountingHook {
+    async fn on_auth_invalid(&self, _auth_data: &AuthData) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+async fn hook_runs(hook: &CountingHook) -> usize {
+    for _ in 0..50 {
+        if hook.0.load(Ordering::SeqCst) > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    hook.0.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+async fn rejected_app_tokens_mark_the_installation_and_retry_with_new_tokens() {
+    let hook = CountingHook::default();
+    let app = TestApp::new().await;
+    app.http_client.app_tokens().on_auth_invalid(hook.clone());
+    let client = app.http_client.clone();
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    app.saleor.respond_to("orderFulfillmentUpdateTracking", json!({ "orderFulfillmentUpdateTracking": { "errors": [] } }));
+    app.saleor.reject_token(TEST_APP_TOKEN);
+
+    let auth_data = app.apl.store().get(&apl_id).await.unwrap().unwrap();
+    let error = update_tracking(&client, &auth_data, "RnVsZmlsbG1lbnQ6MQ==", "JD014600006281230703", false).await.unwrap_err();
+    assert!(error.0.contains("rejected the app token"), "{}", error.0);
+    assert!(app.apl.store().get(&apl_id).await.unwrap().unwrap().token_invalid_since.is_some());
+    assert_eq!(hook_runs(&hook).await, 1);
+
+    // the installation is already marked, the hook doesn't run again
+    update_tracking(&client, &auth_data, "RnVsZmlsbG1lbnQ6MQ==", "JD014600006281230703", false).await.unwrap_err();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(hook.0.load(Ordering::SeqCst), 1);
+
+    // registering again replaces the token, calls with the old one are made again with it
+    let registered = AuthData { token: "new-app-token".into(), token_invalid_since: None, ..auth_data.clone() };
+    app.apl.store().set(&apl_id, registered).await.unwrap();
+    update_tracking(&client, &auth_data, "RnVsZmlsbG1lbnQ6MQ==", "JD014600006281230703", false).await.unwrap();
+    assert!(app.apl.store().get(&apl_id).await.unwrap().unwrap().token_invalid_since.is_none());
+}
+
+#[tokio::test]
+async fn apps_only_run_their_own_hook() {
+    let hook = CountingHook::default();
+    let other = TestApp::new().await;
+    other.http_client.app_tokens().on_auth_invalid(hook.clone());
+    let app = TestApp::new().await;
+    app.saleor.reject_token(TEST_APP_TOKEN);
+
+    let auth_data = app.saleor.auth_data();
+    update_tracking(&app.http_client, &auth_data, "RnVsZmlsbG1lbnQ6MQ==", "JD014600006281230703", false).await.unwrap_err();
+    assert!(app.apl.store().get(&AplId::from_auth_data(&auth_data)).await.unwrap().unwrap().token_invalid_since.is_some());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(hook.0.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn settings_notice_rejected_app_tokens() {
+    let hook = CountingHook::default();
+    let app = TestApp::new().await;
+    app.http_client.app_tokens().on_auth_invalid(hook.clone());
+    app.saleor.reject_token(TEST_APP_TOKEN);
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/config").await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE, "{}", response.text());
+    assert!(response.text().contains("rejected the app token"), "{}", response.text());
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    assert!(app.apl.store().get(&apl_id).await.unwrap().unwrap().token_invalid_since.is_some());
+    assert_eq!(hook_runs(&hook).await, 1);
+}
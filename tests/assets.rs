@@ -0,0 +1,25 @@
+// `dev` serves assets under plain urls and `embed-assets` from the binary instead of `assets_dir`
+#![cfg(not(any(feature = "dev", feature = "embed-assets")))]
+
+use axum::http::{header::CACHE_CONTROL, StatusCode};
+use saleor_app::{config::AppConfig, saleor::SaleorPermission, testing::TestApp};
+
+#[tokio::test]
+async fn pages_link_assets_by_their_content_hash() {
+    let assets_dir = std::env::temp_dir().join(format!("assets-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&assets_dir).unwrap();
+    std::fs::write(assets_dir.join("main.css"), "body { color: teal; }").unwrap();
+    let app = TestApp::with_config(AppConfig { assets_dir: assets_dir.clone(), ..Default::default() }).await;
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/app").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let body = response.text();
+    let url = body.split(r#"<link rel="stylesheet" href=""#).nth(1).and_then(|rest| rest.split('"').next()).unwrap();
+    assert!(url.starts_with("/assets/main.css?v="), "{url}");
+
+    let response = app.get(url).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.headers[CACHE_CONTROL], "public, max-age=31536000, immutable");
+
+    std::fs::remove_dir_all(assets_dir).unwrap();
+}
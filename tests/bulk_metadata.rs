@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use saleor_app::{
+    events::AppEvent,
+    saleor::{bulk_metadata::{BulkMetadataJob, BulkMetadataReport, BULK_METADATA_EVENT}, SaleorPermission},
+    testing::TestApp,
+};
+use serde_json::{json, Map, Value};
+
+const FAILING_PRODUCT: &str = "UHJvZHVjdDoxMg==";
+
+fn product_id(index: usize) -> String {
+    STANDARD.encode(format!("Product:{index}"))
+}
+
+/// Answers the aliased `updateMetadata` mutations, failing the one of [`FAILING_PRODUCT`].
+fn update_metadata(variables: &Value) -> Value {
+    let results = variables
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter_map(|(name, id)| name.strip_prefix("id").map(|index| (index, id)))
+        .map(|(index, id)| {
+            let errors = match id == FAILING_PRODUCT {
+                true => json!([{ "field": "id", "message": "Couldn't resolve to a node" }]),
+                false => json!([]),
+            };
+            (format!("p{index}"), json!({ "errors": errors }))
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(results)
+}
+
+#[tokio::test]
+async fn metadata_is_set_in_chunks_reporting_progress() {
+    let app = TestApp::new().await;
+    app.saleor.respond_with("BulkMetadata", update_metadata);
+    let mut events = app.events.subscribe(&app.saleor.api_url());
+
+    let product_ids = (0..30).map(product_id).collect::<Vec<_>>();
+    let response = app
+        .as_user(&[SaleorPermission::ManageProducts])
+        .post_json("/api/products/bulk-metadata", &json!({ "productIds": product_ids, "metadata": [{ "key": "season", "value": "summer" }] }))
+        .await;
+    assert_eq!(response.status, StatusCode::ACCEPTED, "{}", response.text());
+    let job: BulkMetadataJob = response.json();
+    assert_eq!(job.total, 30);
+
+    let mut received: Vec<AppEvent> = vec![];
+    while !received.iter().any(|event| event.name == "toast") {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.expect("the job finished").unwrap();
+        received.push(event);
+    }
+    let progress = received.iter().filter(|event| event.name == "progress").map(|event| (event.data["done"].as_u64().unwrap(), event.data["total"].as_u64().unwrap())).collect::<Vec<_>>();
+    assert_eq!(progress, vec![(0, 30), (25, 30), (30, 30)]);
+    assert!(received.iter().filter(|event| event.name == "progress").all(|event| event.data["operation"] == job.job));
+
+    let report = received.iter().find(|event| event.name == BULK_METADATA_EVENT).expect("the job reported");
+    let report: BulkMetadataReport = serde_json::from_value(report.data.clone()).unwrap();
+    assert_eq!(report.updated, 29);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].id, FAILING_PRODUCT);
+    assert_eq!(report.failed[0].message, "Couldn't resolve to a node");
+    let toast = received.last().unwrap();
+    assert_eq!(toast.data["payload"]["status"], "warning");
+
+    let mutations = app.saleor.requests().into_iter().filter(|request| request["query"].as_str().unwrap_or_default().contains("BulkMetadata")).collect::<Vec<_>>();
+    assert_eq!(mutations.len(), 2);
+    assert_eq!(mutations[0]["variables"]["input"], json!([{ "key": "season", "value": "summer" }]));
+    assert_eq!(mutations[1]["variables"]["id4"], product_id(29));
+}
+
+#[tokio::test]
+async fn requests_without_products_or_metadata_are_rejected() {
+    let app = TestApp::new().await;
+    let user = app.as_user(&[SaleorPermission::ManageProducts]);
+
+    for body in [
+        json!({ "productIds": [], "metadata": [{ "key": "season", "value": "summer" }] }),
+        json!({ "productIds": [product_id(1)], "metadata": [] }),
+        json!({ "productIds": [product_id(1)], "metadata": [{ "key": " ", "value": "summer" }] }),
+        // an order, the app token could write to it but the user only manages products
+        json!({ "productIds": [product_id(1), "T3JkZXI6MQ=="], "metadata": [{ "key": "season", "value": "summer" }] }),
+        json!({ "productIds": ["product-1"], "metadata": [{ "key": "season", "value": "summer" }] }),
+    ] {
+        let response = user.post_json("/api/products/bulk-metadata", &body).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{body}: {}", response.text());
+    }
+
+    let response = app.as_user(&[SaleorPermission::ManageOrders]).post_json("/api/products/bulk-metadata", &json!({ "productIds": [product_id(1)], "metadata": [{ "key": "season", "value": "summer" }] })).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    let mutations = app.saleor.requests().into_iter().filter(|request| request["query"].as_str().unwrap_or_default().contains("BulkMetadata")).count();
+    assert_eq!(mutations, 0);
+}
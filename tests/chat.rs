@@ -0,0 +1,96 @@
+use std::{net::TcpListener, sync::{Arc, Mutex}};
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use saleor_app::{
+    config::AppConfig,
+    saleor::chat::{self, *},
+    testing::TestApp,
+};
+use serde_json::{json, Value};
+
+/// An incoming chat webhook keeping the messages it gets, answering with `status`.
+#[derive(Clone)]
+struct Channel {
+    url: String,
+    messages: Arc<Mutex<Vec<Value>>>,
+}
+
+impl Channel {
+    fn start(status: StatusCode) -> Self {
+        let messages: Arc<Mutex<Vec<Value>>> = Default::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/channel", listener.local_addr().unwrap());
+        let router = Router::new()
+            .route("/hooks/channel", post(move |State(messages): State<Arc<Mutex<Vec<Value>>>>, Json(message): Json<Value>| async move {
+                messages.lock().unwrap().push(message);
+                status
+            }))
+            .with_state(messages.clone());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(router.into_make_service()));
+        Self { url, messages }
+    }
+}
+
+async fn chat_app() -> TestApp {
+    TestApp::with_routes(AppConfig::default(), chat::router()).await
+}
+
+fn settings(settings: &[(&str, &str)]) -> Value {
+    let metadata: Vec<Value> = settings.iter().map(|(key, value)| json!({ "key": key, "value": value })).collect();
+    json!({ "app": { "id": "QXBwOjE=", "privateMetadata": metadata } })
+}
+
+#[tokio::test]
+async fn new_orders_are_posted_to_slack() {
+    let app = chat_app().await;
+    let channel = Channel::start(StatusCode::OK);
+    app.saleor.respond_to("privateMetadata", settings(&[(CHAT_WEBHOOK_URL_SETTING, &channel.url)]));
+
+    let response = app.deliver_webhook_fixture(ORDER_CREATED_PATH, "order_created", "order_confirmed").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let messages = channel.messages.lock().unwrap();
+    let dashboard = format!("http://{}/dashboard/orders/T3JkZXI6ZjM0ZTg3NzMtNjE4Yy00ZDNjLWE4NzYtYjYzMWQ1NzE0ZDA0", app.saleor.domain());
+    assert_eq!(*messages, [json!({
+        "text": format!("New order #1042 from customer@example.com in default-channel: 74.50 USD\n2 × Monospace Tee (M)\n1 × Gift card\n{dashboard}"),
+    })]);
+}
+
+#[tokio::test]
+async fn templates_and_provider_come_from_the_settings() {
+    let app = chat_app().await;
+    let channel = Channel::start(StatusCode::NO_CONTENT);
+    app.saleor.respond_to("privateMetadata", settings(&[
+        (CHAT_WEBHOOK_URL_SETTING, &channel.url),
+        (CHAT_PROVIDER_SETTING, "discord"),
+        (ORDER_FULLY_PAID_TEMPLATE_SETTING, "💰 #{number} paid ({total})\\n{unknown}"),
+    ]));
+
+    let response = app.deliver_webhook_fixture(ORDER_FULLY_PAID_PATH, "order_fully_paid", "order_confirmed").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(*channel.messages.lock().unwrap(), [json!({
+        "content": "💰 #1042 paid (74.50 USD)\n{unknown}",
+        "allowed_mentions": { "parse": [] },
+    })]);
+}
+
+#[tokio::test]
+async fn failed_posts_are_retried_and_unconfigured_chats_skipped() {
+    let app = chat_app().await;
+    let channel = Channel::start(StatusCode::NOT_FOUND);
+    app.saleor.respond_to("privateMetadata", settings(&[(CHAT_WEBHOOK_URL_SETTING, &channel.url)]));
+    let response = app.deliver_webhook_fixture(ORDER_CREATED_PATH, "order_created", "order_confirmed").await;
+    assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+
+    app.saleor.respond_to("privateMetadata", settings(&[]));
+    let response = app.deliver_webhook_fixture(ORDER_CREATED_PATH, "order_created", "order_confirmed").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(channel.messages.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn provider_is_guessed_from_the_webhook_url() {
+    let settings = Default::default();
+    assert_eq!(ChatProvider::from_settings(&settings, "https://discord.com/api/webhooks/1/abc"), ChatProvider::Discord);
+    assert_eq!(ChatProvider::from_settings(&settings, "https://hooks.slack.com/services/T0/B0/x"), ChatProvider::Slack);
+}
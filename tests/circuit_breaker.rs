@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use axum::{body::Body, http::{Request, StatusCode}, response::IntoResponse};
+use saleor_app::{
+    circuit_breaker::{BreakerState, CircuitBreakerConfig, CircuitBreakers},
+    config::AppConfig,
+    http_client::HttpClient,
+    saleor::{AuthData, JwksCache, JwksFetchConfig, JwksFetchError},
+    testing::{MockSaleor, TestApp},
+};
+
+const SHOP: &str = "https://shop.example.com/graphql/";
+
+fn shop() -> AuthData {
+    AuthData::new(SHOP, "token")
+}
+
+fn breakers() -> CircuitBreakers {
+    CircuitBreakers::new(CircuitBreakerConfig {
+        failure_threshold: 2,
+        open_for: Duration::from_millis(50),
+        probes: 1,
+    })
+}
+
+fn fail(breakers: &CircuitBreakers, times: usize) {
+    for _ in 0..times {
+        breakers.allow(&shop()).unwrap().record(false);
+    }
+}
+
+#[tokio::test]
+async fn opens_after_consecutive_failures() {
+    let breakers = breakers();
+
+    fail(&breakers, 1);
+    breakers.allow(&shop()).unwrap().record(true);
+    fail(&breakers, 1);
+    assert_eq!(breakers.state(SHOP), BreakerState::Closed);
+
+    fail(&breakers, 1);
+    assert_eq!(breakers.state(SHOP), BreakerState::Open);
+    let error = breakers.allow(&shop()).err().unwrap();
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()["retry-after"], "1");
+    assert_eq!(breakers.state("https://other.example.com/graphql/"), BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn probes_close_or_reopen_the_breaker() {
+    let breakers = breakers();
+    fail(&breakers, 2);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(breakers.state(SHOP), BreakerState::HalfOpen);
+
+    let probe = breakers.allow(&shop()).unwrap();
+    assert!(breakers.allow(&shop()).is_err(), "only one probe at a time");
+    probe.record(false);
+    assert_eq!(breakers.state(SHOP), BreakerState::Open);
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    // cancelled probes don't count
+    drop(breakers.allow(&shop()).unwrap());
+    assert_eq!(breakers.state(SHOP), BreakerState::HalfOpen);
+    breakers.allow(&shop()).unwrap().record(true);
+    assert_eq!(breakers.state(SHOP), BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn jwks_fetches_stop_while_open() {
+    let saleor = MockSaleor::start().await;
+    let origin = AuthData::new(format!("http://{}", saleor.domain()), "token");
+    saleor.set_jwks_down(true);
+    let cache = JwksCache::with_fetch_config(JwksFetchConfig {
+        retries: 10,
+        backoff: Duration::from_millis(1),
+        ttl: Duration::ZERO,
+        grace_period: Duration::from_secs(60),
+        retry_after: Duration::from_secs(1),
+    });
+
+    let client = HttpClient::default();
+    let error = cache.fetch(&client, &origin).await.err().unwrap();
+    assert_eq!(client.circuit_breakers().state(&origin.saleor_api_url), BreakerState::Open);
+    let JwksFetchError::Unavailable { retry_after } = error else {
+        panic!("{error:?}");
+    };
+    assert!(retry_after > Duration::from_secs(1), "waits for the breaker, not {retry_after:?}");
+}
+
+#[tokio::test]
+async fn breakers_are_dropped_once_closed() {
+    let breakers = breakers();
+    fail(&breakers, 1);
+    assert_eq!(breakers.tracked(), 1);
+
+    breakers.allow(&shop()).unwrap().record(true);
+    assert_eq!(breakers.tracked(), 0);
+}
+
+#[tokio::test]
+async fn unknown_installations_get_no_breaker() {
+    let app = TestApp::with_config(AppConfig::default()).await;
+    let request = Request::get("/api/hello")
+        .header("saleor-api-url", "https://unknown.example.com/graphql/")
+        .header("authorization", "Bearer token")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.request(request).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.text().starts_with("unknown installation"), "{}", response.text());
+    assert_eq!(app.http_client.circuit_breakers().tracked(), 0);
+}
@@ -0,0 +1,101 @@
+use std::{collections::HashMap, time::Duration};
+
+use axum::http::StatusCode;
+use saleor_app::{
+    concurrency::{ConcurrencyLimits, TenantConcurrency, Workload, MAX_CONCURRENT_GRAPHQL_SETTING, MAX_CONCURRENT_WEBHOOKS_SETTING},
+    testing::TestApp,
+    webhooks::PRODUCT_UPDATED_PATH,
+};
+use serde_json::json;
+use tokio::time::timeout;
+
+const SHOP: &str = "https://shop.example.com/graphql/";
+const OTHER_SHOP: &str = "https://other.example.com/graphql/";
+
+#[tokio::test]
+async fn busy_installations_do_not_block_others() {
+    let concurrency = TenantConcurrency::new(ConcurrencyLimits { graphql: 1, webhooks: 1 });
+
+    let permit = concurrency.acquire(SHOP, Workload::Graphql).await;
+    assert!(timeout(Duration::from_millis(50), concurrency.acquire(SHOP, Workload::Graphql)).await.is_err());
+    assert!(timeout(Duration::from_millis(50), concurrency.acquire(OTHER_SHOP, Workload::Graphql)).await.is_ok());
+    assert!(timeout(Duration::from_millis(50), concurrency.acquire(SHOP, Workload::Webhooks)).await.is_ok());
+
+    drop(permit);
+    assert!(timeout(Duration::from_millis(50), concurrency.acquire(SHOP, Workload::Graphql)).await.is_ok());
+}
+
+#[tokio::test]
+async fn waiting_work_is_counted() {
+    let concurrency = TenantConcurrency::new(ConcurrencyLimits { graphql: 1, webhooks: 1 });
+    let permit = concurrency.acquire(SHOP, Workload::Webhooks).await;
+
+    let waiting = tokio::spawn({
+        let concurrency = concurrency.clone();
+        async move { concurrency.acquire(SHOP, Workload::Webhooks).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(concurrency.waiting(SHOP, Workload::Webhooks), 1);
+
+    drop(permit);
+    let _permit = waiting.await.unwrap();
+    assert_eq!(concurrency.waiting(SHOP, Workload::Webhooks), 0);
+}
+
+#[tokio::test]
+async fn settings_override_limits() {
+    let concurrency = TenantConcurrency::new(ConcurrencyLimits { graphql: 1, webhooks: 4 });
+
+    concurrency.apply_settings(SHOP, &HashMap::from([
+        (MAX_CONCURRENT_GRAPHQL_SETTING.to_string(), "2".to_string()),
+        (MAX_CONCURRENT_WEBHOOKS_SETTING.to_string(), "many".to_string()),
+    ]));
+    assert_eq!(concurrency.limit(SHOP, Workload::Graphql), 2);
+    assert_eq!(concurrency.limit(SHOP, Workload::Webhooks), 4);
+    assert_eq!(concurrency.limit(OTHER_SHOP, Workload::Graphql), 1);
+    let _first = concurrency.acquire(SHOP, Workload::Graphql).await;
+    assert!(timeout(Duration::from_millis(50), concurrency.acquire(SHOP, Workload::Graphql)).await.is_ok());
+
+    concurrency.apply_settings(SHOP, &HashMap::new());
+    assert_eq!(concurrency.limit(SHOP, Workload::Graphql), 1);
+}
+
+#[tokio::test]
+async fn webhooks_read_the_limits_of_their_installation() {
+    let app = TestApp::new().await;
+    app.saleor.respond_to("privateMetadata", json!({ "app": { "id": "QXBwOjE=", "privateMetadata": [
+        { "key": MAX_CONCURRENT_GRAPHQL_SETTING, "value": "3" },
+    ] } }));
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+    timeout(Duration::from_secs(5), async {
+        while app.http_client.concurrency().limit(&app.saleor.api_url(), Workload::Graphql) != 3 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn budgets_are_dropped_once_idle() {
+    let concurrency = TenantConcurrency::new(ConcurrencyLimits { graphql: 1, webhooks: 1 });
+    let permit = concurrency.acquire(SHOP, Workload::Graphql).await;
+    let waiting = tokio::spawn({
+        let concurrency = concurrency.clone();
+        async move { concurrency.acquire(SHOP, Workload::Graphql).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(concurrency.tracked(), 1);
+
+    drop(permit);
+    assert_eq!(concurrency.tracked(), 1, "the waiting work still uses it");
+    drop(waiting.await.unwrap());
+    assert_eq!(concurrency.tracked(), 0);
+    // work that gave up waiting counts too
+    let permit = concurrency.acquire(OTHER_SHOP, Workload::Graphql).await;
+    assert!(timeout(Duration::from_millis(20), concurrency.acquire(OTHER_SHOP, Workload::Graphql)).await.is_err());
+    drop(permit);
+    assert_eq!(concurrency.tracked(), 0);
+}
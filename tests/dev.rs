@@ -0,0 +1,30 @@
+#![cfg(feature = "dev")]
+
+use std::time::Duration;
+
+use axum::{body::Body, http::{Request, StatusCode}};
+use hyper::body::HttpBody;
+use saleor_app::dev::DevReload;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn pages_reload_when_assets_change() {
+    let assets_dir = std::env::temp_dir().join(format!("assets-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&assets_dir).unwrap();
+    std::fs::write(assets_dir.join("main.css"), "body { color: teal; }").unwrap();
+    let router = DevReload::watch(vec![assets_dir.clone()]).router();
+
+    let response = router.oneshot(Request::get("/dev/reload").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut events = response.into_body();
+    let event = |chunk: Option<Result<axum::body::Bytes, axum::Error>>| String::from_utf8(chunk.unwrap().unwrap().to_vec()).unwrap();
+    let hello = event(events.data().await);
+    assert!(hello.starts_with("event:hello\n"), "{hello:?}");
+
+    std::fs::write(assets_dir.join("extra.css"), "p { color: plum; }").unwrap();
+    let reload = tokio::time::timeout(Duration::from_secs(5), events.data()).await.expect("no reload event");
+    assert!(event(reload).starts_with("event:reload\n"));
+
+    std::fs::remove_dir_all(assets_dir).unwrap();
+}
@@ -0,0 +1,101 @@
+use axum::http::StatusCode;
+use saleor_app::{config::AppConfig, doctor::{self, DiagnosticStatus}, http_client::HttpClient, saleor::{AuthData, REQUIRED_SCHEMA_FIELDS}, sessions::{SessionConfig, SessionCookie}, testing::{AplBehavior, AplCall, MockAplStore, MockSaleor, TestApp}};
+use tower_sessions::cookie::SameSite;
+use serde_json::{json, Map, Value};
+
+fn auth_data(saleor: &MockSaleor) -> AuthData {
+    AuthData {
+        domain: Some(saleor.domain()),
+        ..AuthData::new(saleor.api_url(), "app-token")
+    }
+}
+
+/// The introspection response of a schema with every required field, except `skip`.
+fn introspection(skip: &str) -> Value {
+    let types = REQUIRED_SCHEMA_FIELDS
+        .iter()
+        .enumerate()
+        .map(|(i, (type_name, fields))| {
+            let fields = fields
+                .iter()
+                .filter(|field| format!("{}.{}", type_name, field) != skip)
+                .map(|field| json!({ "name": field }))
+                .collect::<Vec<_>>();
+            (format!("t{}", i), json!({ "fields": fields }))
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(types)
+}
+
+#[tokio::test]
+async fn invalid_config_fails() {
+    let config = AppConfig { app_url: Some("ftp://app.example.com".to_string()), ..AppConfig::default() };
+
+    let diagnostic = doctor::check_config(&config);
+    assert_eq!(diagnostic.status, DiagnosticStatus::Fail);
+    assert!(diagnostic.message.unwrap().contains("APP_URL"));
+}
+
+#[test]
+fn session_cookies_have_to_fit_the_app_url() {
+    let config = |app_url: &str, cookie: SessionCookie| AppConfig {
+        app_url: Some(app_url.to_string()),
+        sessions: SessionConfig { cookie, ..Default::default() },
+        ..AppConfig::default()
+    };
+    assert!(config("https://app.example.com", SessionCookie::production()).validate().is_empty());
+    assert!(config("http://localhost:8008", SessionCookie::production()).validate().is_empty());
+    assert!(config("http://app.local:8008", SessionCookie::development()).validate().is_empty());
+
+    let problems = config("http://app.local:8008", SessionCookie::production()).validate();
+    assert!(problems.iter().any(|problem| problem.contains("SESSION_COOKIE_SECURE=true requires an https APP_URL")), "{problems:?}");
+    let insecure = SessionCookie { same_site: SameSite::None, ..SessionCookie::development() };
+    let problems = config("http://localhost:8008", insecure).validate();
+    assert!(problems.iter().any(|problem| problem.contains("SESSION_COOKIE_SAME_SITE=none")), "{problems:?}");
+}
+
+#[tokio::test]
+async fn unreachable_apl_fails() {
+    let apl = MockAplStore::new();
+    apl.script(AplCall::Health, AplBehavior::Error("connection refused".to_string()));
+
+    let report = doctor::startup_checks(&AppConfig::default(), &apl).await;
+    assert!(report.has_failures());
+    assert!(report.problems().any(|d| d.name == "apl"));
+}
+
+#[tokio::test]
+async fn compatible_schema_passes() {
+    let saleor = MockSaleor::start().await;
+    saleor.respond_to("SchemaCheck", introspection(""));
+
+    let diagnostic = doctor::check_schema(&HttpClient::default(), &auth_data(&saleor)).await;
+    assert_eq!(diagnostic.status, DiagnosticStatus::Pass, "{}", diagnostic);
+}
+
+#[tokio::test]
+async fn missing_schema_fields_are_listed() {
+    let saleor = MockSaleor::start().await;
+    saleor.respond_to("SchemaCheck", introspection("App.privateMetadata"));
+
+    let diagnostic = doctor::check_schema(&HttpClient::default(), &auth_data(&saleor)).await;
+    assert_eq!(diagnostic.status, DiagnosticStatus::Fail);
+    assert_eq!(diagnostic.message.unwrap(), "missing App.privateMetadata");
+}
+
+#[tokio::test]
+async fn unreachable_base_url_fails() {
+    // nothing listens on the discard port
+    let diagnostics = doctor::check_base_url(&reqwest::Client::new(), "http://127.0.0.1:9").await;
+    assert!(diagnostics.iter().any(|d| d.name == "base url" && d.status == DiagnosticStatus::Fail), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn readiness_does_not_name_config_problems() {
+    let config = AppConfig { app_url: Some("ftp://app.example.com".to_string()), ..AppConfig::default() };
+    let app = TestApp::unregistered(config).await;
+
+    let response = app.get("/readyz").await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(!response.text().contains("APP_URL"), "{}", response.text());
+}
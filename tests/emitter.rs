@@ -0,0 +1,156 @@
+use std::{net::TcpListener, sync::{Arc, Mutex}, time::Duration};
+
+use async_trait::async_trait;
+use axum::{body::Bytes, extract::State, http::{HeaderMap, StatusCode}, routing::post, Router};
+use saleor_app::{
+    emitter::{self, ChannelSink, EmitError, EmittedEvent, EventEmitter, EventSink, HttpSink, EMIT_EVENT_HEADER, EMIT_SIGNATURE_HEADER},
+    testing::TestApp,
+    webhooks::PRODUCT_UPDATED_PATH,
+};
+use serde_json::json;
+use tokio::{sync::mpsc, time::timeout};
+
+#[tokio::test]
+async fn webhooks_are_emitted() {
+    let app = TestApp::new().await;
+    let (sink, mut events) = ChannelSink::new();
+    app.emitter.add_sink(sink);
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let event = timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    assert_eq!(event.name, "productUpdated");
+    assert_eq!(event.saleor_api_url, app.saleor.api_url());
+    assert!(event.data["id"].is_string());
+}
+
+#[tokio::test]
+async fn http_sink_signs_events() {
+    let (received, mut requests) = mpsc::unbounded_channel::<(HeaderMap, Bytes)>();
+    let router = Router::new()
+        .route("/events", post(|State(received): State<mpsc::UnboundedSender<(HeaderMap, Bytes)>>, headers: HeaderMap, body: Bytes| async move {
+            received.send((headers, body)).unwrap();
+            StatusCode::NO_CONTENT
+        }))
+        .with_state(received);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/events", listener.local_addr().unwrap());
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(router.into_make_service()));
+
+    let emitter = EventEmitter::new().with_sink(HttpSink::new(reqwest::Client::new(), url, "secret"));
+    emitter.emit("https://shop.example.com/graphql/", "orderCreated", json!({ "number": "42" }));
+
+    let (headers, body) = timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+    assert_eq!(headers[EMIT_SIGNATURE_HEADER], emitter::signature("secret", &body).as_str());
+    assert_eq!(headers[EMIT_EVENT_HEADER], "orderCreated");
+    let event: EmittedEvent = serde_json::from_slice(&body).unwrap();
+    assert_eq!(event.data, json!({ "number": "42" }));
+}
+
+/// Fails every other delivery, recording the ones that succeeded.
+#[derive(Default)]
+struct FlakySink {
+    attempts: Mutex<usize>,
+    delivered: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl EventSink for FlakySink {
+    async fn deliver(&self, event: &EmittedEvent) -> Result<(), EmitError> {
+        let mut attempts = self.attempts.lock().unwrap();
+        *attempts += 1;
+        if *attempts % 2 == 1 {
+            return Err(EmitError("unavailable".to_string()));
+        }
+        self.delivered.lock().unwrap().push(event.name.clone());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_in_order() {
+    let sink = FlakySink::default();
+    let delivered = sink.delivered.clone();
+    let emitter = EventEmitter::new().with_retries(3, Duration::from_millis(1)).with_sink(sink);
+
+    let names: Vec<String> = (0..10).map(|i| format!("event{i}")).collect();
+    for name in &names {
+        emitter.emit("https://shop.example.com/graphql/", name, json!({}));
+    }
+
+    timeout(Duration::from_secs(5), async {
+        while delivered.lock().unwrap().len() < names.len() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(*delivered.lock().unwrap(), names);
+}
+
+/// Holds every delivery until it's opened, recording the delivered events.
+struct GatedSink {
+    gate: Arc<tokio::sync::Semaphore>,
+    delivered: Arc<Mutex<Vec<String>>>,
+}
+
+impl GatedSink {
+    fn closed() -> Self {
+        Self { gate: Arc::new(tokio::sync::Semaphore::new(0)), delivered: Arc::default() }
+    }
+}
+
+#[async_trait]
+impl EventSink for GatedSink {
+    async fn deliver(&self, event: &EmittedEvent) -> Result<(), EmitError> {
+        self.gate.acquire().await.unwrap().forget();
+        self.delivered.lock().unwrap().push(event.name.clone());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn events_above_the_queue_capacity_are_dropped() {
+    let sink = GatedSink::closed();
+    let (gate, delivered) = (sink.gate.clone(), sink.delivered.clone());
+    let emitter = EventEmitter::new().with_queue_limits(2, Duration::from_secs(60)).with_sink(sink);
+
+    for i in 0..10 {
+        emitter.emit("https://shop.example.com/graphql/", &format!("event{i}"), json!({}));
+    }
+    gate.add_permits(10);
+    timeout(Duration::from_secs(5), async {
+        while delivered.lock().unwrap().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // the one being delivered and the two waiting
+    let delivered = delivered.lock().unwrap().clone();
+    assert!(delivered.len() <= 3, "{delivered:?}");
+    assert_eq!(delivered[0], "event0");
+}
+
+#[tokio::test]
+async fn idle_queues_are_dropped() {
+    let (sink, mut events) = ChannelSink::new();
+    let emitter = EventEmitter::new().with_queue_limits(10, Duration::from_millis(10)).with_sink(sink);
+
+    emitter.emit("https://shop.example.com/graphql/", "orderCreated", json!({}));
+    timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    timeout(Duration::from_secs(5), async {
+        while emitter.queues() > 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    emitter.emit("https://shop.example.com/graphql/", "orderUpdated", json!({}));
+    let event = timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    assert_eq!(event.name, "orderUpdated");
+}
@@ -0,0 +1,101 @@
+use axum::{
+    body::Body,
+    http::{header::{CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE}, Request, StatusCode},
+};
+use saleor_app::{
+    app::{app_manifest, extension_pages},
+    extensions::NEW_TAB_PATH,
+    saleor::{SaleorAppExtensionMount, SaleorAppExtensionTarget, SaleorPermission},
+    testing::TestApp,
+};
+use serde_json::json;
+
+fn new_tab_request(to: &str, form: &[(&str, &str)]) -> Request<Body> {
+    let body = reqwest::Url::parse_with_params("http://localhost", form).unwrap().query().unwrap().to_string();
+    Request::post(format!("{NEW_TAB_PATH}?to={to}")).header(CONTENT_TYPE, "application/x-www-form-urlencoded").body(Body::from(body)).unwrap()
+}
+
+#[tokio::test]
+async fn document_requests_get_a_shell_loading_the_page() {
+    let app = TestApp::new().await;
+
+    let response = app.get("/app/products/panel?productId=UHJvZHVjdDox&saleorApiUrl=https%3A%2F%2Fshop").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let body = response.text();
+    assert!(body.contains(r#"hx-get="/app/products/panel?productId=UHJvZHVjdDox&amp;saleorApiUrl=https%3A%2F%2Fshop""#), "{body}");
+    assert!(!body.contains("UHJvZHVjdDox</code>"));
+}
+
+#[tokio::test]
+async fn pages_get_the_objects_of_their_mount() {
+    let app = TestApp::new().await;
+    let user = app.as_user(&[SaleorPermission::ManageProducts]);
+
+    let response = user.get("/app/products/panel?productId=UHJvZHVjdDox").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(response.text().contains("<code>UHJvZHVjdDox</code>"));
+
+    let response = user.get("/app/products/bulk?productIds=UHJvZHVjdDox,UHJvZHVjdDoy&productIds=UHJvZHVjdDoz").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let body = response.text();
+    for id in ["UHJvZHVjdDox", "UHJvZHVjdDoy", "UHJvZHVjdDoz"] {
+        assert!(body.contains(&format!("<code>{id}</code>")), "{id} missing from {body}");
+    }
+
+    let response = app.as_user(&[SaleorPermission::ManageOrders]).get("/app/products/panel?productId=UHJvZHVjdDox").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn the_manifest_lists_every_page() {
+    let extensions = app_manifest("https://app.example.com").extensions.unwrap();
+    for page in extension_pages().pages() {
+        let extension = extensions.iter().find(|extension| extension.url == page.manifest_url()).expect("page missing from the manifest");
+        assert_eq!(extension.label, page.label);
+        assert_eq!(extension.permissions, page.permissions);
+    }
+    assert_eq!(extension_pages().pages().find(|page| page.path == "/products/bulk").unwrap().mount, SaleorAppExtensionMount::ProductOverviewMoreActions);
+}
+
+#[tokio::test]
+async fn new_tabs_are_authenticated_through_the_session() {
+    let app = TestApp::new().await;
+    let api_url = app.saleor.api_url();
+    let token = app.saleor.token(&[SaleorPermission::ManageProducts]);
+
+    let form = [("saleorApiUrl", api_url.as_str()), ("accessToken", token.as_str()), ("productId", "UHJvZHVjdDox")];
+    let response = app.request(new_tab_request("/app/products/report", &form)).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER, "{}", response.text());
+    let location = response.headers[LOCATION].to_str().unwrap().to_string();
+    assert!(location.starts_with("/app/products/report?saleorApiUrl="), "{location}");
+    assert!(location.ends_with("&productId=UHJvZHVjdDox") && !location.contains("accessToken"), "{location}");
+    let cookie = response.headers[SET_COOKIE].to_str().unwrap().split(';').next().unwrap().to_string();
+
+    let page = Request::get(&location).header(COOKIE, &cookie).header("hx-request", "true").body(Body::empty()).unwrap();
+    let response = app.request(page).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(response.text().contains("<code>UHJvZHVjdDox</code>"));
+}
+
+#[tokio::test]
+async fn new_tab_handoffs_are_verified() {
+    let app = TestApp::new().await;
+    let api_url = app.saleor.api_url();
+    let token = app.saleor.token(&[SaleorPermission::ManageProducts]);
+
+    let forged = [("saleorApiUrl", api_url.as_str()), ("accessToken", "forged")];
+    assert_eq!(app.request(new_tab_request("/app/products/report", &forged)).await.status, StatusCode::UNAUTHORIZED);
+    let form = [("saleorApiUrl", api_url.as_str()), ("accessToken", token.as_str())];
+    for to in ["/app/products/panel", "https://evil.example.com", "//evil.example.com"] {
+        assert_eq!(app.request(new_tab_request(to, &form)).await.status, StatusCode::BAD_REQUEST, "{to}");
+    }
+    assert_eq!(app.request(new_tab_request("/app/products/report", &form[..1])).await.status, StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn new_tab_pages_are_declared_with_the_handoff() {
+    let extensions = app_manifest("https://app.example.com").extensions.unwrap();
+    let extension = extensions.iter().find(|extension| extension.target == SaleorAppExtensionTarget::NewTab).unwrap();
+    assert_eq!(extension.url, "/app/new-tab?to=%2Fapp%2Fproducts%2Freport");
+    assert_eq!(serde_json::to_value(extension).unwrap()["options"], json!({ "newTabTarget": { "method": "POST" } }));
+}
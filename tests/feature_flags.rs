@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use axum::{body::Body, http::{header::CONTENT_TYPE, Request, StatusCode}};
+use saleor_app::{
+    config::AppConfig,
+    feature_flags::{FeatureFlags, InstallationFeatureFlags},
+    saleor::SaleorPermission,
+    testing::{TestApp, TestResponse},
+};
+use serde_json::{json, Value};
+
+async fn admin_app() -> TestApp {
+    let app = TestApp::with_saleor_config(|saleor| AppConfig {
+        admin_api_urls: vec![saleor.api_url()],
+        ..Default::default()
+    })
+    .await;
+    app.saleor.respond_to("privateMetadata", json!({ "app": { "id": "QXBwOjE=", "privateMetadata": [
+        { "key": "feature_flag.new_checkout", "value": "true" },
+        { "key": "feature_flag.retired", "value": "" },
+        { "key": "notification_email", "value": "shop@example.com" },
+    ] } }));
+    app.saleor.respond_to("updatePrivateMetadata", json!({ "updatePrivateMetadata": { "errors": [] } }));
+    app
+}
+
+async fn put_flag(app: &TestApp, body: Value) -> TestResponse {
+    let request = Request::put("/api/admin/feature-flags")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    app.as_user(&[SaleorPermission::ManageApps]).request(request).await
+}
+
+#[test]
+fn flags_are_read_from_settings() {
+    let flags = FeatureFlags::from_settings(HashMap::from([
+        ("feature_flag.new_checkout".to_string(), "On".to_string()),
+        ("feature_flag.theme".to_string(), "dark".to_string()),
+        ("feature_flag.retired".to_string(), String::new()),
+        ("notification_email".to_string(), "shop@example.com".to_string()),
+    ]));
+
+    assert!(flags.enabled("new_checkout"));
+    assert!(!flags.enabled("theme"));
+    assert_eq!(flags.value("theme"), Some("dark"));
+    assert!(!flags.enabled("retired"));
+    assert!(!flags.enabled("unknown"));
+    assert_eq!(flags.all().len(), 2);
+}
+
+#[tokio::test]
+async fn operators_toggle_flags() {
+    let app = admin_app().await;
+
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get(&format!("/api/admin/feature-flags?saleorApiUrl={}", app.saleor.api_url())).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let flags: InstallationFeatureFlags = response.json();
+    assert_eq!(flags.flags.into_iter().collect::<Vec<_>>(), [("new_checkout".to_string(), "true".to_string())]);
+
+    let response = put_flag(&app, json!({ "saleorApiUrl": app.saleor.api_url(), "name": "new_checkout", "value": "false" })).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let update = app.saleor.requests().into_iter().find(|request| request["query"].as_str().unwrap().contains("updatePrivateMetadata")).unwrap();
+    assert_eq!(update["variables"]["input"], json!([{ "key": "feature_flag.new_checkout", "value": "false" }]));
+}
+
+#[tokio::test]
+async fn flags_need_a_valid_name_and_installation() {
+    let app = admin_app().await;
+
+    let response = put_flag(&app, json!({ "saleorApiUrl": app.saleor.api_url(), "name": "new checkout", "value": "true" })).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let response = put_flag(&app, json!({ "saleorApiUrl": "https://other.saleor.cloud/graphql/", "name": "new_checkout", "value": "true" })).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get(&format!("/api/admin/feature-flags?saleorApiUrl={}", app.saleor.api_url())).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
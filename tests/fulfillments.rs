@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use saleor_app::{
+    config::AppConfig,
+    http_client::HttpClient,
+    saleor::{fulfillments::{self, *}, AuthData},
+    testing::{webhook_fixture, TestApp},
+};
+use serde_json::json;
+
+/// Ships every fulfillment with the same tracking number, remembering the events it got.
+#[derive(Clone, Default)]
+struct Carrier {
+    events: Arc<Mutex<Vec<(String, FulfillmentEvent)>>>,
+}
+
+#[async_trait]
+impl FulfillmentTracker for Carrier {
+    async fn fulfillment_created(&self, auth_data: &AuthData, event: FulfillmentEvent) -> Result<(), FulfillmentError> {
+        self.events.lock().unwrap().push(("created".to_string(), event.clone()));
+        let fulfillment = event.fulfillment.ok_or_else(|| FulfillmentError("no fulfillment".to_string()))?;
+        update_tracking(&HttpClient::default(), auth_data, &fulfillment.id, "JD014600006281230703", true).await
+    }
+
+    async fn tracking_number_updated(&self, _auth_data: &AuthData, event: FulfillmentEvent) -> Result<(), FulfillmentError> {
+        self.events.lock().unwrap().push(("updated".to_string(), event));
+        Ok(())
+    }
+}
+
+async fn fulfillments_app(carrier: Carrier) -> TestApp {
+    TestApp::with_routes(AppConfig::default(), fulfillments::router(carrier)).await
+}
+
+#[tokio::test]
+async fn new_fulfillments_get_a_tracking_number() {
+    let carrier = Carrier::default();
+    let app = fulfillments_app(carrier.clone()).await;
+    app.saleor.respond_to("orderFulfillmentUpdateTracking", json!({ "orderFulfillmentUpdateTracking": { "errors": [] } }));
+
+    let response = app.deliver_webhook_fixture(FULFILLMENT_CREATED_PATH, "fulfillment_created", "fulfillment_created").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    let events = carrier.events.lock().unwrap();
+    let (kind, event) = &events[0];
+    assert_eq!(kind, "created");
+    let fulfillment = event.fulfillment.as_ref().unwrap();
+    assert_eq!(fulfillment.status, FulfillmentStatus::Fulfilled);
+    assert_eq!(fulfillment.tracking_number(), None);
+    assert_eq!(fulfillment.lines[0].order_line.as_ref().unwrap().product_sku.as_deref(), Some("mono-tee-m"));
+    assert_eq!(event.order.as_ref().unwrap().shipping_address.as_ref().unwrap().country.code, "GB");
+
+    let requests = app.saleor.requests();
+    let update = requests.iter().find(|request| request["query"].as_str().unwrap().contains("orderFulfillmentUpdateTracking")).unwrap();
+    assert_eq!(update["variables"], json!({
+        "id": "RnVsZmlsbG1lbnQ6Mw==",
+        "input": { "trackingNumber": "JD014600006281230703", "notifyCustomer": true },
+    }));
+}
+
+#[tokio::test]
+async fn rejected_tracking_numbers_are_retried() {
+    let app = fulfillments_app(Carrier::default()).await;
+    app.saleor.respond_to("orderFulfillmentUpdateTracking", json!({
+        "orderFulfillmentUpdateTracking": { "errors": [{ "field": "id", "message": "Couldn't resolve to a node" }] },
+    }));
+
+    let response = app.deliver_webhook_fixture(FULFILLMENT_CREATED_PATH, "fulfillment_created", "fulfillment_created").await;
+    assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn tracking_number_updates_reach_the_tracker() {
+    let carrier = Carrier::default();
+    let app = fulfillments_app(carrier.clone()).await;
+    let mut payload = webhook_fixture("fulfillment_created");
+    payload["fulfillment"]["trackingNumber"] = json!("JD014600006281230703");
+
+    let response = app.deliver_webhook(FULFILLMENT_TRACKING_NUMBER_UPDATED_PATH, "fulfillment_tracking_number_updated", &payload).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    let events = carrier.events.lock().unwrap();
+    assert_eq!(events[0].0, "updated");
+    assert_eq!(events[0].1.fulfillment.as_ref().unwrap().tracking_number(), Some("JD014600006281230703"));
+    assert!(app.saleor.requests().is_empty());
+
+    let manifest = fulfillments::manifest("https://app.example.com");
+    assert_eq!(manifest[1].target_url, "https://app.example.com/api/webhooks/fulfillments/tracking-number-updated");
+}
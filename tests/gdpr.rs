@@ -0,0 +1,88 @@
+use std::{path::PathBuf, time::Duration};
+
+use axum::http::StatusCode;
+use saleor_app::{
+    audit::{AuditEvent, AuditEventKind, AuditSink, FileAuditSink},
+    config::AppConfig,
+    gdpr::ErasureReport,
+    saleor::SaleorPermission,
+    testing::TestApp,
+};
+use serde_json::{json, Value};
+
+const CUSTOMER: &str = "ada@example.com";
+
+fn audit_log_file() -> PathBuf {
+    std::env::temp_dir().join(format!("audit-{}.jsonl", uuid::Uuid::new_v4()))
+}
+
+/// A registered app whose audit log and settings mention the customer.
+async fn app_with_customer(path: &PathBuf) -> TestApp {
+    let app = TestApp::with_config(AppConfig { audit_log_file: Some(path.clone()), ..Default::default() }).await;
+    let sink = FileAuditSink::new(path);
+    let api_url = app.saleor.api_url();
+    sink.record(&AuditEvent::new(&api_url, AuditEventKind::PermissionDenied).with_detail("Ada@Example.com tried to open the settings")).await;
+    sink.record(&AuditEvent::new(&api_url, AuditEventKind::PermissionDenied).with_detail("grace@example.com tried to open the settings")).await;
+    sink.record(&AuditEvent::new("https://other.saleor.cloud/graphql/", AuditEventKind::PermissionDenied).with_detail(CUSTOMER)).await;
+
+    app.saleor.respond_to("privateMetadata", json!({ "app": { "id": "QXBwOjE=", "privateMetadata": [
+        { "key": "email_from", "value": "shop@example.com" },
+        { "key": "email_bcc", "value": "ada@example.com" },
+    ] } }));
+    app.saleor.respond_to("updatePrivateMetadata", json!({ "updatePrivateMetadata": { "errors": [] } }));
+    app
+}
+
+#[tokio::test]
+async fn exports_everything_mentioning_the_customer() {
+    let path = audit_log_file();
+    let app = app_with_customer(&path).await;
+
+    let response = app.as_user(&[SaleorPermission::ManageApps, SaleorPermission::ManageUsers]).get("/api/personal-data?email=%20ADA@example.com").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let export: Value = response.json();
+    assert_eq!(export["email"], CUSTOMER);
+    assert_eq!(export["installation"]["saleorApiUrl"], app.saleor.api_url());
+    assert!(export["installation"].get("token").is_none());
+    assert_eq!(export["sources"]["settings"], json!([{ "key": "email_bcc", "value": CUSTOMER }]));
+    let events = export["sources"]["auditLog"].as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["detail"], "Ada@Example.com tried to open the settings");
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn erasing_empties_settings_and_redacts_the_audit_log() {
+    let path = audit_log_file();
+    let app = app_with_customer(&path).await;
+
+    let response = app.as_user(&[SaleorPermission::ManageApps, SaleorPermission::ManageUsers]).delete(&format!("/api/personal-data?email={CUSTOMER}")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let report: ErasureReport = response.json();
+    assert_eq!(report.erased["settings"], 1);
+    assert_eq!(report.erased["auditLog"], 1);
+
+    let update = app.saleor.requests().into_iter().find(|request| request["query"].as_str().unwrap().contains("updatePrivateMetadata")).unwrap();
+    assert_eq!(update["variables"]["input"], json!([{ "key": "email_bcc", "value": "" }]));
+
+    // the erasure is audited in the background, without the email
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert!(log.contains("[redacted] tried to open the settings"));
+    assert!(log.contains("grace@example.com"));
+    assert!(log.contains("\"detail\":\"ada@example.com\""), "other installations keep their events");
+    assert!(log.contains("PERSONAL_DATA_ERASED"));
+    assert_eq!(log.to_lowercase().matches(CUSTOMER).count(), 1);
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn requests_need_customer_permissions_and_an_email() {
+    let app = TestApp::new().await;
+
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/personal-data?email=ada@example.com").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    let response = app.as_user(&[SaleorPermission::ManageApps, SaleorPermission::ManageUsers]).delete("/api/personal-data?email=ada").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
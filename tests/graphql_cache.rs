@@ -0,0 +1,112 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use axum::{body::Body, http::{Request, StatusCode}, routing::post, Extension, Router};
+use saleor_app::{
+    config::AppConfig,
+    graphql_cache::{GraphqlCache, GraphqlCacheBackend, GraphqlCacheConfig, GraphqlCacheInvalidationLayer, GraphqlCacheStore, MemoryGraphqlCacheStore},
+    saleor::{SaleorAsyncWebhookEvent, SaleorPermission},
+    testing::TestApp,
+};
+use serde_json::json;
+use tower::ServiceExt;
+
+const SHOP: &str = "https://shop.example.com/graphql/";
+
+async fn fetch(cache: &GraphqlCache, variables: serde_json::Value, queries: &AtomicUsize) -> String {
+    let result: Result<String, String> = cache
+        .fetch(SHOP, "Products", &variables, async {
+            let query = queries.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("answer {query}"))
+        })
+        .await;
+    result.unwrap()
+}
+
+#[tokio::test]
+async fn answers_are_cached_per_variables_until_invalidated() {
+    let cache = GraphqlCache::new(MemoryGraphqlCacheStore::new(), Duration::from_secs(60));
+    let queries = AtomicUsize::new(0);
+
+    assert_eq!(fetch(&cache, json!({ "first": 10 }), &queries).await, "answer 0");
+    assert_eq!(fetch(&cache, json!({ "first": 10 }), &queries).await, "answer 0");
+    assert_eq!(fetch(&cache, json!({ "first": 20 }), &queries).await, "answer 1");
+
+    cache.invalidate(SHOP, "Products").await;
+    assert_eq!(fetch(&cache, json!({ "first": 10 }), &queries).await, "answer 2");
+    assert_eq!(fetch(&cache, json!({ "first": 20 }), &queries).await, "answer 3");
+
+    let failed: Result<String, String> = cache.fetch(SHOP, "Products", &json!(null), async { Err("unavailable".to_string()) }).await;
+    assert!(failed.is_err());
+    assert_eq!(fetch(&cache, json!(null), &queries).await, "answer 4");
+}
+
+#[tokio::test]
+async fn removed_installations_lose_their_answers() {
+    const OTHER_SHOP: &str = "https://other.example.com/graphql/";
+    let store = MemoryGraphqlCacheStore::new();
+    let cache = GraphqlCache::new(store.clone(), Duration::from_secs(60));
+    let queries = AtomicUsize::new(0);
+    assert_eq!(fetch(&cache, json!(null), &queries).await, "answer 0");
+    store.set(OTHER_SHOP, "Products", "null", "\"other answer\"".to_string(), Duration::from_secs(60)).await.unwrap();
+
+    cache.remove_tenant(SHOP).await;
+    assert_eq!(fetch(&cache, json!(null), &queries).await, "answer 1");
+    assert_eq!(store.get(OTHER_SHOP, "Products", "null").await.unwrap().as_deref(), Some("\"other answer\""));
+}
+
+#[tokio::test]
+async fn expired_and_disabled_caches_query_every_time() {
+    let queries = AtomicUsize::new(0);
+    let expired = GraphqlCache::new(MemoryGraphqlCacheStore::new(), Duration::ZERO);
+    assert_eq!(fetch(&expired, json!(null), &queries).await, "answer 0");
+    assert_eq!(fetch(&expired, json!(null), &queries).await, "answer 1");
+
+    let disabled = GraphqlCache::disabled();
+    assert!(!disabled.is_enabled());
+    assert_eq!(fetch(&disabled, json!(null), &queries).await, "answer 2");
+    assert_eq!(fetch(&disabled, json!(null), &queries).await, "answer 3");
+}
+
+#[tokio::test]
+async fn handled_webhooks_invalidate_their_operations() {
+    let cache = GraphqlCache::new(MemoryGraphqlCacheStore::new(), Duration::from_secs(60))
+        .with_invalidation(SaleorAsyncWebhookEvent::ProductUpdated, "Products");
+    let router = Router::new()
+        .route("/ok", post(|| async { StatusCode::OK }))
+        .route("/failing", post(|| async { StatusCode::BAD_REQUEST }))
+        .layer(GraphqlCacheInvalidationLayer)
+        .layer(Extension(cache.clone()));
+    let webhook = |path: &str, event: &str| {
+        Request::post(path).header("saleor-event", event).header("saleor-api-url", SHOP).body(Body::empty()).unwrap()
+    };
+    let queries = AtomicUsize::new(0);
+    assert_eq!(fetch(&cache, json!(null), &queries).await, "answer 0");
+
+    router.clone().oneshot(webhook("/failing", "product_updated")).await.unwrap();
+    router.clone().oneshot(webhook("/ok", "order_created")).await.unwrap();
+    assert_eq!(fetch(&cache, json!(null), &queries).await, "answer 0");
+
+    router.oneshot(webhook("/ok", "product_updated")).await.unwrap();
+    assert_eq!(fetch(&cache, json!(null), &queries).await, "answer 1");
+}
+
+#[tokio::test]
+async fn settings_are_read_through_the_cache() {
+    let config = AppConfig {
+        graphql_cache: GraphqlCacheConfig { backend: GraphqlCacheBackend::Memory, ..Default::default() },
+        ..Default::default()
+    };
+    let app = TestApp::with_config(config).await;
+    app.saleor.respond_to("privateMetadata", json!({ "app": { "id": "QXBwOjE=", "privateMetadata": [] } }));
+    let user = app.as_user(&[SaleorPermission::ManageProducts]);
+
+    for _ in 0..2 {
+        let response = user.get("/api/config").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    }
+    let reads = app.saleor.requests().into_iter().filter(|request| request["query"].as_str().unwrap().contains("privateMetadata")).count();
+    assert_eq!(reads, 1);
+}
@@ -0,0 +1,96 @@
+use std::{io::Write, sync::{Arc, Mutex}};
+
+use axum::{body::Body, http::{Request, StatusCode}, routing::get, Json, Router};
+use saleor_app::{
+    config::AppConfig,
+    saleor::{GraphqlDebugConfig, SaleorAuthLayer, SaleorPermission, UserClient, UserTokenError},
+    testing::TestApp,
+};
+use serde_json::{json, Value};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// The JSON lines logged while it's the default subscriber.
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn lines(&self) -> Vec<Value> {
+        let logs = self.0.lock().unwrap();
+        String::from_utf8_lossy(&logs).lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+async fn create_customer(client: UserClient) -> Result<Json<Value>, UserTokenError> {
+    let variables = json!({ "input": { "email": "jane@example.com", "password": "hunter22", "metadata": [{ "key": "apiKey", "value": "sk_live" }] } });
+    client
+        .query("CreateCustomer", "mutation CreateCustomer($input: UserCreateInput!) { customerCreate(input: $input) { errors { field message } } }", variables)
+        .await
+        .map(Json)
+}
+
+#[tokio::test]
+async fn calls_are_logged_with_redacted_variables() {
+    let routes = Router::new().route("/api/customers", get(create_customer)).route_layer(SaleorAuthLayer::with_permissions(&[]));
+    let graphql_debug = GraphqlDebugConfig { enabled: true, redacted_fields: vec!["email".to_string()] };
+    let app = TestApp::with_routes(AppConfig { graphql_debug, ..Default::default() }, routes).await;
+    app.saleor.respond_to("customerCreate", json!({ "customerCreate": { "errors": [{ "field": "password", "message": "too short" }] } }));
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt().json().with_writer(logs.clone()).finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let response = app.as_user(&[]).get("/api/customers").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    let lines = logs.lines();
+    let call = lines.iter().find(|line| line["fields"]["operation"] == "CreateCustomer").expect("the call was logged");
+    let variables: Value = serde_json::from_str(call["fields"]["variables"].as_str().unwrap()).unwrap();
+    assert_eq!(variables, json!({ "input": { "email": "[redacted]", "password": "[redacted]", "metadata": [{ "key": "apiKey", "value": "sk_live" }] } }));
+    assert!(call["fields"]["duration_ms"].is_u64());
+    assert!(call["fields"]["mutation_errors"].as_str().unwrap().contains("too short"));
+    let logged = serde_json::to_string(&lines).unwrap();
+    assert!(!logged.contains("hunter22"));
+    assert!(!logged.contains("Bearer"));
+}
+
+#[tokio::test]
+async fn cynic_operations_are_logged() {
+    let graphql_debug = GraphqlDebugConfig { enabled: true, redacted_fields: vec![] };
+    let app = TestApp::with_config(AppConfig { graphql_debug, ..Default::default() }).await;
+    app.saleor.respond_to("privateMetadata", json!({ "app": { "id": "QXBwOjE=", "privateMetadata": [] } }));
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt().json().with_writer(logs.clone()).finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/config").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let token = app.saleor.token(&[SaleorPermission::ManageProducts]);
+    let auth = Request::post("/api/auth")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "api_url": app.saleor.api_url(), "token": token }).to_string()))
+        .unwrap();
+    assert_eq!(app.request(auth).await.status, StatusCode::OK);
+
+    let lines = logs.lines();
+    for operation in ["AppPrivateMetadata", "MyId"] {
+        assert!(lines.iter().any(|line| line["fields"]["operation"] == operation), "{operation} wasn't logged");
+    }
+}
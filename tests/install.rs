@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use saleor_app::{http_client::HttpClient, saleor::{AppInstaller, JobStatus, SaleorAppPermission, StaffCredentials}, testing::MockSaleor};
+use serde_json::json;
+
+fn installer(saleor: &MockSaleor) -> AppInstaller {
+    let mut installer = AppInstaller::new(HttpClient::default(), &saleor.api_url());
+    installer.poll_interval = Duration::from_millis(10);
+    installer.timeout = Duration::from_secs(1);
+    installer
+}
+
+fn credentials() -> StaffCredentials {
+    StaffCredentials::Password { email: "admin@example.com".to_string(), password: "admin".to_string() }
+}
+
+#[tokio::test]
+async fn installation_waits_for_pending_job() {
+    let saleor = MockSaleor::start().await;
+    saleor.respond_to("tokenCreate", json!({ "tokenCreate": { "token": "staff-token", "errors": [] } }));
+    saleor.respond_to("appInstall", json!({
+        "appInstall": { "appInstallation": { "id": "QXBwSW5zdGFsbGF0aW9uOjE=", "status": "PENDING", "message": null }, "errors": [] },
+    }));
+    // the job is gone once it succeeded
+    saleor.respond_to("appsInstallations", json!({ "appsInstallations": [] }));
+
+    let installation = installer(&saleor)
+        .install(&credentials(), "saleor-app", "https://app.example.com/api/manifest", &[SaleorAppPermission::ManageProducts])
+        .await
+        .unwrap();
+    assert_eq!(installation.status, JobStatus::Success);
+}
+
+#[tokio::test]
+async fn failed_installation_reports_message() {
+    let saleor = MockSaleor::start().await;
+    saleor.respond_to("appInstall", json!({
+        "appInstall": { "appInstallation": { "id": "QXBwSW5zdGFsbGF0aW9uOjE=", "status": "PENDING", "message": null }, "errors": [] },
+    }));
+    saleor.respond_to("appsInstallations", json!({
+        "appsInstallations": [{ "id": "QXBwSW5zdGFsbGF0aW9uOjE=", "status": "FAILED", "message": "Unable to fetch manifest" }],
+    }));
+
+    let error = installer(&saleor)
+        .install(&StaffCredentials::Token("staff-token".to_string()), "saleor-app", "https://app.example.com/api/manifest", &[])
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Unable to fetch manifest"), "{}", error);
+}
+
+#[tokio::test]
+async fn login_errors_are_reported() {
+    let saleor = MockSaleor::start().await;
+    saleor.respond_to("tokenCreate", json!({
+        "tokenCreate": { "token": null, "errors": [{ "field": "email", "message": "Please, enter valid credentials" }] },
+    }));
+
+    let error = installer(&saleor)
+        .install(&credentials(), "saleor-app", "https://app.example.com/api/manifest", &[])
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("unable to log in"), "{}", error);
+}
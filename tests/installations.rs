@@ -0,0 +1,25 @@
+use axum::http::StatusCode;
+use saleor_app::{config::AppConfig, saleor::SaleorPermission, testing::TestApp, webhooks::PRODUCT_UPDATED_PATH};
+
+#[tokio::test]
+async fn removed_installations_are_gone_until_registered_again() {
+    let app = TestApp::with_saleor_config(|saleor| AppConfig {
+        admin_api_urls: vec![saleor.api_url()],
+        ..Default::default()
+    })
+    .await;
+    let user = app.as_user(&[SaleorPermission::ManageApps, SaleorPermission::ManageProducts]);
+
+    let response = user.delete(&format!("/api/installations?saleorApiUrl={}", app.saleor.api_url())).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    assert_eq!(user.get("/api/hello").await.status, StatusCode::GONE);
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::GONE);
+    // installing the app again is still possible
+    assert_eq!(app.get("/api/manifest").await.status, StatusCode::OK);
+
+    let response = app.register_with_token("new-app-token").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(user.get("/api/hello").await.status, StatusCode::OK);
+}
@@ -0,0 +1,77 @@
+use axum::http::StatusCode;
+use saleor_app::{
+    config::AppConfig,
+    saleor::{invoices::{self, *}, AplId, AplStore},
+    testing::{webhook_fixture, TestApp, TEST_APP_TOKEN},
+};
+use serde_json::json;
+
+async fn invoice_app() -> TestApp {
+    TestApp::with_routes(AppConfig::default(), invoices::router(PdfInvoiceRenderer::new().with_issuer(&["ACME Ltd."]))).await
+}
+
+#[tokio::test]
+async fn requested_invoices_are_uploaded_and_sent() {
+    let app = invoice_app().await;
+    app.saleor.respond_to("fileUpload", json!({ "fileUpload": { "uploadedFile": { "url": "https://media.example.com/invoice-1042.pdf" }, "errors": [] } }));
+    app.saleor.respond_to("invoiceUpdate", json!({ "invoiceUpdate": { "errors": [] } }));
+    app.saleor.respond_to("invoiceSendNotification", json!({ "invoiceSendNotification": { "errors": [] } }));
+
+    let response = app.deliver_webhook_fixture(INVOICE_REQUESTED_PATH, "invoice_requested", "invoice_requested").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let requests = app.saleor.requests();
+    let queries: Vec<&str> = requests.iter().map(|request| request["query"].as_str().unwrap()).collect();
+    assert!(queries[0].contains("fileUpload"));
+    assert!(queries[1].contains("invoiceUpdate"));
+    assert!(queries[2].contains("invoiceSendNotification"));
+    // without a number requested, the invoice is numbered like the order
+    assert_eq!(requests[1]["variables"], json!({
+        "id": "SW52b2ljZTox",
+        "input": { "number": "1042", "url": "https://media.example.com/invoice-1042.pdf" },
+    }));
+}
+
+#[tokio::test]
+async fn failed_updates_are_retried() {
+    let app = invoice_app().await;
+    app.saleor.respond_to("fileUpload", json!({ "fileUpload": { "uploadedFile": { "url": "https://media.example.com/invoice-1042.pdf" }, "errors": [] } }));
+    app.saleor.respond_to("invoiceUpdate", json!({ "invoiceUpdate": { "errors": [{ "field": "url", "message": "Enter a valid URL." }] } }));
+
+    let response = app.deliver_webhook_fixture(INVOICE_REQUESTED_PATH, "invoice_requested", "invoice_requested").await;
+    assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+    assert!(!app.saleor.requests().iter().any(|request| request["query"].as_str().unwrap().contains("invoiceSendNotification")));
+}
+
+#[tokio::test]
+async fn pdf_invoices_list_lines_and_totals() {
+    let request: InvoiceRequested = serde_json::from_value(webhook_fixture("invoice_requested")).unwrap();
+    let renderer = PdfInvoiceRenderer::new().with_issuer(&["ACME Ltd.", "1 Main Street"]);
+
+    let lines = renderer.lines("INV-7", &request.order);
+    let text = lines.join("\n");
+    assert!(text.starts_with("ACME Ltd.\n1 Main Street"));
+    assert!(text.contains("Invoice INV-7\nOrder #1042 of 2024-03-14"));
+    assert!(text.contains("Ada Lovelace\n12 St James's Square\nSW1Y 4JH LONDON\nUnited Kingdom"));
+    assert!(lines.iter().any(|line| line.contains("Monospace Tee (M)") && line.contains("20%") && line.ends_with("48.00 GBP")));
+    assert!(lines.iter().any(|line| line.contains("Shipping (Royal Mail)") && line.ends_with("6.00 GBP")));
+    assert!(lines.last().unwrap().trim_start().starts_with("Total") && lines.last().unwrap().ends_with("54.00 GBP"));
+
+    let file = renderer.render("INV/7", &request.order).await.unwrap();
+    assert_eq!(file.file_name, "invoice-INV_7.pdf");
+    assert!(file.content.starts_with(b"%PDF-1.4\n"));
+    assert!(file.content.ends_with(b"%%EOF\n"));
+    assert!(String::from_utf8_lossy(&file.content).contains("(Invoice INV/7) Tj"));
+}
+
+#[tokio::test]
+async fn rejected_app_tokens_are_noticed_by_uploads() {
+    let app = invoice_app().await;
+    app.saleor.reject_token(TEST_APP_TOKEN);
+
+    let response = app.deliver_webhook_fixture(INVOICE_REQUESTED_PATH, "invoice_requested", "invoice_requested").await;
+    assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+    assert!(response.text().contains("rejected the app token"), "{}", response.text());
+    let auth_data = app.apl.store().get(&AplId::from_api_url(&app.saleor.api_url())).await.unwrap().unwrap();
+    assert!(auth_data.token_invalid_since.is_some());
+}
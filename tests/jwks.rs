@@ -0,0 +1,221 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{http::StatusCode, response::IntoResponse};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::Algorithm;
+use saleor_app::{
+    config::AppConfig,
+    http_client::HttpClient,
+    saleor::{verify_jwt, AplId, AplStore, AuthData, JwksCache, JwksFetchConfig, JwksFetchError, JwtValidation, SaleorPermission, VerifyJwtError},
+    testing::{webhook_fixture, MockSaleor, TestApp},
+    webhooks::PRODUCT_UPDATED_PATH,
+};
+use serde_json::json;
+
+fn config(grace_period: Duration) -> JwksFetchConfig {
+    JwksFetchConfig {
+        retries: 1,
+        backoff: Duration::from_millis(1),
+        ttl: Duration::ZERO,
+        grace_period,
+        retry_after: Duration::from_secs(30),
+    }
+}
+
+#[tokio::test]
+async fn uses_fetched_jwks_while_saleor_is_down() {
+    let saleor = MockSaleor::start().await;
+    let origin = AuthData::new(format!("http://{}", saleor.domain()), "token");
+    let cache = JwksCache::with_fetch_config(config(Duration::from_secs(60)));
+    let client = HttpClient::default();
+
+    let fetched = cache.fetch(&client, &origin).await.unwrap();
+    saleor.set_jwks_down(true);
+    let fallback = cache.fetch(&client, &origin).await.unwrap();
+    assert!(Arc::ptr_eq(&fetched, &fallback));
+}
+
+#[tokio::test]
+async fn answers_unavailable_without_recent_jwks() {
+    let saleor = MockSaleor::start().await;
+    let origin = AuthData::new(format!("http://{}", saleor.domain()), "token");
+    let client = HttpClient::default();
+
+    let never_fetched = JwksCache::with_fetch_config(config(Duration::from_secs(60)));
+    let outdated = JwksCache::with_fetch_config(config(Duration::ZERO));
+    outdated.fetch(&client, &origin).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    saleor.set_jwks_down(true);
+
+    for cache in [never_fetched, outdated] {
+        let error = cache.fetch(&client, &origin).await.err().unwrap();
+        assert_eq!(error, JwksFetchError::Unavailable { retry_after: Duration::from_secs(30) });
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "30");
+    }
+}
+
+#[tokio::test]
+async fn fetched_jwks_are_dropped_after_the_grace_period() {
+    let (saleor, other) = (MockSaleor::start().await, MockSaleor::start().await);
+    let client = HttpClient::default();
+    let cache = JwksCache::with_fetch_config(config(Duration::ZERO));
+
+    cache.fetch(&client, &AuthData::new(format!("http://{}", saleor.domain()), "token")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    cache.fetch(&client, &AuthData::new(format!("http://{}", other.domain()), "token")).await.unwrap();
+    assert_eq!(cache.fallbacks(), 1);
+}
+
+#[tokio::test]
+async fn fetched_jwks_are_reused_within_the_ttl() {
+    let saleor = MockSaleor::start().await;
+    let origin = AuthData::new(format!("http://{}", saleor.domain()), "token");
+    let cache = JwksCache::with_fetch_config(JwksFetchConfig { ttl: Duration::from_secs(60), ..config(Duration::from_secs(60)) });
+    let client = HttpClient::default();
+
+    let fetched = cache.fetch(&client, &origin).await.unwrap();
+    let cached = cache.fetch(&client, &origin).await.unwrap();
+    assert!(Arc::ptr_eq(&fetched, &cached));
+    assert_eq!(saleor.jwks_fetches(), 1);
+}
+
+/// JWKS of the same shape as the ones of `saleor`, without its key.
+fn stale_jwks(saleor: &MockSaleor) -> String {
+    saleor.jwks().replace("test-key", "rotated-away")
+}
+
+#[tokio::test]
+async fn rotated_jwks_are_fetched_and_stored() {
+    let app = TestApp::new().await;
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    let installation = app.apl.store().get(&apl_id).await.unwrap().unwrap();
+    app.apl.store().set(&apl_id, AuthData { jwks: Some(stale_jwks(&app.saleor)), ..installation }).await.unwrap();
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let stored = app.apl.store().get(&apl_id).await.unwrap().unwrap();
+    assert_eq!(stored.jwks.as_deref(), Some(app.saleor.jwks()));
+
+    let fetches = app.saleor.jwks_fetches();
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(app.saleor.jwks_fetches(), fetches);
+}
+
+#[tokio::test]
+async fn webhooks_signed_with_rotated_keys_are_accepted() {
+    let app = TestApp::new().await;
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    let installation = app.apl.store().get(&apl_id).await.unwrap().unwrap();
+    app.apl.store().set(&apl_id, AuthData { jwks: Some(stale_jwks(&app.saleor)), ..installation }).await.unwrap();
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(app.apl.store().get(&apl_id).await.unwrap().unwrap().jwks.as_deref(), Some(app.saleor.jwks()));
+}
+
+#[tokio::test]
+async fn invalid_stored_jwks_are_fetched_again() {
+    let app = TestApp::new().await;
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    let installation = app.apl.store().get(&apl_id).await.unwrap().unwrap();
+    app.apl.store().set(&apl_id, AuthData { jwks: Some("<html>".to_string()), ..installation }).await.unwrap();
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(app.apl.store().get(&apl_id).await.unwrap().unwrap().jwks.as_deref(), Some(app.saleor.jwks()));
+}
+
+#[tokio::test]
+async fn registrations_without_jwks_are_rejected() {
+    let app = TestApp::unregistered(AppConfig::default()).await;
+    app.saleor.set_jwks_down(true);
+
+    let response = app.register().await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{}", response.text());
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "JWKS_NOT_AVAILABLE");
+    assert!(app.apl.store().get(&AplId::from_api_url(&app.saleor.api_url())).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn unknown_keys_are_still_rejected_after_a_refetch() {
+    let app = TestApp::new().await;
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    let header = json!({ "alg": "ES256", "kid": "unknown", "b64": false, "crit": ["b64"] });
+    let signature = format!("{}..c2lnbmF0dXJl", URL_SAFE_NO_PAD.encode(header.to_string()));
+
+    let payload = webhook_fixture("product_updated").to_string();
+    let response = app.deliver_signed_webhook(PRODUCT_UPDATED_PATH, "product_updated", payload, &signature).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{}", response.text());
+    assert_eq!(app.apl.store().get(&apl_id).await.unwrap().unwrap().jwks.as_deref(), Some(app.saleor.jwks()));
+}
+
+#[tokio::test]
+async fn missing_jwks_are_invalid() {
+    // the app itself doesn't serve a JWKS
+    let app = TestApp::new().await;
+    let cache = JwksCache::new();
+
+    let missing = AuthData::new(format!("http://{}/graphql/", app.serve()), "token");
+    let error = cache.fetch(&HttpClient::default(), &missing).await.err().unwrap();
+    assert!(matches!(error, JwksFetchError::Invalid(_)), "{error:?}");
+    assert_eq!(error.into_response().status(), StatusCode::UNAUTHORIZED);
+}
+
+/// A token with the given header and claims, signed with garbage.
+fn forged_token(header: serde_json::Value) -> String {
+    let claims = json!({ "app": "QXBwOjE=", "user_permissions": [], "exp": u32::MAX });
+    format!("{}.{}.c2lnbmF0dXJl", URL_SAFE_NO_PAD.encode(header.to_string()), URL_SAFE_NO_PAD.encode(claims.to_string()))
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[tokio::test]
+async fn unsigned_and_hmac_tokens_are_rejected() {
+    let saleor = MockSaleor::start().await;
+    let validation = JwtValidation::default();
+    assert!(verify_jwt(saleor.jwks(), &saleor.token(&[]), &[], &validation).is_ok());
+
+    for (alg, expected) in [("none", "jwt algorithm none is not allowed"), ("HS256", "jwt algorithm HS256 is not allowed")] {
+        let token = forged_token(json!({ "alg": alg, "kid": "test-key", "typ": "JWT" }));
+        assert_eq!(verify_jwt(saleor.jwks(), &token, &[], &validation).err(), Some(VerifyJwtError::Invalid(expected.to_string())));
+    }
+    assert!(JwtValidation::default().with_algorithms(&[Algorithm::RS256, Algorithm::HS256]).is_err());
+    assert!(JwtValidation::default().with_algorithms(&[]).is_err());
+    assert!(JwtValidation::parse_algorithms("RS256,none").is_err());
+}
+
+#[tokio::test]
+async fn tokens_are_validated_with_the_configured_options() {
+    let saleor = MockSaleor::start().await;
+    let rs256_only = JwtValidation::default().with_algorithms(&[Algorithm::RS256]).unwrap();
+    let error = verify_jwt(saleor.jwks(), &saleor.token(&[]), &[], &rs256_only).err().unwrap();
+    assert_eq!(error, VerifyJwtError::Invalid("jwt algorithm ES256 is not allowed".to_string()));
+
+    // expired half a minute ago, within the default leeway
+    let expired = saleor.sign_token(&json!({ "app": "QXBwOjE=", "user_permissions": [], "exp": now() - 30 }));
+    assert!(verify_jwt(saleor.jwks(), &expired, &[], &JwtValidation::default()).is_ok());
+    assert!(verify_jwt(saleor.jwks(), &expired, &[], &JwtValidation::default().with_leeway(0)).is_err());
+
+    let with_nbf = JwtValidation::default().with_required_claims(&["exp", "nbf"]).unwrap();
+    let error = verify_jwt(saleor.jwks(), &saleor.token(&[]), &[], &with_nbf).err().unwrap();
+    assert!(matches!(&error, VerifyJwtError::Invalid(e) if e.contains("nbf")), "{error:?}");
+    assert!(JwtValidation::default().with_required_claims(&["email"]).is_err());
+}
+
+#[tokio::test]
+async fn the_auth_layer_uses_the_configured_validation() {
+    let config = AppConfig {
+        jwt_validation: JwtValidation::default().with_algorithms(&[Algorithm::RS256]).unwrap(),
+        ..Default::default()
+    };
+    let app = TestApp::with_config(config).await;
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.text().starts_with("jwt algorithm ES256 is not allowed"), "{}", response.text());
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use axum::http::{header::RETRY_AFTER, StatusCode};
+use saleor_app::{
+    config::AppConfig,
+    load_shed::{GroupLimits, LoadShedLimits, LoadShedding, RouteGroup},
+    saleor::SaleorPermission,
+    testing::TestApp,
+    webhooks::PRODUCT_UPDATED_PATH,
+};
+use tokio::time::timeout;
+
+fn limits() -> LoadShedLimits {
+    LoadShedLimits {
+        dashboard: GroupLimits { max_in_flight: 1, max_queued: 1 },
+        webhooks: GroupLimits { max_in_flight: 1, max_queued: 1 },
+        retry_after: Duration::from_secs(3),
+    }
+}
+
+#[tokio::test]
+async fn requests_beyond_the_queue_are_shed() {
+    let load_shedding = LoadShedding::new(limits());
+    let permit = load_shedding.acquire(RouteGroup::Webhooks).await.unwrap();
+
+    let queued = tokio::spawn({
+        let load_shedding = load_shedding.clone();
+        async move { load_shedding.acquire(RouteGroup::Webhooks).await.is_ok() }
+    });
+    while load_shedding.queued(RouteGroup::Webhooks) == 0 {
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(load_shedding.acquire(RouteGroup::Webhooks).await.err(), Some(Duration::from_secs(3)));
+    // the dashboard has its own room
+    assert!(timeout(Duration::from_millis(50), load_shedding.acquire(RouteGroup::Dashboard)).await.unwrap().is_ok());
+
+    drop(permit);
+    assert!(queued.await.unwrap());
+    assert_eq!(load_shedding.queued(RouteGroup::Webhooks), 0);
+}
+
+#[tokio::test]
+async fn webhook_floods_do_not_reach_the_dashboard() {
+    let load_shed = LoadShedLimits { webhooks: GroupLimits { max_in_flight: 1, max_queued: 0 }, ..limits() };
+    let app = TestApp::with_config(AppConfig { load_shed, ..Default::default() }).await;
+    // a webhook still being handled
+    let _permit = app.load_shedding.acquire(RouteGroup::Webhooks).await.unwrap();
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers[RETRY_AFTER], "3");
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[tokio::test]
+async fn apps_have_room_of_their_own() {
+    let load_shed = LoadShedLimits { webhooks: GroupLimits { max_in_flight: 1, max_queued: 0 }, ..limits() };
+    let busy = TestApp::with_config(AppConfig { load_shed, ..Default::default() }).await;
+    let _permit = busy.load_shedding.acquire(RouteGroup::Webhooks).await.unwrap();
+
+    let app = TestApp::with_config(AppConfig { load_shed, ..Default::default() }).await;
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
@@ -0,0 +1,169 @@
+use std::{net::Ipv4Addr, path::PathBuf};
+
+use axum::{body::Body, http::{HeaderValue, Request, StatusCode}};
+use saleor_app::{
+    app::{self, app_manifest},
+    config::AppConfig,
+    rate_limit::TrustedProxies,
+    saleor::{manifest_problems, ManifestDefinition, ManifestValidation, SaleorAppExtensionMount, SaleorAppExtensionTarget, SaleorAppPermission, SaleorManifest},
+    testing::{MockAplStore, TestApp},
+};
+
+fn manifest_file(extension: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("manifest-{}.{extension}", uuid::Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn app_manifest_is_valid() {
+    assert_eq!(manifest_problems(&app_manifest("https://app.example.com")), Vec::<String>::new());
+}
+
+#[test]
+fn mistakes_are_reported() {
+    let mut manifest = app_manifest("https://app.example.com");
+    manifest.permissions = vec![];
+    manifest.support_url = Some("support.example.com".to_string());
+    let extension = &mut manifest.extensions.as_mut().unwrap()[0];
+    extension.mount = SaleorAppExtensionMount::NavigationCatalog;
+    extension.target = SaleorAppExtensionTarget::Popup;
+    extension.permissions = vec![SaleorAppPermission::ManageOrders];
+    let webhook = &mut manifest.webhooks.as_mut().unwrap()[0];
+    webhook.query = " ".to_string();
+    webhook.async_events = Some(vec![]);
+
+    let problems = manifest_problems(&manifest);
+    for expected in ["supportUrl", "requires ManageOrders", "navigation", "permissions are empty", "empty query", "no events"] {
+        assert!(problems.iter().any(|problem| problem.contains(expected)), "{expected} not in {problems:?}");
+    }
+}
+
+#[tokio::test]
+async fn strict_validation_stops_the_app() {
+    let config = AppConfig {
+        app_url: Some("app.example.com".to_string()),
+        manifest_validation: ManifestValidation::Strict,
+        ..AppConfig::default()
+    };
+    let error = app::build(&config, MockAplStore::new()).await.err().expect("the app started");
+    assert!(error.to_string().contains("appUrl"), "{error}");
+
+    let config = AppConfig { manifest_validation: ManifestValidation::Permissive, ..config };
+    assert!(app::build(&config, MockAplStore::new()).await.is_ok());
+}
+
+#[test]
+fn definitions_load_from_toml_and_json() {
+    let toml = manifest_file("toml", r#"
+        name = "Acme sync"
+        about = "Keeps Acme in sync"
+        permissions = ["MANAGE_PRODUCTS", "MANAGE_ORDERS"]
+
+        [brand.logo]
+        default = "https://acme.example.com/logo.png"
+    "#);
+    let definition = ManifestDefinition::load(&toml).unwrap();
+    let json = manifest_file("json", r#"{ "name": "Acme sync", "about": "Keeps Acme in sync", "permissions": ["MANAGE_PRODUCTS", "MANAGE_ORDERS"], "brand": { "logo": { "default": "https://acme.example.com/logo.png" } } }"#);
+    assert_eq!(ManifestDefinition::load(&json).unwrap(), definition);
+
+    let manifest = definition.apply(app_manifest("https://app.example.com"));
+    assert_eq!(manifest.name, "Acme sync");
+    assert_eq!(manifest.about.as_deref(), Some("Keeps Acme in sync"));
+    assert_eq!(manifest.permissions, [SaleorAppPermission::ManageProducts, SaleorAppPermission::ManageOrders]);
+    assert_eq!(manifest.token_target_url, "https://app.example.com/api/register");
+}
+
+#[test]
+fn unknown_fields_and_formats_are_rejected() {
+    let error = ManifestDefinition::load(&manifest_file("toml", "tokenTargetUrl = \"https://evil.example.com\"")).unwrap_err();
+    assert!(error.to_string().contains("tokenTargetUrl"), "{error}");
+    assert!(ManifestDefinition::load(&manifest_file("yaml", "name: Acme")).is_err());
+}
+
+#[tokio::test]
+async fn served_manifest_uses_the_definition() {
+    let app = TestApp::with_config(AppConfig {
+        manifest: ManifestDefinition { name: Some("Acme sync".to_string()), ..Default::default() },
+        ..AppConfig::default()
+    })
+    .await;
+
+    let response = app.get("/api/manifest").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let manifest: SaleorManifest = response.json();
+    assert_eq!(manifest.name, "Acme sync");
+}
+
+async fn served_app_url(app: &TestApp, headers: &[(&str, HeaderValue)]) -> Result<String, StatusCode> {
+    let mut request = Request::get("/api/manifest");
+    for (name, value) in headers {
+        request = request.header(*name, value.clone());
+    }
+    let response = app.request(request.body(Body::empty()).unwrap()).await;
+    match response.status {
+        StatusCode::OK => Ok(response.json::<SaleorManifest>().app_url),
+        status => Err(status),
+    }
+}
+
+fn header(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).unwrap()
+}
+
+fn behind_proxy() -> TrustedProxies {
+    TrustedProxies::new(&[Ipv4Addr::LOCALHOST.into()])
+}
+
+#[tokio::test]
+async fn served_manifest_only_trusts_allowed_hosts() {
+    let app = TestApp::with_config(AppConfig {
+        allowed_hosts: vec!["app.example.com".to_string(), "*.apps.example.com".to_string()],
+        trusted_proxies: behind_proxy(),
+        ..AppConfig::default()
+    })
+    .await;
+
+    let forwarded = [("host", header("app.example.com:8443")), ("x-forwarded-proto", header("HTTP"))];
+    assert_eq!(served_app_url(&app, &forwarded).await, Ok("http://app.example.com:8443".to_string()));
+    assert_eq!(served_app_url(&app, &[("host", header("demo.apps.example.com"))]).await, Ok("https://demo.apps.example.com".to_string()));
+    for host in ["evil.example.com", "apps.example.com", "demo.apps.example.com.evil.com"] {
+        assert_eq!(served_app_url(&app, &[("host", header(host))]).await, Err(StatusCode::BAD_REQUEST), "{host}");
+    }
+    let forged = [("host", header("app.example.com")), ("x-forwarded-host", header("evil.example.com"))];
+    assert_eq!(served_app_url(&app, &forged).await, Err(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn malformed_hosts_are_rejected() {
+    let app = TestApp::with_config(AppConfig { trusted_proxies: behind_proxy(), ..AppConfig::default() }).await;
+    let proxied = [("host", header("internal:8008")), ("x-forwarded-host", header("app.example.com, proxy.internal"))];
+    assert_eq!(served_app_url(&app, &proxied).await, Ok("https://app.example.com".to_string()));
+
+    let non_utf8 = HeaderValue::from_bytes(b"app.example.com\xff").unwrap();
+    let malformed = [
+        vec![("host", header("app.example.com")), ("host", header("evil.example.com"))],
+        vec![("host", non_utf8.clone())],
+        vec![("host", header("app.example.com")), ("x-forwarded-proto", non_utf8)],
+        vec![("host", header("user@evil.example.com"))],
+        vec![("host", header("app.example.com/path"))],
+        vec![("host", header("app.example.com")), ("x-forwarded-proto", header("gopher"))],
+    ];
+    for headers in malformed {
+        assert_eq!(served_app_url(&app, &headers).await, Err(StatusCode::BAD_REQUEST), "{headers:?}");
+    }
+}
+
+#[tokio::test]
+async fn forwarded_headers_are_ignored_without_trusted_proxy() {
+    let app = TestApp::with_config(AppConfig { allowed_hosts: vec!["app.example.com".to_string()], ..AppConfig::default() }).await;
+    let forged = [("host", header("app.example.com")), ("x-forwarded-host", header("evil.example.com")), ("x-forwarded-proto", header("http"))];
+    assert_eq!(served_app_url(&app, &forged).await, Ok("https://app.example.com".to_string()));
+}
+
+#[tokio::test]
+async fn app_url_wins_over_the_request() {
+    let app = TestApp::with_config(AppConfig { app_url: Some("https://app.example.com/".to_string()), ..AppConfig::default() }).await;
+    let forged = [("host", header("evil.example.com")), ("x-forwarded-proto", header("http"))];
+    assert_eq!(served_app_url(&app, &forged).await, Ok("https://app.example.com".to_string()));
+}
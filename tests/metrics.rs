@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use axum::{body::Body, http::Request};
+use saleor_app::{
+    load_shed::{GroupLimits, LoadShedLimits, LoadShedding, RouteGroup},
+    telemetry::{PrometheusMetrics, WEBHOOK_QUEUE_DEPTH},
+};
+use tower::ServiceExt;
+
+async fn scrape(metrics: &PrometheusMetrics) -> String {
+    let response = metrics.clone().router().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn webhook_queue_depth_is_exposed() {
+    let metrics = PrometheusMetrics::install().unwrap();
+    let limits = GroupLimits { max_in_flight: 1, max_queued: 2 };
+    let load_shedding = LoadShedding::new(LoadShedLimits { dashboard: limits, webhooks: limits, retry_after: Duration::from_secs(1) });
+    let permit = load_shedding.acquire(RouteGroup::Webhooks).await.unwrap();
+
+    let queued = tokio::spawn({
+        let load_shedding = load_shedding.clone();
+        async move { load_shedding.acquire(RouteGroup::Webhooks).await.is_ok() }
+    });
+    while load_shedding.queued(RouteGroup::Webhooks) == 0 {
+        tokio::task::yield_now().await;
+    }
+    assert!(scrape(&metrics).await.contains(&format!("{WEBHOOK_QUEUE_DEPTH} 1")));
+
+    // requests cancelled while waiting leave the queue
+    let cancelled = tokio::spawn({
+        let load_shedding = load_shedding.clone();
+        async move { load_shedding.acquire(RouteGroup::Webhooks).await.is_ok() }
+    });
+    while load_shedding.queued(RouteGroup::Webhooks) < 2 {
+        tokio::task::yield_now().await;
+    }
+    cancelled.abort();
+    assert!(cancelled.await.unwrap_err().is_cancelled());
+    assert!(scrape(&metrics).await.contains(&format!("{WEBHOOK_QUEUE_DEPTH} 1")));
+
+    drop(permit);
+    assert!(queued.await.unwrap());
+    assert!(scrape(&metrics).await.contains(&format!("{WEBHOOK_QUEUE_DEPTH} 0")));
+}
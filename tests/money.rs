@@ -0,0 +1,103 @@
+use rust_decimal::Decimal;
+use saleor_app::saleor::{
+    money::{Money, TaxedMoney},
+    taxes::TaxedAmount,
+};
+use serde_json::json;
+
+fn dec(amount: &str) -> Decimal {
+    amount.parse().unwrap()
+}
+
+fn eur(amount: &str) -> Money {
+    Money::new(dec(amount), "EUR")
+}
+
+#[test]
+fn money_has_the_shape_of_saleor() {
+    let money: Money = serde_json::from_value(json!({ "amount": 0.1, "currency": "EUR" })).unwrap();
+    assert_eq!(money, eur("0.1"));
+    assert_eq!(serde_json::to_value(eur("12.5")).unwrap(), json!({ "amount": 12.5, "currency": "EUR" }));
+
+    let taxed: TaxedMoney = serde_json::from_value(json!({
+        "net": { "amount": 10, "currency": "EUR" },
+        "gross": { "amount": 11.9, "currency": "EUR" },
+        "tax": { "amount": 1.9, "currency": "EUR" },
+    }))
+    .unwrap();
+    assert_eq!(taxed, TaxedMoney::from_net(eur("10"), dec("19")).unwrap());
+    assert_eq!(TaxedAmount::from(&taxed), TaxedAmount { net: dec("10"), gross: dec("11.9") });
+}
+
+#[test]
+fn arithmetic_is_exact_and_keeps_currencies_apart() {
+    let total = Money::sum("EUR", &[eur("0.1"), eur("0.2")]).unwrap();
+    assert_eq!(total, eur("0.3"));
+    assert_eq!(eur("1").checked_sub(&eur("0.01")).unwrap(), eur("0.99"));
+    assert_eq!(eur("0.333").checked_mul(Decimal::from(3)).unwrap().round(), eur("1.00"));
+    assert_eq!((-eur("1.005")).round(), eur("-1.01"));
+    assert!((-eur("1")).is_negative());
+
+    let error = eur("1").checked_add(&Money::new(Decimal::ONE, "USD")).unwrap_err();
+    assert_eq!(error.to_string(), "money error: unable to combine EUR with USD");
+    assert!(Money::sum("USD", &[eur("1")]).is_err());
+}
+
+#[test]
+fn overflows_are_errors() {
+    let max = Money::new(Decimal::MAX, "EUR");
+    assert!(max.checked_add(&eur("1")).is_err());
+    assert!((-max.clone()).checked_sub(&eur("1")).is_err());
+    assert!(max.checked_mul(Decimal::from(2)).is_err());
+    assert_eq!(max.minor_units(), None);
+    assert_eq!(Money::new(dec("1e20"), "EUR").minor_units(), None);
+}
+
+#[test]
+fn currencies_are_uppercase() {
+    assert_eq!(Money::new(Decimal::ONE, "eur"), eur("1"));
+    let money: Money = serde_json::from_value(json!({ "amount": 1, "currency": "eur" })).unwrap();
+    assert_eq!(money.currency, "EUR");
+}
+
+#[test]
+fn amounts_are_rounded_to_their_currency() {
+    assert_eq!(Money::new(dec("149.5"), "JPY").round().to_string(), "150 JPY");
+    assert_eq!(Money::new(dec("1.0005"), "KWD").minor_units(), Some(1001));
+    assert_eq!(eur("12.345").minor_units(), Some(1235));
+    assert_eq!(Money::from_minor_units(1999, "EUR"), eur("19.99"));
+    assert_eq!(Money::from_minor_units(1999, "JPY"), Money::new(dec("1999"), "JPY"));
+    assert_eq!(eur("12.5").to_string(), "12.50 EUR");
+}
+
+#[test]
+fn allocations_add_up_to_the_amount() {
+    let parts = eur("10").allocate(&[Decimal::ONE, Decimal::ONE, Decimal::ONE]).unwrap();
+    assert_eq!(parts, vec![eur("3.33"), eur("3.33"), eur("3.34")]);
+    assert_eq!(Money::sum("EUR", &parts).unwrap(), eur("10"));
+
+    let parts = eur("100").allocate(&[dec("1"), dec("3")]).unwrap();
+    assert_eq!(parts, vec![eur("25"), eur("75")]);
+    assert_eq!(eur("5").allocate(&[Decimal::ZERO, Decimal::ZERO]).unwrap(), vec![eur("5"), eur("0")]);
+    assert!(eur("5").allocate(&[]).unwrap().is_empty());
+
+    assert!(Money::new(Decimal::MAX, "JPY").allocate(&[Decimal::MAX, Decimal::ONE]).is_err());
+    assert!(eur("5").allocate(&[Decimal::MAX, Decimal::MAX]).is_err());
+}
+
+#[test]
+fn taxed_money_rounds_net_and_gross() {
+    let taxed = TaxedMoney::from_gross(eur("10"), dec("19")).unwrap();
+    assert_eq!(taxed.net, eur("8.40"));
+    assert_eq!(taxed.tax, eur("1.60"));
+    assert_eq!(taxed.currency(), "EUR");
+
+    let untaxed = TaxedMoney::untaxed(eur("4.999"));
+    assert_eq!(untaxed.gross, eur("5.00"));
+    assert!(untaxed.tax.is_zero());
+    assert_eq!(taxed.checked_add(&untaxed).unwrap().gross, eur("15"));
+
+    assert!(TaxedMoney::from_gross(eur("10"), dec("-100")).is_err());
+    assert!(TaxedMoney::from_net(eur("10"), dec("-150")).is_err());
+    assert!(TaxedMoney::from_net(Money::new(Decimal::MAX, "JPY"), dec("19")).is_err());
+}
@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use saleor_app::{
+    config::AppConfig,
+    email::{EmailError, EmailMessage, EmailTransport, Mailer},
+    saleor::notifications::{self, *},
+    testing::TestApp,
+};
+use serde_json::{json, Value};
+
+/// Keeps the emails instead of sending them, or fails if there's no inbox.
+#[derive(Clone, Default)]
+struct Outbox {
+    sent: Arc<Mutex<Vec<EmailMessage>>>,
+    unavailable: bool,
+}
+
+#[async_trait]
+impl EmailTransport for Outbox {
+    async fn send(&self, message: &EmailMessage) -> Result<(), EmailError> {
+        if self.unavailable {
+            return Err(EmailError("connection refused".to_string()));
+        }
+        self.sent.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
+
+async fn notification_app(mailer: Mailer) -> TestApp {
+    TestApp::with_routes(AppConfig::default(), notifications::router(mailer)).await
+}
+
+fn settings(settings: &[(&str, &str)]) -> Value {
+    let metadata: Vec<Value> = settings.iter().map(|(key, value)| json!({ "key": key, "value": value })).collect();
+    json!({ "app": { "id": "QXBwOjE=", "privateMetadata": metadata } })
+}
+
+#[tokio::test]
+async fn order_confirmations_are_sent_from_the_configured_sender() {
+    let outbox = Outbox::default();
+    let app = notification_app(Mailer::new(outbox.clone())).await;
+    app.saleor.respond_to("privateMetadata", settings(&[("email_sender_address", "orders@shop.example.com"), ("email_sender_name", "Example Shop")]));
+
+    let response = app.deliver_webhook_fixture(ORDER_CONFIRMED_PATH, "order_confirmed", "order_confirmed").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let sent = outbox.sent.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].from, "\"Example Shop\" <orders@shop.example.com>");
+    assert_eq!(sent[0].to, "customer@example.com");
+    assert_eq!(sent[0].subject, "Order #1042 confirmed");
+    assert!(sent[0].html.contains("2 &times; Monospace Tee (M)"));
+    assert!(sent[0].html.contains("74.50 USD"));
+}
+
+#[tokio::test]
+async fn account_emails_link_to_the_storefront() {
+    let outbox = Outbox::default();
+    let app = notification_app(Mailer::new(outbox.clone()).with_default_sender("noreply@example.com")).await;
+    app.saleor.respond_to("privateMetadata", settings(&[]));
+
+    let response = app.deliver_webhook_fixture(ACCOUNT_SET_PASSWORD_REQUESTED_PATH, "account_set_password_requested", "account_set_password_requested").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let sent = outbox.sent.lock().unwrap();
+    assert_eq!(sent[0].from, "noreply@example.com");
+    assert_eq!(sent[0].subject, "Set your password");
+    assert!(sent[0].html.contains("Example Shop"));
+    assert!(sent[0].html.contains("https://shop.example.com/account/reset-password?email=customer%40example.com&amp;token=c1x6xa-2f6a3d5e1b8c4f7a9e0d"));
+}
+
+#[tokio::test]
+async fn installations_without_sender_get_no_emails() {
+    let outbox = Outbox::default();
+    let app = notification_app(Mailer::new(outbox.clone())).await;
+    app.saleor.respond_to("privateMetadata", settings(&[]));
+
+    let response = app.deliver_webhook_fixture(ORDER_CONFIRMED_PATH, "order_confirmed", "order_confirmed").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(outbox.sent.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried() {
+    let outbox = Outbox { unavailable: true, ..Outbox::default() };
+    let app = notification_app(Mailer::new(outbox)).await;
+    app.saleor.respond_to("privateMetadata", settings(&[("email_sender_address", "orders@shop.example.com")]));
+
+    let response = app.deliver_webhook_fixture(ORDER_CONFIRMED_PATH, "order_confirmed", "order_confirmed").await;
+    assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+}
@@ -0,0 +1,79 @@
+use axum::http::StatusCode;
+use saleor_app::{saleor::{orders::OrdersPage, SaleorPermission}, testing::TestApp};
+use serde_json::{json, Value};
+
+fn orders_page(after: Option<&str>) -> Value {
+    let (id, number, cursor) = match after {
+        None => ("T3JkZXI6MQ==", "1042", Some("cursor-1")),
+        Some(_) => ("T3JkZXI6Mg==", "1041", None),
+    };
+    json!({
+        "orders": {
+            "totalCount": 2,
+            "pageInfo": { "hasNextPage": cursor.is_some(), "endCursor": cursor },
+            "edges": [{
+                "node": {
+                    "id": id,
+                    "number": number,
+                    "status": "UNFULFILLED",
+                    "created": "2024-03-01T12:00:00+00:00",
+                    "userEmail": "jane@example.com",
+                    "channel": { "id": "Q2hhbm5lbDox", "slug": "default-channel", "name": "Default Channel" },
+                    "total": { "gross": { "amount": 42.5, "currency": "EUR" } },
+                },
+            }],
+        },
+    })
+}
+
+#[tokio::test]
+async fn orders_are_filtered_and_paginated() {
+    let app = TestApp::new().await;
+    app.saleor.respond_with("AppOrders", |variables| orders_page(variables["after"].as_str()));
+    let user = app.as_user(&[SaleorPermission::ManageOrders]);
+
+    let response = user.get("/api/orders?status=unfulfilled,PARTIALLY_FULFILLED&createdFrom=2024-03-01&createdTo=2024-03-31&channel=Q2hhbm5lbDox&first=1").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let page: OrdersPage = response.json();
+    assert_eq!(page.orders.len(), 1);
+    assert_eq!(page.orders[0].number, "1042");
+    assert_eq!(page.orders[0].channel.slug, "default-channel");
+    assert_eq!(page.total_count, Some(2));
+    assert!(page.has_next_page);
+    let body: Value = response.json();
+    assert_eq!(body["orders"][0]["total"], json!({ "amount": 42.5, "currency": "EUR" }));
+
+    let request = app.saleor.requests().into_iter().rev().find(|request| request["query"].as_str().unwrap_or_default().contains("AppOrders")).unwrap();
+    assert_eq!(request["variables"]["first"], 1);
+    assert_eq!(request["variables"]["filter"], json!({
+        "status": ["UNFULFILLED", "PARTIALLY_FULFILLED"],
+        "created": { "gte": "2024-03-01", "lte": "2024-03-31" },
+        "channels": ["Q2hhbm5lbDox"],
+    }));
+
+    let response = user.get(&format!("/api/orders?after={}", page.end_cursor.unwrap())).await;
+    let page: OrdersPage = response.json();
+    assert_eq!(page.orders[0].number, "1041");
+    assert!(!page.has_next_page);
+    assert_eq!(page.end_cursor, None);
+}
+
+#[tokio::test]
+async fn orders_need_manage_orders() {
+    let app = TestApp::new().await;
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/orders").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    let app = TestApp::new().await;
+    let user = app.as_user(&[SaleorPermission::ManageOrders]);
+
+    for query in ["status=SHIPPED", "createdFrom=01.03.2024", "first=500", "first=0"] {
+        let response = user.get(&format!("/api/orders?{query}")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{query}: {}", response.text());
+    }
+    assert!(!app.saleor.requests().iter().any(|request| request["query"].as_str().unwrap_or_default().contains("AppOrders")));
+}
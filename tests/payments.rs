@@ -0,0 +1,92 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use rust_decimal::Decimal;
+use saleor_app::{
+    config::AppConfig,
+    saleor::payments::{self, *},
+    testing::{webhook_fixture, MockSaleor, TestApp},
+};
+use serde_json::{json, Value};
+
+struct TestPsp;
+
+#[async_trait]
+impl PaymentGateway for TestPsp {
+    async fn initialize_transaction(&self, _saleor_api_url: &str, payload: TransactionSession) -> TransactionSessionResponse {
+        TransactionSessionResponse::new(TransactionSessionResult::ChargeSuccess, payload.action.amount)
+            .with_psp_reference("psp-123")
+            .with_actions(&[TransactionActionKind::Refund])
+    }
+
+    async fn process_transaction(&self, _saleor_api_url: &str, payload: TransactionSession) -> TransactionSessionResponse {
+        TransactionSessionResponse::new(TransactionSessionResult::ChargeFailure, payload.action.amount).with_message("declined")
+    }
+}
+
+/// The payment routes in an app registered with the mock Saleor.
+async fn payment_app() -> TestApp {
+    TestApp::with_routes(AppConfig::default(), payments::router(TestPsp)).await
+}
+
+#[tokio::test]
+async fn transaction_initialize_session_is_answered_with_result() {
+    let app = payment_app().await;
+
+    let response = app.deliver_webhook_fixture(TRANSACTION_INITIALIZE_SESSION_PATH, "transaction_initialize_session", "transaction_initialize_session").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let body: Value = response.json();
+    assert_eq!(body, json!({
+        "pspReference": "psp-123",
+        "result": "CHARGE_SUCCESS",
+        "amount": 54.12,
+        "actions": ["REFUND"],
+    }));
+}
+
+#[tokio::test]
+async fn unsupported_actions_fail() {
+    let app = payment_app().await;
+
+    let response = app.deliver_webhook_fixture(TRANSACTION_REFUND_REQUESTED_PATH, "transaction_refund_requested", "transaction_refund_requested").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let body: Value = response.json();
+    assert_eq!(body["pspReference"], "psp-123");
+    assert_eq!(body["result"], "REFUND_FAILURE");
+    assert_eq!(body["amount"], 10.0);
+}
+
+#[tokio::test]
+async fn unsigned_deliveries_are_rejected() {
+    let app = payment_app().await;
+    let foreign = MockSaleor::start().await;
+
+    let payload = serde_json::to_vec(&webhook_fixture("transaction_initialize_session")).unwrap();
+    let signature = foreign.sign_webhook(&payload);
+    let response = app.deliver_signed_webhook(TRANSACTION_INITIALIZE_SESSION_PATH, "transaction_initialize_session", payload, &signature).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn payloads_keep_decimal_amounts() {
+    let session: TransactionSession = serde_json::from_value(webhook_fixture("transaction_initialize_session")).unwrap();
+    assert_eq!(session.action.amount, Decimal::new(5412, 2));
+    assert_eq!(session.source_object.channel().slug, "default-channel");
+
+    let request: TransactionActionRequest = serde_json::from_value(json!({
+        "transaction": { "id": "VHJhbnNhY3Rpb25JdGVtOjE=", "pspReference": "psp-123" },
+        "action": { "amount": null, "currency": "USD", "actionType": "CANCEL" },
+    }))
+    .unwrap();
+    assert_eq!(request.action.amount, None);
+    assert_eq!(request.psp_reference(), "psp-123");
+}
+
+#[test]
+fn manifest_lists_every_transaction_webhook() {
+    let webhooks = payments::manifest("https://app.example.com");
+    assert_eq!(webhooks.len(), 6);
+    for webhook in webhooks {
+        assert!(webhook.target_url.starts_with("https://app.example.com/api/webhooks/payments/"));
+        assert!(webhook.query.contains("... on "), "{}", webhook.query);
+    }
+}
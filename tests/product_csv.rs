@@ -0,0 +1,100 @@
+use axum::{body::Body, http::{header::{CONTENT_DISPOSITION, CONTENT_TYPE}, Request, StatusCode}};
+use saleor_app::{saleor::SaleorPermission, testing::TestApp};
+use serde_json::{json, Value};
+
+fn product(id: &str, name: &str) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "slug": name.to_lowercase().replace(' ', "-"),
+        "seoTitle": null,
+        "seoDescription": "Soft, \"heavy\" cotton",
+        "externalReference": null,
+        "updatedAt": "2024-03-01T00:00:00+00:00",
+        "productType": { "id": "UHJvZHVjdFR5cGU6MQ==", "name": "T-shirt" },
+        "category": null,
+    })
+}
+
+#[tokio::test]
+async fn export_streams_every_page() {
+    let app = TestApp::new().await;
+    app.saleor.respond_with("ProductsExport", |variables| match variables["after"].as_str() {
+        None => json!({
+            "products": {
+                "pageInfo": { "hasNextPage": true, "endCursor": "cursor-1" },
+                "edges": [{ "node": product("UHJvZHVjdDox", "Monospace Tee") }],
+            },
+        }),
+        Some(_) => json!({
+            "products": {
+                "pageInfo": { "hasNextPage": false, "endCursor": null },
+                "edges": [{ "node": product("UHJvZHVjdDoy", "Gift card") }],
+            },
+        }),
+    });
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/export/products.csv").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.headers[CONTENT_TYPE], "text/csv; charset=utf-8");
+    assert_eq!(response.headers[CONTENT_DISPOSITION], "attachment; filename=\"products.csv\"");
+
+    let csv = response.text();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "id,name,slug,product_type_id,product_type,category_id,category,seo_title,seo_description,external_reference,updated_at");
+    assert_eq!(lines[1], "UHJvZHVjdDox,Monospace Tee,monospace-tee,UHJvZHVjdFR5cGU6MQ==,T-shirt,,,,\"Soft, \"\"heavy\"\" cotton\",,2024-03-01T00:00:00+00:00");
+    assert!(lines[2].starts_with("UHJvZHVjdDoy,Gift card,"));
+    assert_eq!(lines.len(), 3);
+
+    let cursors: Vec<Value> = app.saleor.requests().iter().filter(|request| request["query"].as_str().unwrap().contains("ProductsExport")).map(|request| request["variables"]["after"].clone()).collect();
+    assert_eq!(cursors, [Value::Null, json!("cursor-1")]);
+}
+
+#[tokio::test]
+async fn import_reports_errors_per_row() {
+    let app = TestApp::new().await;
+    app.saleor.respond_to("productBulkCreate", json!({
+        "productBulkCreate": {
+            "results": [
+                { "product": { "id": "UHJvZHVjdDoz" }, "errors": [] },
+                { "product": null, "errors": [{ "path": "slug", "message": "Product with this Slug already exists.", "code": "UNIQUE" }] },
+            ],
+            "errors": [],
+        },
+    }));
+    app.saleor.respond_to("productUpdate", json!({
+        "row0": { "errors": [] },
+    }));
+    let csv = "id,name,slug,product_type_id,seo_description\n\
+        ,Canvas Tote,canvas-tote,UHJvZHVjdFR5cGU6Mg==,\n\
+        ,Duplicate,monospace-tee,UHJvZHVjdFR5cGU6MQ==,\n\
+        ,No type,no-type,,\n\
+        UHJvZHVjdDox,Monospace Tee,,,Now in black\n";
+    let request = Request::post("/api/import/products.csv").header(CONTENT_TYPE, "text/csv").body(Body::from(csv)).unwrap();
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).request(request).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let report: Value = response.json();
+    assert_eq!(report["created"], 1);
+    assert_eq!(report["updated"], 1);
+    assert_eq!(report["errors"], json!([
+        { "line": 3, "field": "slug", "message": "Product with this Slug already exists." },
+        { "line": 4, "field": "product_type_id", "message": "required to create a product" },
+    ]));
+
+    let requests = app.saleor.requests();
+    let create = requests.iter().find(|request| request["query"].as_str().unwrap().contains("productBulkCreate")).unwrap();
+    assert_eq!(create["variables"]["products"][0], json!({ "name": "Canvas Tote", "slug": "canvas-tote", "productType": "UHJvZHVjdFR5cGU6Mg==" }));
+    let update = requests.iter().find(|request| request["query"].as_str().unwrap().contains("productUpdate")).unwrap();
+    assert_eq!(update["variables"], json!({ "id0": "UHJvZHVjdDox", "input0": { "name": "Monospace Tee", "seo": { "description": "Now in black" } } }));
+}
+
+#[tokio::test]
+async fn import_rejects_unrelated_files() {
+    let app = TestApp::new().await;
+    let request = Request::post("/api/import/products.csv").body(Body::from("order,total\n42,10.00\n")).unwrap();
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).request(request).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(app.saleor.requests().iter().all(|request| !request["query"].as_str().unwrap().contains("mutation")));
+}
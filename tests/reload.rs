@@ -0,0 +1,119 @@
+use std::{path::PathBuf, time::Duration};
+
+use axum::{body::Body, http::{header::ORIGIN, Request, StatusCode}};
+use saleor_app::{
+    audit::{AuditEvent, AuditEventKind},
+    config::AppConfig,
+    rate_limit::RateLimitConfig,
+    reload::ConfigChange,
+    saleor::SaleorPermission,
+    testing::TestApp,
+};
+use serde_json::Value;
+
+fn audit_log_file() -> PathBuf {
+    std::env::temp_dir().join(format!("audit-{}.jsonl", uuid::Uuid::new_v4()))
+}
+
+fn admin_config(saleor_api_url: String, audit_log_file: PathBuf) -> AppConfig {
+    AppConfig {
+        admin_api_urls: vec![saleor_api_url],
+        audit_log_file: Some(audit_log_file),
+        ..Default::default()
+    }
+}
+
+async fn cors_allowed(app: &TestApp, origin: &str) -> bool {
+    let request = Request::get("/api/manifest").header(ORIGIN, origin).body(Body::empty()).unwrap();
+    app.request(request).await.headers.contains_key("access-control-allow-origin")
+}
+
+/// Waits for the audit events recorded in the background.
+async fn audit_events(app: &TestApp) -> Vec<AuditEvent> {
+    for _ in 0..50 {
+        let events: Vec<AuditEvent> = app.as_user(&[SaleorPermission::ManageApps]).get("/api/audit").await.json();
+        if events.iter().any(|event| event.kind == AuditEventKind::ConfigReloaded) {
+            return events;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("no config reload was audited");
+}
+
+#[tokio::test]
+async fn reloads_apply_allowlists_and_audit_the_changes() {
+    let path = audit_log_file();
+    let app = TestApp::with_saleor_config(|saleor| admin_config(saleor.api_url(), path.clone())).await;
+    let config = admin_config(app.saleor.api_url(), path.clone());
+    assert!(!cors_allowed(&app, "https://dashboard.example.com").await);
+
+    let changes = app
+        .reloader
+        .apply(AppConfig { cors_origins: vec!["https://dashboard.example.com/".to_string()], ..config.clone() }, "test")
+        .unwrap();
+    assert_eq!(changes, vec![ConfigChange { setting: "CORS_ORIGINS".to_string(), from: String::new(), to: "https://dashboard.example.com/".to_string() }]);
+    assert!(cors_allowed(&app, "https://dashboard.example.com").await);
+
+    let events = audit_events(&app).await;
+    let reloaded = events.iter().find(|event| event.kind == AuditEventKind::ConfigReloaded).unwrap();
+    assert_eq!(reloaded.detail.as_deref(), Some(r#"CORS_ORIGINS: "" -> "https://dashboard.example.com/" by test"#));
+
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/admin/webhook-status").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    app.reloader.apply(AppConfig { admin_api_urls: vec![], ..config }, "test").unwrap();
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/admin/webhook-status").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn reloads_change_rate_limits() {
+    let app = TestApp::new().await;
+    let config = AppConfig { rate_limit: RateLimitConfig { capacity: 1, ..Default::default() }, ..Default::default() };
+    let changes = app.reloader.apply(config, "test").unwrap();
+    assert_eq!(changes.iter().map(|change| change.setting.as_str()).collect::<Vec<_>>(), ["RATE_LIMIT_BURST"]);
+
+    // the token was registered already, the first retry is still let through to be rejected
+    assert_eq!(app.register().await.status, StatusCode::FORBIDDEN);
+    assert_eq!(app.register().await.status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn invalid_configurations_are_not_applied() {
+    let app = TestApp::new().await;
+    let invalid = AppConfig { rate_limit: RateLimitConfig { capacity: 0, ..Default::default() }, cors_origins: vec!["*".to_string()], ..Default::default() };
+    let error = app.reloader.apply(invalid, "test").unwrap_err();
+    assert!(error.0.contains("RATE_LIMIT_BURST"), "{error}");
+    assert!(!cors_allowed(&app, "https://dashboard.example.com").await);
+
+    let invalid = AppConfig { log_filter: Some("saleor_app=loud".to_string()), ..Default::default() };
+    let error = app.reloader.apply(invalid, "test").unwrap_err();
+    assert!(error.0.contains("RUST_LOG"), "{error}");
+}
+
+#[tokio::test]
+async fn operators_reload_the_config_file() {
+    let path = audit_log_file();
+    let app = TestApp::with_saleor_config(|saleor| admin_config(saleor.api_url(), path.clone())).await;
+    let config_file = std::env::temp_dir().join(format!("config-{}.env", uuid::Uuid::new_v4()));
+    std::fs::write(&config_file, format!(
+        "# mounted from a ConfigMap\nADMIN_SALEOR_API_URLS={}\nAUDIT_LOG_FILE={}\nexport CORS_ORIGINS=\"https://dashboard.example.com\"\n",
+        app.saleor.api_url(),
+        path.display(),
+    ))
+    .unwrap();
+    // the only test of this binary reading the configuration from the environment
+    std::env::set_var("CONFIG_FILE", &config_file);
+
+    let response = app.as_user(&[SaleorPermission::ManageApps]).post_json("/api/admin/reload", &Value::Null).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let changes: Value = response.json();
+    let changes = changes["changes"].as_array().unwrap();
+    assert!(changes.iter().any(|change| change["setting"] == "CORS_ORIGINS" && change["to"] == "https://dashboard.example.com"), "{changes:?}");
+    assert!(cors_allowed(&app, "https://dashboard.example.com").await);
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).post_json("/api/admin/reload", &Value::Null).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let _ = std::fs::remove_file(config_file);
+    let _ = std::fs::remove_file(path);
+}
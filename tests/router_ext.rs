@@ -0,0 +1,71 @@
+use axum::{http::StatusCode, routing::post, Router};
+use saleor_app::{
+    config::AppConfig,
+    router_ext::{RouterExt, SaleorWebhooks},
+    saleor::{saleor_webhook, SaleorWebhook, WebhookContext},
+    testing::TestApp,
+    webhooks::PRODUCT_UPDATED_PATH,
+};
+use serde_json::{json, Value};
+
+#[saleor_webhook(event = "PRODUCT_CREATED", query_path = "tests/fixtures/subscriptions/product_created.graphql")]
+async fn product_created(_context: &WebhookContext, _payload: Value) -> StatusCode {
+    StatusCode::ACCEPTED
+}
+
+async fn product_deleted(_webhook: SaleorWebhook<Value>) -> StatusCode {
+    StatusCode::NO_CONTENT
+}
+
+fn webhooks() -> SaleorWebhooks {
+    SaleorWebhooks::new()
+        .with(ProductCreatedWebhook)
+        .route("/api/webhooks/product-deleted", post(product_deleted))
+}
+
+#[test]
+fn declared_webhooks_are_listed_for_the_manifest() {
+    let manifest = webhooks().manifest("https://app.example.com");
+    assert_eq!(manifest.len(), 1);
+    assert_eq!(manifest[0].target_url, "https://app.example.com/api/webhooks/product-created");
+}
+
+#[tokio::test]
+async fn webhooks_are_served_behind_the_webhook_middleware() {
+    let mut config = AppConfig::default();
+    config.limits.webhooks.body_limit_bytes = 64;
+    let app = TestApp::with_routes(config.clone(), Router::new().saleor_webhooks(&config, webhooks())).await;
+
+    let payload = json!({ "product": { "id": "UHJvZHVjdDox" } });
+    let response = app.deliver_webhook("/api/webhooks/product-created", "product_created", &payload).await;
+    assert_eq!(response.status, StatusCode::ACCEPTED, "{}", response.text());
+    let response = app.deliver_webhook("/api/webhooks/product-deleted", "product_deleted", &payload).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT, "{}", response.text());
+
+    // the limits of `WEBHOOK_*` apply instead of the default ones
+    let oversized = json!({ "product": { "id": "UHJvZHVjdDox", "name": "a".repeat(64) } });
+    for path in ["/api/webhooks/product-created", "/api/webhooks/product-deleted"] {
+        let response = app.deliver_webhook(path, "product_created", &oversized).await;
+        assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn webhooks_share_the_rate_limit_of_the_app() {
+    let mut config = AppConfig::default();
+    config.webhook_rate_limit.capacity = 1;
+    config.webhook_rate_limit.refill_per_second = 0.01;
+    let app = TestApp::with_routes(config.clone(), Router::new().saleor_webhooks(&config, webhooks())).await;
+
+    let payload = json!({ "product": { "id": "UHJvZHVjdDox" } });
+    let response = app.deliver_webhook("/api/webhooks/product-created", "product_created", &payload).await;
+    assert_eq!(response.status, StatusCode::ACCEPTED, "{}", response.text());
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+    // reloaded limits apply to them as well
+    config.webhook_rate_limit.refill_per_second = 1e6;
+    app.reloader.apply(config, "test").unwrap();
+    let response = app.deliver_webhook("/api/webhooks/product-created", "product_created", &payload).await;
+    assert_eq!(response.status, StatusCode::ACCEPTED, "{}", response.text());
+}
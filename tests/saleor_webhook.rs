@@ -0,0 +1,71 @@
+use std::sync::Mutex;
+
+use axum::{http::StatusCode, Json, Router};
+use saleor_app::{
+    config::AppConfig,
+    saleor::{manifest_problems, saleor_webhook, DeclaredWebhook, SaleorAsyncWebhookEvent, SaleorSyncWebhookEvent, WebhookContext},
+    testing::TestApp,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Products delivered to [`product_created`], by installation.
+static CREATED: Mutex<Vec<(String, String)>> = Mutex::new(vec![]);
+
+#[derive(Deserialize, Debug)]
+struct ProductCreated {
+    product: Option<Product>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Product {
+    name: String,
+}
+
+#[saleor_webhook(event = "PRODUCT_CREATED", query_path = "tests/fixtures/subscriptions/product_created.graphql")]
+async fn product_created(context: &WebhookContext, payload: ProductCreated) -> StatusCode {
+    if let Some(product) = payload.product {
+        CREATED.lock().unwrap().push((context.auth_data.saleor_api_url.clone(), product.name));
+    }
+    StatusCode::OK
+}
+
+#[saleor_webhook(sync_event = "CHECKOUT_CALCULATE_TAXES", query_path = "tests/fixtures/subscriptions/checkout_calculate_taxes.graphql", path = "/api/taxes", name = "Taxes")]
+async fn calculate_taxes(_payload: Value) -> Json<Value> {
+    Json(json!({ "shipping_price_gross_amount": 0 }))
+}
+
+#[test]
+fn declared_webhooks_build_their_manifest_entry() {
+    let webhook = ProductCreatedWebhook::manifest("https://app.example.com");
+    assert_eq!(webhook.name, "product_created");
+    assert_eq!(webhook.target_url, "https://app.example.com/api/webhooks/product-created");
+    assert_eq!(webhook.async_events, Some(vec![SaleorAsyncWebhookEvent::ProductCreated]));
+    assert_eq!(webhook.sync_events, None);
+    assert!(webhook.query.contains("... on ProductCreated"), "{}", webhook.query);
+
+    let taxes = CalculateTaxesWebhook::manifest("https://app.example.com");
+    assert_eq!((taxes.name.as_str(), taxes.target_url.as_str()), ("Taxes", "https://app.example.com/api/taxes"));
+    assert_eq!(taxes.sync_events, Some(vec![SaleorSyncWebhookEvent::CheckoutCalculateTaxes]));
+
+    let manifest = saleor_app::saleor::SaleorManifest { webhooks: Some(vec![webhook, taxes]), ..saleor_app::app::app_manifest("https://app.example.com") };
+    assert!(manifest_problems(&manifest).is_empty(), "{:?}", manifest_problems(&manifest));
+}
+
+#[tokio::test]
+async fn declared_webhooks_route_verified_deliveries_to_the_function() {
+    let router = CalculateTaxesWebhook.route(ProductCreatedWebhook.route(Router::new()));
+    let app = TestApp::with_routes(AppConfig::default(), router).await;
+
+    let payload = json!({ "product": { "id": "UHJvZHVjdDox", "name": "Apple Juice" } });
+    let response = app.deliver_webhook(ProductCreatedWebhook::PATH, "product_created", &payload).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(*CREATED.lock().unwrap(), vec![(app.saleor.api_url(), "Apple Juice".to_string())]);
+
+    let response = app.deliver_signed_webhook(ProductCreatedWebhook::PATH, "product_created", payload.to_string(), "forged..signature").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(CREATED.lock().unwrap().len(), 1);
+
+    let response = app.deliver_webhook("/api/taxes", "checkout_calculate_taxes", &json!({})).await;
+    assert_eq!(response.json::<Value>()["shipping_price_gross_amount"], 0);
+}
@@ -0,0 +1,51 @@
+use saleor_app::{saleor::SaleorAsyncWebhookEvent, scaffold::{parse_webhook_event, scaffold, AplBackend, ScaffoldOptions}};
+
+fn project_dir(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(uuid::Uuid::new_v4().to_string()).join(name)
+}
+
+#[test]
+fn scaffold_generates_project_with_webhook_stubs() {
+    let dir = project_dir("my-app");
+    let options = ScaffoldOptions {
+        name: "My App".to_string(),
+        apl: AplBackend::Memory,
+        webhooks: vec![parse_webhook_event("order_created").unwrap(), SaleorAsyncWebhookEvent::ProductDeleted],
+        saleor_app_path: None,
+    };
+    let files = scaffold(&dir, &options).unwrap();
+    assert_eq!(files.len(), 7);
+
+    let cargo_toml = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    assert!(cargo_toml.contains("name = \"my-app\""));
+    assert!(cargo_toml.contains("saleor-app = { git = "));
+
+    let main_rs = std::fs::read_to_string(dir.join("src/main.rs")).unwrap();
+    assert!(main_rs.contains("let apl_store = MemoryAplStore::new();"));
+    assert!(main_rs.contains("const APP_NAME: &str = \"My App\";"));
+    assert!(main_rs.contains(".saleor_webhooks(&config, webhooks::webhooks())"));
+    assert!(!main_rs.contains("{{"), "unreplaced placeholder in main.rs");
+
+    let webhooks_rs = std::fs::read_to_string(dir.join("src/webhooks.rs")).unwrap();
+    assert!(webhooks_rs.contains("pub const ORDER_CREATED_PATH: &str = \"/api/webhooks/order-created\";"));
+    assert!(webhooks_rs.contains("... on ProductDeleted {"));
+    assert!(webhooks_rs.contains(".route(PRODUCT_DELETED_PATH, post(product_deleted))"));
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn scaffold_refuses_existing_directory() {
+    let dir = project_dir("existing");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let options = ScaffoldOptions { name: "existing".to_string(), apl: AplBackend::File, webhooks: vec![], saleor_app_path: None };
+    assert!(scaffold(&dir, &options).is_err());
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn unknown_events_are_rejected() {
+    assert!(parse_webhook_event("PRODUCT_EXPLODED").is_err());
+}
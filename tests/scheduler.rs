@@ -0,0 +1,153 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use saleor_app::{
+    http_client::HttpClient,
+    locks::{DistributedLock, MemoryLock},
+    saleor::{AplId, AplStore, AuthData},
+    scheduler::{JobContext, JobError, JobReport, Schedule, Scheduler},
+    testing::MockAplStore,
+};
+use time::{Date, Month, OffsetDateTime, Time};
+
+async fn apl_with(saleor_api_urls: &[&str]) -> MockAplStore {
+    let apl = MockAplStore::new();
+    for saleor_api_url in saleor_api_urls {
+        let auth_data = AuthData::new(*saleor_api_url, "app-token");
+        apl.set(&AplId::from_auth_data(&auth_data), auth_data).await.unwrap();
+    }
+    apl
+}
+
+fn at(year: i32, month: Month, day: u8, hour: u8, minute: u8, second: u8) -> OffsetDateTime {
+    Date::from_calendar_date(year, month, day).unwrap().with_time(Time::from_hms(hour, minute, second).unwrap()).assume_utc()
+}
+
+#[test]
+fn cron_schedules_find_their_next_minute() {
+    let nightly = Schedule::cron("30 3 * * *").unwrap();
+    assert_eq!(nightly.next_after(at(2024, Month::March, 1, 12, 0, 0)), Some(at(2024, Month::March, 2, 3, 30, 0)));
+    assert_eq!(nightly.next_after(at(2024, Month::March, 2, 3, 29, 59)), Some(at(2024, Month::March, 2, 3, 30, 0)));
+
+    let quarter_hours = Schedule::cron("*/15 8-18 * * 1-5").unwrap();
+    // a friday evening, the next run is on monday
+    assert_eq!(quarter_hours.next_after(at(2024, Month::March, 1, 18, 50, 0)), Some(at(2024, Month::March, 4, 8, 0, 0)));
+
+    // either the day of the month or the day of the week
+    let first_or_sunday = Schedule::cron("0 0 1 * 7").unwrap();
+    assert_eq!(first_or_sunday.next_after(at(2024, Month::March, 1, 12, 0, 0)), Some(at(2024, Month::March, 3, 0, 0, 0)));
+
+    assert_eq!(Schedule::cron("@monthly").unwrap().next_after(at(2024, Month::December, 15, 0, 0, 0)), Some(at(2025, Month::January, 1, 0, 0, 0)));
+    assert_eq!(Schedule::cron("0 0 30 2 *").unwrap().next_after(at(2024, Month::January, 1, 0, 0, 0)), None);
+    assert!(Schedule::cron("60 * * * *").is_err());
+    assert!(Schedule::cron("0 0 * *").is_err());
+}
+
+#[tokio::test]
+async fn jobs_run_for_every_installation() {
+    let apl = apl_with(&["https://a.saleor.cloud/graphql/", "https://b.saleor.cloud/graphql/"]).await;
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let job_seen = seen.clone();
+    let scheduler = Scheduler::new(Arc::new(apl), HttpClient::default()).with_job("resync", Schedule::every(Duration::from_secs(3600)), move |ctx: JobContext| {
+        let seen = job_seen.clone();
+        async move {
+            seen.lock().unwrap().push(ctx.auth_data.saleor_api_url.clone());
+            match ctx.auth_data.saleor_api_url.contains("b.saleor") {
+                true => Err(JobError("catalog unavailable".to_string())),
+                false => Ok(()),
+            }
+        }
+    });
+
+    let report = scheduler.run_now("resync").await.unwrap().unwrap();
+    assert_eq!(report, JobReport { succeeded: 1, failed: 1, skipped: 0 });
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort();
+    assert_eq!(seen, ["https://a.saleor.cloud/graphql/", "https://b.saleor.cloud/graphql/"]);
+    assert!(scheduler.run_now("unknown").await.is_none());
+}
+
+#[tokio::test]
+async fn overlapping_runs_are_skipped() {
+    let apl = apl_with(&["https://a.saleor.cloud/graphql/"]).await;
+    let scheduler = Scheduler::new(Arc::new(apl), HttpClient::default()).with_job("slow", Schedule::every(Duration::from_secs(3600)), |_: JobContext| async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        Ok(())
+    });
+
+    let (first, second) = tokio::join!(scheduler.run_now("slow"), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        scheduler.run_now("slow").await
+    });
+    assert_eq!(first.unwrap().unwrap(), JobReport { succeeded: 1, failed: 0, skipped: 0 });
+    assert_eq!(second.unwrap().unwrap(), JobReport { succeeded: 0, failed: 0, skipped: 1 });
+
+    // the installation is free again once the run finished
+    assert_eq!(scheduler.run_now("slow").await.unwrap().unwrap().succeeded, 1);
+}
+
+#[tokio::test]
+async fn replicas_sharing_a_lock_run_jobs_once() {
+    let apl = Arc::new(apl_with(&["https://a.saleor.cloud/graphql/"]).await);
+    let lock = Arc::new(MemoryLock::new());
+    let replica = || {
+        Scheduler::new(apl.clone(), HttpClient::default())
+            .with_lock(lock.clone(), Duration::from_secs(60))
+            .with_job("slow", Schedule::every(Duration::from_secs(3600)), |_: JobContext| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok(())
+            })
+    };
+    let (first, second) = (replica(), replica());
+
+    let (first_report, second_report) = tokio::join!(first.run_now("slow"), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        second.run_now("slow").await
+    });
+    assert_eq!(first_report.unwrap().unwrap(), JobReport { succeeded: 1, failed: 0, skipped: 0 });
+    assert_eq!(second_report.unwrap().unwrap(), JobReport { succeeded: 0, failed: 0, skipped: 1 });
+
+    // the lock is released after the run
+    assert_eq!(second.run_now("slow").await.unwrap().unwrap().succeeded, 1);
+}
+
+#[tokio::test]
+async fn memory_locks_expire_and_are_only_released_by_their_holder() {
+    let lock = MemoryLock::new();
+    let token = lock.try_acquire("job", Duration::from_millis(100)).await.unwrap().unwrap();
+    assert_eq!(lock.try_acquire("job", Duration::from_secs(60)).await.unwrap(), None);
+    assert!(lock.try_acquire("other-job", Duration::from_secs(60)).await.unwrap().is_some());
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let next_token = lock.try_acquire("job", Duration::from_secs(60)).await.unwrap().unwrap();
+    // the expired holder can't release the key taken over in the meantime
+    lock.release("job", &token).await.unwrap();
+    assert_eq!(lock.try_acquire("job", Duration::from_secs(60)).await.unwrap(), None);
+
+    lock.release("job", &next_token).await.unwrap();
+    assert!(lock.try_acquire("job", Duration::from_secs(60)).await.unwrap().is_some());
+}
+
+// the clock only moves on once every task waits, so the runs that are due finish before the test looks
+#[tokio::test(start_paused = true)]
+async fn started_schedulers_run_jobs_until_stopped() {
+    let apl = apl_with(&["https://a.saleor.cloud/graphql/"]).await;
+    let runs = Arc::new(Mutex::new(0));
+    let job_runs = runs.clone();
+    let handle = Scheduler::new(Arc::new(apl), HttpClient::default())
+        .with_job("tick", Schedule::every(Duration::from_millis(20)), move |_: JobContext| {
+            let runs = job_runs.clone();
+            async move {
+                *runs.lock().unwrap() += 1;
+                Ok(())
+            }
+        })
+        .start();
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    drop(handle);
+    let stopped_at = *runs.lock().unwrap();
+    assert_eq!(stopped_at, 7);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(*runs.lock().unwrap(), stopped_at);
+}
@@ -0,0 +1,131 @@
+use std::{net::TcpListener, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use axum::{extract::State, http::{header::AUTHORIZATION, HeaderMap, StatusCode, Uri}, routing::post, Json, Router};
+use saleor_app::{
+    config::AppConfig,
+    http_client::HttpClient,
+    saleor::search::{self, *},
+    search::{document_key, MeilisearchIndex, ProductDocument, SearchError, SearchIndex},
+    testing::{webhook_fixture, MockSaleor, TestApp},
+};
+use serde_json::{json, Value};
+
+#[derive(Clone, Default)]
+struct RecordingIndex {
+    upserted: Arc<Mutex<Vec<ProductDocument>>>,
+    deleted: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl SearchIndex for RecordingIndex {
+    async fn upsert(&self, documents: &[ProductDocument]) -> Result<(), SearchError> {
+        self.upserted.lock().unwrap().extend_from_slice(documents);
+        Ok(())
+    }
+
+    async fn delete(&self, _saleor_api_url: &str, product_ids: &[String]) -> Result<(), SearchError> {
+        self.deleted.lock().unwrap().extend_from_slice(product_ids);
+        Ok(())
+    }
+}
+
+async fn search_app(index: RecordingIndex) -> TestApp {
+    TestApp::with_routes(AppConfig::default(), search::router(index)).await
+}
+
+#[tokio::test]
+async fn updated_products_are_indexed() {
+    let index = RecordingIndex::default();
+    let app = search_app(index.clone()).await;
+
+    let response = app.deliver_webhook_fixture(PRODUCT_CHANGED_PATH, "product_updated", "search_product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let upserted = index.upserted.lock().unwrap();
+    assert_eq!(upserted.len(), 1);
+    assert_eq!(upserted[0].saleor_api_url, app.saleor.api_url());
+    assert_eq!(upserted[0].category.as_deref(), Some("T-shirts"));
+    assert_eq!(upserted[0].variants, ["S", "M", "L"]);
+    assert_eq!(upserted[0].skus, ["mono-tee-s", "mono-tee-m"]);
+    // unpublished channels aren't searchable
+    assert_eq!(upserted[0].channels, ["default-channel"]);
+}
+
+#[tokio::test]
+async fn variant_changes_reindex_their_product() {
+    let index = RecordingIndex::default();
+    let app = search_app(index.clone()).await;
+
+    let product = webhook_fixture("search_product_updated")["product"].clone();
+    let payload = json!({ "productVariant": { "product": product } });
+    let response = app.deliver_webhook(PRODUCT_VARIANT_CHANGED_PATH, "product_variant_deleted", &payload).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(index.upserted.lock().unwrap()[0].id, "UHJvZHVjdDo3Mg==");
+}
+
+#[tokio::test]
+async fn deleted_products_are_removed() {
+    let index = RecordingIndex::default();
+    let app = search_app(index.clone()).await;
+
+    let response = app.deliver_webhook_fixture(PRODUCT_DELETED_PATH, "product_deleted", "search_product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(*index.deleted.lock().unwrap(), ["UHJvZHVjdDo3Mg=="]);
+    assert!(index.upserted.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn reindex_pushes_the_catalog() {
+    let saleor = MockSaleor::start().await;
+    let product = webhook_fixture("search_product_updated")["product"].clone();
+    let other = json!({ "id": "UHJvZHVjdDo3Mw==", "name": "Gift card", "slug": "gift-card", "seoDescription": null, "updatedAt": "2024-03-01T00:00:00+00:00", "category": null, "productType": { "name": "Gift card" }, "thumbnail": null, "channelListings": null, "variants": null });
+    saleor.respond_to("ProductsPage", json!({
+        "products": {
+            "pageInfo": { "hasNextPage": false, "endCursor": "YXJyYXljb25uZWN0aW9uOjE=" },
+            "edges": [{ "node": product }, { "node": other }],
+        },
+    }));
+    let index = RecordingIndex::default();
+
+    let indexed = search::reindex(&HttpClient::default(), &saleor.auth_data(), &index).await.unwrap();
+    assert_eq!(indexed, 2);
+    assert_eq!(index.upserted.lock().unwrap()[1].channels, Vec::<String>::new());
+    assert_eq!(saleor.requests()[0]["variables"], json!({ "first": 100, "after": null }));
+}
+
+/// Uri, authorization header and body of a request to the search engine
+type CapturedRequest = (String, Option<String>, Value);
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<CapturedRequest>>>);
+
+async fn capture(State(captured): State<Captured>, uri: Uri, headers: HeaderMap, Json(body): Json<Value>) -> StatusCode {
+    let authorization = headers.get(AUTHORIZATION).map(|value| value.to_str().unwrap().to_string());
+    captured.0.lock().unwrap().push((uri.to_string(), authorization, body));
+    StatusCode::ACCEPTED
+}
+
+#[tokio::test]
+async fn meilisearch_documents_are_keyed_per_installation() {
+    let captured = Captured::default();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = Router::new().route("/indexes/products/documents", post(capture)).route("/indexes/products/documents/delete-batch", post(capture)).with_state(captured.clone());
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(router.into_make_service()));
+
+    let index = MeilisearchIndex::new(HttpClient::default(), &url, Some("master-key"), "products");
+    let product: IndexedProduct = serde_json::from_value(webhook_fixture("search_product_updated")["product"].clone()).unwrap();
+    let document = product.into_document("https://example.saleor.cloud/graphql/");
+    index.upsert(std::slice::from_ref(&document)).await.unwrap();
+    index.delete("https://example.saleor.cloud/graphql/", std::slice::from_ref(&document.id)).await.unwrap();
+
+    let captured = captured.0.lock().unwrap();
+    let key = document_key("https://example.saleor.cloud/graphql/", "UHJvZHVjdDo3Mg==");
+    assert!(key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    assert_eq!(captured[0].0, "/indexes/products/documents?primaryKey=key");
+    assert_eq!(captured[0].1.as_deref(), Some("Bearer master-key"));
+    assert_eq!(captured[0].2[0]["key"], key.as_str());
+    assert_eq!(captured[0].2[0]["name"], "Monospace Tee");
+    assert_eq!(captured[1].2, json!([key]));
+}
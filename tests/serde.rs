@@ -0,0 +1,279 @@
+use std::{collections::BTreeSet, fmt::Debug};
+
+use axum::http::StatusCode;
+use proptest::{collection::vec, option, prelude::*, sample::{select, subsequence}};
+use saleor_app::{saleor::*, testing::TestApp};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/saleor/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+/// Deserializes and serializes again, the JSON has to come out unchanged.
+fn assert_round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(json: &Value) -> T {
+    let value: T = serde_json::from_value(json.clone()).unwrap_or_else(|e| panic!("unable to deserialize {}: {}", json, e));
+    assert_eq!(&serde_json::to_value(&value).unwrap(), json);
+    let again: T = serde_json::from_value(serde_json::to_value(&value).unwrap()).unwrap();
+    assert_eq!(again, value);
+    value
+}
+
+/// The serialized names of every variant of a unit enum, taken from serde's `unknown variant` error.
+fn variants<T: DeserializeOwned + Debug>() -> BTreeSet<String> {
+    let error = serde_json::from_value::<T>(json!("__UNKNOWN__")).unwrap_err().to_string();
+    // "expected `A`", "expected `A` or `B`" or "expected one of `A`, `B`, `C`"
+    let (_, expected) = error.split_once("expected ").unwrap_or_else(|| panic!("unexpected error {}", error));
+    let expected = expected.split(" at line").next().unwrap();
+    let expected = expected.strip_prefix("one of ").unwrap_or(expected);
+    expected
+        .split(", ")
+        .flat_map(|variants| variants.split(" or "))
+        .map(|variant| variant.trim_matches('`').to_string())
+        .collect()
+}
+
+/// The values of an enum in the GraphQL schema the app is built against.
+fn schema_enum(name: &str) -> BTreeSet<String> {
+    let schema = include_str!("../schemas/saleor.graphql");
+    let start = schema.find(&format!("\nenum {} {{", name)).unwrap_or_else(|| panic!("enum {} not in schema", name));
+    let body = &schema[start..];
+    let body = &body[body.find('{').unwrap() + 1..body.find("\n}").unwrap()];
+
+    let mut values = BTreeSet::new();
+    let mut in_description = false;
+    for line in body.lines().map(str::trim) {
+        if line.starts_with("\"\"\"") {
+            // one line descriptions open and close on the same line
+            in_description = !(in_description || (line.len() > 3 && line.ends_with("\"\"\"")));
+            continue;
+        }
+        if !in_description && !line.is_empty() && !line.starts_with('#') {
+            values.insert(line.split_whitespace().next().unwrap().to_string());
+        }
+    }
+    values
+}
+
+/// Every variant matches a value of the schema and the other way around, and each round-trips.
+fn assert_matches_schema<T: Serialize + DeserializeOwned + PartialEq + Debug>(schema_name: &str) {
+    let schema = schema_enum(schema_name);
+    let variants = variants::<T>();
+    assert_eq!(
+        variants.difference(&schema).collect::<Vec<_>>(),
+        Vec::<&String>::new(),
+        "variants of {} missing in the schema",
+        std::any::type_name::<T>()
+    );
+    assert_eq!(
+        schema.difference(&variants).collect::<Vec<_>>(),
+        Vec::<&String>::new(),
+        "values of {} missing in {}",
+        schema_name,
+        std::any::type_name::<T>()
+    );
+    for variant in variants {
+        assert_round_trip::<T>(&json!(variant));
+    }
+}
+
+#[test]
+fn permissions_match_schema() {
+    assert_matches_schema::<SaleorPermission>("PermissionEnum");
+}
+
+#[test]
+fn app_permissions_are_permissions() {
+    // apps can't request every permission a user can have
+    let permissions = variants::<SaleorPermission>();
+    for permission in variants::<SaleorAppPermission>() {
+        assert!(permissions.contains(&permission), "{} is not a permission", permission);
+        assert_round_trip::<SaleorAppPermission>(&json!(permission));
+    }
+}
+
+#[test]
+fn webhook_events_match_schema() {
+    assert_matches_schema::<SaleorAsyncWebhookEvent>("WebhookEventTypeAsyncEnum");
+    assert_matches_schema::<SaleorSyncWebhookEvent>("WebhookEventTypeSyncEnum");
+}
+
+#[test]
+fn extension_enums_match_schema() {
+    assert_matches_schema::<SaleorAppExtensionMount>("AppExtensionMountEnum");
+    assert_matches_schema::<SaleorAppExtensionTarget>("AppExtensionTargetEnum");
+}
+
+#[test]
+fn fulfillment_enums_match_schema() {
+    assert_matches_schema::<fulfillments::FulfillmentStatus>("FulfillmentStatus");
+}
+
+#[test]
+fn payment_enums_match_schema() {
+    assert_matches_schema::<payments::TransactionFlowStrategy>("TransactionFlowStrategyEnum");
+    assert_matches_schema::<payments::TransactionActionKind>("TransactionActionEnum");
+    assert_matches_schema::<payments::TransactionEventType>("TransactionEventTypeEnum");
+
+    // results are reported as transaction events
+    let events = schema_enum("TransactionEventTypeEnum");
+    let results = [
+        variants::<payments::TransactionSessionResult>(),
+        variants::<payments::TransactionChargeResult>(),
+        variants::<payments::TransactionRefundResult>(),
+        variants::<payments::TransactionCancelResult>(),
+    ];
+    for result in results.into_iter().flatten() {
+        assert!(events.contains(&result), "{} is not a transaction event", result);
+    }
+}
+
+#[test]
+fn captured_manifests_round_trip() {
+    let manifest: SaleorManifest = assert_round_trip(&fixture("manifest"));
+    assert_eq!(manifest.extensions.unwrap()[1].target, SaleorAppExtensionTarget::Popup);
+    assert_eq!(manifest.webhooks.unwrap()[2].sync_events, Some(vec![SaleorSyncWebhookEvent::CheckoutCalculateTaxes]));
+
+    let manifest: SaleorManifest = assert_round_trip(&fixture("manifest_minimal"));
+    assert_eq!(manifest.extensions, None);
+}
+
+#[test]
+fn every_extension_round_trips() {
+    for mount in variants::<SaleorAppExtensionMount>() {
+        for target in variants::<SaleorAppExtensionTarget>() {
+            assert_round_trip::<SaleorAppExtension>(&json!({
+                "label": "Extension",
+                "mount": mount,
+                "target": target,
+                "permissions": ["MANAGE_PRODUCTS"],
+                "url": "/app",
+            }));
+        }
+    }
+}
+
+#[test]
+fn every_webhook_round_trips() {
+    for event in variants::<SaleorAsyncWebhookEvent>() {
+        assert_round_trip::<SaleorWebhookManifest>(&json!({
+            "name": event,
+            "asyncEvents": [event],
+            "query": "subscription { event { __typename } }",
+            "targetUrl": "https://localhost/api/webhooks",
+            "isActive": true,
+        }));
+    }
+    for event in variants::<SaleorSyncWebhookEvent>() {
+        assert_round_trip::<SaleorWebhookManifest>(&json!({
+            "name": event,
+            "syncEvents": [event],
+            "query": "subscription { event { __typename } }",
+            "targetUrl": "https://localhost/api/webhooks",
+        }));
+    }
+}
+
+#[tokio::test]
+async fn served_manifest_round_trips() {
+    let app = TestApp::new().await;
+
+    let response = app.get("/api/manifest").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let manifest: SaleorManifest = assert_round_trip(&response.json());
+    assert_eq!(manifest.token_target_url, "https://localhost/api/register");
+}
+
+/// Every variant of a unit enum, in the order serde lists them.
+fn all<T: DeserializeOwned + Debug>() -> Vec<T> {
+    variants::<T>().into_iter().map(|variant| serde_json::from_value(json!(variant)).unwrap()).collect()
+}
+
+/// Any subset of the variants of a unit enum.
+fn some_of<T: DeserializeOwned + Debug + Clone + 'static>() -> impl Strategy<Value = Vec<T>> {
+    let all = all::<T>();
+    let len = all.len();
+    subsequence(all, 0..=len)
+}
+
+fn extension_options() -> impl Strategy<Value = SaleorAppExtensionOptions> {
+    let widget_target = option::of(select(all::<SaleorWidgetMethod>()).prop_map(|method| SaleorWidgetTarget { method }));
+    let new_tab_target = option::of(select(all::<SaleorNewTabMethod>()).prop_map(|method| SaleorNewTabTarget { method }));
+    (widget_target, new_tab_target).prop_map(|(widget_target, new_tab_target)| SaleorAppExtensionOptions { widget_target, new_tab_target })
+}
+
+fn extension() -> impl Strategy<Value = SaleorAppExtension> {
+    let mount = select(all::<SaleorAppExtensionMount>());
+    let target = select(all::<SaleorAppExtensionTarget>());
+    (any::<String>(), mount, target, some_of::<SaleorAppPermission>(), any::<String>(), option::of(extension_options()))
+        .prop_map(|(label, mount, target, permissions, url, options)| SaleorAppExtension { label, mount, target, permissions, url, options })
+}
+
+fn webhook() -> impl Strategy<Value = SaleorWebhookManifest> {
+    (any::<String>(), option::of(some_of::<SaleorAsyncWebhookEvent>()), option::of(some_of::<SaleorSyncWebhookEvent>()), any::<String>(), any::<String>(), any::<Option<bool>>())
+        .prop_map(|(name, async_events, sync_events, query, target_url, is_active)| SaleorWebhookManifest { name, async_events, sync_events, query, target_url, is_active })
+}
+
+fn manifest() -> impl Strategy<Value = SaleorManifest> {
+    let required = (any::<String>(), any::<String>(), any::<String>(), some_of::<SaleorAppPermission>(), any::<String>(), any::<String>());
+    let optional = (
+        any::<Option<String>>(),
+        any::<Option<String>>(),
+        any::<Option<String>>(),
+        any::<Option<String>>(),
+        any::<Option<String>>(),
+        any::<Option<String>>(),
+        option::of(vec(extension(), 0..4)),
+        option::of(vec(webhook(), 0..4)),
+        option::of(any::<String>().prop_map(|default| SaleorBrand { logo: SaleorLogo { default } })),
+    );
+    (required, optional).prop_map(
+        |(
+            (id, version, name, permissions, app_url, token_target_url),
+            (required_saleor_version, author, about, data_privacy_url, homepage_url, support_url, extensions, webhooks, brand),
+        )| SaleorManifest {
+            id,
+            version,
+            required_saleor_version,
+            name,
+            permissions,
+            app_url,
+            token_target_url,
+            author,
+            about,
+            data_privacy_url,
+            homepage_url,
+            support_url,
+            extensions,
+            webhooks,
+            brand,
+        },
+    )
+}
+
+/// Serializes and deserializes again, the value and its JSON have to come out unchanged.
+fn assert_value_round_trips<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+    let json = serde_json::to_value(value).unwrap();
+    let again: T = assert_round_trip(&json);
+    assert_eq!(&again, value);
+}
+
+proptest! {
+    #[test]
+    fn generated_manifests_round_trip(manifest in manifest()) {
+        assert_value_round_trips(&manifest);
+    }
+
+    #[test]
+    fn permission_sets_round_trip(app_permissions in some_of::<SaleorAppPermission>(), permissions in some_of::<SaleorPermission>()) {
+        assert_value_round_trips(&app_permissions);
+        assert_value_round_trips(&permissions);
+    }
+
+    #[test]
+    fn event_sets_round_trip(async_events in some_of::<SaleorAsyncWebhookEvent>(), sync_events in some_of::<SaleorSyncWebhookEvent>()) {
+        assert_value_round_trips(&async_events);
+        assert_value_round_trips(&sync_events);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, net::TcpListener, sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::{body::Body, extract::{Path, State}, http::{header::CONTENT_TYPE, HeaderMap, Request, StatusCode}, routing::post, Form, Json, Router};
+use rust_decimal::Decimal;
+use saleor_app::{
+    config::AppConfig,
+    http_client::HttpClient,
+    saleor::{
+        money::Money,
+        payments::{TransactionActionKind, TransactionEventType, TRANSACTION_INITIALIZE_SESSION_PATH, TRANSACTION_PROCESS_SESSION_PATH, TRANSACTION_REFUND_REQUESTED_PATH},
+        stripe::{self, *},
+    },
+    testing::{webhook_fixture, MockSaleor, TestApp, TestResponse},
+};
+use serde_json::{json, Value};
+
+const WEBHOOK_SECRET: &str = "whsec_test";
+
+/// A request the mock Stripe API got, with its form parameters.
+#[derive(Debug, Clone)]
+struct StripeRequest {
+    path: String,
+    params: HashMap<String, String>,
+    idempotency_key: Option<String>,
+}
+
+/// The Stripe API, answering every path with a canned object.
+#[derive(Clone, Default)]
+struct MockStripe {
+    url: String,
+    objects: Arc<Mutex<HashMap<String, Value>>>,
+    requests: Arc<Mutex<Vec<StripeRequest>>>,
+}
+
+impl MockStripe {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stripe = Self { url: format!("http://{}", listener.local_addr().unwrap()), ..Default::default() };
+        let router = Router::new()
+            .route("/v1/*path", post(mock_post).get(mock_get))
+            .with_state(stripe.clone());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(router.into_make_service()));
+        stripe
+    }
+
+    fn respond(&self, path: &str, object: Value) {
+        self.objects.lock().unwrap().insert(format!("/v1/{path}"), object);
+    }
+
+    fn requests(&self) -> Vec<StripeRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn mock_post(State(stripe): State<MockStripe>, Path(path): Path<String>, headers: HeaderMap, Form(params): Form<Vec<(String, String)>>) -> (StatusCode, Json<Value>) {
+    let path = format!("/v1/{path}");
+    stripe.requests.lock().unwrap().push(StripeRequest {
+        path: path.clone(),
+        params: params.into_iter().collect(),
+        idempotency_key: headers.get("idempotency-key").map(|key| key.to_str().unwrap().to_string()),
+    });
+    mock_object(&stripe, &path)
+}
+
+async fn mock_get(State(stripe): State<MockStripe>, Path(path): Path<String>) -> (StatusCode, Json<Value>) {
+    mock_object(&stripe, &format!("/v1/{path}"))
+}
+
+fn mock_object(stripe: &MockStripe, path: &str) -> (StatusCode, Json<Value>) {
+    match stripe.objects.lock().unwrap().get(path) {
+        Some(object) => (StatusCode::OK, Json(object.clone())),
+        None => (StatusCode::NOT_FOUND, Json(json!({ "error": { "message": format!("No such object: {path}") } }))),
+    }
+}
+
+fn payment_intent(status: &str, capture_method: &str, saleor: &MockSaleor) -> Value {
+    json!({
+        "id": "pi_3Ofake",
+        "object": "payment_intent",
+        "status": status,
+        "amount": 5412,
+        "amount_capturable": if status == "requires_capture" { 5412 } else { 0 },
+        "amount_received": if status == "succeeded" { 5412 } else { 0 },
+        "currency": "usd",
+        "capture_method": capture_method,
+        "client_secret": "pi_3Ofake_secret_abc",
+        "livemode": false,
+        "metadata": { "saleor_api_url": saleor.api_url(), "saleor_transaction_id": "VHJhbnNhY3Rpb25JdGVtOjE=" },
+        "last_payment_error": null,
+    })
+}
+
+async fn stripe_app(stripe: &MockStripe) -> (TestApp, StripeConfig) {
+    let config = StripeConfig::new("sk_test_123", "pk_test_123", WEBHOOK_SECRET).with_api_url(&stripe.url);
+    let routes = stripe::router(StripeGateway::new(HttpClient::default(), config.clone()));
+    (TestApp::with_routes(AppConfig::default(), routes).await, config)
+}
+
+async fn notify(app: &TestApp, config: &StripeConfig, event: &Value) -> TestResponse {
+    let payload = serde_json::to_vec(event).unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let request = Request::post(STRIPE_WEBHOOK_PATH)
+        .header(STRIPE_SIGNATURE_HEADER, config.sign(now, &payload))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(payload))
+        .unwrap();
+    app.request(request).await
+}
+
+#[tokio::test]
+async fn transactions_create_payment_intents() {
+    let stripe = MockStripe::start();
+    let (app, _) = stripe_app(&stripe).await;
+    stripe.respond("payment_intents", payment_intent("requires_payment_method", "manual", &app.saleor));
+
+    let mut payload = webhook_fixture("transaction_initialize_session");
+    payload["action"]["actionType"] = json!("AUTHORIZATION");
+    payload["data"] = Value::Null;
+    let response = app.deliver_webhook(TRANSACTION_INITIALIZE_SESSION_PATH, "transaction_initialize_session", &payload).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json::<Value>(), json!({
+        "pspReference": "pi_3Ofake",
+        "result": "AUTHORIZATION_ACTION_REQUIRED",
+        "amount": 54.12,
+        "data": { "paymentIntentId": "pi_3Ofake", "clientSecret": "pi_3Ofake_secret_abc" },
+        "externalUrl": "https://dashboard.stripe.com/test/payments/pi_3Ofake",
+    }));
+
+    let requests = stripe.requests();
+    assert_eq!(requests[0].path, "/v1/payment_intents");
+    assert_eq!(requests[0].idempotency_key.as_deref(), Some("VHJhbnNhY3Rpb25JdGVtOjE="));
+    let params = &requests[0].params;
+    assert_eq!(params["amount"], "5412");
+    assert_eq!(params["currency"], "usd");
+    assert_eq!(params["capture_method"], "manual");
+    assert_eq!(params["automatic_payment_methods[enabled]"], "true");
+    assert_eq!(params["metadata[saleor_api_url]"], app.saleor.api_url());
+    assert_eq!(params["metadata[saleor_transaction_id]"], "VHJhbnNhY3Rpb25JdGVtOjE=");
+}
+
+#[tokio::test]
+async fn processed_sessions_only_accept_their_own_payment_intent() {
+    let stripe = MockStripe::start();
+    let (app, _) = stripe_app(&stripe).await;
+    stripe.respond("payment_intents/pi_3Ofake", payment_intent("succeeded", "automatic", &app.saleor));
+
+    let mut payload = webhook_fixture("transaction_initialize_session");
+    payload["data"] = json!({ "paymentIntentId": "pi_3Ofake" });
+    let response = app.deliver_webhook(TRANSACTION_PROCESS_SESSION_PATH, "transaction_process_session", &payload).await;
+    assert_eq!(response.json::<Value>()["result"], "CHARGE_SUCCESS");
+
+    // a succeeded intent of another checkout doesn't pay for this one
+    let mut other = payment_intent("succeeded", "automatic", &app.saleor);
+    other["id"] = json!("pi_3Oother");
+    other["metadata"]["saleor_transaction_id"] = json!("VHJhbnNhY3Rpb25JdGVtOjI=");
+    stripe.respond("payment_intents/pi_3Oother", other);
+    payload["data"] = json!({ "paymentIntentId": "pi_3Oother" });
+    let response = app.deliver_webhook(TRANSACTION_PROCESS_SESSION_PATH, "transaction_process_session", &payload).await;
+    assert_eq!(response.json::<Value>()["result"], "CHARGE_FAILURE");
+
+    // neither does one for a smaller amount
+    let mut cheaper = payment_intent("succeeded", "automatic", &app.saleor);
+    cheaper["amount"] = json!(100);
+    stripe.respond("payment_intents/pi_3Ofake", cheaper);
+    payload["data"] = json!({ "paymentIntentId": "pi_3Ofake" });
+    let response = app.deliver_webhook(TRANSACTION_PROCESS_SESSION_PATH, "transaction_process_session", &payload).await;
+    assert_eq!(response.json::<Value>()["result"], "CHARGE_FAILURE");
+}
+
+#[tokio::test]
+async fn refunds_are_reconciled_from_notifications() {
+    let stripe = MockStripe::start();
+    let (app, config) = stripe_app(&stripe).await;
+    let refund = json!({
+        "id": "re_3Ofake",
+        "object": "refund",
+        "amount": 1000,
+        "currency": "usd",
+        "status": "pending",
+        "payment_intent": "pi_3Ofake",
+        "metadata": {},
+    });
+    stripe.respond("refunds", refund.clone());
+    stripe.respond("payment_intents/pi_3Ofake", payment_intent("succeeded", "automatic", &app.saleor));
+    app.saleor.respond_to("transactionEventReport", json!({ "transactionEventReport": { "alreadyProcessed": false, "errors": [] } }));
+
+    let mut payload = webhook_fixture("transaction_refund_requested");
+    payload["transaction"]["pspReference"] = json!("pi_3Ofake");
+    let response = app.deliver_webhook(TRANSACTION_REFUND_REQUESTED_PATH, "transaction_refund_requested", &payload).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json::<Value>(), json!({ "pspReference": "re_3Ofake" }));
+    assert_eq!(stripe.requests()[0].params["amount"], "1000");
+
+    // a refund without metadata, like one made in the Stripe dashboard, is found through its payment intent
+    let mut succeeded = refund;
+    succeeded["status"] = json!("succeeded");
+    let event = json!({ "id": "evt_1", "type": "refund.updated", "livemode": false, "data": { "object": succeeded } });
+    let response = notify(&app, &config, &event).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let requests = app.saleor.requests();
+    let report = requests.iter().find(|request| request["query"].as_str().unwrap().contains("transactionEventReport")).unwrap();
+    assert_eq!(report["variables"], json!({
+        "id": "VHJhbnNhY3Rpb25JdGVtOjE=",
+        "pspReference": "re_3Ofake",
+        "type": "REFUND_SUCCESS",
+        "amount": 10.0,
+        "externalUrl": "https://dashboard.stripe.com/test/payments/pi_3Ofake",
+    }));
+}
+
+#[tokio::test]
+async fn notifications_must_be_signed_by_stripe() {
+    let stripe = MockStripe::start();
+    let (app, _) = stripe_app(&stripe).await;
+
+    let event = json!({ "id": "evt_2", "type": "payment_intent.succeeded", "data": { "object": payment_intent("succeeded", "automatic", &app.saleor) } });
+    let forged = StripeConfig::new("sk_test_123", "pk_test_123", "whsec_other");
+    let response = notify(&app, &forged, &event).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(app.saleor.requests().is_empty());
+
+    let config = StripeConfig::new("sk_test_123", "pk_test_123", WEBHOOK_SECRET);
+    let payload = serde_json::to_vec(&event).unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert!(config.verify(&config.sign(now, &payload), &payload, now).is_ok());
+    assert!(config.verify(&config.sign(now - 600, &payload), &payload, now).is_err());
+}
+
+#[test]
+fn payment_intents_map_to_transaction_events() {
+    assert_eq!(Money::new(Decimal::new(5412, 2), "USD").minor_units(), Some(5412));
+    assert_eq!(Money::new(Decimal::from(1500), "jpy").minor_units(), Some(1500));
+    assert_eq!(Money::new(Decimal::from(1500), "mga").minor_units(), Some(1500));
+    assert_eq!(Money::from_minor_units(1500, "KWD").amount, Decimal::new(15, 1));
+
+    let intent = |status: &str, capture_method: &str| -> StripeEvent {
+        let mut object = json!({ "id": "pi_1", "status": status, "amount": 5412, "amount_capturable": 5412, "amount_received": 0, "currency": "eur", "capture_method": capture_method });
+        if status == "requires_payment_method" {
+            object["last_payment_error"] = json!({ "message": "Your card was declined." });
+        }
+        serde_json::from_value(json!({ "id": "evt", "type": format!("payment_intent.{status}"), "data": { "object": object } })).unwrap()
+    };
+    let authorized = transaction_event(&StripeEvent { event_type: "payment_intent.amount_capturable_updated".to_string(), ..intent("requires_capture", "manual") }).unwrap().unwrap();
+    assert_eq!(authorized.event_type, TransactionEventType::AuthorizationSuccess);
+    assert_eq!(authorized.amount, Decimal::new(5412, 2));
+    assert_eq!(authorized.available_actions, Some(vec![TransactionActionKind::Charge, TransactionActionKind::Cancel]));
+
+    let failed = transaction_event(&StripeEvent { event_type: "payment_intent.payment_failed".to_string(), ..intent("requires_payment_method", "automatic") }).unwrap().unwrap();
+    assert_eq!(failed.event_type, TransactionEventType::ChargeFailure);
+    assert_eq!(failed.message.as_deref(), Some("Your card was declined."));
+
+    assert!(transaction_event(&intent("requires_action", "automatic")).unwrap().is_none());
+}
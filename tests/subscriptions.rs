@@ -0,0 +1,92 @@
+use axum::http::StatusCode;
+use saleor_app::{
+    saleor::{subscriptions::WebhookSubscription, SaleorAsyncWebhookEvent},
+    testing::{webhook_fixture, TestApp},
+    webhooks::{product_updated_subscription, PRODUCT_UPDATED_PATH},
+};
+use serde_json::json;
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Product", schema_module = "saleor_app::saleor::schema")]
+struct Product {
+    id: cynic::Id,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "ProductCreated", schema_module = "saleor_app::saleor::schema")]
+struct ProductCreated {
+    product: Option<Product>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "ProductDeleted", schema_module = "saleor_app::saleor::schema")]
+struct ProductDeleted {
+    product: Option<Product>,
+}
+
+#[derive(cynic::InlineFragments, Debug)]
+#[cynic(graphql_type = "Event", schema_module = "saleor_app::saleor::schema")]
+enum ProductEvent {
+    ProductCreated(ProductCreated),
+    ProductDeleted(ProductDeleted),
+    #[cynic(fallback)]
+    Other,
+}
+
+#[test]
+fn the_query_selects_the_fragment_on_the_event() {
+    let subscription = product_updated_subscription();
+    assert_eq!(subscription.query(), "subscription {
+  event {
+    __typename
+    ... on ProductUpdated {
+      product {
+        id
+        name
+      }
+    }
+  }
+}");
+
+    let manifest = subscription.manifest("https://app.example.com");
+    assert_eq!(manifest.target_url, format!("https://app.example.com{PRODUCT_UPDATED_PATH}"));
+    assert_eq!(manifest.async_events, Some(vec![SaleorAsyncWebhookEvent::ProductUpdated]));
+    assert_eq!(manifest.sync_events, None);
+}
+
+#[test]
+fn the_fragment_parses_the_payload() {
+    let subscription = product_updated_subscription();
+    let payload = subscription.parse(webhook_fixture("product_updated").to_string().as_bytes()).unwrap();
+    assert_eq!(payload.product.unwrap().name, "Apple Juice");
+
+    let payload = json!({ "__typename": "ProductUpdated", "product": null });
+    assert!(subscription.parse(payload.to_string().as_bytes()).unwrap().product.is_none());
+}
+
+#[test]
+fn several_events_are_selected_as_inline_fragments() {
+    let subscription = WebhookSubscription::<ProductEvent>::new("Products", "/api/webhooks/products")
+        .with_async_event(SaleorAsyncWebhookEvent::ProductCreated)
+        .with_async_event(SaleorAsyncWebhookEvent::ProductDeleted);
+    let query = subscription.query();
+    assert!(query.contains("... on ProductCreated {"), "{query}");
+    assert!(query.contains("... on ProductDeleted {"), "{query}");
+
+    for event in ["ProductCreated", "ProductDeleted"] {
+        let payload = json!({ "__typename": event, "product": { "id": "UHJvZHVjdDox" } });
+        let product = match subscription.parse(payload.to_string().as_bytes()).unwrap() {
+            ProductEvent::ProductCreated(ProductCreated { product }) if event == "ProductCreated" => product,
+            ProductEvent::ProductDeleted(ProductDeleted { product }) if event == "ProductDeleted" => product,
+            other => panic!("unexpected event {other:?}"),
+        };
+        assert_eq!(product.unwrap().id.inner(), "UHJvZHVjdDox");
+    }
+}
+
+#[tokio::test]
+async fn deliveries_are_handled_with_the_fragment() {
+    let app = TestApp::new().await;
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use rust_decimal::Decimal;
+use saleor_app::{
+    config::AppConfig,
+    saleor::taxes::{self, *},
+    testing::{webhook_fixture, TestApp},
+};
+use serde_json::{json, Value};
+
+/// Taxes everything at 10%, except checkouts in channels without a tax provider.
+struct FlatRate;
+
+#[async_trait]
+impl TaxCalculator for FlatRate {
+    async fn calculate_taxes(&self, _saleor_api_url: &str, tax_base: TaxBase) -> Result<CalculateTaxesResponse, TaxError> {
+        if tax_base.channel.slug != "default-channel" {
+            return Err(TaxError(format!("no tax provider for {}", tax_base.channel.slug)));
+        }
+        Ok(CalculateTaxesResponse::flat_rate(&tax_base, Decimal::TEN)?)
+    }
+}
+
+async fn tax_app() -> TestApp {
+    TestApp::with_routes(AppConfig::default(), taxes::router(FlatRate)).await
+}
+
+fn dec(amount: &str) -> Decimal {
+    amount.parse().unwrap()
+}
+
+#[tokio::test]
+async fn checkout_taxes_are_calculated_per_line() {
+    let app = tax_app().await;
+
+    let response = app.deliver_webhook_fixture(CHECKOUT_CALCULATE_TAXES_PATH, "checkout_calculate_taxes", "checkout_calculate_taxes").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let body: Value = response.json();
+    // the 10.00 voucher is spread over both lines, the gift card isn't taxed
+    assert_eq!(body, json!({
+        "shipping_price_gross_amount": 11.0,
+        "shipping_price_net_amount": 10.0,
+        "shipping_tax_rate": 10.0,
+        "lines": [
+            { "total_gross_amount": 36.66, "total_net_amount": 33.33, "tax_rate": 10.0 },
+            { "total_gross_amount": 16.67, "total_net_amount": 16.67, "tax_rate": 0.0 },
+        ],
+    }));
+}
+
+#[tokio::test]
+async fn failed_calculations_are_bad_gateway() {
+    let app = tax_app().await;
+
+    let mut payload = webhook_fixture("checkout_calculate_taxes");
+    payload["taxBase"]["channel"]["slug"] = json!("marketplace");
+    payload["taxBase"]["sourceObject"] = json!({ "__typename": "Order", "id": "T3JkZXI6MQ==" });
+    let response = app.deliver_webhook(ORDER_CALCULATE_TAXES_PATH, "order_calculate_taxes", &payload).await;
+    assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+}
+
+#[test]
+fn amounts_are_rounded_to_the_currency() {
+    assert_eq!(round_money(dec("1.005"), "USD"), dec("1.01"));
+    assert_eq!(round_money(dec("-1.005"), "EUR"), dec("-1.01"));
+    assert_eq!(round_money(dec("149.5"), "JPY"), dec("150"));
+    assert_eq!(round_money(dec("1.0005"), "KWD"), dec("1.001"));
+
+    let taxed = TaxedAmount::from_net(dec("19.99"), dec("19"), "EUR").unwrap();
+    assert_eq!((taxed.net, taxed.gross, taxed.tax()), (dec("19.99"), dec("23.79"), dec("3.80")));
+    let taxed = TaxedAmount::from_gross(dec("23.79"), dec("19"), "EUR").unwrap();
+    assert_eq!((taxed.net, taxed.gross), (dec("19.99"), dec("23.79")));
+}
+
+#[test]
+fn discounts_add_up_after_rounding() {
+    let mut payload = webhook_fixture("checkout_calculate_taxes");
+    let line = payload["taxBase"]["lines"][0].clone();
+    payload["taxBase"]["lines"] = json!([line, line, line]);
+    for line in payload["taxBase"]["lines"].as_array_mut().unwrap() {
+        line["totalPrice"]["amount"] = json!(10.0);
+    }
+    let tax_base = serde_json::from_value::<CalculateTaxes>(payload).unwrap().tax_base;
+
+    let totals = tax_base.discounted_line_totals().unwrap();
+    assert_eq!(totals.iter().sum::<Decimal>(), dec("20.00"));
+    assert_eq!(totals, [dec("6.67"), dec("6.67"), dec("6.66")]);
+}
+
+#[test]
+fn manifest_lists_both_events() {
+    let webhooks = taxes::manifest("https://app.example.com");
+    assert_eq!(webhooks.len(), 2);
+    assert!(webhooks.iter().all(|webhook| webhook.query.contains("... on CalculateTaxes")));
+}
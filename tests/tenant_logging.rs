@@ -0,0 +1,83 @@
+use std::{io::Write, sync::{Arc, Mutex}};
+
+use axum::http::StatusCode;
+use saleor_app::{operator_api, saleor::SaleorPermission, testing::TestApp, APP_ID};
+use serde_json::Value;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// The JSON lines logged while it's the default subscriber.
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn lines(&self) -> Vec<Value> {
+        let logs = self.0.lock().unwrap();
+        String::from_utf8_lossy(&logs).lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[tokio::test]
+async fn request_logs_are_tagged_with_the_tenant() {
+    let app = TestApp::new().await;
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt().json().with_current_span(true).with_span_list(false).with_writer(logs.clone()).finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    let lines = logs.lines();
+    let finished = lines.iter().find(|line| line["fields"]["message"] == "request finished").expect("the request was logged");
+    let span = &finished["span"];
+    assert_eq!(span["saleor_api_url"], app.saleor.api_url());
+    assert_eq!(span["installation_id"], operator_api::installation_id(&app.saleor.api_url()));
+    assert_eq!(span["app_id"], APP_ID);
+}
+
+#[tokio::test]
+async fn requests_without_a_tenant_are_not_tagged() {
+    let app = TestApp::new().await;
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt().json().with_current_span(true).with_span_list(false).with_writer(logs.clone()).finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    app.get("/api/manifest").await;
+
+    let lines = logs.lines();
+    let finished = lines.iter().find(|line| line["fields"]["message"] == "request finished").expect("the request was logged");
+    assert!(finished["span"].get("saleor_api_url").is_none());
+    assert!(finished["span"].get("app_id").is_none());
+}
+#[tokio::test]
+async fn request_logs_leave_out_the_query() {
+    let app = TestApp::new().await;
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt().json().with_current_span(true).with_span_list(false).with_writer(logs.clone()).finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    app.get("/api/manifest?auth_token=secret-token").await;
+
+    let lines = logs.lines();
+    let finished = lines.iter().find(|line| line["fields"]["message"] == "request finished").expect("the request was logged");
+    assert_eq!(finished["span"]["path"], "/api/manifest");
+    assert!(!lines.iter().any(|line| line.to_string().contains("secret-token")));
+}
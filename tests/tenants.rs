@@ -0,0 +1,96 @@
+use axum::{body::Body, http::{header::{CONTENT_TYPE, HOST}, Request, StatusCode}};
+use saleor_app::{
+    config::AppConfig,
+    saleor::{
+        AplId, AplStore, DomainTenantResolver, HeaderTenantResolver, PathPrefixTenantResolver, SaleorPermission, SubdomainTenantResolver, TenantRequest, TenantResolver,
+        TenantResolvers, TenantStrategy, DEFAULT_DOMAIN_API_URL_TEMPLATE, DEFAULT_TENANT_API_URL_TEMPLATE,
+    },
+    testing::TestApp,
+};
+use serde_json::json;
+
+fn resolve(resolver: &impl TenantResolver, request: Request<()>) -> Option<String> {
+    resolver.resolve(&TenantRequest::from_request(&request))
+}
+
+#[test]
+fn path_prefix_names_tenant() {
+    let resolver = PathPrefixTenantResolver::new(DEFAULT_TENANT_API_URL_TEMPLATE);
+
+    let request = Request::get("/t/demo/api/config").body(()).unwrap();
+    assert_eq!(resolve(&resolver, request).as_deref(), Some("https://demo.saleor.cloud/graphql/"));
+    for uri in ["/api/config", "/t/", "/t/../api/config", "/tenant/demo/api/config"] {
+        assert_eq!(resolve(&resolver, Request::get(uri).body(()).unwrap()), None, "{uri}");
+    }
+}
+
+#[test]
+fn subdomain_names_tenant() {
+    let resolver = SubdomainTenantResolver::new("apps.example.com", "https://{tenant}.example.com/graphql/");
+
+    let request = Request::get("/api/config").header(HOST, "Demo.apps.example.com:8000").body(()).unwrap();
+    assert_eq!(resolve(&resolver, request).as_deref(), Some("https://demo.example.com/graphql/"));
+    for host in ["apps.example.com", "demo.example.com", "demoapps.example.com"] {
+        let request = Request::get("/api/config").header(HOST, host).body(()).unwrap();
+        assert_eq!(resolve(&resolver, request), None, "{host}");
+    }
+}
+
+#[test]
+fn legacy_domain_names_tenant() {
+    let resolver = DomainTenantResolver::new(DEFAULT_DOMAIN_API_URL_TEMPLATE);
+
+    let request = Request::get("/api/register").header("saleor-domain", "shop.example.com:8000").body(()).unwrap();
+    assert_eq!(resolve(&resolver, request).as_deref(), Some("https://shop.example.com:8000/graphql/"));
+    for domain in ["", "shop.example.com/evil", "user@shop.example.com"] {
+        let request = Request::get("/api/register").header("saleor-domain", domain).body(()).unwrap();
+        assert_eq!(resolve(&resolver, request), None, "{domain}");
+    }
+}
+
+#[test]
+fn first_resolver_naming_tenant_wins() {
+    let resolvers = TenantResolvers::new()
+        .with_resolver(PathPrefixTenantResolver::new(DEFAULT_TENANT_API_URL_TEMPLATE))
+        .with_resolver(HeaderTenantResolver);
+
+    let request = Request::get("/api/config").header("saleor-api-url", "https://other.saleor.cloud/graphql/").body(()).unwrap();
+    assert_eq!(resolve(&resolvers, request).as_deref(), Some("https://other.saleor.cloud/graphql/"));
+    let request = Request::get("/t/demo/api/config").header("saleor-api-url", "https://other.saleor.cloud/graphql/").body(()).unwrap();
+    assert_eq!(resolve(&resolvers, request).as_deref(), Some("https://demo.saleor.cloud/graphql/"));
+}
+
+#[tokio::test]
+async fn path_prefixed_routes_answer() {
+    let app = TestApp::with_config(AppConfig {
+        tenant_strategies: vec![TenantStrategy::Header, TenantStrategy::PathPrefix { api_url_template: DEFAULT_TENANT_API_URL_TEMPLATE.to_string() }],
+        ..AppConfig::default()
+    })
+    .await;
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/t/demo/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn older_saleor_versions_install_with_the_domain() {
+    let app = TestApp::unregistered(AppConfig {
+        tenant_strategies: TenantStrategy::defaults_with_domain_template("http://{domain}/graphql/"),
+        ..AppConfig::default()
+    })
+    .await;
+
+    let request = Request::post("/api/register")
+        .header("saleor-domain", app.saleor.domain())
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "auth_token": "legacy-token" }).to_string()))
+        .unwrap();
+    let response = app.request(request).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(app.apl.store().get(&AplId::from_api_url(&app.saleor.api_url())).await.unwrap().is_some());
+
+    let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/hello").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::{http::StatusCode, routing::get, Json, Router};
+use saleor_app::{
+    config::AppConfig,
+    saleor::{SaleorAuthLayer, SaleorPermission, UserClient, UserTokenConfig, UserTokenError},
+    testing::{MockSaleor, TestApp},
+};
+use serde_json::{json, Value};
+
+async fn me(client: UserClient) -> Result<Json<Value>, UserTokenError> {
+    client.query("Me", "query Me { me { id } }", json!({})).await.map(Json)
+}
+
+async fn app(user_tokens: UserTokenConfig) -> TestApp {
+    let routes = Router::new().route("/api/me", get(me)).route_layer(SaleorAuthLayer::with_permissions(&[]));
+    TestApp::with_routes(AppConfig { user_tokens, ..Default::default() }, routes).await
+}
+
+/// An access token of the app for the test user, valid for `lifetime`.
+fn access_token(saleor: &MockSaleor, lifetime: Duration) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    saleor.sign_token(&json!({ "iat": now, "exp": now + lifetime.as_secs(), "type": "thirdparty", "app": "QXBwOjE=", "user_id": "VXNlcjox" }))
+}
+
+fn exchanges(saleor: &MockSaleor) -> usize {
+    saleor.requests().iter().filter(|request| request["query"].as_str().unwrap_or_default().contains("AppAccessToken")).count()
+}
+
+#[tokio::test]
+async fn dashboard_tokens_are_exchanged_once_per_user() {
+    let app = app(UserTokenConfig { exchange: true, ..Default::default() }).await;
+    let token = access_token(&app.saleor, Duration::from_secs(3600));
+    app.saleor.respond_to("accessToken", json!({ "app": { "accessToken": token } }));
+
+    for _ in 0..2 {
+        let response = app.as_user(&[SaleorPermission::ManageProducts]).get("/api/me").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        assert_eq!(response.json::<Value>()["me"]["id"], "VXNlcjox");
+    }
+    assert_eq!(exchanges(&app.saleor), 1);
+    let exchange = app.saleor.requests().into_iter().find(|request| request["query"].as_str().unwrap_or_default().contains("AppAccessToken")).unwrap();
+    assert_eq!(exchange["variables"]["id"], "QXBwOjE=");
+}
+
+#[tokio::test]
+async fn tokens_about_to_expire_are_exchanged_again() {
+    let app = app(UserTokenConfig { exchange: true, refresh_before: Duration::from_secs(120) }).await;
+    let token = access_token(&app.saleor, Duration::from_secs(60));
+    app.saleor.respond_to("accessToken", json!({ "app": { "accessToken": token } }));
+
+    for _ in 0..2 {
+        let response = app.as_user(&[]).get("/api/me").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    }
+    assert_eq!(exchanges(&app.saleor), 2);
+}
+
+#[tokio::test]
+async fn dashboard_tokens_are_used_without_exchange() {
+    let app = app(UserTokenConfig::default()).await;
+    let response = app.as_user(&[]).get("/api/me").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(exchanges(&app.saleor), 0);
+
+    let app = self::app(UserTokenConfig { exchange: true, ..Default::default() }).await;
+    app.saleor.respond_to("accessToken", json!({ "app": { "accessToken": null } }));
+    let response = app.as_user(&[]).get("/api/me").await;
+    assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+}
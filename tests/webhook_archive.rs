@@ -0,0 +1,113 @@
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use axum::http::StatusCode;
+use saleor_app::{
+    config::AppConfig,
+    emitter::ChannelSink,
+    saleor::SaleorPermission,
+    testing::TestApp,
+    webhook_archive::{ArchiveFilter, ArchiveStore, ArchivedWebhook, FileArchiveStore},
+    webhooks::PRODUCT_UPDATED_PATH,
+};
+use serde_json::json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::time::timeout;
+
+fn archive_file() -> PathBuf {
+    std::env::temp_dir().join(format!("webhooks-{}.jsonl", uuid::Uuid::new_v4()))
+}
+
+async fn archiving_app() -> TestApp {
+    let path = archive_file();
+    TestApp::with_saleor_config(move |saleor| AppConfig {
+        admin_api_urls: vec![saleor.api_url()],
+        webhook_archive_file: Some(path),
+        ..Default::default()
+    })
+    .await
+}
+
+async fn archived(app: &TestApp, query: &str) -> Vec<ArchivedWebhook> {
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get(&format!("/api/admin/webhooks{query}")).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    response.json()
+}
+
+#[tokio::test]
+async fn webhooks_are_archived_and_redelivered() {
+    let app = archiving_app().await;
+    let (sink, mut events) = ChannelSink::new();
+    app.emitter.add_sink(sink);
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+    events.recv().await.unwrap();
+    let webhooks = timeout(Duration::from_secs(5), async {
+        loop {
+            let webhooks = archived(&app, "?event=product_updated").await;
+            if !webhooks.is_empty() {
+                return webhooks;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(webhooks[0].saleor_api_url, app.saleor.api_url());
+    assert_eq!(webhooks[0].path, PRODUCT_UPDATED_PATH);
+    assert!(webhooks[0].payload.contains("product"));
+
+    let response = app
+        .as_user(&[SaleorPermission::ManageApps])
+        .post_json(&format!("/api/admin/webhooks/{}/redeliver", webhooks[0].id), &json!({}))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let event = timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    assert_eq!(event.name, "productUpdated");
+
+    // redeliveries aren't archived again
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(archived(&app, "").await.len(), 1);
+    assert!(archived(&app, "?since=2100-01-01T00:00:00Z").await.is_empty());
+    assert!(archived(&app, "?event=order_created").await.is_empty());
+}
+
+#[tokio::test]
+async fn searching_needs_valid_timestamps_and_an_archive() {
+    let app = archiving_app().await;
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/admin/webhooks?since=yesterday").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let app = TestApp::with_saleor_config(|saleor| AppConfig {
+        admin_api_urls: vec![saleor.api_url()],
+        ..Default::default()
+    })
+    .await;
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/admin/webhooks").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn old_deliveries_are_pruned() {
+    let store = FileArchiveStore::new(archive_file());
+    let now = OffsetDateTime::now_utc();
+    for (id, received_at) in [("old", now - Duration::from_secs(3600)), ("new", now)] {
+        store
+            .store(&ArchivedWebhook {
+                id: id.to_string(),
+                saleor_api_url: "https://shop.example.com/graphql/".to_string(),
+                event: "product_updated".to_string(),
+                path: PRODUCT_UPDATED_PATH.to_string(),
+                received_at: received_at.format(&Rfc3339).unwrap(),
+                headers: BTreeMap::new(),
+                payload: "{}".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(store.prune(now - Duration::from_secs(60)).await.unwrap(), 1);
+    let kept = store.search(&ArchiveFilter { limit: 10, ..Default::default() }).await.unwrap();
+    assert_eq!(kept.iter().map(|webhook| webhook.id.as_str()).collect::<Vec<_>>(), ["new"]);
+    assert!(store.get("old").await.unwrap().is_none());
+}
@@ -0,0 +1,97 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use axum::{http::StatusCode, Router};
+use saleor_app::{
+    config::AppConfig,
+    http_client::HttpClient,
+    saleor::{webhook_handler, AuthData, SettingsError, SettingsManager, WebhookContext, WebhookHandler},
+    testing::TestApp,
+    webhooks::ProductUpdatedPayload,
+};
+use serde_json::json;
+
+const PATH: &str = "/api/webhooks/recorded";
+
+/// What a [`Recorder`] saw of a delivery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Delivery {
+    saleor_api_url: String,
+    event: String,
+    greeting: Option<String>,
+    product: Option<String>,
+}
+
+#[derive(Clone, Default)]
+struct Recorder {
+    deliveries: Arc<Mutex<Vec<Delivery>>>,
+}
+
+#[async_trait]
+impl WebhookHandler<ProductUpdatedPayload> for Recorder {
+    type Response = Result<StatusCode, SettingsError>;
+
+    async fn handle(&self, context: &WebhookContext, payload: ProductUpdatedPayload) -> Self::Response {
+        let greeting = context.settings.get("greeting").await?;
+        self.deliveries.lock().unwrap().push(Delivery {
+            saleor_api_url: context.auth_data.saleor_api_url.clone(),
+            event: context.event.clone(),
+            greeting,
+            product: payload.product.map(|product| product.name),
+        });
+        Ok(StatusCode::ACCEPTED)
+    }
+}
+
+struct FixedSettings(HashMap<String, String>);
+
+#[async_trait]
+impl SettingsManager for FixedSettings {
+    async fn get_all(&self) -> Result<HashMap<String, String>, SettingsError> {
+        Ok(self.0.clone())
+    }
+
+    async fn set(&self, _settings: HashMap<String, String>) -> Result<(), SettingsError> {
+        Ok(())
+    }
+}
+
+fn product_updated() -> serde_json::Value {
+    json!({ "product": { "id": "UHJvZHVjdDox", "name": "Apple Juice" } })
+}
+
+#[tokio::test]
+async fn handlers_get_the_installation_and_its_settings() {
+    let recorder = Recorder::default();
+    let app = TestApp::with_routes(AppConfig::default(), Router::new().route(PATH, webhook_handler(recorder.clone()))).await;
+    app.saleor.respond_to("privateMetadata", json!({ "app": { "id": "QXBwOjE=", "privateMetadata": [{ "key": "greeting", "value": "hello" }] } }));
+
+    let response = app.deliver_webhook(PATH, "product_updated", &product_updated()).await;
+    assert_eq!(response.status, StatusCode::ACCEPTED, "{}", response.text());
+    assert_eq!(*recorder.deliveries.lock().unwrap(), vec![Delivery {
+        saleor_api_url: app.saleor.api_url(),
+        event: "product_updated".to_string(),
+        greeting: Some("hello".to_string()),
+        product: Some("Apple Juice".to_string()),
+    }]);
+
+    let response = app.deliver_signed_webhook(PATH, "product_updated", product_updated().to_string(), "forged..signature").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(recorder.deliveries.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn handlers_are_called_without_a_request() {
+    let auth_data = AuthData {
+        app_id: "saleor-app".to_string(),
+        ..AuthData::new("https://shop.example.com/graphql/", "token")
+    };
+    let context = WebhookContext::new(auth_data, HttpClient::default())
+        .with_settings(FixedSettings(HashMap::new()))
+        .with_event("product_updated");
+
+    let recorder = Recorder::default();
+    let payload = serde_json::from_value(product_updated()).unwrap();
+    assert_eq!(recorder.handle(&context, payload).await.unwrap(), StatusCode::ACCEPTED);
+    assert_eq!(recorder.deliveries.lock().unwrap()[0].greeting, None);
+}
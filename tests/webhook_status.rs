@@ -0,0 +1,42 @@
+use axum::http::StatusCode;
+use saleor_app::{config::AppConfig, saleor::SaleorPermission, testing::TestApp, webhooks::PRODUCT_UPDATED_PATH};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn reports_deliveries_per_installation() {
+    let app = TestApp::with_saleor_config(|saleor| AppConfig {
+        admin_api_urls: vec![saleor.api_url()],
+        ..Default::default()
+    })
+    .await;
+    let user = app.as_user(&[SaleorPermission::ManageApps]);
+
+    let statuses: Value = user.get("/api/admin/webhook-status").await.json();
+    assert_eq!(statuses, json!([{
+        "saleorApiUrl": app.saleor.api_url(),
+        "lastReceivedAt": null,
+        "events": {},
+        "failures": 0,
+        "backlog": 0,
+    }]));
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.deliver_signed_webhook(PRODUCT_UPDATED_PATH, "product_updated", "{}", "forged").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = user.get("/api/admin/webhook-status").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let statuses: Value = response.json();
+    assert!(statuses[0]["lastReceivedAt"].is_string());
+    assert_eq!(statuses[0]["events"], json!({ "product_updated": { "received": 2, "failed": 1 } }));
+    assert_eq!(statuses[0]["failures"], 1);
+}
+
+#[tokio::test]
+async fn webhook_status_is_for_operators() {
+    let app = TestApp::new().await;
+
+    let response = app.as_user(&[SaleorPermission::ManageApps]).get("/api/admin/webhook-status").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,240 @@
+use axum::{body::Body, http::{Request, StatusCode}};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, EncodingKey};
+use saleor_app::{app::app_manifest, config::AppConfig, http_client::HttpClient, saleor::{sync_webhooks, verify_webhook_signature, AplId, AplStore, JwksCache, WebhookChange, WebhookChangeKind}, testing::{webhook_fixture, MockSaleor, TestApp}, webhooks::PRODUCT_UPDATED_PATH};
+use serde_json::json;
+
+#[tokio::test]
+async fn product_updated_is_published_to_pages() {
+    let app = TestApp::new().await;
+    let mut events = app.events.subscribe(&app.saleor.api_url());
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    let event = events.try_recv().expect("no event published");
+    assert_eq!(event.name, "productUpdated");
+    assert_eq!(event.data, webhook_fixture("product_updated")["product"]);
+}
+
+#[tokio::test]
+async fn tampered_payload_is_rejected() {
+    let app = TestApp::new().await;
+    let mut events = app.events.subscribe(&app.saleor.api_url());
+
+    let payload = webhook_fixture("product_updated").to_string();
+    let signature = app.saleor.sign_webhook(payload.as_bytes());
+    let tampered = payload.replace("Apple Juice", "Orange Juice");
+    let response = app.deliver_signed_webhook(PRODUCT_UPDATED_PATH, "product_updated", tampered, &signature).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn missing_signature_is_rejected() {
+    let app = TestApp::new().await;
+
+    let payload = webhook_fixture("product_updated").to_string();
+    let response = app.deliver_signed_webhook(PRODUCT_UPDATED_PATH, "product_updated", payload, "").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn oversized_payload_is_rejected() {
+    let mut config = AppConfig::default();
+    config.limits.webhooks.body_limit_bytes = 16;
+    let app = TestApp::with_config(config).await;
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn webhooks_above_rate_limit_are_throttled() {
+    let mut config = AppConfig::default();
+    config.webhook_rate_limit.capacity = 1;
+    config.webhook_rate_limit.refill_per_second = 0.01;
+    let app = TestApp::with_config(config).await;
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers.contains_key("retry-after"));
+}
+
+#[tokio::test]
+async fn forged_api_urls_do_not_reset_the_rate_limit() {
+    let mut config = AppConfig::default();
+    config.webhook_rate_limit.capacity = 2;
+    config.webhook_rate_limit.refill_per_second = 0.01;
+    let app = TestApp::with_config(config).await;
+    let forged = |i: usize| {
+        Request::post(PRODUCT_UPDATED_PATH)
+            .header("saleor-api-url", format!("https://shop{i}.example.com/graphql/"))
+            .header("saleor-event", "product_updated")
+            .header("saleor-signature", "forged")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let deliver = || app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated");
+
+    assert_eq!(deliver().await.status, StatusCode::OK);
+    assert_ne!(app.request(forged(1)).await.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_ne!(app.request(forged(2)).await.status, StatusCode::TOO_MANY_REQUESTS);
+    let lookups = app.apl.calls().len();
+    assert_eq!(app.request(forged(3)).await.status, StatusCode::TOO_MANY_REQUESTS);
+    // the flood is limited before it reaches the APL
+    assert_eq!(app.apl.calls().len(), lookups);
+    // installations keep buckets of their own
+    assert_eq!(deliver().await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn tiny_refill_rates_cap_the_retry_after() {
+    let mut config = AppConfig::default();
+    config.webhook_rate_limit.capacity = 1;
+    config.webhook_rate_limit.refill_per_second = 1e-300;
+    let app = TestApp::with_config(config).await;
+
+    app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers["retry-after"], "3600");
+}
+
+#[test]
+fn unusable_refill_rates_are_invalid() {
+    for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        let mut config = AppConfig::default();
+        config.webhook_rate_limit.refill_per_second = rate;
+        assert!(config.validate().contains(&"WEBHOOK_RATE_LIMIT_PER_SECOND must be positive".to_string()), "{rate}");
+    }
+}
+
+#[tokio::test]
+async fn unknown_installation_is_rejected() {
+    let app = TestApp::unregistered(Default::default()).await;
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn webhooks_signed_with_rotated_key_are_rejected() {
+    let app = TestApp::new().await;
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let apl_id = AplId::from_api_url(&app.saleor.api_url());
+    let mut auth_data = app.apl.get(&apl_id).await.unwrap().unwrap();
+    auth_data.jwks = Some(MockSaleor::start().await.jwks().to_string());
+    app.apl.set(&apl_id, auth_data).await.unwrap();
+
+    let response = app.deliver_webhook_fixture(PRODUCT_UPDATED_PATH, "product_updated", "product_updated").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+const RSA_JWKS: &str = r#"{"keys": [{"kty": "RSA", "kid": "rsa", "alg": "RS256", "use": "sig", "e": "AQAB", "n": "81XV0uYh7MUi-IEMNvzvPSFOVq0BJg_SVHYupJ-lHZb02cghD9U5eunm22K6cb5CWNw5BDRcZ0Ts5RU-q-Pi0VAijBYusEIBNIQ2818CXbHhp9prBVD8Jy43jBTiD3boB8Sz5pQEmWDyKZnhHtWLothBeJTMtDxW2Ul0GbFgbnhs1qc56mCCJbzL8LqVKwAXtjv2sMve7r1TqnZPtz6r8fEgv1PAMYstZG5gyoWfsTD3d1_abR8sAetACzIDrTAwvK7CdiVi41iDdKY3NRFNr0ZaMnglHot-_uf2T9NVLhDeKvES9BrObpLMjWNJiSKi7q2Xk7fqEa1w3aL6GuN0_w"}]}"#;
+
+/// A detached JWS over the unencoded `payload`, signed with `secret` as an HMAC key.
+fn hmac_signature(alg: &str, kid: &str, secret: &[u8], payload: &[u8]) -> String {
+    let header = URL_SAFE_NO_PAD.encode(json!({ "alg": alg, "kid": kid, "b64": false, "crit": ["b64"] }).to_string());
+    let mut message = format!("{}.", header).into_bytes();
+    message.extend_from_slice(payload);
+    let signature = jsonwebtoken::crypto::sign(&message, &EncodingKey::from_secret(secret), Algorithm::HS256).unwrap();
+    format!("{}..{}", header, signature)
+}
+
+#[tokio::test]
+async fn hmac_signatures_are_rejected_for_rsa_keys() {
+    let payload = webhook_fixture("product_updated").to_string();
+
+    let signature = hmac_signature("HS256", "rsa", RSA_JWKS.as_bytes(), payload.as_bytes());
+    assert!(verify_webhook_signature(RSA_JWKS, &signature, payload.as_bytes()).is_err());
+    // verifying with a key of another family used to panic
+    let signature = hmac_signature("ES256", "rsa", RSA_JWKS.as_bytes(), payload.as_bytes());
+    assert!(verify_webhook_signature(RSA_JWKS, &signature, payload.as_bytes()).is_err());
+}
+
+#[tokio::test]
+async fn hmac_signatures_are_rejected_for_ec_keys() {
+    let app = TestApp::new().await;
+    let mut events = app.events.subscribe(&app.saleor.api_url());
+
+    let payload = webhook_fixture("product_updated").to_string();
+    let kid = serde_json::from_str::<serde_json::Value>(app.saleor.jwks()).unwrap()["keys"][0]["kid"].as_str().unwrap().to_string();
+    let signature = hmac_signature("HS256", &kid, app.saleor.jwks().as_bytes(), payload.as_bytes());
+    assert!(verify_webhook_signature(app.saleor.jwks(), &signature, payload.as_bytes()).is_err());
+
+    let response = app.deliver_signed_webhook(PRODUCT_UPDATED_PATH, "product_updated", payload, &signature).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn decoding_keys_are_reused_until_jwks_changes() {
+    let saleor = MockSaleor::start().await;
+    let cache = JwksCache::new();
+
+    let jwks = cache.get(&saleor.api_url(), saleor.jwks()).unwrap();
+    let key = jwks.decoding_key(None).unwrap();
+    let cached = cache.get(&saleor.api_url(), saleor.jwks()).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&key, &cached.decoding_key(None).unwrap()));
+
+    let rotated = cache.get(&saleor.api_url(), MockSaleor::start().await.jwks()).unwrap();
+    assert!(!std::sync::Arc::ptr_eq(&key, &rotated.decoding_key(None).unwrap()));
+}
+
+#[tokio::test]
+async fn sync_brings_webhooks_in_line_with_manifest() {
+    let app = TestApp::new().await;
+    let auth_data = app.apl.store().get(&AplId::from_api_url(&app.saleor.api_url())).await.unwrap().unwrap();
+    let webhooks = app_manifest("https://app.example.com").webhooks.unwrap();
+    app.saleor.respond_to("AppWebhooks", json!({
+        "app": {
+            "webhooks": [{
+                "id": "V2ViaG9vazox",
+                "name": "Removed webhook",
+                "targetUrl": "https://app.example.com/api/webhooks/removed",
+                "isActive": true,
+                "subscriptionQuery": "subscription { event { __typename } }",
+                "asyncEvents": [{ "eventType": "ORDER_CREATED" }],
+                "syncEvents": [],
+            }],
+        },
+    }));
+    app.saleor.respond_to("webhookCreate", json!({ "webhookCreate": { "errors": [] } }));
+    app.saleor.respond_to("webhookDelete", json!({ "webhookDelete": { "errors": [] } }));
+
+    let changes = sync_webhooks(&HttpClient::default(), &auth_data, &webhooks, true, false).await.unwrap();
+    assert_eq!(changes, vec![
+        WebhookChange { name: webhooks[0].name.clone(), kind: WebhookChangeKind::Created },
+        WebhookChange { name: "Removed webhook".to_string(), kind: WebhookChangeKind::Deleted },
+    ]);
+}
+
+#[tokio::test]
+async fn sync_keeps_matching_webhooks() {
+    let app = TestApp::new().await;
+    let auth_data = app.apl.store().get(&AplId::from_api_url(&app.saleor.api_url())).await.unwrap().unwrap();
+    let webhooks = app_manifest("https://app.example.com").webhooks.unwrap();
+    app.saleor.respond_to("AppWebhooks", json!({
+        "app": {
+            "webhooks": [{
+                "id": "V2ViaG9vazox",
+                "name": webhooks[0].name,
+                "targetUrl": webhooks[0].target_url,
+                "isActive": true,
+                "subscriptionQuery": webhooks[0].query,
+                "asyncEvents": [{ "eventType": "PRODUCT_UPDATED" }],
+                "syncEvents": [],
+            }],
+        },
+    }));
+    // any mutation fails, nothing may change
+    app.saleor.respond_to("mutation", json!({ "webhookUpdate": { "errors": [{ "field": null, "message": "unexpected" }] } }));
+
+    let changes = sync_webhooks(&HttpClient::default(), &auth_data, &webhooks, true, false).await.unwrap();
+    assert_eq!(changes, vec![WebhookChange { name: webhooks[0].name.clone(), kind: WebhookChangeKind::Unchanged }]);
+}
@@ -0,0 +1,95 @@
+use axum::{body::Body, http::{header::CONTENT_TYPE, Request, StatusCode}, routing::post, Router};
+use saleor_app::{
+    app::app_manifest,
+    config::AppConfig,
+    saleor::{
+        manifest_problems,
+        widgets::{OrderDetails, Widget, ORDER_DETAILS_WIDGET_PATH, PRODUCT_DETAILS_WIDGET_PATH},
+        SaleorAppExtensionMount, SaleorAppExtensionTarget, SaleorPermission,
+    },
+    testing::TestApp,
+};
+use serde_json::json;
+
+fn widget_request(path: &str, form: &[(&str, &str)]) -> Request<Body> {
+    let body = form.iter().map(|(key, value)| format!("{key}={}", urlencode(value))).collect::<Vec<_>>().join("&");
+    Request::post(path).header(CONTENT_TYPE, "application/x-www-form-urlencoded").body(Body::from(body)).unwrap()
+}
+
+fn urlencode(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (byte as char).to_string(),
+        _ => format!("%{byte:02X}"),
+    }).collect()
+}
+
+#[tokio::test]
+async fn product_widgets_render_the_fetched_product() {
+    let app = TestApp::new().await;
+    app.saleor.respond_to("WidgetProduct", json!({ "object": {
+        "id": "UHJvZHVjdDox",
+        "name": "Juice",
+        "slug": "juice",
+        "updatedAt": "2024-01-01T00:00:00+00:00",
+        "productType": { "name": "Beverage" },
+        "category": null,
+        "variants": [{ "id": "UHJvZHVjdFZhcmlhbnQ6MQ==", "name": "1l", "sku": null }],
+    } }));
+    let api_url = app.saleor.api_url();
+    let token = app.saleor.token(&[SaleorPermission::ManageProducts]);
+
+    let form = [("saleorApiUrl", api_url.as_str()), ("accessToken", token.as_str()), ("productId", "UHJvZHVjdDox")];
+    let response = app.request(widget_request(PRODUCT_DETAILS_WIDGET_PATH, &form)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(response.text().contains("Beverage"));
+    let request = app.saleor.requests().into_iter().find(|request| request["query"].as_str().unwrap().contains("WidgetProduct")).unwrap();
+    assert_eq!(request["variables"], json!({ "id": "UHJvZHVjdDox" }));
+
+    let missing_object = app.request(widget_request(PRODUCT_DETAILS_WIDGET_PATH, &form[..2])).await;
+    assert_eq!(missing_object.status, StatusCode::BAD_REQUEST);
+    let invalid_token = [("saleorApiUrl", api_url.as_str()), ("accessToken", "forged"), ("productId", "UHJvZHVjdDox")];
+    assert_eq!(app.request(widget_request(PRODUCT_DETAILS_WIDGET_PATH, &invalid_token)).await.status, StatusCode::UNAUTHORIZED);
+    let token = app.saleor.token(&[SaleorPermission::ManageOrders]);
+    let without_permission = [("saleorApiUrl", api_url.as_str()), ("accessToken", token.as_str()), ("productId", "UHJvZHVjdDox")];
+    assert_eq!(app.request(widget_request(PRODUCT_DETAILS_WIDGET_PATH, &without_permission)).await.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn order_widgets_get_the_order_or_not_found() {
+    let routes = Router::new().route(ORDER_DETAILS_WIDGET_PATH, post(|widget: Widget<OrderDetails>| async move {
+        format!("order {} of {}", widget.object.number, widget.object.total.gross)
+    }));
+    let app = TestApp::with_routes(AppConfig::default(), routes).await;
+    let api_url = app.saleor.api_url();
+    let token = app.saleor.token(&[SaleorPermission::ManageOrders]);
+    let form = [("saleorApiUrl", api_url.as_str()), ("accessToken", token.as_str()), ("orderId", "T3JkZXI6MQ==")];
+
+    app.saleor.respond_to("WidgetOrder", json!({ "object": {
+        "id": "T3JkZXI6MQ==",
+        "number": "1042",
+        "status": "UNFULFILLED",
+        "created": "2024-01-01T00:00:00+00:00",
+        "userEmail": null,
+        "total": { "gross": { "amount": 12.5, "currency": "EUR" } },
+        "lines": [{ "id": "T3JkZXJMaW5lOjE=", "quantity": 2 }],
+    } }));
+    let response = app.request(widget_request(ORDER_DETAILS_WIDGET_PATH, &form)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.text(), "order 1042 of 12.50 EUR");
+
+    app.saleor.respond_to("WidgetOrder", json!({ "object": null }));
+    let response = app.request(widget_request(ORDER_DETAILS_WIDGET_PATH, &form)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn widgets_are_declared_as_widget_targets() {
+    let mut manifest = app_manifest("https://app.example.com");
+    let widget = manifest.extensions.iter().flatten().find(|extension| extension.target == SaleorAppExtensionTarget::Widget).unwrap();
+    assert_eq!(widget.mount, SaleorAppExtensionMount::ProductDetailsWidgets);
+    assert_eq!(serde_json::to_value(widget).unwrap()["options"], json!({ "widgetTarget": { "method": "POST" } }));
+
+    let extension = manifest.extensions.as_mut().unwrap().iter_mut().find(|extension| extension.target == SaleorAppExtensionTarget::Widget).unwrap();
+    extension.mount = SaleorAppExtensionMount::ProductDetailsMoreActions;
+    assert!(manifest_problems(&manifest).iter().any(|problem| problem.contains("only mounted at *_WIDGETS")));
+}
@@ -0,0 +1,163 @@
+use async_trait::async_trait;
+use axum::http::{header::{AUTHORIZATION, ORIGIN}, StatusCode};
+use futures_util::{SinkExt, StreamExt};
+use saleor_app::{
+    config::AppConfig,
+    events::AppEvent,
+    saleor::{SaleorPermission, SALEOR_API_URL_HEADER},
+    templating::NotificationStatus,
+    testing::TestApp,
+    ws::{WsCommand, WsContext},
+};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::{self, client::IntoClientRequest, Message}, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Answers with its arguments, reporting a step of the `echo` job.
+struct Echo;
+
+#[async_trait]
+impl WsCommand for Echo {
+    async fn run(&self, context: &WsContext, args: Value) -> Result<Value, String> {
+        context.events.publish(&context.saleor_api_url, AppEvent::progress("echo", 1, 1));
+        Ok(args)
+    }
+}
+
+/// Never finishes, keeping its room among the running commands.
+struct Stall;
+
+#[async_trait]
+impl WsCommand for Stall {
+    async fn run(&self, _context: &WsContext, _args: Value) -> Result<Value, String> {
+        std::future::pending().await
+    }
+}
+
+async fn connect(app: &TestApp) -> Socket {
+    connect_from(app, None).await.expect("unable to connect")
+}
+
+/// Connects like a page of `origin`.
+async fn connect_from(app: &TestApp, origin: Option<&str>) -> Result<Socket, tungstenite::Error> {
+    let addr = app.serve();
+    let mut request = format!("ws://{addr}/app/ws").into_client_request().unwrap();
+    let user = app.as_user(&[SaleorPermission::ManageProducts]);
+    request.headers_mut().insert(AUTHORIZATION, format!("Bearer {}", user.token).parse().unwrap());
+    request.headers_mut().insert(SALEOR_API_URL_HEADER, app.saleor.api_url().parse().unwrap());
+    if let Some(origin) = origin {
+        request.headers_mut().insert(ORIGIN, origin.replace("{addr}", &addr.to_string()).parse().unwrap());
+    }
+    let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+    Ok(socket)
+}
+
+async fn send(socket: &mut Socket, message: Value) {
+    socket.send(Message::Text(message.to_string())).await.unwrap();
+}
+
+async fn receive(socket: &mut Socket) -> Value {
+    loop {
+        match socket.next().await.expect("socket closed").unwrap() {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn sockets_need_a_dashboard_token() {
+    let app = TestApp::new().await;
+    let mut request = format!("ws://{}/app/ws", app.serve()).into_client_request().unwrap();
+    request.headers_mut().insert(SALEOR_API_URL_HEADER, app.saleor.api_url().parse().unwrap());
+    request.headers_mut().insert(AUTHORIZATION, "Bearer forged".parse().unwrap());
+
+    match tokio_tungstenite::connect_async(request).await {
+        Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), StatusCode::UNAUTHORIZED),
+        other => panic!("connected without a token: {:?}", other.map(|(_, response)| response.status())),
+    }
+}
+
+#[tokio::test]
+async fn sockets_of_other_origins_are_rejected() {
+    let config = AppConfig { cors_origins: vec!["https://dashboard.example.com".to_string()], ..Default::default() };
+    let app = TestApp::with_config(config).await;
+
+    connect_from(&app, Some("https://{addr}")).await.expect("the app's own pages connect");
+    connect_from(&app, Some("https://dashboard.example.com")).await.expect("configured dashboards connect");
+    match connect_from(&app, Some("https://evil.example.com")).await {
+        Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), StatusCode::FORBIDDEN),
+        other => panic!("another origin connected: {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn sockets_get_progress_of_subscribed_jobs_and_toasts() {
+    let app = TestApp::new().await;
+    let mut socket = connect(&app).await;
+
+    send(&mut socket, json!({ "type": "subscribe", "job": "import" })).await;
+    send(&mut socket, json!({ "type": "ping" })).await;
+    assert_eq!(receive(&mut socket).await, json!({ "type": "pong" }));
+
+    let api_url = app.saleor.api_url();
+    app.events.publish(&api_url, AppEvent::progress("export", 1, 2));
+    app.events.publish(&api_url, AppEvent::progress("import", 3, 10));
+    app.events.publish(&api_url, AppEvent::toast(NotificationStatus::Success, "Import finished"));
+    app.events.publish(&api_url, AppEvent::new("product-updated", json!({ "id": "UHJvZHVjdDox" })));
+
+    assert_eq!(receive(&mut socket).await, json!({ "type": "progress", "job": "import", "done": 3, "total": 10 }));
+    let toast = receive(&mut socket).await;
+    assert_eq!(toast["type"], "toast");
+    assert_eq!(toast["action"]["type"], "notification");
+    assert_eq!(toast["action"]["payload"]["title"], "Import finished");
+    assert_eq!(receive(&mut socket).await, json!({ "type": "event", "name": "product-updated", "data": { "id": "UHJvZHVjdDox" } }));
+}
+
+#[tokio::test]
+async fn sockets_run_registered_commands() {
+    let app = TestApp::new().await;
+    app.ws_commands.register("echo", Echo);
+    let mut socket = connect(&app).await;
+
+    send(&mut socket, json!({ "type": "subscribe", "job": "*" })).await;
+    send(&mut socket, json!({ "type": "command", "id": "1", "name": "echo", "args": { "sku": "BOOK-1" } })).await;
+    // the progress the command published and its result race each other
+    let mut messages = vec![receive(&mut socket).await, receive(&mut socket).await];
+    messages.sort_by_key(|message| message["type"].to_string());
+    assert_eq!(messages, vec![
+        json!({ "type": "progress", "job": "echo", "done": 1, "total": 1 }),
+        json!({ "type": "result", "id": "1", "data": { "sku": "BOOK-1" } }),
+    ]);
+
+    send(&mut socket, json!({ "type": "command", "id": "2", "name": "reindex" })).await;
+    assert_eq!(receive(&mut socket).await, json!({ "type": "error", "id": "2", "message": "unknown command reindex" }));
+
+    send(&mut socket, json!({ "type": "launch" })).await;
+    let error = receive(&mut socket).await;
+    assert_eq!(error["type"], "error");
+    assert!(error.get("id").is_none());
+}
+
+#[tokio::test]
+async fn sockets_limit_their_running_commands() {
+    let app = TestApp::new().await;
+    app.ws_commands.register("stall", Stall);
+    app.ws_commands.register("echo", Echo);
+    let mut socket = connect(&app).await;
+
+    for id in 0..8 {
+        send(&mut socket, json!({ "type": "command", "id": id.to_string(), "name": "stall" })).await;
+    }
+    send(&mut socket, json!({ "type": "command", "id": "8", "name": "echo" })).await;
+    let error = receive(&mut socket).await;
+    assert_eq!(error["type"], "error");
+    assert_eq!(error["id"], "8");
+
+    // other sockets have room of their own
+    let mut other = connect(&app).await;
+    send(&mut other, json!({ "type": "command", "id": "1", "name": "echo", "args": {} })).await;
+    assert_eq!(receive(&mut other).await, json!({ "type": "result", "id": "1", "data": {} }));
+}